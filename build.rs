@@ -0,0 +1,16 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/voidrun.proto");
+
+        // Fall back to the vendored protoc so builds don't need it installed
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        }
+
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/voidrun.proto"], &["proto"])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,392 @@
+//! Async client for the sandbox service HTTP API
+//!
+//! ```no_run
+//! # async fn run() -> voidrun_client::Result<()> {
+//! use voidrun_client::{Client, types::CreateSandboxRequest};
+//!
+//! let client = Client::new("http://localhost:8070");
+//! let result = client.execute(&CreateSandboxRequest {
+//!     runtime: "node".to_string(),
+//!     code: "console.log('hello')".to_string(),
+//!     ..Default::default()
+//! }).await?;
+//! println!("{}", result.stdout);
+//! # Ok(())
+//! # }
+//! ```
+
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub use voidrun_types as types;
+use voidrun_types::{
+    AccessLogEntry, Capabilities, CompareRequest, CompareResult, CreateSandboxRequest, CreateSessionRequest, DeploymentList, DeploymentListQuery,
+    DeploymentRequest, DeploymentResponse, DeploymentStats, ErrorResponse, EvalRequest, EvalResult, ExecutionResult, FileUpdateRequest,
+    FormatResult, LintResult, LogEntry, ReplayResult, SandboxFile, SandboxInfo, SandboxList, SandboxListQuery, SandboxResponse, SnapshotRequest,
+    SnapshotResponse, TemplateInfo, TestRequest, TestResults, ToolRequest,
+};
+/// Errors returned by [`Client`]
+#[derive(Debug)]
+pub enum Error {
+    /// The service could not be reached
+    Request(reqwest::Error),
+    /// The service answered with a non-success status
+    Status { status: StatusCode, body: String },
+    /// The response body did not match the expected type
+    Decode(serde_json::Error),
+}
+
+impl Error {
+    /// Structured body of an error response, with its `code` to branch on
+    pub fn api_error(&self) -> Option<ErrorResponse> {
+        match self {
+            Error::Status { body, .. } => serde_json::from_str(body).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Request(e) => write!(f, "request failed: {}", e),
+            Error::Status { status, body } if body.is_empty() => write!(f, "request failed with {}", status),
+            Error::Status { status, body } => write!(f, "request failed with {}: {}", status, body),
+            Error::Decode(e) => write!(f, "invalid response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(e) => Some(e),
+            Error::Status { .. } => None,
+            Error::Decode(e) => Some(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Request(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Client for one sandbox service instance
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl Client {
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Use a preconfigured `reqwest::Client`, e.g. with timeouts or default headers
+    pub fn with_http_client(http: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// GET /health
+    pub async fn health(&self) -> Result<serde_json::Value> {
+        self.request(Method::GET, "/health", None::<&()>).await
+    }
+
+    /// Runtimes, versions, backends and features available on the instance (GET /runtimes)
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        self.request(Method::GET, "/runtimes", None::<&()>).await
+    }
+
+    /// Run code in a one-shot sandbox (POST /execute)
+    pub async fn execute(&self, request: &CreateSandboxRequest) -> Result<SandboxResponse> {
+        self.request(Method::POST, "/execute", Some(request)).await
+    }
+
+    /// Run two variants of the code under the same files, env vars and limits and diff
+    /// their results (POST /execute/compare)
+    pub async fn compare(&self, request: &CompareRequest) -> Result<CompareResult> {
+        self.request(Method::POST, "/execute/compare", Some(request)).await
+    }
+
+    /// Format code with prettier in a one-shot sandbox (POST /tools/format)
+    pub async fn format_code(&self, request: &ToolRequest) -> Result<FormatResult> {
+        self.request(Method::POST, "/tools/format", Some(request)).await
+    }
+
+    /// Lint code with eslint in a one-shot sandbox (POST /tools/lint)
+    pub async fn lint_code(&self, request: &ToolRequest) -> Result<LintResult> {
+        self.request(Method::POST, "/tools/lint", Some(request)).await
+    }
+
+    /// POST /sandbox
+    pub async fn create_sandbox(&self, request: &CreateSandboxRequest) -> Result<SandboxInfo> {
+        self.request(Method::POST, "/sandbox", Some(request)).await
+    }
+
+    /// GET /sandbox/{id}
+    pub async fn get_sandbox(&self, sandbox_id: &str) -> Result<SandboxInfo> {
+        self.request(Method::GET, &format!("/sandbox/{}", sandbox_id), None::<&()>).await
+    }
+
+    /// All sandboxes, newest first, following the pages of GET /sandbox
+    pub async fn list_sandboxes(&self) -> Result<Vec<SandboxInfo>> {
+        let mut query = SandboxListQuery::default();
+        let mut sandboxes = Vec::new();
+        loop {
+            let page = self.list_sandboxes_page(&query).await?;
+            sandboxes.extend(page.sandboxes);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return Ok(sandboxes),
+            }
+        }
+    }
+
+    /// One page of sandboxes matching `query` (GET /sandbox?status=...&cursor=...)
+    pub async fn list_sandboxes_page(&self, query: &SandboxListQuery) -> Result<SandboxList> {
+        let query = serde_urlencoded::to_string(query).expect("sandbox list query is URL-encodable");
+        self.request(Method::GET, &format!("/sandbox?{}", query), None::<&()>).await
+    }
+
+    /// Run a sandbox created with [`Client::create_sandbox`] (POST /sandbox/{id}/execute)
+    pub async fn execute_sandbox(&self, sandbox_id: &str) -> Result<ExecutionResult> {
+        self.request(Method::POST, &format!("/sandbox/{}/execute", sandbox_id), None::<&()>).await
+    }
+
+    /// Run the project's tests in the sandbox, parsed into per-test results
+    /// (POST /sandbox/{id}/test)
+    pub async fn run_tests(&self, sandbox_id: &str, request: &TestRequest) -> Result<TestResults> {
+        self.request(Method::POST, &format!("/sandbox/{}/test", sandbox_id), Some(request)).await
+    }
+
+    /// Run a past execution's request again in a fresh sandbox and compare the output
+    /// (POST /executions/{id}/replay)
+    pub async fn replay_execution(&self, execution_id: &str) -> Result<ReplayResult> {
+        self.request(Method::POST, &format!("/executions/{}/replay", execution_id), None::<&()>).await
+    }
+
+    /// Kill the sandbox's execution in flight (POST /sandbox/{id}/cancel)
+    ///
+    /// The pending [`Client::execute_sandbox`] call returns with `canceled` set.
+    pub async fn cancel_execution(&self, sandbox_id: &str) -> Result<()> {
+        self.send(Method::POST, &format!("/sandbox/{}/cancel", sandbox_id), None::<&()>).await?;
+        Ok(())
+    }
+
+    /// POST /sandbox/{id}/files
+    pub async fn upload_files(&self, sandbox_id: &str, files: &[SandboxFile]) -> Result<()> {
+        self.send(Method::POST, &format!("/sandbox/{}/files", sandbox_id), Some(files)).await?;
+        Ok(())
+    }
+
+    /// Freeze the sandbox's processes (POST /sandbox/{id}/pause)
+    pub async fn pause_sandbox(&self, sandbox_id: &str) -> Result<SandboxInfo> {
+        self.request(Method::POST, &format!("/sandbox/{}/pause", sandbox_id), None::<&()>).await
+    }
+
+    /// POST /sandbox/{id}/resume
+    pub async fn resume_sandbox(&self, sandbox_id: &str) -> Result<SandboxInfo> {
+        self.request(Method::POST, &format!("/sandbox/{}/resume", sandbox_id), None::<&()>).await
+    }
+
+    /// POST /sandbox/{id}/snapshot
+    pub async fn snapshot_sandbox(&self, sandbox_id: &str, name: &str) -> Result<SnapshotResponse> {
+        let request = SnapshotRequest { name: name.to_string() };
+        self.request(Method::POST, &format!("/sandbox/{}/snapshot", sandbox_id), Some(&request)).await
+    }
+
+    /// Zip archive of the sandbox's collected artifacts (GET /sandbox/{id}/artifacts)
+    pub async fn artifacts(&self, sandbox_id: &str) -> Result<Vec<u8>> {
+        let response = self.http.get(self.url(&format!("/sandbox/{}/artifacts", sandbox_id))).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(Error::Status { status, body });
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// DELETE /sandbox/{id}
+    pub async fn delete_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/sandbox/{}", sandbox_id), None::<&()>).await?;
+        Ok(())
+    }
+
+    /// Start a REPL session, returning once it takes evaluations (POST /sessions)
+    pub async fn create_session(&self, request: &CreateSessionRequest) -> Result<SandboxInfo> {
+        self.request(Method::POST, "/sessions", Some(request)).await
+    }
+
+    /// Evaluate code in a session, keeping what it declares for later evaluations
+    /// (POST /sessions/{id}/eval)
+    pub async fn eval(&self, session_id: &str, request: &EvalRequest) -> Result<EvalResult> {
+        self.request(Method::POST, &format!("/sessions/{}/eval", session_id), Some(request)).await
+    }
+
+    /// DELETE /sessions/{id}
+    pub async fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/sessions/{}", session_id), None::<&()>).await?;
+        Ok(())
+    }
+
+    /// Deploy a function (POST /faas/deploy)
+    ///
+    /// A deployment that fails its readiness check is returned with status
+    /// `Failed` and its `error` and `logs` set rather than as an error.
+    pub async fn deploy(&self, request: &DeploymentRequest) -> Result<DeploymentResponse> {
+        self.post_deployment("/faas/deploy", request).await
+    }
+
+    /// Deploy a built-in template, with `request` adding or overriding files and settings
+    /// (POST /faas/deploy?template={name})
+    pub async fn deploy_template(&self, template: &str, request: &DeploymentRequest) -> Result<DeploymentResponse> {
+        self.post_deployment(&format!("/faas/deploy?template={}", template), request).await
+    }
+
+    /// GET /templates
+    pub async fn list_templates(&self) -> Result<Vec<TemplateInfo>> {
+        self.request(Method::GET, "/templates", None::<&()>).await
+    }
+
+    /// GET /faas/deployments/{id}
+    pub async fn get_deployment(&self, deployment_id: &str) -> Result<DeploymentResponse> {
+        self.request(Method::GET, &format!("/faas/deployments/{}", deployment_id), None::<&()>).await
+    }
+
+    /// All deployments, newest first, following the pages of GET /faas/deployments
+    pub async fn list_deployments(&self) -> Result<Vec<DeploymentResponse>> {
+        let mut query = DeploymentListQuery::default();
+        let mut deployments = Vec::new();
+        loop {
+            let page = self.list_deployments_page(&query).await?;
+            deployments.extend(page.deployments);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return Ok(deployments),
+            }
+        }
+    }
+
+    /// One page of deployments matching `query` (GET /faas/deployments?status=...&cursor=...)
+    pub async fn list_deployments_page(&self, query: &DeploymentListQuery) -> Result<DeploymentList> {
+        let query = serde_urlencoded::to_string(query).expect("deployment list query is URL-encodable");
+        self.request(Method::GET, &format!("/faas/deployments?{}", query), None::<&()>).await
+    }
+
+    /// DELETE /faas/deployments/{id}
+    pub async fn undeploy(&self, deployment_id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/faas/deployments/{}", deployment_id), None::<&()>).await?;
+        Ok(())
+    }
+
+    /// Traffic proxied to a deployment (GET /faas/deployments/{id}/stats)
+    pub async fn deployment_stats(&self, deployment_id: &str) -> Result<DeploymentStats> {
+        self.request(Method::GET, &format!("/faas/deployments/{}/stats", deployment_id), None::<&()>).await
+    }
+
+    /// Newest requests proxied to a deployment (GET /faas/deployments/{id}/access-logs)
+    pub async fn access_logs(&self, deployment_id: &str, limit: usize) -> Result<Vec<AccessLogEntry>> {
+        let path = format!("/faas/deployments/{}/access-logs?limit={}", deployment_id, limit);
+        self.request(Method::GET, &path, None::<&()>).await
+    }
+
+    /// Rebuild a deployment in a fresh sandbox, keeping its ID and URL
+    /// (POST /faas/deployments/{id}/redeploy)
+    pub async fn redeploy(&self, deployment_id: &str) -> Result<DeploymentResponse> {
+        self.request(Method::POST, &format!("/faas/deployments/{}/redeploy", deployment_id), None::<&()>).await
+    }
+
+    /// Replace files in a running deployment (PUT /faas/deployments/{id}/files)
+    pub async fn update_files(&self, deployment_id: &str, request: &FileUpdateRequest) -> Result<()> {
+        self.send(Method::PUT, &format!("/faas/deployments/{}/files", deployment_id), Some(request)).await?;
+        Ok(())
+    }
+
+    /// Gzipped tarball of a deployment's manifest and files (GET /faas/deployments/{id}/export)
+    pub async fn export_deployment(&self, deployment_id: &str) -> Result<Vec<u8>> {
+        let response = self.http.get(self.url(&format!("/faas/deployments/{}/export", deployment_id))).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(Error::Status { status, body });
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Recreate a deployment from an exported bundle (POST /faas/import); a deployment
+    /// that fails its readiness check is returned as by [`Client::deploy`]
+    pub async fn import_deployment(&self, bundle: Vec<u8>) -> Result<DeploymentResponse> {
+        let response = self.http.post(self.url("/faas/import"))
+            .header(reqwest::header::CONTENT_TYPE, "application/gzip")
+            .body(bundle)
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            return serde_json::from_str(&body).map_err(|_| Error::Status { status, body });
+        }
+        if !status.is_success() {
+            return Err(Error::Status { status, body });
+        }
+        serde_json::from_str(&body).map_err(Error::Decode)
+    }
+
+    /// Most recent log lines of a sandbox (GET /admin/api/sandboxes/{id}/logs)
+    pub async fn logs(&self, sandbox_id: &str, lines: u32) -> Result<Vec<LogEntry>> {
+        let path = format!("/admin/api/sandboxes/{}/logs?lines={}", sandbox_id, lines);
+        self.request(Method::GET, &path, None::<&()>).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Post a deployment, reading the failed deployment out of a 503 response
+    async fn post_deployment(&self, path: &str, request: &DeploymentRequest) -> Result<DeploymentResponse> {
+        match self.request(Method::POST, path, Some(request)).await {
+            Err(Error::Status { status, body }) if status == StatusCode::SERVICE_UNAVAILABLE => {
+                serde_json::from_str(&body).map_err(|_| Error::Status { status, body })
+            }
+            result => result,
+        }
+    }
+
+    /// Send a request and return the body of a successful response
+    async fn send<B: Serialize + ?Sized>(&self, method: Method, path: &str, body: Option<&B>) -> Result<String> {
+        let mut request = self.http.request(method, self.url(path));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::Status { status, body });
+        }
+        Ok(body)
+    }
+
+    async fn request<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T> {
+        let body = self.send(method, path, body).await?;
+        serde_json::from_str(&body).map_err(Error::Decode)
+    }
+}
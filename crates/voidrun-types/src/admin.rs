@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::SandboxInfo;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub message: String,
+    pub sandbox_id: Option<String>,
+}
+
+/// Sandbox summary plus the live details shown in the admin dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxDetails {
+    #[serde(flatten)]
+    pub sandbox: SandboxInfo,
+    pub uptime: u64,
+    pub cpu_percentage: f64,
+    pub container_id: Option<String>,
+}
+
+/// Page of sandboxes in the admin dashboard, queried like `GET /sandbox`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxDetailsList {
+    pub sandboxes: Vec<SandboxDetails>,
+    /// Sandboxes matching the filters, over all pages
+    pub total: usize,
+    /// Cursor of the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
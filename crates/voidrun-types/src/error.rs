@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// Body of every error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// Machine-readable cause, e.g. `not_found` or `capacity_exceeded`
+    pub code: String,
+    pub message: String,
+    /// Cause-specific fields, such as the exhausted resource of `capacity_exceeded`
+    #[serde(default)]
+    pub details: Option<serde_json::Value>,
+}
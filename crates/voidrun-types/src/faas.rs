@@ -0,0 +1,450 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::sandbox::{CostEstimate, SandboxFile};
+
+
+/// FaaS deployment request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentRequest {
+    /// Runtime environment (bun, node, typescript); may come from the project's `voidrun.toml`
+    #[serde(default)]
+    pub runtime: String,
+    /// Main application code; may be empty when the files include the entry file
+    #[serde(default)]
+    pub code: String,
+    /// Additional files (optional)
+    pub files: Option<Vec<SandboxFile>>,
+    /// Environment variables (optional)
+    pub env_vars: Option<HashMap<String, String>>,
+    /// Memory limit in MB (default: 256)
+    pub memory_limit_mb: Option<u32>,
+    /// Size of the writable project directory in MB (default: 500)
+    pub disk_limit_mb: Option<u64>,
+    /// Maximum number of processes in the sandbox (default: 256)
+    pub max_processes: Option<u64>,
+    /// Entry point command (optional, defaults based on runtime)
+    pub entry_point: Option<String>,
+    /// Auto-scale settings (optional)
+    pub auto_scale: Option<AutoScaleConfig>,
+    /// Requests the proxy forwards to the deployment at once, across instances (optional, default: unlimited)
+    #[serde(default)]
+    pub max_concurrent_requests: Option<u32>,
+    /// Requests waiting for a slot beyond `max_concurrent_requests` before the proxy answers 429 (default: 100)
+    #[serde(default)]
+    pub max_queued_requests: Option<u32>,
+    /// Whether to run as dev server with hot reload (default: true)
+    pub dev_server: Option<bool>,
+    /// Port the service listens on inside the sandbox (default: 3000)
+    pub port: Option<u16>,
+    /// Readiness check gating the deployment (optional)
+    pub health_check: Option<HealthCheckConfig>,
+    /// Proxy response cache for GET requests (optional, disabled by default)
+    pub cache: Option<CacheConfig>,
+    /// Who may call the deployment URL (optional, default: anyone)
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// How file updates reach the running dev server (default: restart)
+    #[serde(default)]
+    pub reload_strategy: Option<ReloadStrategy>,
+    /// Package registries dependencies are installed from (optional, default: the public npm registry)
+    #[serde(default)]
+    pub registries: Option<Vec<PackageRegistry>>,
+    /// Free-form `key: value` labels, also set on the deployment's sandboxes (optional)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// Cross-origin policy the proxy answers browsers with, replacing the service's own (optional)
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Rewrites the proxy applies to requests before forwarding them (optional)
+    #[serde(default)]
+    pub routing: Option<RoutingConfig>,
+    /// Private network shared with other deployments, which reach this one by `hostname` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+    /// Managed services started next to the deployment on its private network (optional)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addons: Vec<Addon>,
+}
+
+/// Membership of a deployment in a private network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Network name; deployments naming the same network reach each other
+    pub name: String,
+    /// Name the other members resolve this deployment's instances by
+    pub hostname: String,
+}
+
+/// Managed service a deployment can ask for, run in a container of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Addon {
+    Postgres,
+    Redis,
+}
+
+impl Addon {
+    /// Name as serialized, e.g. `postgres`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Redis => "redis",
+        }
+    }
+}
+
+/// npm-compatible registry, written to the sandbox's `.npmrc` and `bunfig.toml`
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PackageRegistry {
+    /// Registry URL, e.g. https://npm.pkg.github.com
+    pub url: String,
+    /// Auth token sent to the registry (optional)
+    pub token: Option<String>,
+    /// Scopes installed from this registry, e.g. @acme; without scopes it replaces the default registry
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+// Keeps the token out of logged requests
+impl std::fmt::Debug for PackageRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackageRegistry")
+            .field("url", &self.url)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReloadStrategy {
+    /// Kill the dev server and start it again after each file update
+    #[default]
+    Restart,
+    /// Run the dev server under a file watcher that reloads the code itself (bun and node)
+    Hot,
+}
+
+/// Access control of a deployment URL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub mode: AuthMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Anyone with the URL
+    #[default]
+    Public,
+    /// Callers presenting the access token generated at deploy time
+    Token,
+}
+
+/// Origins browsers may call from, for the API (`server.cors`) or a deployment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Allowed origins, e.g. https://app.example.com, or "*" for any
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in preflighted requests (default: any)
+    #[serde(default)]
+    pub allowed_methods: Option<Vec<String>>,
+    /// Request headers allowed in preflighted requests (default: any)
+    #[serde(default)]
+    pub allowed_headers: Option<Vec<String>>,
+    /// Response headers scripts may read beyond the CORS-safelisted ones
+    #[serde(default)]
+    pub expose_headers: Vec<String>,
+    /// Whether requests may carry cookies and credentials; needs explicit origins
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight answer (seconds)
+    #[serde(default)]
+    pub max_age_seconds: Option<u64>,
+}
+
+/// Rewrites the FaaS proxy applies to a deployment's requests
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingConfig {
+    /// Answer plain HTTP requests with a redirect to HTTPS (default: false)
+    #[serde(default)]
+    pub https_redirect: bool,
+    /// Rules applied in order before the request is forwarded
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+}
+
+/// One rewrite of a proxied request, e.g. `{"action": "strip_prefix", "prefix": "/api"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RoutingRule {
+    /// Set a request header, replacing any the client sent
+    AddHeader { name: String, value: String },
+    /// Drop a request header
+    RemoveHeader { name: String },
+    /// Remove a leading path prefix, e.g. `/api` for a service serving at `/`
+    StripPrefix { prefix: String },
+    /// Set a request header to the value of one of the deployment's `env_vars`, so the
+    /// secret stays on the server
+    InjectSecret { name: String, env_var: String },
+}
+
+/// Readiness check run against the deployed service before it receives traffic
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    /// HTTP path to request (default: /)
+    pub path: Option<String>,
+    /// Port to probe (default: the deployment's port)
+    pub port: Option<u16>,
+    /// Give up after this long (ms, default: 30000)
+    pub timeout_ms: Option<u64>,
+    /// Delay between probes (ms, default: 500)
+    pub interval_ms: Option<u64>,
+}
+
+/// Proxy response cache settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Maximum number of cached responses (default: 256)
+    pub max_entries: Option<usize>,
+    /// Largest response body that is cached (bytes, default: 1048576)
+    pub max_entry_bytes: Option<usize>,
+    /// Freshness of responses without Cache-Control (seconds, default: not cached)
+    pub default_ttl_seconds: Option<u64>,
+}
+
+/// Auto-scaling configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoScaleConfig {
+    /// Scale down after inactivity (minutes, default: 10)
+    pub scale_down_after_minutes: Option<u32>,
+    /// Maximum number of sandbox instances serving the deployment (default: 1)
+    pub max_instances: Option<u32>,
+    /// In-flight requests per instance before another instance is started (default: 10)
+    pub target_concurrency: Option<u32>,
+    /// Keep each client on one instance through a session cookie (default: false)
+    pub sticky_sessions: Option<bool>,
+    /// Proxied paths whose requests do not count as activity, e.g. a polled `/health`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_ignore_paths: Option<Vec<String>>,
+}
+
+impl AutoScaleConfig {
+    pub fn max_instances(&self) -> usize {
+        self.max_instances.unwrap_or(1).max(1) as usize
+    }
+
+    pub fn target_concurrency(&self) -> usize {
+        self.target_concurrency.unwrap_or(10).max(1) as usize
+    }
+
+    pub fn sticky_sessions(&self) -> bool {
+        self.sticky_sessions.unwrap_or(false)
+    }
+
+    /// Whether a request to `path` within the deployment keeps it from being idle;
+    /// an ignored path also covers the paths below it
+    pub fn counts_as_activity(&self, path: &str) -> bool {
+        !self.idle_ignore_paths.iter().flatten().any(|ignored| {
+            path.strip_prefix(ignored.trim_end_matches('/'))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+/// File update request for running deployments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUpdateRequest {
+    /// Files to update or add
+    pub files: Vec<SandboxFile>,
+    /// Whether to restart the dev server after update (default: true, or false with hot reload)
+    pub restart_dev_server: Option<bool>,
+}
+
+/// FaaS deployment response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentResponse {
+    /// Unique deployment ID
+    pub deployment_id: String,
+    /// Public URL to access the service
+    pub url: String,
+    /// Internal sandbox ID
+    pub sandbox_id: String,
+    /// Deployment status
+    pub status: DeploymentStatus,
+    /// Created timestamp
+    pub created_at: DateTime<Utc>,
+    /// Runtime information
+    pub runtime: String,
+    /// Memory allocation
+    pub memory_mb: u32,
+    /// Number of sandbox instances currently serving the deployment
+    pub instances: usize,
+    /// Most instances auto-scaling may start
+    pub max_instances: usize,
+    /// Last time a request was proxied to the deployment
+    pub last_accessed: DateTime<Utc>,
+    /// Why the deployment failed, if it did
+    pub error: Option<String>,
+    /// Stage the deployment failed at, with `status: Failed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<FailureReason>,
+    /// Last 100 dev server log lines, captured when the deployment failed
+    pub logs: Option<String>,
+    /// Token callers of the URL must present, with `auth.mode = "token"`; only in the deploy response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+/// Filters and page of `GET /faas/deployments`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentListQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<DeploymentStatus>,
+    /// Runtime name, e.g. `bun`, or with a version, e.g. `node@20`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    /// Only deployments created after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Comma-separated `key=value` labels, or bare keys, that deployments must all carry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Page size (default: 100, at most 1000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Page of deployments, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentList {
+    pub deployments: Vec<DeploymentResponse>,
+    /// Cursor of the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Traffic through the FaaS proxy to a deployment and usage of its instances
+/// (GET /faas/deployments/{id}/stats)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentStats {
+    pub deployment_id: String,
+    /// Start of the counted period: the deployment's creation or the service's start
+    pub since: DateTime<Utc>,
+    pub requests: u64,
+    /// Requests answered with a 5xx status, including sandboxes that did not answer
+    pub errors: u64,
+    /// `errors` out of `requests`, 0 to 1
+    pub error_rate: f64,
+    /// Requests by status class, `1xx` to `5xx`
+    pub responses: BTreeMap<String, u64>,
+    /// Median latency over the last 1000 requests
+    pub latency_p50_ms: Option<f64>,
+    /// 95th percentile latency over the last 1000 requests
+    pub latency_p95_ms: Option<f64>,
+    /// Latency of all requests added up
+    pub latency_sum_ms: f64,
+    /// Request bodies proxied to the deployment
+    pub request_bytes: u64,
+    /// Response bodies returned by the deployment
+    pub response_bytes: u64,
+    /// Requests waiting for a slot under `max_concurrent_requests` right now
+    #[serde(default)]
+    pub queued_requests: usize,
+    /// Requests answered with 429 because the queue was full or they waited too long
+    #[serde(default)]
+    pub rejected_requests: u64,
+    /// Lifetime of the deployment's instances added up, since `since`
+    #[serde(default)]
+    pub instance_seconds: f64,
+    /// Instance seconds weighted by each instance's memory limit
+    #[serde(default)]
+    pub memory_mb_seconds: f64,
+    /// What the instances cost at the service's configured prices, when it sets any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<CostEstimate>,
+}
+
+/// Request the FaaS proxy passed to a deployment (GET /faas/deployments/{id}/access-logs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    /// Path and query within the deployment, without its access token
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub client_ip: String,
+}
+
+/// Deployment status
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeploymentStatus {
+    Running,
+    /// Sandboxes stopped after inactivity; woken by the next proxied request
+    ScaledToZero,
+    /// The deployment could not be started; see `failure_reason`, `error` and `logs`
+    Failed,
+}
+
+/// Stage a failed deployment stopped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The backend could not create the sandbox
+    SandboxCreation,
+    /// Installing dependencies or starting the entry point failed
+    Setup,
+    /// The server started but did not pass the readiness check in time
+    HealthCheck,
+    /// An add-on container could not be started or did not become ready
+    Addons,
+}
+
+impl FailureReason {
+    /// Name as serialized, e.g. `health_check`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SandboxCreation => "sandbox_creation",
+            Self::Setup => "setup",
+            Self::HealthCheck => "health_check",
+            Self::Addons => "addons",
+        }
+    }
+}
+
+
+/// Built-in starter project listed by `GET /templates`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    /// Name passed as `POST /faas/deploy?template=<name>`
+    pub name: String,
+    pub description: String,
+    pub runtime: String,
+    /// Paths of the files the template deploys
+    pub files: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_ignore_paths() {
+        let auto_scale: AutoScaleConfig = serde_json::from_str(r#"{"idle_ignore_paths": ["/health", "/jobs/"]}"#).unwrap();
+        assert!(!auto_scale.counts_as_activity("/health"));
+        assert!(!auto_scale.counts_as_activity("/health/ready"));
+        assert!(!auto_scale.counts_as_activity("/jobs"));
+        assert!(auto_scale.counts_as_activity("/healthz"));
+        assert!(auto_scale.counts_as_activity("/"));
+    }
+}
@@ -0,0 +1,26 @@
+//! Canonical request and response models of the sandbox service HTTP API
+//!
+//! Shared by the server and `voidrun-client` so both sides agree on the wire format.
+
+pub mod admin;
+pub mod error;
+pub mod faas;
+pub mod sandbox;
+pub mod session;
+pub mod tools;
+
+pub use admin::{LogEntry, SandboxDetails, SandboxDetailsList};
+pub use error::ErrorResponse;
+pub use sandbox::{
+    BackendFeatures, BackendInfo, Capabilities, CompareRequest, CompareResult, CostEstimate, CreateSandboxRequest, Diagnostic,
+    DiagnosticSeverity, ExecutionResult, ExecutionUsage, FileEncoding, LineChange, LineChangeKind, OutputDiff, ReplayResult,
+    RuntimeInfo, SandboxFile, SandboxInfo, SandboxList, SandboxListQuery, SandboxMount, SandboxResponse, SnapshotRequest, SnapshotResponse,
+    SortOrder, TerminationReason, TestCase, TestFormat, TestRequest, TestResults, TestStatus,
+};
+pub use faas::{
+    AccessLogEntry, Addon, AuthConfig, AuthMode, AutoScaleConfig, CacheConfig, CorsConfig, DeploymentList, DeploymentListQuery,
+    DeploymentRequest, DeploymentResponse, DeploymentStats, DeploymentStatus, FailureReason, FileUpdateRequest,
+    HealthCheckConfig, NetworkConfig, PackageRegistry, ReloadStrategy, RoutingConfig, RoutingRule, TemplateInfo,
+};
+pub use session::{CreateSessionRequest, EvalError, EvalRequest, EvalResult, MimeBundle};
+pub use tools::{FormatResult, LintResult, ToolRequest};
@@ -0,0 +1,536 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// File written into a sandbox, used by sandbox creation, uploads and deployments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxFile {
+    /// Path relative to the project root
+    pub path: String,
+    pub content: String,
+    /// Whether the file is made executable (`executable` is accepted as well)
+    #[serde(alias = "executable")]
+    pub is_executable: Option<bool>,
+    /// How `content` is encoded (default: utf8)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<FileEncoding>,
+}
+
+/// Encoding of a file's content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    /// The content is the file's text
+    #[default]
+    Utf8,
+    /// The content is the file's bytes, base64-encoded, for binary files
+    Base64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateSandboxRequest {
+    /// Runtime name, optionally pinned to a version as `<runtime>@<version>` (e.g. `node@20`)
+    pub runtime: String,
+    pub code: String,
+    pub entry_point: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub memory_limit_mb: Option<u64>,
+    pub env_vars: Option<HashMap<String, String>>,
+    pub files: Option<Vec<SandboxFile>>,
+    pub mode: Option<String>, // "oneshot" or "persistent"
+    pub install_deps: Option<bool>,
+    pub dev_server: Option<bool>,
+    pub from_snapshot: Option<String>,
+    pub port: Option<u16>,
+    /// Glob patterns of output files to collect after execution, e.g. `dist/**`
+    pub artifacts: Option<Vec<String>>,
+    /// Size of the writable project directory in MB (default: 50, or 500 when persistent)
+    pub disk_limit_mb: Option<u64>,
+    /// Maximum number of processes in the sandbox (default: 256)
+    pub max_processes: Option<u64>,
+    /// Backend to run on, e.g. `nsjail` (default: chosen by the service)
+    pub backend: Option<String>,
+    /// Syscall restrictions: `strict` (default), `permissive` or `unconfined`
+    pub security_profile: Option<String>,
+    /// Absolute paths writable besides `/sandbox` (default: `/tmp`, plus `/home/sandbox` when persistent)
+    pub writable_paths: Option<Vec<String>>,
+    /// GPUs to pass through to the sandbox (Docker backend only; default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpus: Option<u32>,
+    /// Free-form `key: value` labels, e.g. `{"team": "ml"}`, to filter sandbox lists on
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// `false` runs a one-shot execution even when the service has its result cached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<bool>,
+    /// Media tools a bash script needs, `ffmpeg` or `imagemagick`, which run it on the
+    /// `media` runtime
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    /// Data sets the operator allows, mounted read-only (Docker backend only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mounts: Option<Vec<SandboxMount>>,
+    /// Image to run instead of the runtime's, e.g. `ghcr.io/org/custom:tag`, if the
+    /// operator allows it (Docker backend only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+/// Data set of `sandbox.allowed_mounts` to mount read-only into a sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxMount {
+    /// Name the operator gave the data set
+    pub name: String,
+    /// Absolute path to mount it at; defaults to the one configured for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Sandbox summary returned by the sandbox API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxInfo {
+    pub id: String,
+    pub status: String,
+    pub runtime: String,
+    pub created_at: String,
+    pub timeout_ms: u64,
+    pub memory_limit_mb: u64,
+    pub is_persistent: bool,
+    /// Proxy URL of the sandbox's dev server, if it runs one
+    pub dev_server_url: Option<String>,
+    /// Host port published for the sandbox's service
+    pub allocated_port: Option<u16>,
+    /// Backend the sandbox runs on
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Docker host the sandbox runs on, when the service spreads sandboxes over several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docker_host: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    /// When the last execution of a one-shot sandbox ended; the sandbox is removed once
+    /// `sandbox.finished_sandbox_ttl_seconds` have passed since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// How the sandbox's last execution ended, e.g. `oom_killed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<TerminationReason>,
+}
+
+/// Filters, order and page of `GET /sandbox`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SandboxListQuery {
+    /// Status as listed, e.g. `Running`; matched case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Runtime name, e.g. `bun`, or with a version, e.g. `node@20`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    /// `oneshot` or `persistent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Comma-separated `key=value` labels, or bare keys, that sandboxes must all carry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// `finished` to list one-shot sandboxes whose execution ended too, which are left
+    /// out unless a `status` is asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+    /// Order of creation times (default: newest first)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+    /// Page size (default: 100, at most 1000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page, listed with the same filters and order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Order of a list by creation time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Oldest first
+    Asc,
+    /// Newest first
+    #[default]
+    Desc,
+}
+
+/// Page of sandboxes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxList {
+    pub sandboxes: Vec<SandboxInfo>,
+    /// Sandboxes matching the filters, over all pages
+    pub total: usize,
+    /// Cursor of the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    pub sandbox_id: String,
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub execution_time_ms: u64,
+    /// The execution was canceled; the output is what it produced until then
+    #[serde(default)]
+    pub canceled: bool,
+    /// Resources the execution used, when the backend measures them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ExecutionUsage>,
+    /// How the execution ended, when the backend can tell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<TerminationReason>,
+    /// `memory_limit_exceeded` or `time_limit_exceeded` when a limit stopped the execution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Id the execution is kept under for replays (POST /executions/{id}/replay)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    /// What the execution cost at the service's configured prices, when it sets any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<CostEstimate>,
+    /// Presigned URL of the zip of the collected artifacts, when the service stores them in S3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifacts_url: Option<String>,
+    /// Problems the checker found, for sandboxes created with `mode: "check"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Diagnostic>>,
+}
+
+/// Estimated price of resources used, from the service's `[pricing]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    pub amount: f64,
+    /// Currency code the prices are configured in, e.g. `USD`
+    pub currency: String,
+}
+
+/// Resources an execution used
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionUsage {
+    /// Peak memory in bytes; in a persistent Docker sandbox, the sandbox's peak so far
+    pub peak_memory_bytes: Option<u64>,
+    /// CPU time spent in user and kernel mode
+    pub cpu_time_ms: Option<u64>,
+    pub stdout_bytes: u64,
+    pub stderr_bytes: u64,
+}
+
+/// How an execution ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// The program exited by itself, with whatever exit code
+    Completed,
+    /// Stopped at the request's time limit
+    Timeout,
+    /// Killed for exceeding the memory limit
+    OomKilled,
+    /// Killed by a signal, or canceled
+    Killed,
+    /// The program could not be started
+    SetupFailed,
+}
+
+impl TerminationReason {
+    pub const ALL: [Self; 5] = [Self::Completed, Self::Timeout, Self::OomKilled, Self::Killed, Self::SetupFailed];
+
+    /// Name as serialized, e.g. `oom_killed`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Completed => "completed",
+            Self::Timeout => "timeout",
+            Self::OomKilled => "oom_killed",
+            Self::Killed => "killed",
+            Self::SetupFailed => "setup_failed",
+        }
+    }
+
+    /// Error code of an execution a limit of the sandbox stopped
+    pub fn error_code(&self) -> Option<&'static str> {
+        match self {
+            Self::Timeout => Some("time_limit_exceeded"),
+            Self::OomKilled => Some("memory_limit_exceeded"),
+            _ => None,
+        }
+    }
+}
+
+/// Output of a one-shot execution (POST /execute)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxResponse {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub execution_time_ms: u64,
+    pub is_running: Option<bool>,
+    pub dev_server_url: Option<String>,
+    /// The execution was canceled; the output is what it produced until then
+    #[serde(default)]
+    pub canceled: bool,
+    /// Resources the execution used, when the backend measures them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ExecutionUsage>,
+    /// How the execution ended, when the backend can tell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<TerminationReason>,
+    /// Id the execution is kept under for replays (POST /executions/{id}/replay)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    /// What the execution cost at the service's configured prices, when it sets any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<CostEstimate>,
+    /// Problems the checker found, with `mode: "check"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Diagnostic>>,
+}
+
+/// Problem found in the code by `mode: "check"`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// File relative to the project root, e.g. `index.ts`
+    pub file: String,
+    /// 1-based line, when the checker reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// 1-based column, when the checker reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    pub severity: DiagnosticSeverity,
+    /// Checker's code for the problem, e.g. `TS2322` or `E0425`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+/// A past execution run again next to the original (POST /executions/{id}/replay)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResult {
+    /// Id of the replayed execution
+    pub execution_id: String,
+    pub original: ExecutionResult,
+    pub replay: ExecutionResult,
+    /// Same output, exit code and termination reason
+    pub identical: bool,
+    pub stdout_diff: OutputDiff,
+    pub stderr_diff: OutputDiff,
+}
+
+/// Two variants of the code run side by side (POST /execute/compare)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompareRequest {
+    /// Runtime, baseline `code`, and the files, env vars and limits both variants run with
+    #[serde(flatten)]
+    pub request: CreateSandboxRequest,
+    /// Code run in place of `code` as the variant
+    pub variant_code: String,
+}
+
+/// Both variants' results with the differences between them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompareResult {
+    pub baseline: ExecutionResult,
+    pub variant: ExecutionResult,
+    /// Same output, exit code and termination reason
+    pub identical: bool,
+    pub same_exit_code: bool,
+    pub stdout_diff: OutputDiff,
+    pub stderr_diff: OutputDiff,
+}
+
+/// Test run in a sandbox (POST /sandbox/{id}/test)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TestRequest {
+    /// Shell command running the tests from the project root; defaults to the runtime's,
+    /// e.g. `bun test` or `npm test`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+/// Outcome of a test run, with the tests parsed from its output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestResults {
+    #[serde(flatten)]
+    pub execution: ExecutionResult,
+    /// Format the tests were parsed from; absent when the output matched none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<TestFormat>,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub tests: Vec<TestCase>,
+}
+
+/// Test output formats results are parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestFormat {
+    /// Test Anything Protocol, e.g. `node --test --test-reporter=tap`
+    Tap,
+    /// JUnit XML printed to the output
+    Junit,
+    /// `bun test`'s default output
+    Bun,
+    /// `cargo test`'s default output
+    Cargo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    /// File, class or `describe` block the test belongs to, when the format names one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suite: Option<String>,
+    pub status: TestStatus,
+    /// How long the test took, when the format reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    /// Failure message or skip reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// Lines of an output that differ between two executions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputDiff {
+    pub identical: bool,
+    /// Removed and added lines, in output order
+    pub changes: Vec<LineChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineChange {
+    pub kind: LineChangeKind,
+    /// 1-based line number, in the original output for removed lines and in the new one
+    /// for added lines
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineChangeKind {
+    /// Only in the original output
+    Removed,
+    /// Only in the new output
+    Added,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    pub sandbox_id: String,
+    pub name: String,
+    pub snapshot: String,
+}
+
+/// Runtimes, backends and features available on the instance, reported by `GET /runtimes`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub runtimes: Vec<RuntimeInfo>,
+    pub backends: Vec<BackendInfo>,
+}
+
+/// Runtime and the versions it can be pinned to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    pub name: String,
+    /// Other names accepted for the runtime
+    pub aliases: Vec<String>,
+    /// Version used when the runtime is not pinned
+    pub default_version: String,
+    /// Versions accepted as `<runtime>@<version>` that an available backend can run
+    pub versions: Vec<String>,
+    /// Whether an available backend can run the runtime at all
+    pub available: bool,
+}
+
+/// Sandbox backend of the instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendInfo {
+    /// Backend name, as in the `[sandbox] backend` setting
+    pub name: String,
+    /// Whether the backend passed its availability check
+    pub available: bool,
+    pub features: BackendFeatures,
+    /// Versions the backend can run, by runtime name
+    pub runtimes: std::collections::BTreeMap<String, Vec<String>>,
+}
+
+/// What sandboxes on a backend can do
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackendFeatures {
+    /// Long-running dev servers reachable through the proxy
+    pub dev_server: bool,
+    /// Sandboxes that keep running between executions
+    pub persistent: bool,
+    /// Outbound network access (persistent sandboxes only)
+    pub network: bool,
+    /// Interactive terminals
+    pub terminal: bool,
+    pub snapshots: bool,
+    /// GPUs passed through to sandboxes
+    #[serde(default)]
+    pub gpu: bool,
+    /// Headless browsers, for the `playwright` runtime
+    #[serde(default)]
+    pub browser: bool,
+    /// FFmpeg and ImageMagick, for the `media` runtime
+    #[serde(default)]
+    pub media: bool,
+    /// Read-only mounts of the host directories in `sandbox.allowed_mounts`
+    #[serde(default)]
+    pub mounts: bool,
+    /// Custom images from `sandbox.allowed_images`
+    #[serde(default)]
+    pub images: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_accepts_both_executable_spellings() {
+        let file: SandboxFile = serde_json::from_str(r#"{"path": "run.sh", "content": "", "executable": true}"#).unwrap();
+        assert_eq!(file.is_executable, Some(true));
+
+        let file: SandboxFile = serde_json::from_str(r#"{"path": "run.sh", "content": "", "is_executable": true}"#).unwrap();
+        assert_eq!(file.is_executable, Some(true));
+    }
+
+    #[test]
+    fn test_compare_request_shares_execute_fields() {
+        let request: CompareRequest = serde_json::from_str(
+            r#"{"runtime": "node", "code": "a()", "variant_code": "b()", "env_vars": {"SEED": "1"}, "timeout_ms": 5000}"#,
+        ).unwrap();
+        assert_eq!(request.request.code, "a()");
+        assert_eq!(request.variant_code, "b()");
+        assert_eq!(request.request.timeout_ms, Some(5000));
+        assert_eq!(request.request.env_vars.unwrap()["SEED"], "1");
+    }
+}
@@ -0,0 +1,67 @@
+//! REPL sessions (`/sessions`): a runtime process keeping its variables between evaluations
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::sandbox::SandboxFile;
+
+/// Session to start (POST /sessions)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateSessionRequest {
+    /// `node` or `bun`, optionally pinned as `<runtime>@<version>` (default: node)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    /// Time limit of each evaluation (default: 30000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_vars: Option<HashMap<String, String>>,
+    /// Files the evaluated code can `require` or read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<SandboxFile>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+/// Code to evaluate in a session (POST /sessions/{id}/eval)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvalRequest {
+    pub code: String,
+    /// Time limit of this evaluation (default: the session's)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Value keyed by MIME type, e.g. `{"text/plain": "[ 1, 2 ]", "application/json": [1, 2]}`
+pub type MimeBundle = Map<String, Value>;
+
+/// Outcome of an evaluation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalResult {
+    /// The code ran without throwing
+    pub success: bool,
+    /// Value of the last expression, unless it is `undefined`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<MimeBundle>,
+    /// Values passed to `display()`, in order
+    #[serde(default)]
+    pub outputs: Vec<MimeBundle>,
+    pub stdout: String,
+    pub stderr: String,
+    /// What the code threw
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<EvalError>,
+    pub execution_time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalError {
+    /// e.g. `ReferenceError`
+    pub name: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
+}
@@ -0,0 +1,34 @@
+//! Code tools run in short-lived sandboxes (`/tools/...`)
+
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::{Diagnostic, SandboxFile};
+
+/// Code to format (POST /tools/format) or lint (POST /tools/lint)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolRequest {
+    pub code: String,
+    /// Name the code is written under, relative to the project root; its extension picks
+    /// the language (default: `index.js`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// Further files, such as a `.prettierrc` or `eslint.config.js`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<SandboxFile>>,
+}
+
+/// Code as prettier formats it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormatResult {
+    pub formatted: String,
+    /// The formatted code differs from the code sent
+    pub changed: bool,
+}
+
+/// Problems eslint found in the code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintResult {
+    /// No problem is an error
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
@@ -1,5 +1,4 @@
 use serde_json::json;
-use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -11,7 +10,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Health check
     println!("\n1. Health Check");
-    let health_response = client.get(&format!("{}/health", base_url)).send().await?;
+    let health_response = client.get(format!("{}/health", base_url)).send().await?;
     println!("Status: {}", health_response.status());
     println!("Response: {}", health_response.text().await?);
 
@@ -25,7 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let create_response = client
-        .post(&format!("{}/sandbox", base_url))
+        .post(format!("{}/sandbox", base_url))
         .json(&create_request)
         .send()
         .await?;
@@ -39,7 +38,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Execute the sandbox
     println!("\n3. Executing Node.js Code");
     let execute_response = client
-        .post(&format!("{}/sandbox/{}/execute", base_url, sandbox_id))
+        .post(format!("{}/sandbox/{}/execute", base_url, sandbox_id))
         .send()
         .await?;
 
@@ -56,7 +55,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let ts_create_response = client
-        .post(&format!("{}/sandbox", base_url))
+        .post(format!("{}/sandbox", base_url))
         .json(&ts_create_request)
         .send()
         .await?;
@@ -69,7 +68,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Execute TypeScript code
     println!("\n5. Executing TypeScript Code");
     let ts_execute_response = client
-        .post(&format!("{}/sandbox/{}/execute", base_url, ts_sandbox_id))
+        .post(format!("{}/sandbox/{}/execute", base_url, ts_sandbox_id))
         .send()
         .await?;
 
@@ -86,7 +85,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let bun_create_response = client
-        .post(&format!("{}/sandbox", base_url))
+        .post(format!("{}/sandbox", base_url))
         .json(&bun_create_request)
         .send()
         .await?;
@@ -99,7 +98,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Execute Bun code
     println!("\n7. Executing Bun Code");
     let bun_execute_response = client
-        .post(&format!("{}/sandbox/{}/execute", base_url, bun_sandbox_id))
+        .post(format!("{}/sandbox/{}/execute", base_url, bun_sandbox_id))
         .send()
         .await?;
 
@@ -108,7 +107,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // List all sandboxes
     println!("\n8. Listing All Sandboxes");
-    let list_response = client.get(&format!("{}/sandbox", base_url)).send().await?;
+    let list_response = client.get(format!("{}/sandbox", base_url)).send().await?;
     let sandboxes: serde_json::Value = list_response.json().await?;
     println!("All sandboxes: {}", serde_json::to_string_pretty(&sandboxes)?);
 
@@ -116,7 +115,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n9. Cleaning Up Sandboxes");
     for sandbox_id in [sandbox_id, ts_sandbox_id, bun_sandbox_id] {
         let delete_response = client
-            .delete(&format!("{}/sandbox/{}", base_url, sandbox_id))
+            .delete(format!("{}/sandbox/{}", base_url, sandbox_id))
             .send()
             .await?;
         println!("Deleted sandbox {}: {}", sandbox_id, delete_response.status());
@@ -132,7 +131,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let error_create_response = client
-        .post(&format!("{}/sandbox", base_url))
+        .post(format!("{}/sandbox", base_url))
         .json(&error_request)
         .send()
         .await?;
@@ -141,7 +140,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let error_sandbox_id = error_sandbox_info["id"].as_str().unwrap();
 
     let error_execute_response = client
-        .post(&format!("{}/sandbox/{}/execute", base_url, error_sandbox_id))
+        .post(format!("{}/sandbox/{}/execute", base_url, error_sandbox_id))
         .send()
         .await?;
 
@@ -150,7 +149,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Clean up error sandbox
     client
-        .delete(&format!("{}/sandbox/{}", base_url, error_sandbox_id))
+        .delete(format!("{}/sandbox/{}", base_url, error_sandbox_id))
         .send()
         .await?;
 
@@ -1,6 +1,5 @@
 use sandbox_service::sandbox::backend::{create_backend, SandboxBackendType};
-use sandbox_service::sandbox::{SandboxRequest, SandboxResponse};
-use std::collections::HashMap;
+use sandbox_service::sandbox::{PortAllocator, SandboxRequest};
 use uuid::Uuid;
 
 #[tokio::main]
@@ -8,7 +7,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🧪 Testing Docker Backend Directly");
     
     // Test Docker backend directly
-    let backend = create_backend(SandboxBackendType::Docker)?;
+    let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default())?;
     
     if !backend.is_available().await {
         println!("❌ Docker backend is not available");
@@ -18,15 +17,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("✅ Docker backend is available");
     
     // Create a test request
-    let request = SandboxRequest {
-        id: Uuid::new_v4().to_string(),
-        runtime: "node".to_string(),
-        code: "console.log('Hello from direct test!'); console.log('Success!');".to_string(),
-        entry_point: None,
-        timeout_ms: 5000,
-        memory_limit_mb: 128,
-        env_vars: HashMap::new(),
-    };
+    let request: SandboxRequest = serde_json::from_value(serde_json::json!({
+        "id": Uuid::new_v4().to_string(),
+        "runtime": "node",
+        "code": "console.log('Hello from direct test!'); console.log('Success!');",
+        "timeout_ms": 5000,
+        "memory_limit_mb": 128,
+        "env_vars": {},
+    }))?;
     
     println!("🔨 Creating sandbox: {}", request.id);
     
@@ -1,37 +1,92 @@
 use super::*;
 use axum::{
     extract::{Path, State, Query},
-    response::{Html, Json},
-    http::StatusCode,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        Html, IntoResponse, Json,
+    },
 };
+use futures_util::stream::{BoxStream, StreamExt};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
 use serde_json::json;
 use std::collections::HashMap;
 
+use crate::api::error::ApiError;
+use crate::api::public_url::BaseUrl;
+use crate::api::SandboxListQuery;
+use crate::metering::Meter;
+use crate::sandbox::backend::SandboxHealth;
+use crate::sandbox::gc::OrphanReport;
+use crate::sandbox::listing;
 use crate::sandbox::manager::SandboxManager;
 use crate::admin::ui::ADMIN_UI_HTML;
-use crate::sandbox::SandboxMode;
+use crate::alerts::Alerts;
+use crate::backup::{BackupSummary, Backups, RestoreReport};
+use crate::reload::{ConfigReloader, ReloadReport};
 
 pub async fn admin_ui() -> Html<&'static str> {
     Html(ADMIN_UI_HTML)
 }
 
+/// Effective configuration (file or environment, command line overrides and reloads), secrets redacted
+pub async fn get_config(State(reloader): State<Arc<ConfigReloader>>) -> Json<serde_json::Value> {
+    Json(json!({
+        "source": reloader.source(),
+        "config": reloader.current().redacted(),
+    }))
+}
+
+/// Re-read the configuration file and apply what can change without a restart
+pub async fn reload_config(
+    State(reloader): State<Arc<ConfigReloader>>,
+) -> Result<Json<ReloadReport>, ApiError> {
+    match reloader.reload().await {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => {
+            error!("Failed to reload configuration: {:#}", e);
+            Err(ApiError::Validation(format!("{:#}", e)))
+        }
+    }
+}
+
+/// Alert rules and the alerts firing right now
+pub async fn get_alerts(State(alerts): State<Arc<Alerts>>) -> Json<serde_json::Value> {
+    Json(json!({
+        "sample_interval_seconds": alerts.config().sample_interval_seconds,
+        "rules": alerts.config().rules,
+        "firing": alerts.firing(),
+    }))
+}
+
+/// Backups in the backup directory, newest first
+pub async fn list_backups(State(backups): State<Arc<Backups>>) -> Result<Json<Vec<BackupSummary>>, ApiError> {
+    Ok(Json(backups.list().await?))
+}
+
+/// Back up the deployments now instead of at the next interval
+pub async fn create_backup(State(backups): State<Arc<Backups>>) -> Result<Json<BackupSummary>, ApiError> {
+    Ok(Json(backups.create().await?))
+}
+
+/// Deploy the deployments of a backup that are missing again
+pub async fn restore_backup(
+    State(backups): State<Arc<Backups>>,
+    body: Option<Json<RestoreRequest>>,
+) -> Result<Json<RestoreReport>, ApiError> {
+    let Json(request) = body.unwrap_or_default();
+    Ok(Json(backups.restore(request.backup.as_deref()).await?))
+}
+
 pub async fn get_system_status(
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<SystemStatus>, StatusCode> {
-    let manager = app_state.read().await;
-    
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Json<SystemStatus>, ApiError> {
     let uptime = SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .map_err(|e| {
-            error!("Failed to get system time: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
+        .map_err(|e| ApiError::internal(format!("Failed to get system time: {}", e)))?
         .as_secs();
     
-    let active_sandboxes = manager.list_sandboxes().await.len() as u32;
+    let active_sandboxes = app_state.list_sandboxes().await.len() as u32;
     
     // Try to get real system resource usage, fallback to unavailable if fails
     let memory_usage = get_system_memory_usage().await
@@ -58,16 +113,18 @@ pub async fn get_system_status(
         uptime,
         active_sandboxes,
         total_sandboxes_created: active_sandboxes, // TODO: Implement persistent counter
-        backend_type: format!("{:?}", manager.get_backend_type()),
+        backend_type: format!("{:?}", app_state.get_backend_type()),
         version: env!("CARGO_PKG_VERSION").to_string(),
         memory_usage,
         cpu_usage,
+        docker_hosts: app_state.host_loads().await,
     };
     
     Ok(Json(status))
 }
 
 // Helper function to extract numbers from lines like "Pages free: 12345."
+#[cfg(target_os = "macos")]
 fn extract_number_from_line(line: &str) -> u64 {
     line.split_whitespace()
         .find(|part| part.chars().all(|c| c.is_ascii_digit() || c == '.'))
@@ -75,7 +132,7 @@ fn extract_number_from_line(line: &str) -> u64 {
         .unwrap_or(0)
 }
 
-async fn get_system_memory_usage() -> Result<ResourceUsage, String> {
+pub async fn get_system_memory_usage() -> Result<ResourceUsage, String> {
     #[cfg(target_os = "linux")]
     {
         use std::fs;
@@ -180,7 +237,7 @@ async fn get_system_memory_usage() -> Result<ResourceUsage, String> {
 }
 
 
-async fn get_system_cpu_usage() -> Result<ResourceUsage, String> {
+pub async fn get_system_cpu_usage() -> Result<ResourceUsage, String> {
     #[cfg(target_os = "linux")]
     {
         use std::fs;
@@ -320,242 +377,6 @@ fn parse_iostat_cpu(iostat_output: &str) -> Option<f64> {
     None
 }
 
-async fn get_sandbox_cpu_usage(sandbox_id: &str) -> Result<f64, String> {
-    #[cfg(feature = "docker")]
-    {
-        use bollard::Docker;
-        use bollard::container::StatsOptions;
-        use futures_util::StreamExt;
-        
-        let docker = Docker::connect_with_local_defaults()
-            .map_err(|e| format!("Failed to connect to Docker: {}", e))?;
-        
-        let options = StatsOptions {
-            stream: false,
-            one_shot: true,
-        };
-        
-        let mut stream = docker.stats(sandbox_id, Some(options));
-        
-        if let Some(result) = stream.next().await {
-            let stats = result
-                .map_err(|e| format!("Failed to get container stats: {}", e))?;
-            
-            // Calculate CPU usage percentage
-            let cpu_usage = {
-                let cpu_delta = stats.cpu_stats.cpu_usage.total_usage - stats.precpu_stats.cpu_usage.total_usage;
-                let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) - stats.precpu_stats.system_cpu_usage.unwrap_or(0);
-                
-                if system_delta > 0 {
-                    let cpu_count = stats.cpu_stats.cpu_usage.percpu_usage.as_ref().map(|v| v.len()).unwrap_or(1);
-                    (cpu_delta as f64 / system_delta as f64) * cpu_count as f64 * 100.0
-                } else {
-                    0.0
-                }
-            };
-            
-            Ok(cpu_usage)
-        } else {
-            Err("No stats available for container".to_string())
-        }
-    }
-    
-    #[cfg(not(feature = "docker"))]
-    {
-        Err(format!("Docker feature not enabled for CPU monitoring of sandbox {}", sandbox_id))
-    }
-}
-
-async fn get_container_stats(sandbox_id: &str) -> Result<serde_json::Value, String> {
-    #[cfg(feature = "docker")]
-    {
-        use bollard::Docker;
-        use bollard::container::StatsOptions;
-        use futures_util::StreamExt;
-        
-        let docker = Docker::connect_with_local_defaults()
-            .map_err(|e| format!("Failed to connect to Docker: {}", e))?;
-        
-        let options = StatsOptions {
-            stream: false,
-            one_shot: true,
-        };
-        
-        let mut stream = docker.stats(sandbox_id, Some(options));
-        
-        if let Some(result) = stream.next().await {
-            let stats = result
-                .map_err(|e| format!("Failed to get container stats: {}", e))?;
-            
-            // Calculate memory usage
-            let (memory_used, memory_limit, memory_percentage) = {
-                let used = stats.memory_stats.usage.unwrap_or(0) as f64;
-                let limit = stats.memory_stats.limit.unwrap_or(0) as f64;
-                let percentage = if limit > 0.0 { (used / limit) * 100.0 } else { 0.0 };
-                (used / 1024.0 / 1024.0, limit / 1024.0 / 1024.0, percentage) // Convert to MB
-            };
-            
-            // Calculate CPU usage
-            let cpu_percentage = {
-                let cpu_delta = stats.cpu_stats.cpu_usage.total_usage - stats.precpu_stats.cpu_usage.total_usage;
-                let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) - stats.precpu_stats.system_cpu_usage.unwrap_or(0);
-                
-                if system_delta > 0 {
-                    let cpu_count = stats.cpu_stats.cpu_usage.percpu_usage.as_ref().map(|v| v.len()).unwrap_or(1);
-                    (cpu_delta as f64 / system_delta as f64) * cpu_count as f64 * 100.0
-                } else {
-                    0.0
-                }
-            };
-            
-            // Calculate network usage
-            let (bytes_in, bytes_out) = if let Some(networks) = &stats.networks {
-                let mut total_rx = 0;
-                let mut total_tx = 0;
-                for (_, network) in networks {
-                    total_rx += network.rx_bytes;
-                    total_tx += network.tx_bytes;
-                }
-                (total_rx, total_tx)
-            } else {
-                (0, 0)
-            };
-            
-            // Calculate disk usage (block I/O)
-            let (disk_read, disk_write) = if let Some(io_service_bytes_recursive) = &stats.blkio_stats.io_service_bytes_recursive {
-                let mut total_read = 0;
-                let mut total_write = 0;
-                
-                for entry in io_service_bytes_recursive {
-                    if entry.op == "read" {
-                        total_read += entry.value;
-                    } else if entry.op == "write" {
-                        total_write += entry.value;
-                    }
-                }
-                (total_read, total_write)
-            } else {
-                (0, 0)
-            };
-            
-            let resources = json!({
-                "memory": {
-                    "used": memory_used,
-                    "limit": memory_limit,
-                    "percentage": memory_percentage
-                },
-                "cpu": {
-                    "percentage": cpu_percentage,
-                    "cores": cpu_percentage / 100.0
-                },
-                "disk": {
-                    "read_bytes": disk_read,
-                    "write_bytes": disk_write,
-                    "used": (disk_read + disk_write) as f64 / 1024.0 / 1024.0, // Convert to MB
-                    "limit": 1024.0, // Default limit
-                    "percentage": ((disk_read + disk_write) as f64 / 1024.0 / 1024.0 / 1024.0) * 100.0
-                },
-                "network": {
-                    "bytes_in": bytes_in,
-                    "bytes_out": bytes_out
-                }
-            });
-            
-            Ok(resources)
-        } else {
-            Err("No stats available for container".to_string())
-        }
-    }
-    
-    #[cfg(not(feature = "docker"))]
-    {
-        Err(format!("Docker feature not enabled for stats of sandbox {}", sandbox_id))
-    }
-}
-
-async fn get_container_logs(sandbox_id: &str, lines: u32) -> Result<Vec<LogEntry>, String> {
-    #[cfg(feature = "docker")]
-    {
-        use bollard::Docker;
-        use bollard::container::LogsOptions;
-        use futures_util::StreamExt;
-        use chrono::{DateTime, Utc};
-        
-        let docker = Docker::connect_with_local_defaults()
-            .map_err(|e| format!("Failed to connect to Docker: {}", e))?;
-        
-        let options = LogsOptions::<String> {
-            follow: false,
-            stdout: true,
-            stderr: true,
-            since: 0,
-            until: 0,
-            timestamps: true,
-            tail: lines.to_string(),
-        };
-        
-        let mut stream = docker.logs(sandbox_id, Some(options));
-        let mut logs = Vec::new();
-        
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(log_output) => {
-                    let (level, message) = match log_output {
-                        bollard::container::LogOutput::StdOut { message } => {
-                            ("INFO", String::from_utf8_lossy(&message).to_string())
-                        }
-                        bollard::container::LogOutput::StdErr { message } => {
-                            ("ERROR", String::from_utf8_lossy(&message).to_string())
-                        }
-                        bollard::container::LogOutput::StdIn { message } => {
-                            ("INPUT", String::from_utf8_lossy(&message).to_string())
-                        }
-                        bollard::container::LogOutput::Console { message } => {
-                            ("CONSOLE", String::from_utf8_lossy(&message).to_string())
-                        }
-                    };
-                    
-                    // Parse timestamp if present
-                    let (timestamp, clean_message) = if let Some(space_pos) = message.find(' ') {
-                        let timestamp_str = &message[..space_pos];
-                        let msg = &message[space_pos + 1..];
-                        
-                        // Try to parse the timestamp
-                        if let Ok(parsed_time) = DateTime::parse_from_rfc3339(timestamp_str) {
-                            (parsed_time.to_rfc3339(), msg.to_string())
-                        } else {
-                            (Utc::now().to_rfc3339(), message)
-                        }
-                    } else {
-                        (Utc::now().to_rfc3339(), message)
-                    };
-                    
-                    logs.push(LogEntry {
-                        timestamp,
-                        level: level.to_string(),
-                        message: clean_message.trim().to_string(),
-                        sandbox_id: Some(sandbox_id.to_string()),
-                    });
-                }
-                Err(e) => {
-                    error!("Error reading container logs: {}", e);
-                    break;
-                }
-            }
-        }
-        
-        // Sort logs by timestamp (newest first)
-        logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        
-        Ok(logs)
-    }
-    
-    #[cfg(not(feature = "docker"))]
-    {
-        Err(format!("Docker feature not enabled for logs of sandbox {} (requested {} lines)", sandbox_id, lines))
-    }
-}
-
 async fn get_system_logs_impl(lines: u32) -> Result<Vec<LogEntry>, String> {
     use std::fs;
     use std::process::Command;
@@ -800,176 +621,229 @@ async fn make_api_request(request: ApiTestRequest) -> Result<ApiResponse, String
     })
 }
 
-pub async fn list_sandboxes(
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<Vec<SandboxInfo>>, StatusCode> {
-    let manager = app_state.read().await;
-    let sandboxes = manager.get_all_sandboxes().await;
-    
-    // Only log when there are sandboxes to avoid spamming logs
-    if sandboxes.len() > 0 {
-        debug!("Admin: Found {} sandboxes", sandboxes.len());
-        for sandbox in &sandboxes {
-            debug!("Admin: Sandbox ID: {}, Status: {:?}", sandbox.id, sandbox.status);
-        }
-    } else {
-        debug!("Admin: No active sandboxes found");
+async fn sandbox_details(app_state: &SandboxManager, sandbox: &crate::sandbox::Sandbox, base_url: &str) -> SandboxDetails {
+    SandboxDetails {
+        sandbox: sandbox.to_info_at(base_url),
+        uptime: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() - sandbox.created_at.timestamp() as u64)
+            .unwrap_or(0),
+        cpu_percentage: app_state.sandbox_stats(&sandbox.id).await.map(|stats| stats.cpu_percentage).unwrap_or(0.0),
+        container_id: sandbox.container_id.clone(),
     }
-    
-    let mut sandbox_infos = Vec::new();
-    
-    for sandbox in sandboxes {
-        let info = SandboxInfo {
-            id: sandbox.id.clone(),
-            status: format!("{:?}", sandbox.status),
-            runtime: sandbox.request.runtime.clone(),
-            created_at: sandbox.created_at.to_rfc3339(),
-            uptime: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map(|d| d.as_secs() - sandbox.created_at.timestamp() as u64)
-                .unwrap_or(0),
-            memory_mb: sandbox.request.memory_limit_mb,
-            cpu_percentage: get_sandbox_cpu_usage(&sandbox.id).await.unwrap_or(0.0),
-            dev_server_url: if sandbox.request.dev_server.unwrap_or(false) && matches!(sandbox.request.mode, Some(SandboxMode::Persistent)) {
-                Some(format!("http://127.0.0.1:8070/proxy/{}/", sandbox.id))
-            } else {
-                None
-            },
-            allocated_port: sandbox.dev_server_port,
-            is_persistent: matches!(sandbox.request.mode, Some(SandboxMode::Persistent)),
-            container_id: sandbox.container_id.clone(),
-        };
-        sandbox_infos.push(info);
+}
+
+fn sandbox_not_found(sandbox_id: &str) -> ApiError {
+    ApiError::not_found(format!("Sandbox {} not found", sandbox_id))
+}
+
+/// One page of sandboxes with their live details, filtered and ordered like `GET /sandbox`
+pub async fn list_sandboxes(
+    State(app_state): State<Arc<SandboxManager>>,
+    Query(query): Query<SandboxListQuery>,
+    BaseUrl(base_url): BaseUrl,
+) -> Result<Json<SandboxDetailsList>, ApiError> {
+    let page = listing::page(app_state.get_all_sandboxes().await, &query)
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+    debug!("Admin: Listing {} of {} sandboxes", page.sandboxes.len(), page.total);
+
+    // Live details cost a backend call per sandbox, so only the page gets them
+    let mut sandboxes = Vec::with_capacity(page.sandboxes.len());
+    for sandbox in &page.sandboxes {
+        sandboxes.push(sandbox_details(&app_state, sandbox, &base_url).await);
     }
-    
-    Ok(Json(sandbox_infos))
+
+    Ok(Json(SandboxDetailsList { sandboxes, total: page.total, next_cursor: page.next_cursor }))
 }
 
 pub async fn get_sandbox_info(
     Path(sandbox_id): Path<String>,
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<SandboxInfo>, StatusCode> {
-    let manager = app_state.read().await;
-    let sandboxes = manager.get_all_sandboxes().await;
+    State(app_state): State<Arc<SandboxManager>>,
+    BaseUrl(base_url): BaseUrl,
+) -> Result<Json<SandboxDetails>, ApiError> {
+    let sandboxes = app_state.get_all_sandboxes().await;
     
     let sandbox = sandboxes
         .into_iter()
         .find(|s| s.id == sandbox_id)
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| sandbox_not_found(&sandbox_id))?;
     
-    let info = SandboxInfo {
-        id: sandbox.id.clone(),
-        status: format!("{:?}", sandbox.status),
-        runtime: sandbox.request.runtime.clone(),
-        created_at: sandbox.created_at.to_rfc3339(),
-        uptime: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs() - sandbox.created_at.timestamp() as u64)
-            .unwrap_or(0),
-        memory_mb: sandbox.request.memory_limit_mb,
-        cpu_percentage: get_sandbox_cpu_usage(&sandbox.id).await.unwrap_or(0.0),
-        dev_server_url: if sandbox.request.dev_server.unwrap_or(false) && matches!(sandbox.request.mode, Some(SandboxMode::Persistent)) {
-            Some(format!("http://127.0.0.1:8070/proxy/{}/", sandbox.id))
-        } else {
-            None
-        },
-        allocated_port: sandbox.dev_server_port,
-        is_persistent: matches!(sandbox.request.mode, Some(SandboxMode::Persistent)),
-        container_id: sandbox.container_id.clone(),
-    };
-    
-    Ok(Json(info))
+    Ok(Json(sandbox_details(&app_state, &sandbox, &base_url).await))
 }
 
 pub async fn get_sandbox_logs(
     Path(sandbox_id): Path<String>,
     Query(query): Query<LogQuery>,
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<Vec<LogEntry>>, StatusCode> {
-    let manager = app_state.read().await;
-    let sandboxes = manager.get_all_sandboxes().await;
-    
-    let _sandbox = sandboxes
-        .into_iter()
-        .find(|s| s.id == sandbox_id)
-        .ok_or(StatusCode::NOT_FOUND)?;
-    
-    // Get actual container logs
-    let logs = match get_container_logs(&sandbox_id, query.lines.unwrap_or(100)).await {
-        Ok(logs) => logs,
-        Err(e) => {
-            error!("Failed to get logs for sandbox {}: {}", sandbox_id, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-    
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Json<Vec<LogEntry>>, ApiError> {
+    let lines = query.lines.unwrap_or(100) as usize;
+    let stream = app_state.sandbox_logs(&sandbox_id, lines, false).await?;
+    let mut logs: Vec<LogEntry> = stream
+        .take_while(|result| {
+            if let Err(e) = result {
+                error!("Error reading logs of sandbox {}: {}", sandbox_id, e);
+            }
+            std::future::ready(result.is_ok())
+        })
+        .filter_map(|result| std::future::ready(result.ok()))
+        .collect()
+        .await;
+
+    // Newest first
+    logs.reverse();
     Ok(Json(logs))
 }
 
+/// Follow a sandbox's logs as Server-Sent Events, starting with the last `lines` lines
+///
+/// GET /admin/api/sandboxes/{id}/logs/stream
+pub async fn stream_sandbox_logs(
+    Path(sandbox_id): Path<String>,
+    Query(query): Query<LogQuery>,
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Sse<BoxStream<'static, Result<SseEvent, axum::Error>>>, ApiError> {
+    let lines = query.lines.unwrap_or(100) as usize;
+    let stream = app_state.sandbox_logs(&sandbox_id, lines, true).await?
+        .take_while(move |result| {
+            if let Err(e) = result {
+                error!("Error following logs of sandbox {}: {}", sandbox_id, e);
+            }
+            std::future::ready(result.is_ok())
+        })
+        .filter_map(|result| {
+            let event = result.ok().map(|entry| SseEvent::default().event("log").json_data(entry));
+            std::future::ready(event)
+        })
+        .boxed();
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// What the sandbox's backend says about its state
+///
+/// GET /admin/api/sandboxes/{id}/health
+pub async fn get_sandbox_health(
+    Path(sandbox_id): Path<String>,
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Json<SandboxHealth>, ApiError> {
+    Ok(Json(app_state.sandbox_health(&sandbox_id).await?))
+}
+
 pub async fn force_stop_sandbox(
     Path(sandbox_id): Path<String>,
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let mut manager = app_state.write().await;
-    
-    match manager.delete_sandbox(&sandbox_id).await {
-        Ok(_) => {
-            info!("Force stopped sandbox: {}", sandbox_id);
-            Ok(Json(json!({
-                "success": true,
-                "message": format!("Sandbox {} stopped successfully", sandbox_id)
-            })))
-        }
-        Err(e) => {
-            error!("Failed to force stop sandbox {}: {}", sandbox_id, e);
-            Ok(Json(json!({
-                "success": false,
-                "message": format!("Failed to stop sandbox: {}", e)
-            })))
-        }
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if let Err(e) = app_state.delete_sandbox(&sandbox_id).await {
+        error!("Failed to force stop sandbox {}: {}", sandbox_id, e);
+        return Err(ApiError::from(e));
     }
+    info!("Force stopped sandbox: {}", sandbox_id);
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Sandbox {} stopped successfully", sandbox_id)
+    })))
 }
 
 pub async fn get_sandbox_resources(
     Path(sandbox_id): Path<String>,
-    State(app_state): State<Arc<RwLock<SandboxManager>>>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let manager = app_state.read().await;
-    let sandboxes = manager.get_all_sandboxes().await;
+    State(app_state): State<Arc<SandboxManager>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let sandboxes = app_state.get_all_sandboxes().await;
     
-    let _sandbox = sandboxes
+    let sandbox = sandboxes
         .into_iter()
         .find(|s| s.id == sandbox_id)
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| sandbox_not_found(&sandbox_id))?;
     
-    // Get actual container stats
-    let resources = match get_container_stats(&sandbox_id).await {
-        Ok(stats) => stats,
-        Err(e) => {
-            error!("Failed to get container stats for {}: {}", sandbox_id, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let stats = app_state.sandbox_stats(&sandbox_id).await
+        .map_err(|e| ApiError::internal(format!("Failed to get stats of sandbox {}: {}", sandbox_id, e)))?;
+
+    const MB: f64 = 1024.0 * 1024.0;
+    // Backends that cannot read the memory limit back are held to the one requested
+    let memory_limit = stats.memory_limit_bytes.unwrap_or(sandbox.request.memory_limit_mb * 1024 * 1024) as f64;
+    let memory_used = stats.memory_used_bytes as f64;
+    // The quota is the project directory size the sandbox was created with
+    let disk_limit_mb = sandbox.request.disk_limit_mb();
+    let disk_used_mb = (stats.disk_read_bytes + stats.disk_write_bytes) as f64 / MB;
+
+    Ok(Json(json!({
+        "memory": {
+            "used": memory_used / MB,
+            "limit": memory_limit / MB,
+            "percentage": if memory_limit > 0.0 { memory_used / memory_limit * 100.0 } else { 0.0 }
+        },
+        "cpu": {
+            "percentage": stats.cpu_percentage,
+            "cores": stats.cpu_percentage / 100.0
+        },
+        "disk": {
+            "read_bytes": stats.disk_read_bytes,
+            "write_bytes": stats.disk_write_bytes,
+            "used": disk_used_mb,
+            "limit": disk_limit_mb,
+            "percentage": disk_used_mb / disk_limit_mb as f64 * 100.0
+        },
+        "network": {
+            "bytes_in": stats.network_rx_bytes,
+            "bytes_out": stats.network_tx_bytes
         }
+    })))
+}
+
+/// Outcome of the most recent orphan collection; null before the first one
+pub async fn get_orphan_report(
+    State(state): State<Arc<SandboxManager>>,
+) -> Json<Option<OrphanReport>> {
+    Json(state.last_orphan_report())
+}
+
+/// Usage per API key over `from`..`to`, by default the last 30 days, as JSON or CSV
+pub async fn get_usage(
+    State(meter): State<Meter>,
+    Query(query): Query<UsageQuery>,
+) -> Result<axum::response::Response, ApiError> {
+    let timestamp = |value: Option<String>| {
+        value
+            .map(|value| {
+                chrono::DateTime::parse_from_rfc3339(&value)
+                    .map(|at| at.with_timezone(&chrono::Utc))
+                    .map_err(|e| ApiError::Validation(format!("Invalid timestamp {}: {}", value, e)))
+            })
+            .transpose()
     };
-    
-    Ok(Json(resources))
+    let report = meter.report(timestamp(query.from)?, timestamp(query.to)?);
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("csv") => Ok((
+            [
+                (axum::http::header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (axum::http::header::CONTENT_DISPOSITION, "attachment; filename=\"usage.csv\""),
+            ],
+            report.to_csv(),
+        ).into_response()),
+        Some(other) => Err(ApiError::Validation(format!("Unknown report format {} (expected json or csv)", other))),
+    }
+}
+
+/// Collect orphaned containers and directories now instead of at the next interval
+pub async fn collect_orphans(
+    State(state): State<Arc<SandboxManager>>,
+) -> Json<OrphanReport> {
+    Json(state.collect_orphans().await)
 }
 
 pub async fn get_system_logs(
     Query(query): Query<LogQuery>,
-) -> Result<Json<Vec<LogEntry>>, StatusCode> {
+) -> Result<Json<Vec<LogEntry>>, ApiError> {
     // Get actual system logs
-    let logs = match get_system_logs_impl(query.lines.unwrap_or(100)).await {
-        Ok(logs) => logs,
-        Err(e) => {
-            error!("Failed to get system logs: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    let logs = get_system_logs_impl(query.lines.unwrap_or(100)).await
+        .map_err(|e| ApiError::internal(format!("Failed to get system logs: {}", e)))?;
     
     Ok(Json(logs))
 }
 
-pub async fn get_api_docs() -> Result<Json<Vec<ApiEndpoint>>, StatusCode> {
+pub async fn get_api_docs() -> Json<Vec<ApiEndpoint>> {
     let endpoints = vec![
         ApiEndpoint {
             method: "POST".to_string(),
@@ -1078,22 +952,17 @@ pub async fn get_api_docs() -> Result<Json<Vec<ApiEndpoint>>, StatusCode> {
         },
     ];
     
-    Ok(Json(endpoints))
+    Json(endpoints)
 }
 
 pub async fn test_api_endpoint(
     Json(request): Json<ApiTestRequest>,
-) -> Result<Json<ApiTestResponse>, StatusCode> {
+) -> Result<Json<ApiTestResponse>, ApiError> {
     let start_time = std::time::Instant::now();
     
     // Make actual HTTP request to the API
-    let response = match make_api_request(request).await {
-        Ok(response) => response,
-        Err(e) => {
-            error!("Failed to make API request: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    let response = make_api_request(request).await
+        .map_err(|e| ApiError::BadGateway(format!("Failed to make API request: {}", e)))?;
     
     let response = ApiTestResponse {
         status: response.status,
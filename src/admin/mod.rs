@@ -5,9 +5,12 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
+use crate::alerts::Alerts;
+use crate::backup::Backups;
+use crate::metering::Meter;
+use crate::reload::ConfigReloader;
 use crate::sandbox::manager::SandboxManager;
 
 pub mod handlers;
@@ -22,6 +25,9 @@ pub struct SystemStatus {
     pub version: String,
     pub memory_usage: ResourceUsage,
     pub cpu_usage: ResourceUsage,
+    /// Load of each Docker host when sandboxes are spread over several
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub docker_hosts: Vec<crate::sandbox::hosts::HostLoad>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,34 +37,28 @@ pub struct ResourceUsage {
     pub percentage: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SandboxInfo {
-    pub id: String,
-    pub status: String,
-    pub runtime: String,
-    pub created_at: String,
-    pub uptime: u64,
-    pub memory_mb: u64,
-    pub cpu_percentage: f64,
-    pub dev_server_url: Option<String>,
-    pub allocated_port: Option<u16>,
-    pub is_persistent: bool,
-    pub container_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LogEntry {
-    pub timestamp: String,
-    pub level: String,
-    pub message: String,
-    pub sandbox_id: Option<String>,
-}
+pub use voidrun_types::{LogEntry, SandboxDetails, SandboxDetailsList};
 
 #[derive(Debug, Deserialize)]
 pub struct LogQuery {
     pub lines: Option<u32>,
 }
 
+/// Span and format of a usage report; `from` and `to` are RFC 3339 timestamps
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// `json` (default) or `csv`
+    pub format: Option<String>,
+}
+
+/// Backup a restore reads, the latest when unset
+#[derive(Debug, Default, Deserialize)]
+pub struct RestoreRequest {
+    pub backup: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ApiEndpoint {
     pub method: String,
@@ -93,17 +93,50 @@ pub struct ApiTestResponse {
     pub duration_ms: u64,
 }
 
-pub fn create_admin_router(app_state: Arc<RwLock<SandboxManager>>) -> Router {
+pub fn create_admin_router(
+    app_state: Arc<SandboxManager>,
+    reloader: Arc<ConfigReloader>,
+    meter: Meter,
+    alerts: Arc<Alerts>,
+    backups: Arc<Backups>,
+) -> Router {
+    let config_router = Router::new()
+        .route("/admin/api/config", get(handlers::get_config))
+        .route("/admin/api/config/reload", post(handlers::reload_config))
+        .with_state(reloader);
+
+    // Reports must not wait for executions holding the sandbox manager
+    let usage_router = Router::new()
+        .route("/admin/api/usage", get(handlers::get_usage))
+        .with_state(meter);
+
+    let alerts_router = Router::new()
+        .route("/admin/api/alerts", get(handlers::get_alerts))
+        .with_state(alerts);
+
+    let backups_router = Router::new()
+        .route("/admin/api/backups", get(handlers::list_backups).post(handlers::create_backup))
+        .route("/admin/api/restore", post(handlers::restore_backup))
+        .with_state(backups);
+
     Router::new()
         .route("/admin", get(handlers::admin_ui))
         .route("/admin/api/status", get(handlers::get_system_status))
         .route("/admin/api/sandboxes", get(handlers::list_sandboxes))
         .route("/admin/api/sandboxes/:id", get(handlers::get_sandbox_info))
         .route("/admin/api/sandboxes/:id/logs", get(handlers::get_sandbox_logs))
+        .route("/admin/api/sandboxes/:id/logs/stream", get(handlers::stream_sandbox_logs))
         .route("/admin/api/sandboxes/:id/force-stop", post(handlers::force_stop_sandbox))
         .route("/admin/api/sandboxes/:id/resources", get(handlers::get_sandbox_resources))
+        .route("/admin/api/sandboxes/:id/health", get(handlers::get_sandbox_health))
+        .route("/admin/api/orphans", get(handlers::get_orphan_report))
+        .route("/admin/api/orphans/collect", post(handlers::collect_orphans))
         .route("/admin/api/logs", get(handlers::get_system_logs))
         .route("/admin/api/docs", get(handlers::get_api_docs))
         .route("/admin/api/test", post(handlers::test_api_endpoint))
         .with_state(app_state)
+        .merge(config_router)
+        .merge(usage_router)
+        .merge(alerts_router)
+        .merge(backups_router)
 }
\ No newline at end of file
@@ -532,6 +532,43 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
             border: 1px solid rgba(0, 0, 0, 0.04);
         }
         
+        .config-view {
+            margin: 0;
+            padding: 1rem;
+            font-family: 'SF Mono', Monaco, 'Cascadia Code', monospace;
+            font-size: 13px;
+            white-space: pre-wrap;
+        }
+
+        .config-report {
+            padding: 0 1rem;
+            font-size: 14px;
+        }
+
+        .terminal {
+            background: #1d1d1f;
+            color: #f5f5f7;
+            font-family: 'SF Mono', Monaco, 'Cascadia Code', monospace;
+            font-size: 12px;
+            padding: 0.75rem;
+            border-radius: 8px;
+            height: 260px;
+            overflow-y: auto;
+            white-space: pre-wrap;
+            word-break: break-all;
+            margin: 0.5rem 0;
+        }
+
+        .terminal-input {
+            width: 100%;
+            font-family: 'SF Mono', Monaco, 'Cascadia Code', monospace;
+            font-size: 12px;
+            padding: 0.5rem;
+            border: 1px solid #d2d2d7;
+            border-radius: 8px;
+            box-sizing: border-box;
+        }
+
         .close {
             color: #86868b;
             float: right;
@@ -581,6 +618,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                 <button class="nav-tab" onclick="showTab('faas')">FaaS Deployments</button>
                 <button class="nav-tab" onclick="showTab('logs')">Logs</button>
                 <button class="nav-tab" onclick="showTab('api-docs')">API Docs</button>
+                <button class="nav-tab" onclick="showTab('config')">Config</button>
             </nav>
         </div>
     </header>
@@ -638,6 +676,13 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                         <div class="progress-fill" id="cpu-progress"></div>
                     </div>
                 </div>
+
+                <div class="status-card">
+                    <h3>Alerts</h3>
+                    <div id="alerts-list">
+                        <div class="metric"><span>Loading...</span></div>
+                    </div>
+                </div>
             </div>
         </div>
 
@@ -645,8 +690,32 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         <div id="sandboxes" class="tab-content">
             <div class="sandbox-list">
                 <div class="sandbox-header">
-                    <h3>Active Sandboxes</h3>
-                    <button class="refresh-btn" onclick="refreshSandboxes()">🔄 Refresh</button>
+                    <h3>Active Sandboxes <span id="sandboxes-count"></span></h3>
+                    <div class="log-controls">
+                        <label>
+                            Status:
+                            <select id="sandbox-status" onchange="loadSandboxes()">
+                                <option value="">All</option>
+                                <option value="created">Created</option>
+                                <option value="installing">Installing</option>
+                                <option value="running">Running</option>
+                                <option value="devserver">Dev server</option>
+                                <option value="paused">Paused</option>
+                                <option value="completed">Completed</option>
+                                <option value="failed">Failed</option>
+                                <option value="terminated">Terminated</option>
+                            </select>
+                        </label>
+                        <label>
+                            Mode:
+                            <select id="sandbox-mode" onchange="loadSandboxes()">
+                                <option value="">All</option>
+                                <option value="oneshot">One-shot</option>
+                                <option value="persistent">Persistent</option>
+                            </select>
+                        </label>
+                        <button class="refresh-btn" onclick="refreshSandboxes()">🔄 Refresh</button>
+                    </div>
                 </div>
                 <table class="sandbox-table">
                     <thead>
@@ -667,6 +736,10 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                         </tr>
                     </tbody>
                 </table>
+                <div class="sandbox-header" id="sandboxes-more" style="display: none;">
+                    <span></span>
+                    <button class="refresh-btn" onclick="loadSandboxes(true)">Load more</button>
+                </div>
             </div>
         </div>
 
@@ -706,7 +779,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                     <div class="log-controls">
                         <label>
                             Sandbox:
-                            <select id="log-sandbox">
+                            <select id="log-sandbox" onchange="loadLogs()">
                                 <option value="">All</option>
                             </select>
                         </label>
@@ -734,6 +807,21 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                 </div>
             </div>
         </div>
+
+        <!-- Config Tab -->
+        <div id="config" class="tab-content">
+            <div class="api-docs">
+                <div class="api-header log-header">
+                    <h3>Effective Configuration</h3>
+                    <div class="log-controls">
+                        <span id="config-source"></span>
+                        <button class="refresh-btn" onclick="reloadConfig()">🔄 Reload from source</button>
+                    </div>
+                </div>
+                <div id="config-reload-report" class="config-report"></div>
+                <pre id="config-content" class="config-view"><div class="loading">Loading configuration...</div></pre>
+            </div>
+        </div>
     </div>
 
     <!-- Modal for sandbox details -->
@@ -791,6 +879,9 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
             event.target.classList.add('active');
             
             currentTab = tabName;
+            if (tabName !== 'logs') {
+                closeLogStream();
+            }
 
             // Load tab-specific data
             switch (tabName) {
@@ -809,6 +900,9 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                 case 'api-docs':
                     loadApiDocs();
                     break;
+                case 'config':
+                    loadConfig();
+                    break;
             }
         }
 
@@ -837,30 +931,76 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
             } catch (error) {
                 console.error('Failed to load dashboard:', error);
             }
+
+            loadAlerts();
         }
 
-        // Sandbox functions
-        async function loadSandboxes() {
+        async function loadAlerts() {
             try {
-                const response = await fetch(`${API_BASE}/sandboxes`);
-                const sandboxes = await response.json();
+                const response = await fetch(`${API_BASE}/alerts`);
+                const alerts = await response.json();
+                const list = document.getElementById('alerts-list');
+                list.innerHTML = '';
+                if (alerts.firing.length === 0) {
+                    list.innerHTML = `<div class="metric"><span>No alerts firing (${alerts.rules.length} rules)</span></div>`;
+                    return;
+                }
+                alerts.firing.forEach(alert => {
+                    const row = document.createElement('div');
+                    row.className = 'metric';
+                    row.title = `Above ${alert.threshold}% since ${new Date(alert.since).toLocaleString()}`;
+                    row.innerHTML = `
+                        <span><span class="status-badge status-failed">${alert.metric}</span> ${alert.subject}</span>
+                        <span class="metric-value">${alert.value.toFixed(1)}%</span>
+                    `;
+                    list.appendChild(row);
+                });
+            } catch (error) {
+                console.error('Failed to load alerts:', error);
+            }
+        }
+
+        // Sandbox functions, a page at a time
+        const SANDBOX_PAGE_SIZE = 50;
+        let sandboxCursor = null;
+        let loadedSandboxes = [];
+
+        async function loadSandboxes(more = false) {
+            try {
+                const params = new URLSearchParams({ limit: SANDBOX_PAGE_SIZE });
+                const status = document.getElementById('sandbox-status').value;
+                const mode = document.getElementById('sandbox-mode').value;
+                if (status) params.set('status', status);
+                if (mode) params.set('mode', mode);
+                if (more && sandboxCursor) params.set('cursor', sandboxCursor);
+
+                const response = await fetch(`${API_BASE}/sandboxes?${params}`);
+                const page = await response.json();
                 
                 const tbody = document.getElementById('sandboxes-tbody');
-                tbody.innerHTML = '';
+                if (!more) {
+                    tbody.innerHTML = '';
+                    loadedSandboxes = [];
+                }
+                sandboxCursor = page.next_cursor;
+                loadedSandboxes = loadedSandboxes.concat(page.sandboxes);
+                document.getElementById('sandboxes-count').textContent = page.total > 0 ? `(${loadedSandboxes.length} of ${page.total})` : '';
+                document.getElementById('sandboxes-more').style.display = page.next_cursor ? 'flex' : 'none';
                 
-                if (sandboxes.length === 0) {
+                if (loadedSandboxes.length === 0) {
                     tbody.innerHTML = '<tr><td colspan="8" class="loading">No active sandboxes</td></tr>';
+                    updateLogSandboxDropdown(loadedSandboxes);
                     return;
                 }
                 
-                sandboxes.forEach(sandbox => {
+                page.sandboxes.forEach(sandbox => {
                     const row = document.createElement('tr');
                     row.innerHTML = `
                         <td title="${sandbox.id}">${sandbox.id.substring(0, 8)}...</td>
                         <td><span class="status-badge status-${sandbox.status.toLowerCase()}">${sandbox.status}</span></td>
                         <td>${sandbox.runtime}</td>
                         <td>${formatDuration(sandbox.uptime * 1000)}</td>
-                        <td>${sandbox.memory_mb}MB</td>
+                        <td>${sandbox.memory_limit_mb}MB</td>
                         <td>${sandbox.cpu_percentage.toFixed(1)}%</td>
                         <td>
                             ${sandbox.dev_server_url ? `<a href="${sandbox.dev_server_url}" target="_blank" class="proxy-link">🔗 Open App</a>` : '<span class="proxy-unavailable">No dev server</span>'}
@@ -875,7 +1015,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                 });
                 
                 // Update log sandbox dropdown
-                updateLogSandboxDropdown(sandboxes);
+                updateLogSandboxDropdown(loadedSandboxes);
                 
             } catch (error) {
                 console.error('Failed to load sandboxes:', error);
@@ -889,8 +1029,8 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         // FaaS Deployment functions
         async function loadFaasDeployments() {
             try {
-                const response = await fetch('/faas/deployments');
-                const deployments = await response.json();
+                const response = await fetch('/faas/deployments?limit=1000');
+                const { deployments } = await response.json();
                 
                 const tbody = document.getElementById('faas-tbody');
                 tbody.innerHTML = '';
@@ -999,11 +1139,16 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                     <p><strong>Runtime:</strong> ${sandbox.runtime}</p>
                     <p><strong>Created:</strong> ${new Date(sandbox.created_at).toLocaleString()}</p>
                     <p><strong>Uptime:</strong> ${formatDuration(sandbox.uptime * 1000)}</p>
-                    <p><strong>Memory Limit:</strong> ${sandbox.memory_mb}MB</p>
+                    <p><strong>Memory Limit:</strong> ${sandbox.memory_limit_mb}MB</p>
                     <p><strong>Persistent:</strong> ${sandbox.is_persistent ? 'Yes' : 'No'}</p>
                     ${sandbox.dev_server_url ? `<p><strong>Dev Server:</strong> <a href="${sandbox.dev_server_url}" target="_blank">${sandbox.dev_server_url}</a></p>` : ''}
                     ${sandbox.allocated_port ? `<p><strong>Allocated Port:</strong> ${sandbox.allocated_port}</p>` : ''}
                     ${sandbox.container_id ? `<p><strong>Container ID:</strong> ${sandbox.container_id}</p>` : ''}
+                    ${sandbox.is_persistent ? `<button class="refresh-btn" onclick="openTerminal('${sandbox.id}')">Open Terminal</button>
+                    <div id="terminal-panel" style="display: none;">
+                        <pre id="terminal-output" class="terminal"></pre>
+                        <input id="terminal-input" class="terminal-input" placeholder="Type a command and press Enter" onkeydown="terminalKeyDown(event)">
+                    </div>` : ''}
                 `;
                 
                 document.getElementById('sandbox-modal').style.display = 'block';
@@ -1013,9 +1158,51 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         }
 
         function closeSandboxModal() {
+            closeTerminal();
             document.getElementById('sandbox-modal').style.display = 'none';
         }
 
+        // Interactive shell over GET /sandbox/:id/terminal
+        let terminalSocket = null;
+
+        function openTerminal(sandboxId) {
+            closeTerminal();
+            const output = document.getElementById('terminal-output');
+            const decoder = new TextDecoder();
+            output.textContent = '';
+            document.getElementById('terminal-panel').style.display = 'block';
+
+            const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+            terminalSocket = new WebSocket(`${protocol}//${window.location.host}/sandbox/${sandboxId}/terminal?cols=100&rows=30`);
+            terminalSocket.binaryType = 'arraybuffer';
+            terminalSocket.onmessage = (event) => {
+                // No terminal emulator here, so drop ANSI escape sequences
+                const text = decoder.decode(event.data, { stream: true }).replace(/\x1b\[[0-9;?]*[A-Za-z]/g, '');
+                output.textContent += text;
+                output.scrollTop = output.scrollHeight;
+            };
+            terminalSocket.onclose = () => {
+                output.textContent += '\n[terminal closed]\n';
+                terminalSocket = null;
+            };
+            document.getElementById('terminal-input').focus();
+        }
+
+        function terminalKeyDown(event) {
+            if (event.key !== 'Enter' || !terminalSocket) {
+                return;
+            }
+            terminalSocket.send(event.target.value + '\n');
+            event.target.value = '';
+        }
+
+        function closeTerminal() {
+            if (terminalSocket) {
+                terminalSocket.close();
+                terminalSocket = null;
+            }
+        }
+
         async function forceStopSandbox(sandboxId) {
             if (!confirm(`Are you sure you want to force stop sandbox ${sandboxId}?`)) {
                 return;
@@ -1051,17 +1238,71 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         }
 
         // Log functions
+        // Sandbox logs are followed over SSE instead of polled
+        let logStream = null;
+        let logStreamKey = null;
+
+        function closeLogStream() {
+            if (logStream) {
+                logStream.close();
+                logStream = null;
+                logStreamKey = null;
+            }
+        }
+
+        function appendLogEntry(logContent, log) {
+            const logEntry = document.createElement('div');
+            logEntry.className = 'log-entry';
+            logEntry.innerHTML = `
+                <span class="log-timestamp">${log.timestamp}</span>
+                <span class="log-level log-level-${log.level.toLowerCase()}">[${log.level}]</span>
+                ${log.sandbox_id ? `<span class="log-sandbox">[${log.sandbox_id}]</span>` : ''}
+                <span class="log-message">${log.message}</span>
+            `;
+            logContent.appendChild(logEntry);
+        }
+
+        function tailSandboxLogs(sandboxId, lines) {
+            const key = `${sandboxId}:${lines}`;
+            if (logStream && logStreamKey === key) {
+                return;
+            }
+            closeLogStream();
+
+            const logContent = document.getElementById('log-content');
+            logContent.innerHTML = '';
+            logStreamKey = key;
+            logStream = new EventSource(`${API_BASE}/sandboxes/${sandboxId}/logs/stream?lines=${lines}`);
+            logStream.addEventListener('log', (event) => {
+                const atBottom = logContent.scrollTop + logContent.clientHeight >= logContent.scrollHeight - 10;
+                appendLogEntry(logContent, JSON.parse(event.data));
+                while (logContent.children.length > lines) {
+                    logContent.removeChild(logContent.firstChild);
+                }
+                if (atBottom) {
+                    logContent.scrollTop = logContent.scrollHeight;
+                }
+            });
+            logStream.onerror = () => {
+                // The sandbox is gone; EventSource would otherwise keep reconnecting
+                if (logStream && logStream.readyState === EventSource.CLOSED) {
+                    closeLogStream();
+                }
+            };
+        }
+
         async function loadLogs() {
             try {
                 const sandboxId = document.getElementById('log-sandbox').value;
                 const lines = document.getElementById('log-lines').value;
                 
-                let url = `${API_BASE}/logs?lines=${lines}`;
                 if (sandboxId) {
-                    url = `${API_BASE}/sandboxes/${sandboxId}/logs?lines=${lines}`;
+                    tailSandboxLogs(sandboxId, lines);
+                    return;
                 }
+                closeLogStream();
                 
-                const response = await fetch(url);
+                const response = await fetch(`${API_BASE}/logs?lines=${lines}`);
                 const logs = await response.json();
                 
                 const logContent = document.getElementById('log-content');
@@ -1072,17 +1313,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
                     return;
                 }
                 
-                logs.forEach(log => {
-                    const logEntry = document.createElement('div');
-                    logEntry.className = 'log-entry';
-                    logEntry.innerHTML = `
-                        <span class="log-timestamp">${log.timestamp}</span>
-                        <span class="log-level log-level-${log.level.toLowerCase()}">[${log.level}]</span>
-                        ${log.sandbox_id ? `<span class="log-sandbox">[${log.sandbox_id}]</span>` : ''}
-                        <span class="log-message">${log.message}</span>
-                    `;
-                    logContent.appendChild(logEntry);
-                });
+                logs.forEach(log => appendLogEntry(logContent, log));
                 
                 // Scroll to bottom
                 logContent.scrollTop = logContent.scrollHeight;
@@ -1093,6 +1324,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         }
 
         function refreshLogs() {
+            closeLogStream();
             loadLogs();
         }
 
@@ -1113,6 +1345,38 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
         }
 
         // API documentation functions
+        // Config functions
+        async function loadConfig() {
+            try {
+                const response = await fetch(`${API_BASE}/config`);
+                const effective = await response.json();
+                document.getElementById('config-source').textContent = `Source: ${effective.source}`;
+                document.getElementById('config-content').textContent = JSON.stringify(effective.config, null, 2);
+            } catch (error) {
+                console.error('Failed to load configuration:', error);
+                document.getElementById('config-content').textContent = `Failed to load configuration: ${error.message}`;
+            }
+        }
+
+        async function reloadConfig() {
+            const report = document.getElementById('config-reload-report');
+            try {
+                const response = await fetch(`${API_BASE}/config/reload`, { method: 'POST' });
+                const result = await response.json();
+                if (!response.ok) {
+                    report.innerHTML = `<p style="color: #e74c3c;"><strong>Reload failed:</strong></p><pre class="config-view"></pre>`;
+                    report.querySelector('pre').textContent = result.message;
+                    return;
+                }
+                const list = names => names.length > 0 ? names.join(', ') : 'none';
+                report.innerHTML = `<p><strong>Applied:</strong> ${list(result.applied)}<br>
+                    <strong>Requires restart:</strong> ${list(result.requires_restart)}</p>`;
+                loadConfig();
+            } catch (error) {
+                report.innerHTML = `<p style="color: #e74c3c;"><strong>Error:</strong> ${error.message}</p>`;
+            }
+        }
+
         async function loadApiDocs() {
             try {
                 const response = await fetch(`${API_BASE}/docs`);
@@ -1250,7 +1514,7 @@ pub const ADMIN_UI_HTML: &str = r#"<!DOCTYPE html>
             window.onclick = function(event) {
                 const modal = document.getElementById('sandbox-modal');
                 if (event.target == modal) {
-                    modal.style.display = 'none';
+                    closeSandboxModal();
                 }
             };
         });
@@ -0,0 +1,263 @@
+//! Alerts on live resource usage, `[alerts]` in the configuration
+//!
+//! The metrics sampler feeds [`Alerts`] one [`Sample`] per watched subject every
+//! `sample_interval_seconds`: each running sandbox's memory, the host's CPU and memory,
+//! and each deployment's error rate since the previous sample. A rule's alert fires for a
+//! subject once its samples stayed above the threshold for `for_seconds`, and resolves at
+//! the first sample below it or when the subject is gone. Firing alerts are listed on the
+//! admin dashboard; changes are published on the event bus, where the notifier picks them up.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::events::{EventBus, EventKind};
+
+/// `[alerts]` in the configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// How often the sampler takes usage samples; 0 turns alerting off
+    pub sample_interval_seconds: u64,
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            sample_interval_seconds: 10,
+            rules: vec![
+                AlertRule { metric: AlertMetric::SandboxMemory, threshold: 90.0, for_seconds: 30 },
+                AlertRule { metric: AlertMetric::HostCpu, threshold: 95.0, for_seconds: 0 },
+                AlertRule { metric: AlertMetric::DeploymentErrorRate, threshold: 5.0, for_seconds: 0 },
+            ],
+        }
+    }
+}
+
+/// Alert raised while `metric` stays above `threshold`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub metric: AlertMetric,
+    /// Percentage the metric has to exceed
+    pub threshold: f64,
+    /// How long it has to stay above before the alert fires; 0 fires at the first sample
+    #[serde(default)]
+    pub for_seconds: u64,
+}
+
+/// What a rule watches, each as a percentage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// Memory a running sandbox uses, of its memory limit; one alert per sandbox
+    SandboxMemory,
+    /// CPU busy on the host
+    HostCpu,
+    /// Memory in use on the host
+    HostMemory,
+    /// Requests of a deployment answered with 5xx since the previous sample; one alert per deployment
+    DeploymentErrorRate,
+}
+
+impl AlertMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SandboxMemory => "sandbox_memory",
+            Self::HostCpu => "host_cpu",
+            Self::HostMemory => "host_memory",
+            Self::DeploymentErrorRate => "deployment_error_rate",
+        }
+    }
+}
+
+/// One reading of a metric; `subject` is the sandbox or deployment id, or `host`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub metric: AlertMetric,
+    pub subject: String,
+    pub value: f64,
+}
+
+/// A rule's alert for one subject
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub metric: AlertMetric,
+    pub subject: String,
+    /// Latest sample
+    pub value: f64,
+    pub threshold: f64,
+    /// First sample above the threshold
+    pub since: DateTime<Utc>,
+    pub fired_at: DateTime<Utc>,
+}
+
+impl Alert {
+    fn summary(&self) -> String {
+        format!(
+            "{} of {} is {:.1}%, above {}% since {}",
+            self.metric.as_str(), self.subject, self.value, self.threshold, self.since.to_rfc3339()
+        )
+    }
+}
+
+/// A change [`Alerts::record`] made
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertChange {
+    Fired(Alert),
+    Resolved(Alert),
+}
+
+#[derive(Default)]
+struct State {
+    /// When samples of each rule and subject went above the threshold, before firing
+    pending: HashMap<(usize, String), DateTime<Utc>>,
+    firing: HashMap<(usize, String), Alert>,
+}
+
+/// Alert rules and the alerts they raised
+pub struct Alerts {
+    config: AlertsConfig,
+    state: Mutex<State>,
+    events: EventBus,
+}
+
+impl Alerts {
+    pub fn new(config: AlertsConfig, events: EventBus) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+            events,
+        }
+    }
+
+    pub fn config(&self) -> &AlertsConfig {
+        &self.config
+    }
+
+    /// Whether a rule watches `metric`, which the sampler need not read otherwise
+    pub fn watches(&self, metric: AlertMetric) -> bool {
+        self.config.rules.iter().any(|rule| rule.metric == metric)
+    }
+
+    /// Alerts firing right now, oldest first
+    pub fn firing(&self) -> Vec<Alert> {
+        let mut firing: Vec<Alert> = self.state.lock().unwrap().firing.values().cloned().collect();
+        firing.sort_by(|a, b| (a.fired_at, &a.subject).cmp(&(b.fired_at, &b.subject)));
+        firing
+    }
+
+    /// Evaluate the rules against one round of samples, then publish what changed
+    pub fn record(&self, samples: &[Sample]) -> Vec<AlertChange> {
+        let changes = self.evaluate(Utc::now(), samples);
+        for change in &changes {
+            let kind = match change {
+                AlertChange::Fired(alert) => {
+                    tracing::warn!("Alert fired: {}", alert.summary());
+                    EventKind::AlertFired {
+                        metric: alert.metric.as_str().to_string(),
+                        subject: alert.subject.clone(),
+                        value: alert.value,
+                        threshold: alert.threshold,
+                    }
+                }
+                AlertChange::Resolved(alert) => {
+                    tracing::info!("Alert resolved: {} of {}", alert.metric.as_str(), alert.subject);
+                    EventKind::AlertResolved {
+                        metric: alert.metric.as_str().to_string(),
+                        subject: alert.subject.clone(),
+                    }
+                }
+            };
+            self.events.publish(kind);
+        }
+        changes
+    }
+
+    fn evaluate(&self, now: DateTime<Utc>, samples: &[Sample]) -> Vec<AlertChange> {
+        let mut state = self.state.lock().unwrap();
+        let mut changes = Vec::new();
+        let mut seen = Vec::new();
+        for (index, rule) in self.config.rules.iter().enumerate() {
+            for sample in samples.iter().filter(|sample| sample.metric == rule.metric) {
+                let key = (index, sample.subject.clone());
+                seen.push(key.clone());
+                if sample.value <= rule.threshold {
+                    state.pending.remove(&key);
+                    if let Some(alert) = state.firing.remove(&key) {
+                        changes.push(AlertChange::Resolved(Alert { value: sample.value, ..alert }));
+                    }
+                    continue;
+                }
+                if let Some(alert) = state.firing.get_mut(&key) {
+                    alert.value = sample.value;
+                    continue;
+                }
+                let since = *state.pending.entry(key.clone()).or_insert(now);
+                if now - since >= chrono::Duration::seconds(rule.for_seconds as i64) {
+                    state.pending.remove(&key);
+                    let alert = Alert {
+                        metric: rule.metric,
+                        subject: sample.subject.clone(),
+                        value: sample.value,
+                        threshold: rule.threshold,
+                        since,
+                        fired_at: now,
+                    };
+                    state.firing.insert(key, alert.clone());
+                    changes.push(AlertChange::Fired(alert));
+                }
+            }
+        }
+
+        // Subjects without a sample are gone, such as deleted sandboxes
+        state.pending.retain(|key, _| seen.contains(key));
+        let gone: Vec<(usize, String)> = state.firing.keys().filter(|key| !seen.contains(key)).cloned().collect();
+        for key in gone {
+            if let Some(alert) = state.firing.remove(&key) {
+                changes.push(AlertChange::Resolved(alert));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(metric: AlertMetric, subject: &str, value: f64) -> Sample {
+        Sample { metric, subject: subject.to_string(), value }
+    }
+
+    #[test]
+    fn test_alert_rules() {
+        let alerts = Alerts::new(AlertsConfig::default(), EventBus::new());
+        let start = Utc::now();
+        let at = |seconds: i64| start + chrono::Duration::seconds(seconds);
+
+        // Memory has to stay high for 30s; the host CPU fires at once
+        let changes = alerts.evaluate(at(0), &[
+            sample(AlertMetric::SandboxMemory, "sb-1", 95.0),
+            sample(AlertMetric::HostCpu, "host", 99.0),
+        ]);
+        assert!(matches!(&changes[..], [AlertChange::Fired(alert)] if alert.metric == AlertMetric::HostCpu));
+        assert!(alerts.evaluate(at(20), &[sample(AlertMetric::SandboxMemory, "sb-1", 92.0)]).len() == 1);
+        let changes = alerts.evaluate(at(30), &[sample(AlertMetric::SandboxMemory, "sb-1", 93.0)]);
+        assert!(matches!(&changes[..], [AlertChange::Fired(alert)] if alert.subject == "sb-1" && alert.since == at(0)));
+        assert!(alerts.evaluate(at(40), &[sample(AlertMetric::SandboxMemory, "sb-1", 97.0)]).is_empty());
+        assert_eq!(alerts.firing()[0].value, 97.0);
+
+        // A dip below the threshold resets the wait
+        assert!(matches!(&alerts.evaluate(at(50), &[sample(AlertMetric::SandboxMemory, "sb-1", 50.0)])[..], [AlertChange::Resolved(_)]));
+        assert!(alerts.evaluate(at(60), &[sample(AlertMetric::SandboxMemory, "sb-1", 95.0)]).is_empty());
+        assert!(alerts.evaluate(at(70), &[sample(AlertMetric::SandboxMemory, "sb-1", 40.0)]).is_empty());
+        assert!(alerts.evaluate(at(80), &[sample(AlertMetric::SandboxMemory, "sb-1", 95.0)]).is_empty());
+        assert!(alerts.firing().is_empty());
+
+        alerts.evaluate(at(90), &[sample(AlertMetric::DeploymentErrorRate, "dep-1", 12.5)]);
+        assert_eq!(alerts.firing().len(), 1);
+        assert!(!alerts.watches(AlertMetric::HostMemory));
+    }
+}
@@ -0,0 +1,268 @@
+//! Cross-origin resource sharing for the API and for deployments
+//!
+//! The API answers browsers through a [`CorsLayer`] built from `server.cors`; without
+//! it any origin may call. A deployment declaring `cors` has its preflight requests
+//! answered by the FaaS proxy, which does not forward them, and the `Access-Control-*`
+//! headers of its responses replaced by the policy's. Proxied services without a policy
+//! answer browsers themselves. Browsers enforce the policy: requests from other origins
+//! still reach the service, but their scripts cannot read the responses.
+
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+use voidrun_types::CorsConfig;
+
+use super::error::InvalidInput;
+
+const WILDCARD: &str = "*";
+
+/// A checked `CorsConfig`
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    any_origin: bool,
+    origins: Vec<HeaderValue>,
+    /// Unset allows any method
+    methods: Option<Vec<Method>>,
+    /// Unset allows any request header
+    headers: Option<Vec<HeaderName>>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<u64>,
+}
+
+impl CorsPolicy {
+    pub fn new(config: &CorsConfig) -> Result<Self, InvalidInput> {
+        if config.allowed_origins.is_empty() {
+            return Err(InvalidInput("allowed_origins must list origins, or \"*\" for any".to_string()));
+        }
+        let any_origin = config.allowed_origins.iter().any(|origin| origin == WILDCARD);
+        let origins = config.allowed_origins.iter()
+            .filter(|origin| *origin != WILDCARD)
+            .map(|origin| {
+                let host = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"));
+                match (host, HeaderValue::from_str(origin)) {
+                    (Some(host), Ok(value)) if !host.is_empty() && !host.contains('/') => Ok(value),
+                    _ => Err(InvalidInput(format!(
+                        "Invalid CORS origin {:?}: use scheme://host[:port], e.g. https://app.example.com", origin
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if any_origin && config.allow_credentials {
+            return Err(InvalidInput("allow_credentials needs explicit origins instead of \"*\"".to_string()));
+        }
+
+        let methods = match &config.allowed_methods {
+            Some(methods) if !methods.iter().any(|method| method == WILDCARD) => Some(
+                methods.iter()
+                    .map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                        .map_err(|_| InvalidInput(format!("Invalid CORS method {:?}", method))))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            _ => None,
+        };
+        let header_names = |names: &[String]| {
+            names.iter()
+                .map(|name| HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| InvalidInput(format!("Invalid CORS header name {:?}", name))))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let headers = match &config.allowed_headers {
+            Some(headers) if !headers.iter().any(|name| name == WILDCARD) => Some(header_names(headers)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            any_origin,
+            origins,
+            methods,
+            headers,
+            expose_headers: header_names(&config.expose_headers)?,
+            credentials: config.allow_credentials,
+            max_age: config.max_age_seconds,
+        })
+    }
+
+    /// Layer answering browsers for the API; with credentials, wildcards are answered by
+    /// echoing the request, as browsers do not accept `*` then
+    pub fn layer(&self) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_origin(if self.any_origin { AllowOrigin::from(Any) } else { AllowOrigin::list(self.origins.clone()) })
+            .allow_methods(match &self.methods {
+                Some(methods) => AllowMethods::list(methods.clone()),
+                None if self.credentials => AllowMethods::mirror_request(),
+                None => AllowMethods::from(Any),
+            })
+            .allow_headers(match &self.headers {
+                Some(headers) => AllowHeaders::list(headers.clone()),
+                None if self.credentials => AllowHeaders::mirror_request(),
+                None => AllowHeaders::from(Any),
+            })
+            .expose_headers(self.expose_headers.clone())
+            .allow_credentials(self.credentials);
+        match self.max_age {
+            Some(seconds) => layer.max_age(Duration::from_secs(seconds)),
+            None => layer,
+        }
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.any_origin || self.origins.contains(origin)
+    }
+
+    /// Answer to a preflight request from `origin`; without CORS headers when the origin
+    /// or the requested method is not allowed, so the browser refuses the request
+    pub fn preflight(&self, origin: &HeaderValue, request: &HeaderMap) -> Response {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::VARY, HeaderValue::from_static(
+            "origin, access-control-request-method, access-control-request-headers",
+        ));
+
+        let requested_method = request.get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok());
+        let method_allowed = match (&self.methods, &requested_method) {
+            (Some(methods), Some(method)) => methods.contains(method),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if !self.allows(origin) || !method_allowed {
+            return response;
+        }
+
+        self.allow_origin(origin, headers);
+        let methods = match &self.methods {
+            Some(methods) => join(methods.iter().map(Method::as_str)),
+            None => requested_method.and_then(|method| HeaderValue::from_str(method.as_str()).ok()),
+        };
+        if let Some(methods) = methods {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let allowed_headers = match &self.headers {
+            Some(names) => join(names.iter().map(HeaderName::as_str)),
+            None => request.get(header::ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        };
+        if let Some(allowed_headers) = allowed_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        if let Some(seconds) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(seconds));
+        }
+        response
+    }
+
+    /// Replace the CORS headers of a response to a request from `origin`
+    pub fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        let own: Vec<HeaderName> = headers.keys()
+            .filter(|name| name.as_str().starts_with("access-control-"))
+            .cloned()
+            .collect();
+        for name in own {
+            headers.remove(name);
+        }
+
+        if let Some(origin) = origin.filter(|origin| self.allows(origin)) {
+            self.allow_origin(origin, headers);
+            if let Some(expose) = join(self.expose_headers.iter().map(HeaderName::as_str)) {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+            }
+        }
+    }
+
+    fn allow_origin(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        if self.any_origin {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static(WILDCARD));
+            return;
+        }
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+}
+
+/// Layer answering browsers for the API with `server.cors`, any origin without it
+pub fn api_layer(config: Option<&CorsConfig>) -> Result<CorsLayer, InvalidInput> {
+    match config {
+        Some(config) => Ok(CorsPolicy::new(config)?.layer()),
+        None => Ok(CorsLayer::new().allow_methods(Any).allow_headers(Any).allow_origin(Any)),
+    }
+}
+
+/// Whether the request is a CORS preflight, which the policy answers in place of the service
+pub fn is_preflight(method: &Method, headers: &HeaderMap) -> bool {
+    method == Method::OPTIONS
+        && headers.contains_key(header::ORIGIN)
+        && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let joined = values.collect::<Vec<_>>().join(", ");
+    if joined.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&joined).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cors_policy() {
+        let policy = CorsPolicy::new(&CorsConfig {
+            allowed_methods: Some(vec!["get".to_string(), "POST".to_string()]),
+            expose_headers: vec!["x-request-id".to_string()],
+            allow_credentials: true,
+            max_age_seconds: Some(600),
+            ..config(&["https://app.example.com"])
+        }).unwrap();
+        let allowed = HeaderValue::from_static("https://app.example.com");
+        let other = HeaderValue::from_static("https://evil.example.com");
+
+        let mut request = HeaderMap::new();
+        request.insert(header::ORIGIN, allowed.clone());
+        request.insert(header::ACCESS_CONTROL_REQUEST_METHOD, HeaderValue::from_static("POST"));
+        request.insert(header::ACCESS_CONTROL_REQUEST_HEADERS, HeaderValue::from_static("content-type"));
+        assert!(is_preflight(&Method::OPTIONS, &request));
+        let response = policy.preflight(&allowed, &request);
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert!(!policy.preflight(&other, &request).headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        request.insert(header::ACCESS_CONTROL_REQUEST_METHOD, HeaderValue::from_static("DELETE"));
+        assert!(!policy.preflight(&allowed, &request).headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let mut response = HeaderMap::new();
+        response.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        policy.apply(Some(&allowed), &mut response);
+        assert_eq!(response[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(response[header::ACCESS_CONTROL_EXPOSE_HEADERS], "x-request-id");
+        policy.apply(Some(&other), &mut response);
+        assert!(!response.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let any = CorsPolicy::new(&config(&["*"])).unwrap();
+        let mut response = HeaderMap::new();
+        any.apply(Some(&other), &mut response);
+        assert_eq!(response[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+
+        assert!(CorsPolicy::new(&config(&[])).is_err());
+        assert!(CorsPolicy::new(&config(&["app.example.com"])).is_err());
+        assert!(CorsPolicy::new(&config(&["https://app.example.com/"])).is_err());
+        assert!(CorsPolicy::new(&CorsConfig { allow_credentials: true, ..config(&["*"]) }).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Error responses shared by the REST, FaaS, admin and proxy routes
+//!
+//! Every error is answered with a status matching its cause and an [`ErrorResponse`]
+//! body. Lower layers signal the cause with typed errors, such as [`NotFound`] or
+//! [`CapacityExceeded`], that `From<anyhow::Error>` recognises; anything else is an
+//! internal error.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::runtime::UnsupportedRuntime;
+use crate::sandbox::backend::BackendUnavailable;
+use crate::sandbox::{CapacityExceeded, ShuttingDown, StatusConflict};
+pub use voidrun_types::ErrorResponse;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// 400: the request asks for something this instance does not offer
+    BadRequest { code: &'static str, message: String },
+    /// 401: the deployment requires an access token the request did not present
+    Unauthorized(String),
+    /// 404: the sandbox, deployment or template does not exist
+    NotFound(String),
+    /// 409: the operation does not apply in the sandbox's current status
+    Conflict(String),
+    /// 413: the request body is larger than `server.max_request_body_mb`
+    PayloadTooLarge(String),
+    /// 422: the request is well-formed but its content is invalid
+    Validation(String),
+    /// 429: the caller used up its requests for the current minute, or the deployment's request queue is full
+    TooManyRequests(String),
+    /// 500
+    Internal(String),
+    /// 501: the backend has no support for the operation
+    NotImplemented(String),
+    /// 502: a sandboxed service did not answer
+    BadGateway(String),
+    /// 504: a sandboxed service did not answer within the proxy's timeout
+    GatewayTimeout(String),
+    /// 503: the host has no room for another sandbox
+    CapacityExceeded(CapacityExceeded),
+    /// 503: a sandboxed service keeps refusing connections; retry after the given seconds
+    Unavailable { message: String, retry_after: u64 },
+    /// 503: the service is draining executions before it stops
+    ShuttingDown(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            Self::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::CapacityExceeded(_) | Self::Unavailable { .. } | Self::ShuttingDown(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Machine-readable cause clients can branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest { code, .. } => code,
+            Self::Unauthorized(_) => "unauthorized",
+            Self::NotFound(_) => "not_found",
+            Self::Conflict(_) => "status_conflict",
+            Self::PayloadTooLarge(_) => "payload_too_large",
+            Self::Validation(_) => "validation_failed",
+            Self::TooManyRequests(_) => "rate_limited",
+            Self::Internal(_) => "internal_error",
+            Self::NotImplemented(_) => "not_implemented",
+            Self::BadGateway(_) => "bad_gateway",
+            Self::GatewayTimeout(_) => "gateway_timeout",
+            Self::CapacityExceeded(_) => "capacity_exceeded",
+            Self::Unavailable { .. } => "service_unavailable",
+            Self::ShuttingDown(_) => "shutting_down",
+        }
+    }
+
+    pub fn body(&self) -> ErrorResponse {
+        let (message, details) = match self {
+            Self::BadRequest { message, .. } | Self::Unavailable { message, .. } => (message.clone(), None),
+            Self::Unauthorized(message)
+            | Self::NotFound(message)
+            | Self::Conflict(message)
+            | Self::PayloadTooLarge(message)
+            | Self::Validation(message)
+            | Self::TooManyRequests(message)
+            | Self::Internal(message)
+            | Self::NotImplemented(message)
+            | Self::BadGateway(message)
+            | Self::GatewayTimeout(message)
+            | Self::ShuttingDown(message) => (message.clone(), None),
+            Self::CapacityExceeded(exceeded) => (
+                exceeded.to_string(),
+                Some(json!({
+                    "resource": exceeded.resource,
+                    "requested": exceeded.requested,
+                    "in_use": exceeded.in_use,
+                    "limit": exceeded.limit,
+                })),
+            ),
+        };
+        ErrorResponse {
+            code: self.code().to_string(),
+            message,
+            details,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound(message.into())
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::Internal(message.into())
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body().message)
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(exceeded) = e.downcast_ref::<CapacityExceeded>() {
+            Self::CapacityExceeded(exceeded.clone())
+        } else if let Some(NotFound(message)) = e.downcast_ref() {
+            Self::NotFound(message.clone())
+        } else if let Some(InvalidInput(message)) = e.downcast_ref() {
+            Self::Validation(message.clone())
+        } else if let Some(StatusConflict(message)) = e.downcast_ref() {
+            Self::Conflict(message.clone())
+        } else if let Some(UnsupportedRuntime(message)) = e.downcast_ref() {
+            Self::BadRequest { code: "unsupported_runtime", message: message.clone() }
+        } else if let Some(BackendUnavailable(message)) = e.downcast_ref() {
+            Self::BadRequest { code: "backend_unavailable", message: message.clone() }
+        } else if let Some(shutting_down) = e.downcast_ref::<ShuttingDown>() {
+            Self::ShuttingDown(shutting_down.to_string())
+        } else {
+            Self::Internal(format!("{:#}", e))
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let Self::Internal(message) = &self {
+            tracing::error!("Request failed: {}", message);
+        }
+        if let Self::Unauthorized(_) = &self {
+            let headers = [(header::WWW_AUTHENTICATE, "Bearer")];
+            return (self.status(), headers, Json(self.body())).into_response();
+        }
+        if let Self::Unavailable { retry_after, .. } = &self {
+            let headers = [(header::RETRY_AFTER, retry_after.to_string())];
+            return (self.status(), headers, Json(self.body())).into_response();
+        }
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+/// The sandbox, deployment or other resource a request names does not exist
+#[derive(Debug, Clone)]
+pub struct NotFound(pub String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// A value in the request is malformed, e.g. a snapshot name outside the allowed charset
+#[derive(Debug, Clone)]
+pub struct InvalidInput(pub String);
+
+impl std::fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidInput {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        let not_found = ApiError::from(anyhow::Error::new(NotFound("Sandbox x not found".to_string())));
+        assert_eq!(not_found.status(), StatusCode::NOT_FOUND);
+        assert_eq!(not_found.body().message, "Sandbox x not found");
+
+        let exceeded = ApiError::from(anyhow::Error::new(CapacityExceeded {
+            resource: "sandboxes",
+            requested: 1,
+            in_use: 10,
+            limit: 10,
+        }));
+        assert_eq!(exceeded.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(exceeded.body().details.unwrap()["limit"], 10);
+
+        let invalid = ApiError::from(anyhow::Error::new(InvalidInput("bad name".to_string())).context("Snapshot failed"));
+        assert_eq!(invalid.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(ApiError::from(anyhow::anyhow!("boom")).code(), "internal_error");
+    }
+}
@@ -1,49 +1,55 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Extension, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Redirect},
 };
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use super::{AppState, CreateSandboxRequest, ExecutionResult, SandboxInfo, SandboxFile};
-use crate::sandbox::SandboxRequest;
+use super::error::ApiError;
+use super::{
+    AppState, Capabilities, CompareRequest, CompareResult, CreateSandboxRequest, ExecutionResult, ReplayResult, SandboxInfo, SandboxFile, SandboxList,
+    SandboxListQuery, SnapshotRequest, SnapshotResponse, TestRequest, TestResults, TestStatus,
+};
+use crate::coordination::{IdempotencyKey, IdempotencyKeys};
+use crate::metering::{ApiKey, ANONYMOUS};
+use crate::runtime::{self, media};
+use crate::sandbox::backend::SandboxBackendType;
+use crate::sandbox::diff::diff_lines;
+use crate::sandbox::listing;
+use crate::sandbox::test_runner;
+use crate::sandbox::{Cancellations, SandboxRequest, SandboxResponse, TerminationReason};
+use crate::security::SecurityProfile;
 
-pub async fn health_check() -> Json<Value> {
-    Json(json!({
-        "status": "ok",
+/// `503` with status `draining` once the service is shutting down, so load balancers move on
+pub async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let (status, description) = if state.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    (status, Json(json!({
+        "status": description,
         "service": "sandbox-service",
         "version": "0.1.0"
-    }))
+    })))
+}
+
+pub async fn list_runtimes(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(state.capabilities().await)
 }
 
 pub async fn execute_one_shot(
     State(state): State<AppState>,
+    api_key: ApiKey,
     Json(req): Json<CreateSandboxRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    let sandbox_id = Uuid::new_v4().to_string();
-    
-    let sandbox_req = SandboxRequest {
-        id: sandbox_id.clone(),
-        runtime: req.runtime.clone(),
-        code: req.code,
-        entry_point: req.entry_point,
-        timeout_ms: req.timeout_ms.unwrap_or(30000),
-        memory_limit_mb: req.memory_limit_mb.unwrap_or(512),
-        env_vars: req.env_vars.unwrap_or_default(),
-        files: req.files.map(|files| files.into_iter().map(|f| crate::sandbox::SandboxFile {
-            path: f.path,
-            content: f.content,
-            is_executable: f.is_executable,
-        }).collect()),
-        mode: Some(crate::sandbox::SandboxMode::OneShot),
-        install_deps: req.install_deps,
-        dev_server: req.dev_server,
-    };
+) -> Result<Json<Value>, ApiError> {
+    let use_cache = req.cache != Some(false);
+    let sandbox_req = one_shot_request(req, api_key.0)?;
+    let sandbox_id = sandbox_req.id.clone();
 
-    let mut manager = state.write().await;
-    match manager.execute_sandbox_direct(sandbox_req).await {
-        Ok(result) => {
+    match state.execute_cached(sandbox_req, use_cache).await {
+        Ok((result, cached)) => {
             Ok(Json(json!({
                 "success": result.success,
                 "stdout": result.stdout,
@@ -51,139 +57,410 @@ pub async fn execute_one_shot(
                 "exit_code": result.exit_code,
                 "execution_time_ms": result.execution_time_ms,
                 "is_running": result.is_running,
-                "dev_server_url": result.dev_server_url
+                "dev_server_url": result.dev_server_url,
+                "usage": result.usage,
+                "termination_reason": result.termination_reason,
+                "error_code": result.termination_reason.and_then(|reason| reason.error_code()),
+                "execution_id": result.execution_id,
+                "cost": result.cost,
+                "diagnostics": result.diagnostics,
+                "cached": cached,
             })))
         }
-        Err(e) => {
-            Ok(Json(json!({
+        // Rejected requests get an error response; failed executions are reported as results
+        Err(e) => match ApiError::from(e) {
+            ApiError::Internal(message) => Ok(Json(json!({
                 "success": false,
                 "stdout": "",
-                "stderr": format!("Execution failed: {}", e),
+                "stderr": format!("Execution failed: {}", message),
                 "exit_code": Some(1),
                 "execution_time_ms": 0,
                 "is_running": Some(false),
-                "dev_server_url": None::<String>
-            })))
-        }
+                "dev_server_url": None::<String>,
+                "termination_reason": TerminationReason::SetupFailed
+            }))),
+            rejected => {
+                tracing::warn!("Rejected execution {}: {}", sandbox_id, rejected);
+                Err(rejected)
+            }
+        },
     }
 }
 
+/// One-shot sandbox request for `POST /execute` and its variants
+pub(super) fn one_shot_request(req: CreateSandboxRequest, api_key: Option<String>) -> Result<SandboxRequest, ApiError> {
+    let backend = parse_backend(req.backend.as_deref())?;
+    let security_profile = parse_security_profile(req.security_profile.as_deref())?;
+    let runtime = media::runtime_with_tools(&req.runtime, req.tools.as_deref())?;
+    let mode = match req.mode.as_deref() {
+        Some("check") => {
+            if runtime::check::command(&runtime, "").is_none() {
+                return Err(ApiError::Validation(format!("Runtime {} has no check mode", runtime)));
+            }
+            crate::sandbox::SandboxMode::Check
+        }
+        _ => crate::sandbox::SandboxMode::OneShot,
+    };
+
+    Ok(SandboxRequest {
+        id: Uuid::new_v4().to_string(),
+        runtime: runtime.clone(),
+        code: req.code,
+        entry_point: req.entry_point,
+        timeout_ms: req.timeout_ms.unwrap_or_else(|| runtime::default_timeout_ms(&runtime)),
+        memory_limit_mb: req.memory_limit_mb.unwrap_or_else(|| runtime::default_memory_limit_mb(&runtime)),
+        env_vars: req.env_vars.unwrap_or_default(),
+        files: req.files,
+        mode: Some(mode),
+        install_deps: req.install_deps,
+        dev_server: req.dev_server,
+        from_snapshot: req.from_snapshot,
+        port: req.port,
+        artifacts: req.artifacts.or_else(|| runtime::default_artifacts(&runtime)),
+        disk_limit_mb: req.disk_limit_mb,
+        max_processes: req.max_processes,
+        backend,
+        security_profile,
+        writable_paths: req.writable_paths,
+        gpus: req.gpus,
+        labels: req.labels,
+        api_key,
+        install_proxy: None,
+        mounts: req.mounts,
+        bind_mounts: Vec::new(),
+        image: req.image,
+        registry_credential: None,
+        network: None,
+        deployment: None,
+        output: None,
+    })
+}
+
+/// Run two variants of the code with the same files, env vars and limits and diff their results
+///
+/// POST /execute/compare
+pub async fn compare_executions(
+    State(state): State<AppState>,
+    api_key: ApiKey,
+    Json(req): Json<CompareRequest>,
+) -> Result<Json<CompareResult>, ApiError> {
+    let CompareRequest { request, variant_code } = req;
+    let variant = one_shot_request(CreateSandboxRequest { code: variant_code, ..request.clone() }, api_key.0.clone())?;
+    let baseline = one_shot_request(request, api_key.0)?;
+    // Side by side, so both see the same load on the host
+    let (baseline, variant) = tokio::join!(run_variant(&state, baseline), run_variant(&state, variant));
+    let (baseline, variant) = (baseline?, variant?);
+
+    let stdout_diff = diff_lines(&baseline.stdout, &variant.stdout);
+    let stderr_diff = diff_lines(&baseline.stderr, &variant.stderr);
+    let same_exit_code = baseline.exit_code == variant.exit_code;
+    Ok(Json(CompareResult {
+        identical: stdout_diff.identical
+            && stderr_diff.identical
+            && same_exit_code
+            && baseline.termination_reason == variant.termination_reason,
+        same_exit_code,
+        baseline,
+        variant,
+        stdout_diff,
+        stderr_diff,
+    }))
+}
+
+/// Result of one compared variant, run without the result cache; like `POST /execute`,
+/// an execution that fails to run is reported as a result
+async fn run_variant(state: &AppState, request: SandboxRequest) -> Result<ExecutionResult, ApiError> {
+    let sandbox_id = request.id.clone();
+    let response = match state.execute_cached(request, false).await {
+        Ok((response, _)) => response,
+        Err(e) => match ApiError::from(e) {
+            ApiError::Internal(message) => SandboxResponse {
+                success: false,
+                stdout: String::new(),
+                stderr: format!("Execution failed: {}", message),
+                exit_code: Some(1),
+                execution_time_ms: 0,
+                is_running: Some(false),
+                dev_server_url: None,
+                canceled: false,
+                usage: None,
+                termination_reason: Some(TerminationReason::SetupFailed),
+                execution_id: None,
+                cost: None,
+                diagnostics: None,
+            },
+            rejected => return Err(rejected),
+        },
+    };
+    Ok(execution_result(sandbox_id, response))
+}
+
 pub async fn create_sandbox(
     State(state): State<AppState>,
+    Extension(idempotency): Extension<IdempotencyKeys>,
+    api_key: ApiKey,
+    idempotency_key: IdempotencyKey,
     Json(req): Json<CreateSandboxRequest>,
-) -> Result<Json<SandboxInfo>, StatusCode> {
+) -> Result<Json<SandboxInfo>, ApiError> {
+    let scope = format!("sandbox:{}", api_key.0.as_deref().unwrap_or(ANONYMOUS));
+    idempotency
+        .run(&scope, idempotency_key.0.as_deref(), create(state, api_key, req))
+        .await
+        .map(Json)
+}
+
+async fn create(state: AppState, api_key: ApiKey, req: CreateSandboxRequest) -> Result<SandboxInfo, ApiError> {
     let sandbox_id = Uuid::new_v4().to_string();
-    
-    let sandbox_req = SandboxRequest {
-        id: sandbox_id.clone(),
-        runtime: req.runtime.clone(),
+    let sandbox_req = sandbox_request(sandbox_id.clone(), req, api_key.0)?;
+
+    if let Err(e) = state.create_sandbox(sandbox_req).await {
+        let error = ApiError::from(e);
+        tracing::warn!("Rejected sandbox {}: {}", sandbox_id, error);
+        return Err(error);
+    }
+    let info = state.get_sandbox_info(&sandbox_id).await
+        .ok_or_else(|| ApiError::internal(format!("Sandbox {} vanished after creation", sandbox_id)))?;
+    // Report the request's outcome rather than the live container state
+    Ok(SandboxInfo { status: "created".to_string(), ..info })
+}
+
+/// Sandbox request for `POST /sandbox`, which sessions are created from as well
+pub(super) fn sandbox_request(id: String, req: CreateSandboxRequest, api_key: Option<String>) -> Result<SandboxRequest, ApiError> {
+    let backend = parse_backend(req.backend.as_deref())?;
+    let security_profile = parse_security_profile(req.security_profile.as_deref())?;
+    let runtime = media::runtime_with_tools(&req.runtime, req.tools.as_deref())?;
+
+    Ok(SandboxRequest {
+        id,
+        runtime: runtime.clone(),
         code: req.code,
         entry_point: req.entry_point,
-        timeout_ms: req.timeout_ms.unwrap_or(30000),
-        memory_limit_mb: req.memory_limit_mb.unwrap_or(512),
+        timeout_ms: req.timeout_ms.unwrap_or_else(|| runtime::default_timeout_ms(&runtime)),
+        memory_limit_mb: req.memory_limit_mb.unwrap_or_else(|| runtime::default_memory_limit_mb(&runtime)),
         env_vars: req.env_vars.unwrap_or_default(),
-        files: req.files.map(|files| files.into_iter().map(|f| crate::sandbox::SandboxFile {
-            path: f.path,
-            content: f.content,
-            is_executable: f.is_executable,
-        }).collect()),
+        files: req.files,
         mode: req.mode.as_deref().map(|m| match m {
             "persistent" => crate::sandbox::SandboxMode::Persistent,
             _ => crate::sandbox::SandboxMode::OneShot,
         }),
         install_deps: req.install_deps,
         dev_server: req.dev_server,
-    };
+        from_snapshot: req.from_snapshot,
+        port: req.port,
+        artifacts: req.artifacts.or_else(|| runtime::default_artifacts(&runtime)),
+        disk_limit_mb: req.disk_limit_mb,
+        max_processes: req.max_processes,
+        backend,
+        security_profile,
+        writable_paths: req.writable_paths,
+        gpus: req.gpus,
+        labels: req.labels,
+        api_key,
+        install_proxy: None,
+        mounts: req.mounts,
+        bind_mounts: Vec::new(),
+        image: req.image,
+        registry_credential: None,
+        network: None,
+        deployment: None,
+        output: None,
+    })
+}
 
-    let mut manager = state.write().await;
-    match manager.create_sandbox(sandbox_req).await {
-        Ok(_) => {
-            let info = SandboxInfo {
-                id: sandbox_id,
-                status: "created".to_string(),
-                runtime: req.runtime,
-                created_at: chrono::Utc::now().to_rfc3339(),
-                timeout_ms: req.timeout_ms.unwrap_or(30000),
-                memory_limit_mb: req.memory_limit_mb.unwrap_or(512),
-            };
-            Ok(Json(info))
-        }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+fn parse_backend(name: Option<&str>) -> anyhow::Result<Option<SandboxBackendType>> {
+    Ok(name.map(str::parse).transpose()?)
+}
+
+fn parse_security_profile(name: Option<&str>) -> anyhow::Result<Option<SecurityProfile>> {
+    Ok(name.map(str::parse).transpose()?)
 }
 
 pub async fn get_sandbox(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<Json<SandboxInfo>, StatusCode> {
-    let manager = state.read().await;
-    match manager.get_sandbox_info(&id).await {
+) -> Result<Json<SandboxInfo>, ApiError> {
+    match state.get_sandbox_info(&id).await {
         Some(info) => Ok(Json(info)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!("Sandbox {} not found", id))),
     }
 }
 
 pub async fn delete_sandbox(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    let mut manager = state.write().await;
-    match manager.delete_sandbox(&id).await {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err(StatusCode::NOT_FOUND),
-    }
+) -> Result<StatusCode, ApiError> {
+    state.delete_sandbox(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn pause_sandbox(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SandboxInfo>, ApiError> {
+    Ok(Json(state.pause_sandbox(&id).await?))
+}
+
+pub async fn resume_sandbox(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SandboxInfo>, ApiError> {
+    Ok(Json(state.resume_sandbox(&id).await?))
 }
 
 pub async fn execute_code(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<Json<ExecutionResult>, StatusCode> {
-    let mut manager = state.write().await;
-    match manager.execute_sandbox(&id).await {
-        Ok(result) => {
-            let exec_result = ExecutionResult {
-                sandbox_id: id,
-                success: result.success,
-                stdout: result.stdout,
-                stderr: result.stderr,
-                exit_code: result.exit_code,
-                execution_time_ms: result.execution_time_ms,
-            };
-            Ok(Json(exec_result))
-        }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+) -> Result<Json<ExecutionResult>, ApiError> {
+    let result = state.execute_sandbox(&id).await?;
+    let artifacts_url = state.artifacts_url(&id);
+    Ok(Json(ExecutionResult { artifacts_url, ..execution_result(id, result) }))
+}
+
+/// Run the project's tests in the sandbox and parse the results from their output
+///
+/// POST /sandbox/:id/test
+pub async fn run_tests(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<TestRequest>>,
+) -> Result<Json<TestResults>, ApiError> {
+    let Json(req) = body.unwrap_or_default();
+    let result = state.run_tests(&id, req.command).await?;
+    let artifacts_url = state.artifacts_url(&id);
+    let (format, tests) = test_runner::parse(&result.stdout, &result.stderr).unzip();
+    let tests = tests.unwrap_or_default();
+    let count = |status: TestStatus| tests.iter().filter(|test| test.status == status).count();
+    Ok(Json(TestResults {
+        format,
+        passed: count(TestStatus::Passed),
+        failed: count(TestStatus::Failed),
+        skipped: count(TestStatus::Skipped),
+        execution: ExecutionResult { artifacts_url, ..execution_result(id, result) },
+        tests,
+    }))
+}
+
+fn execution_result(sandbox_id: String, result: SandboxResponse) -> ExecutionResult {
+    ExecutionResult {
+        sandbox_id,
+        success: result.success,
+        stdout: result.stdout,
+        stderr: result.stderr,
+        exit_code: result.exit_code,
+        execution_time_ms: result.execution_time_ms,
+        canceled: result.canceled,
+        usage: result.usage,
+        termination_reason: result.termination_reason,
+        error_code: result.termination_reason.and_then(|reason| reason.error_code()).map(str::to_string),
+        execution_id: result.execution_id,
+        cost: result.cost,
+        artifacts_url: None,
+        diagnostics: result.diagnostics,
+    }
+}
+
+/// Run a past execution's request again in a fresh sandbox and diff its output against the original
+///
+/// POST /executions/:id/replay
+pub async fn replay_execution(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    api_key: ApiKey,
+) -> Result<Json<ReplayResult>, ApiError> {
+    let (original, replay) = state.replay_execution(&id, api_key.0).await?;
+    let original = execution_result(original.request.id, original.response);
+    let replay = execution_result(replay.request.id, replay.response);
+    let stdout_diff = diff_lines(&original.stdout, &replay.stdout);
+    let stderr_diff = diff_lines(&original.stderr, &replay.stderr);
+    Ok(Json(ReplayResult {
+        execution_id: id,
+        identical: stdout_diff.identical
+            && stderr_diff.identical
+            && original.exit_code == replay.exit_code
+            && original.termination_reason == replay.termination_reason,
+        original,
+        replay,
+        stdout_diff,
+        stderr_diff,
+    }))
+}
+
+/// Kill the sandbox's execution in flight; its `/execute` call returns the output so far
+pub async fn cancel_execution(
+    State(state): State<AppState>,
+    Extension(cancellations): Extension<Cancellations>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    if cancellations.cancel(&id) {
+        tracing::info!("Canceling execution of sandbox {}", id);
+        return Ok((StatusCode::ACCEPTED, Json(json!({ "message": "Execution canceled" }))));
+    }
+    if state.get_sandbox_info(&id).await.is_none() {
+        return Err(ApiError::not_found(format!("Sandbox {} not found", id)));
     }
+    Err(ApiError::Conflict(format!("Sandbox {} has no execution in progress", id)))
 }
 
+/// GET /sandbox[?status=running&runtime=node&mode=persistent&label=team=ml&order=asc&limit=50&cursor=...]
 pub async fn list_sandboxes(
     State(state): State<AppState>,
-) -> Result<Json<Vec<SandboxInfo>>, StatusCode> {
-    let manager = state.read().await;
-    let sandboxes = manager.list_sandboxes().await;
-    Ok(Json(sandboxes))
+    Query(query): Query<SandboxListQuery>,
+) -> Result<Json<SandboxList>, ApiError> {
+    let page = listing::page(state.get_all_sandboxes().await, &query)
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+    Ok(Json(SandboxList {
+        sandboxes: page.sandboxes.iter().map(|sandbox| sandbox.to_info()).collect(),
+        total: page.total,
+        next_cursor: page.next_cursor,
+    }))
 }
 
 pub async fn upload_files(
     State(state): State<AppState>,
     Path(id): Path<String>,
     Json(files): Json<Vec<SandboxFile>>,
-) -> Result<Json<Value>, StatusCode> {
-    let mut manager = state.write().await;
-    
-    // Convert API files to sandbox files
-    let sandbox_files: Vec<crate::sandbox::SandboxFile> = files.into_iter().map(|f| {
-        crate::sandbox::SandboxFile {
-            path: f.path,
-            content: f.content,
-            is_executable: f.is_executable,
-        }
-    }).collect();
-    
-    match manager.add_files_to_sandbox(&id, sandbox_files).await {
-        Ok(_) => Ok(Json(json!({
-            "message": "Files uploaded successfully",
-            "sandbox_id": id
-        }))),
-        Err(_) => Err(StatusCode::NOT_FOUND),
+) -> Result<Json<Value>, ApiError> {
+    state.add_files_to_sandbox(&id, files).await?;
+    Ok(Json(json!({
+        "message": "Files uploaded successfully",
+        "sandbox_id": id
+    })))
+}
+
+pub async fn snapshot_sandbox(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<SnapshotRequest>,
+) -> Result<Json<SnapshotResponse>, ApiError> {
+    let snapshot = state.snapshot_sandbox(&id, &req.name).await?;
+    Ok(Json(SnapshotResponse {
+        sandbox_id: id,
+        name: req.name,
+        snapshot,
+    }))
+}
+
+/// Download the artifacts collected after the sandbox's last execution as a zip archive
+pub async fn get_artifacts(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if state.get_sandbox_info(&id).await.is_none() {
+        return Err(ApiError::not_found(format!("Sandbox {} not found", id)));
+    }
+    if let Some(url) = state.artifacts_url(&id) {
+        return Ok(Redirect::temporary(&url).into_response());
     }
-}
\ No newline at end of file
+    let artifacts = state.artifacts(&id)
+        .ok_or_else(|| ApiError::not_found(format!("Sandbox {} has no collected artifacts", id)))?;
+
+    let archive = crate::sandbox::artifacts::to_zip(&artifacts)
+        .map_err(|e| ApiError::internal(format!("Failed to archive artifacts of sandbox {}: {}", id, e)))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}-artifacts.zip\"", id)),
+        ],
+        archive,
+    ).into_response())
+}
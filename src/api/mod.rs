@@ -1,68 +1,54 @@
 use axum::{
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::sandbox::SandboxManager;
+use crate::coordination::IdempotencyKeys;
+use crate::sandbox::{Cancellations, SandboxManager};
 
+pub mod cors;
+pub mod error;
 pub mod handlers;
+pub mod public_url;
+pub mod sessions;
+pub mod terminal;
+pub mod tools;
+
+pub use voidrun_types::sandbox::{
+    Capabilities, CompareRequest, CompareResult, CreateSandboxRequest, ExecutionResult, ReplayResult, SandboxFile, SandboxInfo, SandboxList, SandboxListQuery,
+    SnapshotRequest, SnapshotResponse, TestRequest, TestResults, TestStatus,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SandboxFile {
-    pub path: String,
-    pub content: String,
-    pub is_executable: Option<bool>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CreateSandboxRequest {
-    pub runtime: String,
-    pub code: String,
-    pub entry_point: Option<String>,
-    pub timeout_ms: Option<u64>,
-    pub memory_limit_mb: Option<u64>,
-    pub env_vars: Option<HashMap<String, String>>,
-    pub files: Option<Vec<SandboxFile>>,
-    pub mode: Option<String>, // "oneshot" or "persistent"
-    pub install_deps: Option<bool>,
-    pub dev_server: Option<bool>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SandboxInfo {
-    pub id: String,
-    pub status: String,
-    pub runtime: String,
-    pub created_at: String,
-    pub timeout_ms: u64,
-    pub memory_limit_mb: u64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExecutionResult {
-    pub sandbox_id: String,
-    pub success: bool,
-    pub stdout: String,
-    pub stderr: String,
-    pub exit_code: Option<i32>,
-    pub execution_time_ms: u64,
-}
-
-pub type AppState = Arc<RwLock<SandboxManager>>;
+pub type AppState = Arc<SandboxManager>;
 
-pub fn create_router(state: AppState) -> Router {
+/// `cancellations` must be the manager's, see [`SandboxManager::cancellations`]
+pub fn create_router(state: AppState, cancellations: Cancellations, idempotency: IdempotencyKeys) -> Router {
     Router::new()
         .route("/health", get(handlers::health_check))
+        .route("/runtimes", get(handlers::list_runtimes))
         .route("/execute", post(handlers::execute_one_shot))
+        .route("/execute/compare", post(handlers::compare_executions))
+        .route("/tools/format", post(tools::format_code))
+        .route("/tools/lint", post(tools::lint_code))
+        .route("/executions/:id/replay", post(handlers::replay_execution))
         .route("/sandbox", post(handlers::create_sandbox))
         .route("/sandbox/:id", get(handlers::get_sandbox))
         .route("/sandbox/:id", axum::routing::delete(handlers::delete_sandbox))
         .route("/sandbox/:id/execute", post(handlers::execute_code))
+        .route("/sandbox/:id/test", post(handlers::run_tests))
         .route("/sandbox", get(handlers::list_sandboxes))
         .route("/sandbox/:id/files", post(handlers::upload_files))
+        .route("/sandbox/:id/pause", post(handlers::pause_sandbox))
+        .route("/sandbox/:id/resume", post(handlers::resume_sandbox))
+        .route("/sandbox/:id/cancel", post(handlers::cancel_execution))
+        .route("/sandbox/:id/snapshot", post(handlers::snapshot_sandbox))
+        .route("/sandbox/:id/artifacts", get(handlers::get_artifacts))
+        .route("/sandbox/:id/terminal", get(terminal::open_terminal))
+        .route("/sessions", post(sessions::create_session))
+        .route("/sessions/:id", axum::routing::delete(sessions::delete_session))
+        .route("/sessions/:id/eval", post(sessions::eval))
+        .layer(Extension(cancellations))
+        .layer(Extension(idempotency))
         .with_state(state)
 }
\ No newline at end of file
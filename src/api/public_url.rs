@@ -0,0 +1,136 @@
+//! URL clients reach the service at, for the URLs it hands out
+//!
+//! `server.public_url` wins when set. Otherwise the URL is rebuilt from the request: a
+//! reverse proxy's `X-Forwarded-Proto`, `X-Forwarded-Host` and `X-Forwarded-Prefix`,
+//! else the `Host` header, else the address the service is bound to.
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap};
+use std::convert::Infallible;
+
+pub const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+pub const FORWARDED_HOST_HEADER: &str = "x-forwarded-host";
+pub const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+
+/// Base URL used when nothing else is known
+pub const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8070";
+
+/// How the service's public base URL is found, shared with handlers as an extension
+#[derive(Debug, Clone)]
+pub struct PublicUrl {
+    configured: Option<String>,
+    fallback: String,
+}
+
+impl Default for PublicUrl {
+    fn default() -> Self {
+        Self::new(None, DEFAULT_BASE_URL.to_string())
+    }
+}
+
+impl PublicUrl {
+    /// `fallback` is the URL of the bound address, used when a request names no host
+    pub fn new(configured: Option<String>, fallback: String) -> Self {
+        Self {
+            configured: configured.map(|url| url.trim_end_matches('/').to_string()),
+            fallback: fallback.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Base URL without a request: the configured one, else the bound address
+    pub fn base(&self) -> &str {
+        self.configured.as_deref().unwrap_or(&self.fallback)
+    }
+
+    /// Base URL, without a trailing slash, a client sending `headers` reached the service at
+    pub fn resolve(&self, headers: &HeaderMap) -> String {
+        if let Some(configured) = &self.configured {
+            return configured.clone();
+        }
+
+        let prefix = first_value(headers, FORWARDED_PREFIX_HEADER)
+            .map(|prefix| prefix.trim_end_matches('/'))
+            .filter(|prefix| is_path(prefix))
+            .map(|prefix| if prefix.starts_with('/') { prefix.to_string() } else { format!("/{}", prefix) })
+            .unwrap_or_default();
+        let host = first_value(headers, FORWARDED_HOST_HEADER)
+            .or_else(|| first_value(headers, header::HOST.as_str()))
+            .filter(|host| is_host(host));
+        let Some(host) = host else {
+            return format!("{}{}", self.fallback, prefix);
+        };
+        let proto = match first_value(headers, FORWARDED_PROTO_HEADER) {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        };
+        format!("{}://{}{}", proto, host, prefix)
+    }
+}
+
+/// First entry of a header a chain of proxies may have appended to
+fn first_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    let value = headers.get(name)?.to_str().ok()?;
+    value.split(',').next().map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn is_host(host: &str) -> bool {
+    host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+fn is_path(prefix: &str) -> bool {
+    prefix.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '-' | '_' | '~'))
+}
+
+/// Public base URL of the service as seen by the request's client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUrl(pub String);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for BaseUrl {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let url = match parts.extensions.get::<PublicUrl>() {
+            Some(public_url) => public_url.resolve(&parts.headers),
+            None => PublicUrl::default().resolve(&parts.headers),
+        };
+        Ok(Self(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_resolve() {
+        let public_url = PublicUrl::new(None, "http://0.0.0.0:8070".to_string());
+        assert_eq!(public_url.resolve(&HeaderMap::new()), "http://0.0.0.0:8070");
+        assert_eq!(public_url.resolve(&headers(&[("host", "localhost:8070")])), "http://localhost:8070");
+        assert_eq!(
+            public_url.resolve(&headers(&[
+                ("host", "10.0.0.2:8070"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "sandbox.example.com, proxy.internal"),
+                ("x-forwarded-prefix", "/voidrun/"),
+            ])),
+            "https://sandbox.example.com/voidrun"
+        );
+        assert_eq!(
+            public_url.resolve(&headers(&[("x-forwarded-host", "evil.com/x"), ("x-forwarded-prefix", "api")])),
+            "http://0.0.0.0:8070/api"
+        );
+
+        let configured = PublicUrl::new(Some("https://run.example.com/".to_string()), "http://0.0.0.0:8070".to_string());
+        assert_eq!(configured.resolve(&headers(&[("x-forwarded-host", "other.com")])), "https://run.example.com");
+    }
+}
@@ -0,0 +1,58 @@
+//! REPL sessions, `/sessions`
+//!
+//! A session is created like a sandbox from the request's runtime and files, with the
+//! driver evaluating code as its dev server; see [`crate::sandbox::sessions`].
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use uuid::Uuid;
+use voidrun_types::{CreateSessionRequest, EvalRequest, EvalResult};
+
+use super::error::ApiError;
+use super::handlers::sandbox_request;
+use super::{AppState, SandboxInfo};
+use crate::metering::ApiKey;
+use crate::sandbox::sessions;
+
+/// Start a session, answering once it takes evaluations
+///
+/// POST /sessions
+pub async fn create_session(
+    State(state): State<AppState>,
+    api_key: ApiKey,
+    Json(req): Json<CreateSessionRequest>,
+) -> Result<Json<SandboxInfo>, ApiError> {
+    let session_id = Uuid::new_v4().to_string();
+    let request = sandbox_request(session_id.clone(), sessions::sandbox_request(req)?, api_key.0)?;
+    if let Err(e) = state.create_session(request).await {
+        let error = ApiError::from(e);
+        tracing::warn!("Rejected session {}: {}", session_id, error);
+        return Err(error);
+    }
+    state.get_sandbox_info(&session_id).await
+        .map(Json)
+        .ok_or_else(|| ApiError::internal(format!("Session {} vanished after creation", session_id)))
+}
+
+/// Evaluate code in the session, keeping what it declares for later evaluations
+///
+/// POST /sessions/:id/eval
+pub async fn eval(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<EvalRequest>,
+) -> Result<Json<EvalResult>, ApiError> {
+    Ok(Json(state.eval_session(&id, req).await?))
+}
+
+/// DELETE /sessions/:id
+pub async fn delete_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.delete_session(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
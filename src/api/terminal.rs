@@ -0,0 +1,124 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::Response,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use super::error::ApiError;
+use super::AppState;
+use crate::sandbox::backend::TerminalSession;
+
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+#[derive(Debug, Deserialize)]
+pub struct TerminalQuery {
+    /// Shell to start (default: /bin/sh)
+    pub shell: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+}
+
+/// Control messages sent as JSON text frames; any other frame is terminal input
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TerminalControl {
+    Resize { cols: u16, rows: u16 },
+}
+
+/// Interactive shell into a persistent sandbox
+///
+/// GET /sandbox/{id}/terminal (WebSocket)
+/// Output is sent as binary frames; input is read from binary and text frames.
+pub async fn open_terminal(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TerminalQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let shell = query.shell.unwrap_or_else(|| DEFAULT_SHELL.to_string());
+
+    let session = {
+        let sandbox = state.get_sandbox_info(&id).await
+            .ok_or_else(|| ApiError::not_found(format!("Sandbox {} not found", id)))?;
+        if !sandbox.is_persistent {
+            return Err(ApiError::Conflict(format!("Sandbox {} is not persistent", id)));
+        }
+        let backend = state.get_backend(&id)
+            .ok_or_else(|| ApiError::internal(format!("Backend of sandbox {} is not registered", id)))?;
+        backend
+            .open_terminal(&id, &shell, query.cols.unwrap_or(80), query.rows.unwrap_or(24))
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to open terminal in sandbox {}: {}", id, e);
+                if e.to_string().contains("does not support") {
+                    ApiError::NotImplemented(e.to_string())
+                } else {
+                    ApiError::from(e)
+                }
+            })?
+    };
+
+    info!("Terminal {} attached to sandbox {}", session.id, id);
+    Ok(ws.on_upgrade(move |socket| run_terminal(socket, state, session)))
+}
+
+async fn run_terminal(socket: WebSocket, state: AppState, session: TerminalSession) {
+    let TerminalSession { id, mut output, mut input } = session;
+    let (mut sender, mut receiver) = socket.split();
+
+    let mut forward_output = tokio::spawn(async move {
+        while let Some(chunk) = output.next().await {
+            match chunk {
+                Ok(data) => {
+                    if sender.send(Message::Binary(data)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    warn!("Terminal output stream failed: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = sender.send(Message::Close(None)).await;
+    });
+
+    loop {
+        let message = tokio::select! {
+            _ = &mut forward_output => break,
+            message = receiver.next() => message,
+        };
+
+        let data = match message {
+            Some(Ok(Message::Binary(data))) => data,
+            Some(Ok(Message::Text(text))) => match serde_json::from_str::<TerminalControl>(&text) {
+                Ok(TerminalControl::Resize { cols, rows }) => {
+                    if let Some(backend) = state.get_backend(&id) {
+                        if let Err(e) = backend.resize_terminal(&id, cols, rows).await {
+                            warn!("Failed to resize terminal {}: {}", id, e);
+                        }
+                    }
+                    continue;
+                }
+                Err(_) => text.into_bytes(),
+            },
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            Some(Ok(_)) => continue,
+        };
+
+        if input.write_all(&data).await.is_err() || input.flush().await.is_err() {
+            break;
+        }
+    }
+
+    // Closing stdin ends the shell
+    let _ = input.shutdown().await;
+    forward_output.abort();
+    info!("Terminal {} closed", id);
+}
@@ -0,0 +1,196 @@
+//! Code tools, `/tools/format` and `/tools/lint`
+//!
+//! The code is formatted with prettier or linted with eslint, both preinstalled in the
+//! runtime images, by a fixed Node.js entry point running in a one-shot sandbox. The
+//! code is written under `code/` next to the request's files, so a `.prettierrc` or
+//! `eslint.config.js` at the project root applies to it. Results go through the result
+//! cache like other one-shot executions.
+
+use axum::{extract::State, response::Json};
+use serde::Deserialize;
+use voidrun_types::{CreateSandboxRequest, Diagnostic, DiagnosticSeverity, FormatResult, LintResult, SandboxFile, ToolRequest};
+
+use super::error::ApiError;
+use super::handlers::one_shot_request;
+use super::AppState;
+use crate::metering::ApiKey;
+use crate::sandbox::{SandboxResponse, TerminationReason};
+
+const DEFAULT_FILENAME: &str = "index.js";
+/// Directory the code is written to, so it cannot collide with the entry point
+const CODE_DIR: &str = "code";
+/// Exit code of the entry point when the tool is not installed
+const TOOL_MISSING: i32 = 127;
+const TOOL_TIMEOUT_MS: u64 = 30_000;
+
+/// Config eslint runs with when the request brings none: core rules catching likely bugs
+const DEFAULT_ESLINT_CONFIG: &str = r#"module.exports = [{
+  languageOptions: { ecmaVersion: "latest", sourceType: "module" },
+  rules: {
+    "no-unused-vars": "warn",
+    "no-unreachable": "error",
+    "no-dupe-keys": "error",
+    "no-const-assign": "error",
+    "no-self-assign": "error",
+    "no-debugger": "warn",
+  },
+}];
+"#;
+
+/// Format the code with prettier
+///
+/// POST /tools/format
+pub async fn format_code(
+    State(state): State<AppState>,
+    api_key: ApiKey,
+    Json(req): Json<ToolRequest>,
+) -> Result<Json<FormatResult>, ApiError> {
+    let code = req.code.clone();
+    let response = run_tool(&state, api_key, req, "prettier", &[]).await?;
+    Ok(Json(FormatResult {
+        changed: response.stdout != code,
+        formatted: response.stdout,
+    }))
+}
+
+/// Lint the code with eslint
+///
+/// POST /tools/lint
+pub async fn lint_code(
+    State(state): State<AppState>,
+    api_key: ApiKey,
+    Json(mut req): Json<ToolRequest>,
+) -> Result<Json<LintResult>, ApiError> {
+    let has_config = req.files.iter().flatten().any(|file| file.path.trim_start_matches("./").starts_with("eslint.config."));
+    if !has_config {
+        req.files.get_or_insert_with(Vec::new).push(file("eslint.config.js", DEFAULT_ESLINT_CONFIG.to_string()));
+    }
+    let filename = filename(&req);
+    // eslint exits with 1 when the code has errors, which are results like warnings
+    let response = run_tool(&state, api_key, req, "eslint", &[1]).await?;
+    let diagnostics = parse_eslint(&response.stdout, &filename)
+        .ok_or_else(|| ApiError::internal(format!("eslint printed no report: {}", response.stderr.trim())))?;
+    Ok(Json(LintResult {
+        success: !diagnostics.iter().any(|diagnostic| diagnostic.severity == DiagnosticSeverity::Error),
+        diagnostics,
+    }))
+}
+
+fn filename(req: &ToolRequest) -> String {
+    req.filename.clone().unwrap_or_else(|| DEFAULT_FILENAME.to_string())
+}
+
+fn file(path: &str, content: String) -> SandboxFile {
+    SandboxFile {
+        path: path.to_string(),
+        content,
+        is_executable: None,
+        encoding: None,
+    }
+}
+
+/// Run `tool` on the request's code, passing on what it printed if it exited with `0` or
+/// one of `ok_exit_codes`
+async fn run_tool(
+    state: &AppState,
+    api_key: ApiKey,
+    req: ToolRequest,
+    tool: &str,
+    ok_exit_codes: &[i32],
+) -> Result<SandboxResponse, ApiError> {
+    let path = format!("{}/{}", CODE_DIR, filename(&req));
+    let mut files = req.files.unwrap_or_default();
+    files.push(file(&path, req.code));
+    let request = one_shot_request(CreateSandboxRequest {
+        runtime: "node".to_string(),
+        code: entry_point(tool, &path),
+        timeout_ms: Some(TOOL_TIMEOUT_MS),
+        files: Some(files),
+        ..Default::default()
+    }, api_key.0)?;
+
+    let (response, _) = state.execute_cached(request, true).await?;
+    match (response.termination_reason, response.exit_code) {
+        (_, Some(0)) => Ok(response),
+        (Some(TerminationReason::Completed), Some(code)) if ok_exit_codes.contains(&code) => Ok(response),
+        (Some(TerminationReason::Completed), Some(code)) if code != TOOL_MISSING => {
+            Err(ApiError::Validation(format!("{} failed: {}", tool, response.stderr.trim())))
+        }
+        _ => Err(ApiError::internal(format!("{} did not run: {}", tool, response.stderr.trim()))),
+    }
+}
+
+/// Node.js program running `tool` on the file at `path` from the project root, with its
+/// output and exit code
+fn entry_point(tool: &str, path: &str) -> String {
+    let args = match tool {
+        "eslint" => vec!["--format", "json", path],
+        _ => vec![path],
+    };
+    let args = serde_json::to_string(&args).unwrap_or_default();
+    format!(
+        r#"const {{ spawnSync }} = require("child_process");
+const result = spawnSync("{tool}", {args}, {{ cwd: __dirname, stdio: "inherit" }});
+if (result.error) {{
+  console.error("{tool} could not be started: " + result.error.message);
+  process.exit({missing});
+}}
+process.exit(result.status === null ? 1 : result.status);
+"#,
+        tool = tool,
+        args = args,
+        missing = TOOL_MISSING,
+    )
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintFile {
+    messages: Vec<EslintMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintMessage {
+    rule_id: Option<String>,
+    /// 1 for warnings, 2 for errors
+    severity: u8,
+    message: String,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+/// Diagnostics in eslint's JSON report of `filename`
+fn parse_eslint(stdout: &str, filename: &str) -> Option<Vec<Diagnostic>> {
+    let files: Vec<EslintFile> = serde_json::from_str(stdout.trim()).ok()?;
+    Some(files.into_iter().flat_map(|file| file.messages).map(|message| Diagnostic {
+        file: filename.to_string(),
+        line: message.line,
+        column: message.column,
+        severity: if message.severity >= 2 { DiagnosticSeverity::Error } else { DiagnosticSeverity::Warning },
+        code: message.rule_id,
+        message: message.message,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_eslint() {
+        let report = r#"[{"filePath":"/sandbox/code/index.js","messages":[
+            {"ruleId":"no-unused-vars","severity":1,"message":"'x' is assigned a value but never used.","line":1,"column":7},
+            {"ruleId":null,"fatal":true,"severity":2,"message":"Parsing error: Unexpected token ;","line":2,"column":9}
+        ],"errorCount":1,"warningCount":1}]"#;
+        let diagnostics = parse_eslint(report, "index.js").unwrap();
+        assert_eq!(diagnostics[0].code.as_deref(), Some("no-unused-vars"));
+        assert_eq!(diagnostics[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostics[1].code, None);
+        assert_eq!(diagnostics[1].severity, DiagnosticSeverity::Error);
+        assert_eq!((diagnostics[1].file.as_str(), diagnostics[1].line, diagnostics[1].column), ("index.js", Some(2), Some(9)));
+
+        assert!(parse_eslint("Oops! Something went wrong!", "index.js").is_none());
+        assert!(entry_point("eslint", "code/a \"b\".js").contains(r#"["--format","json","code/a \"b\".js"]"#));
+    }
+}
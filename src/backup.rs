@@ -0,0 +1,314 @@
+//! Scheduled backups of the deployments, `[backup]`, and restores from them
+//!
+//! Deployments are the one state the service cannot rebuild by itself: sandboxes are
+//! reattached after a restart and the cluster store's ownership, heartbeats and port
+//! leases are written anew by the running instances, but a deployment whose instances
+//! are gone, and every static site, is lost. A backup is named after the time it was
+//! taken and holds `backup.json` and one [`bundle`] per deployment, in a directory under
+//! `dir` or, with `s3 = true`, under `backups/<name>/` in the `[s3]` bucket. A restore
+//! deploys the deployments of a backup that are missing again, under their IDs, URLs
+//! and access tokens.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::api::error::{InvalidInput, NotFound};
+use crate::config::BackupConfig;
+use crate::faas::{bundle, FaasManager};
+use crate::sandbox::StatusConflict;
+use crate::storage::ObjectStore;
+
+pub const INDEX_FILE: &str = "backup.json";
+/// Prefix of a backup being written to `dir`, which listings skip
+const PARTIAL_PREFIX: &str = ".partial-";
+/// Object key prefix of backups in the bucket
+const OBJECT_PREFIX: &str = "backups/";
+
+/// `backup.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupIndex {
+    created_at: DateTime<Utc>,
+    deployments: Vec<BackedUpDeployment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackedUpDeployment {
+    deployment_id: String,
+    runtime: String,
+    /// Hash of the access token, kept so clients holding it can reach the restored
+    /// deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    access_token_hash: Option<String>,
+    /// Bundle file next to `backup.json`
+    bundle: String,
+}
+
+/// A backup as listed by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupSummary {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub deployments: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub backup: String,
+    /// Deployments deployed again
+    pub restored: Vec<String>,
+    /// Deployments left alone as they exist already
+    pub skipped: Vec<String>,
+    pub failed: Vec<RestoreFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreFailure {
+    pub deployment_id: String,
+    pub error: String,
+}
+
+/// Where backups are written
+#[derive(Debug)]
+enum Target {
+    Dir(PathBuf),
+    Bucket(Arc<ObjectStore>),
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dir(dir) => write!(f, "{}", dir.display()),
+            Self::Bucket(store) => write!(f, "{}", store.uri(OBJECT_PREFIX)),
+        }
+    }
+}
+
+impl Target {
+    /// Store a file of backup `name`, which stays hidden until [`Self::finish`]
+    async fn write(&self, name: &str, file: &str, contents: Vec<u8>) -> Result<()> {
+        match self {
+            Self::Dir(dir) => {
+                let partial = dir.join(format!("{}{}", PARTIAL_PREFIX, name));
+                tokio::fs::create_dir_all(&partial).await
+                    .with_context(|| format!("Cannot create backup directory {}", partial.display()))?;
+                tokio::fs::write(partial.join(file), contents).await
+                    .with_context(|| format!("Cannot write {} of backup {}", file, name))
+            }
+            Self::Bucket(store) => store.put(&object_key(name, file), contents, content_type(file)).await,
+        }
+    }
+
+    /// Make backup `name` visible; in the bucket, writing `backup.json` last already does
+    async fn finish(&self, name: &str) -> Result<()> {
+        if let Self::Dir(dir) = self {
+            tokio::fs::rename(dir.join(format!("{}{}", PARTIAL_PREFIX, name)), dir.join(name)).await
+                .with_context(|| format!("Cannot finish backup {}", name))?;
+        }
+        Ok(())
+    }
+
+    async fn read(&self, name: &str, file: &str) -> Result<Vec<u8>> {
+        match self {
+            Self::Dir(dir) => tokio::fs::read(dir.join(name).join(file)).await
+                .with_context(|| format!("Cannot read {} of backup {}", file, name)),
+            Self::Bucket(store) => store.get(&object_key(name, file)).await?
+                .ok_or_else(|| anyhow::anyhow!("Backup {} has no {}", name, file)),
+        }
+    }
+
+    /// Names of the finished backups
+    async fn names(&self) -> Result<Vec<String>> {
+        match self {
+            Self::Dir(dir) => {
+                let mut names = Vec::new();
+                let mut entries = match tokio::fs::read_dir(dir).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+                    Err(e) => return Err(e).with_context(|| format!("Cannot read backup directory {}", dir.display())),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if !name.starts_with('.') && entry.file_type().await?.is_dir() {
+                        names.push(name);
+                    }
+                }
+                Ok(names)
+            }
+            Self::Bucket(store) => Ok(store.list(OBJECT_PREFIX).await?.iter()
+                .filter_map(|key| key.strip_prefix(OBJECT_PREFIX)?.strip_suffix(&format!("/{}", INDEX_FILE)))
+                .filter(|name| !name.contains('/'))
+                .map(str::to_string)
+                .collect()),
+        }
+    }
+
+    async fn remove(&self, name: &str) -> Result<()> {
+        match self {
+            Self::Dir(dir) => Ok(tokio::fs::remove_dir_all(dir.join(name)).await?),
+            Self::Bucket(store) => {
+                // The index goes first, so a backup removed halfway is no longer listed
+                store.delete(&object_key(name, INDEX_FILE)).await?;
+                for key in store.list(&object_key(name, "")).await? {
+                    store.delete(&key).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn object_key(name: &str, file: &str) -> String {
+    format!("{}{}/{}", OBJECT_PREFIX, name, file)
+}
+
+fn content_type(file: &str) -> &'static str {
+    if file == INDEX_FILE { "application/json" } else { "application/gzip" }
+}
+
+pub struct Backups {
+    config: BackupConfig,
+    target: Option<Target>,
+    faas_manager: Arc<FaasManager>,
+    /// Held while a backup is taken or restored, so neither sees the other half done
+    busy: Mutex<()>,
+}
+
+impl Backups {
+    /// Backups to `config.dir`, or to `store` with `config.s3`
+    pub fn new(config: BackupConfig, faas_manager: Arc<FaasManager>, store: Option<Arc<ObjectStore>>) -> Self {
+        let target = if config.s3 {
+            store.map(Target::Bucket)
+        } else {
+            config.dir.clone().map(Target::Dir)
+        };
+        Self { config, target, faas_manager, busy: Mutex::new(()) }
+    }
+
+    /// Where backups go, when enabled
+    pub fn target(&self) -> Option<String> {
+        self.target.as_ref().map(Target::to_string)
+    }
+
+    fn enabled_target(&self) -> Result<&Target> {
+        self.target.as_ref().ok_or_else(|| StatusConflict("Backups are disabled; set [backup] dir or s3".to_string()).into())
+    }
+
+    /// Take a backup every `interval_seconds`, the first one an interval after startup
+    pub fn start(self: Arc<Self>) {
+        if self.target.is_none() || self.config.interval_seconds == 0 {
+            return;
+        }
+        let interval = Duration::from_secs(self.config.interval_seconds);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.create().await {
+                    error!("Scheduled backup failed: {:#}", e);
+                }
+            }
+        });
+    }
+
+    /// Back up every deployment that is not failed, then drop backups past `keep`
+    pub async fn create(&self) -> Result<BackupSummary> {
+        let target = self.enabled_target()?;
+        let _busy = self.busy.lock().await;
+        let created_at = Utc::now();
+        let name = created_at.format("%Y%m%dT%H%M%S%.3fZ").to_string();
+
+        let mut deployments = Vec::new();
+        for (deployment_id, request, access_token_hash) in self.faas_manager.backup_entries().await {
+            let file = format!("{}.tar.gz", deployment_id);
+            target.write(&name, &file, bundle::export(&deployment_id, &request)?).await
+                .with_context(|| format!("Cannot back up deployment {}", deployment_id))?;
+            deployments.push(BackedUpDeployment { deployment_id, runtime: request.runtime, access_token_hash, bundle: file });
+        }
+        let index = BackupIndex { created_at, deployments };
+        target.write(&name, INDEX_FILE, serde_json::to_vec_pretty(&index)?).await?;
+        target.finish(&name).await?;
+        info!("Backed up {} deployments to {} as {}", index.deployments.len(), target, name);
+
+        self.prune(target).await;
+        Ok(BackupSummary { name, created_at, deployments: index.deployments.len() })
+    }
+
+    /// Backups, newest first
+    pub async fn list(&self) -> Result<Vec<BackupSummary>> {
+        let target = self.enabled_target()?;
+        let mut backups = Vec::new();
+        for name in target.names().await? {
+            if let Ok(index) = read_index(target, &name).await {
+                backups.push(BackupSummary { name, created_at: index.created_at, deployments: index.deployments.len() });
+            }
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+        Ok(backups)
+    }
+
+    /// Deploy the deployments of backup `name`, the latest without one, that do not exist
+    pub async fn restore(&self, name: Option<&str>) -> Result<RestoreReport> {
+        let target = self.enabled_target()?;
+        let _busy = self.busy.lock().await;
+        // Only names listed are looked up, so a name cannot point outside the backups
+        let backups = self.list().await?;
+        let backup = match name {
+            Some(name) => backups.into_iter().find(|backup| backup.name == name)
+                .ok_or_else(|| NotFound(format!("Backup {} not found", name)))?,
+            None => backups.into_iter().next().ok_or_else(|| NotFound("No backups to restore".to_string()))?,
+        };
+        let index = read_index(target, &backup.name).await?;
+
+        let mut report = RestoreReport { backup: backup.name.clone(), ..Default::default() };
+        for entry in index.deployments {
+            match self.restore_one(target, &backup.name, &entry).await {
+                Ok(()) => report.restored.push(entry.deployment_id),
+                Err(e) if e.is::<StatusConflict>() => report.skipped.push(entry.deployment_id),
+                Err(e) => {
+                    warn!("Cannot restore deployment {} from backup {}: {:#}", entry.deployment_id, backup.name, e);
+                    report.failed.push(RestoreFailure { deployment_id: entry.deployment_id, error: format!("{:#}", e) });
+                }
+            }
+        }
+        info!(
+            "Restored {} deployments from backup {} ({} skipped, {} failed)",
+            report.restored.len(), report.backup, report.skipped.len(), report.failed.len()
+        );
+        Ok(report)
+    }
+
+    async fn restore_one(&self, target: &Target, name: &str, entry: &BackedUpDeployment) -> Result<()> {
+        if entry.bundle.contains('/') || entry.bundle.contains("..") {
+            return Err(InvalidInput(format!("Invalid bundle name {:?}", entry.bundle)).into());
+        }
+        let (_, request) = bundle::import(&target.read(name, &entry.bundle).await?)?;
+        self.faas_manager.restore_deployment(&entry.deployment_id, entry.access_token_hash.clone(), request).await?;
+        Ok(())
+    }
+
+    /// Remove the oldest backups past `keep`
+    async fn prune(&self, target: &Target) {
+        let backups = match self.list().await {
+            Ok(backups) => backups,
+            Err(e) => {
+                warn!("Cannot list backups to prune: {:#}", e);
+                return;
+            }
+        };
+        for backup in backups.iter().skip(self.config.keep) {
+            if let Err(e) = target.remove(&backup.name).await {
+                warn!("Cannot remove backup {}: {:#}", backup.name, e);
+            }
+        }
+    }
+}
+
+async fn read_index(target: &Target, name: &str) -> Result<BackupIndex> {
+    Ok(serde_json::from_slice(&target.read(name, INDEX_FILE).await?)?)
+}
@@ -0,0 +1,262 @@
+//! `voidrun` - command line client for the sandbox service HTTP API
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use voidrun_client::types::{CreateSandboxRequest, DeploymentRequest, DeploymentStatus, FileEncoding, SandboxFile};
+use voidrun_client::Client;
+
+/// Directories never uploaded by `voidrun deploy`
+const IGNORED_DIRS: &[&str] = &["node_modules", ".git"];
+
+/// Project config files the service reads deployment settings from
+const PROJECT_CONFIG_FILES: &[&str] = &["voidrun.toml", "voidrun.json"];
+
+#[derive(Parser)]
+#[command(name = "voidrun")]
+#[command(about = "Run code and manage deployments on a sandbox service")]
+struct Cli {
+    #[arg(long, env = "VOIDRUN_URL", default_value = "http://localhost:8070", help = "Sandbox service URL")]
+    url: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Execute a file in a one-shot sandbox
+    Run {
+        file: PathBuf,
+        #[arg(long, help = "Runtime (bun, node, typescript, rust, bash); inferred from the extension by default")]
+        runtime: Option<String>,
+        #[arg(long, help = "Timeout in milliseconds")]
+        timeout_ms: Option<u64>,
+    },
+    /// Deploy a project directory as a serverless function
+    Deploy {
+        dir: PathBuf,
+        #[arg(long, help = "Runtime (bun, node); read from voidrun.toml or inferred from the project by default")]
+        runtime: Option<String>,
+        #[arg(long, help = "Main file, relative to the project (default: index.ts or index.js)")]
+        entry_file: Option<String>,
+        #[arg(long, help = "Port the service listens on")]
+        port: Option<u16>,
+    },
+    /// Show the dev server logs of a deployment
+    Logs {
+        deployment: String,
+        #[arg(short = 'n', long, default_value_t = 100)]
+        lines: u32,
+    },
+    /// Rebuild a deployment in a fresh sandbox, keeping its URL
+    Redeploy {
+        deployment: String,
+    },
+    /// List deployments, or sandboxes with --sandboxes
+    Ls {
+        #[arg(long)]
+        sandboxes: bool,
+    },
+    /// Remove a deployment, or a sandbox with --sandbox
+    Rm {
+        id: String,
+        #[arg(long)]
+        sandbox: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let api = Client::new(&cli.url);
+
+    match cli.command {
+        Command::Run { file, runtime, timeout_ms } => run(&api, &file, runtime, timeout_ms).await,
+        Command::Deploy { dir, runtime, entry_file, port } => deploy(&api, &dir, runtime, entry_file, port).await,
+        Command::Logs { deployment, lines } => logs(&api, &deployment, lines).await,
+        Command::Redeploy { deployment } => redeploy(&api, &deployment).await,
+        Command::Ls { sandboxes } => list(&api, sandboxes).await,
+        Command::Rm { id, sandbox } => remove(&api, &id, sandbox).await,
+    }
+}
+
+async fn run(api: &Client, file: &Path, runtime: Option<String>, timeout_ms: Option<u64>) -> Result<()> {
+    let code = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let runtime = runtime.unwrap_or_else(|| {
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("ts") => "typescript",
+            Some("rs") => "rust",
+            Some("sh") => "bash",
+            _ => "node",
+        }.to_string()
+    });
+
+    let result = api.execute(&CreateSandboxRequest {
+        runtime,
+        code,
+        timeout_ms,
+        ..Default::default()
+    }).await?;
+
+    print!("{}", result.stdout);
+    eprint!("{}", result.stderr);
+
+    if !result.success {
+        let exit_code = result.exit_code.unwrap_or(1);
+        std::process::exit(if exit_code == 0 { 1 } else { exit_code });
+    }
+    Ok(())
+}
+
+async fn deploy(
+    api: &Client,
+    dir: &Path,
+    runtime: Option<String>,
+    entry_file: Option<String>,
+    port: Option<u16>,
+) -> Result<()> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+
+    // The service reads runtime, entry point and the rest from the project config itself
+    let has_project_config = files.iter()
+        .any(|file| PROJECT_CONFIG_FILES.contains(&file.path.as_str()));
+    if has_project_config && entry_file.is_none() {
+        let runtime = runtime.unwrap_or_default();
+        println!("Deploying {} ({} files, settings from project config)...", dir.display(), files.len());
+        return finish_deploy(api, DeploymentRequest {
+            runtime,
+            files: Some(files),
+            port,
+            ..Default::default()
+        }).await;
+    }
+
+    let entry_file = match entry_file {
+        Some(entry_file) => entry_file,
+        None => ["index.ts", "index.js"].iter()
+            .find(|name| files.iter().any(|file| file.path == **name))
+            .map(|name| name.to_string())
+            .context("No index.ts or index.js found; pass --entry-file")?,
+    };
+    let code = files.iter()
+        .find(|file| file.path == entry_file && file.encoding.is_none())
+        .map(|file| file.content.clone())
+        .with_context(|| format!("Entry file {} not found in {}", entry_file, dir.display()))?;
+
+    let runtime = runtime.unwrap_or_else(|| {
+        let uses_bun = files.iter().any(|file| file.path == "bun.lockb" || file.path == "bun.lock")
+            || entry_file.ends_with(".ts");
+        if uses_bun { "bun" } else { "node" }.to_string()
+    });
+
+    let files: Vec<SandboxFile> = files.into_iter()
+        .filter(|file| file.path != entry_file)
+        .collect();
+    println!("Deploying {} ({} files, runtime {})...", dir.display(), files.len() + 1, runtime);
+
+    finish_deploy(api, DeploymentRequest {
+        runtime,
+        code,
+        files: Some(files),
+        port,
+        ..Default::default()
+    }).await
+}
+
+async fn finish_deploy(api: &Client, request: DeploymentRequest) -> Result<()> {
+    let deployment = api.deploy(&request).await?;
+
+    println!("Deployment {} is {:?}", deployment.deployment_id, deployment.status);
+    if deployment.status == DeploymentStatus::Failed {
+        if let Some(logs) = &deployment.logs {
+            eprintln!("{}", logs.trim_end());
+        }
+        let stage = deployment.failure_reason.map(|reason| reason.as_str()).unwrap_or("deploy");
+        anyhow::bail!("{} failed: {}", stage, deployment.error.unwrap_or_default());
+    }
+    println!("URL: {}", deployment.url);
+    if let Some(token) = deployment.access_token {
+        println!("Access token: {}", token);
+    }
+    Ok(())
+}
+
+/// Read every file under `dir`, base64-encoding those that are not text
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<SandboxFile>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+        if path.is_dir() {
+            if !IGNORED_DIRS.contains(&name) {
+                collect_files(root, &path, files)?;
+            }
+            continue;
+        }
+
+        let relative = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+        let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let (content, encoding) = match String::from_utf8(bytes) {
+            Ok(text) => (text, None),
+            Err(e) => (BASE64_STANDARD.encode(e.into_bytes()), Some(FileEncoding::Base64)),
+        };
+        files.push(SandboxFile { path: relative, content, is_executable: None, encoding });
+    }
+    Ok(())
+}
+
+async fn logs(api: &Client, deployment_id: &str, lines: u32) -> Result<()> {
+    let deployment = api.get_deployment(deployment_id).await?;
+
+    // Failed deployments keep the logs captured before their sandbox was removed
+    if let Some(captured) = deployment.logs {
+        print!("{}", captured);
+        return Ok(());
+    }
+
+    for entry in api.logs(&deployment.sandbox_id, lines).await? {
+        println!("{} {}", entry.timestamp, entry.message);
+    }
+    Ok(())
+}
+
+async fn list(api: &Client, sandboxes: bool) -> Result<()> {
+    if sandboxes {
+        println!("{:<38} {:<12} {:<10} CREATED", "ID", "STATUS", "RUNTIME");
+        for sandbox in api.list_sandboxes().await? {
+            println!("{:<38} {:<12} {:<10} {}", sandbox.id, sandbox.status, sandbox.runtime, sandbox.created_at);
+        }
+    } else {
+        println!("{:<38} {:<13} {:<10} {:<9} URL", "ID", "STATUS", "RUNTIME", "INSTANCES");
+        for deployment in api.list_deployments().await? {
+            println!("{:<38} {:<13} {:<10} {:<9} {}",
+                     deployment.deployment_id,
+                     format!("{:?}", deployment.status),
+                     deployment.runtime,
+                     deployment.instances,
+                     deployment.url);
+        }
+    }
+    Ok(())
+}
+
+async fn redeploy(api: &Client, deployment_id: &str) -> Result<()> {
+    let deployment = api.redeploy(deployment_id).await?;
+    println!("Deployment {} is {:?} in sandbox {}", deployment.deployment_id, deployment.status, deployment.sandbox_id);
+    Ok(())
+}
+
+async fn remove(api: &Client, id: &str, sandbox: bool) -> Result<()> {
+    if sandbox {
+        api.delete_sandbox(id).await?;
+        println!("Removed sandbox {}", id);
+    } else {
+        api.undeploy(id).await?;
+        println!("Removed deployment {}", id);
+    }
+    Ok(())
+}
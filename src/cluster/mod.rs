@@ -0,0 +1,914 @@
+//! Cluster mode: several instances behind one load balancer
+//!
+//! Every instance runs the sandboxes and deployments it created. Through a
+//! [`ClusterStore`] the instances share which instance owns each of them, which
+//! instances are alive, and the record each sandbox and deployment is listed and
+//! re-created from. A request for a sandbox or deployment another instance owns is
+//! forwarded there by [`route_middleware`], so the load balancer needs no affinity.
+//! Ownership and records follow the event bus and the instance's [`Registry`], and are
+//! re-asserted with every heartbeat. The deployments of an instance that stopped sending
+//! heartbeats, or left, are adopted by a live instance, which deploys them again from
+//! their records; its sandboxes cannot move and stay unreachable until it comes back.
+//!
+//! The store is Postgres, or Redis when the service coordinates through Redis
+//! (`store = "redis"`). Without cluster mode it is in memory and only ever holds this
+//! instance.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::api::error::ApiError;
+use crate::api::public_url::FORWARDED_HOST_HEADER;
+use crate::config::ProxyConfig;
+use crate::coordination::StoreKind;
+use crate::events::{EventBus, EventKind};
+use crate::proxy::headers;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// Header marking a request forwarded by another instance, which is never forwarded again
+pub const FORWARDED_HEADER: &str = "x-voidrun-forwarded-by";
+
+/// `[cluster]` in the configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Share ownership with other instances through `database_url` and forward requests
+    pub enabled: bool,
+    /// Name of this instance, unique in the cluster; defaults to the host name
+    pub node_id: Option<String>,
+    /// URL other instances reach this one at, e.g. `http://10.0.0.5:8070`
+    pub advertise_url: Option<String>,
+    /// Postgres connection string of the shared store (without TLS)
+    pub database_url: Option<String>,
+    pub heartbeat_interval_seconds: u64,
+    /// Silence after which an instance counts as down and its resources as unreachable
+    pub node_timeout_seconds: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_id: None,
+            advertise_url: None,
+            database_url: None,
+            heartbeat_interval_seconds: 5,
+            node_timeout_seconds: 30,
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Configured node id, else the host name
+    pub fn node_id(&self) -> String {
+        self.node_id.clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "voidrun".to_string())
+    }
+}
+
+/// What an instance owns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Sandbox,
+    Deployment,
+}
+
+impl ResourceKind {
+    pub const ALL: [Self; 2] = [Self::Sandbox, Self::Deployment];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sandbox => "sandbox",
+            Self::Deployment => "deployment",
+        }
+    }
+}
+
+/// An instance as last reported in its heartbeat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub url: String,
+    pub started_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub sandboxes: usize,
+    pub deployments: usize,
+}
+
+/// Sandboxes and deployments an instance owns
+#[derive(Debug, Clone, Default)]
+pub struct Owned {
+    pub sandboxes: HashSet<String>,
+    pub deployments: HashSet<String>,
+}
+
+impl Owned {
+    fn ids(&mut self, kind: ResourceKind) -> &mut HashSet<String> {
+        match kind {
+            ResourceKind::Sandbox => &mut self.sandboxes,
+            ResourceKind::Deployment => &mut self.deployments,
+        }
+    }
+}
+
+/// Record of a sandbox or deployment as shared in the store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharedRecord {
+    pub id: String,
+    /// Instance owning it; None once its owner left or stopped claiming it
+    pub node_id: Option<String>,
+    /// The sandbox's persisted request, or the deployment's record
+    pub record: Value,
+}
+
+/// Registry of instances, of the owner of each sandbox and deployment and of their records
+#[async_trait]
+pub trait ClusterStore: Send + Sync {
+    /// Record the instance as alive and replace its ownership records with `owned`
+    async fn heartbeat(&self, node: &NodeInfo, owned: &Owned) -> Result<()>;
+    async fn claim(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()>;
+    /// Drop the ownership record, if `node_id` still holds it, and the shared record,
+    /// unless another instance owns it
+    async fn release(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()>;
+    async fn owner(&self, kind: ResourceKind, id: &str) -> Result<Option<NodeInfo>>;
+    async fn nodes(&self) -> Result<Vec<NodeInfo>>;
+    /// Remove the instance, what it owns and the records of its sandboxes; the records
+    /// of its deployments stay for another instance to adopt
+    async fn leave(&self, node_id: &str) -> Result<()>;
+    /// Share the record the resource is listed and re-created from
+    async fn save_record(&self, kind: ResourceKind, id: &str, record: &Value) -> Result<()>;
+    /// Records of all sandboxes or deployments of the cluster, by id
+    async fn records(&self, kind: ResourceKind) -> Result<Vec<SharedRecord>>;
+    /// Make `node_id` the owner if `previous` still owns the resource, or nobody does
+    /// when `previous` is None; whether it did
+    async fn adopt(&self, kind: ResourceKind, id: &str, previous: Option<&str>, node_id: &str) -> Result<bool>;
+}
+
+/// This instance's sandboxes and deployments, as the cluster shares them
+#[async_trait]
+pub trait Registry: Send + Sync {
+    async fn ids(&self, kind: ResourceKind) -> Vec<String>;
+    /// Record other instances list the resource by, if this instance has it
+    async fn record(&self, kind: ResourceKind, id: &str) -> Option<Value>;
+    /// Deploy a deployment of an instance that went down again, from its record
+    async fn adopt(&self, deployment_id: &str, record: Value) -> Result<()>;
+}
+
+/// Store of a single instance
+#[derive(Default)]
+pub struct MemoryStore {
+    nodes: Mutex<HashMap<String, NodeInfo>>,
+    owners: Mutex<HashMap<(ResourceKind, String), String>>,
+    records: Mutex<HashMap<(ResourceKind, String), Value>>,
+}
+
+#[async_trait]
+impl ClusterStore for MemoryStore {
+    async fn heartbeat(&self, node: &NodeInfo, owned: &Owned) -> Result<()> {
+        self.nodes.lock().unwrap().insert(node.node_id.clone(), node.clone());
+        let mut owners = self.owners.lock().unwrap();
+        owners.retain(|_, owner| *owner != node.node_id);
+        for (kind, ids) in [(ResourceKind::Sandbox, &owned.sandboxes), (ResourceKind::Deployment, &owned.deployments)] {
+            for id in ids {
+                owners.insert((kind, id.clone()), node.node_id.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn claim(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        self.owners.lock().unwrap().insert((kind, id.to_string()), node_id.to_string());
+        Ok(())
+    }
+
+    async fn release(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        let key = (kind, id.to_string());
+        let mut owners = self.owners.lock().unwrap();
+        match owners.get(&key) {
+            Some(owner) if owner != node_id => {}
+            Some(_) => {
+                owners.remove(&key);
+                self.records.lock().unwrap().remove(&key);
+            }
+            None => {
+                self.records.lock().unwrap().remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn owner(&self, kind: ResourceKind, id: &str) -> Result<Option<NodeInfo>> {
+        let owner = self.owners.lock().unwrap().get(&(kind, id.to_string())).cloned();
+        Ok(owner.and_then(|node_id| self.nodes.lock().unwrap().get(&node_id).cloned()))
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>> {
+        let mut nodes: Vec<NodeInfo> = self.nodes.lock().unwrap().values().cloned().collect();
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        Ok(nodes)
+    }
+
+    async fn leave(&self, node_id: &str) -> Result<()> {
+        self.nodes.lock().unwrap().remove(node_id);
+        let mut owners = self.owners.lock().unwrap();
+        let mut records = self.records.lock().unwrap();
+        owners.retain(|key, owner| {
+            if owner != node_id {
+                return true;
+            }
+            if key.0 == ResourceKind::Sandbox {
+                records.remove(key);
+            }
+            false
+        });
+        Ok(())
+    }
+
+    async fn save_record(&self, kind: ResourceKind, id: &str, record: &Value) -> Result<()> {
+        self.records.lock().unwrap().insert((kind, id.to_string()), record.clone());
+        Ok(())
+    }
+
+    async fn records(&self, kind: ResourceKind) -> Result<Vec<SharedRecord>> {
+        let owners = self.owners.lock().unwrap();
+        let mut records: Vec<SharedRecord> = self.records.lock().unwrap().iter()
+            .filter(|((record_kind, _), _)| *record_kind == kind)
+            .map(|(key, record)| SharedRecord {
+                id: key.1.clone(),
+                node_id: owners.get(key).cloned(),
+                record: record.clone(),
+            })
+            .collect();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(records)
+    }
+
+    async fn adopt(&self, kind: ResourceKind, id: &str, previous: Option<&str>, node_id: &str) -> Result<bool> {
+        let mut owners = self.owners.lock().unwrap();
+        let key = (kind, id.to_string());
+        if owners.get(&key).map(String::as_str) != previous {
+            return Ok(false);
+        }
+        owners.insert(key, node_id.to_string());
+        Ok(true)
+    }
+}
+
+/// An instance with whether it answered heartbeats recently
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    #[serde(flatten)]
+    pub node: NodeInfo,
+    pub alive: bool,
+}
+
+/// Response of `GET /admin/api/cluster`
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterView {
+    pub enabled: bool,
+    /// The instance that answered
+    pub node_id: String,
+    pub nodes: Vec<NodeStatus>,
+}
+
+/// A shared record with whether requests for its resource are answered
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordStatus {
+    #[serde(flatten)]
+    pub shared: SharedRecord,
+    /// Whether its owner answered heartbeats recently
+    pub reachable: bool,
+}
+
+/// Response of `GET /admin/api/cluster/sandboxes` and `GET /admin/api/cluster/deployments`
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordList {
+    pub records: Vec<RecordStatus>,
+}
+
+/// This instance's membership in the cluster
+#[derive(Clone)]
+pub struct Cluster {
+    enabled: bool,
+    node_id: String,
+    url: String,
+    started_at: DateTime<Utc>,
+    store: Arc<dyn ClusterStore>,
+    owned: Arc<Mutex<Owned>>,
+    heartbeat_interval: Duration,
+    node_timeout: chrono::Duration,
+    client: reqwest::Client,
+    /// Time the owner has to start answering a forwarded request
+    request_timeout: Duration,
+    /// Largest request body forwarded, as the local routes accept
+    max_body_bytes: usize,
+    /// Where the records of this instance's resources come from and adopted deployments go
+    registry: Option<Arc<dyn Registry>>,
+    /// Deployments being deployed again after their owner went down
+    adopting: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Cluster {
+    pub fn new(config: &ClusterConfig, url: String, store: Arc<dyn ClusterStore>) -> Self {
+        Self {
+            enabled: config.enabled,
+            node_id: config.node_id(),
+            url,
+            started_at: Utc::now(),
+            store,
+            owned: Arc::new(Mutex::new(Owned::default())),
+            heartbeat_interval: Duration::from_secs(config.heartbeat_interval_seconds.max(1)),
+            node_timeout: chrono::Duration::seconds(config.node_timeout_seconds as i64),
+            client: ProxyConfig::default().client(),
+            request_timeout: Duration::from_secs(ProxyConfig::default().request_timeout_seconds),
+            max_body_bytes: usize::MAX,
+            registry: None,
+            adopting: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Forward requests with the proxy's HTTP client settings and timeout, and at most
+    /// `max_body_bytes` of request body
+    pub fn with_forwarding(mut self, proxy: &ProxyConfig, max_body_bytes: usize) -> Self {
+        self.client = proxy.client();
+        self.request_timeout = Duration::from_secs(proxy.request_timeout_seconds);
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    /// Share the records of `registry`'s sandboxes and deployments, and deploy adopted
+    /// deployments to it; without cluster mode nothing is shared
+    pub fn with_registry(mut self, registry: Arc<dyn Registry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Join the cluster the configuration describes; `local_url` is this instance's own
+    /// address, advertised when the configuration sets none
+    ///
+    /// With the Redis `store`, ownership is shared in the Redis at `redis_url` rather
+    /// than in `cluster.database_url`.
+    pub async fn connect(config: &ClusterConfig, local_url: &str, store: StoreKind, redis_url: Option<&str>) -> Result<Self> {
+        let url = config.advertise_url.clone().unwrap_or_else(|| local_url.to_string());
+        let url = url.trim_end_matches('/').to_string();
+        if !config.enabled {
+            return Ok(Self::new(config, url, Arc::new(MemoryStore::default())));
+        }
+
+        if store == StoreKind::Redis {
+            #[cfg(feature = "redis")]
+            {
+                let redis_url = redis_url
+                    .ok_or_else(|| anyhow::anyhow!("redis_url is required with store = \"redis\""))?;
+                let store = redis::RedisStore::connect(redis_url).await?;
+                return Ok(Self::new(config, url, Arc::new(store)));
+            }
+            #[cfg(not(feature = "redis"))]
+            {
+                let _ = redis_url;
+                anyhow::bail!("store = \"redis\" needs a build with the 'redis' feature");
+            }
+        }
+
+        #[cfg(feature = "postgres")]
+        {
+            let database_url = config.database_url.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("cluster.database_url is required in cluster mode"))?;
+            let store = postgres::PostgresStore::connect(database_url).await?;
+            Ok(Self::new(config, url, Arc::new(store)))
+        }
+        #[cfg(not(feature = "postgres"))]
+        anyhow::bail!("Cluster mode needs a build with the 'postgres' feature")
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Follow ownership on the event bus and send heartbeats until the process exits
+    pub fn start(&self, events: &EventBus) {
+        let cluster = self.clone();
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => cluster.follow(&event.kind).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("[CLUSTER] Missed {} events; ownership may be stale until they are deleted", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+
+        let cluster = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(cluster.heartbeat_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = cluster.heartbeat().await {
+                    warn!("[CLUSTER] Heartbeat failed: {:#}", e);
+                }
+            }
+        });
+    }
+
+    async fn follow(&self, event: &EventKind) {
+        let (kind, id, claimed) = match event {
+            EventKind::SandboxCreated { sandbox_id, .. } => (ResourceKind::Sandbox, sandbox_id, true),
+            EventKind::SandboxDeleted { sandbox_id } => (ResourceKind::Sandbox, sandbox_id, false),
+            EventKind::DeploymentCreated { deployment_id, .. }
+            | EventKind::DeploymentFailed { deployment_id, .. } => (ResourceKind::Deployment, deployment_id, true),
+            EventKind::DeploymentDeleted { deployment_id, .. } => (ResourceKind::Deployment, deployment_id, false),
+            _ => return,
+        };
+
+        let result = if claimed {
+            self.owned.lock().unwrap().ids(kind).insert(id.clone());
+            match self.store.claim(kind, id, &self.node_id).await {
+                Ok(()) => self.share(kind, id).await,
+                Err(e) => Err(e),
+            }
+        } else {
+            self.owned.lock().unwrap().ids(kind).remove(id);
+            self.store.release(kind, id, &self.node_id).await
+        };
+        if let Err(e) = result {
+            // The next heartbeat records ownership again
+            warn!("[CLUSTER] Failed to record {} {}: {:#}", kind.name(), id, e);
+        }
+    }
+
+    /// Registry records are shared from, in cluster mode
+    fn registry(&self) -> Option<&Arc<dyn Registry>> {
+        self.registry.as_ref().filter(|_| self.enabled)
+    }
+
+    /// Save the record of one of this instance's resources in the store
+    async fn share(&self, kind: ResourceKind, id: &str) -> Result<()> {
+        let Some(registry) = self.registry() else {
+            return Ok(());
+        };
+        match registry.record(kind, id).await {
+            Some(record) => self.store.save_record(kind, id, &record).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Own what the registry holds, sharing the records of what was not owned yet, such
+    /// as sandboxes reattached and deployments restored after a restart
+    async fn sync(&self, registry: &Arc<dyn Registry>) -> Result<()> {
+        for kind in ResourceKind::ALL {
+            let mut ids: HashSet<String> = registry.ids(kind).await.into_iter().collect();
+            if kind == ResourceKind::Deployment {
+                ids.extend(self.adopting.lock().unwrap().iter().cloned());
+            }
+            let added: Vec<String> = {
+                let mut owned = self.owned.lock().unwrap();
+                let owned = owned.ids(kind);
+                let added = ids.difference(owned).cloned().collect();
+                *owned = ids;
+                added
+            };
+            for id in added {
+                self.share(kind, &id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deploy the deployments no live instance owns again on this one; the store lets
+    /// one instance adopt each
+    async fn adopt_orphans(&self, registry: &Arc<dyn Registry>) -> Result<()> {
+        let alive: HashSet<String> = self.store.nodes().await?
+            .into_iter()
+            .filter(|node| self.is_alive(node))
+            .map(|node| node.node_id)
+            .collect();
+        for shared in self.store.records(ResourceKind::Deployment).await? {
+            let previous = shared.node_id.as_deref();
+            if previous.is_some_and(|node_id| alive.contains(node_id)) {
+                continue;
+            }
+            if !self.store.adopt(ResourceKind::Deployment, &shared.id, previous, &self.node_id).await? {
+                continue;
+            }
+            info!("[CLUSTER] Adopting deployment {} of {}", shared.id,
+                  previous.map_or("an instance that left".to_string(), |node_id| format!("instance {}", node_id)));
+            self.adopting.lock().unwrap().insert(shared.id.clone());
+            self.owned.lock().unwrap().deployments.insert(shared.id.clone());
+
+            // Deploying takes longer than heartbeats may be apart
+            let cluster = self.clone();
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                if let Err(e) = registry.adopt(&shared.id, shared.record).await {
+                    // Released by the next heartbeat, so it is tried again
+                    warn!("[CLUSTER] Failed to adopt deployment {}: {:#}", shared.id, e);
+                }
+                cluster.adopting.lock().unwrap().remove(&shared.id);
+            });
+        }
+        Ok(())
+    }
+
+    async fn heartbeat(&self) -> Result<()> {
+        if let Some(registry) = self.registry() {
+            self.sync(registry).await?;
+        }
+        let owned = self.owned.lock().unwrap().clone();
+        let node = NodeInfo {
+            node_id: self.node_id.clone(),
+            url: self.url.clone(),
+            started_at: self.started_at,
+            last_seen: Utc::now(),
+            sandboxes: owned.sandboxes.len(),
+            deployments: owned.deployments.len(),
+        };
+        self.store.heartbeat(&node, &owned).await?;
+        if let Some(registry) = self.registry() {
+            self.adopt_orphans(registry).await?;
+        }
+        Ok(())
+    }
+
+    fn is_alive(&self, node: &NodeInfo) -> bool {
+        Utc::now() - node.last_seen <= self.node_timeout
+    }
+
+    /// Live instance other than this one that owns the resource
+    async fn remote_owner(&self, kind: ResourceKind, id: &str) -> Option<NodeInfo> {
+        if self.owned.lock().unwrap().ids(kind).contains(id) {
+            return None;
+        }
+        match self.store.owner(kind, id).await {
+            Ok(Some(node)) if node.node_id != self.node_id && self.is_alive(&node) => Some(node),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("[CLUSTER] Failed to look up the owner of {} {}: {:#}", kind.name(), id, e);
+                None
+            }
+        }
+    }
+
+    /// Whether a live instance other than this one owns the resource
+    pub async fn owned_elsewhere(&self, kind: ResourceKind, id: &str) -> bool {
+        self.remote_owner(kind, id).await.is_some()
+    }
+
+    pub async fn view(&self) -> Result<ClusterView> {
+        let nodes = self.store.nodes().await?
+            .into_iter()
+            .map(|node| NodeStatus { alive: self.is_alive(&node), node })
+            .collect();
+        Ok(ClusterView {
+            enabled: self.enabled,
+            node_id: self.node_id.clone(),
+            nodes,
+        })
+    }
+
+    /// Sandboxes or deployments of all instances, as shared in the store
+    pub async fn records(&self, kind: ResourceKind) -> Result<RecordList> {
+        let alive: HashSet<String> = self.store.nodes().await?
+            .into_iter()
+            .filter(|node| self.is_alive(node))
+            .map(|node| node.node_id)
+            .collect();
+        let records = self.store.records(kind).await?
+            .into_iter()
+            .map(|shared| RecordStatus {
+                reachable: shared.node_id.as_ref().is_some_and(|node_id| alive.contains(node_id)),
+                shared,
+            })
+            .collect();
+        Ok(RecordList { records })
+    }
+
+    /// Leave the cluster on shutdown: this instance's sandboxes are gone with it, and
+    /// its deployments are adopted by another instance
+    pub async fn leave(&self) {
+        if let Err(e) = self.store.leave(&self.node_id).await {
+            warn!("[CLUSTER] Failed to leave the cluster: {:#}", e);
+        }
+    }
+
+    /// Send the request to the instance that owns its sandbox or deployment
+    async fn forward(&self, owner: &NodeInfo, req: Request) -> Result<Response, ApiError> {
+        let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string();
+        let target_url = format!("{}{}", owner.url, path_and_query);
+        let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+            .map_err(|e| ApiError::internal(format!("Unsupported method: {}", e)))?;
+
+        let mut request_builder = self.client.request(method, &target_url)
+            .header(FORWARDED_HEADER, &self.node_id);
+        let connection_tokens = headers::connection_tokens(
+            req.headers().get_all(axum::http::header::CONNECTION).iter().filter_map(|value| value.to_str().ok()),
+        );
+        for (name, value) in req.headers() {
+            if headers::is_hop_by_hop(name.as_str(), &connection_tokens)
+                || name == axum::http::header::HOST
+                || name == axum::http::header::CONTENT_LENGTH
+            {
+                continue;
+            }
+            if let Ok(value) = value.to_str() {
+                request_builder = request_builder.header(name.as_str(), value);
+            }
+        }
+        // The owner builds URLs in its responses from the host the client reached
+        if !req.headers().contains_key(FORWARDED_HOST_HEADER) {
+            if let Some(host) = req.headers().get(axum::http::header::HOST).and_then(|host| host.to_str().ok()) {
+                request_builder = request_builder.header(FORWARDED_HOST_HEADER, host);
+            }
+        }
+        let body = axum::body::to_bytes(req.into_body(), self.max_body_bytes)
+            .await
+            .map_err(|e| match e.into_inner() {
+                e if e.is::<http_body_util::LengthLimitError>() => {
+                    ApiError::PayloadTooLarge(format!("Request body is larger than {} bytes", self.max_body_bytes))
+                }
+                e => ApiError::internal(format!("Failed to read request body: {}", e)),
+            })?;
+
+        // Only the response head is timed: event streams and log tails answer with a
+        // body that lasts as long as the client follows it
+        let response = tokio::time::timeout(self.request_timeout, request_builder.body(body).send())
+            .await
+            .map_err(|_| ApiError::GatewayTimeout(format!(
+                "Instance {} owning the resource did not answer within {}s", owner.node_id, self.request_timeout.as_secs()
+            )))?
+            .map_err(|e| {
+                ApiError::BadGateway(format!("Instance {} owning the resource did not answer: {}", owner.node_id, e))
+            })?;
+
+        let mut response_builder = Response::builder().status(response.status().as_u16());
+        let connection_tokens = headers::connection_tokens(
+            response.headers().get_all("connection").iter().filter_map(|value| value.to_str().ok()),
+        );
+        for (name, value) in response.headers() {
+            if headers::is_hop_by_hop(name.as_str(), &connection_tokens) {
+                continue;
+            }
+            if let Ok(value) = value.to_str() {
+                response_builder = response_builder.header(name.as_str(), value);
+            }
+        }
+        // Streamed, so event streams and log tails pass through as they are produced
+        response_builder
+            .body(Body::from_stream(response.bytes_stream()))
+            .map_err(|e| ApiError::internal(format!("Failed to build forwarded response: {}", e)))
+    }
+}
+
+/// Sandbox or deployment a request path is about
+fn route_target(path: &str) -> Option<(ResourceKind, &str)> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    let target = match (segments.next()?, segments.next()?) {
+        ("sandbox" | "proxy", id) => (ResourceKind::Sandbox, id),
+        ("faas", "deployments") => (ResourceKind::Deployment, segments.next()?),
+        ("faas", "deploy") => return None,
+        ("faas", id) => (ResourceKind::Deployment, id),
+        ("admin", "api") => match (segments.next()?, segments.next()?) {
+            ("sandboxes", id) => (ResourceKind::Sandbox, id),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(target).filter(|(_, id)| !id.is_empty())
+}
+
+/// Forward requests for sandboxes and deployments of other instances to their owner
+pub async fn route_middleware(State(cluster): State<Cluster>, req: Request, next: Next) -> Response {
+    if req.headers().contains_key(FORWARDED_HEADER) {
+        return next.run(req).await;
+    }
+    let Some((kind, id)) = route_target(req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let Some(owner) = cluster.remote_owner(kind, id).await else {
+        return next.run(req).await;
+    };
+
+    if req.headers().contains_key(axum::http::header::UPGRADE) {
+        return ApiError::BadRequest {
+            code: "owned_by_other_node",
+            message: format!(
+                "{} {} runs on instance {}; connect to {} directly",
+                kind.name(), id, owner.node_id, owner.url
+            ),
+        }.into_response();
+    }
+    cluster.forward(&owner, req).await.unwrap_or_else(IntoResponse::into_response)
+}
+
+/// Instances of the cluster and what each owns
+///
+/// GET /admin/api/cluster
+pub async fn get_cluster(State(cluster): State<Cluster>) -> Result<Json<ClusterView>, ApiError> {
+    Ok(Json(cluster.view().await?))
+}
+
+/// Sandboxes of all instances, with their persisted requests
+///
+/// GET /admin/api/cluster/sandboxes
+pub async fn list_cluster_sandboxes(State(cluster): State<Cluster>) -> Result<Json<RecordList>, ApiError> {
+    Ok(Json(cluster.records(ResourceKind::Sandbox).await?))
+}
+
+/// Deployments of all instances, with their deployment records
+///
+/// GET /admin/api/cluster/deployments
+pub async fn list_cluster_deployments(State(cluster): State<Cluster>) -> Result<Json<RecordList>, ApiError> {
+    Ok(Json(cluster.records(ResourceKind::Deployment).await?))
+}
+
+pub fn create_cluster_router(cluster: Cluster) -> Router {
+    Router::new()
+        .route("/admin/api/cluster", get(get_cluster))
+        .route("/admin/api/cluster/sandboxes", get(list_cluster_sandboxes))
+        .route("/admin/api/cluster/deployments", get(list_cluster_deployments))
+        .with_state(cluster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_target() {
+        assert_eq!(route_target("/sandbox/abc/execute"), Some((ResourceKind::Sandbox, "abc")));
+        assert_eq!(route_target("/proxy/abc"), Some((ResourceKind::Sandbox, "abc")));
+        assert_eq!(route_target("/admin/api/sandboxes/abc/logs"), Some((ResourceKind::Sandbox, "abc")));
+        assert_eq!(route_target("/faas/deployments/d1/files"), Some((ResourceKind::Deployment, "d1")));
+        assert_eq!(route_target("/faas/d1/api/users"), Some((ResourceKind::Deployment, "d1")));
+        assert_eq!(route_target("/faas/deployments"), None);
+        assert_eq!(route_target("/faas/deploy"), None);
+        assert_eq!(route_target("/sandbox"), None);
+        assert_eq!(route_target("/admin/api/status"), None);
+    }
+
+    #[tokio::test]
+    async fn test_remote_owner() {
+        let store: Arc<dyn ClusterStore> = Arc::new(MemoryStore::default());
+        let config = ClusterConfig { node_id: Some("a".to_string()), ..Default::default() };
+        let cluster = Cluster::new(&config, "http://a:8070".to_string(), store.clone());
+
+        let mut owned = Owned::default();
+        owned.sandboxes.insert("s1".to_string());
+        let b = NodeInfo {
+            node_id: "b".to_string(),
+            url: "http://b:8070".to_string(),
+            started_at: Utc::now(),
+            last_seen: Utc::now(),
+            sandboxes: 1,
+            deployments: 0,
+        };
+        store.heartbeat(&b, &owned).await.unwrap();
+        assert_eq!(cluster.remote_owner(ResourceKind::Sandbox, "s1").await.unwrap().node_id, "b");
+        assert!(cluster.remote_owner(ResourceKind::Sandbox, "s2").await.is_none());
+
+        // Instances that stopped sending heartbeats are not forwarded to
+        let stale = NodeInfo { last_seen: Utc::now() - chrono::Duration::minutes(5), ..b };
+        store.heartbeat(&stale, &owned).await.unwrap();
+        assert!(cluster.remote_owner(ResourceKind::Sandbox, "s1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shared_records() {
+        let store = MemoryStore::default();
+        for kind in ResourceKind::ALL {
+            store.claim(kind, "r1", "a").await.unwrap();
+            store.save_record(kind, "r1", &serde_json::json!({ "kind": kind.name() })).await.unwrap();
+        }
+        store.save_record(ResourceKind::Sandbox, "r2", &serde_json::json!({})).await.unwrap();
+        store.claim(ResourceKind::Sandbox, "r2", "a").await.unwrap();
+
+        // Another instance's release leaves the record to its owner
+        store.release(ResourceKind::Sandbox, "r2", "b").await.unwrap();
+        assert_eq!(store.records(ResourceKind::Sandbox).await.unwrap().len(), 2);
+        store.release(ResourceKind::Sandbox, "r2", "a").await.unwrap();
+        let records = store.records(ResourceKind::Sandbox).await.unwrap();
+        assert_eq!(records, vec![SharedRecord {
+            id: "r1".to_string(),
+            node_id: Some("a".to_string()),
+            record: serde_json::json!({ "kind": "sandbox" }),
+        }]);
+
+        // Leaving drops sandbox records and keeps deployment records for adoption
+        store.leave("a").await.unwrap();
+        assert!(store.records(ResourceKind::Sandbox).await.unwrap().is_empty());
+        let records = store.records(ResourceKind::Deployment).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].node_id, None);
+
+        assert!(!store.adopt(ResourceKind::Deployment, "r1", Some("a"), "b").await.unwrap());
+        assert!(store.adopt(ResourceKind::Deployment, "r1", None, "b").await.unwrap());
+        assert!(!store.adopt(ResourceKind::Deployment, "r1", None, "c").await.unwrap());
+        assert_eq!(store.records(ResourceKind::Deployment).await.unwrap()[0].node_id.as_deref(), Some("b"));
+    }
+
+    #[derive(Default)]
+    struct TestRegistry {
+        adopted: Mutex<Vec<(String, Value)>>,
+    }
+
+    #[async_trait]
+    impl Registry for TestRegistry {
+        async fn ids(&self, kind: ResourceKind) -> Vec<String> {
+            match kind {
+                ResourceKind::Sandbox => vec!["s1".to_string()],
+                ResourceKind::Deployment => vec![],
+            }
+        }
+
+        async fn record(&self, _kind: ResourceKind, id: &str) -> Option<Value> {
+            Some(serde_json::json!({ "id": id }))
+        }
+
+        async fn adopt(&self, deployment_id: &str, record: Value) -> Result<()> {
+            self.adopted.lock().unwrap().push((deployment_id.to_string(), record));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_adopt_deployments_of_dead_instance() {
+        let store: Arc<dyn ClusterStore> = Arc::new(MemoryStore::default());
+        let registry = Arc::new(TestRegistry::default());
+        let config = ClusterConfig { enabled: true, node_id: Some("a".to_string()), ..Default::default() };
+        let cluster = Cluster::new(&config, "http://a:8070".to_string(), store.clone())
+            .with_registry(registry.clone());
+
+        let mut owned = Owned::default();
+        owned.deployments.insert("d1".to_string());
+        let b = NodeInfo {
+            node_id: "b".to_string(),
+            url: "http://b:8070".to_string(),
+            started_at: Utc::now(),
+            last_seen: Utc::now() - chrono::Duration::minutes(5),
+            sandboxes: 0,
+            deployments: 1,
+        };
+        store.heartbeat(&b, &owned).await.unwrap();
+        store.save_record(ResourceKind::Deployment, "d1", &serde_json::json!({ "id": "d1" })).await.unwrap();
+
+        cluster.heartbeat().await.unwrap();
+        for _ in 0..100 {
+            if cluster.adopting.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*registry.adopted.lock().unwrap(), vec![("d1".to_string(), serde_json::json!({ "id": "d1" }))]);
+        assert_eq!(store.owner(ResourceKind::Deployment, "d1").await.unwrap().unwrap().node_id, "a");
+
+        // This instance's own sandboxes are shared with their records
+        let sandboxes = cluster.records(ResourceKind::Sandbox).await.unwrap().records;
+        assert_eq!(sandboxes.len(), 1);
+        assert_eq!((sandboxes[0].shared.id.as_str(), sandboxes[0].reachable), ("s1", true));
+    }
+
+    #[tokio::test]
+    async fn test_forward_body_limit() {
+        let config = ClusterConfig { node_id: Some("a".to_string()), ..Default::default() };
+        let cluster = Cluster::new(&config, "http://a:8070".to_string(), Arc::new(MemoryStore::default()))
+            .with_forwarding(&ProxyConfig::default(), 4);
+        let owner = NodeInfo {
+            node_id: "b".to_string(),
+            url: "http://b:8070".to_string(),
+            started_at: Utc::now(),
+            last_seen: Utc::now(),
+            sandboxes: 1,
+            deployments: 0,
+        };
+        let req = Request::builder().method("POST").uri("/sandbox/s1/execute").body(Body::from("too long")).unwrap();
+        let error = cluster.forward(&owner, req).await.unwrap_err();
+        assert_eq!(error.code(), "payload_too_large");
+    }
+}
@@ -0,0 +1,218 @@
+//! Cluster store in Postgres, shared by all instances
+//!
+//! The tables are created on connect; records are kept as JSON text. Connections are
+//! plain TCP; put the database on a private network or behind a TLS-terminating proxy.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::warn;
+
+use super::{ClusterStore, NodeInfo, Owned, ResourceKind, SharedRecord};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS voidrun_nodes (
+        node_id TEXT PRIMARY KEY,
+        url TEXT NOT NULL,
+        started_at TIMESTAMPTZ NOT NULL,
+        last_seen TIMESTAMPTZ NOT NULL,
+        sandboxes BIGINT NOT NULL,
+        deployments BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS voidrun_owners (
+        kind TEXT NOT NULL,
+        id TEXT NOT NULL,
+        node_id TEXT NOT NULL,
+        PRIMARY KEY (kind, id)
+    );
+    CREATE INDEX IF NOT EXISTS voidrun_owners_node ON voidrun_owners (node_id);
+    CREATE TABLE IF NOT EXISTS voidrun_records (
+        kind TEXT NOT NULL,
+        id TEXT NOT NULL,
+        record TEXT NOT NULL,
+        PRIMARY KEY (kind, id)
+    );
+";
+
+const NODE_COLUMNS: &str = "n.node_id, n.url, n.started_at, n.last_seen, n.sandboxes, n.deployments";
+
+pub struct PostgresStore {
+    database_url: String,
+    /// Replaced by a new connection when the database drops the current one
+    client: Mutex<Client>,
+}
+
+impl PostgresStore {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let client = open(database_url).await?;
+        client.batch_execute(SCHEMA).await.context("Failed to create the cluster tables")?;
+        Ok(Self {
+            database_url: database_url.to_string(),
+            client: Mutex::new(client),
+        })
+    }
+
+    async fn client(&self) -> Result<tokio::sync::MutexGuard<'_, Client>> {
+        let mut client = self.client.lock().await;
+        if client.is_closed() {
+            *client = open(&self.database_url).await?;
+        }
+        Ok(client)
+    }
+}
+
+async fn open(database_url: &str) -> Result<Client> {
+    let (client, connection) = tokio_postgres::connect(database_url, NoTls)
+        .await
+        .context("Failed to connect to the cluster database")?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!("[CLUSTER] Database connection closed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+fn node(row: &Row) -> NodeInfo {
+    NodeInfo {
+        node_id: row.get(0),
+        url: row.get(1),
+        started_at: row.get::<_, DateTime<Utc>>(2),
+        last_seen: row.get::<_, DateTime<Utc>>(3),
+        sandboxes: row.get::<_, i64>(4) as usize,
+        deployments: row.get::<_, i64>(5) as usize,
+    }
+}
+
+#[async_trait]
+impl ClusterStore for PostgresStore {
+    async fn heartbeat(&self, node: &NodeInfo, owned: &Owned) -> Result<()> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(
+            "INSERT INTO voidrun_nodes (node_id, url, started_at, last_seen, sandboxes, deployments)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (node_id) DO UPDATE SET url = EXCLUDED.url, started_at = EXCLUDED.started_at,
+                 last_seen = EXCLUDED.last_seen, sandboxes = EXCLUDED.sandboxes, deployments = EXCLUDED.deployments",
+            &[&node.node_id, &node.url, &node.started_at, &node.last_seen,
+              &(node.sandboxes as i64), &(node.deployments as i64)],
+        ).await?;
+
+        for (kind, ids) in [(ResourceKind::Sandbox, &owned.sandboxes), (ResourceKind::Deployment, &owned.deployments)] {
+            let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+            transaction.execute(
+                "DELETE FROM voidrun_owners WHERE node_id = $1 AND kind = $2 AND NOT (id = ANY($3))",
+                &[&node.node_id, &kind.name(), &ids],
+            ).await?;
+            transaction.execute(
+                "INSERT INTO voidrun_owners (kind, id, node_id) SELECT $1, unnest($2::text[]), $3
+                 ON CONFLICT (kind, id) DO UPDATE SET node_id = EXCLUDED.node_id",
+                &[&kind.name(), &ids, &node.node_id],
+            ).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn claim(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        self.client().await?.execute(
+            "INSERT INTO voidrun_owners (kind, id, node_id) VALUES ($1, $2, $3)
+             ON CONFLICT (kind, id) DO UPDATE SET node_id = EXCLUDED.node_id",
+            &[&kind.name(), &id, &node_id],
+        ).await?;
+        Ok(())
+    }
+
+    async fn release(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(
+            "DELETE FROM voidrun_records r WHERE r.kind = $1 AND r.id = $2 AND NOT EXISTS (
+                 SELECT 1 FROM voidrun_owners o WHERE o.kind = r.kind AND o.id = r.id AND o.node_id <> $3)",
+            &[&kind.name(), &id, &node_id],
+        ).await?;
+        transaction.execute(
+            "DELETE FROM voidrun_owners WHERE kind = $1 AND id = $2 AND node_id = $3",
+            &[&kind.name(), &id, &node_id],
+        ).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn owner(&self, kind: ResourceKind, id: &str) -> Result<Option<NodeInfo>> {
+        let row = self.client().await?.query_opt(
+            &format!(
+                "SELECT {} FROM voidrun_owners o JOIN voidrun_nodes n ON n.node_id = o.node_id
+                 WHERE o.kind = $1 AND o.id = $2",
+                NODE_COLUMNS
+            ),
+            &[&kind.name(), &id],
+        ).await?;
+        Ok(row.as_ref().map(node))
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>> {
+        let rows = self.client().await?.query(
+            &format!("SELECT {} FROM voidrun_nodes n ORDER BY n.node_id", NODE_COLUMNS),
+            &[],
+        ).await?;
+        Ok(rows.iter().map(node).collect())
+    }
+
+    async fn leave(&self, node_id: &str) -> Result<()> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(
+            "DELETE FROM voidrun_records r USING voidrun_owners o
+             WHERE o.kind = r.kind AND o.id = r.id AND o.node_id = $1 AND r.kind = $2",
+            &[&node_id, &ResourceKind::Sandbox.name()],
+        ).await?;
+        transaction.execute("DELETE FROM voidrun_owners WHERE node_id = $1", &[&node_id]).await?;
+        transaction.execute("DELETE FROM voidrun_nodes WHERE node_id = $1", &[&node_id]).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn save_record(&self, kind: ResourceKind, id: &str, record: &Value) -> Result<()> {
+        self.client().await?.execute(
+            "INSERT INTO voidrun_records (kind, id, record) VALUES ($1, $2, $3)
+             ON CONFLICT (kind, id) DO UPDATE SET record = EXCLUDED.record",
+            &[&kind.name(), &id, &record.to_string()],
+        ).await?;
+        Ok(())
+    }
+
+    async fn records(&self, kind: ResourceKind) -> Result<Vec<SharedRecord>> {
+        let rows = self.client().await?.query(
+            "SELECT r.id, o.node_id, r.record FROM voidrun_records r
+             LEFT JOIN voidrun_owners o ON o.kind = r.kind AND o.id = r.id
+             WHERE r.kind = $1 ORDER BY r.id",
+            &[&kind.name()],
+        ).await?;
+        rows.iter()
+            .map(|row| Ok(SharedRecord {
+                id: row.get(0),
+                node_id: row.get(1),
+                record: serde_json::from_str(row.get(2))?,
+            }))
+            .collect()
+    }
+
+    async fn adopt(&self, kind: ResourceKind, id: &str, previous: Option<&str>, node_id: &str) -> Result<bool> {
+        let client = self.client().await?;
+        let adopted = match previous {
+            Some(previous) => client.execute(
+                "UPDATE voidrun_owners SET node_id = $4 WHERE kind = $1 AND id = $2 AND node_id = $3",
+                &[&kind.name(), &id, &previous, &node_id],
+            ).await?,
+            None => client.execute(
+                "INSERT INTO voidrun_owners (kind, id, node_id) VALUES ($1, $2, $3) ON CONFLICT (kind, id) DO NOTHING",
+                &[&kind.name(), &id, &node_id],
+            ).await?,
+        };
+        Ok(adopted == 1)
+    }
+}
@@ -0,0 +1,223 @@
+//! Cluster store in Redis, used when the service coordinates through Redis (`store = "redis"`)
+//!
+//! Nodes are fields of one hash holding their last heartbeat. Each sandbox and
+//! deployment has an owner key naming its node, and each node a set of what it owns so
+//! heartbeats and leaving can drop owner keys it no longer holds. Records are fields of
+//! one hash per kind, holding their JSON.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::{ClusterStore, NodeInfo, Owned, ResourceKind, SharedRecord};
+use crate::coordination::KEY_PREFIX;
+
+const DELETE_IF: &str = "
+    if redis.call('GET', KEYS[1]) == ARGV[1] then
+        return redis.call('DEL', KEYS[1])
+    end
+    return 0
+";
+
+/// Drop the record, unless a node other than `ARGV[1]` owns it
+const DELETE_RECORD: &str = "
+    local owner = redis.call('GET', KEYS[1])
+    if owner and owner ~= ARGV[1] then
+        return 0
+    end
+    return redis.call('HDEL', KEYS[2], ARGV[2])
+";
+
+/// Make `ARGV[2]` the owner if `ARGV[1]` is, or nobody is when it is empty
+const ADOPT: &str = "
+    local owner = redis.call('GET', KEYS[1])
+    if (owner or '') ~= ARGV[1] then
+        return 0
+    end
+    redis.call('SET', KEYS[1], ARGV[2])
+    redis.call('SADD', KEYS[2], ARGV[3])
+    return 1
+";
+
+pub struct RedisStore {
+    connection: ConnectionManager,
+    delete_if: Script,
+    delete_record: Script,
+    adopt: Script,
+}
+
+impl RedisStore {
+    pub async fn connect(redis_url: &str) -> Result<Self> {
+        let client = redis::Client::open(redis_url).context("Invalid redis_url")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to the cluster Redis")?;
+        Ok(Self {
+            connection,
+            delete_if: Script::new(DELETE_IF),
+            delete_record: Script::new(DELETE_RECORD),
+            adopt: Script::new(ADOPT),
+        })
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        self.connection.clone()
+    }
+
+    /// Drop the owner key of `resource` if `node_id` still holds it
+    async fn disown(&self, resource: &str, node_id: &str) -> Result<()> {
+        let _: i64 = self.delete_if.key(owner_key(resource)).arg(node_id)
+            .invoke_async(&mut self.connection())
+            .await?;
+        Ok(())
+    }
+}
+
+fn nodes_key() -> String {
+    format!("{}cluster:nodes", KEY_PREFIX)
+}
+
+/// `resource` is `kind:id`, as in the owned sets
+fn owner_key(resource: &str) -> String {
+    format!("{}cluster:owner:{}", KEY_PREFIX, resource)
+}
+
+fn owned_key(node_id: &str) -> String {
+    format!("{}cluster:owned:{}", KEY_PREFIX, node_id)
+}
+
+fn records_key(kind: ResourceKind) -> String {
+    format!("{}cluster:records:{}", KEY_PREFIX, kind.name())
+}
+
+fn resource(kind: ResourceKind, id: &str) -> String {
+    format!("{}:{}", kind.name(), id)
+}
+
+#[async_trait]
+impl ClusterStore for RedisStore {
+    async fn heartbeat(&self, node: &NodeInfo, owned: &Owned) -> Result<()> {
+        let mut connection = self.connection();
+        let _: () = connection.hset(nodes_key(), &node.node_id, serde_json::to_string(node)?).await?;
+
+        let current: HashSet<String> = [(ResourceKind::Sandbox, &owned.sandboxes), (ResourceKind::Deployment, &owned.deployments)]
+            .into_iter()
+            .flat_map(|(kind, ids)| ids.iter().map(move |id| resource(kind, id)))
+            .collect();
+        let previous: HashSet<String> = connection.smembers(owned_key(&node.node_id)).await?;
+        for gone in previous.difference(&current) {
+            self.disown(gone, &node.node_id).await?;
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(owned_key(&node.node_id)).ignore();
+        for resource in &current {
+            pipe.set(owner_key(resource), &node.node_id).ignore();
+            pipe.sadd(owned_key(&node.node_id), resource).ignore();
+        }
+        let _: () = pipe.query_async(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn claim(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        let resource = resource(kind, id);
+        let _: () = redis::pipe()
+            .atomic()
+            .set(owner_key(&resource), node_id).ignore()
+            .sadd(owned_key(node_id), &resource).ignore()
+            .query_async(&mut self.connection())
+            .await?;
+        Ok(())
+    }
+
+    async fn release(&self, kind: ResourceKind, id: &str, node_id: &str) -> Result<()> {
+        let resource = resource(kind, id);
+        let _: i64 = self.delete_record.key(owner_key(&resource)).key(records_key(kind)).arg(node_id).arg(id)
+            .invoke_async(&mut self.connection())
+            .await?;
+        self.disown(&resource, node_id).await?;
+        let _: () = self.connection().srem(owned_key(node_id), &resource).await?;
+        Ok(())
+    }
+
+    async fn owner(&self, kind: ResourceKind, id: &str) -> Result<Option<NodeInfo>> {
+        let mut connection = self.connection();
+        let Some(node_id): Option<String> = connection.get(owner_key(&resource(kind, id))).await? else {
+            return Ok(None);
+        };
+        let node: Option<String> = connection.hget(nodes_key(), &node_id).await?;
+        Ok(node.map(|node| serde_json::from_str(&node)).transpose()?)
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeInfo>> {
+        let nodes: Vec<String> = self.connection().hvals(nodes_key()).await?;
+        let mut nodes = nodes.iter()
+            .map(|node| serde_json::from_str(node))
+            .collect::<Result<Vec<NodeInfo>, _>>()?;
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        Ok(nodes)
+    }
+
+    async fn leave(&self, node_id: &str) -> Result<()> {
+        let mut connection = self.connection();
+        let owned: Vec<String> = connection.smembers(owned_key(node_id)).await?;
+        let sandbox_prefix = resource(ResourceKind::Sandbox, "");
+        for resource in &owned {
+            if let Some(id) = resource.strip_prefix(&sandbox_prefix) {
+                let _: i64 = self.delete_record.key(owner_key(resource)).key(records_key(ResourceKind::Sandbox))
+                    .arg(node_id).arg(id)
+                    .invoke_async(&mut connection)
+                    .await?;
+            }
+            self.disown(resource, node_id).await?;
+        }
+        let _: () = redis::pipe()
+            .atomic()
+            .del(owned_key(node_id)).ignore()
+            .hdel(nodes_key(), node_id).ignore()
+            .query_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_record(&self, kind: ResourceKind, id: &str, record: &Value) -> Result<()> {
+        let _: () = self.connection().hset(records_key(kind), id, record.to_string()).await?;
+        Ok(())
+    }
+
+    async fn records(&self, kind: ResourceKind) -> Result<Vec<SharedRecord>> {
+        let mut connection = self.connection();
+        let records: HashMap<String, String> = connection.hgetall(records_key(kind)).await?;
+        let mut ids: Vec<&String> = records.keys().collect();
+        ids.sort();
+        let owners: Vec<Option<String>> = if ids.is_empty() {
+            Vec::new()
+        } else {
+            let mut pipe = redis::pipe();
+            for id in &ids {
+                pipe.get(owner_key(&resource(kind, id)));
+            }
+            pipe.query_async(&mut connection).await?
+        };
+        ids.into_iter()
+            .zip(owners)
+            .map(|(id, node_id)| Ok(SharedRecord {
+                id: id.clone(),
+                node_id,
+                record: serde_json::from_str(&records[id])?,
+            }))
+            .collect()
+    }
+
+    async fn adopt(&self, kind: ResourceKind, id: &str, previous: Option<&str>, node_id: &str) -> Result<bool> {
+        let resource = resource(kind, id);
+        let adopted: i64 = self.adopt.key(owner_key(&resource)).key(owned_key(node_id))
+            .arg(previous.unwrap_or_default()).arg(node_id).arg(&resource)
+            .invoke_async(&mut self.connection())
+            .await?;
+        Ok(adopted == 1)
+    }
+}
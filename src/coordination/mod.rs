@@ -0,0 +1,318 @@
+//! Coordination state shared by instances: port leases, idempotency keys and rate limits
+//!
+//! With `store = "memory"`, the default, the state lives in this process and only
+//! coordinates its own requests. With `store = "redis"` instances pointed at the same
+//! Redis do not publish sandboxes on the same host port, deploy twice for one
+//! idempotency key or each grant a caller the full rate limit.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::api::error::ApiError;
+
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// Prefix of every key the service writes
+pub const KEY_PREFIX: &str = "voidrun:";
+
+/// How long a finished request's response is replayed for its idempotency key
+pub const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a request may run before its idempotency key can be used again
+const IDEMPOTENCY_PENDING_TTL: Duration = Duration::from_secs(15 * 60);
+
+const PENDING: &str = "pending";
+
+/// Header clients name a request with so retrying it does not repeat its effect
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Where coordination state is kept, `store` in the configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    Memory,
+    Redis,
+}
+
+impl std::str::FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "redis" => Ok(Self::Redis),
+            _ => Err(format!("unknown store {:?} (expected memory or redis)", name)),
+        }
+    }
+}
+
+/// Expiring key-value operations coordination is built on
+#[async_trait]
+pub trait Coordinator: Send + Sync {
+    /// Set `key` unless it exists; true when it was set
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool>;
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()>;
+    /// Delete `key` if it still holds `value`
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool>;
+    /// Restart `key`'s expiry if it still holds `value`
+    async fn renew_if(&self, key: &str, value: &str, ttl: Duration) -> Result<bool>;
+    /// Count a hit on `key`, which expires `window` after its first hit; returns the count
+    async fn increment(&self, key: &str, window: Duration) -> Result<u64>;
+}
+
+/// Coordinator for the `memory` store
+#[derive(Default)]
+pub struct LocalCoordinator {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl LocalCoordinator {
+    /// Entries of unexpired keys, after dropping the expired ones
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, (String, Instant)>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, (_, expires)| *expires > now);
+        entries
+    }
+}
+
+#[async_trait]
+impl Coordinator for LocalCoordinator {
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let mut entries = self.entries();
+        if entries.contains_key(key) {
+            return Ok(false);
+        }
+        entries.insert(key.to_string(), (value.to_string(), Instant::now() + ttl));
+        Ok(true)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.entries().get(key).map(|(value, _)| value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()> {
+        self.entries().insert(key.to_string(), (value.to_string(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool> {
+        let mut entries = self.entries();
+        if entries.get(key).is_some_and(|(held, _)| held == value) {
+            entries.remove(key);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn renew_if(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        match self.entries().get_mut(key) {
+            Some((held, expires)) if held == value => {
+                *expires = Instant::now() + ttl;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn increment(&self, key: &str, window: Duration) -> Result<u64> {
+        let mut entries = self.entries();
+        let (count, _) = entries
+            .entry(key.to_string())
+            .or_insert_with(|| ("0".to_string(), Instant::now() + window));
+        let next = count.parse::<u64>().unwrap_or(0) + 1;
+        *count = next.to_string();
+        Ok(next)
+    }
+}
+
+/// Coordinator of the configured store; `redis_url` is required for Redis
+pub async fn connect(store: StoreKind, redis_url: Option<&str>) -> Result<Arc<dyn Coordinator>> {
+    match store {
+        StoreKind::Memory => Ok(Arc::new(LocalCoordinator::default())),
+        #[cfg(feature = "redis")]
+        StoreKind::Redis => {
+            let redis_url = redis_url.ok_or_else(|| anyhow::anyhow!("redis_url is required with store = \"redis\""))?;
+            Ok(Arc::new(redis::RedisCoordinator::connect(redis_url).await?))
+        }
+        #[cfg(not(feature = "redis"))]
+        StoreKind::Redis => {
+            let _ = redis_url;
+            anyhow::bail!("store = \"redis\" needs a build with the 'redis' feature")
+        }
+    }
+}
+
+/// State of an idempotency key when a request presents it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Idempotent {
+    /// First use; the request runs and records its response with [`IdempotencyKeys::finish`]
+    Started,
+    /// A request with the same key is still running
+    InProgress,
+    /// The response of the request that used the key
+    Done(Value),
+}
+
+/// `Idempotency-Key` a request was sent with, if any
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdempotencyKey(pub Option<String>);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IdempotencyKey {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(Self(None));
+        };
+        match value.to_str().map(str::trim) {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH => Ok(Self(Some(key.to_string()))),
+            _ => Err(ApiError::Validation(format!(
+                "Idempotency-Key must be 1 to {} visible ASCII characters",
+                MAX_IDEMPOTENCY_KEY_LENGTH
+            ))),
+        }
+    }
+}
+
+/// Responses of requests sent with an `Idempotency-Key` header, replayed for repeats
+#[derive(Clone)]
+pub struct IdempotencyKeys {
+    coordinator: Arc<dyn Coordinator>,
+}
+
+impl IdempotencyKeys {
+    pub fn new(coordinator: Arc<dyn Coordinator>) -> Self {
+        Self { coordinator }
+    }
+
+    /// Key of `key` for requests of `scope`, e.g. the operation and the caller's API key
+    fn key(scope: &str, key: &str) -> String {
+        format!("{}idempotency:{}:{}", KEY_PREFIX, scope, key)
+    }
+
+    pub async fn begin(&self, scope: &str, key: &str) -> Result<Idempotent> {
+        let key = Self::key(scope, key);
+        if self.coordinator.set_if_absent(&key, PENDING, IDEMPOTENCY_PENDING_TTL).await? {
+            return Ok(Idempotent::Started);
+        }
+        match self.coordinator.get(&key).await? {
+            Some(value) if value != PENDING => Ok(Idempotent::Done(serde_json::from_str(&value)?)),
+            Some(_) => Ok(Idempotent::InProgress),
+            // Expired in between; the caller may retry
+            None => Ok(Idempotent::InProgress),
+        }
+    }
+
+    pub async fn finish(&self, scope: &str, key: &str, response: &impl Serialize) -> Result<()> {
+        let response = serde_json::to_string(response)?;
+        self.coordinator.set(&Self::key(scope, key), &response, IDEMPOTENCY_TTL).await
+    }
+
+    /// Free the key of a request that failed, so a retry runs again
+    pub async fn abandon(&self, scope: &str, key: &str) -> Result<()> {
+        self.coordinator.delete_if(&Self::key(scope, key), PENDING).await?;
+        Ok(())
+    }
+
+    /// Run `request` once per key, answering repeats with its recorded response
+    ///
+    /// Without a key the request just runs. A repeat while the first request still runs
+    /// is a conflict; a failed request leaves the key free for a retry.
+    pub async fn run<T, F>(&self, scope: &str, key: Option<&str>, request: F) -> Result<T, ApiError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Future<Output = Result<T, ApiError>>,
+    {
+        let Some(key) = key else {
+            return request.await;
+        };
+        match self.begin(scope, key).await? {
+            Idempotent::Started => {}
+            Idempotent::InProgress => {
+                return Err(ApiError::Conflict(format!("A request with Idempotency-Key {} is still in progress", key)));
+            }
+            Idempotent::Done(response) => {
+                return serde_json::from_value(response).map_err(|e| ApiError::internal(e.to_string()));
+            }
+        }
+
+        let result = request.await;
+        let recorded = match &result {
+            Ok(response) => self.finish(scope, key, response).await,
+            Err(_) => self.abandon(scope, key).await,
+        };
+        if let Err(e) = recorded {
+            warn!("Failed to record the outcome of Idempotency-Key {}: {}", key, e);
+        }
+        result
+    }
+}
+
+/// Requests per caller and minute, counted in the coordination store
+#[derive(Clone)]
+pub struct RateLimiter {
+    coordinator: Arc<dyn Coordinator>,
+}
+
+impl RateLimiter {
+    pub fn new(coordinator: Arc<dyn Coordinator>) -> Self {
+        Self { coordinator }
+    }
+
+    /// Count a request of `caller`; false once it made more than `per_minute` this minute
+    pub async fn allow(&self, caller: &str, per_minute: u32) -> Result<bool> {
+        let minute = chrono::Utc::now().timestamp() / 60;
+        let key = format!("{}ratelimit:{}:{}", KEY_PREFIX, caller, minute);
+        let count = self.coordinator.increment(&key, Duration::from_secs(60)).await?;
+        Ok(count <= per_minute as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        let keys = IdempotencyKeys::new(Arc::new(LocalCoordinator::default()));
+        assert_eq!(keys.begin("deploy", "k1").await.unwrap(), Idempotent::Started);
+        assert_eq!(keys.begin("deploy", "k1").await.unwrap(), Idempotent::InProgress);
+        assert_eq!(keys.begin("sandbox", "k1").await.unwrap(), Idempotent::Started);
+
+        keys.finish("deploy", "k1", &serde_json::json!({ "deployment_id": "d1" })).await.unwrap();
+        assert_eq!(
+            keys.begin("deploy", "k1").await.unwrap(),
+            Idempotent::Done(serde_json::json!({ "deployment_id": "d1" }))
+        );
+
+        keys.abandon("sandbox", "k1").await.unwrap();
+        assert_eq!(keys.begin("sandbox", "k1").await.unwrap(), Idempotent::Started);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Arc::new(LocalCoordinator::default()));
+        assert!(limiter.allow("a", 2).await.unwrap());
+        assert!(limiter.allow("a", 2).await.unwrap());
+        assert!(limiter.allow("b", 2).await.unwrap());
+        // May straddle a minute boundary, which starts a new window
+        let third = limiter.allow("a", 2).await.unwrap();
+        let fourth = limiter.allow("a", 2).await.unwrap();
+        assert!(!third || !fourth);
+    }
+}
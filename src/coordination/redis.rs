@@ -0,0 +1,105 @@
+//! Coordinator in Redis, shared by all instances pointed at the same server
+//!
+//! Conditional updates run as Lua scripts so they are atomic across instances. The
+//! connection is re-established by the connection manager after Redis drops it.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use std::time::Duration;
+
+use super::Coordinator;
+
+const DELETE_IF: &str = "
+    if redis.call('GET', KEYS[1]) == ARGV[1] then
+        return redis.call('DEL', KEYS[1])
+    end
+    return 0
+";
+
+const RENEW_IF: &str = "
+    if redis.call('GET', KEYS[1]) == ARGV[1] then
+        return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    end
+    return 0
+";
+
+const INCREMENT: &str = "
+    local count = redis.call('INCR', KEYS[1])
+    if count == 1 then
+        redis.call('PEXPIRE', KEYS[1], ARGV[1])
+    end
+    return count
+";
+
+pub struct RedisCoordinator {
+    connection: ConnectionManager,
+    delete_if: Script,
+    renew_if: Script,
+    increment: Script,
+}
+
+impl RedisCoordinator {
+    pub async fn connect(redis_url: &str) -> Result<Self> {
+        let client = redis::Client::open(redis_url).context("Invalid redis_url")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self {
+            connection,
+            delete_if: Script::new(DELETE_IF),
+            renew_if: Script::new(RENEW_IF),
+            increment: Script::new(INCREMENT),
+        })
+    }
+}
+
+fn millis(ttl: Duration) -> u64 {
+    ttl.as_millis().max(1) as u64
+}
+
+#[async_trait]
+impl Coordinator for RedisCoordinator {
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("PX")
+            .arg(millis(ttl))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(reply.is_some())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.connection.clone().get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()> {
+        let _: () = self.connection.clone().pset_ex(key, value, millis(ttl)).await?;
+        Ok(())
+    }
+
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool> {
+        let deleted: i64 = self.delete_if.key(key).arg(value)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    async fn renew_if(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let renewed: i64 = self.renew_if.key(key).arg(value).arg(millis(ttl))
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(renewed > 0)
+    }
+
+    async fn increment(&self, key: &str, window: Duration) -> Result<u64> {
+        let count: u64 = self.increment.key(key).arg(millis(window))
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,187 @@
+use axum::{
+    extract::State,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::sandbox::{SandboxStatus, TerminationReason};
+
+/// Number of events buffered per subscriber before slow consumers start lagging
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Lifecycle event published on the internal event bus
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// When the event was published
+    pub timestamp: DateTime<Utc>,
+    /// Event payload, serialized with a `type` discriminator
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Event payloads emitted by the sandbox and FaaS managers, and by alerts
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    SandboxCreated {
+        sandbox_id: String,
+        runtime: String,
+    },
+    SandboxStatusChanged {
+        sandbox_id: String,
+        status: SandboxStatus,
+    },
+    ExecutionFinished {
+        sandbox_id: String,
+        success: bool,
+        exit_code: Option<i32>,
+        execution_time_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        termination_reason: Option<TerminationReason>,
+    },
+    SandboxDeleted {
+        sandbox_id: String,
+    },
+    DeploymentCreated {
+        deployment_id: String,
+        sandbox_id: String,
+        runtime: String,
+    },
+    DeploymentDeleted {
+        deployment_id: String,
+        sandbox_id: String,
+    },
+    DeploymentFailed {
+        deployment_id: String,
+        sandbox_id: String,
+        error: String,
+    },
+    DeploymentScaledDown {
+        deployment_id: String,
+        sandbox_id: String,
+        idle_minutes: i64,
+    },
+    DeploymentScaledUp {
+        deployment_id: String,
+        sandbox_id: String,
+        instances: usize,
+    },
+    DeploymentWoken {
+        deployment_id: String,
+        sandbox_id: String,
+    },
+    DeploymentRedeployed {
+        deployment_id: String,
+        sandbox_id: String,
+    },
+    /// Containers or directories no sandbox claims were removed, `failed` of them unsuccessfully
+    OrphansCollected {
+        resources: usize,
+        failed: usize,
+    },
+    AlertFired {
+        metric: String,
+        subject: String,
+        value: f64,
+        threshold: f64,
+    },
+    AlertResolved {
+        metric: String,
+        subject: String,
+    },
+}
+
+impl EventKind {
+    /// SSE event name for this payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::SandboxCreated { .. } => "sandbox_created",
+            EventKind::SandboxStatusChanged { .. } => "sandbox_status_changed",
+            EventKind::ExecutionFinished { .. } => "execution_finished",
+            EventKind::SandboxDeleted { .. } => "sandbox_deleted",
+            EventKind::DeploymentCreated { .. } => "deployment_created",
+            EventKind::DeploymentDeleted { .. } => "deployment_deleted",
+            EventKind::DeploymentFailed { .. } => "deployment_failed",
+            EventKind::DeploymentScaledDown { .. } => "deployment_scaled_down",
+            EventKind::DeploymentScaledUp { .. } => "deployment_scaled_up",
+            EventKind::DeploymentWoken { .. } => "deployment_woken",
+            EventKind::DeploymentRedeployed { .. } => "deployment_redeployed",
+            EventKind::OrphansCollected { .. } => "orphans_collected",
+            EventKind::AlertFired { .. } => "alert_fired",
+            EventKind::AlertResolved { .. } => "alert_resolved",
+        }
+    }
+}
+
+/// Broadcast bus shared by everything that publishes lifecycle events
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event to all current subscribers
+    pub fn publish(&self, kind: EventKind) {
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = self.sender.send(Event {
+            timestamp: Utc::now(),
+            kind,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stream lifecycle events as Server-Sent Events
+///
+/// GET /events
+pub async fn event_stream(
+    State(events): State<EventBus>,
+) -> Sse<impl Stream<Item = Result<SseEvent, axum::Error>>> {
+    let receiver = events.subscribe();
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse_event = SseEvent::default()
+                        .event(event.kind.name())
+                        .json_data(&event);
+                    return Some((sse_event, receiver));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("[EVENTS] Subscriber lagged behind, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Create the event stream router
+pub fn create_events_router(events: EventBus) -> Router {
+    Router::new()
+        .route("/events", get(event_stream))
+        .with_state(events)
+}
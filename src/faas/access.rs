@@ -0,0 +1,82 @@
+//! Access tokens of deployments deployed with `auth: { mode: "token" }`
+//!
+//! The token is generated at deploy time and returned once in the deploy response.
+//! Callers of the deployment URL present it as `Authorization: Bearer <token>` or as
+//! the `voidrun_token` query parameter; the proxy checks it and strips it before the
+//! request reaches the deployed service. Deployments keep only the token's [`hash`],
+//! which is what their container labels and backups persist.
+
+use axum::http::{header, HeaderMap};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Query parameter carrying the token, for links that cannot set headers
+pub const ACCESS_TOKEN_PARAM: &str = "voidrun_token";
+
+/// New random token, 64 hex characters
+pub fn generate() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// SHA-256 of a token, hex encoded
+pub fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Token presented as a bearer token
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// Token presented as the query parameter
+pub fn from_query(query: Option<&str>) -> Option<&str> {
+    query?.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == ACCESS_TOKEN_PARAM)
+        .map(|(_, token)| token)
+}
+
+/// Query without the token parameter, as forwarded to the deployed service
+pub fn strip_token(query: Option<&str>) -> Option<String> {
+    let query = query?
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some(ACCESS_TOKEN_PARAM))
+        .collect::<Vec<_>>()
+        .join("&");
+    (!query.is_empty()).then_some(query)
+}
+
+/// Compare without exiting at the first differing byte, so timing leaks nothing
+pub fn matches(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected.bytes().zip(presented.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presented_tokens() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer(&headers), None);
+        headers.insert(header::AUTHORIZATION, "Bearer abc".parse().unwrap());
+        assert_eq!(bearer(&headers), Some("abc"));
+
+        assert_eq!(from_query(Some("a=1&voidrun_token=xyz")), Some("xyz"));
+        assert_eq!(from_query(Some("a=1")), None);
+        assert_eq!(strip_token(Some("a=1&voidrun_token=xyz&b=2")).as_deref(), Some("a=1&b=2"));
+        assert_eq!(strip_token(Some("voidrun_token=xyz")), None);
+
+        let token = generate();
+        assert_eq!(token.len(), 64);
+        assert!(matches(&token, &token.clone()));
+        assert!(!matches(&token, &generate()));
+        assert!(!matches(&token, "short"));
+        assert_eq!(hash(&token).len(), 64);
+        assert_ne!(hash(&token), token);
+        assert!(matches(&hash(&token), &hash(&token.clone())));
+    }
+}
@@ -0,0 +1,95 @@
+//! Access log of the requests the FaaS proxy passed to one deployment
+//!
+//! Kept in memory next to the deployment: the newest [`MAX_ENTRIES`] requests of the
+//! last [`RETENTION_HOURS`] hours, gone when the deployment is undeployed or the
+//! service restarts.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{Duration, Utc};
+
+use super::AccessLogEntry;
+
+/// Requests kept per deployment
+pub const MAX_ENTRIES: usize = 1000;
+/// Age after which requests are dropped
+pub const RETENTION_HOURS: i64 = 24;
+/// Entries returned when the query sets no limit
+pub const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    entries: Mutex<VecDeque<AccessLogEntry>>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, entry: AccessLogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+        expire(&mut entries);
+    }
+
+    /// Newest `limit` entries within the retention period, newest first
+    pub fn recent(&self, limit: usize) -> Vec<AccessLogEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        expire(&mut entries);
+        entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+fn expire(entries: &mut VecDeque<AccessLogEntry>) {
+    let cutoff = Utc::now() - Duration::hours(RETENTION_HOURS);
+    while entries.front().is_some_and(|entry| entry.timestamp < cutoff) {
+        entries.pop_front();
+    }
+}
+
+/// Path of a request within its deployment, from the proxy's `/faas/{id}/...` path
+pub fn deployment_path(path: &str, deployment_id: &str) -> String {
+    let path = path.strip_prefix("/faas/")
+        .and_then(|path| path.strip_prefix(deployment_id))
+        .unwrap_or(path);
+    if path.starts_with('/') { path.to_string() } else { format!("/{}", path) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, hours_ago: i64) -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: Utc::now() - Duration::hours(hours_ago),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status: 200,
+            latency_ms: 1.5,
+            client_ip: "203.0.113.7".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_access_log_retention() {
+        let log = AccessLog::new();
+        log.record(entry("/old", RETENTION_HOURS + 1));
+        for i in 0..MAX_ENTRIES + 5 {
+            log.record(entry(&format!("/{}", i), 0));
+        }
+
+        let recent = log.recent(MAX_ENTRIES * 2);
+        assert_eq!(recent.len(), MAX_ENTRIES);
+        assert_eq!(recent[0].path, format!("/{}", MAX_ENTRIES + 4));
+        assert!(recent.iter().all(|entry| entry.path != "/old" && entry.path != "/0"));
+        assert_eq!(log.recent(2).len(), 2);
+
+        assert_eq!(deployment_path("/faas/abc", "abc"), "/");
+        assert_eq!(deployment_path("/faas/abc/api/items", "abc"), "/api/items");
+    }
+}
@@ -0,0 +1,186 @@
+//! Managed services started next to a deployment, `addons: ["postgres", "redis"]`
+//!
+//! Each add-on runs in a Docker container of its own on the deployment's private
+//! network: the one the request names, or else one of the deployment's own. It is
+//! reached there as `<hostname>-<addon>`, e.g. `app-postgres`, and the connection
+//! settings are written into the deployment's env vars at deploy time, so they move
+//! with exports and backups; env vars the request sets are kept. The containers live as
+//! long as the deployment, across restarts of the service and scale-to-zero, and their
+//! data goes with them when it is removed.
+
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::{DeploymentRequest, STATIC_RUNTIME};
+use crate::api::error::InvalidInput;
+use crate::sandbox::backend::AddonContainer;
+use crate::sandbox::NetworkConfig;
+
+pub use voidrun_types::faas::Addon;
+
+/// Host name of a deployment on the network of its own
+pub const DEFAULT_HOSTNAME: &str = "app";
+/// Memory limit of each add-on container
+pub const ADDON_MEMORY_LIMIT_MB: u64 = 256;
+
+const POSTGRES_IMAGE: &str = "postgres:16-alpine";
+const POSTGRES_PORT: u16 = 5432;
+const REDIS_IMAGE: &str = "redis:7-alpine";
+const REDIS_PORT: u16 = 6379;
+
+/// Check the add-ons can be provided for the request
+pub fn validate(request: &DeploymentRequest) -> Result<(), InvalidInput> {
+    if request.addons.is_empty() {
+        return Ok(());
+    }
+    if request.runtime == STATIC_RUNTIME {
+        return Err(InvalidInput("Static sites cannot have add-ons".to_string()));
+    }
+    for (index, addon) in request.addons.iter().enumerate() {
+        if request.addons[..index].contains(addon) {
+            return Err(InvalidInput(format!("Add-on {} is listed twice", addon.as_str())));
+        }
+    }
+    Ok(())
+}
+
+/// Private network the deployment's instances join: the request's, or with add-ons and
+/// none, one named after the deployment
+pub fn network(deployment_id: &str, request: &DeploymentRequest) -> Option<NetworkConfig> {
+    request.network.clone().or_else(|| {
+        (!request.addons.is_empty()).then(|| NetworkConfig {
+            name: deployment_id.to_string(),
+            hostname: DEFAULT_HOSTNAME.to_string(),
+        })
+    })
+}
+
+/// Host names the deployment takes in its network: its own and its add-ons'
+pub fn hostnames(deployment_id: &str, request: &DeploymentRequest) -> Vec<String> {
+    let Some(network) = network(deployment_id, request) else {
+        return Vec::new();
+    };
+    let mut hostnames: Vec<String> = request.addons.iter().map(|addon| hostname(&network, *addon)).collect();
+    hostnames.push(network.hostname);
+    hostnames
+}
+
+fn hostname(network: &NetworkConfig, addon: Addon) -> String {
+    format!("{}-{}", network.hostname, addon.as_str())
+}
+
+/// Name of an add-on's container
+pub fn container_name(deployment_id: &str, addon: Addon) -> String {
+    format!("voidrun-addon-{}-{}", deployment_id, addon.as_str())
+}
+
+/// Add the connection env vars of each add-on the request does not set, with new passwords
+pub fn provision(deployment_id: &str, request: &mut DeploymentRequest) {
+    let Some(network) = network(deployment_id, request) else {
+        return;
+    };
+    let env = request.env_vars.get_or_insert_with(HashMap::new);
+    for addon in &request.addons {
+        let host = hostname(&network, *addon);
+        let mut set = |name: &str, value: String| {
+            env.entry(name.to_string()).or_insert(value);
+        };
+        match addon {
+            Addon::Postgres => {
+                set("PGHOST", host);
+                set("PGPORT", POSTGRES_PORT.to_string());
+                set("PGUSER", "voidrun".to_string());
+                set("PGPASSWORD", Uuid::new_v4().simple().to_string());
+                set("PGDATABASE", "app".to_string());
+                let url = format!(
+                    "postgres://{}:{}@{}:{}/{}",
+                    env["PGUSER"], env["PGPASSWORD"], env["PGHOST"], env["PGPORT"], env["PGDATABASE"]
+                );
+                env.entry("DATABASE_URL".to_string()).or_insert(url);
+            }
+            Addon::Redis => {
+                set("REDIS_HOST", host);
+                set("REDIS_PORT", REDIS_PORT.to_string());
+                set("REDIS_PASSWORD", Uuid::new_v4().simple().to_string());
+                let url = format!("redis://:{}@{}:{}", env["REDIS_PASSWORD"], env["REDIS_HOST"], env["REDIS_PORT"]);
+                env.entry("REDIS_URL".to_string()).or_insert(url);
+            }
+        }
+    }
+}
+
+/// Containers of the deployment's add-ons, configured from the env vars [`provision`] set
+pub fn containers(deployment_id: &str, request: &DeploymentRequest) -> Vec<AddonContainer> {
+    let Some(network) = network(deployment_id, request) else {
+        return Vec::new();
+    };
+    let env = request.env_vars.clone().unwrap_or_default();
+    let var = |name: &str| env.get(name).cloned().unwrap_or_default();
+    request.addons.iter().map(|addon| {
+        let network = NetworkConfig { name: network.name.clone(), hostname: hostname(&network, *addon) };
+        let name = container_name(deployment_id, *addon);
+        match addon {
+            Addon::Postgres => AddonContainer {
+                name,
+                image: POSTGRES_IMAGE.to_string(),
+                command: None,
+                env: HashMap::from([
+                    ("POSTGRES_USER".to_string(), var("PGUSER")),
+                    ("POSTGRES_PASSWORD".to_string(), var("PGPASSWORD")),
+                    ("POSTGRES_DB".to_string(), var("PGDATABASE")),
+                ]),
+                // The server the image initializes the database with listens on the socket only
+                ready_check: "pg_isready -h 127.0.0.1 -U \"$POSTGRES_USER\" -d \"$POSTGRES_DB\"".to_string(),
+                memory_limit_mb: ADDON_MEMORY_LIMIT_MB,
+                network,
+            },
+            Addon::Redis => AddonContainer {
+                name,
+                image: REDIS_IMAGE.to_string(),
+                command: Some(vec![
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    "exec redis-server --requirepass \"$REDIS_PASSWORD\"".to_string(),
+                ]),
+                env: HashMap::from([("REDIS_PASSWORD".to_string(), var("REDIS_PASSWORD"))]),
+                ready_check: "redis-cli --no-auth-warning -a \"$REDIS_PASSWORD\" ping | grep -q PONG".to_string(),
+                memory_limit_mb: ADDON_MEMORY_LIMIT_MB,
+                network,
+            },
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provision() {
+        let mut request = DeploymentRequest {
+            runtime: "bun".to_string(),
+            addons: vec![Addon::Postgres, Addon::Redis],
+            env_vars: Some(HashMap::from([("PGDATABASE".to_string(), "shop".to_string())])),
+            ..Default::default()
+        };
+        assert!(validate(&request).is_ok());
+        provision("d1", &mut request);
+        let env = request.env_vars.clone().unwrap();
+        assert_eq!(env["PGHOST"], "app-postgres");
+        assert_eq!(env["DATABASE_URL"], format!("postgres://voidrun:{}@app-postgres:5432/shop", env["PGPASSWORD"]));
+        assert_eq!(env["REDIS_URL"], format!("redis://:{}@app-redis:6379", env["REDIS_PASSWORD"]));
+
+        // Provisioning again, e.g. on a restore, keeps the passwords
+        provision("d1", &mut request);
+        assert_eq!(request.env_vars.as_ref().unwrap(), &env);
+
+        let containers = containers("d1", &request);
+        assert_eq!(containers[0].name, "voidrun-addon-d1-postgres");
+        assert_eq!(containers[0].network, NetworkConfig { name: "d1".to_string(), hostname: "app-postgres".to_string() });
+        assert_eq!(containers[0].env["POSTGRES_PASSWORD"], env["PGPASSWORD"]);
+        assert_eq!(hostnames("d1", &request), ["app-postgres", "app-redis", "app"]);
+
+        request.addons.push(Addon::Redis);
+        assert!(validate(&request).is_err());
+    }
+}
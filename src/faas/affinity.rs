@@ -0,0 +1,104 @@
+//! Session affinity of deployments scaled to several instances
+//!
+//! With `auto_scale.sticky_sessions`, the proxy hands each client a random session key
+//! in the `voidrun_session` cookie and routes by rendezvous hashing of that key over the
+//! deployment's instances. A session keeps its instance for as long as the instance
+//! serves the deployment; adding or removing an instance only moves the sessions of
+//! that instance. The cookie is stripped before requests reach the deployed service.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use uuid::Uuid;
+
+/// Cookie carrying the session key
+pub const SESSION_COOKIE: &str = "voidrun_session";
+
+/// New random session key
+pub fn new_session() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Session key presented in the request's cookies
+pub fn session(headers: &HeaderMap) -> Option<String> {
+    cookies(headers)
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Index of the instance serving `session`, stable while that instance is listed
+pub fn pick(instances: &[String], session: &str) -> usize {
+    instances.iter()
+        .enumerate()
+        .max_by_key(|(_, instance)| {
+            let mut hasher = DefaultHasher::new();
+            (session, instance.as_str()).hash(&mut hasher);
+            hasher.finish()
+        })
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Take the session cookie out of the request, keeping the service's own cookies
+pub fn strip_cookie(headers: &mut HeaderMap) {
+    if session(headers).is_none() {
+        return;
+    }
+    let kept = cookies(headers)
+        .filter(|(name, _)| *name != SESSION_COOKIE)
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("; ");
+    headers.remove(header::COOKIE);
+    if let Ok(value) = HeaderValue::from_str(&kept) {
+        if !kept.is_empty() {
+            headers.insert(header::COOKIE, value);
+        }
+    }
+}
+
+/// `Set-Cookie` value handing out `session` for requests under `path`, the path of the deployment's URL
+pub fn set_cookie(path: &str, session: &str) -> String {
+    format!("{}={}; Path={}; HttpOnly; SameSite=Lax", SESSION_COOKIE, session, path)
+}
+
+fn cookies(headers: &HeaderMap) -> impl Iterator<Item = (&str, &str)> {
+    headers.get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_keep_their_instance() {
+        let mut instances: Vec<String> = (0..4).map(|i| format!("sandbox-{}", i)).collect();
+        let sessions: Vec<String> = (0..200).map(|_| new_session()).collect();
+        let before: Vec<String> = sessions.iter().map(|s| instances[pick(&instances, s)].clone()).collect();
+
+        // Only the sessions of a removed instance move
+        instances.remove(2);
+        for (session, previous) in sessions.iter().zip(&before) {
+            let current = &instances[pick(&instances, session)];
+            if previous != "sandbox-2" {
+                assert_eq!(current, previous);
+            }
+        }
+        assert!(before.iter().any(|instance| instance == "sandbox-2"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "theme=dark; voidrun_session=abc; lang=en".parse().unwrap());
+        assert_eq!(session(&headers).as_deref(), Some("abc"));
+        strip_cookie(&mut headers);
+        assert_eq!(headers.get(header::COOKIE).unwrap(), "theme=dark; lang=en");
+
+        headers.insert(header::COOKIE, "voidrun_session=abc".parse().unwrap());
+        strip_cookie(&mut headers);
+        assert!(headers.get(header::COOKIE).is_none());
+    }
+}
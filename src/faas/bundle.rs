@@ -0,0 +1,159 @@
+//! Deployments as portable bundles, for moving them between instances and for backups
+//!
+//! A bundle is a gzipped tarball holding `manifest.json`, with the deployment request
+//! and the list of its files, and each file's bytes under `files/`. It carries what the
+//! deployment was created with, including files updated since, its env vars and registry
+//! tokens; its ID, URL and access token are issued anew where it is imported.
+
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+
+use super::DeploymentRequest;
+use crate::api::error::InvalidInput;
+use crate::sandbox::{file_contents, FileEncoding, SandboxFile};
+
+pub const MANIFEST_PATH: &str = "manifest.json";
+/// Format the manifest declares, checked on import
+pub const BUNDLE_FORMAT: &str = "voidrun-deployment/1";
+/// Bytes an imported bundle may unpack to, guarding against archives that inflate without bound
+pub const MAX_UNPACKED_BYTES: u64 = 256 * 1024 * 1024;
+
+const FILES_DIR: &str = "files/";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    deployment_id: String,
+    exported_at: DateTime<Utc>,
+    /// The deployment request without its files' contents
+    request: DeploymentRequest,
+    files: Vec<BundledFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundledFile {
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    is_executable: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<FileEncoding>,
+}
+
+/// Name of a file's entry in the archive
+fn entry_path(path: &str) -> String {
+    format!("{}{}", FILES_DIR, path.trim_start_matches('/'))
+}
+
+/// Object key an exported bundle is uploaded under when `[s3]` is configured
+pub fn object_key(deployment_id: &str) -> String {
+    format!("bundles/{}.tar.gz", deployment_id)
+}
+
+/// Bundle of a deployment created from `request`
+pub fn export(deployment_id: &str, request: &DeploymentRequest) -> Result<Vec<u8>> {
+    let files = request.files.clone().unwrap_or_default();
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        deployment_id: deployment_id.to_string(),
+        exported_at: Utc::now(),
+        request: DeploymentRequest { files: None, ..request.clone() },
+        files: files.iter()
+            .map(|file| BundledFile { path: file.path.clone(), is_executable: file.is_executable, encoding: file.encoding })
+            .collect(),
+    };
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+    let mtime = manifest.exported_at.timestamp().max(0) as u64;
+    let mut append = |path: &str, contents: &[u8], mode: u32| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        archive.append_data(&mut header, path, contents).with_context(|| format!("Cannot bundle {}", path))
+    };
+    append(MANIFEST_PATH, &serde_json::to_vec_pretty(&manifest)?, 0o644)?;
+    for file in &files {
+        let mode = if file.is_executable.unwrap_or(false) { 0o755 } else { 0o644 };
+        append(&entry_path(&file.path), &file_contents(file)?, mode)?;
+    }
+    Ok(archive.into_inner()?.finish()?)
+}
+
+/// Deployment request a bundle was exported from, with the ID it had there
+pub fn import(bundle: &[u8]) -> Result<(String, DeploymentRequest), InvalidInput> {
+    let invalid = |e: std::io::Error| InvalidInput(format!("Invalid bundle: {}", e));
+    let mut archive = tar::Archive::new(GzDecoder::new(bundle).take(MAX_UNPACKED_BYTES));
+    let mut entries: HashMap<String, Vec<u8>> = HashMap::new();
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(invalid)?.to_string_lossy().into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).map_err(invalid)?;
+        entries.insert(path, contents);
+    }
+
+    let manifest = entries.get(MANIFEST_PATH)
+        .ok_or_else(|| InvalidInput(format!("Invalid bundle: no {}", MANIFEST_PATH)))?;
+    let manifest: Manifest = serde_json::from_slice(manifest)
+        .map_err(|e| InvalidInput(format!("Invalid bundle {}: {}", MANIFEST_PATH, e)))?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(InvalidInput(format!("Unsupported bundle format {:?} (expected {})", manifest.format, BUNDLE_FORMAT)));
+    }
+
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for bundled in manifest.files {
+        let contents = entries.remove(&entry_path(&bundled.path))
+            .ok_or_else(|| InvalidInput(format!("Invalid bundle: file {} is missing", bundled.path)))?;
+        let content = match bundled.encoding.unwrap_or_default() {
+            FileEncoding::Utf8 => String::from_utf8(contents)
+                .map_err(|_| InvalidInput(format!("Invalid bundle: file {} is not UTF-8", bundled.path)))?,
+            FileEncoding::Base64 => BASE64_STANDARD.encode(contents),
+        };
+        files.push(SandboxFile { path: bundled.path, content, is_executable: bundled.is_executable, encoding: bundled.encoding });
+    }
+    let request = DeploymentRequest { files: (!files.is_empty()).then_some(files), ..manifest.request };
+    Ok((manifest.deployment_id, request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let request = DeploymentRequest {
+            runtime: "bun".to_string(),
+            code: "Bun.serve({ fetch: () => new Response('ok') })".to_string(),
+            files: Some(vec![
+                SandboxFile { path: "bin/start.sh".to_string(), content: "#!/bin/sh\n".to_string(), is_executable: Some(true), encoding: None },
+                SandboxFile { path: "logo.png".to_string(), content: "iVBORw0KGgo=".to_string(), is_executable: None, encoding: Some(FileEncoding::Base64) },
+            ]),
+            env_vars: Some(HashMap::from([("MODE".to_string(), "production".to_string())])),
+            memory_limit_mb: Some(256),
+            ..Default::default()
+        };
+        let bundle = export("a1", &request).unwrap();
+        let (deployment_id, imported) = import(&bundle).unwrap();
+        assert_eq!(deployment_id, "a1");
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&request).unwrap());
+
+        assert!(import(b"not a bundle").is_err());
+        let without_manifest = {
+            let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+            let mut header = tar::Header::new_gnu();
+            header.set_size(2);
+            archive.append_data(&mut header, "files/a", &b"hi"[..]).unwrap();
+            archive.into_inner().unwrap().finish().unwrap()
+        };
+        assert!(import(&without_manifest).unwrap_err().0.contains(MANIFEST_PATH));
+    }
+}
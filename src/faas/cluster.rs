@@ -0,0 +1,52 @@
+//! What this instance shares with the other instances of a cluster
+//!
+//! A sandbox is shared as its [`PersistedRequest`] and a deployment as the
+//! [`DeploymentRecord`] its instances carry, so the shared records keep no more secrets
+//! than container labels do. A deployment adopted from an instance that went down is
+//! deployed again from its record under the same id, keeping its access token.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::restore::DeploymentRecord;
+use super::FaasManager;
+use crate::cluster::{Registry, ResourceKind};
+use crate::sandbox::persisted::PersistedRequest;
+
+#[async_trait]
+impl Registry for FaasManager {
+    async fn ids(&self, kind: ResourceKind) -> Vec<String> {
+        match kind {
+            ResourceKind::Sandbox => self.sandbox_manager.get_all_sandboxes().await
+                .into_iter()
+                .map(|sandbox| sandbox.id)
+                .collect(),
+            ResourceKind::Deployment => self.deployments.read().await.keys().cloned().collect(),
+        }
+    }
+
+    async fn record(&self, kind: ResourceKind, id: &str) -> Option<Value> {
+        match kind {
+            ResourceKind::Sandbox => {
+                let sandbox = self.sandbox_manager.get_sandbox(id).await?;
+                serde_json::to_value(PersistedRequest::from(&sandbox.request)).ok()
+            }
+            ResourceKind::Deployment => {
+                let deployments = self.deployments.read().await;
+                let deployment = deployments.get(id)?;
+                DeploymentRecord::new(deployment, &deployment.request, true).to_value()
+            }
+        }
+    }
+
+    async fn adopt(&self, deployment_id: &str, record: Value) -> Result<()> {
+        // Restored from this instance's own containers after a restart
+        if self.deployments.read().await.contains_key(deployment_id) {
+            return Ok(());
+        }
+        let record: DeploymentRecord = serde_json::from_value(record)?;
+        self.restore_deployment(deployment_id, record.access_token_hash, record.request).await?;
+        Ok(())
+    }
+}
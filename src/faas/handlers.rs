@@ -1,40 +1,111 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Redirect},
     routing::{get, post, delete, put},
     Router,
 };
+use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{info, error, warn};
 
-use super::{FaasManager, DeploymentRequest, DeploymentResponse, FileUpdateRequest};
+use super::access_log;
+use super::bundle;
+use super::{
+    AccessLogEntry, FaasManager, DeploymentList, DeploymentListQuery, DeploymentRequest, DeploymentResponse, DeploymentStats,
+    DeploymentStatus, FileUpdateRequest,
+};
+use super::project::InvalidProjectConfig;
+use crate::api::error::ApiError;
+use crate::api::public_url::BaseUrl;
+use crate::coordination::{IdempotencyKey, IdempotencyKeys};
+use crate::events::EventBus;
+use crate::metering::{ApiKey, ANONYMOUS};
 use crate::sandbox::SandboxManager;
+use crate::templates;
+use voidrun_types::TemplateInfo;
 
 /// FaaS API state
 #[derive(Clone)]
 pub struct FaasState {
     pub faas_manager: Arc<FaasManager>,
+    /// Deploy requests already answered, by their `Idempotency-Key`
+    pub idempotency: IdempotencyKeys,
 }
 
 impl FaasState {
-    pub fn new(sandbox_manager: Arc<RwLock<SandboxManager>>, base_url: String) -> Self {
+    pub fn new(
+        sandbox_manager: Arc<SandboxManager>,
+        base_url: String,
+        events: EventBus,
+        idempotency: IdempotencyKeys,
+    ) -> Self {
         Self {
-            faas_manager: Arc::new(FaasManager::new(sandbox_manager, base_url)),
+            faas_manager: Arc::new(FaasManager::new(sandbox_manager, base_url, events)),
+            idempotency,
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AccessLogQuery {
+    /// Entries to return, newest first (default: 100)
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeployQuery {
+    /// Built-in template the request's files and settings are layered on
+    pub template: Option<String>,
+}
+
 /// Deploy a new serverless function
 ///
-/// POST /faas/deploy
+/// POST /faas/deploy[?template=<name>]
 /// Body: DeploymentRequest
-/// Returns: DeploymentResponse with unique URL
+/// Returns: DeploymentResponse with unique URL, or 503 with the failed deployment
+/// when its health check does not pass
+///
+/// Repeats with the same `Idempotency-Key` header get the first request's response
+/// instead of a second deployment. The deployment URL follows the public URL the
+/// request reached the service at.
 pub async fn deploy_function(
     State(state): State<FaasState>,
+    Query(query): Query<DeployQuery>,
+    api_key: ApiKey,
+    idempotency_key: IdempotencyKey,
+    BaseUrl(base_url): BaseUrl,
     Json(request): Json<DeploymentRequest>,
-) -> Result<Json<DeploymentResponse>, StatusCode> {
+) -> Result<(StatusCode, Json<DeploymentResponse>), ApiError> {
+    let scope = format!("deploy:{}", api_key.0.as_deref().unwrap_or(ANONYMOUS));
+    let response = state.idempotency
+        .run(&scope, idempotency_key.0.as_deref(), deploy(&state, query, api_key, &base_url, request))
+        .await?;
+    let status = if response.status == DeploymentStatus::Failed {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(response)))
+}
+
+async fn deploy(
+    state: &FaasState,
+    query: DeployQuery,
+    api_key: ApiKey,
+    base_url: &str,
+    mut request: DeploymentRequest,
+) -> Result<DeploymentResponse, ApiError> {
+    if let Some(name) = query.template {
+        let template = templates::find(&name).ok_or_else(|| {
+            warn!("[HTTP] Deploy requested unknown template: {}", name);
+            ApiError::not_found(format!("Template {} not found", name))
+        })?;
+        info!("[HTTP] Deploying from template {}", template.name);
+        template.apply(&mut request);
+    }
+
     info!("[HTTP] Deploy request received - Runtime: {}, Memory: {}MB, Dev server: {}", 
           request.runtime, 
           request.memory_limit_mb.unwrap_or(256),
@@ -48,11 +119,20 @@ pub async fn deploy_function(
         info!("[HTTP] Deploy includes {} environment variables", env_vars.len());
     }
     
-    match state.faas_manager.deploy(request).await {
+    match state.faas_manager.deploy(request, api_key.0, base_url).await {
+        Ok(response) if response.status == DeploymentStatus::Failed => {
+            error!("[HTTP] Deployment {} failed: {}", 
+                   response.deployment_id, response.error.as_deref().unwrap_or("unknown error"));
+            Ok(response)
+        }
         Ok(response) => {
             info!("[HTTP] Function deployed successfully - ID: {}, URL: {}, Sandbox: {}", 
                   response.deployment_id, response.url, response.sandbox_id);
-            Ok(Json(response))
+            Ok(response)
+        }
+        Err(e) if e.downcast_ref::<InvalidProjectConfig>().is_some() => {
+            warn!("[HTTP] Rejected deployment: {}", e);
+            Err(ApiError::Validation(e.to_string()))
         }
         Err(e) => {
             error!("[HTTP] Failed to deploy function: {}", e);
@@ -61,11 +141,11 @@ pub async fn deploy_function(
             
             // Check if it's a health check failure
             if e.to_string().contains("Health check failed") {
-                error!("[HTTP] HEALTH CHECK FAILURE - The deployed code is not starting a web server on port 3000");
-                error!("[HTTP] Make sure your code starts a web server (e.g., Express, Fastify, etc.) listening on port 3000");
+                error!("[HTTP] HEALTH CHECK FAILURE - The deployed code is not starting a web server on its configured port");
+                error!("[HTTP] Make sure your code starts a web server (e.g., Express, Fastify, etc.) listening on the deployment port (default 3000)");
             }
             
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::from(e))
         }
     }
 }
@@ -77,22 +157,54 @@ pub async fn deploy_function(
 pub async fn get_deployment(
     State(state): State<FaasState>,
     Path(deployment_id): Path<String>,
-) -> Result<Json<DeploymentResponse>, StatusCode> {
+) -> Result<Json<DeploymentResponse>, ApiError> {
     match state.faas_manager.get_deployment(&deployment_id).await {
         Some(deployment) => Ok(Json(deployment)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!("Deployment {} not found", deployment_id))),
     }
 }
 
-/// List all deployments
+/// Traffic the FaaS proxy has passed to a deployment
 ///
-/// GET /faas/deployments
-/// Returns: Vec<DeploymentResponse>
+/// GET /faas/deployments/{deployment_id}/stats
+/// Returns: DeploymentStats
+pub async fn get_deployment_stats(
+    State(state): State<FaasState>,
+    Path(deployment_id): Path<String>,
+) -> Result<Json<DeploymentStats>, ApiError> {
+    match state.faas_manager.stats(&deployment_id).await {
+        Some(stats) => Ok(Json(stats)),
+        None => Err(ApiError::not_found(format!("Deployment {} not found", deployment_id))),
+    }
+}
+
+/// Requests the FaaS proxy passed to a deployment, newest first
+///
+/// GET /faas/deployments/{deployment_id}/access-logs[?limit=<n>]
+/// Returns: Vec<AccessLogEntry>
+pub async fn get_access_logs(
+    State(state): State<FaasState>,
+    Path(deployment_id): Path<String>,
+    Query(query): Query<AccessLogQuery>,
+) -> Result<Json<Vec<AccessLogEntry>>, ApiError> {
+    let limit = query.limit.unwrap_or(access_log::DEFAULT_LIMIT);
+    match state.faas_manager.access_logs(&deployment_id, limit).await {
+        Some(entries) => Ok(Json(entries)),
+        None => Err(ApiError::not_found(format!("Deployment {} not found", deployment_id))),
+    }
+}
+
+/// List deployments, newest first
+///
+/// GET /faas/deployments[?status=&runtime=&created_after=&label=&limit=&cursor=]
+/// Returns: DeploymentList with the next page's cursor
 pub async fn list_deployments(
     State(state): State<FaasState>,
-) -> Result<Json<Vec<DeploymentResponse>>, StatusCode> {
-    let deployments = state.faas_manager.list_deployments().await;
-    Ok(Json(deployments))
+    Query(query): Query<DeploymentListQuery>,
+) -> Result<Json<DeploymentList>, ApiError> {
+    state.faas_manager.list_deployments(&query).await
+        .map(Json)
+        .map_err(|e| ApiError::Validation(e.to_string()))
 }
 
 /// Undeploy a function
@@ -102,7 +214,7 @@ pub async fn list_deployments(
 pub async fn undeploy_function(
     State(state): State<FaasState>,
     Path(deployment_id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     info!("[HTTP] Undeploy request received for deployment: {}", deployment_id);
     
     // Check if deployment exists first
@@ -122,14 +234,7 @@ pub async fn undeploy_function(
         Err(e) => {
             error!("[HTTP] Failed to undeploy function {}: {}", deployment_id, e);
             error!("[HTTP] Undeploy error details: {:?}", e);
-            
-            if e.to_string().contains("not found") {
-                error!("[HTTP] Deployment {} not found for undeploy", deployment_id);
-                Err(StatusCode::NOT_FOUND)
-            } else {
-                error!("[HTTP] Internal error during undeploy");
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
-            }
+            Err(ApiError::from(e))
         }
     }
 }
@@ -143,7 +248,7 @@ pub async fn update_files(
     State(state): State<FaasState>,
     Path(deployment_id): Path<String>,
     Json(request): Json<FileUpdateRequest>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     info!("[HTTP] Update files request for deployment: {}", deployment_id);
     info!("[HTTP] Update details - Files: {}, Restart dev server: {}", 
           request.files.len(),
@@ -151,7 +256,7 @@ pub async fn update_files(
     
     for (idx, file) in request.files.iter().enumerate() {
         info!("[HTTP] File {} - Path: {}, Size: {} bytes, Executable: {}", 
-              idx + 1, file.path, file.content.len(), file.executable.unwrap_or(false));
+              idx + 1, file.path, file.content.len(), file.is_executable.unwrap_or(false));
     }
     
     match state.faas_manager.update_files(&deployment_id, request).await {
@@ -162,24 +267,103 @@ pub async fn update_files(
         Err(e) => {
             error!("[HTTP] Failed to update files for deployment {}: {}", deployment_id, e);
             error!("[HTTP] Update error details: {:?}", e);
-            if e.to_string().contains("not found") {
-                error!("[HTTP] Deployment {} not found", deployment_id);
-                Err(StatusCode::NOT_FOUND)
-            } else {
-                error!("[HTTP] Internal error during update");
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
-            }
+            Err(ApiError::from(e))
+        }
+    }
+}
+
+/// Rebuild a deployment in a fresh sandbox, keeping its ID and URL
+///
+/// POST /faas/deployments/{deployment_id}/redeploy
+/// Returns: DeploymentResponse
+pub async fn redeploy_function(
+    State(state): State<FaasState>,
+    Path(deployment_id): Path<String>,
+) -> Result<Json<DeploymentResponse>, ApiError> {
+    info!("[HTTP] Redeploy request received for deployment: {}", deployment_id);
+    match state.faas_manager.redeploy(&deployment_id).await {
+        Ok(response) => {
+            info!("[HTTP] Deployment {} redeployed in sandbox {}", deployment_id, response.sandbox_id);
+            Ok(Json(response))
+        }
+        Err(e) => {
+            error!("[HTTP] Failed to redeploy {}: {:#}", deployment_id, e);
+            Err(ApiError::from(e))
         }
     }
 }
 
+/// Download a deployment as a bundle: a tarball of its manifest and files
+///
+/// GET /faas/deployments/{deployment_id}/export
+/// Returns: `application/gzip`, for POST /faas/import; with `[s3]`, a redirect to the
+/// bundle's presigned URL
+pub async fn export_deployment(
+    State(state): State<FaasState>,
+    Path(deployment_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(url) = state.faas_manager.export_url(&deployment_id).await? {
+        info!("[HTTP] Exported deployment {} to object storage", deployment_id);
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+    let bundle = state.faas_manager.export(&deployment_id).await?;
+    info!("[HTTP] Exported deployment {} as a {} byte bundle", deployment_id, bundle.len());
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"deployment-{}.tar.gz\"", deployment_id)),
+        ],
+        bundle,
+    ).into_response())
+}
+
+/// Recreate a deployment from a bundle exported by this or another instance
+///
+/// POST /faas/import
+/// Body: the bundle from GET /faas/deployments/{deployment_id}/export
+/// Returns: DeploymentResponse of the new deployment, as POST /faas/deploy does
+pub async fn import_deployment(
+    State(state): State<FaasState>,
+    api_key: ApiKey,
+    idempotency_key: IdempotencyKey,
+    BaseUrl(base_url): BaseUrl,
+    body: Bytes,
+) -> Result<(StatusCode, Json<DeploymentResponse>), ApiError> {
+    let (source_id, request) = bundle::import(&body).map_err(anyhow::Error::from)?;
+    info!("[HTTP] Importing deployment {} from a {} byte bundle", source_id, body.len());
+    let scope = format!("import:{}", api_key.0.as_deref().unwrap_or(ANONYMOUS));
+    let response = state.idempotency
+        .run(&scope, idempotency_key.0.as_deref(), deploy(&state, DeployQuery { template: None }, api_key, &base_url, request))
+        .await?;
+    let status = if response.status == DeploymentStatus::Failed {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(response)))
+}
+
+/// List the built-in project templates
+///
+/// GET /templates
+/// Returns: Vec<TemplateInfo>
+pub async fn list_templates() -> Json<Vec<TemplateInfo>> {
+    Json(templates::all().iter().map(|template| template.info()).collect())
+}
+
 /// Create FaaS router
 pub fn create_faas_router(state: FaasState) -> Router {
     Router::new()
         .route("/faas/deploy", post(deploy_function))
+        .route("/faas/import", post(import_deployment))
+        .route("/templates", get(list_templates))
         .route("/faas/deployments", get(list_deployments))
         .route("/faas/deployments/:deployment_id", get(get_deployment))
         .route("/faas/deployments/:deployment_id", delete(undeploy_function))
         .route("/faas/deployments/:deployment_id/files", put(update_files))
+        .route("/faas/deployments/:deployment_id/stats", get(get_deployment_stats))
+        .route("/faas/deployments/:deployment_id/access-logs", get(get_access_logs))
+        .route("/faas/deployments/:deployment_id/redeploy", post(redeploy_function))
+        .route("/faas/deployments/:deployment_id/export", get(export_deployment))
         .with_state(state)
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+//! Concurrency limit of a deployment, enforced by the FaaS proxy
+//!
+//! With `max_concurrent_requests`, at most that many requests are forwarded to the
+//! deployment at once, across all of its instances. Further requests wait in a queue of
+//! `max_queued_requests` for up to [`QUEUE_TIMEOUT_SECS`]; requests that find the queue
+//! full or wait too long are answered with `429 Too Many Requests`.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::DeploymentRequest;
+use crate::api::error::InvalidInput;
+
+/// Queue length when the request sets none
+pub const DEFAULT_MAX_QUEUED: u32 = 100;
+/// Longest a request waits in the queue
+pub const QUEUE_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+    max_queued: usize,
+    queued: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+impl ConcurrencyLimit {
+    /// The limit a deployment asks for, if it sets one
+    pub fn from_request(request: &DeploymentRequest) -> Option<Self> {
+        let max_concurrent = request.max_concurrent_requests?;
+        Some(Self {
+            permits: Arc::new(Semaphore::new(max_concurrent as usize)),
+            max_queued: request.max_queued_requests.unwrap_or(DEFAULT_MAX_QUEUED) as usize,
+            queued: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Wait for a slot to forward a request in; `None` when the request is turned away
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        // Leaves the queue also when the client gives up while waiting
+        let _queued = Queued(&self.queued);
+
+        let wait = self.permits.clone().acquire_owned();
+        match tokio::time::timeout(Duration::from_secs(QUEUE_TIMEOUT_SECS), wait).await {
+            Ok(Ok(permit)) => Some(permit),
+            _ => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Requests waiting for a slot right now
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Requests turned away so far
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Reject a limit that would turn away every request
+pub fn validate(request: &DeploymentRequest) -> Result<(), InvalidInput> {
+    if request.max_concurrent_requests == Some(0) {
+        return Err(InvalidInput("max_concurrent_requests must be at least 1".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_excess_requests_queue_then_get_rejected() {
+        let request = DeploymentRequest {
+            max_concurrent_requests: Some(1),
+            max_queued_requests: Some(1),
+            ..Default::default()
+        };
+        let limit = Arc::new(ConcurrencyLimit::from_request(&request).unwrap());
+
+        let first = limit.acquire().await.unwrap();
+        let waiting = tokio::spawn({
+            let limit = limit.clone();
+            async move { limit.acquire().await.is_some() }
+        });
+        while limit.queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The queue is full
+        assert!(limit.acquire().await.is_none());
+        assert_eq!(limit.rejected(), 1);
+
+        drop(first);
+        assert!(waiting.await.unwrap());
+        assert_eq!(limit.queued(), 0);
+
+        assert!(ConcurrencyLimit::from_request(&DeploymentRequest::default()).is_none());
+        assert!(validate(&DeploymentRequest { max_concurrent_requests: Some(0), ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! Filtering and cursor pagination of `GET /faas/deployments`
+//!
+//! Deployments are listed newest first, ties broken by ID, so pages stay stable while
+//! deployments come and go. The cursor encodes the sort key of a page's last entry.
+
+use super::{DeploymentList, DeploymentListQuery, DeploymentResponse};
+use crate::api::error::InvalidInput;
+use crate::runtime;
+use crate::sandbox::labels::LabelSelector;
+use crate::sandbox::listing::{decode_cursor, encode_cursor, validate_limit};
+
+/// The page of `deployments` the query asks for
+pub fn page(mut deployments: Vec<DeploymentResponse>, query: &DeploymentListQuery) -> Result<DeploymentList, InvalidInput> {
+    let limit = validate_limit(query.limit)?;
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+    let labels = query.label.as_deref().map(LabelSelector::parse).transpose()?;
+
+    deployments.retain(|deployment| {
+        query.status.as_ref().is_none_or(|status| deployment.status == *status)
+            && query.runtime.as_deref().is_none_or(|name| runtime::matches(&deployment.runtime, name))
+            && query.created_after.is_none_or(|time| deployment.created_at > time)
+            && labels.as_ref().is_none_or(|labels| labels.matches(&deployment.labels))
+            && after.as_ref().is_none_or(|(time, id)| (deployment.created_at, &deployment.deployment_id) < (*time, id))
+    });
+    deployments.sort_by(|a, b| (b.created_at, &b.deployment_id).cmp(&(a.created_at, &a.deployment_id)));
+
+    let next_cursor = (deployments.len() > limit).then(|| {
+        let last = &deployments[limit - 1];
+        encode_cursor(last.created_at, &last.deployment_id)
+    });
+    deployments.truncate(limit);
+    Ok(DeploymentList { deployments, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faas::DeploymentStatus;
+    use chrono::{Duration, Utc};
+
+    fn deployment(id: &str, runtime: &str, minutes_ago: i64, status: DeploymentStatus) -> DeploymentResponse {
+        let created_at = Utc::now() - Duration::minutes(minutes_ago);
+        DeploymentResponse {
+            deployment_id: id.to_string(),
+            url: String::new(),
+            sandbox_id: String::new(),
+            status,
+            created_at,
+            runtime: runtime.to_string(),
+            memory_mb: 256,
+            instances: 1,
+            max_instances: 1,
+            last_accessed: created_at,
+            error: None,
+            failure_reason: None,
+            logs: None,
+            access_token: None,
+            labels: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_deployment_pages() {
+        let deployments = vec![
+            deployment("a", "bun", 30, DeploymentStatus::Running),
+            deployment("b", "node@20", 20, DeploymentStatus::Failed),
+            deployment("c", "node", 10, DeploymentStatus::Running),
+            deployment("d", "bun@1.1", 5, DeploymentStatus::ScaledToZero),
+        ];
+        let ids = |list: &DeploymentList| list.deployments.iter().map(|d| d.deployment_id.clone()).collect::<Vec<_>>();
+
+        let query = DeploymentListQuery { limit: Some(3), ..Default::default() };
+        let first = page(deployments.clone(), &query).unwrap();
+        assert_eq!(ids(&first), ["d", "c", "b"]);
+        let query = DeploymentListQuery { limit: Some(3), cursor: first.next_cursor, ..Default::default() };
+        let second = page(deployments.clone(), &query).unwrap();
+        assert_eq!(ids(&second), ["a"]);
+        assert!(second.next_cursor.is_none());
+
+        let query = DeploymentListQuery { runtime: Some("node".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(deployments.clone(), &query).unwrap()), ["c", "b"]);
+        let query = DeploymentListQuery { status: Some(DeploymentStatus::Running), ..Default::default() };
+        assert_eq!(ids(&page(deployments.clone(), &query).unwrap()), ["c", "a"]);
+        let query = DeploymentListQuery { created_after: Some(Utc::now() - Duration::minutes(15)), ..Default::default() };
+        assert_eq!(ids(&page(deployments.clone(), &query).unwrap()), ["d", "c"]);
+        let mut labeled = deployments.clone();
+        labeled[1].labels.insert("team".to_string(), "ml".to_string());
+        let query = DeploymentListQuery { label: Some("team=ml".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(labeled, &query).unwrap()), ["b"]);
+
+        let query = DeploymentListQuery { cursor: Some("not a cursor".to_string()), ..Default::default() };
+        assert!(page(deployments.clone(), &query).is_err());
+        let query = DeploymentListQuery { limit: Some(0), ..Default::default() };
+        assert!(page(deployments, &query).is_err());
+    }
+}
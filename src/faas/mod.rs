@@ -1,88 +1,109 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
-use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use anyhow::Result;
 use tracing::{info, warn, error};
 
-use crate::sandbox::{SandboxManager, SandboxRequest, SandboxMode};
+use crate::api::cors::CorsPolicy;
+use crate::api::error::{InvalidInput, NotFound};
+use crate::cluster::{Cluster, ResourceKind};
+use crate::events::{EventBus, EventKind};
+use crate::metering::Meter;
+use crate::runtime::{self, bash, rust, UnsupportedRuntime};
+use crate::proxy::cache::ResponseCache;
+use crate::sandbox::backend::BackendUnavailable;
+use crate::sandbox::labels;
+use crate::sandbox::{
+    CapacityExceeded, SandboxFile, SandboxManager, SandboxRequest, SandboxMode, StatusConflict, DEFAULT_APP_PORT,
+};
+use static_site::{StaticSite, STATIC_RUNTIME};
+use access_log::AccessLog;
+use limit::ConcurrencyLimit;
+use restore::DeploymentRecord;
+use routing::Routing;
+use stats::TrafficStats;
 
+pub mod access;
+pub mod access_log;
+pub mod addons;
+pub mod affinity;
+pub mod bundle;
+mod cluster;
 pub mod handlers;
+pub mod limit;
+pub mod listing;
+pub mod project;
+pub mod registry;
+pub mod reload;
+pub mod restore;
+pub mod routing;
+pub mod static_site;
+pub mod stats;
 
-/// FaaS deployment request
-#[derive(Debug, Clone, Deserialize)]
-pub struct DeploymentRequest {
-    /// Runtime environment (bun, node, typescript)
-    pub runtime: String,
-    /// Main application code
-    pub code: String,
-    /// Additional files (optional)
-    pub files: Option<Vec<FileSpec>>,
-    /// Environment variables (optional)
-    pub env_vars: Option<HashMap<String, String>>,
-    /// Memory limit in MB (default: 256)
-    pub memory_limit_mb: Option<u32>,
-    /// Entry point command (optional, defaults based on runtime)
-    pub entry_point: Option<String>,
-    /// Auto-scale settings (optional)
-    pub auto_scale: Option<AutoScaleConfig>,
-    /// Whether to run as dev server with hot reload (default: true)
-    pub dev_server: Option<bool>,
-}
+pub use voidrun_types::faas::{
+    AccessLogEntry, AuthMode, AutoScaleConfig, CacheConfig, DeploymentList, DeploymentListQuery, DeploymentRequest,
+    DeploymentResponse, DeploymentStats, DeploymentStatus, FailureReason, FileUpdateRequest, PackageRegistry, ReloadStrategy,
+    RoutingRule,
+};
+
+/// Number of dev server log lines kept on a failed deployment
+const FAILURE_LOG_LINES: usize = 100;
 
-/// File specification for additional files
-#[derive(Debug, Clone, Deserialize)]
-pub struct FileSpec {
-    /// File path relative to project root
-    pub path: String,
-    /// File content
-    pub content: String,
-    /// Whether file should be executable
-    pub executable: Option<bool>,
+/// Reject health check paths that are not absolute or would not reach the probe as one
+/// URL path: quotes, whitespace and control characters
+fn validate_health_check(request: &DeploymentRequest) -> Result<(), InvalidInput> {
+    let Some(path) = request.health_check.as_ref().and_then(|check| check.path.as_deref()) else {
+        return Ok(());
+    };
+    if !path.starts_with('/') || path.chars().any(|c| c.is_whitespace() || c.is_control() || c == '\'' || c == '"') {
+        return Err(InvalidInput(format!("Invalid health_check.path {:?}: must start with / and contain no quotes, whitespace or control characters", path)));
+    }
+    Ok(())
 }
 
-/// Auto-scaling configuration
-#[derive(Debug, Clone, Deserialize)]
-pub struct AutoScaleConfig {
-    /// Scale down after inactivity (minutes, default: 10)
-    pub scale_down_after_minutes: Option<u32>,
+/// Proxy response cache for a deployment's cache settings
+fn build_cache(config: &CacheConfig) -> ResponseCache {
+    ResponseCache::new(
+        config.max_entries.unwrap_or(256),
+        config.max_entry_bytes.unwrap_or(1024 * 1024),
+        config.default_ttl_seconds.map(Duration::from_secs),
+    )
 }
 
-/// File update request for running deployments
-#[derive(Debug, Clone, Deserialize)]
-pub struct FileUpdateRequest {
-    /// Files to update or add
-    pub files: Vec<FileSpec>,
-    /// Whether to restart the dev server after update (default: true)
-    pub restart_dev_server: Option<bool>,
+/// Cross-origin policy a deployment request sets, if any
+fn cors_policy(request: &DeploymentRequest) -> Result<Option<CorsPolicy>, InvalidInput> {
+    request.cors.as_ref()
+        .map(|config| CorsPolicy::new(config).map_err(|e| InvalidInput(format!("Invalid cors: {}", e.0))))
+        .transpose()
 }
 
-/// FaaS deployment response
-#[derive(Debug, Clone, Serialize)]
-pub struct DeploymentResponse {
-    /// Unique deployment ID
-    pub deployment_id: String,
-    /// Public URL to access the service
-    pub url: String,
-    /// Internal sandbox ID
-    pub sandbox_id: String,
-    /// Deployment status
-    pub status: DeploymentStatus,
-    /// Created timestamp
-    pub created_at: DateTime<Utc>,
-    /// Runtime information
-    pub runtime: String,
-    /// Memory allocation
-    pub memory_mb: u32,
+/// Access token of a deployment with `auth.mode = "token"`: the new token to hand out
+/// in the deploy response, unless the deployment keeps the one `kept_hash` was taken
+/// from, and the hash the proxy checks callers against
+fn issue_access_token(request: &DeploymentRequest, kept_hash: Option<String>) -> (Option<String>, Option<String>) {
+    if !request.auth.as_ref().is_some_and(|auth| auth.mode == AuthMode::Token) {
+        return (None, None);
+    }
+    match kept_hash {
+        Some(hash) => (None, Some(hash)),
+        None => {
+            let token = access::generate();
+            let hash = access::hash(&token);
+            (Some(token), Some(hash))
+        }
+    }
 }
 
-/// Deployment status
-#[derive(Debug, Clone, Serialize, PartialEq)]
-pub enum DeploymentStatus {
-    Running,
+/// Errors that refuse the sandbox outright, returned to the caller instead of recorded as a failed deployment
+fn is_rejection(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<CapacityExceeded>().is_some()
+        || e.downcast_ref::<UnsupportedRuntime>().is_some()
+        || e.downcast_ref::<BackendUnavailable>().is_some()
+        || e.downcast_ref::<InvalidInput>().is_some()
 }
 
 /// Deployment information for management
@@ -98,27 +119,190 @@ pub struct Deployment {
     pub memory_mb: u32,
     pub auto_scale: AutoScaleConfig,
     pub request: DeploymentRequest,
+    /// Sandboxes serving this deployment, primary sandbox first
+    pub instances: Arc<RwLock<Vec<String>>>,
+    /// Replicas removed from routing, deleted on the next cleanup tick
+    pub draining: Arc<RwLock<Vec<String>>>,
+    /// Proxied requests currently in flight across all instances
+    pub in_flight: Arc<AtomicUsize>,
+    /// Round-robin cursor for instance selection
+    pub next_instance: Arc<AtomicUsize>,
+    /// Set while an extra instance is being started
+    pub scaling: Arc<AtomicBool>,
+    /// Serializes wake-ups and scale-to-zero for the deployment
+    pub wake_lock: Arc<tokio::sync::Mutex<()>>,
+    pub error: Option<String>,
+    pub failure_reason: Option<FailureReason>,
+    pub logs: Option<String>,
+    /// Cached proxy responses, when the deployment enabled caching
+    pub cache: Option<Arc<ResponseCache>>,
+    /// Cap on requests forwarded at once, with `max_concurrent_requests`
+    pub limit: Option<Arc<ConcurrencyLimit>>,
+    /// Files of a static site deployment, which runs no sandbox
+    pub site: Option<Arc<StaticSite>>,
+    /// Cross-origin policy the proxy answers browsers with, when the deployment sets one
+    pub cors: Option<Arc<CorsPolicy>>,
+    /// Rewrites the proxy applies to requests, when the deployment sets them
+    pub routing: Option<Arc<Routing>>,
+    /// Key of the caller that deployed, which the instances' usage is metered to
+    pub api_key: Option<String>,
+    /// Hash of the token the proxy requires of callers, with `auth.mode = "token"`
+    pub access_token_hash: Option<String>,
+    /// Requests proxied to the deployment
+    pub traffic: Arc<TrafficStats>,
+    pub access_log: Arc<AccessLog>,
+}
+
+impl Deployment {
+    pub async fn to_response(&self) -> DeploymentResponse {
+        DeploymentResponse {
+            deployment_id: self.id.clone(),
+            url: self.url.clone(),
+            sandbox_id: self.sandbox_id.clone(),
+            status: self.status.clone(),
+            created_at: self.created_at,
+            runtime: self.runtime.clone(),
+            memory_mb: self.memory_mb,
+            instances: self.instances.read().await.len(),
+            max_instances: self.auto_scale.max_instances(),
+            last_accessed: *self.last_accessed.read().await,
+            error: self.error.clone(),
+            failure_reason: self.failure_reason,
+            logs: self.logs.clone(),
+            access_token: None,
+            labels: self.request.labels.clone(),
+        }
+    }
+
+    /// Response to the deploy request, the one place the access token is handed out
+    async fn to_deploy_response(&self, access_token: Option<String>) -> DeploymentResponse {
+        DeploymentResponse {
+            access_token,
+            ..self.to_response().await
+        }
+    }
+
+    /// Traffic so far, with the requests waiting under the concurrency limit
+    fn stats(&self, meter: &Meter) -> DeploymentStats {
+        let mut stats = self.traffic.snapshot(&self.id);
+        if let Some(limit) = &self.limit {
+            stats.queued_requests = limit.queued();
+            stats.rejected_requests = limit.rejected();
+        }
+        let (usage, cost) = meter.deployment_usage(&self.id);
+        stats.instance_seconds = usage.sandbox_seconds;
+        stats.memory_mb_seconds = usage.memory_mb_seconds;
+        stats.estimated_cost = cost;
+        stats
+    }
+
+    /// All sandboxes owned by this deployment, including draining replicas
+    async fn all_sandboxes(&self) -> Vec<String> {
+        let mut sandboxes = self.instances.read().await.clone();
+        sandboxes.extend(self.draining.read().await.iter().cloned());
+        sandboxes
+    }
+}
+
+/// Proxy lease on a deployment instance; releases its in-flight slot when dropped
+pub struct InstanceLease {
+    pub sandbox_id: String,
+    /// `Set-Cookie` value handing out a new session, with sticky sessions
+    pub set_cookie: Option<String>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InstanceLease {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Where the FaaS proxy records the requests of one deployment
+pub struct ProxiedTraffic {
+    pub stats: Arc<TrafficStats>,
+    pub access_log: Arc<AccessLog>,
+    last_accessed: Arc<RwLock<DateTime<Utc>>>,
+    auto_scale: AutoScaleConfig,
+}
+
+impl ProxiedTraffic {
+    /// Mark the deployment active after a request to `path`, unless the path is ignored for idleness
+    pub async fn touch(&self, path: &str) {
+        if self.auto_scale.counts_as_activity(path) {
+            *self.last_accessed.write().await = Utc::now();
+        }
+    }
 }
 
 /// FaaS Manager - handles serverless deployments
+#[derive(Clone)]
 pub struct FaasManager {
     deployments: Arc<RwLock<HashMap<String, Deployment>>>,
-    sandbox_manager: Arc<RwLock<SandboxManager>>,
+    sandbox_manager: Arc<SandboxManager>,
+    /// Public base URL for deployments whose request does not tell one
     base_url: String,
+    events: EventBus,
 }
 
 impl FaasManager {
-    pub fn new(sandbox_manager: Arc<RwLock<SandboxManager>>, base_url: String) -> Self {
+    pub fn new(sandbox_manager: Arc<SandboxManager>, base_url: String, events: EventBus) -> Self {
         Self {
             deployments: Arc::new(RwLock::new(HashMap::new())),
             sandbox_manager,
             base_url,
+            events,
+        }
+    }
+
+    /// Public base URL deployments are reached at unless their request tells another
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Deploy a new serverless function on behalf of the caller's `api_key`; its URL is
+    /// under `base_url`, the service's public URL as the caller reached it
+    pub async fn deploy(&self, request: DeploymentRequest, api_key: Option<String>, base_url: &str) -> Result<DeploymentResponse> {
+        self.deploy_as(Uuid::new_v4().to_string(), None, request, api_key, base_url).await
+    }
+
+    /// Deploy a deployment from a backup again under its ID, URL and the access token
+    /// `access_token_hash` was taken from
+    pub async fn restore_deployment(&self, deployment_id: &str, access_token_hash: Option<String>, request: DeploymentRequest) -> Result<DeploymentResponse> {
+        if self.deployments.read().await.contains_key(deployment_id) {
+            return Err(StatusConflict(format!("Deployment {} already exists", deployment_id)).into());
         }
+        let base_url = self.base_url.clone();
+        self.deploy_as(deployment_id.to_string(), access_token_hash, request, None, &base_url).await
     }
 
-    /// Deploy a new serverless function
-    pub async fn deploy(&self, request: DeploymentRequest) -> Result<DeploymentResponse> {
-        let deployment_id = Uuid::new_v4().to_string();
+    /// Deploy under `deployment_id`, keeping the access token `access_token_hash` was
+    /// taken from instead of issuing one if given
+    async fn deploy_as(
+        &self,
+        deployment_id: String,
+        access_token_hash: Option<String>,
+        request: DeploymentRequest,
+        api_key: Option<String>,
+        base_url: &str,
+    ) -> Result<DeploymentResponse> {
+        // Settings in the project's voidrun.toml fill in what the request leaves out
+        let mut request = project::resolve(request)?;
+        crate::sandbox::validate_files(request.files.as_deref())?;
+        labels::validate(&request.labels)?;
+        cors_policy(&request)?;
+        Routing::from_request(&request)?;
+        addons::validate(&request)?;
+        self.check_network(&deployment_id, &request).await?;
+        if request.runtime == STATIC_RUNTIME {
+            return self.deploy_static(deployment_id, access_token_hash, request, api_key, base_url).await;
+        }
+        runtime::resolve_version(&request.runtime)?;
+        reload::validate(&request)?;
+        limit::validate(&request)?;
+        registry::validate(&request)?;
+        addons::provision(&deployment_id, &mut request);
+        validate_health_check(&request)?;
         let sandbox_id = Uuid::new_v4().to_string();
         
         info!("Starting deployment {} with runtime {}", deployment_id, request.runtime);
@@ -130,7 +314,7 @@ impl FaasManager {
         if let Some(ref files) = request.files {
             info!("Additional files to deploy: {}", files.len());
             for file in files {
-                info!("  - {} (executable: {})", file.path, file.executable.unwrap_or(false));
+                info!("  - {} (executable: {})", file.path, file.is_executable.unwrap_or(false));
             }
         }
         
@@ -139,11 +323,25 @@ impl FaasManager {
         }
 
         // Generate unique URL
-        let url = format!("{}/faas/{}", self.base_url, deployment_id);
+        let url = format!("{}/faas/{}", base_url, deployment_id);
+        let mut deployment = Self::new_deployment(&deployment_id, &sandbox_id, &url, &request, Vec::new(), api_key);
+        let (access_token, access_token_hash) = issue_access_token(&request, access_token_hash);
+        deployment.access_token_hash = access_token_hash;
+        let record = DeploymentRecord::new(&deployment, &request, true);
+
+        // Add-ons come first, so the service can connect to them as it starts
+        if let Err(e) = self.start_addons(&deployment_id, &request).await {
+            if is_rejection(&e) {
+                self.remove_addons(&deployment_id, &request).await;
+                return Err(e);
+            }
+            error!("Failed to start add-ons of deployment {}: {:#}", deployment_id, e);
+            return Ok(self.record_failure(deployment, FailureReason::Addons, &e, None).await);
+        }
 
         // Prepare sandbox request
         info!("Creating sandbox request for deployment {}", deployment_id);
-        let sandbox_request = match self.create_sandbox_request(&sandbox_id, &request).await {
+        let sandbox_request = match self.create_sandbox_request(&sandbox_id, &request, deployment.api_key.as_deref(), &record).await {
             Ok(req) => {
                 info!("Sandbox request created - Entry point: {}, Mode: {:?}", 
                       req.entry_point.as_ref().unwrap_or(&"default".to_string()),
@@ -152,29 +350,39 @@ impl FaasManager {
             }
             Err(e) => {
                 error!("Failed to create sandbox request for deployment {}: {}", deployment_id, e);
-                return Err(anyhow::anyhow!("Failed to create sandbox request: {}", e));
+                return Err(e.context("Failed to create sandbox request"));
             }
         };
 
         // Create sandbox
         info!("Creating sandbox {} for deployment {}", sandbox_id, deployment_id);
         let sandbox_create_start = std::time::Instant::now();
-        let mut manager = self.sandbox_manager.write().await;
-        match manager.create_sandbox(sandbox_request).await {
+        match self.sandbox_manager.create_sandbox(sandbox_request).await {
             Ok(_) => {
                 info!("Sandbox {} created successfully in {:?}", sandbox_id, sandbox_create_start.elapsed());
+                self.sandbox_manager.meter().deployment_instance(&sandbox_id, &deployment_id);
+            }
+            Err(e) if is_rejection(&e) => {
+                warn!("Sandbox for deployment {} was rejected: {}", deployment_id, e);
+                self.remove_addons(&deployment_id, &request).await;
+                return Err(e);
             }
             Err(e) => {
                 error!("Failed to create sandbox {} for deployment {} after {:?}: {}", sandbox_id, deployment_id, sandbox_create_start.elapsed(), e);
-                return Err(anyhow::anyhow!("Failed to create sandbox: {}", e));
+                let e = e.context("Failed to create sandbox");
+                return Ok(self.record_failure(deployment, FailureReason::SandboxCreation, &e, None).await);
             }
         };
-        drop(manager);
 
         // Execute initial setup
         info!("Setting up deployment {} in sandbox {}", deployment_id, sandbox_id);
         info!("Deployment code preview: {}", &request.code[..std::cmp::min(100, request.code.len())]);
-        if let Err(e) = self.setup_deployment(&sandbox_id, &request).await {
+        let setup_result = match self.setup_deployment(&sandbox_id, &request).await {
+            Ok(()) => self.wait_until_ready(&sandbox_id, &request).await
+                .map_err(|e| (FailureReason::HealthCheck, e)),
+            Err(e) => Err((FailureReason::Setup, e)),
+        };
+        if let Err((reason, e)) = setup_result {
             error!("Failed to setup deployment {} in sandbox {}: {}", deployment_id, sandbox_id, e);
             error!("Setup failure details: {:#}", e);
             
@@ -184,35 +392,22 @@ impl FaasManager {
                 error!("- Code: {}", request.code);
                 error!("- Entry point: {}", request.entry_point.as_ref().unwrap_or(&"default".to_string()));
                 error!("- Runtime: {}", request.runtime);
-                error!("- The code executed but didn't start a web server on port 3000");
+                error!("- The code executed but didn't answer the readiness check");
                 error!("- For web deployments, make sure your code starts a server (Express, Fastify, etc.)");
             }
+
+            // Keep the dev server output before the sandbox goes away
+            let logs = self.capture_logs(&sandbox_id).await;
             
             // Try to cleanup the sandbox
-            let mut manager = self.sandbox_manager.write().await;
-            if let Err(cleanup_err) = manager.delete_sandbox(&sandbox_id).await {
+            if let Err(cleanup_err) = self.sandbox_manager.delete_sandbox(&sandbox_id).await {
                 error!("Failed to cleanup sandbox {} after setup failure: {}", sandbox_id, cleanup_err);
             }
-            return Err(e);
-        }
 
-        // Create deployment record
-        let auto_scale = request.auto_scale.clone().unwrap_or(AutoScaleConfig {
-            scale_down_after_minutes: Some(10),
-        });
+            return Ok(self.record_failure(deployment, reason, &e, logs).await);
+        }
 
-        let deployment = Deployment {
-            id: deployment_id.clone(),
-            sandbox_id: sandbox_id.clone(),
-            url: url.clone(),
-            status: DeploymentStatus::Running,
-            created_at: Utc::now(),
-            last_accessed: Arc::new(RwLock::new(Utc::now())),
-            runtime: request.runtime.clone(),
-            memory_mb: request.memory_limit_mb.unwrap_or(256),
-            auto_scale,
-            request: request.clone(),
-        };
+        deployment.instances.write().await.push(sandbox_id.clone());
 
         // Store deployment
         {
@@ -220,59 +415,169 @@ impl FaasManager {
             deployments.insert(deployment_id.clone(), deployment.clone());
             info!("Deployment {} stored in registry. Total deployments: {}", deployment_id, deployments.len());
         }
+        self.events.publish(EventKind::DeploymentCreated {
+            deployment_id: deployment_id.clone(),
+            sandbox_id: sandbox_id.clone(),
+            runtime: request.runtime.clone(),
+        });
 
         info!("Deployment {} created successfully at {}", deployment_id, url);
         info!("Deployment summary - ID: {}, Sandbox: {}, Runtime: {}, Memory: {}MB, Status: {:?}",
               deployment_id, sandbox_id, request.runtime, request.memory_limit_mb.unwrap_or(256),
               DeploymentStatus::Running);
 
-        Ok(DeploymentResponse {
+        Ok(deployment.to_deploy_response(access_token).await)
+    }
+
+    /// Store a deployment that could not be started, so clients can inspect why
+    async fn record_failure(
+        &self,
+        mut deployment: Deployment,
+        reason: FailureReason,
+        error: &anyhow::Error,
+        logs: Option<String>,
+    ) -> DeploymentResponse {
+        deployment.status = DeploymentStatus::Failed;
+        deployment.error = Some(format!("{:#}", error));
+        deployment.failure_reason = Some(reason);
+        deployment.logs = logs;
+        // Nothing is left to use them
+        self.remove_addons(&deployment.id, &deployment.request).await;
+
+        self.deployments.write().await.insert(deployment.id.clone(), deployment.clone());
+        self.events.publish(EventKind::DeploymentFailed {
+            deployment_id: deployment.id.clone(),
+            sandbox_id: deployment.sandbox_id.clone(),
+            error: format!("{:#}", error),
+        });
+        deployment.to_response().await
+    }
+
+    /// Register a static site deployment, served from memory by the FaaS proxy
+    async fn deploy_static(
+        &self,
+        deployment_id: String,
+        access_token_hash: Option<String>,
+        request: DeploymentRequest,
+        api_key: Option<String>,
+        base_url: &str,
+    ) -> Result<DeploymentResponse> {
+        let url = format!("{}/faas/{}", base_url, deployment_id);
+        let site = StaticSite::from_request(&request)?;
+        info!("Deploying static site {} with {} files", deployment_id, site.file_count());
+
+        let mut deployment = Self::new_deployment(&deployment_id, "", &url, &request, Vec::new(), api_key);
+        let (access_token, access_token_hash) = issue_access_token(&request, access_token_hash);
+        deployment.access_token_hash = access_token_hash;
+        deployment.memory_mb = 0;
+        deployment.site = Some(Arc::new(site));
+
+        self.deployments.write().await.insert(deployment_id.clone(), deployment.clone());
+        self.events.publish(EventKind::DeploymentCreated {
             deployment_id: deployment_id.clone(),
-            url,
-            sandbox_id,
+            sandbox_id: String::new(),
+            runtime: request.runtime.clone(),
+        });
+
+        info!("Static site {} deployed at {}", deployment_id, url);
+        Ok(deployment.to_deploy_response(access_token).await)
+    }
+
+    /// Build the registry record for a deployment
+    fn new_deployment(
+        deployment_id: &str,
+        sandbox_id: &str,
+        url: &str,
+        request: &DeploymentRequest,
+        instances: Vec<String>,
+        api_key: Option<String>,
+    ) -> Deployment {
+        let auto_scale = request.auto_scale.clone().unwrap_or(AutoScaleConfig {
+            scale_down_after_minutes: Some(10),
+            max_instances: Some(1),
+            target_concurrency: Some(10),
+            sticky_sessions: None,
+            idle_ignore_paths: None,
+        });
+
+        Deployment {
+            id: deployment_id.to_string(),
+            sandbox_id: sandbox_id.to_string(),
+            url: url.to_string(),
             status: DeploymentStatus::Running,
             created_at: Utc::now(),
-            runtime: request.runtime,
+            last_accessed: Arc::new(RwLock::new(Utc::now())),
+            runtime: request.runtime.clone(),
             memory_mb: request.memory_limit_mb.unwrap_or(256),
-        })
+            auto_scale,
+            request: request.clone(),
+            instances: Arc::new(RwLock::new(instances)),
+            draining: Arc::new(RwLock::new(Vec::new())),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            next_instance: Arc::new(AtomicUsize::new(0)),
+            scaling: Arc::new(AtomicBool::new(false)),
+            wake_lock: Arc::new(tokio::sync::Mutex::new(())),
+            error: None,
+            failure_reason: None,
+            logs: None,
+            cache: request.cache.as_ref().map(|config| Arc::new(build_cache(config))),
+            limit: ConcurrencyLimit::from_request(request).map(Arc::new),
+            site: None,
+            cors: cors_policy(request).ok().flatten().map(Arc::new),
+            routing: Routing::from_request(request).ok().flatten().map(Arc::new),
+            api_key,
+            access_token_hash: None,
+            traffic: Arc::new(TrafficStats::new()),
+            access_log: Arc::new(AccessLog::new()),
+        }
     }
 
     /// Get deployment information
     pub async fn get_deployment(&self, deployment_id: &str) -> Option<DeploymentResponse> {
         let deployments = self.deployments.read().await;
         if let Some(deployment) = deployments.get(deployment_id) {
-            // Update last accessed time
-            {
-                let mut last_accessed = deployment.last_accessed.write().await;
-                *last_accessed = Utc::now();
-            }
-
-            Some(DeploymentResponse {
-                deployment_id: deployment.id.clone(),
-                url: deployment.url.clone(),
-                sandbox_id: deployment.sandbox_id.clone(),
-                status: deployment.status.clone(),
-                created_at: deployment.created_at,
-                runtime: deployment.runtime.clone(),
-                memory_mb: deployment.memory_mb,
-            })
+            Some(deployment.to_response().await)
         } else {
             None
         }
     }
 
-    /// List all deployments
-    pub async fn list_deployments(&self) -> Vec<DeploymentResponse> {
+    /// Deployments worth backing up, with the request each runs and the hash of its
+    /// access token; failed deployments are left out
+    pub async fn backup_entries(&self) -> Vec<(String, DeploymentRequest, Option<String>)> {
+        self.deployments.read().await.values()
+            .filter(|deployment| deployment.status != DeploymentStatus::Failed)
+            .map(|deployment| (deployment.id.clone(), deployment.request.clone(), deployment.access_token_hash.clone()))
+            .collect()
+    }
+
+    /// Bundle of a deployment's request and files, to import on another instance
+    pub async fn export(&self, deployment_id: &str) -> Result<Vec<u8>> {
+        let request = self.deployments.read().await.get(deployment_id)
+            .map(|deployment| deployment.request.clone())
+            .ok_or_else(|| NotFound(format!("Deployment {} not found", deployment_id)))?;
+        bundle::export(deployment_id, &request)
+    }
+
+    /// Upload the deployment's bundle to the object store and return a presigned URL of it;
+    /// None without an object store
+    pub async fn export_url(&self, deployment_id: &str) -> Result<Option<String>> {
+        let Some(store) = self.sandbox_manager.storage() else {
+            return Ok(None);
+        };
+        let key = bundle::object_key(deployment_id);
+        store.put(&key, self.export(deployment_id).await?, "application/gzip").await?;
+        Ok(Some(store.presigned_url(&key)))
+    }
+
+    /// Page of the deployments matching `query`
+    pub async fn list_deployments(&self, query: &DeploymentListQuery) -> Result<DeploymentList, InvalidInput> {
         let deployments = self.deployments.read().await;
-        deployments.values().map(|d| DeploymentResponse {
-            deployment_id: d.id.clone(),
-            url: d.url.clone(),
-            sandbox_id: d.sandbox_id.clone(),
-            status: d.status.clone(),
-            created_at: d.created_at,
-            runtime: d.runtime.clone(),
-            memory_mb: d.memory_mb,
-        }).collect()
+        let mut responses = Vec::with_capacity(deployments.len());
+        for deployment in deployments.values() {
+            responses.push(deployment.to_response().await);
+        }
+        listing::page(responses, query)
     }
 
     /// Stop and remove a deployment
@@ -294,6 +599,15 @@ impl FaasManager {
             deployment
         };
 
+        if let Some(store) = self.sandbox_manager.storage() {
+            let key = bundle::object_key(deployment_id);
+            tokio::spawn(async move {
+                if let Err(e) = store.delete(&key).await {
+                    warn!("Failed to delete exported bundle {}: {:#}", key, e);
+                }
+            });
+        }
+
         if let Some(deployment) = deployment {
             info!("Undeploying {} - Sandbox: {}, Runtime: {}, Created: {}", 
                   deployment_id, deployment.sandbox_id, deployment.runtime, deployment.created_at);
@@ -302,55 +616,258 @@ impl FaasManager {
             let lifetime = Utc::now() - deployment.created_at;
             info!("Deployment {} was active for {} minutes", deployment_id, lifetime.num_minutes());
             
-            // Stop sandbox
-            info!("Deleting sandbox {} for deployment {}", deployment.sandbox_id, deployment_id);
-            let mut manager = self.sandbox_manager.write().await;
-            match manager.delete_sandbox(&deployment.sandbox_id).await {
-                Ok(()) => {
-                    info!("Sandbox {} deleted successfully", deployment.sandbox_id);
-                }
-                Err(e) => {
-                    error!("Failed to delete sandbox {} for deployment {}: {}", 
-                          deployment.sandbox_id, deployment_id, e);
-                    warn!("Deployment {} removed from registry but sandbox {} cleanup failed", 
-                          deployment_id, deployment.sandbox_id);
-                    // Don't return error here - deployment is already removed from registry
+            // Stop every sandbox serving the deployment
+            for sandbox_id in deployment.all_sandboxes().await {
+                info!("Deleting sandbox {} for deployment {}", sandbox_id, deployment_id);
+                match self.sandbox_manager.delete_sandbox(&sandbox_id).await {
+                    Ok(()) => {
+                        info!("Sandbox {} deleted successfully", sandbox_id);
+                    }
+                    Err(e) => {
+                        error!("Failed to delete sandbox {} for deployment {}: {}", 
+                              sandbox_id, deployment_id, e);
+                        warn!("Deployment {} removed from registry but sandbox {} cleanup failed", 
+                              deployment_id, sandbox_id);
+                        // Don't return error here - deployment is already removed from registry
+                    }
                 }
             }
+            self.remove_addons(deployment_id, &deployment.request).await;
+            self.sandbox_manager.meter().forget_deployment(deployment_id);
+            self.events.publish(EventKind::DeploymentDeleted {
+                deployment_id: deployment_id.to_string(),
+                sandbox_id: deployment.sandbox_id.clone(),
+            });
             
             info!("Deployment {} undeployed successfully", deployment_id);
             Ok(())
         } else {
             error!("Cannot undeploy - Deployment {} not found", deployment_id);
-            Err(anyhow::anyhow!("Deployment {} not found", deployment_id))
+            Err(NotFound(format!("Deployment {} not found", deployment_id)).into())
         }
     }
 
+    /// Files of a static site deployment
+    pub async fn static_site(&self, deployment_id: &str) -> Option<Arc<StaticSite>> {
+        let deployment = self.deployments.read().await.get(deployment_id).cloned()?;
+        deployment.site
+    }
+
+    /// Hash of the token callers of a deployment must present, if it is deployed with one
+    pub async fn access_token_hash(&self, deployment_id: &str) -> Option<String> {
+        self.deployments.read().await.get(deployment_id)?.access_token_hash.clone()
+    }
+
+    /// Cross-origin policy of a deployment, if it sets one
+    pub async fn cors_policy(&self, deployment_id: &str) -> Option<Arc<CorsPolicy>> {
+        self.deployments.read().await.get(deployment_id)?.cors.clone()
+    }
+
+    /// Request rewrites of a deployment, if it sets any
+    pub async fn routing(&self, deployment_id: &str) -> Option<Arc<Routing>> {
+        self.deployments.read().await.get(deployment_id)?.routing.clone()
+    }
+
+    /// Response cache of a deployment, if it has caching enabled
+    pub async fn response_cache(&self, deployment_id: &str) -> Option<Arc<ResponseCache>> {
+        self.deployments.read().await.get(deployment_id)?.cache.clone()
+    }
+
+    /// Traffic counters, access log and activity of a deployment, which the FaaS proxy records its requests in
+    pub async fn traffic(&self, deployment_id: &str) -> Option<ProxiedTraffic> {
+        self.deployments.read().await.get(deployment_id).map(|d| ProxiedTraffic {
+            stats: d.traffic.clone(),
+            access_log: d.access_log.clone(),
+            last_accessed: d.last_accessed.clone(),
+            auto_scale: d.auto_scale.clone(),
+        })
+    }
+
+    /// Newest `limit` requests proxied to a deployment, newest first
+    pub async fn access_logs(&self, deployment_id: &str, limit: usize) -> Option<Vec<AccessLogEntry>> {
+        let access_log = self.deployments.read().await.get(deployment_id)?.access_log.clone();
+        Some(access_log.recent(limit))
+    }
+
+    /// Traffic proxied to a deployment so far
+    pub async fn stats(&self, deployment_id: &str) -> Option<DeploymentStats> {
+        let meter = self.sandbox_manager.meter();
+        self.deployments.read().await.get(deployment_id).map(|deployment| deployment.stats(&meter))
+    }
+
+    /// Concurrency limit the FaaS proxy holds a deployment's requests to, if it sets one
+    pub async fn concurrency_limit(&self, deployment_id: &str) -> Option<Arc<ConcurrencyLimit>> {
+        self.deployments.read().await.get(deployment_id)?.limit.clone()
+    }
+
+    /// Traffic of every deployment, with its number of instances
+    pub async fn all_stats(&self) -> Vec<(DeploymentStats, usize)> {
+        let deployments: Vec<Deployment> = self.deployments.read().await.values().cloned().collect();
+        let meter = self.sandbox_manager.meter();
+        let mut stats = Vec::with_capacity(deployments.len());
+        for deployment in deployments {
+            let instances = deployment.instances.read().await.len();
+            stats.push((deployment.stats(&meter), instances));
+        }
+        stats
+    }
+
     /// Get deployment by ID for proxying
-    pub async fn get_deployment_for_proxy(&self, deployment_id: &str) -> Option<String> {
-        let deployments = self.deployments.read().await;
-        if let Some(deployment) = deployments.get(deployment_id) {
-            // Update last accessed time
-            tokio::spawn({
-                let last_accessed = deployment.last_accessed.clone();
-                async move {
-                    let mut last_accessed = last_accessed.write().await;
-                    *last_accessed = Utc::now();
+    ///
+    /// Wakes deployments that were scaled to zero, picks an instance round-robin, or by
+    /// the caller's `session` with sticky sessions, and starts another one in the
+    /// background when in-flight requests exceed the deployment's target concurrency.
+    pub async fn get_deployment_for_proxy(&self, deployment_id: &str, session: Option<&str>) -> Option<InstanceLease> {
+        let deployment = self.deployments.read().await.get(deployment_id).cloned();
+        if let Some(deployment) = deployment {
+            if deployment.status == DeploymentStatus::Failed {
+                return None;
+            }
+
+            if deployment.instances.read().await.is_empty() {
+                if let Err(e) = self.wake(&deployment).await {
+                    error!("Failed to wake deployment {}: {}", deployment_id, e);
+                    return None;
                 }
-            });
-            
-            Some(deployment.sandbox_id.clone())
+            }
+
+            let instances = deployment.instances.read().await.clone();
+            if instances.is_empty() {
+                return None;
+            }
+
+            let (index, set_cookie) = if deployment.auto_scale.sticky_sessions() {
+                match session {
+                    Some(session) => (affinity::pick(&instances, session), None),
+                    None => {
+                        let session = affinity::new_session();
+                        let path = reqwest::Url::parse(&deployment.url)
+                            .map(|url| url.path().to_string())
+                            .unwrap_or_else(|_| format!("/faas/{}", deployment_id));
+                        (affinity::pick(&instances, &session), Some(affinity::set_cookie(&path, &session)))
+                    }
+                }
+            } else {
+                (deployment.next_instance.fetch_add(1, Ordering::Relaxed) % instances.len(), None)
+            };
+            let in_flight = deployment.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+
+            let wants_more = instances.len() < deployment.auto_scale.max_instances()
+                && in_flight > deployment.auto_scale.target_concurrency() * instances.len();
+            if wants_more && !deployment.scaling.swap(true, Ordering::SeqCst) {
+                info!("Deployment {} has {} requests in flight across {} instances, scaling up",
+                      deployment_id, in_flight, instances.len());
+                let manager = self.clone();
+                let deployment_id = deployment_id.to_string();
+                tokio::spawn(async move {
+                    manager.scale_up(&deployment_id).await;
+                });
+            }
+
+            Some(InstanceLease {
+                sandbox_id: instances[index].clone(),
+                set_cookie,
+                in_flight: deployment.in_flight.clone(),
+            })
         } else {
             None
         }
     }
 
+    /// Start one more sandbox instance for a deployment
+    async fn scale_up(&self, deployment_id: &str) {
+        let deployment = self.deployments.read().await.get(deployment_id).cloned();
+        let deployment = match deployment {
+            Some(deployment) => deployment,
+            None => return,
+        };
+
+        let sandbox_id = Uuid::new_v4().to_string();
+        info!("Scaling up deployment {} with new instance {}", deployment_id, sandbox_id);
+
+        match self.start_instance(&sandbox_id, &deployment, &deployment.request, false).await {
+            Ok(()) => {
+                let instance_count = {
+                    let mut instances = deployment.instances.write().await;
+                    instances.push(sandbox_id.clone());
+                    instances.len()
+                };
+                info!("Deployment {} scaled up to {} instances", deployment_id, instance_count);
+                self.events.publish(EventKind::DeploymentScaledUp {
+                    deployment_id: deployment_id.to_string(),
+                    sandbox_id,
+                    instances: instance_count,
+                });
+            }
+            Err(e) => {
+                error!("Failed to scale up deployment {}: {}", deployment_id, e);
+            }
+        }
+
+        deployment.scaling.store(false, Ordering::SeqCst);
+    }
+
+    /// Create and set up a sandbox serving a deployment from `request`, removing it again
+    /// on failure; replicas are started with `primary` unset
+    async fn start_instance(&self, sandbox_id: &str, deployment: &Deployment, request: &DeploymentRequest, primary: bool) -> Result<()> {
+        let record = DeploymentRecord::new(deployment, request, primary);
+        let sandbox_request = self.create_sandbox_request(sandbox_id, request, deployment.api_key.as_deref(), &record).await?;
+        self.sandbox_manager.create_sandbox(sandbox_request).await?;
+        self.sandbox_manager.meter().deployment_instance(sandbox_id, &deployment.id);
+
+        let setup_result = match self.setup_deployment(sandbox_id, request).await {
+            Ok(()) => self.wait_until_ready(sandbox_id, request).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = setup_result {
+            if let Err(cleanup_err) = self.sandbox_manager.delete_sandbox(sandbox_id).await {
+                error!("Failed to cleanup sandbox {} after setup failure: {}", sandbox_id, cleanup_err);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Take one replica out of rotation for deployments whose load fits in fewer instances
+    async fn scale_down_replicas(&self) {
+        let deployments: Vec<Deployment> = self.deployments.read().await.values().cloned().collect();
+
+        for deployment in deployments {
+            // Replicas drained on the previous tick have had time to finish their requests
+            let drained: Vec<String> = deployment.draining.write().await.drain(..).collect();
+            if !drained.is_empty() {
+                for sandbox_id in drained {
+                    info!("Auto-scale: Deleting drained instance {} of deployment {}", sandbox_id, deployment.id);
+                    if let Err(e) = self.sandbox_manager.delete_sandbox(&sandbox_id).await {
+                        error!("Auto-scale: Failed to delete instance {}: {}", sandbox_id, e);
+                    }
+                }
+            }
+
+            let in_flight = deployment.in_flight.load(Ordering::SeqCst);
+            let mut instances = deployment.instances.write().await;
+            if instances.len() > 1
+                && in_flight <= deployment.auto_scale.target_concurrency() * (instances.len() - 1)
+            {
+                if let Some(sandbox_id) = instances.pop() {
+                    info!("Auto-scale: Draining instance {} of deployment {} ({} requests in flight)",
+                          sandbox_id, deployment.id, in_flight);
+                    deployment.draining.write().await.push(sandbox_id);
+                }
+            }
+        }
+    }
+
     /// Update files in a running deployment
+    ///
+    /// Files are written and dev servers restarted through cloned backend handles, so
+    /// neither the deployment registry nor the sandbox manager is locked meanwhile.
     pub async fn update_files(&self, deployment_id: &str, update_request: FileUpdateRequest) -> Result<()> {
         info!("Starting file update for deployment {}", deployment_id);
         info!("Update request - Files: {}, Restart dev server: {}", 
               update_request.files.len(),
               update_request.restart_dev_server.unwrap_or(true));
+        crate::sandbox::validate_files(Some(&update_request.files))?;
         
         let deployment = {
             let deployments = self.deployments.read().await;
@@ -368,55 +885,66 @@ impl FaasManager {
         };
 
         if let Some(deployment) = deployment {
-            info!("Updating {} files for deployment {} in sandbox {}", 
-                  update_request.files.len(), deployment_id, deployment.sandbox_id);
-            
-            let mut manager = self.sandbox_manager.write().await;
-            
-            // Update files in the container
-            for file in &update_request.files {
-                info!("Adding file {} to sandbox {} (executable: {})", 
-                      file.path, deployment.sandbox_id, file.executable.unwrap_or(false));
+            let instances = deployment.instances.read().await.clone();
+            // A hot-reloading dev server picks the files up by itself
+            let should_restart = match reload::strategy(&deployment.request) {
+                ReloadStrategy::Restart => update_request.restart_dev_server.unwrap_or(true),
+                ReloadStrategy::Hot => update_request.restart_dev_server.unwrap_or(false),
+            };
+            let is_dev_server = deployment.request.dev_server.unwrap_or(false);
+
+            for sandbox_id in &instances {
+                info!("Updating {} files for deployment {} in sandbox {}", 
+                      update_request.files.len(), deployment_id, sandbox_id);
                 
-                if let Err(e) = manager.add_files_to_sandbox(&deployment.sandbox_id, vec![crate::sandbox::SandboxFile {
-                    path: file.path.clone(),
-                    content: file.content.clone(),
-                    is_executable: file.executable,
-                }]).await {
-                    error!("Failed to add file {} to sandbox {}: {}", file.path, deployment.sandbox_id, e);
-                    warn!("Continuing with remaining files despite error");
+                // Record the files on the sandbox
+                for file in &update_request.files {
+                    info!("Adding file {} to sandbox {} (executable: {})", 
+                          file.path, sandbox_id, file.is_executable.unwrap_or(false));
                 }
-            }
+                if let Err(e) = self.sandbox_manager.add_files_to_sandbox(sandbox_id, update_request.files.clone()).await {
+                    error!("Failed to record files of sandbox {}: {}", sandbox_id, e);
+                    warn!("Continuing with the container update despite error");
+                }
+
+                // Update files directly in the running container
+                info!("Updating files directly in running container {}", sandbox_id);
+                if let Err(e) = self.update_container_files(sandbox_id, &update_request.files).await {
+                    error!("Failed to update container files for sandbox {}: {}", sandbox_id, e);
+                    return Err(anyhow::anyhow!("Failed to update container files: {}", e));
+                }
+                info!("Container files updated successfully");
 
-            // Update files directly in the running container
-            info!("Updating files directly in running container {}", deployment.sandbox_id);
-            if let Err(e) = self.update_container_files(&deployment.sandbox_id, &update_request.files).await {
-                error!("Failed to update container files for sandbox {}: {}", deployment.sandbox_id, e);
-                return Err(anyhow::anyhow!("Failed to update container files: {}", e));
+                // Restart dev server if requested (default: true)
+                if should_restart && is_dev_server {
+                    info!("Restarting dev server for deployment {} in sandbox {}", 
+                          deployment_id, sandbox_id);
+                    if let Err(e) = self.restart_dev_server(sandbox_id, &deployment.request).await {
+                        error!("Failed to restart dev server for sandbox {}: {}", sandbox_id, e);
+                        return Err(anyhow::anyhow!("Failed to restart dev server: {}", e));
+                    }
+                    info!("Dev server restarted successfully");
+                } else {
+                    info!("Skipping dev server restart - Requested: {}, Is dev server: {}", 
+                          should_restart, is_dev_server);
+                }
             }
-            info!("Container files updated successfully");
 
-            // Restart dev server if requested (default: true)
-            let should_restart = update_request.restart_dev_server.unwrap_or(true);
-            let is_dev_server = deployment.request.dev_server.unwrap_or(false);
-            
-            if should_restart && is_dev_server {
-                info!("Restarting dev server for deployment {} in sandbox {}", 
-                      deployment_id, deployment.sandbox_id);
-                if let Err(e) = self.restart_dev_server(&deployment.sandbox_id, &deployment.request).await {
-                    error!("Failed to restart dev server for sandbox {}: {}", deployment.sandbox_id, e);
-                    return Err(anyhow::anyhow!("Failed to restart dev server: {}", e));
-                }
-                info!("Dev server restarted successfully");
-            } else {
-                info!("Skipping dev server restart - Requested: {}, Is dev server: {}", 
-                      should_restart, is_dev_server);
+            // Cached responses were produced by the old code
+            if let Some(cache) = &deployment.cache {
+                cache.clear();
             }
 
-            // Update last accessed time
-            {
-                let mut last_accessed = deployment.last_accessed.write().await;
-                *last_accessed = Utc::now();
+            // Remember the new files so instances started later serve the same code
+            if let Some(stored) = self.deployments.write().await.get_mut(deployment_id) {
+                let files = stored.request.files.get_or_insert_with(Vec::new);
+                for file in &update_request.files {
+                    files.retain(|existing| existing.path != file.path);
+                    files.push(file.clone());
+                }
+                if stored.site.is_some() {
+                    stored.site = Some(Arc::new(StaticSite::from_request(&stored.request)?));
+                }
             }
 
             info!("File update completed successfully for deployment {}", deployment_id);
@@ -426,91 +954,359 @@ impl FaasManager {
             Ok(())
         } else {
             error!("Cannot update files - Deployment {} not found", deployment_id);
-            Err(anyhow::anyhow!("Deployment {} not found", deployment_id))
+            Err(NotFound(format!("Deployment {} not found", deployment_id)).into())
+        }
+    }
+
+    /// Rebuild a deployment in a fresh sandbox from its stored request, keeping its ID and URL
+    ///
+    /// Dependencies are installed again and the previous instances are drained once the
+    /// new one passes its health check. If the new sandbox cannot be started, the
+    /// deployment is left as it was and the error returned.
+    pub async fn redeploy(&self, deployment_id: &str) -> Result<DeploymentResponse> {
+        let deployment = self.deployments.read().await.get(deployment_id).cloned()
+            .ok_or_else(|| NotFound(format!("Deployment {} not found", deployment_id)))?;
+        if deployment.site.is_some() {
+            return Err(StatusConflict(format!("Deployment {} is a static site without a sandbox", deployment_id)).into());
+        }
+
+        // Keeps wake-ups and scale-to-zero from racing the instance swap
+        let _wake_guard = deployment.wake_lock.lock().await;
+        let request = self.deployments.read().await
+            .get(deployment_id)
+            .map(|d| d.request.clone())
+            .ok_or_else(|| NotFound(format!("Deployment {} not found", deployment_id)))?;
+
+        let sandbox_id = Uuid::new_v4().to_string();
+        let redeploy_start = std::time::Instant::now();
+        info!("Redeploying {} in new sandbox {}", deployment_id, sandbox_id);
+        if let Err(e) = self.start_instance(&sandbox_id, &deployment, &request, true).await {
+            error!("Failed to redeploy {}: {:#}", deployment_id, e);
+            return Err(e.context(format!("Failed to redeploy {}", deployment_id)));
+        }
+
+        // Old instances finish their requests and are deleted on the next cleanup tick
+        {
+            let mut instances = deployment.instances.write().await;
+            let previous: Vec<String> = instances.drain(..).collect();
+            instances.push(sandbox_id.clone());
+            deployment.draining.write().await.extend(previous);
+        }
+        if let Some(cache) = &deployment.cache {
+            cache.clear();
+        }
+        *deployment.last_accessed.write().await = Utc::now();
+
+        let response = match self.deployments.write().await.get_mut(deployment_id) {
+            Some(stored) => {
+                stored.sandbox_id = sandbox_id.clone();
+                stored.status = DeploymentStatus::Running;
+                stored.error = None;
+                stored.failure_reason = None;
+                stored.logs = None;
+                stored.clone()
+            }
+            None => {
+                // Undeployed while the new sandbox was starting
+                if let Err(e) = self.sandbox_manager.delete_sandbox(&sandbox_id).await {
+                    error!("Failed to delete sandbox {} of undeployed {}: {}", sandbox_id, deployment_id, e);
+                }
+                return Err(NotFound(format!("Deployment {} not found", deployment_id)).into());
+            }
+        };
+
+        info!("Deployment {} redeployed in {:?}", deployment_id, redeploy_start.elapsed());
+        self.events.publish(EventKind::DeploymentRedeployed {
+            deployment_id: deployment_id.to_string(),
+            sandbox_id,
+        });
+        Ok(response.to_response().await)
+    }
+
+    /// Rebuild the deployments served by sandboxes a previous run left, from the records
+    /// their requests carry; returns how many were restored
+    ///
+    /// Runs once the sandbox manager has reattached the sandboxes. The instances are
+    /// health checked again in the background, under the deployment's wake lock so that
+    /// requests arriving meanwhile wait for them; a deployment none of whose instances
+    /// pass is woken by its next request. Deployments another instance of `cluster`
+    /// adopted while this one was down are deleted instead.
+    pub async fn restore_deployments(&self, cluster: &Cluster) -> usize {
+        let recovered = restore::recover(self.sandbox_manager.get_all_sandboxes().await);
+        let mut restored = 0;
+        for found in recovered {
+            let record = found.record;
+            if self.deployments.read().await.contains_key(&record.deployment_id) {
+                continue;
+            }
+            if cluster.owned_elsewhere(ResourceKind::Deployment, &record.deployment_id).await {
+                info!("Deployment {} was adopted by another instance, deleting its instances here", record.deployment_id);
+                for sandbox_id in found.instances.iter().chain(&found.leftovers) {
+                    if let Err(e) = self.sandbox_manager.delete_sandbox(sandbox_id).await {
+                        error!("Failed to delete instance {} of adopted deployment {}: {}", sandbox_id, record.deployment_id, e);
+                    }
+                }
+                continue;
+            }
+            for sandbox_id in &found.leftovers {
+                info!("Deleting leftover instance {} of deployment {}", sandbox_id, record.deployment_id);
+                if let Err(e) = self.sandbox_manager.delete_sandbox(sandbox_id).await {
+                    error!("Failed to delete leftover instance {}: {}", sandbox_id, e);
+                }
+            }
+
+            let sandbox_id = found.instances.first().map(String::as_str).unwrap_or_default();
+            let mut deployment = Self::new_deployment(&record.deployment_id, sandbox_id, &record.url, &record.request, Vec::new(), None);
+            deployment.created_at = record.created_at;
+            deployment.access_token_hash = record.access_token_hash;
+            if found.instances.is_empty() {
+                deployment.status = DeploymentStatus::ScaledToZero;
+            }
+            for sandbox_id in &found.instances {
+                self.sandbox_manager.meter().deployment_instance(sandbox_id, &deployment.id);
+            }
+            let wake_guard = deployment.wake_lock.clone().lock_owned().await;
+            self.deployments.write().await.insert(deployment.id.clone(), deployment.clone());
+            info!("Restored deployment {} with {} instances to check", deployment.id, found.instances.len());
+            restored += 1;
+
+            let manager = self.clone();
+            tokio::spawn(async move {
+                let _wake_guard = wake_guard;
+                // Stopped if the Docker daemon restarted meanwhile
+                if let Err(e) = manager.start_addons(&deployment.id, &deployment.request).await {
+                    warn!("Failed to start add-ons of restored deployment {}: {:#}", deployment.id, e);
+                }
+                manager.recheck_instances(&deployment, found.instances).await;
+            });
+        }
+        restored
+    }
+
+    /// Route a restored deployment to those of its instances that pass the health check
+    /// again, deleting the others
+    async fn recheck_instances(&self, deployment: &Deployment, candidates: Vec<String>) {
+        for sandbox_id in candidates {
+            let healthy = match self.wait_until_ready(&sandbox_id, &deployment.request).await {
+                Ok(()) => true,
+                Err(e) => {
+                    // The dev server may have stopped along with the previous run
+                    warn!("Restored instance {} of deployment {} is not ready, restarting its dev server: {}",
+                          sandbox_id, deployment.id, e);
+                    self.restart_dev_server(&sandbox_id, &deployment.request).await.is_ok()
+                        && self.wait_until_ready(&sandbox_id, &deployment.request).await.is_ok()
+                }
+            };
+            // Undeployed while the instance was checked
+            let registered = self.deployments.read().await.contains_key(&deployment.id);
+            if healthy && registered {
+                info!("Restored instance {} of deployment {} is serving", sandbox_id, deployment.id);
+                deployment.instances.write().await.push(sandbox_id);
+                continue;
+            }
+            if !healthy {
+                error!("Restored instance {} of deployment {} failed its health check", sandbox_id, deployment.id);
+            }
+            if let Err(e) = self.sandbox_manager.delete_sandbox(&sandbox_id).await {
+                error!("Failed to delete instance {} of deployment {}: {}", sandbox_id, deployment.id, e);
+            }
+        }
+
+        let instances = deployment.instances.read().await.clone();
+        if let Some(stored) = self.deployments.write().await.get_mut(&deployment.id) {
+            match instances.first() {
+                Some(primary) => stored.sandbox_id = primary.clone(),
+                None => stored.status = DeploymentStatus::ScaledToZero,
+            }
         }
     }
 
     /// Start cleanup task for idle deployments
     pub async fn start_cleanup_task(&self) {
-        let deployments = self.deployments.clone();
-        let sandbox_manager = self.sandbox_manager.clone();
+        let faas_manager = self.clone();
         
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(60)); // Check every minute
             
             loop {
                 interval.tick().await;
-                
-                let now = Utc::now();
-                let mut to_remove = Vec::new();
-                
-                {
-                    let deployments_read = deployments.read().await;
-                    for (id, deployment) in deployments_read.iter() {
-                        let last_accessed = *deployment.last_accessed.read().await;
-                        let idle_minutes = (now - last_accessed).num_minutes();
-                        let scale_down_after = deployment.auto_scale.scale_down_after_minutes.unwrap_or(10) as i64;
-                        
-                        if idle_minutes > scale_down_after {
-                            to_remove.push((id.clone(), deployment.sandbox_id.clone()));
-                        }
-                    }
+
+                faas_manager.scale_down_replicas().await;
+                faas_manager.scale_idle_to_zero().await;
+            }
+        });
+    }
+
+    /// Stop the sandboxes of idle deployments while keeping their records for wake-on-request
+    async fn scale_idle_to_zero(&self) {
+        let now = Utc::now();
+        let mut to_stop = Vec::new();
+        
+        {
+            let deployments_read = self.deployments.read().await;
+            for (id, deployment) in deployments_read.iter() {
+                // Static sites hold no sandbox to stop
+                if deployment.status != DeploymentStatus::Running || deployment.site.is_some() {
+                    continue;
                 }
-                
-                // Remove idle deployments
-                if !to_remove.is_empty() {
-                    info!("Auto-cleanup: Found {} idle deployments to remove", to_remove.len());
+
+                // Requests still being answered keep the deployment active however long they take
+                if deployment.in_flight.load(Ordering::SeqCst) > 0 {
+                    continue;
                 }
+
+                let last_accessed = *deployment.last_accessed.read().await;
+                let idle_minutes = (now - last_accessed).num_minutes();
+                let scale_down_after = deployment.auto_scale.scale_down_after_minutes.unwrap_or(10) as i64;
                 
-                for (deployment_id, sandbox_id) in to_remove {
-                    info!("Auto-cleanup: Removing idle deployment {} (sandbox: {})", deployment_id, sandbox_id);
-                    
-                    {
-                        let mut deployments_write = deployments.write().await;
-                        if let Some(deployment) = deployments_write.get(&deployment_id) {
-                            let idle_time = (now - *deployment.last_accessed.read().await).num_minutes();
-                            info!("Auto-cleanup: Deployment {} was idle for {} minutes", deployment_id, idle_time);
-                        }
-                        deployments_write.remove(&deployment_id);
+                if idle_minutes > scale_down_after {
+                    to_stop.push((id.clone(), deployment.clone(), idle_minutes));
+                }
+            }
+        }
+        
+        if !to_stop.is_empty() {
+            info!("Auto-cleanup: Found {} idle deployments to scale to zero", to_stop.len());
+        }
+        
+        for (deployment_id, deployment, idle_minutes) in to_stop {
+            // A request is waking this deployment right now; leave it running
+            let _wake_guard = match deployment.wake_lock.try_lock() {
+                Ok(guard) => guard,
+                Err(_) => continue,
+            };
+
+            info!("Auto-cleanup: Scaling idle deployment {} to zero after {} idle minutes", deployment_id, idle_minutes);
+
+            let instances: Vec<String> = {
+                let mut instances = deployment.instances.write().await;
+                let mut draining = deployment.draining.write().await;
+                instances.drain(..).chain(draining.drain(..)).collect()
+            };
+
+            if let Some(stored) = self.deployments.write().await.get_mut(&deployment_id) {
+                stored.status = DeploymentStatus::ScaledToZero;
+            }
+            
+            // Stop every instance
+            for instance_id in &instances {
+                info!("Auto-cleanup: Deleting sandbox {} for deployment {}", instance_id, deployment_id);
+                match self.sandbox_manager.delete_sandbox(instance_id).await {
+                    Ok(()) => {
+                        info!("Auto-cleanup: Successfully deleted sandbox {} for deployment {}", 
+                              instance_id, deployment_id);
                     }
-                    
-                    // Stop sandbox
-                    info!("Auto-cleanup: Deleting sandbox {} for deployment {}", sandbox_id, deployment_id);
-                    let mut manager = sandbox_manager.write().await;
-                    match manager.delete_sandbox(&sandbox_id).await {
-                        Ok(()) => {
-                            info!("Auto-cleanup: Successfully deleted sandbox {} for deployment {}", 
-                                  sandbox_id, deployment_id);
-                        }
-                        Err(e) => {
-                            error!("Auto-cleanup: Failed to delete sandbox {} for deployment {}: {}", 
-                                   sandbox_id, deployment_id, e);
-                        }
+                    Err(e) => {
+                        error!("Auto-cleanup: Failed to delete sandbox {} for deployment {}: {}", 
+                               instance_id, deployment_id, e);
                     }
                 }
             }
+
+            self.events.publish(EventKind::DeploymentScaledDown {
+                deployment_id,
+                sandbox_id: deployment.sandbox_id.clone(),
+                idle_minutes,
+            });
+        }
+    }
+
+    /// Re-create the sandbox of a deployment that was scaled to zero
+    ///
+    /// Concurrent callers wait on the same wake-up; returns once the dev server
+    /// health check of the new instance has passed.
+    async fn wake(&self, deployment: &Deployment) -> Result<()> {
+        let _wake_guard = deployment.wake_lock.lock().await;
+        if !deployment.instances.read().await.is_empty() {
+            // Another request already woke the deployment
+            return Ok(());
+        }
+
+        // Use the stored request, which includes any files updated while idle
+        let request = self.deployments.read().await
+            .get(&deployment.id)
+            .map(|d| d.request.clone())
+            .ok_or_else(|| anyhow::anyhow!("Deployment {} not found", deployment.id))?;
+
+        let sandbox_id = Uuid::new_v4().to_string();
+        let wake_start = std::time::Instant::now();
+        info!("Waking deployment {} in new sandbox {}", deployment.id, sandbox_id);
+
+        self.start_instance(&sandbox_id, deployment, &request, true).await?;
+        deployment.instances.write().await.push(sandbox_id.clone());
+
+        if let Some(stored) = self.deployments.write().await.get_mut(&deployment.id) {
+            stored.sandbox_id = sandbox_id.clone();
+            stored.status = DeploymentStatus::Running;
+        }
+
+        info!("Deployment {} woke up in {:?}", deployment.id, wake_start.elapsed());
+        self.events.publish(EventKind::DeploymentWoken {
+            deployment_id: deployment.id.clone(),
+            sandbox_id,
         });
+        Ok(())
     }
 
-    /// Create sandbox request from deployment request
-    async fn create_sandbox_request(&self, sandbox_id: &str, request: &DeploymentRequest) -> Result<SandboxRequest> {
-        // Convert files
-        let files = if let Some(ref file_specs) = request.files {
-            Some(file_specs.iter().map(|f| crate::sandbox::SandboxFile {
-                path: f.path.clone(),
-                content: f.content.clone(),
-                is_executable: f.executable,
-            }).collect())
-        } else {
-            None
+    /// Network and host names a deployment joins, which another deployment of the network
+    /// must not be reached by already
+    async fn check_network(&self, deployment_id: &str, request: &DeploymentRequest) -> Result<()> {
+        let Some(network) = &request.network else {
+            return Ok(());
         };
+        crate::sandbox::validate_network(network)?;
+        if request.runtime == STATIC_RUNTIME {
+            return Err(InvalidInput("Static sites have no instances to join a network".to_string()).into());
+        }
+        let hostnames = addons::hostnames(deployment_id, request);
+        let deployments = self.deployments.read().await;
+        let members = deployments.values()
+            .filter(|deployment| deployment.id != deployment_id)
+            .filter(|deployment| deployment.request.network.as_ref().is_some_and(|joined| joined.name == network.name));
+        for member in members {
+            if let Some(taken) = addons::hostnames(&member.id, &member.request).into_iter().find(|host| hostnames.contains(host)) {
+                return Err(StatusConflict(format!("Host name {} is taken in network {}", taken, network.name)).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the deployment's add-on containers, or those that stopped
+    async fn start_addons(&self, deployment_id: &str, request: &DeploymentRequest) -> Result<()> {
+        for addon in addons::containers(deployment_id, request) {
+            info!("Starting add-on {} of deployment {}", addon.name, deployment_id);
+            self.sandbox_manager.start_addon(&addon).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_addons(&self, deployment_id: &str, request: &DeploymentRequest) {
+        for addon in &request.addons {
+            let name = addons::container_name(deployment_id, *addon);
+            if let Err(e) = self.sandbox_manager.remove_addon(&name).await {
+                error!("Failed to remove add-on {} of deployment {}: {:#}", name, deployment_id, e);
+            }
+        }
+    }
+
+    /// Create sandbox request from deployment request
+    async fn create_sandbox_request(
+        &self,
+        sandbox_id: &str,
+        request: &DeploymentRequest,
+        api_key: Option<&str>,
+        record: &DeploymentRecord,
+    ) -> Result<SandboxRequest> {
+        let files = registry::with_config_files(request);
 
         // Determine entry point based on runtime
         let entry_point = request.entry_point.clone().unwrap_or_else(|| {
-            match request.runtime.as_str() {
+            match runtime::name(&request.runtime) {
                 "bun" => "bun dev".to_string(),
                 "node" | "nodejs" => "npm run dev".to_string(),
                 "typescript" | "ts" => "bun dev".to_string(),
+                rust::RUNTIME => rust::DEV_COMMAND.to_string(),
+                bash::RUNTIME => bash::RUN_COMMAND.to_string(),
                 _ => "npm run dev".to_string(),
             }
         });
@@ -519,7 +1315,7 @@ impl FaasManager {
             id: sandbox_id.to_string(),
             runtime: request.runtime.clone(),
             code: request.code.clone(),
-            entry_point: Some(entry_point),
+            entry_point: Some(reload::command(request, entry_point)),
             files,
             env_vars: request.env_vars.clone().unwrap_or_default(),
             timeout_ms: 300000, // 5 minutes default
@@ -527,6 +1323,25 @@ impl FaasManager {
             mode: Some(SandboxMode::Persistent),
             dev_server: Some(true),
             install_deps: Some(true),
+            from_snapshot: None,
+            port: request.port,
+            artifacts: None,
+            disk_limit_mb: request.disk_limit_mb,
+            max_processes: request.max_processes,
+            backend: None,
+            security_profile: None,
+            writable_paths: None,
+            gpus: None,
+            labels: request.labels.clone(),
+            api_key: api_key.map(str::to_string),
+            install_proxy: None,
+            mounts: None,
+            bind_mounts: Vec::new(),
+            image: None,
+            registry_credential: None,
+            network: addons::network(&record.deployment_id, request),
+            deployment: record.to_value(),
+            output: None,
         })
     }
 
@@ -535,21 +1350,19 @@ impl FaasManager {
         let start_time = std::time::Instant::now();
         info!("Starting deployment setup for sandbox {}", sandbox_id);
         info!("Executing entry point: {}", request.entry_point.as_ref()
-              .unwrap_or(&match request.runtime.as_str() {
+              .unwrap_or(&match runtime::name(&request.runtime) {
                   "bun" => "bun dev".to_string(),
                   "node" | "nodejs" => "npm run dev".to_string(),
+                  rust::RUNTIME => rust::DEV_COMMAND.to_string(),
+                  bash::RUNTIME => bash::RUN_COMMAND.to_string(),
                   _ => "npm run dev".to_string(),
               }));
         
         // Execute the sandbox to start the web service
-        info!("Acquiring sandbox manager lock...");
-        let mut manager = self.sandbox_manager.write().await;
-        info!("Sandbox manager lock acquired after {:?}", start_time.elapsed());
-        
         // For FaaS, we execute the sandbox to start the service
         info!("Executing sandbox {} to start web service", sandbox_id);
         let exec_start = std::time::Instant::now();
-        let exec_result = match manager.execute_sandbox(sandbox_id).await {
+        let exec_result = match self.sandbox_manager.execute_sandbox(sandbox_id).await {
             Ok(result) => {
                 info!("Sandbox execution completed in {:?} - Success: {}, Exit code: {:?}", 
                       exec_start.elapsed(), result.success, result.exit_code);
@@ -577,26 +1390,65 @@ impl FaasManager {
         Ok(())
     }
 
+    /// Poll the deployment's health check until it passes or times out
+    async fn wait_until_ready(&self, sandbox_id: &str, request: &DeploymentRequest) -> Result<()> {
+        let check = request.health_check.clone().unwrap_or_default();
+        let path = check.path.unwrap_or_else(|| "/".to_string());
+        let port = check.port.or(request.port).unwrap_or(DEFAULT_APP_PORT);
+        let timeout = Duration::from_millis(check.timeout_ms.unwrap_or(30000));
+        let interval = Duration::from_millis(check.interval_ms.unwrap_or(500));
+
+        info!("Waiting for sandbox {} to answer GET {} on port {} (timeout {:?})",
+              sandbox_id, path, port, timeout);
+        let start_time = std::time::Instant::now();
+        loop {
+            let probe = {
+                match self.sandbox_manager.get_backend(sandbox_id) {
+                    Some(backend) => backend.probe_http(sandbox_id, port, &path).await,
+                    None => return Err(anyhow::anyhow!("No sandbox backend available")),
+                }
+            };
+
+            match probe {
+                Ok(()) => {
+                    info!("Sandbox {} passed its health check after {:?}", sandbox_id, start_time.elapsed());
+                    return Ok(());
+                }
+                Err(e) if start_time.elapsed() >= timeout => {
+                    return Err(anyhow::anyhow!(
+                        "Health check failed: GET {} on port {} not ready after {:?}: {}",
+                        path, port, timeout, e
+                    ));
+                }
+                Err(_) => tokio::time::sleep(interval).await,
+            }
+        }
+    }
+
+    /// Tail of the dev server log, if the backend can provide one
+    async fn capture_logs(&self, sandbox_id: &str) -> Option<String> {
+        let backend = self.sandbox_manager.get_backend(sandbox_id)?;
+        match backend.dev_server_logs(sandbox_id, FAILURE_LOG_LINES).await {
+            Ok(logs) if !logs.trim().is_empty() => Some(logs),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Failed to capture dev server logs for sandbox {}: {}", sandbox_id, e);
+                None
+            }
+        }
+    }
+
     /// Update files using the sandbox backend abstraction
-    async fn update_container_files(&self, sandbox_id: &str, files: &[FileSpec]) -> Result<()> {
-        info!("Converting {} FileSpec to SandboxFile format", files.len());
-        
-        // Convert FileSpec to SandboxFile
-        let sandbox_files: Vec<crate::sandbox::SandboxFile> = files.iter().map(|f| {
-            info!("  - Converting file: {} (size: {} bytes)", f.path, f.content.len());
-            crate::sandbox::SandboxFile {
-                path: f.path.clone(),
-                content: f.content.clone(),
-                is_executable: f.executable,
-            }
-        }).collect();
+    async fn update_container_files(&self, sandbox_id: &str, files: &[SandboxFile]) -> Result<()> {
+        for file in files {
+            info!("  - Updating file: {} (size: {} bytes)", file.path, file.content.len());
+        }
         
         // Use sandbox manager to get the backend and call update_files
         info!("Getting sandbox backend for file updates");
-        let manager = self.sandbox_manager.read().await;
-        if let Some(backend) = manager.get_backend() {
+        if let Some(backend) = self.sandbox_manager.get_backend(sandbox_id) {
             info!("Calling backend.update_files for sandbox {}", sandbox_id);
-            match backend.update_files(sandbox_id, &sandbox_files).await {
+            match backend.update_files(sandbox_id, files).await {
                 Ok(()) => {
                     info!("Backend update_files completed successfully");
                     Ok(())
@@ -608,7 +1460,7 @@ impl FaasManager {
             }
         } else {
             error!("No sandbox backend available for file updates");
-            return Err(anyhow::anyhow!("No sandbox backend available"));
+            Err(anyhow::anyhow!("No sandbox backend available"))
         }
     }
 
@@ -619,20 +1471,22 @@ impl FaasManager {
             info!("Using custom entry point: {}", entry_point);
             entry_point.clone()
         } else {
-            let default_cmd = match request.runtime.as_str() {
+            let default_cmd = match runtime::name(&request.runtime) {
                 "bun" => "bun dev".to_string(),
                 "node" | "nodejs" => "npm run dev".to_string(),
+                rust::RUNTIME => rust::DEV_COMMAND.to_string(),
+                bash::RUNTIME => bash::RUN_COMMAND.to_string(),
                 _ => "bun dev".to_string(),
             };
             info!("Using default entry point for runtime {}: {}", request.runtime, default_cmd);
             default_cmd
         };
+        let command = reload::command(request, command);
         
         info!("Restarting process in sandbox {} with command: {}", sandbox_id, command);
         
         // Use sandbox manager to get the backend and call restart_process
-        let manager = self.sandbox_manager.read().await;
-        if let Some(backend) = manager.get_backend() {
+        if let Some(backend) = self.sandbox_manager.get_backend(sandbox_id) {
             info!("Calling backend.restart_process for sandbox {}", sandbox_id);
             match backend.restart_process(sandbox_id, &command).await {
                 Ok(()) => {
@@ -646,7 +1500,7 @@ impl FaasManager {
             }
         } else {
             error!("No sandbox backend available for process restart");
-            return Err(anyhow::anyhow!("No sandbox backend available"));
+            Err(anyhow::anyhow!("No sandbox backend available"))
         }
     }
 }
\ No newline at end of file
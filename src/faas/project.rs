@@ -0,0 +1,135 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::{AutoScaleConfig, DeploymentRequest, ReloadStrategy};
+use voidrun_types::{CorsConfig, HealthCheckConfig, RoutingConfig};
+use crate::sandbox::{file_contents, SandboxFile};
+
+/// Project files read for deployment settings, in order of preference
+pub const PROJECT_CONFIG_FILES: &[&str] = &["voidrun.toml", "voidrun.json"];
+
+/// Deployment settings shipped with the project as `voidrun.toml` or `voidrun.json`
+///
+/// Every field is a default: whatever the deployment request sets wins.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectConfig {
+    pub runtime: Option<String>,
+    pub entry_point: Option<String>,
+    pub port: Option<u16>,
+    pub memory_limit_mb: Option<u32>,
+    /// Environment variables, merged under the request's
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(alias = "scaling")]
+    pub auto_scale: Option<AutoScaleConfig>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub reload_strategy: Option<ReloadStrategy>,
+    pub cors: Option<CorsConfig>,
+    pub routing: Option<RoutingConfig>,
+}
+
+/// A project config file exists but cannot be used
+#[derive(Debug)]
+pub struct InvalidProjectConfig(pub String);
+
+impl std::fmt::Display for InvalidProjectConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid project config: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidProjectConfig {}
+
+impl ProjectConfig {
+    /// Parse the project config among the deployment files, if there is one
+    pub fn from_files(files: &[SandboxFile]) -> Result<Option<Self>> {
+        let file = PROJECT_CONFIG_FILES.iter()
+            .find_map(|name| files.iter().find(|f| f.path.trim_start_matches("./") == *name));
+        let file = match file {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let content = file_contents(file).ok().and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| InvalidProjectConfig(format!("{}: not UTF-8 text", file.path)))?;
+        let config = if file.path.ends_with(".json") {
+            serde_json::from_str(&content).map_err(|e| InvalidProjectConfig(format!("{}: {}", file.path, e)))
+        } else {
+            toml::from_str(&content).map_err(|e| InvalidProjectConfig(format!("{}: {}", file.path, e)))
+        };
+        Ok(Some(config?))
+    }
+
+    /// Fill the settings the request leaves unset
+    pub fn apply(self, request: &mut DeploymentRequest) {
+        if request.runtime.is_empty() {
+            request.runtime = self.runtime.unwrap_or_default();
+        }
+        request.entry_point = request.entry_point.take().or(self.entry_point);
+        request.port = request.port.or(self.port);
+        request.memory_limit_mb = request.memory_limit_mb.or(self.memory_limit_mb);
+        request.auto_scale = request.auto_scale.take().or(self.auto_scale);
+        request.health_check = request.health_check.take().or(self.health_check);
+        request.reload_strategy = request.reload_strategy.or(self.reload_strategy);
+        request.cors = request.cors.take().or(self.cors);
+        request.routing = request.routing.take().or(self.routing);
+
+        if !self.env.is_empty() {
+            let mut env_vars = self.env;
+            env_vars.extend(request.env_vars.take().unwrap_or_default());
+            request.env_vars = Some(env_vars);
+        }
+    }
+}
+
+/// Apply the project config shipped in the request's files and check the result is deployable
+pub fn resolve(mut request: DeploymentRequest) -> Result<DeploymentRequest> {
+    if let Some(config) = ProjectConfig::from_files(request.files.as_deref().unwrap_or_default())? {
+        config.apply(&mut request);
+    }
+    if request.runtime.is_empty() {
+        return Err(InvalidProjectConfig(
+            "no runtime in the request or in voidrun.toml/voidrun.json".to_string(),
+        ).into());
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str) -> SandboxFile {
+        SandboxFile { path: path.to_string(), content: content.to_string(), is_executable: None, encoding: None }
+    }
+
+    #[test]
+    fn test_project_config_defaults() {
+        let config = "runtime = \"bun\"\nport = 8080\n\n[env]\nMODE = \"config\"\nLEVEL = \"debug\"\n\n[scaling]\nmax_instances = 3\n";
+        let mut request = DeploymentRequest {
+            files: Some(vec![file("voidrun.toml", config)]),
+            port: Some(3000),
+            env_vars: Some(HashMap::from([("MODE".to_string(), "request".to_string())])),
+            ..Default::default()
+        };
+        request = resolve(request).unwrap();
+
+        assert_eq!(request.runtime, "bun");
+        assert_eq!(request.port, Some(3000));
+        let env_vars = request.env_vars.unwrap();
+        assert_eq!(env_vars["MODE"], "request");
+        assert_eq!(env_vars["LEVEL"], "debug");
+        assert_eq!(request.auto_scale.unwrap().max_instances(), 3);
+    }
+
+    #[test]
+    fn test_project_config_errors() {
+        let request = DeploymentRequest {
+            files: Some(vec![file("voidrun.json", "{\"runtime\": 1}")]),
+            ..Default::default()
+        };
+        assert!(resolve(request).unwrap_err().downcast_ref::<InvalidProjectConfig>().is_some());
+        assert!(resolve(DeploymentRequest::default()).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Private package registries of a deployment
+//!
+//! Registries are written to the project's `.npmrc`, which npm and recent Bun versions read,
+//! and for Bun runtimes to `bunfig.toml` as well. The files are only added to the sandbox
+//! request, so tokens stay out of the deployment's stored files, its logs and API responses.
+
+use reqwest::Url;
+
+use super::{DeploymentRequest, PackageRegistry};
+use crate::api::error::InvalidInput;
+use crate::runtime;
+use crate::sandbox::{file_contents, SandboxFile};
+
+const NPMRC: &str = ".npmrc";
+const BUNFIG: &str = "bunfig.toml";
+
+/// Reject registries that cannot be written to the config files as given
+pub fn validate(request: &DeploymentRequest) -> Result<(), InvalidInput> {
+    for registry in request.registries.iter().flatten() {
+        registry_url(registry)?;
+        if registry.token.as_deref().is_some_and(|token| token.is_empty() || token.contains(char::is_whitespace)) {
+            return Err(InvalidInput(format!("Registry {} has an empty or malformed token", registry.url)));
+        }
+        for scope in &registry.scopes {
+            let name = scope.strip_prefix('@').unwrap_or_default();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c)) {
+                return Err(InvalidInput(format!("Invalid registry scope {:?}, expected e.g. @acme", scope)));
+            }
+        }
+    }
+    if request.registries.iter().flatten().filter(|registry| registry.scopes.is_empty()).count() > 1 {
+        return Err(InvalidInput("Only one registry may replace the default registry".to_string()));
+    }
+    Ok(())
+}
+
+/// The deployment's files with its registries' config files added
+pub fn with_config_files(request: &DeploymentRequest) -> Option<Vec<SandboxFile>> {
+    let registries = match request.registries.as_deref() {
+        Some(registries) if !registries.is_empty() => registries,
+        _ => return request.files.clone(),
+    };
+    let mut files = request.files.clone().unwrap_or_default();
+    let position = |files: &[SandboxFile], name: &str| {
+        files.iter().position(|f| f.path.trim_start_matches("./") == name)
+    };
+
+    // Settings of a shipped .npmrc are kept, with the registries appended so they take effect
+    let mut npmrc = match position(&files, NPMRC) {
+        Some(index) => {
+            let file = files.remove(index);
+            let content = file_contents(&file).unwrap_or_default();
+            let mut content = String::from_utf8_lossy(&content).into_owned();
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content
+        }
+        None => String::new(),
+    };
+    npmrc.push_str(&npmrc_lines(registries));
+    files.push(config_file(NPMRC, npmrc));
+
+    // A shipped bunfig.toml is left alone; Bun then picks the registries up from .npmrc
+    let bun = matches!(runtime::name(&request.runtime), "bun" | "typescript" | "ts");
+    if bun && position(&files, BUNFIG).is_none() {
+        files.push(config_file(BUNFIG, bunfig(registries)));
+    }
+    Some(files)
+}
+
+fn config_file(path: &str, content: String) -> SandboxFile {
+    SandboxFile { path: path.to_string(), content, is_executable: None, encoding: None }
+}
+
+/// Registry URL with a trailing slash, as npm matches auth settings against it
+fn registry_url(registry: &PackageRegistry) -> Result<Url, InvalidInput> {
+    let invalid = || InvalidInput(format!("Invalid registry URL {:?}", registry.url));
+    let mut url = Url::parse(&registry.url).map_err(|_| invalid())?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none()
+        || !url.username().is_empty() || url.password().is_some() {
+        return Err(invalid());
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+fn npmrc_lines(registries: &[PackageRegistry]) -> String {
+    let mut lines = String::new();
+    for registry in registries {
+        // Validated when the deployment was created
+        let Ok(url) = registry_url(registry) else { continue };
+        if registry.scopes.is_empty() {
+            lines.push_str(&format!("registry={}\n", url));
+        }
+        for scope in &registry.scopes {
+            lines.push_str(&format!("{}:registry={}\n", scope, url));
+        }
+        if let Some(token) = &registry.token {
+            let (_, location) = url.as_str().split_once(':').unwrap_or_default();
+            lines.push_str(&format!("{}:_authToken={}\n", location, token));
+        }
+    }
+    lines
+}
+
+fn bunfig(registries: &[PackageRegistry]) -> String {
+    let registry = |registry: &PackageRegistry, url: &Url| match &registry.token {
+        Some(token) => format!("{{ url = {}, token = {} }}", quoted(url.as_str()), quoted(token)),
+        None => quoted(url.as_str()),
+    };
+
+    let mut default = String::new();
+    let mut scopes = String::new();
+    for entry in registries {
+        let Ok(url) = registry_url(entry) else { continue };
+        if entry.scopes.is_empty() {
+            default = format!("registry = {}\n", registry(entry, &url));
+        }
+        for scope in &entry.scopes {
+            scopes.push_str(&format!("{} = {}\n", quoted(scope.trim_start_matches('@')), registry(entry, &url)));
+        }
+    }
+
+    let mut config = format!("[install]\n{}", default);
+    if !scopes.is_empty() {
+        config.push_str(&format!("\n[install.scopes]\n{}", scopes));
+    }
+    config
+}
+
+/// TOML basic string; JSON string escapes are valid TOML
+fn quoted(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_config_files() {
+        let request = DeploymentRequest {
+            runtime: "bun".to_string(),
+            files: Some(vec![config_file(".npmrc", "save-exact=true".to_string())]),
+            registries: Some(vec![
+                PackageRegistry {
+                    url: "https://npm.pkg.github.com".to_string(),
+                    token: Some("ghp_secret".to_string()),
+                    scopes: vec!["@acme".to_string()],
+                },
+                PackageRegistry { url: "https://mirror.example.com/npm".to_string(), ..Default::default() },
+            ]),
+            ..Default::default()
+        };
+        assert!(validate(&request).is_ok());
+
+        let files = with_config_files(&request).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[0].content,
+            "save-exact=true\n@acme:registry=https://npm.pkg.github.com/\n\
+             //npm.pkg.github.com/:_authToken=ghp_secret\nregistry=https://mirror.example.com/npm/\n"
+        );
+        assert_eq!(
+            files[1].content,
+            "[install]\nregistry = \"https://mirror.example.com/npm/\"\n\n[install.scopes]\n\
+             \"acme\" = { url = \"https://npm.pkg.github.com/\", token = \"ghp_secret\" }\n"
+        );
+        assert!(!format!("{:?}", request).contains("ghp_secret"));
+
+        let invalid = |registry: PackageRegistry| {
+            validate(&DeploymentRequest { registries: Some(vec![registry]), ..Default::default() }).is_err()
+        };
+        assert!(invalid(PackageRegistry { url: "file:///etc".to_string(), ..Default::default() }));
+        assert!(invalid(PackageRegistry {
+            url: "https://r.example.com".to_string(),
+            token: Some("a\nregistry=https://evil".to_string()),
+            scopes: Vec::new(),
+        }));
+        assert!(invalid(PackageRegistry {
+            url: "https://r.example.com".to_string(),
+            token: None,
+            scopes: vec!["acme".to_string()],
+        }));
+    }
+}
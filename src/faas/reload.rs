@@ -0,0 +1,83 @@
+//! How file updates reach a running deployment
+//!
+//! With the default `restart` strategy the service kills the dev server after writing the
+//! files and starts it again. With `hot`, the dev server runs under a watcher the service
+//! configures, `bun --hot` for Bun and nodemon for Node.js, which reloads the code itself.
+
+use super::{DeploymentRequest, ReloadStrategy};
+use crate::api::error::InvalidInput;
+use crate::runtime;
+
+/// Extensions nodemon restarts on
+const WATCHED_EXTENSIONS: &str = "js,mjs,cjs,jsx,ts,tsx,json";
+
+pub fn strategy(request: &DeploymentRequest) -> ReloadStrategy {
+    request.reload_strategy.unwrap_or_default()
+}
+
+/// Reject hot reload for runtimes without a watcher
+pub fn validate(request: &DeploymentRequest) -> Result<(), InvalidInput> {
+    if strategy(request) == ReloadStrategy::Hot && watched(&request.runtime, "true").is_none() {
+        return Err(InvalidInput(format!(
+            "reload_strategy \"hot\" is available for bun and node deployments, not {}",
+            request.runtime
+        )));
+    }
+    Ok(())
+}
+
+/// Dev server command as started for the deployment's strategy
+pub fn command(request: &DeploymentRequest, command: String) -> String {
+    match strategy(request) {
+        ReloadStrategy::Restart => command,
+        ReloadStrategy::Hot => watched(&request.runtime, &command).unwrap_or(command),
+    }
+}
+
+/// `command` under the runtime's watcher
+fn watched(runtime_name: &str, command: &str) -> Option<String> {
+    match runtime::name(runtime_name) {
+        "bun" | "typescript" | "ts" => Some(match command.strip_prefix("bun ") {
+            Some(args) if !args.trim_start().starts_with("--hot") => format!("bun --hot {}", args),
+            Some(_) => command.to_string(),
+            // Commands not run by bun itself restart through bunx's nodemon
+            None => nodemon("bunx", command),
+        }),
+        "node" | "nodejs" => Some(nodemon("npx", command)),
+        _ => None,
+    }
+}
+
+fn nodemon(runner: &str, command: &str) -> String {
+    format!(
+        "{} --yes nodemon --quiet --watch /sandbox --ignore node_modules/ --ext {} --exec '{}'",
+        runner,
+        WATCHED_EXTENSIONS,
+        command.replace('\'', r"'\''")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_commands() {
+        let mut request = DeploymentRequest { runtime: "bun".to_string(), ..Default::default() };
+        assert_eq!(command(&request, "bun dev".to_string()), "bun dev");
+
+        request.reload_strategy = Some(ReloadStrategy::Hot);
+        assert_eq!(command(&request, "bun run index.ts".to_string()), "bun --hot run index.ts");
+        assert_eq!(command(&request, "bun --hot index.ts".to_string()), "bun --hot index.ts");
+
+        request.runtime = "node@20".to_string();
+        assert_eq!(
+            command(&request, "node -e 'x'".to_string()),
+            r"npx --yes nodemon --quiet --watch /sandbox --ignore node_modules/ --ext js,mjs,cjs,jsx,ts,tsx,json --exec 'node -e '\''x'\'''"
+        );
+        assert!(validate(&request).is_ok());
+
+        request.runtime = "rust".to_string();
+        assert!(validate(&request).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Deployments restored after a service restart
+//!
+//! The deployment registry lives in memory, so every sandbox serving a deployment
+//! carries a [`DeploymentRecord`] in its request, which the Docker backend persists in
+//! the container's labels. Once the sandbox manager has reattached the containers a
+//! previous run left, with their ports leased again, the records of their sandboxes
+//! rebuild the deployments, whose instances are health checked again before they serve.
+//!
+//! Instances older than a deployment's newest primary instance were being replaced by
+//! a redeploy and are deleted. Deployments scaled to zero and static sites have no
+//! container and are lost, as are files updated in place after the newest instance
+//! started. Usage of restored deployments is metered anonymously, like that of any
+//! reattached sandbox. Records keep no secrets but env vars: the access token is kept as
+//! its hash and registry tokens are dropped, so new instances of a restored deployment
+//! install from its private registries only once it is redeployed.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Deployment, DeploymentRequest};
+use crate::sandbox::{Sandbox, SandboxRequest, SandboxStatus};
+
+/// What a deployment instance's sandbox request records about its deployment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentRecord {
+    pub deployment_id: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    /// Request as stored when the instance started, with the files updated until then
+    /// and without registry tokens
+    pub request: DeploymentRequest,
+    /// Hash of the token callers must present, with `auth.mode = "token"`; container
+    /// labels are readable by anyone with access to the Docker daemon
+    pub access_token_hash: Option<String>,
+    /// Started by the deploy, a redeploy or a wake-up rather than as a replica
+    pub primary: bool,
+}
+
+impl DeploymentRecord {
+    pub fn new(deployment: &Deployment, request: &DeploymentRequest, primary: bool) -> Self {
+        let mut request = request.clone();
+        for registry in request.registries.iter_mut().flatten() {
+            registry.token = None;
+        }
+        Self {
+            deployment_id: deployment.id.clone(),
+            url: deployment.url.clone(),
+            created_at: deployment.created_at,
+            request,
+            access_token_hash: deployment.access_token_hash.clone(),
+            primary,
+        }
+    }
+
+    pub fn to_value(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+
+    /// Record a sandbox request carries, if the sandbox serves a deployment
+    pub fn of(request: &SandboxRequest) -> Option<Self> {
+        serde_json::from_value(request.deployment.clone()?).ok()
+    }
+}
+
+/// Sandboxes of one deployment found after a restart
+#[derive(Debug)]
+pub struct Recovered {
+    /// Record of the newest primary instance, or of the newest instance without one
+    pub record: DeploymentRecord,
+    /// Running instances to health check, primary first
+    pub instances: Vec<String>,
+    /// Stopped instances and those replaced by a redeploy, to delete
+    pub leftovers: Vec<String>,
+}
+
+/// Group the sandboxes serving deployments by deployment
+pub fn recover(sandboxes: Vec<Sandbox>) -> Vec<Recovered> {
+    let mut by_deployment: HashMap<String, Vec<(Sandbox, DeploymentRecord)>> = HashMap::new();
+    for sandbox in sandboxes {
+        if let Some(record) = DeploymentRecord::of(&sandbox.request) {
+            by_deployment.entry(record.deployment_id.clone()).or_default().push((sandbox, record));
+        }
+    }
+
+    let mut recovered = Vec::with_capacity(by_deployment.len());
+    for mut found in by_deployment.into_values() {
+        // Newest first, primaries ahead of replicas started at the same time
+        found.sort_by(|(a, a_record), (b, b_record)| {
+            (b.created_at, b_record.primary, &b.id).cmp(&(a.created_at, a_record.primary, &a.id))
+        });
+        let current = found.iter().position(|(_, record)| record.primary).unwrap_or(0);
+        let record = found[current].1.clone();
+        let since = found[current].0.created_at;
+
+        let mut instances = Vec::new();
+        let mut leftovers = Vec::new();
+        for (index, (sandbox, _)) in found.into_iter().enumerate() {
+            let running = matches!(sandbox.status, SandboxStatus::Running | SandboxStatus::DevServer);
+            if running && sandbox.created_at >= since {
+                if index == current {
+                    instances.insert(0, sandbox.id);
+                } else {
+                    instances.push(sandbox.id);
+                }
+            } else {
+                leftovers.push(sandbox.id);
+            }
+        }
+        recovered.push(Recovered { record, instances, leftovers });
+    }
+    recovered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faas::{access, issue_access_token, registry, AuthMode, FaasManager, PackageRegistry};
+    use crate::sandbox::backend::SandboxBackendType;
+    use crate::sandbox::persisted::PersistedRequest;
+    use chrono::Duration;
+    use voidrun_types::faas::AuthConfig;
+
+    fn instance(id: &str, deployment_id: &str, primary: bool, minutes_ago: i64, status: SandboxStatus) -> Sandbox {
+        let mut request: SandboxRequest = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "runtime": "bun",
+            "code": "",
+            "timeout_ms": 1000,
+            "memory_limit_mb": 128,
+            "env_vars": {},
+        })).unwrap();
+        request.deployment = DeploymentRecord {
+            deployment_id: deployment_id.to_string(),
+            url: format!("http://localhost:8070/faas/{}", deployment_id),
+            created_at: Utc::now(),
+            request: DeploymentRequest { code: format!("// {}", id), ..Default::default() },
+            access_token_hash: None,
+            primary,
+        }.to_value();
+        let mut sandbox = Sandbox::new(request, SandboxBackendType::Docker);
+        sandbox.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        sandbox.status = status;
+        sandbox
+    }
+
+    #[test]
+    fn test_recover_deployments() {
+        let mut plain = instance("plain", "", true, 0, SandboxStatus::Running);
+        plain.request.deployment = None;
+        let sandboxes = vec![
+            plain,
+            instance("old", "a", true, 30, SandboxStatus::DevServer),
+            instance("old-replica", "a", false, 25, SandboxStatus::Running),
+            instance("new", "a", true, 10, SandboxStatus::DevServer),
+            instance("replica", "a", false, 5, SandboxStatus::Running),
+            instance("stopped", "a", false, 4, SandboxStatus::Terminated),
+            instance("only-replica", "b", false, 5, SandboxStatus::Running),
+        ];
+
+        let mut recovered = recover(sandboxes);
+        recovered.sort_by(|a, b| a.record.deployment_id.cmp(&b.record.deployment_id));
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].record.request.code, "// new");
+        assert_eq!(recovered[0].instances, ["new", "replica"]);
+        let mut leftovers = recovered[0].leftovers.clone();
+        leftovers.sort();
+        assert_eq!(leftovers, ["old", "old-replica", "stopped"]);
+        assert_eq!(recovered[1].instances, ["only-replica"]);
+        assert!(recovered[1].leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_label_keeps_no_tokens() {
+        let request = DeploymentRequest {
+            runtime: "bun".to_string(),
+            auth: Some(AuthConfig { mode: AuthMode::Token }),
+            registries: Some(vec![PackageRegistry {
+                url: "https://npm.pkg.github.com".to_string(),
+                token: Some("ghp_secret".to_string()),
+                scopes: vec!["@acme".to_string()],
+            }]),
+            ..Default::default()
+        };
+        let mut deployment = FaasManager::new_deployment("a", "sandbox", "http://localhost:8070/faas/a", &request, Vec::new(), None);
+        let (access_token, access_token_hash) = issue_access_token(&request, None);
+        deployment.access_token_hash = access_token_hash;
+
+        let mut sandbox = instance("sandbox", "a", true, 0, SandboxStatus::DevServer);
+        sandbox.request.files = registry::with_config_files(&request);
+        sandbox.request.deployment = DeploymentRecord::new(&deployment, &request, true).to_value();
+        let label = serde_json::to_string(&PersistedRequest::from(&sandbox.request)).unwrap();
+        let access_token = access_token.unwrap();
+        assert!(!label.contains("ghp_secret"));
+        assert!(!label.contains(&access_token));
+        assert!(label.contains(&access::hash(&access_token)));
+    }
+}
@@ -0,0 +1,165 @@
+//! Request rewrites of a deployment, applied by the FaaS proxy
+//!
+//! With `routing`, plain HTTP requests can be redirected to HTTPS and the rules rewrite
+//! each request, in order, before it is forwarded: setting or dropping headers, removing
+//! a path prefix, or setting a header to one of the deployment's `env_vars` so that a
+//! credential the service needs never reaches clients. Requests count as plain HTTP
+//! unless a reverse proxy in front of the service reports `X-Forwarded-Proto: https`.
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use super::{DeploymentRequest, RoutingRule};
+use crate::api::error::InvalidInput;
+use crate::api::public_url::FORWARDED_PROTO_HEADER;
+
+/// Headers the proxy sets itself, which rules may not touch
+const RESERVED_HEADERS: [HeaderName; 3] = [header::HOST, header::CONTENT_LENGTH, header::TRANSFER_ENCODING];
+
+#[derive(Debug)]
+enum Rule {
+    SetHeader(HeaderName, HeaderValue),
+    RemoveHeader(HeaderName),
+    StripPrefix(String),
+}
+
+/// Checked routing settings of a deployment
+#[derive(Debug)]
+pub struct Routing {
+    https_redirect: bool,
+    rules: Vec<Rule>,
+}
+
+impl Routing {
+    /// The rewrites a deployment asks for, if it sets any
+    pub fn from_request(request: &DeploymentRequest) -> Result<Option<Self>, InvalidInput> {
+        let Some(config) = &request.routing else {
+            return Ok(None);
+        };
+        let rules = config.rules.iter()
+            .map(|rule| match rule {
+                RoutingRule::AddHeader { name, value } => {
+                    let value = HeaderValue::from_str(value)
+                        .map_err(|_| InvalidInput(format!("Invalid value of routing header {:?}", name)))?;
+                    Ok(Rule::SetHeader(header_name(name)?, value))
+                }
+                RoutingRule::RemoveHeader { name } => Ok(Rule::RemoveHeader(header_name(name)?)),
+                RoutingRule::StripPrefix { prefix } => {
+                    let prefix = prefix.trim_end_matches('/');
+                    if !prefix.starts_with('/') || prefix.contains(['?', '#']) {
+                        return Err(InvalidInput(format!("Invalid routing prefix {:?}: use a path such as /api", prefix)));
+                    }
+                    Ok(Rule::StripPrefix(prefix.to_string()))
+                }
+                RoutingRule::InjectSecret { name, env_var } => {
+                    let secret = request.env_vars.as_ref().and_then(|env_vars| env_vars.get(env_var))
+                        .ok_or_else(|| InvalidInput(format!(
+                            "Routing header {:?} injects env var {:?}, which the deployment does not set", name, env_var
+                        )))?;
+                    let mut value = HeaderValue::from_str(secret)
+                        .map_err(|_| InvalidInput(format!("Env var {:?} is not a valid header value", env_var)))?;
+                    // Kept out of debug output
+                    value.set_sensitive(true);
+                    Ok(Rule::SetHeader(header_name(name)?, value))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(Self { https_redirect: config.https_redirect, rules }))
+    }
+
+    /// Redirect to HTTPS answering a plain HTTP request, with `https_redirect`; `base_url`
+    /// is the service's public URL as the client reached it
+    pub fn redirect(&self, headers: &HeaderMap, base_url: &str, path_and_query: &str) -> Option<Response> {
+        let proto = headers.get(FORWARDED_PROTO_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim);
+        if !self.https_redirect || proto.is_some_and(|proto| proto.eq_ignore_ascii_case("https")) {
+            return None;
+        }
+        let base_url = base_url.strip_prefix("http://").unwrap_or(base_url.trim_start_matches("https://"));
+        let location = HeaderValue::from_str(&format!("https://{}{}", base_url, path_and_query)).ok()?;
+        Some((StatusCode::PERMANENT_REDIRECT, [(header::LOCATION, location)]).into_response())
+    }
+
+    /// Apply the rules to the request's headers and its path below the deployment URL,
+    /// returning the path to forward to
+    pub fn rewrite(&self, headers: &mut HeaderMap, path: &str) -> String {
+        let mut path = if path.starts_with('/') { path.to_string() } else { format!("/{}", path) };
+        for rule in &self.rules {
+            match rule {
+                Rule::SetHeader(name, value) => {
+                    headers.insert(name.clone(), value.clone());
+                }
+                Rule::RemoveHeader(name) => {
+                    headers.remove(name);
+                }
+                Rule::StripPrefix(prefix) => {
+                    if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                        if rest.is_empty() || rest.starts_with('/') {
+                            path = if rest.is_empty() { "/".to_string() } else { rest.to_string() };
+                        }
+                    }
+                }
+            }
+        }
+        path
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, InvalidInput> {
+    match HeaderName::from_bytes(name.as_bytes()) {
+        Ok(name) if !RESERVED_HEADERS.contains(&name) => Ok(name),
+        Ok(name) => Err(InvalidInput(format!("Routing rules cannot change the {} header", name))),
+        Err(_) => Err(InvalidInput(format!("Invalid routing header name {:?}", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use voidrun_types::faas::RoutingConfig;
+
+    fn request(rules: Vec<RoutingRule>) -> DeploymentRequest {
+        DeploymentRequest {
+            env_vars: Some(HashMap::from([("UPSTREAM_TOKEN".to_string(), "s3cret".to_string())])),
+            routing: Some(RoutingConfig { https_redirect: true, rules }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_routing_rules() {
+        let routing = Routing::from_request(&request(vec![
+            RoutingRule::StripPrefix { prefix: "/api/".to_string() },
+            RoutingRule::AddHeader { name: "X-Env".to_string(), value: "prod".to_string() },
+            RoutingRule::RemoveHeader { name: "cookie".to_string() },
+            RoutingRule::InjectSecret { name: "authorization".to_string(), env_var: "UPSTREAM_TOKEN".to_string() },
+        ])).unwrap().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("a=1"));
+        headers.insert("x-env", HeaderValue::from_static("dev"));
+        assert_eq!(routing.rewrite(&mut headers, "api/users"), "/users");
+        assert_eq!(headers["x-env"], "prod");
+        assert_eq!(headers[header::AUTHORIZATION], "s3cret");
+        assert!(!headers.contains_key(header::COOKIE));
+        assert!(!format!("{:?}", routing).contains("s3cret"));
+        assert_eq!(routing.rewrite(&mut headers, "/api"), "/");
+        assert_eq!(routing.rewrite(&mut headers, "/apis"), "/apis");
+
+        let redirect = routing.redirect(&HeaderMap::new(), "http://run.example.com", "/faas/d/x?y=1").unwrap();
+        assert_eq!(redirect.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(redirect.headers()[header::LOCATION], "https://run.example.com/faas/d/x?y=1");
+        let mut forwarded = HeaderMap::new();
+        forwarded.insert(FORWARDED_PROTO_HEADER, HeaderValue::from_static("https"));
+        assert!(routing.redirect(&forwarded, "https://run.example.com", "/faas/d/x").is_none());
+
+        let invalid = |rule: RoutingRule| Routing::from_request(&request(vec![rule])).is_err();
+        assert!(invalid(RoutingRule::InjectSecret { name: "authorization".to_string(), env_var: "MISSING".to_string() }));
+        assert!(invalid(RoutingRule::AddHeader { name: "host".to_string(), value: "evil.com".to_string() }));
+        assert!(invalid(RoutingRule::StripPrefix { prefix: "api".to_string() }));
+        assert!(Routing::from_request(&DeploymentRequest::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{
+    body::Body,
+    http::{header, Method, StatusCode},
+    response::Response,
+};
+use std::collections::HashMap;
+
+use super::project::PROJECT_CONFIG_FILES;
+use super::DeploymentRequest;
+use crate::api::error::InvalidInput;
+use crate::sandbox::file_contents;
+
+/// Deployment runtime served straight from the uploaded files, without a sandbox
+pub const STATIC_RUNTIME: &str = "static";
+
+const INDEX_FILE: &str = "index.html";
+
+/// Files of a static site deployment, served from memory by the FaaS proxy
+#[derive(Debug, Default)]
+pub struct StaticSite {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl StaticSite {
+    /// Site made of the request's files, with `code` as the index page unless the files include one
+    pub fn from_request(request: &DeploymentRequest) -> Result<Self, InvalidInput> {
+        let mut files = HashMap::new();
+        for file in request.files.iter().flatten() {
+            let path = normalize(&file.path);
+            if !PROJECT_CONFIG_FILES.contains(&path) {
+                files.insert(path.to_string(), file_contents(file)?);
+            }
+        }
+        if !request.code.is_empty() {
+            files.entry(INDEX_FILE.to_string()).or_insert_with(|| request.code.clone().into_bytes());
+        }
+        Ok(Self { files })
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Resolve a request path to a file
+    ///
+    /// Directories map to their `index.html`; paths without an extension that match
+    /// no file fall back to the root `index.html` so client-side routers work.
+    fn resolve(&self, path: &str) -> Option<(&str, &[u8])> {
+        let path = normalize(path);
+        let candidates = [
+            path.to_string(),
+            format!("{}/{}", path.trim_end_matches('/'), INDEX_FILE).trim_start_matches('/').to_string(),
+        ];
+        for candidate in &candidates {
+            if let Some((path, content)) = self.files.get_key_value(candidate.as_str()) {
+                return Some((path, content.as_slice()));
+            }
+        }
+
+        let has_extension = path.rsplit('/').next().is_some_and(|name| name.contains('.'));
+        if has_extension {
+            return None;
+        }
+        self.files.get_key_value(INDEX_FILE).map(|(path, content)| (path.as_str(), content.as_slice()))
+    }
+
+    /// Respond to a GET or HEAD request for `path`
+    pub fn serve(&self, method: &Method, path: &str) -> Response {
+        if method != Method::GET && method != Method::HEAD {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        let (file, content) = match self.resolve(path) {
+            Some(found) => found,
+            None => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .body(Body::from("Not Found"))
+                    .unwrap();
+            }
+        };
+
+        let mime = mime_guess::from_path(file).first_or_octet_stream();
+        let content_type = if mime.type_() == mime_guess::mime::TEXT || mime.subtype() == mime_guess::mime::JAVASCRIPT {
+            format!("{}; charset=utf-8", mime)
+        } else {
+            mime.to_string()
+        };
+        let body = if method == Method::HEAD { Body::empty() } else { Body::from(content.to_vec()) };
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, content.len())
+            .body(body)
+            .unwrap()
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::SandboxFile;
+
+    fn site(paths: &[&str]) -> StaticSite {
+        StaticSite::from_request(&DeploymentRequest {
+            runtime: STATIC_RUNTIME.to_string(),
+            files: Some(paths.iter().map(|path| SandboxFile {
+                path: path.to_string(),
+                content: path.to_string(),
+                is_executable: None,
+                encoding: None,
+            }).collect()),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let site = site(&["index.html", "docs/index.html", "./assets/app.js", "voidrun.toml"]);
+        assert_eq!(site.resolve("").unwrap().0, "index.html");
+        assert_eq!(site.resolve("/assets/app.js").unwrap().0, "assets/app.js");
+        assert_eq!(site.resolve("docs/").unwrap().0, "docs/index.html");
+        assert_eq!(site.resolve("docs").unwrap().0, "docs/index.html");
+        // SPA routes fall back to the index page, missing assets do not
+        assert_eq!(site.resolve("settings/profile").unwrap().0, "index.html");
+        assert!(site.resolve("assets/missing.css").is_none());
+        assert!(site.resolve("voidrun.toml").is_none());
+    }
+}
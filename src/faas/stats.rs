@@ -0,0 +1,128 @@
+//! Traffic through the FaaS proxy, per deployment
+//!
+//! Counters cover the deployment's whole lifetime in this process, including wake-ups
+//! and redeploys; latency percentiles are taken over the last [`LATENCY_WINDOW`] requests.
+//! Latency is measured in the proxy, from the request arriving to the response being
+//! ready, so it includes waking a deployment that was scaled to zero.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::DeploymentStats;
+
+/// Requests the latency percentiles are computed over
+pub const LATENCY_WINDOW: usize = 1000;
+
+/// Status classes responses are counted by, `1xx` to `5xx`
+pub const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Running totals of one deployment's proxied requests
+#[derive(Debug)]
+pub struct TrafficStats {
+    since: DateTime<Utc>,
+    totals: Mutex<Totals>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Totals {
+    by_class: [u64; 5],
+    request_bytes: u64,
+    response_bytes: u64,
+    latency_sum: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrafficStats {
+    pub fn new() -> Self {
+        Self { since: Utc::now(), totals: Mutex::new(Totals::default()) }
+    }
+
+    /// Count one answered request
+    pub fn record(&self, status: u16, latency: Duration, request_bytes: u64, response_bytes: u64) {
+        let mut totals = self.totals.lock().unwrap();
+        let class = (status / 100).clamp(1, 5) as usize - 1;
+        totals.by_class[class] += 1;
+        totals.request_bytes += request_bytes;
+        totals.response_bytes += response_bytes;
+        totals.latency_sum += latency;
+        if totals.recent.len() == LATENCY_WINDOW {
+            totals.recent.pop_front();
+        }
+        totals.recent.push_back(latency);
+    }
+
+    pub fn snapshot(&self, deployment_id: &str) -> DeploymentStats {
+        let totals = self.totals.lock().unwrap().clone();
+        let requests: u64 = totals.by_class.iter().sum();
+        let errors = totals.by_class[4];
+
+        let mut recent: Vec<Duration> = totals.recent.into_iter().collect();
+        recent.sort();
+        let percentile = |p: f64| -> Option<f64> {
+            let index = ((recent.len() as f64 * p).ceil() as usize).checked_sub(1)?;
+            recent.get(index).map(|latency| latency.as_secs_f64() * 1000.0)
+        };
+
+        DeploymentStats {
+            deployment_id: deployment_id.to_string(),
+            since: self.since,
+            requests,
+            errors,
+            error_rate: if requests == 0 { 0.0 } else { errors as f64 / requests as f64 },
+            responses: STATUS_CLASSES.iter().zip(totals.by_class)
+                .map(|(class, count)| (class.to_string(), count))
+                .collect(),
+            latency_p50_ms: percentile(0.5),
+            latency_p95_ms: percentile(0.95),
+            latency_sum_ms: totals.latency_sum.as_secs_f64() * 1000.0,
+            request_bytes: totals.request_bytes,
+            response_bytes: totals.response_bytes,
+            queued_requests: 0,
+            rejected_requests: 0,
+            instance_seconds: 0.0,
+            memory_mb_seconds: 0.0,
+            estimated_cost: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_stats() {
+        let stats = TrafficStats::new();
+        assert_eq!(stats.snapshot("d").latency_p50_ms, None);
+
+        for ms in 1..=100 {
+            let status = if ms % 10 == 0 { 502 } else { 200 };
+            stats.record(status, Duration::from_millis(ms), 10, 100);
+        }
+        stats.record(404, Duration::from_millis(1), 0, 0);
+
+        let snapshot = stats.snapshot("d");
+        assert_eq!(snapshot.requests, 101);
+        assert_eq!(snapshot.errors, 10);
+        assert_eq!(snapshot.responses["2xx"], 90);
+        assert_eq!(snapshot.responses["4xx"], 1);
+        assert_eq!(snapshot.request_bytes, 1000);
+        assert_eq!(snapshot.response_bytes, 10000);
+        assert_eq!(snapshot.latency_p50_ms, Some(50.0));
+        assert_eq!(snapshot.latency_p95_ms, Some(95.0));
+
+        for _ in 0..LATENCY_WINDOW {
+            stats.record(200, Duration::from_millis(7), 0, 0);
+        }
+        assert_eq!(stats.snapshot("d").latency_p95_ms, Some(7.0));
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::api::error::InvalidInput;
+use crate::api::AppState;
+use crate::faas::project::InvalidProjectConfig;
+use crate::faas::{DeploymentRequest, FaasManager};
+use crate::metering::{ApiKey, API_KEY_HEADER};
+use crate::runtime::{self, UnsupportedRuntime};
+use crate::sandbox::backend::BackendUnavailable;
+use crate::sandbox::{CapacityExceeded, FileEncoding, OutputChunk, SandboxFile, SandboxMode, SandboxRequest, ShuttingDown};
+
+pub mod proto {
+    tonic::include_proto!("voidrun.v1");
+}
+
+use proto::execute_event::Event;
+use proto::voidrun_server::{Voidrun, VoidrunServer};
+use proto::{
+    CreateSandboxRequest, DeployRequest, Deployment, ExecuteEvent, ExecuteRequest, ExecuteResponse,
+    SandboxInfo,
+};
+
+/// gRPC front-end sharing the managers behind the REST API
+pub struct VoidrunService {
+    sandbox_manager: AppState,
+    faas_manager: Arc<FaasManager>,
+}
+
+impl VoidrunService {
+    pub fn new(sandbox_manager: AppState, faas_manager: Arc<FaasManager>) -> Self {
+        Self {
+            sandbox_manager,
+            faas_manager,
+        }
+    }
+
+    pub fn into_server(self) -> VoidrunServer<Self> {
+        VoidrunServer::new(self)
+    }
+
+    async fn execute_one_shot(&self, request: SandboxRequest) -> Result<ExecuteResponse, Status> {
+        let sandbox_id = request.id.clone();
+        match self.sandbox_manager.execute_sandbox_direct(request).await {
+            Ok(result) => {
+                let usage = result.usage.unwrap_or_default();
+                Ok(ExecuteResponse {
+                    sandbox_id,
+                    success: result.success,
+                    stdout: result.stdout,
+                    stderr: result.stderr,
+                    exit_code: result.exit_code,
+                    execution_time_ms: result.execution_time_ms,
+                    peak_memory_bytes: usage.peak_memory_bytes,
+                    cpu_time_ms: usage.cpu_time_ms,
+                    stdout_bytes: usage.stdout_bytes,
+                    stderr_bytes: usage.stderr_bytes,
+                    termination_reason: result.termination_reason
+                        .map(|reason| reason.as_str().to_string())
+                        .unwrap_or_default(),
+                })
+            }
+            Err(e) if e.downcast_ref::<ShuttingDown>().is_some() => Err(Status::unavailable(e.to_string())),
+            Err(e) if e.downcast_ref::<UnsupportedRuntime>().is_some()
+                || e.downcast_ref::<BackendUnavailable>().is_some()
+                || e.downcast_ref::<InvalidInput>().is_some() => {
+                Err(Status::invalid_argument(e.to_string()))
+            }
+            Err(e) => {
+                error!("[GRPC] Execution in sandbox {} failed: {}", sandbox_id, e);
+                Err(Status::internal(format!("Execution failed: {}", e)))
+            }
+        }
+    }
+}
+
+fn sandbox_files(files: Vec<proto::File>) -> Option<Vec<SandboxFile>> {
+    if files.is_empty() {
+        return None;
+    }
+    Some(files.into_iter().map(|f| SandboxFile {
+        path: f.path,
+        content: f.content,
+        is_executable: Some(f.executable),
+        encoding: f.base64.then_some(FileEncoding::Base64),
+    }).collect())
+}
+
+/// Key the caller is metered to, from the same metadata as the REST API's headers
+fn api_key<T>(request: &Request<T>) -> Option<String> {
+    let value = |name| request.metadata().get(name).and_then(|value| value.to_str().ok());
+    ApiKey::parse(value(API_KEY_HEADER), value("authorization")).0
+}
+
+fn one_shot_request(request: Request<ExecuteRequest>) -> SandboxRequest {
+    let api_key = api_key(&request);
+    let req = request.into_inner();
+    SandboxRequest {
+        id: Uuid::new_v4().to_string(),
+        timeout_ms: req.timeout_ms.unwrap_or_else(|| runtime::default_timeout_ms(&req.runtime)),
+        memory_limit_mb: req.memory_limit_mb.unwrap_or_else(|| runtime::default_memory_limit_mb(&req.runtime)),
+        runtime: req.runtime,
+        code: req.code,
+        entry_point: req.entry_point,
+        env_vars: req.env_vars.into_iter().collect(),
+        files: sandbox_files(req.files),
+        mode: Some(SandboxMode::OneShot),
+        install_deps: req.install_deps,
+        dev_server: None,
+        from_snapshot: None,
+        port: None,
+        artifacts: None,
+        disk_limit_mb: None,
+        max_processes: None,
+        backend: None,
+        security_profile: None,
+        writable_paths: None,
+        gpus: None,
+        labels: HashMap::new(),
+        api_key,
+        install_proxy: None,
+        mounts: None,
+        bind_mounts: Vec::new(),
+        image: None,
+        registry_credential: None,
+        network: None,
+        deployment: None,
+        output: None,
+    }
+}
+
+fn port_from_proto(port: Option<u32>) -> Result<Option<u16>, String> {
+    port.map(|port| u16::try_from(port).map_err(|_| format!("Invalid port {}", port)))
+        .transpose()
+}
+
+fn event(event: Event) -> ExecuteEvent {
+    ExecuteEvent { event: Some(event) }
+}
+
+#[tonic::async_trait]
+impl Voidrun for VoidrunService {
+    async fn execute(&self, request: Request<ExecuteRequest>) -> Result<Response<ExecuteResponse>, Status> {
+        let sandbox_request = one_shot_request(request);
+        info!("[GRPC] Execute - Runtime: {}, Sandbox: {}", sandbox_request.runtime, sandbox_request.id);
+        self.execute_one_shot(sandbox_request).await.map(Response::new)
+    }
+
+    type ExecuteStreamStream = ReceiverStream<Result<ExecuteEvent, Status>>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let mut sandbox_request = one_shot_request(request);
+        info!("[GRPC] ExecuteStream - Runtime: {}, Sandbox: {}", sandbox_request.runtime, sandbox_request.id);
+
+        let (sender, receiver) = mpsc::channel(16);
+        let (output, mut chunks) = mpsc::unbounded_channel();
+        sandbox_request.output = Some(output);
+        let service = Self::new(self.sandbox_manager.clone(), self.faas_manager.clone());
+        tokio::spawn(async move {
+            if sender.send(Ok(event(Event::Started(sandbox_request.id.clone())))).await.is_err() {
+                return;
+            }
+
+            // Output is forwarded as the backend reads it, while the execution runs
+            let (mut streamed_stdout, mut streamed_stderr) = (false, false);
+            let mut forward = |chunk| match chunk {
+                OutputChunk::Stdout(text) => {
+                    streamed_stdout = true;
+                    event(Event::Stdout(text))
+                }
+                OutputChunk::Stderr(text) => {
+                    streamed_stderr = true;
+                    event(Event::Stderr(text))
+                }
+            };
+            let execution = service.execute_one_shot(sandbox_request);
+            tokio::pin!(execution);
+            let result = loop {
+                tokio::select! {
+                    result = &mut execution => break result,
+                    Some(chunk) = chunks.recv() => {
+                        if sender.send(Ok(forward(chunk))).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            };
+            while let Ok(chunk) = chunks.try_recv() {
+                let _ = sender.send(Ok(forward(chunk))).await;
+            }
+            let result = match result {
+                Ok(result) => result,
+                Err(status) => {
+                    let _ = sender.send(Err(status)).await;
+                    return;
+                }
+            };
+
+            // Backends that cannot forward output return it at the end
+            if !streamed_stdout && !result.stdout.is_empty() {
+                let _ = sender.send(Ok(event(Event::Stdout(result.stdout.clone())))).await;
+            }
+            if !streamed_stderr && !result.stderr.is_empty() {
+                let _ = sender.send(Ok(event(Event::Stderr(result.stderr.clone())))).await;
+            }
+            let _ = sender.send(Ok(event(Event::Finished(result)))).await;
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn create_sandbox(
+        &self,
+        request: Request<CreateSandboxRequest>,
+    ) -> Result<Response<SandboxInfo>, Status> {
+        let api_key = api_key(&request);
+        let req = request.into_inner();
+        let sandbox_id = Uuid::new_v4().to_string();
+        let timeout_ms = req.timeout_ms.unwrap_or_else(|| runtime::default_timeout_ms(&req.runtime));
+        let memory_limit_mb = req.memory_limit_mb.unwrap_or_else(|| runtime::default_memory_limit_mb(&req.runtime));
+
+        let sandbox_request = SandboxRequest {
+            id: sandbox_id.clone(),
+            runtime: req.runtime.clone(),
+            code: req.code,
+            entry_point: req.entry_point,
+            timeout_ms,
+            memory_limit_mb,
+            env_vars: req.env_vars.into_iter().collect(),
+            files: sandbox_files(req.files),
+            mode: req.mode.as_deref().map(|m| match m {
+                "persistent" => SandboxMode::Persistent,
+                _ => SandboxMode::OneShot,
+            }),
+            install_deps: req.install_deps,
+            dev_server: req.dev_server,
+            from_snapshot: req.from_snapshot,
+            port: port_from_proto(req.port).map_err(Status::invalid_argument)?,
+            artifacts: None,
+            disk_limit_mb: None,
+            max_processes: None,
+            backend: None,
+            security_profile: None,
+            writable_paths: None,
+            gpus: None,
+            labels: HashMap::new(),
+            api_key,
+            install_proxy: None,
+            mounts: None,
+            bind_mounts: Vec::new(),
+            image: None,
+            registry_credential: None,
+            network: None,
+            deployment: None,
+            output: None,
+        };
+
+        info!("[GRPC] CreateSandbox - Runtime: {}, Sandbox: {}", req.runtime, sandbox_id);
+        match self.sandbox_manager.create_sandbox(sandbox_request).await {
+            Ok(()) => Ok(Response::new(SandboxInfo {
+                id: sandbox_id,
+                status: "created".to_string(),
+                runtime: req.runtime,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                timeout_ms,
+                memory_limit_mb,
+            })),
+            Err(e) if e.downcast_ref::<CapacityExceeded>().is_some() => {
+                Err(Status::resource_exhausted(e.to_string()))
+            }
+            Err(e) if e.downcast_ref::<ShuttingDown>().is_some() => Err(Status::unavailable(e.to_string())),
+            Err(e) if e.downcast_ref::<UnsupportedRuntime>().is_some()
+                || e.downcast_ref::<BackendUnavailable>().is_some()
+                || e.downcast_ref::<InvalidInput>().is_some() => {
+                Err(Status::invalid_argument(e.to_string()))
+            }
+            Err(e) => {
+                error!("[GRPC] Failed to create sandbox {}: {}", sandbox_id, e);
+                Err(Status::internal(format!("Failed to create sandbox: {}", e)))
+            }
+        }
+    }
+
+    async fn deploy(&self, request: Request<DeployRequest>) -> Result<Response<Deployment>, Status> {
+        let api_key = api_key(&request);
+        let req = request.into_inner();
+        let deployment_request = DeploymentRequest {
+            runtime: req.runtime,
+            code: req.code,
+            files: sandbox_files(req.files),
+            env_vars: if req.env_vars.is_empty() { None } else { Some(req.env_vars.into_iter().collect()) },
+            memory_limit_mb: req.memory_limit_mb,
+            disk_limit_mb: None,
+            max_processes: None,
+            entry_point: req.entry_point,
+            auto_scale: None,
+            max_concurrent_requests: None,
+            max_queued_requests: None,
+            dev_server: req.dev_server,
+            port: port_from_proto(req.port).map_err(Status::invalid_argument)?,
+            health_check: None,
+            cache: None,
+            auth: None,
+            reload_strategy: None,
+            registries: None,
+            labels: HashMap::new(),
+            cors: None,
+            routing: None,
+            network: None,
+            addons: Vec::new(),
+        };
+
+        info!("[GRPC] Deploy - Runtime: {}", deployment_request.runtime);
+        match self.faas_manager.deploy(deployment_request, api_key, self.faas_manager.base_url()).await {
+            Ok(deployment) => Ok(Response::new(Deployment {
+                deployment_id: deployment.deployment_id,
+                url: deployment.url,
+                sandbox_id: deployment.sandbox_id,
+                status: format!("{:?}", deployment.status),
+                created_at: deployment.created_at.to_rfc3339(),
+                runtime: deployment.runtime,
+                memory_mb: deployment.memory_mb,
+                instances: deployment.instances as u64,
+                error: deployment.error,
+                logs: deployment.logs,
+                failure_reason: deployment.failure_reason.map(|reason| reason.as_str().to_string()),
+            })),
+            Err(e) if e.downcast_ref::<InvalidProjectConfig>().is_some()
+                || e.downcast_ref::<UnsupportedRuntime>().is_some()
+                || e.downcast_ref::<InvalidInput>().is_some() => {
+                Err(Status::invalid_argument(e.to_string()))
+            }
+            Err(e) => {
+                error!("[GRPC] Failed to deploy function: {:#}", e);
+                Err(Status::internal(format!("Failed to deploy function: {}", e)))
+            }
+        }
+    }
+}
@@ -434,6 +434,10 @@ POST /faas/deploy
   "entry_point": "bun dev"
 }
 
+# Or start from a built-in template (see GET /templates)
+POST /faas/deploy?template=express-bun
+{}
+
 # Access your deployed service
 GET /faas/{deployment_id}/
 
@@ -1,4 +1,16 @@
+pub mod alerts;
 pub mod api;
+pub mod cluster;
 pub mod config;
+pub mod coordination;
+pub mod events;
+pub mod metering;
+pub mod notify;
+/// Header handling the proxy shares with cluster forwarding
+pub mod proxy {
+    pub mod headers;
+}
 pub mod runtime;
-pub mod sandbox;
\ No newline at end of file
+pub mod sandbox;
+pub mod security;
+pub mod storage;
\ No newline at end of file
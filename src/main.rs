@@ -5,35 +5,64 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::signal;
-use tokio::sync::RwLock;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
 use tower::ServiceBuilder;
 use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 use axum::{
-    extract::ConnectInfo,
-    http::Request,
+    extract::{ConnectInfo, DefaultBodyLimit, State},
+    http::{header, HeaderValue, Request},
     middleware::{self, Next},
-    response::Response as AxumResponse,
+    response::{IntoResponse, Response as AxumResponse},
+    Extension,
 };
 use std::time::Instant;
 use std::net::SocketAddr;
 
 mod admin;
+mod alerts;
 mod api;
+mod backup;
+mod cluster;
 mod config;
+mod coordination;
+mod events;
 mod faas;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod homepage;
+mod metering;
+mod metrics;
+mod notify;
 mod proxy;
+mod reload;
 mod runtime;
 mod sandbox;
+mod security;
+mod storage;
+mod templates;
 
 use admin::create_admin_router;
+use backup::Backups;
+use storage::ObjectStore;
+use alerts::Alerts;
+use cluster::{create_cluster_router, Cluster};
 use api::create_router;
+use api::cors::api_layer as api_cors_layer;
+use api::error::ApiError;
+use api::public_url::PublicUrl;
 use config::Config;
+use coordination::{IdempotencyKeys, RateLimiter, StoreKind};
+use events::create_events_router;
 use faas::handlers::{FaasState, create_faas_router};
 use homepage::homepage;
 use proxy::{ProxyState, create_proxy_router};
+use metering::{ApiKey, ANONYMOUS};
+use metrics::{create_metrics_router, spawn_sampler};
+use notify::Notifier;
+use reload::{ConfigReloader, SharedConfig};
 use sandbox::manager::SandboxManager;
 
 // Nginx-style access log middleware
@@ -44,7 +73,13 @@ async fn access_log_middleware(
 ) -> AxumResponse {
     let start = Instant::now();
     let method = req.method().clone();
-    let uri = req.uri().clone();
+    // Deployment access tokens in the query stay out of the log
+    let uri = if faas::access::from_query(req.uri().query()).is_some() {
+        let query = faas::access::strip_token(req.uri().query()).map(|query| format!("?{}", query)).unwrap_or_default();
+        format!("{}{}", req.uri().path(), query)
+    } else {
+        req.uri().to_string()
+    };
     let version = req.version();
     let user_agent = req.headers()
         .get("user-agent")
@@ -85,7 +120,7 @@ async fn access_log_middleware(
     response
 }
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(name = "sandbox-service")]
 #[command(about = "A secure sandbox service for running TypeScript/Bun/Node.js code")]
 struct Args {
@@ -102,74 +137,241 @@ struct Args {
     backend: Option<String>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-    
-    let mut config = if let Some(config_path) = args.config {
-        Config::from_file(&config_path)?
+/// Read the configuration file, or the environment without one, apply command line overrides and validate the result
+fn load_config(args: &Args) -> Result<Config> {
+    let mut config = if let Some(config_path) = &args.config {
+        Config::from_file(config_path)?
     } else {
-        Config::from_env()
+        Config::from_env()?
     };
 
-    if let Some(host) = args.host {
-        config.server.host = host;
+    if let Some(host) = &args.host {
+        config.server.host = host.clone();
     }
     
     if let Some(port) = args.port {
         config.server.port = port;
     }
     
-    if let Some(backend) = args.backend {
-        config.sandbox.backend = match backend.to_lowercase().as_str() {
-            "docker" => sandbox::backend::SandboxBackendType::Docker,
-            "nsjail" => sandbox::backend::SandboxBackendType::Nsjail,
-            _ => {
-                warn!("Unknown backend '{}', using nsjail", backend);
-                sandbox::backend::SandboxBackendType::Nsjail
-            }
-        };
+    if let Some(backend) = &args.backend {
+        config.sandbox.backend = backend.parse()?;
     }
 
-    init_tracing(&config.logging.level)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Turn away callers past `server.rate_limit_per_minute`, read per request so reloads apply
+///
+/// Callers are told apart by API key, or by address when they send none.
+async fn rate_limit_middleware(
+    State((limiter, config)): State<(RateLimiter, SharedConfig)>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> AxumResponse {
+    let Some(per_minute) = config.load().server.rate_limit_per_minute else {
+        return next.run(req).await;
+    };
+    // Forwarded requests were counted by the instance that received them
+    if !is_rate_limited(req.uri().path()) || req.headers().contains_key(cluster::FORWARDED_HEADER) {
+        return next.run(req).await;
+    }
+
+    let caller = match ApiKey::from_headers(req.headers()).0 {
+        Some(api_key) => api_key,
+        None => format!("{}@{}", ANONYMOUS, addr.ip()),
+    };
+    match limiter.allow(&caller, per_minute).await {
+        Ok(true) => next.run(req).await,
+        Ok(false) => {
+            let message = format!("Rate limit of {} requests per minute exceeded", per_minute);
+            let mut response = ApiError::TooManyRequests(message).into_response();
+            let retry_after = 60 - chrono::Utc::now().timestamp() % 60;
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+        Err(e) => {
+            warn!("Rate limiter unavailable, letting the request through: {}", e);
+            next.run(req).await
+        }
+    }
+}
+
+/// Whether requests to `path` count against the rate limit: API calls do, health checks,
+/// metrics scrapes and traffic proxied to sandboxes and deployments do not
+fn is_rate_limited(path: &str) -> bool {
+    let mut segments = path.trim_start_matches('/').split('/');
+    match (segments.next(), segments.next()) {
+        (Some("health" | "metrics" | "proxy"), _) => false,
+        (Some("faas"), Some("deploy" | "deployments")) => true,
+        (Some("faas"), Some(_)) => false,
+        _ => true,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = load_config(&args)?;
+
+    let set_log_level = init_tracing(&config.logging.level)?;
 
     info!("Starting sandbox service with backend: {:?}", config.sandbox.backend);
 
-    let sandbox_manager = SandboxManager::new(config.sandbox.backend.clone()).await?;
-    let app_state = Arc::new(RwLock::new(sandbox_manager));
+    // Port leases, idempotency keys and rate limits; shared with other instances in Redis
+    let coordinator = coordination::connect(config.store, config.redis_url.as_deref()).await?;
+    let mut port_allocator = sandbox::PortAllocator::new(
+        config.sandbox.port_range_start,
+        config.sandbox.port_range_end,
+    );
+    if config.store == StoreKind::Redis {
+        info!("Coordinating ports, idempotency keys and rate limits through Redis");
+        port_allocator = port_allocator.with_coordinator(coordinator.clone());
+    }
+    let storage = config.s3.clone().map(ObjectStore::new).transpose()?.map(Arc::new);
+    if let Some(store) = &storage {
+        info!("Storing artifacts, snapshots and bundles in S3 bucket {}", store.bucket());
+    }
+    let docker_hosts = &config.sandbox.docker_hosts;
+    let sandbox_manager = SandboxManager::new(config.sandbox.backend.clone(), port_allocator, config.sandbox.security.clone(), docker_hosts).await?
+        .with_backends(&config.sandbox.extra_backends, docker_hosts).await
+        .with_limits(config.sandbox.capacity_limits())
+        .with_orphan_grace_period(config.sandbox.orphan_grace_period_seconds)
+        .with_finished_sandbox_ttl(config.sandbox.finished_sandbox_ttl_seconds)
+        .with_http_proxy(config.sandbox.http_proxy.clone())
+        .with_allowed_mounts(config.sandbox.allowed_mounts.clone())
+        .with_images(config.sandbox.image_policy())
+        .with_result_cache(config.sandbox.result_cache_ttl_seconds)
+        .with_pricing(config.pricing.clone())
+        .with_storage(storage.clone());
+    let reattached = sandbox_manager.reattach_sandboxes().await;
+    if reattached > 0 {
+        info!("Reattached {} sandboxes left by a previous run", reattached);
+    }
+    let events = sandbox_manager.events();
+    Arc::new(Notifier::new(&config.notifications)).start(&events);
+    let port_allocator = sandbox_manager.port_allocator();
+    if config.store == StoreKind::Redis {
+        spawn_lease_renewal(port_allocator.clone());
+    }
+    let cancellations = sandbox_manager.cancellations();
+    let meter = sandbox_manager.meter();
+    let app_state = Arc::new(sandbox_manager);
+    spawn_orphan_collector(app_state.clone(), config.sandbox.cleanup_interval_seconds);
+    spawn_retention_sweep(app_state.clone());
+
+    // Log level and capacity limits follow the configuration on SIGHUP or an admin request
+    let shared_config = Arc::new(arc_swap::ArcSwap::from_pointee(config.clone()));
+    let config_source = match &args.config {
+        Some(path) => format!("file {}", path.display()),
+        None => "environment".to_string(),
+    };
+    let reloader = Arc::new(ConfigReloader::new(
+        shared_config.clone(),
+        config_source,
+        move || load_config(&args),
+        set_log_level,
+        app_state.clone(),
+    ));
+    #[cfg(unix)]
+    spawn_reload_on_sighup(reloader.clone())?;
     
     // Create FaaS state
     let base_url = format!("http://{}:{}", config.server.host, config.server.port);
-    let faas_state = FaasState::new(app_state.clone(), base_url);
+    let public_url = PublicUrl::new(config.server.public_url.clone(), base_url.clone());
+
+    // Share ownership with the other instances and forward requests for theirs
+    let cluster = Cluster::connect(&config.cluster, &base_url, config.store, config.redis_url.as_deref()).await?
+        .with_forwarding(&config.proxy, config.server.max_request_body_mb * 1024 * 1024);
+    let idempotency = IdempotencyKeys::new(coordinator.clone());
+    let faas_state = FaasState::new(app_state.clone(), public_url.base().to_string(), events.clone(), idempotency.clone());
     
+    // Deployments whose instances were reattached serve again once healthy
+    let restored = faas_state.faas_manager.restore_deployments(&cluster).await;
+    if restored > 0 {
+        info!("Restored {} deployments left by a previous run", restored);
+    }
+
+    // Share the records of what this instance runs, and adopt deployments of instances that went down
+    let cluster = cluster.with_registry(faas_state.faas_manager.clone());
+    cluster.start(&events);
+    if config.cluster.enabled {
+        info!("Joined cluster as node {}", cluster.node_id());
+    }
+
     // Start FaaS cleanup task
     faas_state.faas_manager.start_cleanup_task().await;
+
+    // Back up the deployments on the configured schedule
+    let backups = Arc::new(Backups::new(config.backup.clone(), faas_state.faas_manager.clone(), storage));
+    backups.clone().start();
+    if let Some(target) = backups.target() {
+        info!("Backing up deployments to {}", target);
+    }
+
+    // Sample usage for the alert rules
+    let alerts = Arc::new(Alerts::new(config.alerts.clone(), events.clone()));
+    spawn_sampler(alerts.clone(), app_state.clone(), faas_state.faas_manager.clone());
+    
+    // Serve the gRPC API next to the REST API
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = config.server.grpc_port {
+        let grpc_addr = tokio::net::lookup_host((config.server.host.as_str(), grpc_port)).await?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Cannot resolve gRPC address {}:{}", config.server.host, grpc_port))?;
+        let grpc_service = grpc::VoidrunService::new(app_state.clone(), faas_state.faas_manager.clone());
+
+        info!("gRPC API listening on {}", grpc_addr);
+        tokio::spawn(async move {
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(grpc_service.into_server())
+                .serve(grpc_addr)
+                .await
+            {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+    }
     
     // Create proxy state for handling sandbox web services
-    let proxy_state = ProxyState::new(8080) // Start port allocation from 8080
-        .with_faas_manager(faas_state.faas_manager.clone());
+    let proxy_state = ProxyState::new(port_allocator, &config.proxy)
+        .with_sandbox_manager(app_state.clone())
+        .with_faas_manager(faas_state.faas_manager.clone())
+        .with_meter(meter.clone());
 
-    let cors = CorsLayer::new()
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .allow_origin(Any);
+    let cors = api_cors_layer(config.server.cors.as_ref())?;
 
-    let api_router = create_router(app_state.clone());
+    let api_router = create_router(app_state.clone(), cancellations, idempotency);
+    let metrics_router = create_metrics_router(faas_state.faas_manager.clone(), app_state.clone());
     let faas_router = create_faas_router(faas_state);
     let proxy_router = create_proxy_router(proxy_state);
-    let admin_router = create_admin_router(app_state.clone());
+    let admin_router = create_admin_router(app_state.clone(), reloader, meter, alerts, backups);
+    let events_router = create_events_router(events);
+    let cluster_router = create_cluster_router(cluster.clone());
     
     let app = Router::new()
         .route("/", axum::routing::get(homepage))
         .merge(api_router)
         .merge(faas_router)
-        .merge(proxy_router)
         .merge(admin_router)
+        .merge(events_router)
+        .merge(cluster_router)
+        .merge(metrics_router)
+        // Proxied services answer browsers themselves, or through their deployment's policy
+        .layer(cors)
+        .merge(proxy_router)
+        .layer(Extension(public_url))
+        // File uploads and deployments carry whole projects, beyond axum's 2MB default
+        .layer(DefaultBodyLimit::max(config.server.max_request_body_mb * 1024 * 1024))
+        .layer(middleware::from_fn_with_state(cluster.clone(), cluster::route_middleware))
+        .layer(middleware::from_fn_with_state((RateLimiter::new(coordinator), shared_config), rate_limit_middleware))
         .layer(
             ServiceBuilder::new()
                 .layer(middleware::from_fn(access_log_middleware))
                 .layer(TraceLayer::new_for_http())
-                .layer(cors)
+                // Proxied responses the sandboxed service encoded itself pass through as they are
+                .layer(CompressionLayer::new())
         );
 
     let addr = format!("{}:{}", config.server.host, config.server.port);
@@ -179,29 +381,85 @@ async fn main() -> Result<()> {
     info!("Health check: http://{}/health", addr);
 
     axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
-        .with_graceful_shutdown(shutdown_signal(app_state))
+        .with_graceful_shutdown(shutdown_signal(app_state, config.server.shutdown_grace_secs))
         .await?;
+    cluster.leave().await;
 
     Ok(())
 }
 
-fn init_tracing(level: &str) -> Result<()> {
-    let subscriber = tracing_subscriber::FmtSubscriber::builder()
-        .with_max_level(match level.to_lowercase().as_str() {
-            "trace" => tracing::Level::TRACE,
-            "debug" => tracing::Level::DEBUG,
-            "info" => tracing::Level::INFO,
-            "warn" => tracing::Level::WARN,
-            "error" => tracing::Level::ERROR,
-            _ => tracing::Level::INFO,
-        })
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber)?;
+fn level_filter(level: &str) -> LevelFilter {
+    match level.to_lowercase().as_str() {
+        "trace" => LevelFilter::TRACE,
+        "debug" => LevelFilter::DEBUG,
+        "info" => LevelFilter::INFO,
+        "warn" => LevelFilter::WARN,
+        "error" => LevelFilter::ERROR,
+        _ => LevelFilter::INFO,
+    }
+}
+
+/// Install the global subscriber, returning a function that changes its level
+fn init_tracing(level: &str) -> Result<impl Fn(&str) -> Result<()> + Send + Sync + 'static> {
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(level_filter(level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .try_init()?;
+
+    Ok(move |level: &str| handle.reload(level_filter(level)).map_err(Into::into))
+}
+
+/// Periodically remove containers and directories no sandbox claims
+fn spawn_orphan_collector(app_state: Arc<SandboxManager>, interval_seconds: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            app_state.collect_orphans().await;
+        }
+    });
+}
+
+/// Periodically remove one-shot sandboxes kept past their retention period
+fn spawn_retention_sweep(app_state: Arc<SandboxManager>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(sandbox::gc::RETENTION_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            app_state.expire_finished_sandboxes().await;
+        }
+    });
+}
+
+/// Keep the shared leases of published ports from lapsing while their sandboxes live
+fn spawn_lease_renewal(port_allocator: sandbox::PortAllocator) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(sandbox::ports::PORT_LEASE_RENEW_INTERVAL);
+        loop {
+            interval.tick().await;
+            port_allocator.renew_leases().await;
+        }
+    });
+}
+
+#[cfg(unix)]
+fn spawn_reload_on_sighup(reloader: Arc<ConfigReloader>) -> Result<()> {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            if let Err(e) = reloader.reload().await {
+                tracing::error!("Failed to reload configuration: {:#}", e);
+            }
+        }
+    });
     Ok(())
 }
 
-async fn shutdown_signal(app_state: Arc<RwLock<SandboxManager>>) {
+/// Wait for SIGINT or SIGTERM, let executions in flight finish for up to `grace_secs`
+/// while new work is turned away, then stop every sandbox
+async fn shutdown_signal(app_state: Arc<SandboxManager>, grace_secs: u64) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -224,10 +482,14 @@ async fn shutdown_signal(app_state: Arc<RwLock<SandboxManager>>) {
         _ = terminate => {},
     }
 
-    info!("Received shutdown signal, cleaning up...");
-    
-    let mut manager = app_state.write().await;
-    if let Err(e) = manager.cleanup_all().await {
+    info!("Received shutdown signal, draining executions for up to {}s", grace_secs);
+    let running = app_state.drain(std::time::Duration::from_secs(grace_secs)).await;
+    if running > 0 {
+        warn!("{} executions still running after {}s, stopping them", running, grace_secs);
+    }
+
+    info!("Cleaning up sandboxes...");
+    if let Err(e) = app_state.cleanup_all().await {
         warn!("Error during cleanup: {}", e);
     }
     
@@ -0,0 +1,460 @@
+//! Usage metering per API key, groundwork for chargeback in multi-team installs
+//!
+//! Callers are told apart by the key they send as `X-API-Key` or as a bearer token. The
+//! service does not verify keys, so the figures attribute usage rather than enforce
+//! anything; requests without a key count as [`ANONYMOUS`]. Usage is summed in hourly
+//! buckets kept in memory for [`RETENTION_DAYS`] days, and starts over on restart.
+//!
+//! With `[pricing]` set, executions, report rows and deployment stats carry a cost
+//! estimate, so platforms built on the service can show their users what a run cost.
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, HeaderMap};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, RwLock};
+use voidrun_types::{CostEstimate, SandboxResponse};
+
+/// Header callers identify with, besides `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+/// Account of usage by requests that sent no key
+pub const ANONYMOUS: &str = "anonymous";
+/// Age after which hourly buckets are dropped
+pub const RETENTION_DAYS: i64 = 90;
+/// Span reports cover when the query sets no start
+pub const DEFAULT_REPORT_DAYS: i64 = 30;
+
+const BUCKET_SECONDS: i64 = 3600;
+
+/// Key a request is metered to, if it sent one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiKey(pub Option<String>);
+
+impl ApiKey {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Self::parse(value(API_KEY_HEADER), value(header::AUTHORIZATION.as_str()))
+    }
+
+    /// Key from the values of the `X-API-Key` and `Authorization` headers; the former wins
+    pub fn parse(api_key: Option<&str>, authorization: Option<&str>) -> Self {
+        let bearer = authorization.and_then(|value| value.strip_prefix("Bearer "));
+        Self(api_key.or(bearer).map(str::trim).filter(|key| !key.is_empty()).map(str::to_string))
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiKey {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+/// `[pricing]`: prices cost estimates are computed with; with both at 0 no estimates are made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricingConfig {
+    /// Price of one second of CPU time
+    #[serde(default)]
+    pub cpu_second: f64,
+    /// Price of one GB of memory limit held for one second
+    #[serde(default)]
+    pub memory_gb_second: f64,
+    /// Currency code shown with estimates
+    #[serde(default = "default_currency")]
+    pub currency: String,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self { cpu_second: 0.0, memory_gb_second: 0.0, currency: default_currency() }
+    }
+}
+
+impl PricingConfig {
+    pub fn is_priced(&self) -> bool {
+        self.cpu_second > 0.0 || self.memory_gb_second > 0.0
+    }
+
+    fn estimate(&self, cpu_seconds: f64, memory_gb_seconds: f64) -> Option<CostEstimate> {
+        self.is_priced().then(|| CostEstimate {
+            amount: cpu_seconds * self.cpu_second + memory_gb_seconds * self.memory_gb_second,
+            currency: self.currency.clone(),
+        })
+    }
+
+    /// Cost of one execution: its CPU time, or its run time where the backend does not
+    /// measure CPU time, and its memory limit held for its run time
+    pub fn execution_cost(&self, memory_limit_mb: u64, response: &SandboxResponse) -> Option<CostEstimate> {
+        let seconds = response.execution_time_ms as f64 / 1000.0;
+        let cpu_seconds = response.usage.as_ref()
+            .and_then(|usage| usage.cpu_time_ms)
+            .map_or(seconds, |ms| ms as f64 / 1000.0);
+        self.estimate(cpu_seconds, seconds * memory_limit_mb as f64 / 1024.0)
+    }
+
+    /// Cost of metered usage; the CPU time of sandboxes kept alive is not measured, so each
+    /// sandbox second is priced as a CPU second
+    pub fn usage_cost(&self, usage: &Usage) -> Option<CostEstimate> {
+        self.estimate(usage.sandbox_seconds, usage.memory_mb_seconds / 1024.0)
+    }
+}
+
+/// Resources consumed by one key
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Usage {
+    /// Lifetime of the key's sandboxes plus the run time of its one-shot executions
+    pub sandbox_seconds: f64,
+    /// Sandbox seconds weighted by each sandbox's memory limit
+    pub memory_mb_seconds: f64,
+    pub executions: u64,
+    /// Request and response bodies proxied to and from the key's sandboxes
+    pub proxy_bytes: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.sandbox_seconds += other.sandbox_seconds;
+        self.memory_mb_seconds += other.memory_mb_seconds;
+        self.executions += other.executions;
+        self.proxy_bytes += other.proxy_bytes;
+    }
+}
+
+/// Usage of one key over a report's span
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyUsage {
+    /// The key shortened to its ends, so reports do not hand out working keys
+    pub api_key: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<CostEstimate>,
+}
+
+/// Usage per key over `[from, to)`, to hour granularity
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub keys: Vec<KeyUsage>,
+}
+
+impl UsageReport {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("api_key,sandbox_seconds,memory_mb_seconds,executions,proxy_bytes,estimated_cost,currency\n");
+        for key in &self.keys {
+            let (cost, currency) = key.estimated_cost.as_ref()
+                .map_or((String::new(), ""), |cost| (format!("{:.6}", cost.amount), cost.currency.as_str()));
+            csv.push_str(&format!(
+                "{},{:.3},{:.3},{},{},{},{}\n",
+                csv_field(&key.api_key),
+                key.usage.sandbox_seconds,
+                key.usage.memory_mb_seconds,
+                key.usage.executions,
+                key.usage.proxy_bytes,
+                cost,
+                csv_field(currency),
+            ));
+        }
+        csv
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Form of a key shown in reports: its first and last four characters
+fn mask(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    if api_key == ANONYMOUS {
+        api_key.to_string()
+    } else if chars.len() < 12 {
+        "****".to_string()
+    } else {
+        let head: String = chars[..4].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}…{}", head, tail)
+    }
+}
+
+fn bucket_start(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(BUCKET_SECONDS) * BUCKET_SECONDS
+}
+
+/// Sandbox whose lifetime is still being counted
+#[derive(Clone)]
+struct LiveSandbox {
+    api_key: String,
+    memory_limit_mb: u64,
+    accounted_until: DateTime<Utc>,
+    /// FaaS deployment the sandbox is an instance of
+    deployment: Option<String>,
+}
+
+#[derive(Default)]
+struct Ledger {
+    /// Usage by hour start (Unix seconds) and key
+    buckets: BTreeMap<(i64, String), Usage>,
+    live: HashMap<String, LiveSandbox>,
+    /// Instance lifetimes per deployment, kept until it is undeployed
+    deployments: HashMap<String, Usage>,
+}
+
+impl Ledger {
+    fn bucket(&mut self, at: DateTime<Utc>, api_key: &str) -> &mut Usage {
+        self.buckets.entry((bucket_start(at), api_key.to_string())).or_default()
+    }
+
+    /// Count a sandbox running over `[from, to)`, split across the hours it spans
+    fn accrue(&mut self, api_key: &str, memory_limit_mb: u64, from: DateTime<Utc>, to: DateTime<Utc>) {
+        let mut start = from;
+        while start < to {
+            let hour_end = Utc.timestamp_opt(bucket_start(start) + BUCKET_SECONDS, 0).unwrap();
+            let end = hour_end.min(to);
+            let seconds = (end - start).num_milliseconds() as f64 / 1000.0;
+            let usage = self.bucket(start, api_key);
+            usage.sandbox_seconds += seconds;
+            usage.memory_mb_seconds += seconds * memory_limit_mb as f64;
+            start = end;
+        }
+    }
+
+    /// Count a live sandbox from where it was accounted until to `now`
+    fn accrue_live(&mut self, sandbox: &LiveSandbox, now: DateTime<Utc>) {
+        self.accrue(&sandbox.api_key, sandbox.memory_limit_mb, sandbox.accounted_until, now);
+        if let Some(deployment_id) = &sandbox.deployment {
+            let seconds = (now - sandbox.accounted_until).num_milliseconds() as f64 / 1000.0;
+            let usage = self.deployments.entry(deployment_id.clone()).or_default();
+            usage.sandbox_seconds += seconds;
+            usage.memory_mb_seconds += seconds * sandbox.memory_limit_mb as f64;
+        }
+    }
+
+    fn start(&mut self, sandbox_id: &str, api_key: &str, memory_limit_mb: u64, now: DateTime<Utc>) {
+        self.live.insert(sandbox_id.to_string(), LiveSandbox {
+            api_key: api_key.to_string(),
+            memory_limit_mb,
+            accounted_until: now,
+            deployment: None,
+        });
+    }
+
+    fn stop(&mut self, sandbox_id: &str, now: DateTime<Utc>) {
+        if let Some(sandbox) = self.live.remove(sandbox_id) {
+            self.accrue_live(&sandbox, now);
+        }
+    }
+
+    fn owner(&self, sandbox_id: &str) -> String {
+        self.live.get(sandbox_id).map_or(ANONYMOUS, |sandbox| &sandbox.api_key).to_string()
+    }
+
+    /// Bring live sandboxes up to `now` and drop buckets past retention
+    fn settle(&mut self, now: DateTime<Utc>) {
+        let pending: Vec<LiveSandbox> = self.live.values_mut()
+            .map(|sandbox| {
+                let pending = sandbox.clone();
+                sandbox.accounted_until = now;
+                pending
+            })
+            .collect();
+        for sandbox in pending {
+            self.accrue_live(&sandbox, now);
+        }
+
+        let oldest = bucket_start(now - Duration::days(RETENTION_DAYS));
+        self.buckets.retain(|(hour, _), _| *hour >= oldest);
+    }
+
+    fn report(&mut self, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> UsageReport {
+        self.settle(now);
+
+        let mut totals: BTreeMap<&str, Usage> = BTreeMap::new();
+        for ((hour, api_key), usage) in &self.buckets {
+            // Hours overlapping the span count whole
+            if hour + BUCKET_SECONDS > from.timestamp() && *hour < to.timestamp() {
+                totals.entry(api_key.as_str()).or_default().add(usage);
+            }
+        }
+
+        UsageReport {
+            from,
+            to,
+            keys: totals.into_iter()
+                .map(|(api_key, usage)| KeyUsage { api_key: mask(api_key), usage, estimated_cost: None })
+                .collect(),
+        }
+    }
+}
+
+/// Usage ledger shared by the sandbox manager, the proxy and the admin API
+#[derive(Clone, Default)]
+pub struct Meter {
+    ledger: Arc<Mutex<Ledger>>,
+    pricing: Arc<RwLock<PricingConfig>>,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Price estimates from now on with `pricing`
+    pub fn set_pricing(&self, pricing: PricingConfig) {
+        *self.pricing.write().unwrap() = pricing;
+    }
+
+    /// Estimated cost of an execution with `memory_limit_mb`, if prices are set
+    pub fn execution_cost(&self, memory_limit_mb: u64, response: &SandboxResponse) -> Option<CostEstimate> {
+        self.pricing.read().unwrap().execution_cost(memory_limit_mb, response)
+    }
+
+    /// Count the lifetime of a sandbox towards a FaaS deployment as well as its key
+    pub fn deployment_instance(&self, sandbox_id: &str, deployment_id: &str) {
+        if let Some(sandbox) = self.ledger.lock().unwrap().live.get_mut(sandbox_id) {
+            sandbox.deployment = Some(deployment_id.to_string());
+        }
+    }
+
+    /// Lifetime of a deployment's instances, with its estimated cost if prices are set
+    pub fn deployment_usage(&self, deployment_id: &str) -> (Usage, Option<CostEstimate>) {
+        let usage = {
+            let mut ledger = self.ledger.lock().unwrap();
+            ledger.settle(Utc::now());
+            ledger.deployments.get(deployment_id).cloned().unwrap_or_default()
+        };
+        let cost = self.pricing.read().unwrap().usage_cost(&usage);
+        (usage, cost)
+    }
+
+    /// Drop the usage of an undeployed deployment
+    pub fn forget_deployment(&self, deployment_id: &str) {
+        self.ledger.lock().unwrap().deployments.remove(deployment_id);
+    }
+
+    /// Start counting the lifetime of a sandbox created for `api_key`
+    pub fn sandbox_started(&self, sandbox_id: &str, api_key: Option<&str>, memory_limit_mb: u64) {
+        self.ledger.lock().unwrap().start(sandbox_id, api_key.unwrap_or(ANONYMOUS), memory_limit_mb, Utc::now());
+    }
+
+    pub fn sandbox_stopped(&self, sandbox_id: &str) {
+        self.ledger.lock().unwrap().stop(sandbox_id, Utc::now());
+    }
+
+    /// Count an execution in a sandbox, for the key that created it
+    pub fn execution(&self, sandbox_id: &str) {
+        let mut ledger = self.ledger.lock().unwrap();
+        let api_key = ledger.owner(sandbox_id);
+        ledger.bucket(Utc::now(), &api_key).executions += 1;
+    }
+
+    /// Count a one-shot execution, whose sandbox lives as long as it runs
+    pub fn one_shot(&self, api_key: Option<&str>, memory_limit_mb: u64, execution_time_ms: u64) {
+        let api_key = api_key.unwrap_or(ANONYMOUS);
+        let now = Utc::now();
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.accrue(api_key, memory_limit_mb, now - Duration::milliseconds(execution_time_ms as i64), now);
+        ledger.bucket(now, api_key).executions += 1;
+    }
+
+    /// Count traffic the proxy forwarded to and from a sandbox
+    pub fn proxy_bytes(&self, sandbox_id: &str, bytes: u64) {
+        let mut ledger = self.ledger.lock().unwrap();
+        let api_key = ledger.owner(sandbox_id);
+        ledger.bucket(Utc::now(), &api_key).proxy_bytes += bytes;
+    }
+
+    /// Usage per key over `[from, to)`, by default the last [`DEFAULT_REPORT_DAYS`] days
+    pub fn report(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> UsageReport {
+        let now = Utc::now();
+        let to = to.unwrap_or(now);
+        let from = from.unwrap_or(to - Duration::days(DEFAULT_REPORT_DAYS));
+        let mut report = self.ledger.lock().unwrap().report(from, to, now);
+        let pricing = self.pricing.read().unwrap();
+        for key in &mut report.keys {
+            key.estimated_cost = pricing.usage_cost(&key.usage);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let mut ledger = Ledger::default();
+        ledger.start("a", "team-a-0123456789", 100, at(10, 30));
+        ledger.stop("a", at(12, 15));
+        ledger.start("b", ANONYMOUS, 50, at(11, 0));
+        ledger.bucket(at(11, 5), ANONYMOUS).executions += 1;
+
+        let report = ledger.report(at(0, 0), at(23, 0), at(11, 30));
+        assert_eq!(report.keys.len(), 2);
+        assert_eq!(report.keys[0].api_key, ANONYMOUS);
+        assert_eq!(report.keys[0].usage.sandbox_seconds, 1800.0);
+        assert_eq!(report.keys[0].usage.executions, 1);
+        assert_eq!(report.keys[1].api_key, "team…6789");
+        assert_eq!(report.keys[1].usage.sandbox_seconds, 6300.0);
+        assert_eq!(report.keys[1].usage.memory_mb_seconds, 630_000.0);
+
+        // Only the hour from 12:00 overlaps, holding 15 minutes of sandbox "a"
+        let late = ledger.report(at(12, 0), at(13, 0), at(11, 30));
+        assert_eq!(late.keys.len(), 1);
+        assert_eq!(late.keys[0].usage.sandbox_seconds, 900.0);
+        assert!(late.to_csv().starts_with("api_key,sandbox_seconds"));
+    }
+
+    #[test]
+    fn test_cost_estimates() {
+        let mut ledger = Ledger::default();
+        ledger.start("instance", "team-a-0123456789", 2048, at(10, 0));
+        ledger.live.get_mut("instance").unwrap().deployment = Some("d".to_string());
+        ledger.settle(at(10, 5));
+        ledger.stop("instance", at(10, 10));
+        assert_eq!(ledger.deployments["d"].sandbox_seconds, 600.0);
+        assert_eq!(ledger.deployments["d"].memory_mb_seconds, 1_228_800.0);
+
+        let pricing = PricingConfig { cpu_second: 0.00002, memory_gb_second: 0.000001, ..Default::default() };
+        let cost = pricing.usage_cost(&ledger.deployments["d"]).unwrap();
+        assert!((cost.amount - 0.0132).abs() < 1e-9);
+        assert_eq!(cost.currency, "USD");
+
+        let response: SandboxResponse = serde_json::from_value(serde_json::json!({
+            "success": true, "stdout": "", "stderr": "", "exit_code": 0, "execution_time_ms": 2000,
+            "is_running": false, "dev_server_url": null,
+            "usage": { "peak_memory_bytes": null, "cpu_time_ms": 500, "stdout_bytes": 0, "stderr_bytes": 0 },
+        })).unwrap();
+        let cost = pricing.execution_cost(512, &response).unwrap();
+        assert!((cost.amount - 0.000011).abs() < 1e-12);
+        assert!(PricingConfig::default().execution_cost(512, &response).is_none());
+    }
+
+    #[test]
+    fn test_api_key_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(ApiKey::from_headers(&headers), ApiKey(None));
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert_eq!(ApiKey::from_headers(&headers).0.as_deref(), Some("secret"));
+        headers.insert(API_KEY_HEADER, "explicit".parse().unwrap());
+        assert_eq!(ApiKey::from_headers(&headers).0.as_deref(), Some("explicit"));
+    }
+}
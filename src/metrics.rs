@@ -0,0 +1,215 @@
+//! Prometheus metrics of the FaaS proxy and of executions (`GET /metrics`)
+//!
+//! Every deployment on this instance is reported under its `deployment_id` label; the
+//! latency quantiles cover its last 1000 requests, like `GET /faas/deployments/{id}/stats`.
+//! Executions are counted by how they ended since the service started. The sampler
+//! feeds the alert rules the usage they watch.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+
+use crate::admin::handlers::{get_system_cpu_usage, get_system_memory_usage};
+use crate::alerts::{AlertMetric, Alerts, Sample};
+use crate::faas::stats::STATUS_CLASSES;
+use crate::faas::{DeploymentStats, FaasManager};
+use crate::sandbox::{SandboxManager, TerminationReason};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Clone)]
+struct MetricsState {
+    faas_manager: Arc<FaasManager>,
+    sandbox_manager: Arc<SandboxManager>,
+}
+
+/// GET /metrics in the Prometheus text format
+async fn metrics(State(state): State<MetricsState>) -> impl IntoResponse {
+    let mut stats = state.faas_manager.all_stats().await;
+    stats.sort_by(|(a, _), (b, _)| a.deployment_id.cmp(&b.deployment_id));
+    let terminations = state.sandbox_manager.termination_counts();
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], render(&stats, &terminations))
+}
+
+pub fn create_metrics_router(faas_manager: Arc<FaasManager>, sandbox_manager: Arc<SandboxManager>) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(MetricsState { faas_manager, sandbox_manager })
+}
+
+/// Feed the alert rules a sample of what they watch every `sample_interval_seconds`
+pub fn spawn_sampler(alerts: Arc<Alerts>, sandbox_manager: Arc<SandboxManager>, faas_manager: Arc<FaasManager>) {
+    let seconds = alerts.config().sample_interval_seconds;
+    if seconds == 0 || alerts.config().rules.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        // Requests and errors of each deployment at the previous sample
+        let mut counted: HashMap<String, (u64, u64)> = HashMap::new();
+        loop {
+            interval.tick().await;
+            let samples = sample(&alerts, &sandbox_manager, &faas_manager, &mut counted).await;
+            alerts.record(&samples);
+        }
+    });
+}
+
+/// Usage of everything the alert rules watch, as percentages
+async fn sample(
+    alerts: &Alerts,
+    sandbox_manager: &SandboxManager,
+    faas_manager: &FaasManager,
+    counted: &mut HashMap<String, (u64, u64)>,
+) -> Vec<Sample> {
+    let mut samples = Vec::new();
+
+    if alerts.watches(AlertMetric::SandboxMemory) {
+        for info in sandbox_manager.list_sandboxes().await {
+            if !matches!(info.status.as_str(), "Running" | "DevServer") {
+                continue;
+            }
+            let Ok(stats) = sandbox_manager.sandbox_stats(&info.id).await else {
+                continue;
+            };
+            let limit = stats.memory_limit_bytes.unwrap_or(info.memory_limit_mb * 1024 * 1024);
+            if limit > 0 {
+                let value = stats.memory_used_bytes as f64 * 100.0 / limit as f64;
+                samples.push(Sample { metric: AlertMetric::SandboxMemory, subject: info.id, value });
+            }
+        }
+    }
+
+    if alerts.watches(AlertMetric::HostCpu) {
+        match get_system_cpu_usage().await {
+            Ok(usage) => samples.push(Sample { metric: AlertMetric::HostCpu, subject: "host".to_string(), value: usage.percentage }),
+            Err(e) => tracing::debug!("No CPU usage sample: {}", e),
+        }
+    }
+    if alerts.watches(AlertMetric::HostMemory) {
+        match get_system_memory_usage().await {
+            Ok(usage) => samples.push(Sample { metric: AlertMetric::HostMemory, subject: "host".to_string(), value: usage.percentage }),
+            Err(e) => tracing::debug!("No memory usage sample: {}", e),
+        }
+    }
+
+    if alerts.watches(AlertMetric::DeploymentErrorRate) {
+        let mut current = HashMap::new();
+        for (stats, _) in faas_manager.all_stats().await {
+            // A deployment's first sample only sets where its counting starts
+            if let Some((requests, errors)) = counted.get(&stats.deployment_id) {
+                let requests = stats.requests.saturating_sub(*requests);
+                let errors = stats.errors.saturating_sub(*errors);
+                let value = if requests == 0 { 0.0 } else { errors as f64 * 100.0 / requests as f64 };
+                samples.push(Sample { metric: AlertMetric::DeploymentErrorRate, subject: stats.deployment_id.clone(), value });
+            }
+            current.insert(stats.deployment_id, (stats.requests, stats.errors));
+        }
+        *counted = current;
+    }
+
+    samples
+}
+
+/// Metrics of `deployments`, each with its number of instances, and of executions by how
+/// they ended
+fn render(deployments: &[(DeploymentStats, usize)], terminations: &[(TerminationReason, u64)]) -> String {
+    let mut out = String::new();
+
+    describe(&mut out, "voidrun_deployment_requests_total", "counter", "Requests proxied to the deployment, by status class");
+    for (stats, _) in deployments {
+        for class in STATUS_CLASSES {
+            let count = stats.responses.get(class).copied().unwrap_or(0);
+            let _ = writeln!(out, "voidrun_deployment_requests_total{{deployment_id=\"{}\",status=\"{}\"}} {}",
+                             label(&stats.deployment_id), class, count);
+        }
+    }
+
+    describe(&mut out, "voidrun_deployment_request_bytes_total", "counter", "Request body bytes proxied to the deployment");
+    for (stats, _) in deployments {
+        let _ = writeln!(out, "voidrun_deployment_request_bytes_total{{deployment_id=\"{}\"}} {}",
+                         label(&stats.deployment_id), stats.request_bytes);
+    }
+
+    describe(&mut out, "voidrun_deployment_response_bytes_total", "counter", "Response body bytes returned by the deployment");
+    for (stats, _) in deployments {
+        let _ = writeln!(out, "voidrun_deployment_response_bytes_total{{deployment_id=\"{}\"}} {}",
+                         label(&stats.deployment_id), stats.response_bytes);
+    }
+
+    describe(&mut out, "voidrun_deployment_request_duration_seconds", "summary", "Latency of requests proxied to the deployment");
+    for (stats, _) in deployments {
+        let id = label(&stats.deployment_id);
+        for (quantile, value) in [("0.5", stats.latency_p50_ms), ("0.95", stats.latency_p95_ms)] {
+            if let Some(ms) = value {
+                let _ = writeln!(out, "voidrun_deployment_request_duration_seconds{{deployment_id=\"{}\",quantile=\"{}\"}} {}",
+                                 id, quantile, ms / 1000.0);
+            }
+        }
+        let _ = writeln!(out, "voidrun_deployment_request_duration_seconds_sum{{deployment_id=\"{}\"}} {}",
+                         id, stats.latency_sum_ms / 1000.0);
+        let _ = writeln!(out, "voidrun_deployment_request_duration_seconds_count{{deployment_id=\"{}\"}} {}",
+                         id, stats.requests);
+    }
+
+    describe(&mut out, "voidrun_deployment_queued_requests", "gauge", "Requests waiting under the deployment's concurrency limit");
+    for (stats, _) in deployments {
+        let _ = writeln!(out, "voidrun_deployment_queued_requests{{deployment_id=\"{}\"}} {}",
+                         label(&stats.deployment_id), stats.queued_requests);
+    }
+
+    describe(&mut out, "voidrun_deployment_rejected_requests_total", "counter", "Requests turned away by the deployment's concurrency limit");
+    for (stats, _) in deployments {
+        let _ = writeln!(out, "voidrun_deployment_rejected_requests_total{{deployment_id=\"{}\"}} {}",
+                         label(&stats.deployment_id), stats.rejected_requests);
+    }
+
+    describe(&mut out, "voidrun_deployment_instances", "gauge", "Sandbox instances serving the deployment");
+    for (stats, instances) in deployments {
+        let _ = writeln!(out, "voidrun_deployment_instances{{deployment_id=\"{}\"}} {}",
+                         label(&stats.deployment_id), instances);
+    }
+
+    describe(&mut out, "voidrun_executions_total", "counter", "Executions finished, by how they ended");
+    for (reason, count) in terminations {
+        let _ = writeln!(out, "voidrun_executions_total{{termination_reason=\"{}\"}} {}", reason.as_str(), count);
+    }
+
+    out
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Label value with Prometheus escapes
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::faas::stats::TrafficStats;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_metrics() {
+        let traffic = TrafficStats::new();
+        traffic.record(200, Duration::from_millis(20), 5, 50);
+        traffic.record(503, Duration::from_millis(40), 0, 10);
+
+        let text = render(&[(traffic.snapshot("dep-1"), 2)], &[(TerminationReason::OomKilled, 3)]);
+        assert!(text.contains("# TYPE voidrun_deployment_requests_total counter\n"));
+        assert!(text.contains("voidrun_deployment_requests_total{deployment_id=\"dep-1\",status=\"5xx\"} 1\n"));
+        assert!(text.contains("voidrun_deployment_response_bytes_total{deployment_id=\"dep-1\"} 60\n"));
+        assert!(text.contains("voidrun_deployment_request_duration_seconds{deployment_id=\"dep-1\",quantile=\"0.5\"} 0.02\n"));
+        assert!(text.contains("voidrun_deployment_request_duration_seconds_count{deployment_id=\"dep-1\"} 2\n"));
+        assert!(text.contains("voidrun_deployment_instances{deployment_id=\"dep-1\"} 2\n"));
+        assert!(text.contains("voidrun_executions_total{termination_reason=\"oom_killed\"} 3\n"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Notifications as mail, sent through an SMTP relay
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+
+use super::{Channel, Notification};
+
+/// `type = "email"` channel settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// Unset sends without authenticating
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender, `voidrun@example.com` or `Voidrun <voidrun@example.com>`
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+/// How the connection to the relay is encrypted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Upgraded with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Unencrypted, for a relay on the same host
+    None,
+}
+
+impl EmailConfig {
+    pub fn check(&self) -> Result<(), String> {
+        if self.smtp_host.trim().is_empty() {
+            return Err("smtp_host must be set".to_string());
+        }
+        if self.to.is_empty() {
+            return Err("to must list at least one address".to_string());
+        }
+        if self.username.is_some() != self.password.is_some() {
+            return Err("username and password must be set together".to_string());
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            address.parse::<Mailbox>().map_err(|e| format!("invalid address {:?}: {}", address, e))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct EmailChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailChannel {
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        config.check().map_err(anyhow::Error::msg)?;
+        let builder = match config.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)
+                .with_context(|| format!("Cannot set up STARTTLS to {}", config.smtp_host))?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)
+                .with_context(|| format!("Cannot set up TLS to {}", config.smtp_host))?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+        };
+        let mut builder = builder.port(config.smtp_port);
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to: config.to.iter().map(|address| address.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Channel for EmailChannel {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("[Voidrun] {}", notification.title));
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let body = format!("{}\n\n{}\n", notification.text, notification.event.timestamp.to_rfc3339());
+        self.transport.send(message.body(body)?).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Notifications to operators, `[notifications]` in the configuration
+//!
+//! The [`Notifier`] follows the event bus and reports what an operator has to hear about
+//! to every configured channel subscribed to its topic: alerts firing and resolving,
+//! deployments failing, and the automatic cleanup of idle deployments and orphaned
+//! resources. Channels are webhooks, Slack incoming webhooks and email over SMTP; each is
+//! a [`Channel`], and delivery failures are logged without retries.
+
+mod email;
+mod webhook;
+
+pub use email::EmailConfig;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::{Event, EventBus, EventKind};
+
+/// How long delivering one notification to one channel may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `[notifications]` in the configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub channels: Vec<ChannelConfig>,
+}
+
+/// A channel and the topics it receives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelConfig {
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+    /// Topics sent to the channel; empty sends all
+    #[serde(default)]
+    pub topics: Vec<Topic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelKind {
+    /// JSON POSTed to `url`
+    Webhook { url: String },
+    /// Message posted to a Slack incoming webhook
+    Slack { webhook_url: String },
+    /// Mail sent through an SMTP relay
+    Email(EmailConfig),
+}
+
+/// What a notification is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// Alerts firing and resolving
+    Alerts,
+    /// Deployments that failed to start
+    DeploymentFailures,
+    /// Idle deployments scaled to zero and orphaned resources removed
+    Cleanup,
+}
+
+impl ChannelConfig {
+    /// Problems with the channel's settings, found before anything is sent
+    pub fn check(&self) -> Result<(), String> {
+        let http_url = |name: &str, url: &str| {
+            if url.starts_with("http://") || url.starts_with("https://") {
+                Ok(())
+            } else {
+                Err(format!("{} must be an http(s) URL", name))
+            }
+        };
+        match &self.kind {
+            ChannelKind::Webhook { url } => http_url("url", url),
+            ChannelKind::Slack { webhook_url } => http_url("webhook_url", webhook_url),
+            ChannelKind::Email(config) => config.check(),
+        }
+    }
+}
+
+fn receives(topics: &[Topic], topic: Topic) -> bool {
+    topics.is_empty() || topics.contains(&topic)
+}
+
+/// What an operator is told about one event
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub topic: Topic,
+    /// One line, such as a mail subject
+    pub title: String,
+    pub text: String,
+    /// The event the notification is about
+    pub event: Event,
+}
+
+/// Where notifications are delivered
+#[async_trait]
+pub trait Channel: Send + Sync {
+    /// Name in logs, the channel type
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// The configured channels, with the topics each receives
+pub struct Notifier {
+    channels: Vec<(Vec<Topic>, Arc<dyn Channel>)>,
+}
+
+impl Notifier {
+    /// Channels that cannot be set up are left out with a warning
+    pub fn new(config: &NotificationsConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        let mut channels = Vec::new();
+        for channel in &config.channels {
+            let built: Result<Arc<dyn Channel>> = match &channel.kind {
+                ChannelKind::Webhook { url } => Ok(Arc::new(webhook::WebhookChannel::new(client.clone(), url.clone()))),
+                ChannelKind::Slack { webhook_url } => Ok(Arc::new(webhook::SlackChannel::new(client.clone(), webhook_url.clone()))),
+                ChannelKind::Email(config) => email::EmailChannel::new(config).map(|channel| Arc::new(channel) as Arc<dyn Channel>),
+            };
+            match built {
+                Ok(built) => channels.push((channel.topics.clone(), built)),
+                Err(e) => tracing::warn!("Notification channel left out: {:#}", e),
+            }
+        }
+        Self { channels }
+    }
+
+    /// Follow the event bus and notify about what the channels subscribed to
+    pub fn start(self: Arc<Self>, events: &EventBus) {
+        if self.channels.is_empty() {
+            return;
+        }
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        if let Some(notification) = notification(&event) {
+                            self.notify(notification);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Notifier missed {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// Send to every channel receiving the notification's topic, each on its own so a
+    /// slow mail relay holds up no one
+    pub fn notify(&self, notification: Notification) {
+        let notification = Arc::new(notification);
+        for (topics, channel) in &self.channels {
+            if !receives(topics, notification.topic) {
+                continue;
+            }
+            let channel = channel.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                let result = tokio::time::timeout(DELIVERY_TIMEOUT, channel.send(&notification)).await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+                if let Err(e) = result {
+                    tracing::warn!("Failed to send {:?} notification to {}: {:#}", notification.title, channel.name(), e);
+                }
+            });
+        }
+    }
+}
+
+/// The notification an event calls for, if any
+pub fn notification(event: &Event) -> Option<Notification> {
+    let (topic, title, text) = match &event.kind {
+        EventKind::AlertFired { metric, subject, value, threshold } => (
+            Topic::Alerts,
+            format!("Alert: {} of {}", metric, subject),
+            format!("{} of {} is {:.1}%, above the threshold of {}%.", metric, subject, value, threshold),
+        ),
+        EventKind::AlertResolved { metric, subject } => (
+            Topic::Alerts,
+            format!("Resolved: {} of {}", metric, subject),
+            format!("{} of {} is back below its threshold.", metric, subject),
+        ),
+        EventKind::DeploymentFailed { deployment_id, sandbox_id, error } => (
+            Topic::DeploymentFailures,
+            format!("Deployment {} failed", deployment_id),
+            format!("Deployment {} failed in sandbox {}: {}", deployment_id, sandbox_id, error),
+        ),
+        EventKind::DeploymentScaledDown { deployment_id, idle_minutes, .. } => (
+            Topic::Cleanup,
+            format!("Deployment {} scaled to zero", deployment_id),
+            format!("Deployment {} was idle for {} minutes; its sandboxes were stopped until the next request.", deployment_id, idle_minutes),
+        ),
+        EventKind::OrphansCollected { resources, failed } => (
+            Topic::Cleanup,
+            format!("Removed {} orphaned resources", resources - failed),
+            if *failed > 0 {
+                format!("Orphan collection found {} resources no sandbox claims; {} could not be removed.", resources, failed)
+            } else {
+                format!("Orphan collection removed {} resources no sandbox claims.", resources)
+            },
+        ),
+        _ => return None,
+    };
+    Some(Notification { topic, title, text, event: event.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_notifications_config() {
+        let config: NotificationsConfig = toml::from_str(r#"
+            [[channels]]
+            type = "slack"
+            webhook_url = "https://hooks.slack.com/services/T0/B0/x"
+            topics = ["alerts"]
+
+            [[channels]]
+            type = "email"
+            smtp_host = "smtp.example.com"
+            from = "Voidrun <voidrun@example.com>"
+            to = ["ops@example.com"]
+        "#).unwrap();
+        let [slack, mail] = &config.channels[..] else { panic!("two channels expected") };
+        assert!(receives(&slack.topics, Topic::Alerts) && !receives(&slack.topics, Topic::Cleanup));
+        assert!(receives(&mail.topics, Topic::DeploymentFailures));
+        assert!(slack.check().is_ok() && mail.check().is_ok());
+        let ChannelKind::Email(email) = &mail.kind else { panic!("email channel expected") };
+        assert_eq!(email.smtp_port, 587);
+        assert!(ChannelConfig { kind: ChannelKind::Webhook { url: "ops.example.com".to_string() }, topics: vec![] }.check().is_err());
+
+        let event = |kind| Event { timestamp: Utc::now(), kind };
+        let failed = notification(&event(EventKind::DeploymentFailed {
+            deployment_id: "d1".to_string(),
+            sandbox_id: "s1".to_string(),
+            error: "npm install failed".to_string(),
+        })).unwrap();
+        assert_eq!(failed.topic, Topic::DeploymentFailures);
+        assert!(failed.text.contains("npm install failed"));
+        assert_eq!(notification(&event(EventKind::OrphansCollected { resources: 3, failed: 1 })).unwrap().topic, Topic::Cleanup);
+        assert!(notification(&event(EventKind::SandboxDeleted { sandbox_id: "s1".to_string() })).is_none());
+    }
+}
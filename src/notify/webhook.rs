@@ -0,0 +1,61 @@
+//! Channels that POST notifications over HTTP
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::{Channel, Notification, Topic};
+
+/// The notification as JSON, with the event it is about
+pub struct WebhookChannel {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookChannel {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Self { client, url }
+    }
+}
+
+#[async_trait]
+impl Channel for WebhookChannel {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.client.post(&self.url).json(notification).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// A message to a Slack incoming webhook
+pub struct SlackChannel {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackChannel {
+    pub fn new(client: reqwest::Client, webhook_url: String) -> Self {
+        Self { client, webhook_url }
+    }
+}
+
+#[async_trait]
+impl Channel for SlackChannel {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let icon = match notification.topic {
+            Topic::Alerts => ":rotating_light:",
+            Topic::DeploymentFailures => ":x:",
+            Topic::Cleanup => ":broom:",
+        };
+        let text = format!("{} *{}*\n{}", icon, notification.title, notification.text);
+        self.client.post(&self.webhook_url).json(&json!({ "text": text })).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Circuit breakers of the sandboxes the proxy forwards to
+//!
+//! A sandbox that fails to accept [`FAILURE_THRESHOLD`] connections in a row, each within
+//! [`FAILURE_WINDOW_SECS`] of the last, has its circuit opened: for [`OPEN_SECS`] the proxy
+//! answers its requests with `503` and `Retry-After` without trying to connect. After that
+//! one request is let through as a probe; the circuit closes when it connects and opens
+//! again when it does not. Only failed connections count, connect timeouts included, not
+//! the responses of a service that answers, however slowly and whatever their status.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive connection failures that open a circuit
+pub const FAILURE_THRESHOLD: u32 = 5;
+/// Failures further apart than this start counting again
+pub const FAILURE_WINDOW_SECS: u64 = 60;
+/// How long an open circuit turns requests away before probing
+pub const OPEN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy)]
+enum Circuit {
+    Closed { failures: u32, last_failure: Instant },
+    Open { until: Instant },
+    /// A probe is in flight; a probe that never reports back is given up after the open period
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug)]
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+    threshold: u32,
+    open_for: Duration,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(FAILURE_THRESHOLD, Duration::from_secs(OPEN_SECS))
+    }
+}
+
+impl CircuitBreakers {
+    pub fn new(threshold: u32, open_for: Duration) -> Self {
+        Self { circuits: Mutex::new(HashMap::new()), threshold: threshold.max(1), open_for }
+    }
+
+    /// Whether a request may go to `target`; if not, how long until it may try again
+    pub fn admit(&self, target: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(target) else {
+            return Ok(());
+        };
+        match *circuit {
+            Circuit::Closed { .. } => Ok(()),
+            Circuit::Open { until } if now < until => Err(until - now),
+            Circuit::HalfOpen { since } if now < since + self.open_for => Err(Duration::from_secs(1)),
+            // This request is the probe
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                *circuit = Circuit::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    /// `target` accepted a connection
+    pub fn succeeded(&self, target: &str) {
+        self.circuits.lock().unwrap().remove(target);
+    }
+
+    /// `target` refused or timed out a connection; true when this opened its circuit
+    pub fn failed(&self, target: &str) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(FAILURE_WINDOW_SECS);
+        let mut circuits = self.circuits.lock().unwrap();
+        // Forget circuits of sandboxes that stopped failing, or stopped existing
+        circuits.retain(|_, circuit| match *circuit {
+            Circuit::Closed { last_failure, .. } => now < last_failure + window,
+            Circuit::Open { until } => now < until + window,
+            Circuit::HalfOpen { since } => now < since + self.open_for + window,
+        });
+
+        let circuit = circuits.entry(target.to_string())
+            .or_insert(Circuit::Closed { failures: 0, last_failure: now });
+        match *circuit {
+            Circuit::Closed { failures, .. } if failures + 1 < self.threshold => {
+                *circuit = Circuit::Closed { failures: failures + 1, last_failure: now };
+                false
+            }
+            Circuit::Closed { .. } | Circuit::HalfOpen { .. } => {
+                *circuit = Circuit::Open { until: now + self.open_for };
+                true
+            }
+            // Requests admitted before the circuit opened are failing late
+            Circuit::Open { .. } => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_and_probes() {
+        let breakers = CircuitBreakers::new(3, Duration::from_secs(60));
+        assert!(!breakers.failed("a"));
+        assert!(!breakers.failed("a"));
+        assert!(breakers.admit("a").is_ok());
+        assert!(breakers.failed("a"));
+        assert!(breakers.admit("a").unwrap_err() > Duration::from_secs(59));
+        assert!(breakers.admit("b").is_ok());
+
+        // Once the open period is over a single probe goes through
+        let breakers = CircuitBreakers::new(1, Duration::ZERO);
+        assert!(breakers.failed("a"));
+        assert!(breakers.admit("a").is_ok());
+        assert!(breakers.failed("a"));
+        assert!(breakers.admit("a").is_ok());
+        breakers.succeeded("a");
+        assert!(breakers.admit("a").is_ok());
+
+        let breakers = CircuitBreakers::new(1, Duration::from_secs(60));
+        breakers.circuits.lock().unwrap().insert("a".to_string(), Circuit::Open { until: Instant::now() });
+        assert!(breakers.admit("a").is_ok());
+        assert_eq!(breakers.admit("a"), Err(Duration::from_secs(1)));
+    }
+}
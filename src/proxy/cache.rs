@@ -0,0 +1,214 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{body::Bytes, extract::Request, response::Response};
+use lru::LruCache;
+
+/// Header telling clients whether a response was served from the cache
+const CACHE_STATUS_HEADER: &str = "x-cache";
+
+/// Buffered upstream response
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+/// In-memory LRU cache for proxied GET responses
+///
+/// Follows the shared-cache rules of Cache-Control: responses are stored only
+/// while they are fresh, and `no-store`, `no-cache` and `private` are honoured on
+/// both requests and responses.
+#[derive(Debug)]
+pub struct ResponseCache {
+    entries: Mutex<LruCache<String, CachedResponse>>,
+    max_entry_bytes: usize,
+    default_ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    pub fn new(max_entries: usize, max_entry_bytes: usize, default_ttl: Option<Duration>) -> Self {
+        let capacity = NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            max_entry_bytes,
+            default_ttl,
+        }
+    }
+
+    /// Cache key for a request, or None when the request must bypass the cache
+    pub fn cache_key(req: &Request) -> Option<String> {
+        if req.method() != axum::http::Method::GET {
+            return None;
+        }
+        if req.headers().contains_key(axum::http::header::AUTHORIZATION) {
+            return None;
+        }
+        let cache_control = req.headers()
+            .get_all(axum::http::header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        if has_directive(&cache_control, "no-store") || has_directive(&cache_control, "no-cache") {
+            return None;
+        }
+        // Responses are stored as encoded by the service, so each accepted encoding gets its own entry
+        let path_and_query = req.uri().path_and_query()?;
+        match req.headers().get(axum::http::header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()) {
+            Some(encoding) => Some(format!("{} {}", path_and_query, encoding)),
+            None => Some(path_and_query.to_string()),
+        }
+    }
+
+    /// Serve a fresh cached response
+    pub fn get(&self, key: &str) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > now => {
+                let mut builder = Response::builder().status(entry.status);
+                for (name, value) in &entry.headers {
+                    builder = builder.header(name.as_str(), value.as_str());
+                }
+                builder
+                    .header("age", now.duration_since(entry.stored_at).as_secs().to_string())
+                    .header(CACHE_STATUS_HEADER, "HIT")
+                    .body(axum::body::Body::from(entry.body.clone()))
+                    .ok()
+            }
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store an upstream response if it is cacheable
+    pub fn put(&self, key: String, status: u16, headers: Vec<(String, String)>, body: Bytes) {
+        if status != 200 || body.len() > self.max_entry_bytes {
+            return;
+        }
+        // Responses that vary on request headers other than Accept-Encoding, which is
+        // part of the key, would need a key per variant
+        if headers.iter().any(|(name, value)| {
+            (name.eq_ignore_ascii_case("vary") && !varies_on_encoding_only(value)) || name.eq_ignore_ascii_case("set-cookie")
+        }) {
+            return;
+        }
+        let ttl = match freshness_lifetime(&headers, self.default_ttl) {
+            Some(ttl) if !ttl.is_zero() => ttl,
+            _ => return,
+        };
+
+        let now = Instant::now();
+        self.entries.lock().unwrap().put(key, CachedResponse {
+            status,
+            headers,
+            body,
+            stored_at: now,
+            expires_at: now + ttl,
+        });
+    }
+
+    /// Drop every cached response, e.g. after the deployment's code changed
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Mark a response that was forwarded to the sandbox
+pub fn mark_miss(response: &mut Response) {
+    response.headers_mut().insert(CACHE_STATUS_HEADER, axum::http::HeaderValue::from_static("MISS"));
+}
+
+/// How long a response may be served from a shared cache
+fn freshness_lifetime(headers: &[(String, String)], default_ttl: Option<Duration>) -> Option<Duration> {
+    let cache_control = headers.iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if cache_control.is_empty() {
+        return default_ttl;
+    }
+    if has_directive(&cache_control, "no-store")
+        || has_directive(&cache_control, "no-cache")
+        || has_directive(&cache_control, "private")
+    {
+        return None;
+    }
+
+    // s-maxage takes precedence over max-age for shared caches
+    directive_seconds(&cache_control, "s-maxage")
+        .or_else(|| directive_seconds(&cache_control, "max-age"))
+        .map(Duration::from_secs)
+        .or(default_ttl)
+}
+
+fn has_directive(cache_control: &str, directive: &str) -> bool {
+    cache_control.split(',')
+        .any(|part| part.trim().split('=').next().unwrap_or("").eq_ignore_ascii_case(directive))
+}
+
+fn directive_seconds(cache_control: &str, directive: &str) -> Option<u64> {
+    cache_control.split(',').find_map(|part| {
+        let (name, value) = part.trim().split_once('=')?;
+        if name.trim().eq_ignore_ascii_case(directive) {
+            value.trim().trim_matches('"').parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn varies_on_encoding_only(vary: &str) -> bool {
+    vary.split(',').all(|name| name.trim().eq_ignore_ascii_case("accept-encoding"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(cache_control: &str) -> Vec<(String, String)> {
+        vec![("Cache-Control".to_string(), cache_control.to_string())]
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        assert_eq!(freshness_lifetime(&headers("public, max-age=60"), None), Some(Duration::from_secs(60)));
+        assert_eq!(freshness_lifetime(&headers("max-age=60, s-maxage=10"), None), Some(Duration::from_secs(10)));
+        assert_eq!(freshness_lifetime(&headers("private, max-age=60"), None), None);
+        assert_eq!(freshness_lifetime(&headers("no-store"), Some(Duration::from_secs(5))), None);
+        assert_eq!(freshness_lifetime(&[], Some(Duration::from_secs(5))), Some(Duration::from_secs(5)));
+        assert_eq!(freshness_lifetime(&[], None), None);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = ResponseCache::new(1, 1024, None);
+        cache.put("/a".to_string(), 200, headers("max-age=60"), Bytes::from_static(b"a"));
+        assert!(cache.get("/a").is_some());
+
+        cache.put("/b".to_string(), 200, headers("max-age=60"), Bytes::from_static(b"b"));
+        assert!(cache.get("/a").is_none());
+        assert!(cache.get("/b").is_some());
+
+        cache.put("/c".to_string(), 500, headers("max-age=60"), Bytes::from_static(b"c"));
+        assert!(cache.get("/c").is_none());
+
+        let mut varying = headers("max-age=60");
+        varying.push(("Vary".to_string(), "Accept-Encoding".to_string()));
+        cache.put("/d gzip".to_string(), 200, varying.clone(), Bytes::from_static(b"d"));
+        assert!(cache.get("/d gzip").is_some());
+        varying.push(("Vary".to_string(), "Cookie".to_string()));
+        cache.put("/e".to_string(), 200, varying, Bytes::from_static(b"e"));
+        assert!(cache.get("/e").is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! gRPC through the proxy, over HTTP/2 end to end
+//!
+//! The listener speaks HTTP/2 without TLS (prior knowledge) next to HTTP/1.1. gRPC
+//! requests, HTTP/2 requests with an `application/grpc` content type, are forwarded
+//! to the sandboxed service over HTTP/2 as well: bodies stream in both directions
+//! instead of being buffered, and response trailers such as `grpc-status` reach the
+//! caller. The service must accept HTTP/2 without TLS, as gRPC servers do. Everything
+//! else, gRPC-web included, is forwarded over HTTP/1.1.
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, Uri, Version},
+    response::Response,
+};
+use http_body_util::BodyExt;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+
+use super::headers;
+use crate::api::error::ApiError;
+use crate::config::ProxyConfig;
+use crate::metering::Meter;
+
+pub type H2Client = Client<HttpConnector, Body>;
+
+/// HTTP/2-only client for forwarding gRPC requests, pooled and timed like the HTTP/1.1 one
+pub fn client(config: &ProxyConfig) -> H2Client {
+    let mut connector = HttpConnector::new();
+    connector.set_connect_timeout(Some(Duration::from_millis(config.connect_timeout_ms)));
+    connector.set_keepalive(Some(Duration::from_secs(60)));
+    Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_seconds))
+        .build(connector)
+}
+
+/// Whether the request is gRPC over HTTP/2, which is forwarded over HTTP/2
+pub fn is_grpc(req: &Request) -> bool {
+    req.version() == Version::HTTP_2
+        && req.headers().get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| {
+                content_type == "application/grpc" || content_type.starts_with("application/grpc+")
+            })
+}
+
+/// Outcome of forwarding a gRPC request, for the caller's circuit breaker
+pub enum Forwarded {
+    Response(Response),
+    /// The service could not be connected to
+    ConnectFailed(ApiError),
+    Failed(ApiError),
+}
+
+/// Forward `req` to `target_url`; `timeout` bounds the wait for the response headers,
+/// not the stream that follows. Bytes in both directions are metered to `sandbox_id`.
+pub async fn forward(
+    client: &H2Client,
+    meter: Option<Meter>,
+    sandbox_id: &str,
+    req: Request,
+    client_ip: Option<std::net::IpAddr>,
+    target_url: &str,
+    timeout: Duration,
+) -> Forwarded {
+    let uri: Uri = match target_url.parse() {
+        Ok(uri) => uri,
+        Err(e) => return Forwarded::Failed(ApiError::internal(format!("Invalid target URL {}: {}", target_url, e))),
+    };
+
+    let (mut parts, body) = req.into_parts();
+    let mut forwarded = headers::forwarded_request_headers(&parts.headers, client_ip);
+    // gRPC requires `TE: trailers`, the one TE value HTTP/2 allows
+    if parts.headers.get_all(header::TE).iter().any(|value| value.as_bytes().eq_ignore_ascii_case(b"trailers")) {
+        forwarded.insert(header::TE, HeaderValue::from_static("trailers"));
+    }
+    parts.uri = uri;
+    parts.version = Version::HTTP_2;
+    parts.headers = forwarded;
+    let request = Request::from_parts(parts, Body::new(metered(body, meter.clone(), sandbox_id)));
+
+    let response = match tokio::time::timeout(timeout, client.request(request)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) if e.is_connect() => {
+            return Forwarded::ConnectFailed(ApiError::BadGateway(format!("Sandboxed service did not answer: {}", e)));
+        }
+        Ok(Err(e)) => {
+            return Forwarded::Failed(ApiError::BadGateway(format!("Sandboxed service did not answer: {}", e)));
+        }
+        Err(_) => {
+            return Forwarded::Failed(ApiError::GatewayTimeout(format!(
+                "Sandboxed service did not answer within {}s", timeout.as_secs()
+            )));
+        }
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let tokens = headers::connection_tokens(parts.headers.get_all(header::CONNECTION).iter().filter_map(|v| v.to_str().ok()));
+    let hop_by_hop: Vec<_> = parts.headers.keys()
+        .filter(|name| headers::is_hop_by_hop(name.as_str(), &tokens))
+        .cloned()
+        .collect();
+    for name in hop_by_hop {
+        parts.headers.remove(name);
+    }
+    Forwarded::Response(Response::from_parts(parts, Body::new(metered(body, meter, sandbox_id))))
+}
+
+/// `body` with its data frames counted in the sandbox's proxied bytes as they pass;
+/// trailers pass through untouched
+fn metered<B>(body: B, meter: Option<Meter>, sandbox_id: &str) -> impl hyper::body::Body<Data = B::Data, Error = B::Error>
+where
+    B: hyper::body::Body,
+    B::Data: hyper::body::Buf,
+{
+    let sandbox_id = sandbox_id.to_string();
+    body.map_frame(move |frame| {
+        if let (Some(meter), Some(data)) = (&meter, frame.data_ref()) {
+            meter.proxy_bytes(&sandbox_id, hyper::body::Buf::remaining(data) as u64);
+        }
+        frame
+    })
+}
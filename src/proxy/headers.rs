@@ -0,0 +1,102 @@
+//! Headers of proxied requests and responses
+//!
+//! Hop-by-hop headers (RFC 9110 section 7.6.1), and any header a `Connection` header
+//! names, describe one connection and are not passed on. `Host` and `Content-Length`
+//! are set by the client for the forwarded request. The sandboxed service learns where
+//! the request came from through `X-Forwarded-For`, `X-Forwarded-Proto` and
+//! `X-Forwarded-Host`, extending what a reverse proxy in front of the service sent.
+//! WebSocket upgrades to deployments are not forwarded as such: the proxy opens a
+//! WebSocket connection of its own to the instance and relays messages over it.
+
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap, HeaderValue};
+
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Header names listed in `Connection` header values, lowercased
+pub fn connection_tokens<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    values.flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+/// Whether the header `name` only concerns the connection it arrived on
+pub fn is_hop_by_hop(name: &str, connection_tokens: &[String]) -> bool {
+    let name = name.to_ascii_lowercase();
+    HOP_BY_HOP.contains(&name.as_str()) || connection_tokens.contains(&name)
+}
+
+/// Headers to forward a request with, from the client at `client_ip`
+pub fn forwarded_request_headers(headers: &HeaderMap, client_ip: Option<IpAddr>) -> HeaderMap {
+    let tokens = connection_tokens(headers.get_all(header::CONNECTION).iter().filter_map(|v| v.to_str().ok()));
+    let mut forwarded: HeaderMap = headers.iter()
+        .filter(|(name, _)| !is_hop_by_hop(name.as_str(), &tokens))
+        .filter(|(name, _)| *name != header::HOST && *name != header::CONTENT_LENGTH)
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    if let Some(client_ip) = client_ip {
+        let chain = headers.get_all(X_FORWARDED_FOR).iter()
+            .filter_map(|value| value.to_str().ok())
+            .chain(std::iter::once(client_ip.to_string().as_str()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(value) = HeaderValue::from_str(&chain) {
+            forwarded.insert(X_FORWARDED_FOR, value);
+        }
+    }
+    if !forwarded.contains_key(X_FORWARDED_PROTO) {
+        forwarded.insert(X_FORWARDED_PROTO, HeaderValue::from_static("http"));
+    }
+    if !forwarded.contains_key(X_FORWARDED_HOST) {
+        if let Some(host) = headers.get(header::HOST) {
+            forwarded.insert(X_FORWARDED_HOST, host.clone());
+        }
+    }
+    forwarded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_request_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "sandbox.example.com".parse().unwrap());
+        headers.insert(header::CONNECTION, "keep-alive, X-Trace".parse().unwrap());
+        headers.insert("x-trace", "1".parse().unwrap());
+        headers.insert(header::CONTENT_LENGTH, "5".parse().unwrap());
+        headers.insert(header::TRANSFER_ENCODING, "chunked".parse().unwrap());
+        headers.insert(header::ACCEPT, "text/html".parse().unwrap());
+        headers.insert(X_FORWARDED_FOR, "203.0.113.7".parse().unwrap());
+
+        let forwarded = forwarded_request_headers(&headers, Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(forwarded.get(header::ACCEPT).unwrap(), "text/html");
+        for removed in [header::HOST, header::CONNECTION, header::CONTENT_LENGTH, header::TRANSFER_ENCODING] {
+            assert!(!forwarded.contains_key(&removed), "{}", removed);
+        }
+        assert!(!forwarded.contains_key("x-trace"));
+        assert_eq!(forwarded.get(X_FORWARDED_FOR).unwrap(), "203.0.113.7, 10.0.0.2");
+        assert_eq!(forwarded.get(X_FORWARDED_PROTO).unwrap(), "http");
+        assert_eq!(forwarded.get(X_FORWARDED_HOST).unwrap(), "sandbox.example.com");
+
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        assert_eq!(forwarded_request_headers(&headers, None).get(X_FORWARDED_PROTO).unwrap(), "https");
+    }
+}
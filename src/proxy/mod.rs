@@ -1,118 +1,116 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::Utc;
 use axum::{
-    extract::{Path, State, Request},
-    http::StatusCode,
-    response::Response,
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, Path, State, Request},
+    http::{header, HeaderMap, HeaderValue, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::any,
     Router,
 };
-use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-#[cfg(feature = "docker")]
-use bollard::Docker;
+pub mod breaker;
+pub mod cache;
+pub mod h2;
+pub mod headers;
+pub mod websocket;
 
-/// Port allocation manager for sandbox containers
-#[derive(Debug, Clone)]
-pub struct PortAllocator {
-    allocated_ports: Arc<RwLock<HashMap<String, u16>>>,
-}
-
-impl PortAllocator {
-    pub fn new(_start_port: u16) -> Self {
-        Self {
-            allocated_ports: Arc::new(RwLock::new(HashMap::new())),
-        }
-    }
+use breaker::CircuitBreakers;
+use cache::ResponseCache;
+use crate::api::cors;
+use crate::api::error::ApiError;
+use crate::api::public_url::BaseUrl;
+use crate::config::ProxyConfig;
+use crate::faas::{access, access_log, affinity, AccessLogEntry, InstanceLease};
+use crate::faas::routing::Routing;
+use crate::faas::static_site::StaticSite;
+use crate::metering::Meter;
+use crate::sandbox::manager::SandboxManager;
 
-    
-    pub async fn get_port(&self, sandbox_id: &str) -> Option<u16> {
-        let allocated = self.allocated_ports.read().await;
-        allocated.get(sandbox_id).copied()
-    }
-}
+pub use crate::sandbox::PortAllocator;
 
 /// Reverse proxy state
 #[derive(Clone)]
 pub struct ProxyState {
     pub client: reqwest::Client,
+    /// Client gRPC requests are forwarded over HTTP/2 with
+    pub h2_client: h2::H2Client,
+    /// Timeouts requests are forwarded with
+    pub config: Arc<ProxyConfig>,
     pub port_allocator: PortAllocator,
+    /// Asked where a sandbox's service is when no port was allocated for it
+    pub sandbox_manager: Option<Arc<SandboxManager>>,
     pub faas_manager: Option<Arc<crate::faas::FaasManager>>,
+    /// Ledger proxied bytes are counted in, for the key owning the sandbox
+    pub meter: Option<Meter>,
+    /// Sandboxes that keep refusing connections are turned away here
+    pub breakers: Arc<CircuitBreakers>,
 }
 
 impl ProxyState {
-    pub fn new(start_port: u16) -> Self {
+    /// `port_allocator` must be the allocator the sandbox backend publishes ports from
+    pub fn new(port_allocator: PortAllocator, config: &ProxyConfig) -> Self {
         Self {
-            client: reqwest::Client::new(),
-            port_allocator: PortAllocator::new(start_port),
+            client: config.client(),
+            h2_client: h2::client(config),
+            config: Arc::new(config.clone()),
+            port_allocator,
+            sandbox_manager: None,
             faas_manager: None,
+            meter: None,
+            breakers: Arc::new(CircuitBreakers::default()),
         }
     }
     
+    pub fn with_sandbox_manager(mut self, sandbox_manager: Arc<SandboxManager>) -> Self {
+        self.sandbox_manager = Some(sandbox_manager);
+        self
+    }
+
     pub fn with_faas_manager(mut self, faas_manager: Arc<crate::faas::FaasManager>) -> Self {
         self.faas_manager = Some(faas_manager);
         self
     }
-}
 
-/// Get the mapped port for a container by inspecting Docker
-async fn get_container_port(sandbox_id: &str) -> Option<u16> {
-    #[cfg(feature = "docker")]
-    {
-        info!("[PROXY] Looking up container port for sandbox {}", sandbox_id);
-        let docker = Docker::connect_with_local_defaults().ok()?;
-        
-        // First, find the container by name (sandbox_id is used as container name)
-        let containers = docker.list_containers(Some(bollard::container::ListContainersOptions {
-            all: true,
-            filters: [("name".to_string(), vec![sandbox_id.to_string()])].into(),
-            ..Default::default()
-        })).await.ok()?;
-        
-        if containers.is_empty() {
-            info!("[PROXY] No container found with name {}", sandbox_id);
-            return None;
+    pub fn with_meter(mut self, meter: Meter) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Host and port to forward a sandbox's requests to: its allocated port, else
+    /// wherever its backend reports the service, which may be a remote Docker host
+    async fn target(&self, sandbox_id: &str) -> Result<(String, u16), ApiError> {
+        if let Some(target) = self.port_allocator.get_target(sandbox_id).await {
+            return Ok(target);
         }
-        
-        let container = containers.first()?;
-        let container_id = &container.id.as_ref()?;
-        info!("[PROXY] Found container {} for sandbox {}", container_id, sandbox_id);
-        
-        let container_info = docker.inspect_container(container_id, None).await.ok()?;
-        
-        if let Some(network_settings) = container_info.network_settings {
-            if let Some(ports) = network_settings.ports {
-                info!("[PROXY] Container ports available: {:?}", ports.keys().collect::<Vec<_>>());
-                // Look for port 3000/tcp mapping
-                if let Some(port_bindings) = ports.get("3000/tcp") {
-                    if let Some(bindings) = port_bindings {
-                        if let Some(binding) = bindings.first() {
-                            if let Some(host_port) = &binding.host_port {
-                                let port = host_port.parse::<u16>().ok()?;
-                                info!("[PROXY] Found host port {} mapped to container port 3000", port);
-                                return Some(port);
-                            }
-                        }
-                    }
-                }
-                info!("[PROXY] No port 3000/tcp mapping found for container");
-            } else {
-                info!("[PROXY] No port mappings found for container");
+        let Some(sandbox_manager) = &self.sandbox_manager else {
+            return Err(no_published_port(sandbox_id));
+        };
+        match sandbox_manager.endpoint_for(sandbox_id).await {
+            Ok(Some(target)) => Ok(target),
+            Ok(None) => Err(no_published_port(sandbox_id)),
+            Err(e) => {
+                warn!("[PROXY] Could not find the service of sandbox {}: {}", sandbox_id, e);
+                Err(no_published_port(sandbox_id))
             }
-        } else {
-            info!("[PROXY] No network settings found for container");
         }
-        
-        info!("[PROXY] Failed to find port mapping for sandbox {}", sandbox_id);
     }
-    
-    #[cfg(not(feature = "docker"))]
-    {
-        let _ = sandbox_id; // Suppress unused warning
+
+    /// Count the bodies of a request forwarded to a sandbox and of its response
+    fn meter_traffic(&self, sandbox_id: &str, request_bytes: usize, response_bytes: usize) {
+        if let Some(meter) = &self.meter {
+            meter.proxy_bytes(sandbox_id, (request_bytes + response_bytes) as u64);
+        }
     }
-    
-    None
+}
+
+fn no_published_port(sandbox_id: &str) -> ApiError {
+    ApiError::not_found(format!("Sandbox {} does not publish a service", sandbox_id))
 }
 
 /// Proxy handler for sandbox web services
@@ -120,15 +118,8 @@ pub async fn proxy_handler(
     Path((sandbox_id, remainder)): Path<(String, String)>,
     State(state): State<ProxyState>,
     req: Request,
-) -> Result<Response, StatusCode> {
-    // Try to get port from port allocator first
-    let port = if let Some(port) = state.port_allocator.get_port(&sandbox_id).await {
-        port
-    } else {
-        // Fallback: inspect Docker container to find mapped port
-        get_container_port(&sandbox_id).await
-            .ok_or(StatusCode::NOT_FOUND)?
-    };
+) -> Result<Response, ApiError> {
+    let (host, port) = state.target(&sandbox_id).await?;
 
     // Build the target URL - strip the proxy prefix and use the remainder
     let target_path = if remainder.is_empty() { 
@@ -138,53 +129,9 @@ pub async fn proxy_handler(
     };
     let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
     
-    let target_url = format!("http://127.0.0.1:{}{}{}", port, target_path, query);
-    
-    // Forward the request using reqwest
-    let method = req.method().clone();
-    let headers = req.headers().clone();
-    let body = axum::body::to_bytes(req.into_body(), usize::MAX)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let method_str = method.as_str();
-    let mut request_builder = state.client.request(reqwest::Method::from_bytes(method_str.as_bytes()).unwrap(), &target_url);
-    
-    // Copy headers (convert from axum to reqwest)
-    for (name, value) in headers {
-        if let Some(name) = name {
-            if let Ok(value_str) = value.to_str() {
-                request_builder = request_builder.header(name.as_str(), value_str);
-            }
-        }
-    }
-    
-    // Send the request
-    let response = request_builder
-        .body(body)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Proxy request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-    
-    // Build the response
-    let mut response_builder = Response::builder()
-        .status(response.status().as_u16());
-    
-    // Copy response headers (convert from reqwest to axum)
-    for (name, value) in response.headers() {
-        if let Ok(value_str) = value.to_str() {
-            response_builder = response_builder.header(name.as_str(), value_str);
-        }
-    }
-    
-    let body = response.bytes().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let target_url = format!("http://{}:{}{}{}", host, port, target_path, query);
     
-    response_builder
-        .body(axum::body::Body::from(body))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    forward_request(state, &sandbox_id, req, target_url, None).await
 }
 
 /// Proxy handler for sandbox web services (no trailing path)
@@ -192,231 +139,423 @@ pub async fn proxy_handler_root(
     Path(sandbox_id): Path<String>,
     State(state): State<ProxyState>,
     req: Request,
-) -> Result<Response, StatusCode> {
-    // Try to get port from port allocator first
-    let port = if let Some(port) = state.port_allocator.get_port(&sandbox_id).await {
-        port
-    } else {
-        // Fallback: inspect Docker container to find mapped port
-        get_container_port(&sandbox_id).await
-            .ok_or(StatusCode::NOT_FOUND)?
-    };
+) -> Result<Response, ApiError> {
+    let (host, port) = state.target(&sandbox_id).await?;
 
     // Build the target URL - default to root path
     let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
-    let target_url = format!("http://127.0.0.1:{}{}", port, query);
-    
-    // Forward the request using reqwest
-    let method = req.method().clone();
-    let headers = req.headers().clone();
-    let body = axum::body::to_bytes(req.into_body(), usize::MAX)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let method_str = method.as_str();
-    let mut request_builder = state.client.request(reqwest::Method::from_bytes(method_str.as_bytes()).unwrap(), &target_url);
-    
-    // Copy headers (convert from axum to reqwest)
-    for (name, value) in headers {
-        if let Some(name) = name {
-            if let Ok(value_str) = value.to_str() {
-                request_builder = request_builder.header(name.as_str(), value_str);
-            }
-        }
-    }
-    
-    // Send the request
-    let response = request_builder
-        .body(body)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Proxy request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-    
-    // Build the response
-    let mut response_builder = Response::builder()
-        .status(response.status().as_u16());
-    
-    // Copy response headers (convert from reqwest to axum)
-    for (name, value) in response.headers() {
-        if let Ok(value_str) = value.to_str() {
-            response_builder = response_builder.header(name.as_str(), value_str);
-        }
-    }
+    let target_url = format!("http://{}:{}{}", host, port, query);
     
-    let body = response.bytes().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    response_builder
-        .body(axum::body::Body::from(body))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    forward_request(state, &sandbox_id, req, target_url, None).await
 }
 
 
 /// Create the proxy router
 pub fn create_proxy_router(state: ProxyState) -> Router {
+    let faas_routes = Router::new()
+        .route("/faas/:deployment_id", any(faas_proxy_handler_root))
+        .route("/faas/:deployment_id/*remainder", any(faas_proxy_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), limit_deployment_concurrency))
+        .route_layer(middleware::from_fn_with_state(state.clone(), apply_deployment_cors))
+        .route_layer(middleware::from_fn_with_state(state.clone(), record_deployment_traffic));
+
     Router::new()
         .route("/proxy/:sandbox_id", any(proxy_handler_root))
         .route("/proxy/:sandbox_id/*remainder", any(proxy_handler))
-        .route("/faas/:deployment_id", any(faas_proxy_handler_root))
-        .route("/faas/:deployment_id/*remainder", any(faas_proxy_handler))
+        .merge(faas_routes)
         .with_state(state)
 }
 
-/// FaaS proxy handler for root path
-pub async fn faas_proxy_handler_root(
-    Path(deployment_id): Path<String>,
+/// Count each request to a deployment, with its latency and body sizes, in the deployment's
+/// traffic stats, add it to the deployment's access log, and mark the deployment active
+async fn record_deployment_traffic(
     State(state): State<ProxyState>,
+    Path(params): Path<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     req: Request,
-) -> Result<Response, StatusCode> {
-    info!("[PROXY] FaaS root request - Deployment: {}", deployment_id);
-    
-    // Get sandbox ID from FaaS manager
-    let sandbox_id = if let Some(ref faas_manager) = state.faas_manager {
-        match faas_manager.get_deployment_for_proxy(&deployment_id).await {
-            Some(id) => {
-                info!("[PROXY] Found sandbox {} for deployment {}", id, deployment_id);
-                id
-            }
-            None => {
-                error!("[PROXY] Deployment {} not found", deployment_id);
-                return Err(StatusCode::NOT_FOUND);
-            }
+    next: Next,
+) -> Response {
+    let deployment_id = params.get("deployment_id").map(String::as_str).unwrap_or_default();
+    let traffic = match &state.faas_manager {
+        Some(faas_manager) => faas_manager.traffic(deployment_id).await,
+        None => None,
+    };
+    let Some(traffic) = traffic else {
+        return next.run(req).await;
+    };
+
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let request_path = access_log::deployment_path(req.uri().path(), deployment_id);
+    // Deployment access tokens in the query stay out of the log
+    let path = match access::strip_token(req.uri().query()) {
+        Some(query) => format!("{}?{}", request_path, query),
+        None => request_path.clone(),
+    };
+    let client_ip = forwarded_for(req.headers()).unwrap_or_else(|| addr.ip().to_string());
+    let request_bytes = body_size(req.headers(), req.body());
+
+    let response = next.run(req).await;
+
+    let latency = start.elapsed();
+    let status = response.status().as_u16();
+    let response_bytes = body_size(response.headers(), response.body());
+    traffic.stats.record(status, latency, request_bytes, response_bytes);
+    traffic.touch(&request_path).await;
+    traffic.access_log.record(AccessLogEntry {
+        timestamp: Utc::now(),
+        method,
+        path,
+        status,
+        latency_ms: latency.as_secs_f64() * 1000.0,
+        client_ip,
+    });
+    response
+}
+
+/// Answer preflight requests to a deployment with a CORS policy, and give its responses
+/// the policy's headers in place of the service's
+async fn apply_deployment_cors(
+    State(state): State<ProxyState>,
+    Path(params): Path<HashMap<String, String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let deployment_id = params.get("deployment_id").map(String::as_str).unwrap_or_default();
+    let policy = match &state.faas_manager {
+        Some(faas_manager) => faas_manager.cors_policy(deployment_id).await,
+        None => None,
+    };
+    let Some(policy) = policy else {
+        return next.run(req).await;
+    };
+
+    let origin = req.headers().get(header::ORIGIN).cloned();
+    if let Some(origin) = &origin {
+        if cors::is_preflight(req.method(), req.headers()) {
+            return policy.preflight(origin, req.headers());
         }
-    } else {
-        error!("[PROXY] FaaS manager not available");
-        return Err(StatusCode::NOT_FOUND);
+    }
+    let mut response = next.run(req).await;
+    policy.apply(origin.as_ref(), response.headers_mut());
+    response
+}
+
+/// Hold requests to a deployment with `max_concurrent_requests` to its limit, queueing the
+/// excess and answering 429 once the queue is full
+async fn limit_deployment_concurrency(
+    State(state): State<ProxyState>,
+    Path(params): Path<HashMap<String, String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let deployment_id = params.get("deployment_id").map(String::as_str).unwrap_or_default();
+    let limit = match &state.faas_manager {
+        Some(faas_manager) => faas_manager.concurrency_limit(deployment_id).await,
+        None => None,
+    };
+    let Some(limit) = limit else {
+        return next.run(req).await;
     };
 
-    // Get port
-    let port = if let Some(port) = state.port_allocator.get_port(&sandbox_id).await {
-        info!("[PROXY] Using allocated port {} for sandbox {}", port, sandbox_id);
-        port
-    } else {
-        info!("[PROXY] No allocated port for sandbox {}, checking container", sandbox_id);
-        match get_container_port(&sandbox_id).await {
-            Some(port) => {
-                info!("[PROXY] Found container port {} for sandbox {}", port, sandbox_id);
-                port
-            }
-            None => {
-                error!("[PROXY] No port found for sandbox {}", sandbox_id);
-                return Err(StatusCode::NOT_FOUND);
-            }
+    match limit.acquire().await {
+        Some(_permit) => next.run(req).await,
+        None => {
+            warn!("[PROXY] Deployment {} is at its concurrency limit, rejecting request", deployment_id);
+            let message = format!("Deployment {} is busy, retry later", deployment_id);
+            let mut response = ApiError::TooManyRequests(message).into_response();
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(1));
+            response
         }
+    }
+}
+
+/// Client address a reverse proxy in front of the service reported
+fn forwarded_for(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get("x-forwarded-for")?.to_str().ok()?;
+    let client = value.split(',').next()?.trim();
+    (!client.is_empty()).then(|| client.to_string())
+}
+
+/// Size of a body known up front, or else its `Content-Length`
+fn body_size(headers: &HeaderMap, body: &Body) -> u64 {
+    body.size_hint().exact()
+        .or_else(|| headers.get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Files of a static site deployment, which the proxy serves itself
+async fn static_site(state: &ProxyState, deployment_id: &str) -> Option<Arc<StaticSite>> {
+    state.faas_manager.as_ref()?.static_site(deployment_id).await
+}
+
+/// Request rewrites of a deployment, if it sets any
+async fn deployment_routing(state: &ProxyState, deployment_id: &str) -> Option<Arc<Routing>> {
+    state.faas_manager.as_ref()?.routing(deployment_id).await
+}
+
+/// Redirect to HTTPS a deployment with `https_redirect` answers a plain HTTP request with
+fn https_redirect(routing: Option<&Routing>, req: &Request, base_url: &str) -> Option<Response> {
+    let path_and_query = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    routing?.redirect(req.headers(), base_url, path_and_query)
+}
+
+/// Give a client without a session of a sticky deployment the one it was routed by
+fn hand_out_session(response: &mut Response, lease: &InstanceLease) {
+    if let Some(value) = lease.set_cookie.as_deref().and_then(|cookie| HeaderValue::from_str(cookie).ok()) {
+        response.headers_mut().append(header::SET_COOKIE, value);
+    }
+}
+
+/// Reject requests to a token-protected deployment without its token, and take the
+/// token out of the request so the deployed service never sees it
+async fn check_access_token(state: &ProxyState, deployment_id: &str, req: &mut Request) -> Result<(), ApiError> {
+    let Some(faas_manager) = &state.faas_manager else {
+        return Ok(());
+    };
+    let Some(expected) = faas_manager.access_token_hash(deployment_id).await else {
+        return Ok(());
     };
 
-    // Build target URL
-    let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
-    let target_url = format!("http://127.0.0.1:{}{}", port, query);
-    
-    info!("[PROXY] Forwarding root to: {}", target_url);
-    forward_request(state, req, target_url).await
+    if access::bearer(req.headers()).is_some_and(|token| access::matches(&expected, &access::hash(token))) {
+        req.headers_mut().remove(header::AUTHORIZATION);
+        return Ok(());
+    }
+    if access::from_query(req.uri().query()).is_some_and(|token| access::matches(&expected, &access::hash(token))) {
+        let path = req.uri().path();
+        let path_and_query = match access::strip_token(req.uri().query()) {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        let mut parts = req.uri().clone().into_parts();
+        parts.path_and_query = Some(path_and_query.parse().map_err(|_| ApiError::internal("Invalid request URI"))?);
+        *req.uri_mut() = Uri::from_parts(parts).map_err(|_| ApiError::internal("Invalid request URI"))?;
+        return Ok(());
+    }
+
+    warn!("[PROXY] Rejected request to deployment {} without its access token", deployment_id);
+    Err(ApiError::Unauthorized(format!(
+        "Deployment {} requires its access token as a bearer token or the {} query parameter",
+        deployment_id, access::ACCESS_TOKEN_PARAM
+    )))
+}
+
+/// FaaS proxy handler for root path
+pub async fn faas_proxy_handler_root(
+    Path(deployment_id): Path<String>,
+    State(state): State<ProxyState>,
+    BaseUrl(base_url): BaseUrl,
+    req: Request,
+) -> Result<Response, ApiError> {
+    info!("[PROXY] FaaS root request - Deployment: {}", deployment_id);
+    proxy_to_deployment(state, &deployment_id, "/", &base_url, req).await
 }
 
 /// FaaS proxy handler with path
 pub async fn faas_proxy_handler(
     Path((deployment_id, remainder)): Path<(String, String)>,
     State(state): State<ProxyState>,
+    BaseUrl(base_url): BaseUrl,
     req: Request,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     info!("[PROXY] FaaS request - Deployment: {}, Path: {}", deployment_id, remainder);
+    proxy_to_deployment(state, &deployment_id, &remainder, &base_url, req).await
+}
+
+/// Serve a request for `path` of a deployment: redirect it to HTTPS, check its access
+/// token and routing, then answer from the static site or response cache, or forward it
+/// to an instance leased for the request
+async fn proxy_to_deployment(
+    state: ProxyState,
+    deployment_id: &str,
+    path: &str,
+    base_url: &str,
+    mut req: Request,
+) -> Result<Response, ApiError> {
+    let routing = deployment_routing(&state, deployment_id).await;
+    if let Some(redirect) = https_redirect(routing.as_deref(), &req, base_url) {
+        return Ok(redirect);
+    }
+    check_access_token(&state, deployment_id, &mut req).await?;
+    let path = match &routing {
+        Some(routing) => routing.rewrite(req.headers_mut(), path),
+        None => path.to_string(),
+    };
     
-    // Get sandbox ID from FaaS manager
-    let sandbox_id = if let Some(ref faas_manager) = state.faas_manager {
-        match faas_manager.get_deployment_for_proxy(&deployment_id).await {
-            Some(id) => {
-                info!("[PROXY] Found sandbox {} for deployment {}", id, deployment_id);
-                id
+    if let Some(site) = static_site(&state, deployment_id).await {
+        return Ok(site.serve(req.method(), path.trim_start_matches('/')));
+    }
+
+    // Serve fresh cached responses without touching the sandbox
+    let cache = match state.faas_manager {
+        Some(ref faas_manager) => faas_manager.response_cache(deployment_id).await,
+        None => None,
+    };
+    let upgrade = websocket::is_upgrade(&req);
+    let cache_key = cache.as_ref()
+        .filter(|_| !upgrade)
+        .and_then(|_| ResponseCache::cache_key(&req));
+    if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+        if let Some(response) = cache.get(key) {
+            info!("[PROXY] Cache hit for deployment {}: {}", deployment_id, key);
+            return Ok(response);
+        }
+    }
+
+    // Get an instance lease from FaaS manager; held until the request has been forwarded
+    let session = affinity::session(req.headers());
+    affinity::strip_cookie(req.headers_mut());
+    let lease = if let Some(ref faas_manager) = state.faas_manager {
+        match faas_manager.get_deployment_for_proxy(deployment_id, session.as_deref()).await {
+            Some(lease) => {
+                info!("[PROXY] Found sandbox {} for deployment {}", lease.sandbox_id, deployment_id);
+                lease
             }
             None => {
                 error!("[PROXY] Deployment {} not found", deployment_id);
-                return Err(StatusCode::NOT_FOUND);
+                return Err(ApiError::not_found(format!("Deployment {} not found", deployment_id)));
             }
         }
     } else {
         error!("[PROXY] FaaS manager not available");
-        return Err(StatusCode::NOT_FOUND);
+        return Err(ApiError::not_found(format!("Deployment {} not found", deployment_id)));
     };
+    let sandbox_id = &lease.sandbox_id;
 
-    // Get port
-    let port = if let Some(port) = state.port_allocator.get_port(&sandbox_id).await {
-        info!("[PROXY] Using allocated port {} for sandbox {}", port, sandbox_id);
-        port
-    } else {
-        info!("[PROXY] No allocated port for sandbox {}, checking container", sandbox_id);
-        match get_container_port(&sandbox_id).await {
-            Some(port) => {
-                info!("[PROXY] Found container port {} for sandbox {}", port, sandbox_id);
-                port
-            }
-            None => {
-                error!("[PROXY] No port found for sandbox {}", sandbox_id);
-                return Err(StatusCode::NOT_FOUND);
-            }
-        }
-    };
+    let (host, port) = state.target(sandbox_id).await?;
+    info!("[PROXY] Using {}:{} for sandbox {}", host, port, sandbox_id);
 
     // Build target URL
-    let target_path = if remainder.starts_with('/') { &remainder } else { &format!("/{}", remainder) };
+    let target_path = if path.starts_with('/') { path } else { format!("/{}", path) };
     let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
-    let target_url = format!("http://127.0.0.1:{}{}{}", port, target_path, query);
-    
+    let target_url = format!("http://{}:{}{}{}", host, port, target_path, query);
+    
+    if upgrade {
+        info!("[PROXY] Opening WebSocket to: {}", target_url);
+        let traffic = match state.faas_manager {
+            Some(ref faas_manager) => faas_manager.traffic(deployment_id).await,
+            None => None,
+        };
+        let request_path = access_log::deployment_path(req.uri().path(), deployment_id);
+        let timeout = state.config.timeout_for(req.uri().path());
+        let client_ip = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+        return websocket::forward(req, client_ip, &target_url, timeout, lease, traffic, request_path).await;
+    }
+
     info!("[PROXY] Forwarding to: {}", target_url);
-    forward_request(state, req, target_url).await
+    let cache = cache.zip(cache_key);
+    let mut response = forward_request(state, sandbox_id, req, target_url, cache).await?;
+    hand_out_session(&mut response, &lease);
+    Ok(response)
 }
 
-/// Helper function to forward requests
+/// Helper function to forward requests, storing cacheable responses under the given key
 async fn forward_request(
     state: ProxyState,
+    sandbox_id: &str,
     req: Request,
     target_url: String,
-) -> Result<Response, StatusCode> {
+    cache: Option<(Arc<ResponseCache>, String)>,
+) -> Result<Response, ApiError> {
+    if let Err(retry_after) = state.breakers.admit(sandbox_id) {
+        return Err(ApiError::Unavailable {
+            message: format!("Sandbox {} is not accepting connections, retry later", sandbox_id),
+            retry_after: (retry_after.as_millis() as u64).div_ceil(1000).max(1),
+        });
+    }
+
+    let timeout = state.config.timeout_for(req.uri().path());
+    let client_ip = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    if h2::is_grpc(&req) {
+        let forwarded = h2::forward(&state.h2_client, state.meter.clone(), sandbox_id, req, client_ip, &target_url, timeout).await;
+        return match forwarded {
+            h2::Forwarded::Response(response) => {
+                state.breakers.succeeded(sandbox_id);
+                Ok(response)
+            }
+            h2::Forwarded::ConnectFailed(e) => {
+                if state.breakers.failed(sandbox_id) {
+                    warn!("[PROXY] Sandbox {} keeps refusing connections, opening its circuit", sandbox_id);
+                }
+                Err(e)
+            }
+            h2::Forwarded::Failed(e) => Err(e),
+        };
+    }
+
     let method = req.method().clone();
-    let headers = req.headers().clone();
+    let headers = headers::forwarded_request_headers(req.headers(), client_ip);
     let body = axum::body::to_bytes(req.into_body(), usize::MAX)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::internal(format!("Failed to read request body: {}", e)))?;
     
     let method_str = method.as_str();
     let mut request_builder = state.client.request(
         reqwest::Method::from_bytes(method_str.as_bytes()).unwrap(), 
         &target_url
-    );
+    ).timeout(timeout);
     
-    // Copy headers
-    for (name, value) in headers {
-        if let Some(name) = name {
-            if let Ok(value_str) = value.to_str() {
-                request_builder = request_builder.header(name.as_str(), value_str);
-            }
+    // Copy end-to-end headers
+    for (name, value) in &headers {
+        if let Ok(value_str) = value.to_str() {
+            request_builder = request_builder.header(name.as_str(), value_str);
         }
     }
     
     // Send request
-    let response = request_builder
-        .body(body)
-        .send()
-        .await
-        .map_err(|e| {
+    let request_bytes = body.len();
+    let response = match request_builder.body(body).send().await {
+        Ok(response) => {
+            state.breakers.succeeded(sandbox_id);
+            response
+        }
+        Err(e) => {
             error!("Proxy request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
+            if e.is_connect() && state.breakers.failed(sandbox_id) {
+                warn!("[PROXY] Sandbox {} keeps refusing connections, opening its circuit", sandbox_id);
+            }
+            return Err(upstream_error(e, timeout));
+        }
+    };
     
     // Build response
+    let status = response.status().as_u16();
     let mut response_builder = Response::builder()
-        .status(response.status().as_u16());
+        .status(status);
     
+    let mut response_headers = Vec::new();
+    let connection_tokens = headers::connection_tokens(
+        response.headers().get_all("connection").iter().filter_map(|value| value.to_str().ok()),
+    );
     for (name, value) in response.headers() {
+        if headers::is_hop_by_hop(name.as_str(), &connection_tokens) {
+            continue;
+        }
         if let Ok(value_str) = value.to_str() {
             response_builder = response_builder.header(name.as_str(), value_str);
+            response_headers.push((name.as_str().to_string(), value_str.to_string()));
         }
     }
     
-    let body = response.bytes().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let body = response.bytes().await.map_err(|e| upstream_error(e, timeout))?;
+    state.meter_traffic(sandbox_id, request_bytes, body.len());
     
-    response_builder
-        .body(axum::body::Body::from(body))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
\ No newline at end of file
+    let mut response = response_builder
+        .body(axum::body::Body::from(body.clone()))
+        .map_err(|e| ApiError::internal(format!("Failed to build proxied response: {}", e)))?;
+
+    if let Some((cache, key)) = cache {
+        cache.put(key, status, response_headers, body);
+        cache::mark_miss(&mut response);
+    }
+    Ok(response)
+}
+
+/// Error answering a request the sandboxed service failed, or took longer than `timeout`, to answer
+fn upstream_error(e: reqwest::Error, timeout: Duration) -> ApiError {
+    if e.is_timeout() && !e.is_connect() {
+        ApiError::GatewayTimeout(format!("Sandboxed service did not answer within {}s", timeout.as_secs()))
+    } else {
+        ApiError::BadGateway(format!("Sandboxed service did not answer: {}", e))
+    }
+}
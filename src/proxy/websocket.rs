@@ -0,0 +1,182 @@
+//! WebSocket connections to deployments through the proxy
+//!
+//! An upgrade request is accepted once the instance accepted a WebSocket connection of
+//! its own, with the subprotocol the instance chose. Text, binary and close messages are
+//! then relayed both ways until either side goes away; pings are answered on each leg.
+//! The instance lease is held while the connection is open, so it counts as a request in
+//! flight, and each relayed message marks the deployment active.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{self, WebSocket, WebSocketUpgrade},
+        FromRequestParts, Request,
+    },
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest, protocol::frame::coding::CloseCode};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::info;
+
+use super::headers;
+use crate::api::error::ApiError;
+use crate::faas::{InstanceLease, ProxiedTraffic};
+
+type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Whether the request asks to upgrade to a WebSocket connection
+pub fn is_upgrade(req: &Request) -> bool {
+    req.headers().get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Connect to `target_url` and relay the upgraded request's connection to it; `timeout`
+/// bounds the instance's handshake. `path` is the deployment path messages touch.
+pub async fn forward(
+    req: Request,
+    client_ip: Option<IpAddr>,
+    target_url: &str,
+    timeout: Duration,
+    lease: InstanceLease,
+    traffic: Option<ProxiedTraffic>,
+    path: String,
+) -> Result<Response, ApiError> {
+    let (mut parts, _) = req.into_parts();
+    let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let url = target_url.replacen("http://", "ws://", 1);
+    let mut request = url.as_str().into_client_request()
+        .map_err(|e| ApiError::internal(format!("Invalid WebSocket target {}: {}", url, e)))?;
+    // The handshake headers are the client library's own, all but the subprotocols offered
+    for (name, value) in headers::forwarded_request_headers(&parts.headers, client_ip).iter() {
+        if !name.as_str().starts_with("sec-websocket-") || name == header::SEC_WEBSOCKET_PROTOCOL {
+            request.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+
+    let (upstream, response) = match tokio::time::timeout(timeout, tokio_tungstenite::connect_async(request)).await {
+        Ok(Ok(connected)) => connected,
+        Ok(Err(e)) => {
+            return Err(ApiError::BadGateway(format!(
+                "Sandbox {} did not accept the WebSocket connection: {}", lease.sandbox_id, e
+            )));
+        }
+        Err(_) => {
+            return Err(ApiError::GatewayTimeout(format!(
+                "Sandbox {} did not accept the WebSocket connection within {}ms", lease.sandbox_id, timeout.as_millis()
+            )));
+        }
+    };
+    let protocol = response.headers().get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let upgrade = match protocol {
+        Some(protocol) => upgrade.protocols([protocol]),
+        None => upgrade,
+    };
+
+    info!("[PROXY] WebSocket connection to sandbox {} opened", lease.sandbox_id);
+    Ok(upgrade.on_upgrade(move |socket| relay(socket, upstream, lease, traffic, path)))
+}
+
+/// Pass messages between the client and the instance until either closes
+async fn relay(client: WebSocket, upstream: Upstream, lease: InstanceLease, traffic: Option<ProxiedTraffic>, path: String) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+
+    loop {
+        let relayed = tokio::select! {
+            message = client_rx.next() => {
+                let Some(Ok(message)) = message else { break };
+                match to_upstream(message) {
+                    Some(message) => upstream_tx.send(message).await.is_ok(),
+                    None => continue,
+                }
+            }
+            message = upstream_rx.next() => {
+                let Some(Ok(message)) = message else { break };
+                match to_client(message) {
+                    Some(message) => client_tx.send(message).await.is_ok(),
+                    None => continue,
+                }
+            }
+        };
+        if !relayed {
+            break;
+        }
+        if let Some(traffic) = &traffic {
+            traffic.touch(&path).await;
+        }
+    }
+
+    let _ = client_tx.close().await;
+    let _ = upstream_tx.close().await;
+    info!("[PROXY] WebSocket connection to sandbox {} closed", lease.sandbox_id);
+}
+
+/// Client message to relay to the instance; pings and pongs stay on their leg
+fn to_upstream(message: ws::Message) -> Option<tungstenite::Message> {
+    match message {
+        ws::Message::Text(text) => Some(tungstenite::Message::Text(text)),
+        ws::Message::Binary(data) => Some(tungstenite::Message::Binary(data)),
+        ws::Message::Close(frame) => Some(tungstenite::Message::Close(frame.map(|frame| {
+            tungstenite::protocol::CloseFrame { code: CloseCode::from(frame.code), reason: frame.reason }
+        }))),
+        ws::Message::Ping(_) | ws::Message::Pong(_) => None,
+    }
+}
+
+/// Instance message to relay to the client; pings and pongs stay on their leg
+fn to_client(message: tungstenite::Message) -> Option<ws::Message> {
+    match message {
+        tungstenite::Message::Text(text) => Some(ws::Message::Text(text)),
+        tungstenite::Message::Binary(data) => Some(ws::Message::Binary(data)),
+        tungstenite::Message::Close(frame) => Some(ws::Message::Close(frame.map(|frame| {
+            ws::CloseFrame { code: frame.code.into(), reason: frame.reason }
+        }))),
+        tungstenite::Message::Ping(_) | tungstenite::Message::Pong(_) | tungstenite::Message::Frame(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_upgrade() {
+        let request = |upgrade: Option<&str>| {
+            let mut builder = Request::builder().uri("/faas/d/socket");
+            if let Some(upgrade) = upgrade {
+                builder = builder.header(header::CONNECTION, "Upgrade").header(header::UPGRADE, upgrade);
+            }
+            builder.body(axum::body::Body::empty()).unwrap()
+        };
+        assert!(is_upgrade(&request(Some("websocket"))));
+        assert!(is_upgrade(&request(Some("WebSocket"))));
+        assert!(!is_upgrade(&request(Some("h2c"))));
+        assert!(!is_upgrade(&request(None)));
+    }
+
+    #[test]
+    fn test_close_frame_is_relayed() {
+        let frame = ws::CloseFrame { code: 4001, reason: "bye".into() };
+        let Some(tungstenite::Message::Close(Some(upstream))) = to_upstream(ws::Message::Close(Some(frame))) else {
+            panic!("close frame was not relayed");
+        };
+        assert_eq!(u16::from(upstream.code), 4001);
+        let Some(ws::Message::Close(Some(client))) = to_client(tungstenite::Message::Close(Some(upstream))) else {
+            panic!("close frame was not relayed");
+        };
+        assert_eq!((client.code, client.reason.as_ref()), (4001, "bye"));
+        assert!(to_upstream(ws::Message::Ping(vec![1])).is_none());
+    }
+}
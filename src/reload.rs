@@ -0,0 +1,137 @@
+//! Runtime reload of the configuration on SIGHUP or `POST /admin/api/config/reload`
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::api::AppState;
+use crate::config::Config;
+
+/// Configuration currently in effect, shared with the modules that read it
+pub type SharedConfig = Arc<ArcSwap<Config>>;
+
+type Loader = Box<dyn Fn() -> Result<Config> + Send + Sync>;
+type LogLevelSetter = Box<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+/// Outcome of a reload, as `section.field` names of the settings that changed
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    pub applied: Vec<String>,
+    /// Changed settings that only take effect after a restart
+    pub requires_restart: Vec<String>,
+}
+
+/// Re-reads the configuration the service started from and applies what can change at runtime
+pub struct ConfigReloader {
+    config: SharedConfig,
+    /// Where the configuration is read from, for operators
+    source: String,
+    load: Loader,
+    set_log_level: LogLevelSetter,
+    sandbox_manager: AppState,
+}
+
+impl ConfigReloader {
+    /// `load` reads the configuration the way startup did, including command line overrides
+    pub fn new(
+        config: SharedConfig,
+        source: String,
+        load: impl Fn() -> Result<Config> + Send + Sync + 'static,
+        set_log_level: impl Fn(&str) -> Result<()> + Send + Sync + 'static,
+        sandbox_manager: AppState,
+    ) -> Self {
+        Self {
+            config,
+            source,
+            load: Box::new(load),
+            set_log_level: Box::new(set_log_level),
+            sandbox_manager,
+        }
+    }
+
+    /// Configuration in effect, after command line overrides and reloads
+    pub fn current(&self) -> Arc<Config> {
+        self.config.load_full()
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        let loaded = (self.load)()?;
+        let current = self.config.load_full();
+
+        // Only the log level, capacity limits, rate limit and prices change; the rest keep
+        // the values the service runs with
+        let mut effective = (*current).clone();
+        effective.logging.level = loaded.logging.level.clone();
+        effective.server.rate_limit_per_minute = loaded.server.rate_limit_per_minute;
+        effective.sandbox.max_concurrent_sandboxes = loaded.sandbox.max_concurrent_sandboxes;
+        effective.sandbox.max_total_memory_mb = loaded.sandbox.max_total_memory_mb;
+        effective.pricing = loaded.pricing.clone();
+
+        if effective.logging.level != current.logging.level {
+            (self.set_log_level)(&effective.logging.level)?;
+        }
+        self.sandbox_manager.set_limits(effective.sandbox.capacity_limits());
+        self.sandbox_manager.meter().set_pricing(effective.pricing.clone());
+
+        let report = ReloadReport {
+            applied: changed_settings(&current, &effective)?,
+            requires_restart: changed_settings(&effective, &loaded)?,
+        };
+        self.config.store(Arc::new(effective));
+        info!(
+            "Configuration reloaded; applied: {:?}, requires restart: {:?}",
+            report.applied, report.requires_restart
+        );
+        Ok(report)
+    }
+}
+
+/// `section.field` names of the settings that differ between two configurations, and
+/// the names of top-level settings such as `store`
+fn changed_settings(old: &Config, new: &Config) -> Result<Vec<String>> {
+    let (old, new) = (serde_json::to_value(old)?, serde_json::to_value(new)?);
+    let mut changed = Vec::new();
+    if let (Value::Object(old_sections), Value::Object(new_sections)) = (&old, &new) {
+        for (section, new_fields) in new_sections {
+            let (Some(Value::Object(old_fields)), Value::Object(new_fields)) = (old_sections.get(section), new_fields) else {
+                if old_sections.get(section) != Some(new_fields) {
+                    changed.push(section.clone());
+                }
+                continue;
+            };
+            for (field, value) in new_fields {
+                if old_fields.get(field) != Some(value) {
+                    changed.push(format!("{}.{}", section, field));
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_settings() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.logging.level = "debug".to_string();
+        new.server.port = 9000;
+        new.store = crate::coordination::StoreKind::Redis;
+
+        let changed = changed_settings(&old, &new).unwrap();
+        assert_eq!(changed.len(), 3);
+        assert!(changed.contains(&"store".to_string()));
+        assert!(changed.contains(&"logging.level".to_string()));
+        assert!(changed.contains(&"server.port".to_string()));
+    }
+}
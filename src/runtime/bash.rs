@@ -0,0 +1,12 @@
+//! Bash runtime: `code` is run as a shell script
+
+/// Bash version shell scripts run with
+pub const VERSION: &str = "5.2";
+
+pub const RUNTIME: &str = "bash";
+
+/// Where the request's `code` is written, relative to the project root
+pub const SCRIPT_FILE: &str = "index.sh";
+
+/// Command running the script, from the project root
+pub const RUN_COMMAND: &str = "bash index.sh";
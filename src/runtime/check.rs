@@ -0,0 +1,247 @@
+//! Check mode, `mode: "check"`: the code is typechecked or syntax checked instead of run
+//!
+//! The runtime's checker runs in the sandbox in place of the program, and what it prints
+//! is parsed into diagnostics. TypeScript is typechecked with `tsc`, Rust with
+//! `cargo check`; Bun, Node.js and Bash (including media scripts) only have their syntax
+//! checked.
+
+use voidrun_types::{Diagnostic, DiagnosticSeverity};
+
+use super::{bash, media, rust};
+
+/// Project directory sandboxes see their files under
+const PROJECT_ROOT: &str = "/sandbox/";
+
+/// Command checking the code written to `code_file`, run from the project root
+pub fn command(runtime: &str, code_file: &str) -> Option<String> {
+    Some(match super::name(runtime) {
+        "node" | "nodejs" => format!("node --check {}", code_file),
+        // Bun transpiles without typechecking, which still catches syntax errors
+        "bun" => format!("bun build --no-bundle {} --outdir /tmp/voidrun-check", code_file),
+        "typescript" | "ts" => format!("npx tsc --noEmit --pretty false {}", code_file),
+        rust::RUNTIME => "cargo check --quiet --offline --message-format short".to_string(),
+        bash::RUNTIME | media::RUNTIME => format!("bash -n {}", code_file),
+        _ => return None,
+    })
+}
+
+/// Diagnostics in the checker output of sandbox `sandbox_id`
+pub fn diagnostics(runtime: &str, sandbox_id: &str, stdout: &str, stderr: &str) -> Vec<Diagnostic> {
+    let output = format!("{}\n{}", stdout, stderr);
+    let relative = |path: &str| relative_path(path, sandbox_id);
+    match super::name(runtime) {
+        "node" | "nodejs" => parse_node(&output, relative),
+        "bun" => parse_bun(&output, relative),
+        "typescript" | "ts" => parse_tsc(&output, relative),
+        rust::RUNTIME => parse_cargo(&output, relative),
+        bash::RUNTIME | media::RUNTIME => parse_bash(&output, relative),
+        _ => Vec::new(),
+    }
+}
+
+/// Path relative to the project root, which is `/sandbox` in containers and a directory
+/// named after the sandbox on the host otherwise
+fn relative_path(path: &str, sandbox_id: &str) -> String {
+    let host_root = format!("/{}/", sandbox_id);
+    let path = match path.find(&host_root) {
+        Some(index) => &path[index + host_root.len()..],
+        None => path.strip_prefix(PROJECT_ROOT).unwrap_or(path),
+    };
+    path.trim_start_matches("./").to_string()
+}
+
+/// Leading `error` or `warning`, and what follows it
+fn severity(text: &str) -> Option<(DiagnosticSeverity, &str)> {
+    if let Some(rest) = text.strip_prefix("error") {
+        Some((DiagnosticSeverity::Error, rest))
+    } else {
+        text.strip_prefix("warning").map(|rest| (DiagnosticSeverity::Warning, rest))
+    }
+}
+
+/// `index.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.`
+fn parse_tsc(output: &str, relative: impl Fn(&str) -> String) -> Vec<Diagnostic> {
+    output.lines().filter_map(|line| {
+        let (location, rest) = line.split_once("): ")?;
+        let (file, position) = location.rsplit_once('(')?;
+        let (line, column) = position.split_once(',')?;
+        let (severity, rest) = severity(rest)?;
+        let (code, message) = rest.trim_start().split_once(": ")?;
+        Some(Diagnostic {
+            file: relative(file),
+            line: line.parse().ok(),
+            column: column.parse().ok(),
+            severity,
+            code: Some(code.to_string()),
+            message: message.to_string(),
+        })
+    }).collect()
+}
+
+/// `src/main.rs:2:5: error[E0425]: cannot find value `x` in this scope`
+fn parse_cargo(output: &str, relative: impl Fn(&str) -> String) -> Vec<Diagnostic> {
+    output.lines().filter_map(|line| {
+        let mut parts = line.splitn(4, ':');
+        let (file, line, column, rest) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let (severity, rest) = severity(rest.trim_start())?;
+        let (code, message) = match rest.strip_prefix('[') {
+            Some(rest) => {
+                let (code, message) = rest.split_once("]:")?;
+                (Some(code.to_string()), message)
+            }
+            None => (None, rest.strip_prefix(':')?),
+        };
+        Some(Diagnostic {
+            file: relative(file),
+            line: Some(line.parse().ok()?),
+            column: column.parse().ok(),
+            severity,
+            code,
+            message: message.trim().to_string(),
+        })
+    }).collect()
+}
+
+/// `index.sh: line 3: syntax error near unexpected token `fi'`
+fn parse_bash(output: &str, relative: impl Fn(&str) -> String) -> Vec<Diagnostic> {
+    output.lines().filter_map(|line| {
+        let (file, rest) = line.split_once(": line ")?;
+        let (line, message) = rest.split_once(": ")?;
+        // Bash follows each error with the offending source line in backquotes
+        if message.starts_with('`') {
+            return None;
+        }
+        Some(Diagnostic {
+            file: relative(file),
+            line: Some(line.parse().ok()?),
+            column: None,
+            severity: DiagnosticSeverity::Error,
+            code: None,
+            message: message.to_string(),
+        })
+    }).collect()
+}
+
+/// Node.js reports the first syntax error only:
+///
+/// ```text
+/// /sandbox/index.js:1
+/// let x = ;
+///         ^
+///
+/// SyntaxError: Unexpected token ';'
+/// ```
+fn parse_node(output: &str, relative: impl Fn(&str) -> String) -> Vec<Diagnostic> {
+    let location = |line: &str| {
+        let (file, number) = line.rsplit_once(':')?;
+        let number: u32 = number.parse().ok()?;
+        (!file.is_empty() && !file.contains(' ')).then(|| (file.to_string(), number))
+    };
+    let lines: Vec<&str> = output.lines().collect();
+    let Some(start) = lines.iter().position(|line| location(line).is_some()) else {
+        return Vec::new();
+    };
+    let (file, line) = location(lines[start]).unwrap_or_default();
+    let column = lines.get(start + 2).and_then(|caret| {
+        let caret = caret.trim_end();
+        let carets = caret.trim_start();
+        (!carets.is_empty() && carets.chars().all(|c| c == '^')).then(|| (caret.len() - carets.len()) as u32 + 1)
+    });
+    let error = lines[start..].iter().find_map(|line| {
+        let (name, message) = line.split_once(": ")?;
+        (name.ends_with("Error") && !name.contains(' ')).then_some((name, message))
+    });
+    let Some((name, message)) = error else {
+        return Vec::new();
+    };
+    vec![Diagnostic {
+        file: relative(&file),
+        line: Some(line),
+        column,
+        severity: DiagnosticSeverity::Error,
+        code: Some(name.to_string()),
+        message: message.to_string(),
+    }]
+}
+
+/// Bun prints each error before its location:
+///
+/// ```text
+/// 1 | let x: number = ;
+///                     ^
+/// error: Unexpected ;
+///     at /sandbox/index.ts:1:17
+/// ```
+fn parse_bun(output: &str, relative: impl Fn(&str) -> String) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut located = true;
+    for line in output.lines() {
+        if let Some((severity, message)) = severity(line).and_then(|(severity, rest)| Some((severity, rest.strip_prefix(": ")?))) {
+            diagnostics.push(Diagnostic {
+                file: String::new(),
+                line: None,
+                column: None,
+                severity,
+                code: None,
+                message: message.to_string(),
+            });
+            located = false;
+        } else if let (Some(location), false) = (line.trim_start().strip_prefix("at "), located) {
+            let mut parts = location.rsplitn(3, ':');
+            let (column, line, file) = (parts.next(), parts.next(), parts.next());
+            if let (Some(diagnostic), Some(file)) = (diagnostics.last_mut(), file) {
+                diagnostic.file = relative(file);
+                diagnostic.line = line.and_then(|line| line.parse().ok());
+                diagnostic.column = column.and_then(|column| column.parse().ok());
+            }
+            located = true;
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(file: &str, line: u32, column: Option<u32>, code: Option<&str>, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: file.to_string(),
+            line: Some(line),
+            column,
+            severity: DiagnosticSeverity::Error,
+            code: code.map(str::to_string),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let tsc = "index.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.\n";
+        assert_eq!(diagnostics("typescript", "s1", tsc, ""), vec![
+            diagnostic("index.ts", 3, Some(7), Some("TS2322"), "Type 'string' is not assignable to type 'number'."),
+        ]);
+
+        let cargo = "src/main.rs:2:5: error[E0425]: cannot find value `x` in this scope\nerror: could not compile `sandbox`\n";
+        assert_eq!(diagnostics("rust", "s1", "", cargo), vec![
+            diagnostic("src/main.rs", 2, Some(5), Some("E0425"), "cannot find value `x` in this scope"),
+        ]);
+
+        let bash = "index.sh: line 3: syntax error near unexpected token `fi'\nindex.sh: line 3: `fi'\n";
+        assert_eq!(diagnostics("bash", "s1", "", bash), vec![
+            diagnostic("index.sh", 3, None, None, "syntax error near unexpected token `fi'"),
+        ]);
+
+        let node = "/sandbox/index.js:1\nlet x = ;\n        ^\n\nSyntaxError: Unexpected token ';'\n    at wrapSafe (node:internal)\n";
+        assert_eq!(diagnostics("node@20", "s1", "", node), vec![
+            diagnostic("index.js", 1, Some(9), Some("SyntaxError"), "Unexpected token ';'"),
+        ]);
+
+        let bun = "1 | let x: number = ;\n                    ^\nerror: Unexpected ;\n    at /tmp/sandboxes/s1/index.ts:1:17\n";
+        assert_eq!(diagnostics("bun", "s1", "", bun), vec![
+            diagnostic("index.ts", 1, Some(17), None, "Unexpected ;"),
+        ]);
+
+        assert!(diagnostics("typescript", "s1", "", "").is_empty());
+    }
+}
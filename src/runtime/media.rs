@@ -0,0 +1,76 @@
+//! Media runtime: `code` is a shell script transforming audio, video and images with
+//! FFmpeg and ImageMagick
+//!
+//! Scripts run like Bash ones, on an image with the tools installed, which the Docker
+//! backend builds on first use unless the operator names a prebuilt one. Transcodes are
+//! slow and their files large, so the runtime defaults to more time, memory and disk, and
+//! whatever the script writes under `out/` is collected as artifacts.
+
+use crate::api::error::InvalidInput;
+
+pub const RUNTIME: &str = "media";
+
+/// Version of the tool set, bumped when the image changes
+pub const VERSION: &str = "1";
+
+/// Tools of the image, which requests can name in `tools` instead of the runtime
+pub const TOOLS: &[&str] = &["ffmpeg", "imagemagick"];
+
+/// Image name overriding the built one, e.g. an image from a private registry
+pub const IMAGE_ENV: &str = "VOIDRUN_MEDIA_IMAGE";
+/// Tag of the image the Docker backend builds from [`DOCKERFILE`]
+pub const BUILT_IMAGE: &str = "voidrun-media:1";
+pub const DOCKERFILE: &str = "FROM alpine:3.20\nRUN apk add --no-cache bash ffmpeg imagemagick\n";
+
+/// The request's `code` is written to `index.sh` like Bash code; `out/`, collected as
+/// artifacts, is created before it runs
+pub const RUN_COMMAND: &str = "mkdir -p out && bash index.sh";
+
+pub const TIMEOUT_MS: u64 = 300_000;
+pub const MEMORY_LIMIT_MB: u64 = 1024;
+pub const DISK_LIMIT_MB: u64 = 2048;
+
+pub const DEFAULT_ARTIFACTS: &[&str] = &["out/**"];
+
+pub fn is_media(runtime: &str) -> bool {
+    super::name(runtime) == RUNTIME
+}
+
+/// Image the backend runs, and whether it builds it rather than pulling it
+pub fn image() -> (String, bool) {
+    match std::env::var(IMAGE_ENV).ok().filter(|image| !image.is_empty()) {
+        Some(image) => (image, false),
+        None => (BUILT_IMAGE.to_string(), true),
+    }
+}
+
+/// Runtime of a request naming `tools`: scripts needing media tools run on the media
+/// runtime, which other runtimes cannot provide them on
+pub fn runtime_with_tools(runtime: &str, tools: Option<&[String]>) -> anyhow::Result<String> {
+    let Some(tools) = tools.filter(|tools| !tools.is_empty()) else {
+        return Ok(runtime.to_string());
+    };
+    if let Some(unknown) = tools.iter().find(|tool| !TOOLS.contains(&tool.as_str())) {
+        return Err(InvalidInput(format!("Unknown tool {}; available: {}", unknown, TOOLS.join(", "))).into());
+    }
+    match super::name(runtime) {
+        RUNTIME => Ok(runtime.to_string()),
+        super::bash::RUNTIME => Ok(RUNTIME.to_string()),
+        _ => Err(InvalidInput(format!("Tools are only available to bash scripts, not {}", runtime)).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_with_tools() {
+        assert_eq!(runtime_with_tools("node", None).unwrap(), "node");
+        assert_eq!(runtime_with_tools("bash", Some(&[])).unwrap(), "bash");
+        assert_eq!(runtime_with_tools("bash@5.2", Some(&["ffmpeg".to_string()])).unwrap(), RUNTIME);
+        assert_eq!(runtime_with_tools("media@1", Some(&["imagemagick".to_string()])).unwrap(), "media@1");
+        assert!(runtime_with_tools("bash", Some(&["sox".to_string()])).is_err());
+        assert!(runtime_with_tools("node", Some(&["ffmpeg".to_string()])).is_err());
+    }
+}
@@ -1,10 +1,206 @@
 use serde::{Deserialize, Serialize};
+use voidrun_types::RuntimeInfo;
 
+pub mod bash;
+pub mod check;
+pub mod media;
+pub mod playwright;
+pub mod rust;
+
+/// Runtime a sandbox can run, with the versions a request may pin as `<runtime>@<version>`
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeDefinition {
+    pub name: &'static str,
+    /// Other names accepted for the runtime
+    pub aliases: &'static [&'static str],
+    /// Version used when the request does not pin one
+    pub default_version: &'static str,
+    pub versions: &'static [&'static str],
+}
+
+impl RuntimeDefinition {
+    /// The runtime with all of its versions, before checking what the backend can run
+    pub fn info(&self) -> RuntimeInfo {
+        RuntimeInfo {
+            name: self.name.to_string(),
+            aliases: self.aliases.iter().map(|alias| alias.to_string()).collect(),
+            default_version: self.default_version.to_string(),
+            versions: self.versions.iter().map(|version| version.to_string()).collect(),
+            available: true,
+        }
+    }
+}
+
+/// TypeScript runs on the Node.js images, so it offers the same versions
+const NODE_VERSIONS: &[&str] = &["18", "20", "22"];
+
+pub const RUNTIMES: &[RuntimeDefinition] = &[
+    RuntimeDefinition {
+        name: "node",
+        aliases: &["nodejs"],
+        default_version: "18",
+        versions: NODE_VERSIONS,
+    },
+    RuntimeDefinition {
+        name: "bun",
+        aliases: &[],
+        default_version: "1",
+        versions: &["1", "1.0", "1.1"],
+    },
+    RuntimeDefinition {
+        name: "typescript",
+        aliases: &["ts"],
+        default_version: "18",
+        versions: NODE_VERSIONS,
+    },
+    RuntimeDefinition {
+        name: rust::RUNTIME,
+        aliases: &[],
+        default_version: rust::TOOLCHAIN_VERSION,
+        versions: &[rust::TOOLCHAIN_VERSION],
+    },
+    RuntimeDefinition {
+        name: bash::RUNTIME,
+        aliases: &[],
+        default_version: bash::VERSION,
+        versions: &[bash::VERSION],
+    },
+    RuntimeDefinition {
+        name: playwright::RUNTIME,
+        aliases: &[],
+        default_version: playwright::VERSION,
+        versions: playwright::VERSIONS,
+    },
+    RuntimeDefinition {
+        name: media::RUNTIME,
+        aliases: &[],
+        default_version: media::VERSION,
+        versions: &[media::VERSION],
+    },
+];
+
+/// Time limit of sandboxes not setting one
+pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+/// Memory limit of sandboxes not setting one
+pub const DEFAULT_MEMORY_LIMIT_MB: u64 = 512;
+
+/// The runtime or the version a request asked for is not available
+#[derive(Debug, Clone)]
+pub struct UnsupportedRuntime(pub String);
+
+impl std::fmt::Display for UnsupportedRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedRuntime {}
+
+/// Split a runtime such as `node@20` into its name and pinned version
+pub fn parse(runtime: &str) -> (&str, Option<&str>) {
+    match runtime.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (runtime, None),
+    }
+}
+
+/// Runtime name without its version pin
+pub fn name(runtime: &str) -> &str {
+    parse(runtime).0
+}
+
+/// Whether `runtime` passes a list filter; a filter without a version matches every version
+pub fn matches(runtime: &str, filter: &str) -> bool {
+    runtime == filter || (!filter.contains('@') && name(runtime) == filter)
+}
+
+pub fn find(name: &str) -> Option<&'static RuntimeDefinition> {
+    RUNTIMES.iter().find(|definition| definition.name == name || definition.aliases.contains(&name))
+}
+
+/// Version a request runs: its pin, or the runtime's default
+pub fn resolve_version(runtime: &str) -> Result<&'static str, UnsupportedRuntime> {
+    let (name, version) = parse(runtime);
+    let definition = find(name)
+        .ok_or_else(|| UnsupportedRuntime(format!("Unsupported runtime: {}", name)))?;
+    match version {
+        None => Ok(definition.default_version),
+        Some(version) => definition.versions.iter().copied().find(|v| *v == version).ok_or_else(|| {
+            UnsupportedRuntime(format!(
+                "Unsupported {} version {}; available: {}",
+                definition.name,
+                version,
+                definition.versions.join(", ")
+            ))
+        }),
+    }
+}
+
+/// Docker image running `runtime` at its resolved version, unless the request names one
+pub fn image(runtime: &str) -> Result<String, UnsupportedRuntime> {
+    let version = resolve_version(runtime)?;
+    Ok(match name(runtime) {
+        "node" | "nodejs" | "typescript" | "ts" => format!("node:{}-alpine", version),
+        "bun" => format!("oven/bun:{}-alpine", version),
+        rust::RUNTIME => format!("rust:{}-slim", version),
+        bash::RUNTIME => format!("bash:{}", version),
+        playwright::RUNTIME => playwright::image(version),
+        media::RUNTIME => media::image().0,
+        _ => return Err(UnsupportedRuntime(format!("Unsupported runtime: {}", runtime))),
+    })
+}
+
+/// Whether the runtime manages dependencies with a package.json
+pub fn uses_package_json(runtime: &str) -> bool {
+    matches!(name(runtime), "node" | "nodejs" | "bun" | "typescript" | "ts" | playwright::RUNTIME)
+}
+
+/// Time limit of a sandbox on `runtime` not setting one
+pub fn default_timeout_ms(runtime: &str) -> u64 {
+    match name(runtime) {
+        media::RUNTIME => media::TIMEOUT_MS,
+        _ => DEFAULT_TIMEOUT_MS,
+    }
+}
+
+/// Memory limit of a sandbox on `runtime` not setting one
+pub fn default_memory_limit_mb(runtime: &str) -> u64 {
+    match name(runtime) {
+        playwright::RUNTIME => playwright::MEMORY_LIMIT_MB,
+        media::RUNTIME => media::MEMORY_LIMIT_MB,
+        _ => DEFAULT_MEMORY_LIMIT_MB,
+    }
+}
+
+/// Disk quota of sandboxes on `runtime` not setting one, for runtimes needing more than
+/// the mode's default
+pub fn default_disk_limit_mb(runtime: &str) -> Option<u64> {
+    match name(runtime) {
+        playwright::RUNTIME => Some(playwright::DISK_LIMIT_MB),
+        media::RUNTIME => Some(media::DISK_LIMIT_MB),
+        _ => None,
+    }
+}
+
+/// Artifact patterns of a request on `runtime` naming none, for runtimes producing files
+pub fn default_artifacts(runtime: &str) -> Option<Vec<String>> {
+    let patterns = match name(runtime) {
+        playwright::RUNTIME => playwright::DEFAULT_ARTIFACTS,
+        media::RUNTIME => media::DEFAULT_ARTIFACTS,
+        _ => return None,
+    };
+    Some(patterns.iter().map(|pattern| pattern.to_string()).collect())
+}
+
+// Library API; the service binary names runtimes by string
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RuntimeType {
     Node,
     Bun,
     TypeScript,
+    Rust,
+    Bash,
 }
 
 
@@ -20,4 +216,21 @@ mod tests {
         let deserialized: RuntimeType = serde_json::from_str(&serialized).unwrap();
         assert!(matches!(deserialized, RuntimeType::Node));
     }
+
+    #[test]
+    fn test_resolve_version() {
+        assert_eq!(parse("node@20"), ("node", Some("20")));
+        assert_eq!(name("bun@1.1"), "bun");
+        assert_eq!(resolve_version("node").unwrap(), "18");
+        assert_eq!(resolve_version("nodejs@22").unwrap(), "22");
+        assert_eq!(resolve_version("ts@20").unwrap(), "20");
+        assert!(resolve_version("node@17").is_err());
+        assert!(resolve_version("python").is_err());
+        assert_eq!(resolve_version("playwright@1.46.0").unwrap(), "1.46.0");
+        assert_eq!(default_memory_limit_mb("playwright"), playwright::MEMORY_LIMIT_MB);
+        assert_eq!((default_timeout_ms("media@1"), default_timeout_ms("node")), (media::TIMEOUT_MS, DEFAULT_TIMEOUT_MS));
+        assert_eq!(default_artifacts("media"), Some(vec!["out/**".to_string()]));
+        assert_eq!(default_artifacts("bash"), None);
+        assert_eq!(image("ts@20").unwrap(), "node:20-alpine");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,51 @@
+//! Playwright runtime: `code` is a Node.js script driving headless browsers with Playwright
+//!
+//! It runs on a browser-enabled image on the Docker backend only. Browsers keep their
+//! rendering buffers in `/dev/shm`, which Docker sizes at 64 MB, so containers get a
+//! larger one, and the runtime defaults to more memory, disk and processes than the
+//! others. Screenshots, videos, PDFs and traces the script saves under the project
+//! directory are collected as artifacts unless the request names its own patterns.
+
+pub const RUNTIME: &str = "playwright";
+
+/// Playwright release the default image is built for
+pub const VERSION: &str = "1.47.0";
+pub const VERSIONS: &[&str] = &["1.45.0", "1.46.0", VERSION];
+
+/// Image template overriding [`DEFAULT_IMAGE`], for images carrying the `playwright`
+/// package; `{version}` is replaced with the requested version
+pub const IMAGE_ENV: &str = "VOIDRUN_PLAYWRIGHT_IMAGE";
+/// Microsoft's image with the browsers and their system libraries
+pub const DEFAULT_IMAGE: &str = "mcr.microsoft.com/playwright:v{version}-jammy";
+
+/// The request's `code` is written to `index.js` like Node.js code, and run from the
+/// project root so relative output paths land in the project directory
+pub const RUN_COMMAND: &str = "node index.js";
+
+/// Packages installed with `npm install -g` resolve from scripts through NODE_PATH
+pub const NODE_PATH: &str = "/usr/lib/node_modules";
+
+/// Size of `/dev/shm`; Chromium crashes on pages needing more than Docker's 64 MB default
+pub const SHM_SIZE_MB: u64 = 1024;
+
+pub const MEMORY_LIMIT_MB: u64 = 2048;
+/// Browser profiles, caches and videos outgrow the 50 MB other one-shot sandboxes get
+pub const DISK_LIMIT_MB: u64 = 500;
+/// The process limit counts threads, of which each browser process has dozens
+pub const MAX_PROCESSES: u64 = 1024;
+
+/// Files collected after each run when the request names no artifact patterns
+pub const DEFAULT_ARTIFACTS: &[&str] = &["**/*.png", "**/*.jpg", "**/*.jpeg", "**/*.webm", "**/*.pdf", "**/*.zip"];
+
+pub fn is_playwright(runtime: &str) -> bool {
+    super::name(runtime) == RUNTIME
+}
+
+/// Image running `version`
+pub fn image(version: &str) -> String {
+    std::env::var(IMAGE_ENV)
+        .ok()
+        .filter(|image| !image.is_empty())
+        .unwrap_or_else(|| DEFAULT_IMAGE.to_string())
+        .replace("{version}", version)
+}
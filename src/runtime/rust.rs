@@ -0,0 +1,41 @@
+//! Rust runtime: user code is compiled with a pinned toolchain inside the sandbox and run with cargo
+
+/// Only toolchain offered; pinned so the shared build cache stays valid
+pub const TOOLCHAIN_VERSION: &str = "1.79";
+
+pub const RUNTIME: &str = "rust";
+
+/// Where the request's `code` is written, relative to the project root
+pub const MAIN_FILE: &str = "src/main.rs";
+
+pub const MANIFEST_FILE: &str = "Cargo.toml";
+
+/// Manifest used when the request does not ship a Cargo.toml
+pub const DEFAULT_MANIFEST: &str = r#"[package]
+name = "sandbox"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#;
+
+/// One-shot sandboxes have no network, so dependencies must already be in the shared registry cache
+pub const RUN_COMMAND: &str = "cargo run --quiet --offline";
+
+/// Dev servers may fetch dependencies
+pub const DEV_COMMAND: &str = "cargo run";
+
+/// Compiler warnings would otherwise land on stderr and fail the execution
+pub const RUSTFLAGS: &str = "-A warnings";
+
+/// Docker volumes shared by all Rust sandboxes so dependencies are downloaded and compiled once
+pub const REGISTRY_VOLUME: &str = "voidrun-cargo-home";
+pub const TARGET_VOLUME: &str = "voidrun-cargo-target";
+
+/// Mount points of the cache volumes, exported as CARGO_HOME and CARGO_TARGET_DIR
+pub const CARGO_HOME: &str = "/cargo";
+pub const CARGO_TARGET_DIR: &str = "/cargo-target";
+
+pub fn is_rust(runtime: &str) -> bool {
+    super::name(runtime) == RUNTIME
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+
+/// Upper bound on the combined size of a sandbox's artifacts
+pub const MAX_ARTIFACT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Output file collected from a sandbox after execution
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// Path relative to the sandbox project root
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// Whether `path` (relative, `/`-separated) matches any of the artifact patterns
+pub fn matches_any(patterns: &[String], path: &str) -> bool {
+    patterns.iter().any(|pattern| matches(pattern, path))
+}
+
+/// Glob match where `*` and `?` stay within one path segment and `**` spans any number of segments
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_start_matches("./").trim_start_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_start_matches("./").split('/').collect();
+    match_segments(&pattern, &path)
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path_rest)) => match_segment(segment.as_bytes(), name.as_bytes()) && match_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_segment(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_segment(rest, &name[1..]),
+    }
+}
+
+/// Pack artifacts into a zip archive
+pub fn to_zip(artifacts: &[Artifact]) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for artifact in artifacts {
+        writer.start_file(artifact.path.as_str(), options)
+            .with_context(|| format!("Failed to add {} to archive", artifact.path))?;
+        writer.write_all(&artifact.data)?;
+    }
+
+    Ok(writer.finish().context("Failed to finish archive")?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("output.json", "output.json"));
+        assert!(!matches("output.json", "dist/output.json"));
+        assert!(matches("dist/**", "dist/index.js"));
+        assert!(matches("dist/**", "dist/assets/app.css"));
+        assert!(!matches("dist/**", "src/index.js"));
+        assert!(matches("*.json", "package.json"));
+        assert!(!matches("*.json", "dist/package.json"));
+        assert!(matches("**/*.map", "dist/assets/app.js.map"));
+        assert!(matches("./report-?.txt", "report-1.txt"));
+    }
+}
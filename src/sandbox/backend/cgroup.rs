@@ -0,0 +1,172 @@
+//! cgroup v2 resource control for nsjail executions
+//!
+//! Each execution runs below a cgroup of its own, `<root>/<sandbox id>`, whose
+//! `memory.max`, `cpu.max` and `pids.max` come from the request. This caps resident
+//! memory, which an address-space rlimit does not: V8 reserves far more address space
+//! than it uses. nsjail places the jailed program in a child cgroup it removes when the
+//! program exits, so the sandbox's cgroup keeps the totals, read back once the execution
+//! ended and removed after.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::sandbox::SandboxRequest;
+
+/// Cgroup the per-sandbox cgroups are created below, unless `VOIDRUN_NSJAIL_CGROUP` names another
+pub const DEFAULT_ROOT: &str = "/sys/fs/cgroup/voidrun-nsjail";
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+/// CPU time a sandbox may use per period, half a core like a Docker sandbox
+const CPU_QUOTA_USEC: u64 = 50_000;
+const CPU_PERIOD_USEC: u64 = 100_000;
+/// The same CPU limit in nsjail's terms
+pub const CPU_MS_PER_SEC: u64 = CPU_QUOTA_USEC * 1000 / CPU_PERIOD_USEC;
+
+/// Delegated cgroup sandbox cgroups are created in
+#[derive(Debug)]
+pub struct CgroupRoot {
+    path: PathBuf,
+}
+
+impl CgroupRoot {
+    /// Create the root below a cgroup v2 hierarchy and enable the controllers for its children
+    ///
+    /// Fails where cgroups v1 are mounted or the service may not manage the parent cgroup;
+    /// under systemd, run the service with `Delegate=yes` and point the root into its cgroup.
+    pub fn init(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let parent = path.parent().context("The cgroup root needs a parent cgroup")?;
+        if !parent.join("cgroup.controllers").exists() {
+            anyhow::bail!("{} is not in a cgroup v2 hierarchy", parent.display());
+        }
+        std::fs::create_dir_all(&path).with_context(|| format!("Failed to create cgroup {}", path.display()))?;
+        enable_controllers(parent)?;
+        let available = std::fs::read_to_string(path.join("cgroup.controllers"))
+            .with_context(|| format!("Failed to read the controllers of {}", path.display()))?;
+        let missing: Vec<&str> = CONTROLLERS.iter()
+            .filter(|controller| !available.split_whitespace().any(|name| name == **controller))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Controllers {} are not delegated to {}", missing.join(", "), path.display());
+        }
+        enable_controllers(&path)?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Cgroup of a sandbox's execution, with the request's limits; one left by an
+    /// interrupted execution is reused
+    pub fn create(&self, request: &SandboxRequest) -> Result<Cgroup> {
+        let cgroup = Cgroup { path: self.path.join(&request.id) };
+        if !cgroup.path.is_dir() {
+            std::fs::create_dir(&cgroup.path)
+                .with_context(|| format!("Failed to create cgroup {}", cgroup.path.display()))?;
+        }
+        cgroup.write("memory.max", &(request.memory_limit_mb * 1024 * 1024).to_string())?;
+        cgroup.write("cpu.max", &format!("{} {}", CPU_QUOTA_USEC, CPU_PERIOD_USEC))?;
+        cgroup.write("pids.max", &request.max_processes().to_string())?;
+        // Without swap accounting the file is missing, and there is nothing to turn off
+        if cgroup.path.join("memory.swap.max").exists() {
+            cgroup.write("memory.swap.max", "0")?;
+        }
+        enable_controllers(&cgroup.path)?;
+        Ok(cgroup)
+    }
+
+    /// Cgroup of the sandbox's execution in flight, if there is one
+    pub fn get(&self, sandbox_id: &str) -> Option<Cgroup> {
+        let path = self.path.join(sandbox_id);
+        path.is_dir().then_some(Cgroup { path })
+    }
+}
+
+/// The cgroup of one execution
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+/// What an execution's processes consumed, read from its cgroup
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CgroupUsage {
+    pub memory_current_bytes: u64,
+    /// Highest memory use; unset before Linux 5.19, which lacks `memory.peak`
+    pub memory_peak_bytes: Option<u64>,
+    pub memory_max_bytes: Option<u64>,
+    pub cpu_usec: u64,
+    /// Processes the OOM killer ended at the memory limit
+    pub oom_kills: u64,
+}
+
+impl Cgroup {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<()> {
+        std::fs::write(self.path.join(file), value)
+            .with_context(|| format!("Failed to set {} of {}", file, self.path.display()))
+    }
+
+    fn read(&self, file: &str) -> Option<String> {
+        std::fs::read_to_string(self.path.join(file)).ok()
+    }
+
+    pub fn usage(&self) -> CgroupUsage {
+        let number = |content: Option<String>| content.and_then(|content| content.trim().parse::<u64>().ok());
+        CgroupUsage {
+            memory_current_bytes: number(self.read("memory.current")).unwrap_or(0),
+            memory_peak_bytes: number(self.read("memory.peak")),
+            // "max" when unlimited
+            memory_max_bytes: number(self.read("memory.max")),
+            cpu_usec: self.read("cpu.stat").map_or(0, |stat| flat_keyed(&stat, "usage_usec")),
+            oom_kills: self.read("memory.events").map_or(0, |events| flat_keyed(&events, "oom_kill")),
+        }
+    }
+
+    /// Remove the cgroup, with any child nsjail left behind
+    ///
+    /// Fails while processes remain in it.
+    pub fn remove(self) -> Result<()> {
+        if let Ok(children) = std::fs::read_dir(&self.path) {
+            for child in children.flatten().filter(|entry| entry.path().is_dir()) {
+                std::fs::remove_dir(child.path())
+                    .with_context(|| format!("Failed to remove cgroup {}", child.path().display()))?;
+            }
+        }
+        std::fs::remove_dir(&self.path).with_context(|| format!("Failed to remove cgroup {}", self.path.display()))
+    }
+}
+
+/// Let the children of `cgroup` use the controllers sandboxes are limited by
+fn enable_controllers(cgroup: &Path) -> Result<()> {
+    let enable = CONTROLLERS.iter().map(|controller| format!("+{}", controller)).collect::<Vec<_>>().join(" ");
+    std::fs::write(cgroup.join("cgroup.subtree_control"), enable)
+        .with_context(|| format!("Failed to enable the {} controllers below {}", CONTROLLERS.join(", "), cgroup.display()))
+}
+
+/// Value of `key` in a flat keyed cgroup file such as `cpu.stat`, 0 when missing
+fn flat_keyed(content: &str, key: &str) -> u64 {
+    content.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(name, _)| *name == key)
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_keyed() {
+        let events = "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(flat_keyed(events, "oom_kill"), 1);
+        assert_eq!(flat_keyed(events, "oom"), 1);
+        assert_eq!(flat_keyed("usage_usec 123456\nuser_usec 100000\n", "usage_usec"), 123456);
+        assert_eq!(flat_keyed("", "usage_usec"), 0);
+    }
+}
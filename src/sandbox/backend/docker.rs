@@ -2,31 +2,93 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bollard::{
-    container::{Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions},
-    exec::{CreateExecOptions, StartExecResults},
-    image::CreateImageOptions,
+    container::{
+        Config, CreateContainerOptions, ListContainersOptions, LogOutput, LogsOptions, NetworkingConfig,
+        RemoveContainerOptions, StartContainerOptions, StatsOptions,
+    },
+    exec::{CreateExecOptions, ResizeExecOptions, StartExecOptions, StartExecResults},
+    image::{BuildImageOptions, CommitContainerOptions, CreateImageOptions},
+    models::{ContainerSummary, EndpointSettings, HealthStatusEnum},
+    network::{CreateNetworkOptions, InspectNetworkOptions},
     ClientVersion, Docker,
 };
 use futures_util::StreamExt;
 use std::collections::HashMap;
 use std::time::Instant;
+use tokio::io::AsyncWriteExt;
 use tokio::time::{timeout, Duration};
 
-use super::SandboxBackend;
-use crate::sandbox::{SandboxRequest, SandboxResponse, SandboxFile};
+use super::{AddonContainer, LogStream, RecoveredSandbox, SandboxBackend, SandboxHealth, SandboxStats, TerminalSession};
+use crate::runtime::{self, bash, media, playwright, rust, RuntimeDefinition};
+use crate::sandbox::artifacts::{self, Artifact, MAX_ARTIFACT_BYTES};
+use crate::sandbox::gc::SandboxResource;
+use crate::sandbox::hosts::{daemon_address, LOCAL_ADDRESS};
+use crate::sandbox::images::RegistryCredential;
+use crate::sandbox::persisted::PersistedRequest;
+use crate::sandbox::{
+    file_contents, ExecutionUsage, NetworkConfig, OutputChunk, OutputSender, PortAllocator, SandboxRequest, SandboxResponse, SandboxFile, SandboxStatus, TerminationReason, SANDBOX_HOME,
+};
+use crate::security::ContainerSecurity;
 use tracing::{info, warn, error, debug};
+use voidrun_types::{BackendFeatures, LogEntry};
+
+/// Repository that committed sandbox snapshots are tagged under
+const SNAPSHOT_REPO: &str = "voidrun-snapshot";
+/// /sandbox is a tmpfs that `docker commit` skips, so snapshot images carry its contents here
+const SNAPSHOT_ARCHIVE: &str = "/var/lib/voidrun/sandbox.tar";
+/// Container label recording which port the sandboxed service listens on
+pub const PORT_LABEL: &str = "voidrun.port";
+/// Container label marking sandbox containers with the id of their sandbox
+pub const SANDBOX_ID_LABEL: &str = "voidrun.sandbox_id";
+/// Container label holding the sandbox's [`PersistedRequest`] as JSON, to re-register the
+/// sandbox after a restart
+const REQUEST_LABEL: &str = "voidrun.request";
+/// Prefix of the Docker networks backing private networks
+const NETWORK_PREFIX: &str = "voidrun-net-";
+/// Network label with the name of the private network
+const NETWORK_LABEL: &str = "voidrun.network";
+/// Container label marking add-on containers, which are not sandboxes
+const ADDON_LABEL: &str = "voidrun.addon";
+/// How long an add-on may take to accept connections after it starts
+const ADDON_READY_TIMEOUT: Duration = Duration::from_secs(90);
+/// Pid of the shell running the current execution, to find its processes on cancel
+const EXEC_PID_FILE: &str = "/tmp/voidrun-exec.pid";
+/// Prefix of the directory uploaded files are extracted into before they are moved into place
+const UPLOAD_STAGING_DIR: &str = "/sandbox/.voidrun-upload";
+/// Where the execution's cgroup readings are left for `read_usage`
+const EXEC_USAGE_FILE: &str = "/tmp/voidrun-exec.usage";
+/// Prints the container's CPU time in microseconds, from cgroup v2 or v1
+const CGROUP_CPU_USEC: &str = "(awk '/^usage_usec/ {print $2}' /sys/fs/cgroup/cpu.stat || expr $(cat /sys/fs/cgroup/cpuacct/cpuacct.usage) / 1000) 2>/dev/null";
+/// Prints the container's peak memory in bytes, from cgroup v2 or v1
+const CGROUP_MEMORY_PEAK: &str = "(cat /sys/fs/cgroup/memory.peak || cat /sys/fs/cgroup/memory/memory.max_usage_in_bytes) 2>/dev/null";
 
 pub struct DockerBackend {
     docker: Docker,
+    ports: PortAllocator,
+    security: ContainerSecurity,
+    /// Host of the daemon, where ports published on all interfaces are reached
+    address: String,
 }
 
 impl DockerBackend {
     /// Execute a command in the container and capture output with detailed logging
     async fn execute_with_logging(&self, container_id: &str, command: &str, operation: &str) -> Result<(String, String, bool)> {
+        self.execute_with_env(container_id, command, Vec::new(), operation).await
+    }
+
+    /// `execute_with_logging` with extra environment variables, which are not logged
+    async fn execute_with_env(
+        &self,
+        container_id: &str,
+        command: &str,
+        env: Vec<String>,
+        operation: &str,
+    ) -> Result<(String, String, bool)> {
         info!("[DOCKER] Executing {} in container {}: {}", operation, container_id, command);
         
         let exec_options = CreateExecOptions {
             cmd: Some(vec!["sh", "-c", command]),
+            env: Some(env.iter().map(String::as_str).collect()),
             attach_stdout: Some(true),
             attach_stderr: Some(true),
             ..Default::default()
@@ -62,7 +124,12 @@ impl DockerBackend {
                     }
                 }
 
-                let success = stderr.is_empty() || !stderr.contains("error") && !stderr.contains("Error") && !stderr.contains("ERROR");
+                // Commands write warnings and progress to stderr, so only the exit code tells failure
+                let exit_code = self.exec_exit_code(&exec.id).await;
+                let success = match exit_code {
+                    Some(code) => code == 0,
+                    None => stderr.is_empty(),
+                };
                 
                 if success {
                     info!("[DOCKER] {} completed successfully", operation);
@@ -70,7 +137,7 @@ impl DockerBackend {
                         info!("[DOCKER] {} output: {}", operation, stdout.trim());
                     }
                 } else {
-                    error!("[DOCKER] {} failed with stderr: {}", operation, stderr.trim());
+                    error!("[DOCKER] {} failed with exit code {:?} and stderr: {}", operation, exit_code, stderr.trim());
                 }
                 
                 Ok((stdout, stderr, success))
@@ -86,83 +153,428 @@ impl DockerBackend {
         }
     }
 
-    pub fn new() -> Result<Self> {
+    /// Exit code of a finished exec, `None` if Docker cannot tell
+    async fn exec_exit_code(&self, exec_id: &str) -> Option<i64> {
+        match self.docker.inspect_exec(exec_id).await {
+            Ok(exec) => exec.exit_code,
+            Err(e) => {
+                debug!("[DOCKER] Failed to inspect exec {}: {}", exec_id, e);
+                None
+            }
+        }
+    }
+
+    /// Read a file from the container as raw bytes
+    async fn read_file(&self, container_id: &str, path: &str) -> Result<Vec<u8>> {
+        self.exec_stdout(container_id, vec!["cat", "--", path], &format!("read {}", path)).await
+    }
+
+    /// Run a command in the container and return its raw stdout, failing if it writes to stderr
+    async fn exec_stdout(&self, container_id: &str, cmd: Vec<&str>, operation: &str) -> Result<Vec<u8>> {
+        let exec_options = CreateExecOptions {
+            cmd: Some(cmd),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(container_id, exec_options).await
+            .context(format!("Failed to create exec to {}", operation))?;
+
+        let mut data = Vec::new();
+        if let StartExecResults::Attached { mut output, .. } = self.docker.start_exec(&exec.id, None).await? {
+            while let Some(chunk) = output.next().await {
+                match chunk? {
+                    bollard::container::LogOutput::StdOut { message } => data.extend_from_slice(&message),
+                    bollard::container::LogOutput::StdErr { message } => {
+                        anyhow::bail!("Failed to {}: {}", operation, String::from_utf8_lossy(&message).trim());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Write a file in the container, creating its directory
+    async fn write_file(&self, container_id: &str, path: &str, data: &[u8]) -> Result<()> {
+        let write_cmd = format!("mkdir -p $(dirname {0}) && cat > {0}", path);
+        self.exec_with_input(container_id, &write_cmd, data, &format!("write {}", path)).await
+    }
+
+    /// Run a shell command fed `data` on stdin, failing unless it exits with 0
+    async fn exec_with_input(&self, container_id: &str, command: &str, data: &[u8], operation: &str) -> Result<()> {
+        let exec_options = CreateExecOptions {
+            cmd: Some(vec!["sh", "-c", command]),
+            attach_stdin: Some(true),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(container_id, exec_options).await
+            .context(format!("Failed to create exec for {}", operation))?;
+
+        let mut stderr = String::new();
+        if let StartExecResults::Attached { mut output, mut input } = self.docker.start_exec(&exec.id, None).await? {
+            input.write_all(data).await.context(format!("Failed to {}", operation))?;
+            // Closing stdin ends the input of the command
+            input.shutdown().await?;
+            drop(input);
+            while let Some(chunk) = output.next().await {
+                if let bollard::container::LogOutput::StdErr { message } = chunk? {
+                    stderr.push_str(&String::from_utf8_lossy(&message));
+                }
+            }
+        }
+        let success = match self.exec_exit_code(&exec.id).await {
+            Some(code) => code == 0,
+            None => stderr.is_empty(),
+        };
+        if !success {
+            anyhow::bail!("Failed to {}: {}", operation, stderr.trim());
+        }
+        Ok(())
+    }
+
+    /// Write request files, decoded per their encoding, as one tar archive
+    ///
+    /// The archive is streamed into `tar` in the container rather than sent with Docker's
+    /// archive API, which refuses to write into the tmpfs mounts of a read-only root
+    /// filesystem. Files are extracted into a staging directory first and then renamed over
+    /// their targets, so a broken archive changes nothing and dev servers watching the
+    /// project never pick up a half-written file.
+    async fn write_sandbox_files(&self, container_id: &str, files: &[SandboxFile]) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        let archive = files_archive(files)?;
+        let extract_cmd = format!(
+            "staging=$(mktemp -d {0}.XXXXXX) || exit 1; trap 'rm -rf \"$staging\"' EXIT; \
+             tar -x -C \"$staging\" || exit 1; \
+             cd \"$staging\" && find . ! -type d | while IFS= read -r path; do \
+             target=\"/${{path#./}}\"; mkdir -p \"$(dirname \"$target\")\" && mv -f \"$path\" \"$target\" || exit 1; done",
+            UPLOAD_STAGING_DIR
+        );
+        let operation = format!("upload {} files", files.len());
+        self.exec_with_input(container_id, &extract_cmd, &archive, &operation).await?;
+        debug!("[DOCKER] Uploaded {} files ({} bytes archived) to container {}", files.len(), archive.len(), container_id);
+        Ok(())
+    }
+
+    /// Let the container user write to the shared cargo volumes, which Docker creates owned by root
+    ///
+    /// Runs as root, which owns the volumes and so needs no capability to change their mode.
+    async fn open_cargo_volumes(&self, container_id: &str) -> Result<()> {
+        let exec_options = CreateExecOptions {
+            cmd: Some(vec!["chmod", "a+rwx", rust::CARGO_HOME, rust::CARGO_TARGET_DIR]),
+            user: Some("0"),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(container_id, exec_options).await
+            .context("Failed to create exec for cargo volumes")?;
+        if let StartExecResults::Attached { mut output, .. } = self.docker.start_exec(&exec.id, None).await? {
+            while let Some(chunk) = output.next().await {
+                if let bollard::container::LogOutput::StdErr { message } = chunk? {
+                    anyhow::bail!("Failed to open cargo volumes: {}", String::from_utf8_lossy(&message).trim());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the scratch container, store `archive` in it and commit it as the snapshot's image
+    async fn commit_snapshot(&self, container_id: &str, archive: &[u8], snapshot_name: &str) -> Result<()> {
+        self.docker
+            .start_container(container_id, None::<StartContainerOptions<String>>)
+            .await
+            .context("Failed to start snapshot container")?;
+        self.write_file(container_id, SNAPSHOT_ARCHIVE, archive).await?;
+
+        let options = CommitContainerOptions {
+            container: container_id.to_string(),
+            repo: SNAPSHOT_REPO.to_string(),
+            tag: snapshot_name.to_string(),
+            comment: format!("Snapshot {}", snapshot_name),
+            pause: true,
+            ..Default::default()
+        };
+        self.docker
+            .commit_container(options, Config::<String>::default())
+            .await
+            .context("Failed to commit container")?;
+        Ok(())
+    }
+
+    /// Containers of every sandbox, running or not
+    async fn list_sandbox_containers(&self) -> Result<Vec<ContainerSummary>> {
+        let options = ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("label".to_string(), vec![SANDBOX_ID_LABEL.to_string()])]),
+            ..Default::default()
+        };
+        let containers = self.docker
+            .list_containers(Some(options))
+            .await
+            .context("Failed to list sandbox containers")?;
+        Ok(containers)
+    }
+
+    pub fn new(ports: PortAllocator, security: ContainerSecurity) -> Result<Self> {
         // Check for DOCKER_HOST environment variable, otherwise use local defaults
-        let docker = if let Ok(docker_host) = std::env::var("DOCKER_HOST") {
+        let (docker, ports) = if let Ok(docker_host) = std::env::var("DOCKER_HOST") {
             if docker_host.starts_with("tcp://") {
                 let addr = docker_host.strip_prefix("tcp://").unwrap();
-                Docker::connect_with_http(addr, 120, &ClientVersion { major_version: 1, minor_version: 41 })
-                    .context("Failed to connect to Docker daemon with DOCKER_HOST")?
+                let docker = Docker::connect_with_http(addr, 120, &ClientVersion { major_version: 1, minor_version: 41 })
+                    .context("Failed to connect to Docker daemon with DOCKER_HOST")?;
+                // Published ports are bound on, and proxied to, the remote host
+                (docker, ports.on_host(&daemon_address(&docker_host)))
             } else {
-                Docker::connect_with_local_defaults()
-                    .context("Failed to connect to Docker daemon")?
+                let docker = Docker::connect_with_local_defaults()
+                    .context("Failed to connect to Docker daemon")?;
+                (docker, ports)
             }
         } else {
-            Docker::connect_with_local_defaults()
-                .context("Failed to connect to Docker daemon")?
+            let docker = Docker::connect_with_local_defaults()
+                .context("Failed to connect to Docker daemon")?;
+            (docker, ports)
         };
-        Ok(Self { docker })
+        let address = ports.host().to_string();
+        Ok(Self { docker, ports, security, address })
     }
 
-    fn find_available_port(&self) -> u16 {
-        // Simple port allocation starting from 8080
-        // In production, this should be more sophisticated
-        use std::net::{TcpListener, SocketAddr};
-        
-        for port in 8080..9000 {
-            let addr = SocketAddr::from(([127, 0, 0, 1], port));
-            if TcpListener::bind(addr).is_ok() {
-                return port;
+    /// Backend of the daemon at `url`, `unix://<socket>` or `tcp://<host>:<port>`
+    ///
+    /// `ports` should be a handle [`PortAllocator::on_host`] made for the daemon's host,
+    /// as published ports are bound to its address.
+    pub fn connect(url: &str, ports: PortAllocator, security: ContainerSecurity) -> Result<Self> {
+        let version = ClientVersion { major_version: 1, minor_version: 41 };
+        let docker = if let Some(socket) = url.strip_prefix("unix://") {
+            Docker::connect_with_unix(socket, 120, &version)
+        } else if let Some(addr) = url.strip_prefix("tcp://") {
+            Docker::connect_with_http(addr, 120, &version)
+        } else {
+            Docker::connect_with_http(url, 120, &version)
+        }
+        .with_context(|| format!("Failed to connect to Docker daemon at {}", url))?;
+        let address = ports.host().to_string();
+        Ok(Self { docker, ports, security, address })
+    }
+
+    /// Docker network backing a private network, created when its first member starts
+    async fn ensure_network(&self, network: &NetworkConfig) -> Result<String> {
+        let name = format!("{}{}", NETWORK_PREFIX, network.name);
+        if self.docker.inspect_network(&name, None::<InspectNetworkOptions<String>>).await.is_ok() {
+            return Ok(name);
+        }
+        let options = CreateNetworkOptions {
+            name: name.clone(),
+            driver: "bridge".to_string(),
+            check_duplicate: true,
+            labels: HashMap::from([(NETWORK_LABEL.to_string(), network.name.clone())]),
+            ..Default::default()
+        };
+        match self.docker.create_network(options).await {
+            Ok(_) => info!("[DOCKER] Created network {}", name),
+            // Another member created it meanwhile
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 409, .. }) => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to create network {}", name)),
+        }
+        Ok(name)
+    }
+
+    /// Docker networks of private networks the container is attached to
+    async fn private_networks(&self, container: &str) -> Vec<String> {
+        self.docker.inspect_container(container, None).await.ok()
+            .and_then(|container| container.network_settings?.networks)
+            .map(|networks| networks.into_keys().filter(|name| name.starts_with(NETWORK_PREFIX)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Wait for an add-on's health check to pass
+    async fn wait_for_addon(&self, name: &str) -> Result<()> {
+        let deadline = Instant::now() + ADDON_READY_TIMEOUT;
+        loop {
+            let container = self.docker.inspect_container(name, None).await
+                .with_context(|| format!("Add-on container {} is gone", name))?;
+            let state = container.state.unwrap_or_default();
+            match state.health.and_then(|health| health.status) {
+                Some(HealthStatusEnum::HEALTHY) => return Ok(()),
+                _ if !state.running.unwrap_or(false) => anyhow::bail!("Add-on container {} exited", name),
+                _ if Instant::now() >= deadline => {
+                    anyhow::bail!("Add-on container {} not ready after {}s", name, ADDON_READY_TIMEOUT.as_secs())
+                }
+                _ => tokio::time::sleep(Duration::from_millis(500)).await,
             }
         }
-        
-        // Fallback to 8080 if nothing is available
-        8080
     }
 
-    async fn ensure_runtime_image(&self, runtime: &str) -> Result<String> {
-        let image_name = match runtime {
-            "node" | "nodejs" => "node:18-alpine",
-            "bun" => "oven/bun:1-alpine",
-            "typescript" | "ts" => "node:18-alpine",
-            _ => anyhow::bail!("Unsupported runtime: {}", runtime),
+    /// Remove a private network's Docker network once no container is attached to it
+    async fn remove_network_if_unused(&self, name: &str) {
+        let unused = match self.docker.inspect_network(name, None::<InspectNetworkOptions<String>>).await {
+            Ok(network) => network.containers.unwrap_or_default().is_empty(),
+            Err(_) => false,
         };
+        if !unused {
+            return;
+        }
+        match self.docker.remove_network(name).await {
+            Ok(()) => info!("[DOCKER] Removed network {}", name),
+            // A new member may have joined since
+            Err(e) => debug!("[DOCKER] Kept network {}: {}", name, e),
+        }
+    }
+
+    /// Pull the request's image, or its runtime's, which for the media runtime is built
+    /// locally unless overridden
+    async fn ensure_runtime_image(&self, request: &SandboxRequest) -> Result<String> {
+        let image_name = match &request.image {
+            Some(image) => image.clone(),
+            None => runtime::image(&request.runtime)?,
+        };
+        if request.image.is_none() && media::is_media(&request.runtime) && media::image().1 {
+            self.build_image(&image_name, media::DOCKERFILE).await?;
+            return Ok(image_name);
+        }
 
+        self.pull_image(&image_name, request.registry_credential.as_ref()).await;
+        Ok(image_name)
+    }
+
+    /// Build `tag` from `dockerfile` unless the daemon already has it
+    async fn build_image(&self, tag: &str, dockerfile: &str) -> Result<()> {
+        if self.docker.inspect_image(tag).await.is_ok() {
+            return Ok(());
+        }
+        info!("[DOCKER] Building image {}", tag);
+        let context = files_archive(&[SandboxFile {
+            path: "/Dockerfile".to_string(),
+            content: dockerfile.to_string(),
+            is_executable: None,
+            encoding: None,
+        }])?;
+        let options = BuildImageOptions {
+            dockerfile: "Dockerfile",
+            t: tag,
+            rm: true,
+            ..Default::default()
+        };
+        let mut stream = self.docker.build_image(options, None, Some(context.into()));
+        while let Some(result) = stream.next().await {
+            let info = result.with_context(|| format!("Failed to build image {}", tag))?;
+            if let Some(error) = info.error {
+                anyhow::bail!("Failed to build image {}: {}", tag, error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pull `image`, logging in with `credential` for private registries
+    async fn pull_image(&self, image: &str, credential: Option<&RegistryCredential>) {
         let options = CreateImageOptions {
-            from_image: image_name,
+            from_image: image.to_string(),
             ..Default::default()
         };
 
-        let mut stream = self.docker.create_image(Some(options), None, None);
+        let credentials = credential.map(RegistryCredential::docker_credentials);
+        let mut stream = self.docker.create_image(Some(options), None, credentials);
         while let Some(result) = stream.next().await {
             match result {
                 Ok(_) => {}
                 Err(e) => tracing::warn!("Image pull warning: {}", e),
             }
         }
+    }
 
-        Ok(image_name.to_string())
+    fn snapshot_image(snapshot_name: &str) -> String {
+        format!("{}:{}", SNAPSHOT_REPO, snapshot_name)
+    }
+
+    /// Resolve the image for a sandbox, preferring a snapshot over the runtime image
+    async fn resolve_image(&self, request: &SandboxRequest) -> Result<String> {
+        match &request.from_snapshot {
+            Some(snapshot_name) => {
+                crate::sandbox::validate_snapshot_name(snapshot_name)?;
+                let image = Self::snapshot_image(snapshot_name);
+                self.docker
+                    .inspect_image(&image)
+                    .await
+                    .context(format!("Snapshot {} not found", snapshot_name))?;
+                info!("[DOCKER] Restoring sandbox {} from snapshot {}", request.id, snapshot_name);
+                Ok(image)
+            }
+            None => self.ensure_runtime_image(request).await,
+        }
     }
 
     async fn create_container(&self, request: &SandboxRequest, image: &str, host_port: Option<u16>) -> Result<(String, Option<u16>)> {
         // Auto-allocate port for dev servers if not provided
         let actual_host_port = if request.dev_server.unwrap_or(false) && matches!(request.mode, Some(crate::sandbox::SandboxMode::Persistent)) {
-            host_port.or_else(|| Some(self.find_available_port()))
+            match host_port {
+                Some(port) => Some(port),
+                None => {
+                    let (start, end) = self.ports.range();
+                    let port = self.ports.allocate(&request.id).await
+                        .ok_or_else(|| anyhow::anyhow!("No free host port in range {}-{}", start, end))?;
+                    Some(port)
+                }
+            }
         } else {
             host_port
         };
+        let app_port = request.app_port();
+        let container_port = format!("{}/tcp", app_port);
+
         let mut env_vars = Vec::new();
         for (key, value) in &request.env_vars {
             env_vars.push(format!("{}={}", key, value));
         }
+        // Most frameworks pick their listen port from PORT
+        if !request.env_vars.contains_key("PORT") {
+            env_vars.push(format!("PORT={}", app_port));
+        }
+        // The container user usually has no home directory in the image
+        if !request.env_vars.contains_key("HOME") {
+            env_vars.push(format!("HOME={}", SANDBOX_HOME));
+        }
+
+        // Rust sandboxes share the cargo registry and build cache across sandboxes
+        let is_rust = rust::is_rust(&request.runtime);
+        if is_rust {
+            env_vars.push(format!("CARGO_HOME={}", rust::CARGO_HOME));
+            env_vars.push(format!("CARGO_TARGET_DIR={}", rust::CARGO_TARGET_DIR));
+            env_vars.push(format!("RUSTFLAGS={}", rust::RUSTFLAGS));
+        }
+        let is_playwright = playwright::is_playwright(&request.runtime);
+        if is_playwright && !request.env_vars.contains_key("NODE_PATH") {
+            env_vars.push(format!("NODE_PATH={}", playwright::NODE_PATH));
+        }
 
         let is_persistent = matches!(request.mode, Some(crate::sandbox::SandboxMode::Persistent));
         let has_dev_server = request.dev_server.unwrap_or(false);
+        // Only these, the project directory and the cargo volumes are writable
+        let writable_paths = request.writable_paths()?;
+        let mut binds: Vec<String> = request.bind_mounts.iter().map(|mount| mount.bind()).collect();
+        if is_rust {
+            binds.push(format!("{}:{}", rust::REGISTRY_VOLUME, rust::CARGO_HOME));
+            binds.push(format!("{}:{}", rust::TARGET_VOLUME, rust::CARGO_TARGET_DIR));
+        }
+        let network = match &request.network {
+            Some(network) => Some((self.ensure_network(network).await?, network.hostname.clone())),
+            None => None,
+        };
 
         let config = Config {
             image: Some(image.to_string()),
             working_dir: Some("/sandbox".to_string()),
+            user: Some(self.security.user.clone()),
             env: Some(env_vars),
+            // The caller's labels cannot shadow these, as `voidrun.` keys are rejected
+            labels: Some(request.labels.clone().into_iter().chain([
+                (SANDBOX_ID_LABEL.to_string(), request.id.clone()),
+                (PORT_LABEL.to_string(), app_port.to_string()),
+                (REQUEST_LABEL.to_string(), serde_json::to_string(&PersistedRequest::from(request))?),
+            ]).collect()),
             cmd: if is_persistent {
                 Some(vec!["tail".to_string(), "-f".to_string(), "/dev/null".to_string()])
             } else {
@@ -172,48 +584,60 @@ impl DockerBackend {
                 memory: Some((request.memory_limit_mb * 1024 * 1024) as i64),
                 cpu_quota: Some(50000), // 50% CPU
                 cpu_period: Some(100000),
-                network_mode: if is_persistent && has_dev_server {
-                    Some("bridge".to_string()) // Allow network for dev server
-                } else {
-                    Some("none".to_string()) // No network access
-                },
-                readonly_rootfs: Some(!is_persistent), // Allow writes for persistent mode
-                port_bindings: if is_persistent && has_dev_server && actual_host_port.is_some() {
-                    Some({
-                        let mut port_bindings = HashMap::new();
-                        port_bindings.insert(
-                            "3000/tcp".to_string(),
-                            Some(vec![bollard::models::PortBinding {
-                                host_ip: Some("127.0.0.1".to_string()),
-                                host_port: Some(actual_host_port.unwrap().to_string()),
-                            }])
-                        );
-                        port_bindings
-                    })
-                } else {
-                    None
+                pids_limit: Some(request.max_processes() as i64),
+                cap_drop: Some(vec!["ALL".to_string()]),
+                cap_add: Some(self.security.capabilities.clone()).filter(|capabilities| !capabilities.is_empty()),
+                security_opt: self.security.no_new_privileges.then(|| vec!["no-new-privileges".to_string()]),
+                binds: Some(binds).filter(|binds| !binds.is_empty()),
+                network_mode: match &network {
+                    Some((name, _)) => Some(name.clone()), // Private network shared with its other members
+                    None if is_persistent && has_dev_server => Some("bridge".to_string()), // Allow network for dev server
+                    None => Some("none".to_string()), // No network access
                 },
-                tmpfs: Some({
-                    let mut tmpfs = HashMap::new();
-                    tmpfs.insert("/tmp".to_string(), "size=10m".to_string());
-                    if is_persistent {
-                        tmpfs.insert("/sandbox".to_string(), "size=500m".to_string());
-                    } else {
-                        tmpfs.insert("/sandbox".to_string(), "size=50m".to_string());
-                    }
-                    tmpfs
+                readonly_rootfs: Some(true),
+                shm_size: is_playwright.then_some((playwright::SHM_SIZE_MB * 1024 * 1024) as i64),
+                port_bindings: actual_host_port.filter(|_| is_persistent && has_dev_server).map(|host_port| {
+                    let mut port_bindings = HashMap::new();
+                    port_bindings.insert(
+                        container_port.clone(),
+                        Some(vec![bollard::models::PortBinding {
+                            host_ip: Some(self.ports.host().to_string()),
+                            host_port: Some(host_port.to_string()),
+                        }])
+                    );
+                    port_bindings
                 }),
+                tmpfs: Some(
+                    std::iter::once("/sandbox".to_string())
+                        .chain(writable_paths)
+                        .map(|path| (path, format!("size={}m", request.disk_limit_mb())))
+                        .collect(),
+                ),
+                // Same as `docker run --gpus <n>`: needs the NVIDIA Container Toolkit on the host
+                device_requests: (request.gpus() > 0).then(|| vec![bollard::models::DeviceRequest {
+                    driver: Some("nvidia".to_string()),
+                    count: Some(request.gpus() as i64),
+                    capabilities: Some(vec![vec!["gpu".to_string()]]),
+                    ..Default::default()
+                }]),
                 ..Default::default()
             }),
             exposed_ports: if is_persistent && has_dev_server {
                 Some({
                     let mut exposed_ports = HashMap::new();
-                    exposed_ports.insert("3000/tcp".to_string(), HashMap::new());
+                    exposed_ports.insert(container_port.clone(), HashMap::new());
                     exposed_ports
                 })
             } else {
                 None
             },
+            // Other members resolve the sandbox by its host name
+            networking_config: network.map(|(name, hostname)| NetworkingConfig {
+                endpoints_config: HashMap::from([(name, EndpointSettings {
+                    aliases: Some(vec![hostname]),
+                    ..Default::default()
+                })]),
+            }),
             ..Default::default()
         };
 
@@ -233,141 +657,30 @@ impl DockerBackend {
     }
 
 
-    /// Perform internal health check on the dev server
-    async fn perform_health_check(&self, container_id: &str) -> Result<()> {
-        info!("[DOCKER] Starting internal health check");
-        
-        // Check if any process is listening on port 3000
-        let port_check_cmd = "netstat -tlnp 2>/dev/null | grep ':3000' || ss -tlnp 2>/dev/null | grep ':3000' || echo 'No process on port 3000'";
-        let (port_output, _, _) = self.execute_with_logging(container_id, port_check_cmd, "port 3000 check").await?;
-        
-        if port_output.contains("No process on port 3000") {
-            error!("[DOCKER] Health check FAILED: No process listening on port 3000");
-            
-            // Check what processes are running
-            let ps_cmd = "ps aux | grep -E '(node|bun|npm)' | grep -v grep || echo 'No Node/Bun processes running'";
-            let (ps_output, _, _) = self.execute_with_logging(container_id, ps_cmd, "process check").await?;
-            warn!("[DOCKER] Running processes: {}", ps_output);
-            
-            return Err(anyhow::anyhow!("Health check failed: No service listening on port 3000"));
-        } else {
-            info!("[DOCKER] Health check: Process found on port 3000: {}", port_output.trim());
-        }
-        
-        // Try to make an HTTP request to the service using wget (available in Alpine) or nc
-        let http_check_cmd = "wget -q -O- --timeout=5 http://localhost:3000 2>/dev/null || nc -z localhost 3000 && echo 'PORT_ACCESSIBLE' || echo 'HTTP_CHECK_FAILED'";
-        let (http_output, _, _) = self.execute_with_logging(container_id, http_check_cmd, "HTTP health check").await?;
-        
-        if http_output.contains("HTTP_CHECK_FAILED") {
-            warn!("[DOCKER] Health check WARNING: HTTP request failed, but port is open");
-            
-            // Check if the service is still starting up using nc (netcat)
-            let retry_cmd = "sleep 2 && nc -z localhost 3000 && echo 'PORT_ACCESSIBLE_RETRY' || echo 'HTTP_RETRY_FAILED'";
-            let (retry_output, _, _) = self.execute_with_logging(container_id, retry_cmd, "HTTP retry check").await?;
-            
-            if retry_output.contains("HTTP_RETRY_FAILED") {
-                error!("[DOCKER] Health check FAILED: Cannot connect to port 3000 after retry");
-                return Err(anyhow::anyhow!("Health check failed: Service not responding on port 3000"));
-            } else {
-                info!("[DOCKER] Health check PASSED on retry: Port 3000 is accessible");
-            }
-        } else if http_output.contains("PORT_ACCESSIBLE") {
-            info!("[DOCKER] Health check PASSED: Port 3000 is accessible");
-        } else {
-            info!("[DOCKER] Health check PASSED: HTTP response received: {}", http_output.trim());
-        }
-        
-        info!("[DOCKER] Internal health check completed successfully");
-        Ok(())
-    }
-
     async fn execute_persistent_container(&self, container_id: &str, request: &SandboxRequest, start_time: Instant) -> Result<SandboxResponse> {
         // Create additional files if provided
         if let Some(files) = &request.files {
-            // Create directories for nested files
-            let mut directories = std::collections::HashSet::new();
-            for file in files {
-                if let Some(parent) = std::path::Path::new(&file.path).parent() {
-                    if !parent.as_os_str().is_empty() && parent != std::path::Path::new(".") {
-                        directories.insert(format!("/sandbox/{}", parent.display()));
-                    }
-                }
-            }
-            
-            // Create directories
-            for dir in directories {
-                let mkdir_cmd = format!("mkdir -p {}", dir);
-                let mkdir_exec_options = CreateExecOptions {
-                    cmd: Some(vec!["sh", "-c", &mkdir_cmd]),
-                    attach_stdout: Some(true),
-                    attach_stderr: Some(true),
-                    ..Default::default()
-                };
-                let mkdir_exec = self.docker.create_exec(container_id, mkdir_exec_options).await?;
-                if let Err(e) = self.docker.start_exec(&mkdir_exec.id, None).await {
-                    tracing::error!("Failed to create directory {}: {}", dir, e);
-                }
-            }
-
-            // Create files
-            for file in files {
-                let file_path = if file.path.starts_with('/') {
-                    file.path.clone()
-                } else {
-                    format!("/sandbox/{}", file.path)
-                };
-
-                // Use proper escaping for file content
-                let write_cmd = format!("cat > {} << 'EOF'\n{}\nEOF", file_path, file.content);
-
-                let exec_options = CreateExecOptions {
-                    cmd: Some(vec!["sh", "-c", &write_cmd]),
-                    attach_stdout: Some(true),
-                    attach_stderr: Some(true),
-                    ..Default::default()
-                };
-
-                let exec = self.docker.create_exec(container_id, exec_options).await?;
-                if let Err(e) = self.docker.start_exec(&exec.id, None).await {
-                    tracing::error!("Failed to create file {}: {}", file.path, e);
-                }
-
-                // Make executable if specified
-                if file.is_executable.unwrap_or(false) {
-                    let chmod_cmd = format!("chmod +x {}", file_path);
-
-                    let chmod_exec_options = CreateExecOptions {
-                        cmd: Some(vec!["sh", "-c", &chmod_cmd]),
-                        attach_stdout: Some(true),
-                        attach_stderr: Some(true),
-                        ..Default::default()
-                    };
-
-                    let chmod_exec = self.docker.create_exec(container_id, chmod_exec_options).await?;
-                    if let Err(e) = self.docker.start_exec(&chmod_exec.id, None).await {
-                        tracing::error!("Failed to chmod file {}: {}", file.path, e);
-                    }
-                }
+            if let Err(e) = self.write_sandbox_files(container_id, files).await {
+                tracing::error!("Failed to create files: {}", e);
             }
         }
 
-        // Write main code to file if not provided in files
-        if request.files.is_none() || !request.files.as_ref().unwrap().iter().any(|f| f.path.contains("index") || f.path.contains("main")) {
-            let code_file = match request.runtime.as_str() {
-                "bun" => {
-                    // Bun can run TypeScript directly, use .ts for import syntax
-                    if request.code.contains("import ") || request.code.contains("export ") {
-                        "/sandbox/index.ts"
-                    } else {
-                        "/sandbox/index.js"
-                    }
-                },
+        // Write main code to file if not provided in files; reattached sandboxes have no
+        // code to write, and run what their project directory holds
+        let has_main_file = request.files.as_ref().is_some_and(|files| files.iter().any(|f| f.path.contains("index") || f.path.contains("main")));
+        if !has_main_file && !request.code.is_empty() {
+            let code_file = match runtime::name(&request.runtime) {
+                // Bun can run TypeScript directly, use .ts for import syntax
+                "bun" if request.code.contains("import ") || request.code.contains("export ") => "/sandbox/index.ts",
+                "bun" => "/sandbox/index.js",
                 "node" | "nodejs" => "/sandbox/index.js", 
                 "typescript" | "ts" => "/sandbox/index.ts",
+                rust::RUNTIME => "/sandbox/src/main.rs",
+                bash::RUNTIME | media::RUNTIME => "/sandbox/index.sh",
                 _ => "/sandbox/index.js",
             };
             
-            let write_code_cmd = format!("cat > {} << 'EOF'\n{}\nEOF", code_file, request.code);
+            let write_code_cmd = format!("mkdir -p $(dirname {0}) && cat > {0} << 'EOF'\n{1}\nEOF", code_file, request.code);
 
             let exec_options = CreateExecOptions {
                 cmd: Some(vec!["sh", "-c", &write_code_cmd]),
@@ -382,6 +695,15 @@ impl DockerBackend {
             }
         }
 
+        // Cargo needs a manifest to build the project
+        if rust::is_rust(&request.runtime) {
+            let manifest_cmd = format!(
+                "test -f /sandbox/{0} || cat > /sandbox/{0} << 'EOF'\n{1}EOF",
+                rust::MANIFEST_FILE, rust::DEFAULT_MANIFEST
+            );
+            self.execute_with_logging(container_id, &manifest_cmd, "Cargo.toml creation").await?;
+        }
+
         // Install dependencies if requested
         if request.install_deps.unwrap_or(false) || request.dev_server.unwrap_or(false) {
             info!("[DOCKER] Installing dependencies for {} runtime", request.runtime);
@@ -392,10 +714,10 @@ impl DockerBackend {
             info!("[DOCKER] Package check result: {}", check_output.trim());
             
             // Auto-create package.json if none exists and we're using Bun or Node
-            if check_output.contains("package.json not found") {
+            if check_output.contains("package.json not found") && runtime::uses_package_json(&request.runtime) {
                 info!("[DOCKER] Auto-creating package.json for {} runtime", request.runtime);
                 
-                let package_json_content = match request.runtime.as_str() {
+                let package_json_content = match runtime::name(&request.runtime) {
                     "bun" => {
                         // Determine if we should use .ts or .js based on code content
                         let entry_file = if request.code.contains("import ") || request.code.contains("export ") {
@@ -462,7 +784,7 @@ impl DockerBackend {
             }
             
             // Now proceed with dependency installation
-            let install_cmd = match request.runtime.as_str() {
+            let install_cmd = match runtime::name(&request.runtime) {
                 "bun" => {
                     info!("[DOCKER] Using Bun package manager for dependency installation");
                     "cd /sandbox && bun install --verbose"
@@ -471,13 +793,22 @@ impl DockerBackend {
                     info!("[DOCKER] Using npm package manager for dependency installation");
                     "cd /sandbox && npm install --verbose"
                 }
+                rust::RUNTIME => {
+                    info!("[DOCKER] Using cargo for dependency installation");
+                    "cd /sandbox && cargo fetch"
+                }
+                bash::RUNTIME => {
+                    info!("[DOCKER] Shell scripts have no dependencies to install");
+                    "true"
+                }
                 _ => {
                     warn!("[DOCKER] Unknown runtime {}, defaulting to npm", request.runtime);
                     "cd /sandbox && npm install --verbose"
                 }
             };
 
-            match self.execute_with_logging(container_id, install_cmd, "dependency installation").await {
+            let env = request.install_proxy.as_deref().map(proxy_env).unwrap_or_default();
+            match self.execute_with_env(container_id, install_cmd, env, "dependency installation").await {
                 Ok((stdout, stderr, success)) => {
                     if success {
                         info!("[DOCKER] Dependencies installed successfully");
@@ -509,9 +840,13 @@ impl DockerBackend {
                 info!("[DOCKER] Using custom entry point: {}", entry_point);
                 format!("cd /sandbox && {}", entry_point)
             } else {
-                let default_cmd = match request.runtime.as_str() {
+                let default_cmd = match runtime::name(&request.runtime) {
                     "bun" => "cd /sandbox && bun dev".to_string(),
                     "node" | "nodejs" => "cd /sandbox && npm run dev".to_string(),
+                    rust::RUNTIME => format!("cd /sandbox && {}", rust::DEV_COMMAND),
+                    bash::RUNTIME => format!("cd /sandbox && {}", bash::RUN_COMMAND),
+                    playwright::RUNTIME => format!("cd /sandbox && {}", playwright::RUN_COMMAND),
+                    media::RUNTIME => format!("cd /sandbox && {}", media::RUN_COMMAND),
                     _ => "cd /sandbox && bun dev".to_string(),
                 };
                 info!("[DOCKER] Using default dev command for {}: {}", request.runtime, default_cmd);
@@ -544,27 +879,7 @@ impl DockerBackend {
                 }
             }
 
-            // Wait for the server to start
-            info!("[DOCKER] Waiting for dev server to initialize...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            
-            // Check dev server logs
-            let log_cmd = "cd /sandbox && tail -20 dev-server.log 2>/dev/null || echo 'No dev server logs found'";
-            match self.execute_with_logging(container_id, log_cmd, "dev server logs check").await {
-                Ok((log_output, _, _)) => {
-                    if !log_output.trim().is_empty() && log_output != "No dev server logs found" {
-                        info!("[DOCKER] Dev server logs:\n{}", log_output);
-                    } else {
-                        warn!("[DOCKER] No dev server logs found");
-                    }
-                }
-                Err(e) => {
-                    warn!("[DOCKER] Failed to read dev server logs: {}", e);
-                }
-            }
-            
-            // Perform health check
-            self.perform_health_check(container_id).await?;
+            // Readiness is probed by the caller (see `probe_http`) rather than assumed here
         }
 
         // Container is already running with tail -f /dev/null as the main process
@@ -586,10 +901,61 @@ impl DockerBackend {
             exit_code: Some(0),
             execution_time_ms: execution_time,
             is_running: Some(true),
-            dev_server_url: Some("http://localhost:3000".to_string()),
+            dev_server_url: Some(format!("http://localhost:{}", request.app_port())),
+            canceled: false,
+            usage: None,
+            termination_reason: Some(TerminationReason::Completed),
+            execution_id: None,
+            cost: None,
+            diagnostics: None,
         })
     }
 
+    /// CPU time and peak memory of the last execution, from the readings its wrapper left
+    async fn read_usage(&self, container_id: &str) -> (Option<u64>, Option<u64>) {
+        let command = format!("cat {0} 2>/dev/null; rm -f {0}", EXEC_USAGE_FILE);
+        let readings = match self.execute_with_logging(container_id, &command, "usage read").await {
+            Ok((readings, _, _)) => readings,
+            Err(e) => {
+                debug!("[DOCKER] No usage readings for container {}: {}", container_id, e);
+                return (None, None);
+            }
+        };
+        let reading = |name: &str| {
+            readings.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let cpu_time_ms = match (reading("cpu_before"), reading("cpu_after")) {
+            (Some(before), Some(after)) => Some(after.saturating_sub(before) / 1000),
+            _ => None,
+        };
+        (cpu_time_ms, reading("peak"))
+    }
+
+    /// How an execution exiting with `exit_code` ended, given the container's OOM flag
+    async fn termination_reason(&self, container_id: &str, exit_code: i32) -> TerminationReason {
+        // The wrapper shell exits with 128 + n when signal n killed the program
+        match exit_code {
+            code if code > 128 => {
+                // Docker keeps the flag set until the container restarts, so only a SIGKILL counts
+                let oom_killed = match self.docker.inspect_container(container_id, None).await {
+                    Ok(container) => container.state.and_then(|state| state.oom_killed).unwrap_or(false),
+                    Err(e) => {
+                        debug!("[DOCKER] Failed to inspect container {}: {}", container_id, e);
+                        false
+                    }
+                };
+                if oom_killed && code == 128 + libc::SIGKILL {
+                    TerminationReason::OomKilled
+                } else {
+                    TerminationReason::Killed
+                }
+            }
+            _ => TerminationReason::Completed,
+        }
+    }
+
     async fn execute_in_container(&self, container_id: &str, request: &SandboxRequest) -> Result<SandboxResponse> {
         let start_time = Instant::now();
         
@@ -602,47 +968,12 @@ impl DockerBackend {
 
         // Create additional files if provided
         if let Some(files) = &request.files {
-            for file in files {
-                let file_cmd = if file.path.starts_with('/') {
-                    format!("echo '{}' > {}", file.content.replace('\'', "'\"'\"'"), file.path)
-                } else {
-                    format!("echo '{}' > /sandbox/{}", file.content.replace('\'', "'\"'\"'"), file.path)
-                };
-
-                let exec_options = CreateExecOptions {
-                    cmd: Some(vec!["sh", "-c", &file_cmd]),
-                    attach_stdout: Some(true),
-                    attach_stderr: Some(true),
-                    ..Default::default()
-                };
-
-                let exec = self.docker.create_exec(container_id, exec_options).await?;
-                self.docker.start_exec(&exec.id, None).await?;
-
-                // Make executable if specified
-                if file.is_executable.unwrap_or(false) {
-                    let chmod_cmd = if file.path.starts_with('/') {
-                        format!("chmod +x {}", file.path)
-                    } else {
-                        format!("chmod +x /sandbox/{}", file.path)
-                    };
-
-                    let chmod_exec_options = CreateExecOptions {
-                        cmd: Some(vec!["sh", "-c", &chmod_cmd]),
-                        attach_stdout: Some(true),
-                        attach_stderr: Some(true),
-                        ..Default::default()
-                    };
-
-                    let chmod_exec = self.docker.create_exec(container_id, chmod_exec_options).await?;
-                    self.docker.start_exec(&chmod_exec.id, None).await?;
-                }
-            }
+            self.write_sandbox_files(container_id, files).await?;
         }
 
         // Write code to container
-        let code_write_cmd = match request.runtime.as_str() {
-            "node" | "nodejs" => {
+        let code_write_cmd = match runtime::name(&request.runtime) {
+            "node" | "nodejs" | playwright::RUNTIME => {
                 format!("echo '{}' > /sandbox/index.js", request.code.replace('\'', "'\"'\"'"))
             }
             "bun" => {
@@ -656,6 +987,16 @@ impl DockerBackend {
             "typescript" | "ts" => {
                 format!("echo '{}' > /sandbox/index.ts", request.code.replace('\'', "'\"'\"'"))
             }
+            rust::RUNTIME => {
+                format!(
+                    "mkdir -p /sandbox/src && echo '{}' > /sandbox/{} && (test -f /sandbox/{} || echo '{}' > /sandbox/{})",
+                    request.code.replace('\'', "'\"'\"'"), rust::MAIN_FILE,
+                    rust::MANIFEST_FILE, rust::DEFAULT_MANIFEST, rust::MANIFEST_FILE
+                )
+            }
+            bash::RUNTIME | media::RUNTIME => {
+                format!("echo '{}' > /sandbox/{}", request.code.replace('\'', "'\"'\"'"), bash::SCRIPT_FILE)
+            }
             _ => anyhow::bail!("Unsupported runtime: {}", request.runtime),
         };
 
@@ -678,7 +1019,7 @@ impl DockerBackend {
             .context("Failed to write code to container")?;
 
         // Execute code
-        let run_cmd = match request.runtime.as_str() {
+        let run_cmd = match runtime::name(&request.runtime) {
             "node" | "nodejs" => "node /sandbox/index.js",
             "bun" => {
                 // Bun can run both .js and .ts files directly
@@ -689,11 +1030,49 @@ impl DockerBackend {
                 }
             },
             "typescript" | "ts" => "npx ts-node /sandbox/index.ts",
+            rust::RUNTIME => &format!("cd /sandbox && {}", rust::RUN_COMMAND),
+            bash::RUNTIME => &format!("cd /sandbox && {}", bash::RUN_COMMAND),
+            playwright::RUNTIME => &format!("cd /sandbox && {}", playwright::RUN_COMMAND),
+            media::RUNTIME => &format!("cd /sandbox && {}", media::RUN_COMMAND),
             _ => anyhow::bail!("Unsupported runtime: {}", request.runtime),
         };
+        let check_cmd;
+        let run_cmd = if matches!(request.mode, Some(crate::sandbox::SandboxMode::Check)) {
+            // The file the code was written to above, relative to the project root
+            let code_file = match runtime::name(&request.runtime) {
+                "bun" if request.code.contains("import ") || request.code.contains("export ") => "index.ts",
+                "typescript" | "ts" => "index.ts",
+                bash::RUNTIME | media::RUNTIME => bash::SCRIPT_FILE,
+                _ => "index.js",
+            };
+            let command = runtime::check::command(&request.runtime, code_file)
+                .with_context(|| format!("Runtime {} has no check mode", request.runtime))?;
+            check_cmd = format!("cd /sandbox && {}", command);
+            check_cmd.as_str()
+        } else {
+            run_cmd
+        };
+        self.run_in_container(container_id, run_cmd, request.timeout_ms, start_time, request.output.as_ref()).await
+    }
 
+    /// Run `run_cmd` in the container like an execution: within `timeout_ms`, measuring
+    /// what it uses
+    async fn run_in_container(
+        &self,
+        container_id: &str,
+        run_cmd: &str,
+        timeout_ms: u64,
+        start_time: Instant,
+        output_sender: Option<&OutputSender>,
+    ) -> Result<SandboxResponse> {
+        let run_cmd = format!(
+            "echo $$ > {0}; cpu=$({2}); {1}; status=$?; \
+             printf 'cpu_before=%s\\ncpu_after=%s\\npeak=%s\\n' \"$cpu\" \"$({2})\" \"$({3})\" > {4}; \
+             rm -f {0}; exit $status",
+            EXEC_PID_FILE, run_cmd, CGROUP_CPU_USEC, CGROUP_MEMORY_PEAK, EXEC_USAGE_FILE
+        );
         let exec_options = CreateExecOptions {
-            cmd: Some(vec!["sh", "-c", run_cmd]),
+            cmd: Some(vec!["sh", "-c", &run_cmd]),
             attach_stdout: Some(true),
             attach_stderr: Some(true),
             ..Default::default()
@@ -705,7 +1084,7 @@ impl DockerBackend {
             .await
             .context("Failed to create exec for running code")?;
 
-        let timeout_duration = Duration::from_millis(request.timeout_ms);
+        let timeout_duration = Duration::from_millis(timeout_ms);
         let exec_result = timeout(timeout_duration, self.docker.start_exec(&exec.id, None)).await;
 
         let execution_time = start_time.elapsed().as_millis() as u64;
@@ -714,14 +1093,25 @@ impl DockerBackend {
             Ok(Ok(StartExecResults::Attached { mut output, .. })) => {
                 let mut stdout = String::new();
                 let mut stderr = String::new();
+                let mut usage = ExecutionUsage::default();
 
                 while let Some(chunk) = output.next().await {
                     match chunk {
                         Ok(bollard::container::LogOutput::StdOut { message }) => {
-                            stdout.push_str(&String::from_utf8_lossy(&message));
+                            usage.stdout_bytes += message.len() as u64;
+                            let text = String::from_utf8_lossy(&message);
+                            if let Some(sender) = output_sender {
+                                let _ = sender.send(OutputChunk::Stdout(text.to_string()));
+                            }
+                            stdout.push_str(&text);
                         }
                         Ok(bollard::container::LogOutput::StdErr { message }) => {
-                            stderr.push_str(&String::from_utf8_lossy(&message));
+                            usage.stderr_bytes += message.len() as u64;
+                            let text = String::from_utf8_lossy(&message);
+                            if let Some(sender) = output_sender {
+                                let _ = sender.send(OutputChunk::Stderr(text.to_string()));
+                            }
+                            stderr.push_str(&text);
                         }
                         Ok(_) => {}
                         Err(e) => {
@@ -729,16 +1119,28 @@ impl DockerBackend {
                         }
                     }
                 }
+                (usage.cpu_time_ms, usage.peak_memory_bytes) = self.read_usage(container_id).await;
+                let exit_code = match self.exec_exit_code(&exec.id).await {
+                    Some(code) => code as i32,
+                    None if stderr.is_empty() => 0,
+                    None => 1,
+                };
+                let termination_reason = self.termination_reason(container_id, exit_code).await;
 
-                let success = stderr.is_empty();
                 Ok(SandboxResponse {
-                    success,
+                    success: exit_code == 0,
                     stdout,
                     stderr,
-                    exit_code: Some(if success { 0 } else { 1 }),
+                    exit_code: Some(exit_code),
                     execution_time_ms: execution_time,
                     is_running: Some(false),
                     dev_server_url: None,
+                    canceled: false,
+                    usage: Some(usage),
+                    termination_reason: Some(termination_reason),
+                    execution_id: None,
+                    cost: None,
+                    diagnostics: None,
                 })
             }
             Ok(Ok(StartExecResults::Detached)) => {
@@ -750,6 +1152,12 @@ impl DockerBackend {
                     execution_time_ms: execution_time,
                     is_running: Some(false),
                     dev_server_url: None,
+                    canceled: false,
+                    usage: None,
+                    termination_reason: Some(TerminationReason::SetupFailed),
+                    execution_id: None,
+                    cost: None,
+                    diagnostics: None,
                 })
             }
             Ok(Err(e)) => {
@@ -761,6 +1169,12 @@ impl DockerBackend {
                     execution_time_ms: execution_time,
                     is_running: Some(false),
                     dev_server_url: None,
+                    canceled: false,
+                    usage: None,
+                    termination_reason: Some(TerminationReason::SetupFailed),
+                    execution_id: None,
+                    cost: None,
+                    diagnostics: None,
                 })
             }
             Err(_) => {
@@ -772,29 +1186,119 @@ impl DockerBackend {
                     execution_time_ms: execution_time,
                     is_running: Some(false),
                     dev_server_url: None,
+                    canceled: false,
+                    usage: None,
+                    termination_reason: Some(TerminationReason::Timeout),
+                    execution_id: None,
+                    cost: None,
+                    diagnostics: None,
                 })
             }
         }
     }
 }
 
+/// Environment routing bun, npm and cargo through `proxy`; tools differ in the case they read
+/// Log entry of a container output line, timestamped by Docker
+fn log_entry(sandbox_id: &str, output: LogOutput) -> LogEntry {
+    let (level, message) = match output {
+        LogOutput::StdOut { message } => ("INFO", message),
+        LogOutput::StdErr { message } => ("ERROR", message),
+        LogOutput::StdIn { message } => ("INPUT", message),
+        LogOutput::Console { message } => ("CONSOLE", message),
+    };
+    let message = String::from_utf8_lossy(&message);
+    let (timestamp, message) = message.split_once(' ')
+        .and_then(|(timestamp, rest)| {
+            chrono::DateTime::parse_from_rfc3339(timestamp).ok().map(|timestamp| (timestamp.to_rfc3339(), rest))
+        })
+        .unwrap_or_else(|| (chrono::Utc::now().to_rfc3339(), &message));
+    LogEntry {
+        timestamp,
+        level: level.to_string(),
+        message: message.trim().to_string(),
+        sandbox_id: Some(sandbox_id.to_string()),
+    }
+}
+
+/// Where a port published on `host_ip` of the daemon at `daemon` is reached: the
+/// daemon's host when bound to all interfaces, or to loopback of a remote daemon
+fn published_address(host_ip: &str, daemon: &str) -> String {
+    match host_ip {
+        "" | "0.0.0.0" | "::" => daemon.to_string(),
+        "127.0.0.1" | "::1" if daemon != LOCAL_ADDRESS => daemon.to_string(),
+        _ => host_ip.to_string(),
+    }
+}
+
+fn proxy_env(proxy: &str) -> Vec<String> {
+    ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy", "npm_config_proxy", "npm_config_https_proxy"]
+        .iter()
+        .map(|name| format!("{}={}", name, proxy))
+        .collect()
+}
+
+/// Tar archive of request files, with paths relative to `/` and relative request paths under /sandbox
+fn files_archive(files: &[SandboxFile]) -> Result<Vec<u8>> {
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut archive = tar::Builder::new(Vec::new());
+    for file in files {
+        let contents = file_contents(file)?;
+        let path = match file.path.strip_prefix('/') {
+            Some(absolute) => absolute.to_string(),
+            None => format!("sandbox/{}", file.path.trim_start_matches("./")),
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(if file.is_executable.unwrap_or(false) { 0o755 } else { 0o644 });
+        header.set_mtime(mtime);
+        // Rejects paths with `..` components, which would escape the staging directory
+        archive.append_data(&mut header, &path, contents.as_slice())
+            .with_context(|| format!("Invalid file path {}", file.path))?;
+    }
+    Ok(archive.into_inner()?)
+}
+
 #[async_trait]
 impl SandboxBackend for DockerBackend {
-    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<()> {
-        let image = self.ensure_runtime_image(&request.runtime).await?;
-        let (container_id, allocated_port) = self.create_container(request, &image, None).await?;
+    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<Option<u16>> {
+        let image = self.resolve_image(request).await?;
+        let (container_id, allocated_port) = match self.create_container(request, &image, None).await {
+            Ok(created) => created,
+            Err(e) => {
+                self.ports.release(&request.id).await;
+                return Err(e);
+            }
+        };
         
         if let Some(port) = allocated_port {
             info!("[DOCKER] Sandbox {} allocated host port {}", request.id, port);
-            // TODO: Store port mapping for proxy access
         }
         
-        self.docker
+        if let Err(e) = self.docker
             .start_container(&container_id, None::<StartContainerOptions<String>>)
             .await
-            .context("Failed to start container")?;
+        {
+            self.ports.release(&request.id).await;
+            return Err(anyhow::Error::new(e).context("Failed to start container"));
+        }
 
-        Ok(())
+        if rust::is_rust(&request.runtime) {
+            self.open_cargo_volumes(&container_id).await?;
+        }
+
+        if request.from_snapshot.is_some() {
+            let restore_cmd = format!("tar -C /sandbox -xf {}", SNAPSHOT_ARCHIVE);
+            let (_, stderr, success) = self.execute_with_logging(&container_id, &restore_cmd, "snapshot restore").await?;
+            if !success {
+                return Err(anyhow::anyhow!("Failed to restore snapshot contents: {}", stderr));
+            }
+        }
+
+        Ok(allocated_port)
     }
 
     async fn execute_sandbox(&self, request: &SandboxRequest) -> Result<SandboxResponse> {
@@ -802,7 +1306,12 @@ impl SandboxBackend for DockerBackend {
         Ok(response)
     }
 
+    async fn run_command(&self, request: &SandboxRequest, command: &str) -> Result<SandboxResponse> {
+        self.run_in_container(&request.id, &format!("cd /sandbox && {}", command), request.timeout_ms, Instant::now(), request.output.as_ref()).await
+    }
+
     async fn cleanup_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        let networks = self.private_networks(sandbox_id).await;
         let options = RemoveContainerOptions {
             force: true,
             ..Default::default()
@@ -812,64 +1321,240 @@ impl SandboxBackend for DockerBackend {
             .remove_container(sandbox_id, Some(options))
             .await
             .context("Failed to remove container")?;
+        for network in networks {
+            self.remove_network_if_unused(&network).await;
+        }
 
+        if let Some(port) = self.ports.release(sandbox_id).await {
+            debug!("[DOCKER] Released host port {} of sandbox {}", port, sandbox_id);
+        }
         Ok(())
     }
 
-    async fn is_available(&self) -> bool {
-        self.docker.ping().await.is_ok()
+    async fn cancel_execution(&self, sandbox_id: &str) -> Result<()> {
+        // Docker cannot kill an exec, so its process tree is stopped and killed from inside,
+        // parents first so nothing forks past the walk
+        let kill_cmd = format!(
+            "kill_tree() {{ kill -STOP $1 2>/dev/null; \
+             for child in $(cat /proc/$1/task/*/children 2>/dev/null); do kill_tree $child; done; \
+             kill -KILL $1 2>/dev/null; }}; \
+             test -f {0} && kill_tree $(cat {0}); rm -f {0}",
+            EXEC_PID_FILE
+        );
+        let (_, stderr, success) = self.execute_with_logging(sandbox_id, &kill_cmd, "execution kill").await?;
+        if !success {
+            return Err(anyhow::anyhow!("Failed to kill execution: {}", stderr));
+        }
+        info!("[DOCKER] Killed execution of sandbox {}", sandbox_id);
+        Ok(())
     }
 
-    
-    async fn update_files(&self, sandbox_id: &str, files: &[SandboxFile]) -> Result<()> {
-        for file in files {
-            // Create directories if needed
-            if let Some(parent) = std::path::Path::new(&file.path).parent() {
-                if !parent.as_os_str().is_empty() && parent != std::path::Path::new(".") {
-                    let mkdir_cmd = format!("mkdir -p /sandbox/{}", parent.display());
-                    let mkdir_exec_options = CreateExecOptions {
-                        cmd: Some(vec!["sh", "-c", &mkdir_cmd]),
-                        attach_stdout: Some(true),
-                        attach_stderr: Some(true),
-                        ..Default::default()
-                    };
-                    let mkdir_exec = self.docker.create_exec(sandbox_id, mkdir_exec_options).await?;
-                    if let Err(e) = self.docker.start_exec(&mkdir_exec.id, None).await {
-                        warn!("Failed to create directory for {}: {}", file.path, e);
-                    }
-                }
+    async fn pause_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        self.docker
+            .pause_container(sandbox_id)
+            .await
+            .context("Failed to pause container")?;
+        info!("[DOCKER] Paused sandbox {}", sandbox_id);
+        Ok(())
+    }
+
+    async fn resume_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        self.docker
+            .unpause_container(sandbox_id)
+            .await
+            .context("Failed to unpause container")?;
+        info!("[DOCKER] Resumed sandbox {}", sandbox_id);
+        Ok(())
+    }
+
+    async fn is_available(&self) -> bool {
+        if self.docker.ping().await.is_err() {
+            return false;
+        }
+        if !self.security.userns_remap {
+            return true;
+        }
+        // The daemon lists `name=userns` among its security options when it remaps users
+        match self.docker.info().await {
+            Ok(info) if info.security_options.iter().flatten().any(|option| option.contains("name=userns")) => true,
+            Ok(_) => {
+                error!("[DOCKER] userns_remap is required but the Docker daemon does not remap container users");
+                false
+            }
+            Err(e) => {
+                error!("[DOCKER] Failed to read Docker daemon info: {}", e);
+                false
             }
+        }
+    }
+
+    async fn health_check(&self, sandbox_id: &str) -> Result<SandboxHealth> {
+        let container = self.docker.inspect_container(sandbox_id, None).await
+            .with_context(|| format!("Failed to inspect container of sandbox {}", sandbox_id))?;
+        let state = container.state.unwrap_or_default();
+        // The image's HEALTHCHECK, when it has one, has the last word on a running container
+        let check = state.health.and_then(|health| health.status)
+            .filter(|status| !matches!(status, HealthStatusEnum::EMPTY | HealthStatusEnum::NONE));
+        let status = match (&state.status, &check) {
+            (_, Some(check)) => check.to_string(),
+            // The kernel killed the container at its memory limit
+            _ if state.oom_killed == Some(true) && state.running != Some(true) => "oom_killed".to_string(),
+            (Some(status), None) => status.to_string(),
+            (None, None) => "unknown".to_string(),
+        };
+        let healthy = state.running.unwrap_or(false) && !matches!(check, Some(HealthStatusEnum::UNHEALTHY));
+        Ok(SandboxHealth { healthy, status })
+    }
 
-            // Write file content
-            let file_path = format!("/sandbox/{}", file.path);
-            let write_cmd = format!("cat > {} << 'EOF'\n{}\nEOF", file_path, file.content);
+    async fn get_stats(&self, sandbox_id: &str) -> Result<SandboxStats> {
+        let options = StatsOptions { stream: false, one_shot: true };
+        let stats = self.docker.stats(sandbox_id, Some(options)).next().await
+            .ok_or_else(|| anyhow::anyhow!("No stats available for container of sandbox {}", sandbox_id))?
+            .with_context(|| format!("Failed to get stats of sandbox {}", sandbox_id))?;
+
+        let cpu_delta = stats.cpu_stats.cpu_usage.total_usage.saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+        let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0)
+            .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or(0));
+        let cpu_percentage = if system_delta > 0 {
+            let cpu_count = stats.cpu_stats.cpu_usage.percpu_usage.as_ref().map(|usage| usage.len()).unwrap_or(1);
+            (cpu_delta as f64 / system_delta as f64) * cpu_count as f64 * 100.0
+        } else {
+            0.0
+        };
 
-            let exec_options = CreateExecOptions {
-                cmd: Some(vec!["sh", "-c", &write_cmd]),
-                attach_stdout: Some(true),
-                attach_stderr: Some(true),
-                ..Default::default()
+        let networks = stats.networks.unwrap_or_default();
+        let io = stats.blkio_stats.io_service_bytes_recursive.unwrap_or_default();
+        let io_bytes = |op: &str| io.iter().filter(|entry| entry.op.eq_ignore_ascii_case(op)).map(|entry| entry.value).sum();
+        Ok(SandboxStats {
+            memory_used_bytes: stats.memory_stats.usage.unwrap_or(0),
+            memory_limit_bytes: stats.memory_stats.limit,
+            cpu_percentage,
+            network_rx_bytes: networks.values().map(|network| network.rx_bytes).sum(),
+            network_tx_bytes: networks.values().map(|network| network.tx_bytes).sum(),
+            disk_read_bytes: io_bytes("read"),
+            disk_write_bytes: io_bytes("write"),
+        })
+    }
+
+    async fn get_logs(&self, sandbox_id: &str, lines: usize, follow: bool) -> Result<LogStream> {
+        let options = LogsOptions::<String> {
+            follow,
+            stdout: true,
+            stderr: true,
+            timestamps: true,
+            tail: lines.to_string(),
+            ..Default::default()
+        };
+        let sandbox_id = sandbox_id.to_string();
+        let stream = self.docker.logs(&sandbox_id, Some(options))
+            .map(move |output| {
+                output.map(|output| log_entry(&sandbox_id, output))
+                    .context("Failed to read container logs")
+            })
+            .boxed();
+        Ok(stream)
+    }
+
+    async fn recover_sandboxes(&self) -> Result<Vec<RecoveredSandbox>> {
+        let containers = self.list_sandbox_containers().await?;
+        let mut recovered = Vec::new();
+        for container in containers {
+            let labels = container.labels.unwrap_or_default();
+            let Some(sandbox_id) = labels.get(SANDBOX_ID_LABEL) else {
+                continue;
+            };
+            let request = match labels.get(REQUEST_LABEL).map(|json| serde_json::from_str::<PersistedRequest>(json)) {
+                Some(Ok(persisted)) => persisted.into_request(),
+                _ => {
+                    warn!("[DOCKER] Container of sandbox {} has no readable request label, not reattaching it", sandbox_id);
+                    continue;
+                }
             };
 
-            let exec = self.docker.create_exec(sandbox_id, exec_options).await?;
-            self.docker.start_exec(&exec.id, None).await
-                .map_err(|e| anyhow::anyhow!("Failed to update file {}: {}", file.path, e))?;
-
-            // Make executable if specified
-            if file.is_executable.unwrap_or(false) {
-                let chmod_cmd = format!("chmod +x {}", file_path);
-                let chmod_exec_options = CreateExecOptions {
-                    cmd: Some(vec!["sh", "-c", &chmod_cmd]),
-                    attach_stdout: Some(true),
-                    attach_stderr: Some(true),
-                    ..Default::default()
-                };
-                let chmod_exec = self.docker.create_exec(sandbox_id, chmod_exec_options).await?;
-                if let Err(e) = self.docker.start_exec(&chmod_exec.id, None).await {
-                    warn!("Failed to chmod file {}: {}", file.path, e);
+            let host_port = container.ports.unwrap_or_default().iter()
+                .find(|port| port.private_port == request.app_port())
+                .and_then(|port| port.public_port);
+            if let Some(port) = host_port {
+                if !self.ports.reserve(sandbox_id, port).await {
+                    warn!("[DOCKER] Host port {} of sandbox {} is held by another sandbox", port, sandbox_id);
                 }
             }
 
+            let status = match container.state.as_deref() {
+                Some("running") | Some("restarting") => SandboxStatus::Running,
+                Some("paused") => SandboxStatus::Paused,
+                Some("created") => SandboxStatus::Created,
+                _ => SandboxStatus::Terminated,
+            };
+            let created_at = container.created
+                .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+                .unwrap_or_else(chrono::Utc::now);
+
+            recovered.push(RecoveredSandbox {
+                request,
+                created_at,
+                container_id: container.id,
+                status,
+                host_port,
+            });
+        }
+        Ok(recovered)
+    }
+
+    async fn list_resources(&self) -> Result<Vec<SandboxResource>> {
+        let containers = self.list_sandbox_containers().await?;
+        Ok(containers.into_iter()
+            .filter_map(|container| {
+                Some(SandboxResource {
+                    sandbox_id: container.labels.as_ref()?.get(SANDBOX_ID_LABEL).cloned(),
+                    name: container.id?,
+                    created_at: container.created.and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))?,
+                })
+            })
+            .collect())
+    }
+
+    async fn remove_resource(&self, resource: &SandboxResource) -> Result<()> {
+        let options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        self.docker
+            .remove_container(&resource.name, Some(options))
+            .await
+            .context("Failed to remove container")?;
+
+        if let Some(sandbox_id) = &resource.sandbox_id {
+            self.ports.release(sandbox_id).await;
+        }
+        info!("[DOCKER] Removed orphaned container {}", resource.name);
+        Ok(())
+    }
+
+    fn features(&self) -> BackendFeatures {
+        BackendFeatures {
+            dev_server: true,
+            persistent: true,
+            network: true,
+            terminal: true,
+            snapshots: true,
+            gpu: true,
+            browser: true,
+            media: true,
+            mounts: true,
+            images: true,
+        }
+    }
+
+    async fn runtime_versions(&self, runtime: &RuntimeDefinition) -> Vec<&'static str> {
+        // Images of every version are pulled on demand
+        runtime.versions.to_vec()
+    }
+
+    
+    async fn update_files(&self, sandbox_id: &str, files: &[SandboxFile]) -> Result<()> {
+        self.write_sandbox_files(sandbox_id, files).await.context("Failed to update files")?;
+        for file in files {
             info!("Updated file: /sandbox/{}", file.path);
         }
         Ok(())
@@ -879,6 +1564,8 @@ impl SandboxBackend for DockerBackend {
         // Kill existing processes that match the command pattern
         let kill_cmd = match command {
             cmd if cmd.contains("bun") => "pkill -f 'bun.*dev' || true",
+            cmd if cmd.contains("cargo") => "pkill -f 'cargo run'; pkill -f '/cargo-target/' || true",
+            cmd if cmd.contains("bash") => "pkill -f 'bash index.sh' || true",
             cmd if cmd.contains("npm") => "pkill -f 'npm.*run' || true", 
             cmd if cmd.contains("node") => "pkill -f 'node.*' || true",
             _ => "pkill -f 'dev' || true",
@@ -912,4 +1599,232 @@ impl SandboxBackend for DockerBackend {
         info!("Restarted process '{}' for sandbox {}", command, sandbox_id);
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn endpoint_for(&self, sandbox_id: &str) -> Result<Option<(String, u16)>> {
+        let container = self.docker.inspect_container(sandbox_id, None).await
+            .with_context(|| format!("Failed to inspect container of sandbox {}", sandbox_id))?;
+        let app_port = container.config.as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get(PORT_LABEL))
+            .and_then(|port| port.parse::<u16>().ok())
+            .unwrap_or(crate::sandbox::DEFAULT_APP_PORT);
+        let Some(network) = container.network_settings else {
+            return Ok(None);
+        };
+
+        // The host port the service is published on, at the address it is bound to
+        let binding = network.ports.as_ref()
+            .and_then(|ports| ports.get(&format!("{}/tcp", app_port)))
+            .and_then(|bindings| bindings.as_ref())
+            .and_then(|bindings| bindings.iter().find_map(|binding| {
+                let port = binding.host_port.as_deref()?.parse::<u16>().ok()?;
+                Some((binding.host_ip.clone().unwrap_or_default(), port))
+            }));
+        if let Some((host_ip, port)) = binding {
+            return Ok(Some((published_address(&host_ip, &self.address), port)));
+        }
+
+        // Unpublished, the service is reachable on the container's own address when
+        // the service shares its network, as with a local daemon
+        let container_ip = network.networks.unwrap_or_default().into_values()
+            .filter_map(|endpoint| endpoint.ip_address)
+            .find(|ip| !ip.is_empty());
+        Ok(container_ip.map(|ip| (ip, app_port)))
+    }
+
+    async fn probe_http(&self, sandbox_id: &str, port: u16, path: &str) -> Result<()> {
+        // busybox wget exits non-zero on connection errors and non-2xx responses; the URL
+        // is an argument of its own, so no shell reads the path
+        let url = format!("http://127.0.0.1:{}{}", port, path);
+        let exec_options = CreateExecOptions {
+            cmd: Some(vec!["wget", "-q", "-O", "/dev/null", "-T", "2", url.as_str()]),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(sandbox_id, exec_options).await
+            .context("Failed to create exec for readiness probe")?;
+        if let StartExecResults::Attached { mut output, .. } = self.docker.start_exec(&exec.id, None).await? {
+            while output.next().await.is_some() {}
+        }
+        let exit_code = self.docker.inspect_exec(&exec.id).await
+            .context("Failed to inspect readiness probe")?
+            .exit_code;
+        if exit_code == Some(0) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("No successful response from port {} at {}", port, path))
+        }
+    }
+
+    async fn dev_server_logs(&self, sandbox_id: &str, lines: usize) -> Result<String> {
+        let log_cmd = format!("tail -n {} /sandbox/dev-server.log 2>/dev/null || true", lines);
+        let (stdout, _, _) = self.execute_with_logging(sandbox_id, &log_cmd, "dev server logs").await?;
+        Ok(stdout)
+    }
+
+    async fn snapshot_sandbox(&self, sandbox_id: &str, snapshot_name: &str) -> Result<String> {
+        // The sandbox's root filesystem is read-only, so the archive of its project directory
+        // goes into a scratch container of the same image, which is committed instead
+        let archive = self.exec_stdout(sandbox_id, vec!["tar", "-C", "/sandbox", "-cf", "-", "."], "archive sandbox contents").await?;
+        let base_image = self.docker.inspect_container(sandbox_id, None).await
+            .context("Failed to inspect container")?
+            .image
+            .ok_or_else(|| anyhow::anyhow!("Container of sandbox {} has no image", sandbox_id))?;
+
+        let scratch_config = Config {
+            image: Some(base_image),
+            cmd: Some(vec!["tail".to_string(), "-f".to_string(), "/dev/null".to_string()]),
+            host_config: Some(bollard::models::HostConfig {
+                network_mode: Some("none".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let scratch = self.docker
+            .create_container(None::<CreateContainerOptions<String>>, scratch_config)
+            .await
+            .context("Failed to create snapshot container")?;
+        let committed = self.commit_snapshot(&scratch.id, &archive, snapshot_name).await;
+        let remove_options = RemoveContainerOptions { force: true, ..Default::default() };
+        if let Err(e) = self.docker.remove_container(&scratch.id, Some(remove_options)).await {
+            warn!("[DOCKER] Failed to remove snapshot container {}: {}", scratch.id, e);
+        }
+        committed?;
+
+        let image = Self::snapshot_image(snapshot_name);
+        info!("[DOCKER] Sandbox {} snapshotted to {}", sandbox_id, image);
+        Ok(image)
+    }
+
+    async fn start_addon(&self, addon: &AddonContainer) -> Result<()> {
+        let network = self.ensure_network(&addon.network).await?;
+        if self.docker.inspect_container(&addon.name, None).await.is_ok() {
+            // Left from before a restart, with its data
+            self.docker.start_container(&addon.name, None::<StartContainerOptions<String>>).await.ok();
+            return self.wait_for_addon(&addon.name).await;
+        }
+        self.pull_image(&addon.image, None).await;
+
+        let config = Config {
+            image: Some(addon.image.clone()),
+            cmd: addon.command.clone(),
+            env: Some(addon.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect()),
+            labels: Some(HashMap::from([(ADDON_LABEL.to_string(), addon.name.clone())])),
+            healthcheck: Some(bollard::models::HealthConfig {
+                test: Some(vec!["CMD-SHELL".to_string(), addon.ready_check.clone()]),
+                interval: Some(1_000_000_000),
+                timeout: Some(5_000_000_000),
+                retries: Some(3),
+                ..Default::default()
+            }),
+            host_config: Some(bollard::models::HostConfig {
+                memory: Some((addon.memory_limit_mb * 1024 * 1024) as i64),
+                network_mode: Some(network.clone()),
+                ..Default::default()
+            }),
+            networking_config: Some(NetworkingConfig {
+                endpoints_config: HashMap::from([(network, EndpointSettings {
+                    aliases: Some(vec![addon.network.hostname.clone()]),
+                    ..Default::default()
+                })]),
+            }),
+            ..Default::default()
+        };
+        let options = CreateContainerOptions { name: addon.name.as_str(), platform: None };
+        self.docker.create_container(Some(options), config).await
+            .with_context(|| format!("Failed to create add-on container {}", addon.name))?;
+        self.docker.start_container(&addon.name, None::<StartContainerOptions<String>>).await
+            .with_context(|| format!("Failed to start add-on container {}", addon.name))?;
+        info!("[DOCKER] Started add-on container {} from {}", addon.name, addon.image);
+        self.wait_for_addon(&addon.name).await
+    }
+
+    async fn remove_addon(&self, name: &str) -> Result<()> {
+        let networks = self.private_networks(name).await;
+        let options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        match self.docker.remove_container(name, Some(options)).await {
+            Ok(()) => info!("[DOCKER] Removed add-on container {}", name),
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to remove add-on container {}", name)),
+        }
+        for network in networks {
+            self.remove_network_if_unused(&network).await;
+        }
+        Ok(())
+    }
+
+    async fn collect_artifacts(&self, sandbox_id: &str, patterns: &[String]) -> Result<Vec<Artifact>> {
+        let list_cmd = "cd /sandbox && find . -type f -not -path './node_modules/*'";
+        let (stdout, stderr, success) = self.execute_with_logging(sandbox_id, list_cmd, "artifact listing").await?;
+        if !success {
+            return Err(anyhow::anyhow!("Failed to list sandbox files: {}", stderr));
+        }
+
+        let mut collected = Vec::new();
+        let mut total_bytes = 0;
+        for path in stdout.lines().map(|line| line.trim().trim_start_matches("./")) {
+            if path.is_empty() || !artifacts::matches_any(patterns, path) {
+                continue;
+            }
+            let data = self.read_file(sandbox_id, &format!("/sandbox/{}", path)).await?;
+            total_bytes += data.len();
+            if total_bytes > MAX_ARTIFACT_BYTES {
+                anyhow::bail!("Artifacts of sandbox {} exceed {} bytes", sandbox_id, MAX_ARTIFACT_BYTES);
+            }
+            collected.push(Artifact { path: path.to_string(), data });
+        }
+
+        info!("[DOCKER] Collected {} artifacts ({} bytes) from sandbox {}", collected.len(), total_bytes, sandbox_id);
+        Ok(collected)
+    }
+
+    async fn open_terminal(&self, sandbox_id: &str, shell: &str, cols: u16, rows: u16) -> Result<TerminalSession> {
+        let exec_options = CreateExecOptions {
+            cmd: Some(vec![shell]),
+            env: Some(vec!["TERM=xterm-256color"]),
+            working_dir: Some("/sandbox"),
+            attach_stdin: Some(true),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            tty: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(sandbox_id, exec_options).await
+            .context("Failed to create exec for terminal")?;
+
+        // The TTY was requested when the exec was created
+        let start_options = StartExecOptions {
+            detach: false,
+            output_capacity: None,
+        };
+        let (output, input) = match self.docker.start_exec(&exec.id, Some(start_options)).await
+            .context("Failed to start terminal")?
+        {
+            StartExecResults::Attached { output, input } => (output, input),
+            StartExecResults::Detached => anyhow::bail!("Terminal exec detached unexpectedly"),
+        };
+
+        // The exec's TTY can only be resized once it is running
+        self.resize_terminal(&exec.id, cols, rows).await?;
+        info!("[DOCKER] Opened terminal {} ({}) in sandbox {}", exec.id, shell, sandbox_id);
+
+        Ok(TerminalSession {
+            id: exec.id,
+            output: Box::pin(output.map(|chunk| {
+                chunk.map(|output| output.into_bytes().to_vec()).map_err(anyhow::Error::from)
+            })),
+            input,
+        })
+    }
+
+    async fn resize_terminal(&self, session_id: &str, cols: u16, rows: u16) -> Result<()> {
+        self.docker
+            .resize_exec(session_id, ResizeExecOptions { height: rows, width: cols })
+            .await
+            .context("Failed to resize terminal")
+    }
+}
@@ -0,0 +1,218 @@
+//! In-process V8 isolate for one-shot JavaScript
+//!
+//! Isolates start in milliseconds instead of the hundreds a container needs, but
+//! offer no filesystem, network, modules or Node/Bun APIs: only the language and a
+//! `console`. [`accepts`] picks the requests that can run here; everything else,
+//! including TypeScript, which is not transpiled here, goes to the configured
+//! container backend.
+
+use anyhow::{Context, Result};
+use deno_core::{op2, v8, JsRuntime, OpState, PollEventLoopOptions, RuntimeOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::sandbox::{ExecutionUsage, SandboxRequest, SandboxResponse, TerminationReason};
+
+/// Heap ceiling of an isolate, whatever the request's memory limit
+const MAX_HEAP_MB: u64 = 256;
+
+/// Output kept per stream; the rest is dropped
+const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Identifiers that need an API the isolate does not provide
+const UNSUPPORTED_APIS: &[&str] = &[
+    "require(", "import ", "import(", "export ", "process.", "Bun.", "Deno.", "fetch(", "__dirname", "__filename",
+];
+
+/// Installs `console` on top of the print op and hides the Deno internals
+const PRELUDE: &str = r#"
+((globalThis) => {
+  const print = globalThis.Deno.core.ops.op_voidrun_print;
+  const format = (value) => {
+    if (typeof value === 'string') return value;
+    if (value instanceof Error) return value.stack ?? String(value);
+    try {
+      return JSON.stringify(value) ?? String(value);
+    } catch {
+      return String(value);
+    }
+  };
+  const writer = (isErr) => (...args) => print(args.map(format).join(' ') + '\n', isErr);
+  globalThis.console = {
+    log: writer(false),
+    info: writer(false),
+    debug: writer(false),
+    warn: writer(true),
+    error: writer(true),
+  };
+  delete globalThis.Deno;
+})(globalThis);
+"#;
+
+#[derive(Default)]
+struct Output {
+    stdout: String,
+    stderr: String,
+}
+
+impl Output {
+    fn push(&mut self, message: &str, is_err: bool) {
+        let stream = if is_err { &mut self.stderr } else { &mut self.stdout };
+        if stream.len() + message.len() <= MAX_OUTPUT_BYTES {
+            stream.push_str(message);
+        }
+    }
+}
+
+#[op2(fast)]
+fn op_voidrun_print(state: &mut OpState, #[string] message: &str, is_err: bool) {
+    state.borrow_mut::<Output>().push(message, is_err);
+}
+
+deno_core::extension!(voidrun_isolate, ops = [op_voidrun_print]);
+
+/// Whether a request can run in an isolate: plain JavaScript, no files, dependencies,
+/// dev server or snapshot, no backend, GPU, security profile or network of its own, and
+/// no use of module or runtime APIs
+pub fn accepts(request: &SandboxRequest) -> bool {
+    let is_javascript = matches!(request.runtime.as_str(), "node" | "nodejs" | "bun");
+    let needs_container = request.backend.is_some()
+        || request.gpus() > 0
+        || request.security_profile.is_some()
+        || request.network.is_some();
+    let needs_filesystem = request.files.as_ref().is_some_and(|files| !files.is_empty())
+        || request.install_deps.unwrap_or(false)
+        || request.dev_server.unwrap_or(false)
+        || request.from_snapshot.is_some()
+        || request.mounts.as_ref().is_some_and(|mounts| !mounts.is_empty())
+        || request.image.is_some()
+        || request.artifacts.as_ref().is_some_and(|patterns| !patterns.is_empty());
+    let is_one_shot = matches!(request.mode, None | Some(crate::sandbox::SandboxMode::OneShot));
+
+    is_javascript
+        && is_one_shot
+        && !needs_filesystem
+        && !needs_container
+        && request.env_vars.is_empty()
+        && !UNSUPPORTED_APIS.iter().any(|api| request.code.contains(api))
+}
+
+/// Runs one-shot JavaScript in a fresh V8 isolate per request
+#[derive(Debug, Default)]
+pub struct IsolateBackend;
+
+impl IsolateBackend {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub async fn execute(&self, request: &SandboxRequest) -> Result<SandboxResponse> {
+        let code = request.code.clone();
+        let heap_mb = request.memory_limit_mb.min(MAX_HEAP_MB);
+        let time_limit = Duration::from_millis(request.timeout_ms);
+
+        // Isolates are not Send; each execution owns a blocking thread
+        tokio::task::spawn_blocking(move || run_isolate(code, heap_mb, time_limit))
+            .await
+            .context("Isolate execution panicked")?
+    }
+}
+
+fn run_isolate(code: String, heap_mb: u64, time_limit: Duration) -> Result<SandboxResponse> {
+    let start_time = Instant::now();
+    let heap_bytes = (heap_mb * 1024 * 1024) as usize;
+
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        extensions: vec![voidrun_isolate::init_ops()],
+        create_params: Some(v8::CreateParams::default().heap_limits(0, heap_bytes)),
+        ..Default::default()
+    });
+    runtime.op_state().borrow_mut().put(Output::default());
+
+    // Stop the script instead of letting V8 abort the process when the heap runs out
+    let out_of_memory = Arc::new(AtomicBool::new(false));
+    {
+        let handle = runtime.v8_isolate().thread_safe_handle();
+        let out_of_memory = out_of_memory.clone();
+        runtime.add_near_heap_limit_callback(move |current, _initial| {
+            out_of_memory.store(true, Ordering::SeqCst);
+            handle.terminate_execution();
+            // Headroom for the termination to unwind
+            current * 2
+        });
+    }
+
+    // Watchdog terminating the script at the time limit
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let (finished, watchdog) = mpsc::channel::<()>();
+    {
+        let handle = runtime.v8_isolate().thread_safe_handle();
+        let timed_out = timed_out.clone();
+        std::thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = watchdog.recv_timeout(time_limit) {
+                timed_out.store(true, Ordering::SeqCst);
+                handle.terminate_execution();
+            }
+        });
+    }
+
+    let event_loop = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start isolate event loop")?;
+    let result = runtime.execute_script("[voidrun:prelude]", PRELUDE)
+        .and_then(|_| runtime.execute_script("[sandbox]", code))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            event_loop
+                .block_on(runtime.run_event_loop(PollEventLoopOptions::default()))
+                .map_err(|e| e.to_string())
+        });
+    let _ = finished.send(());
+
+    let output = runtime.op_state().borrow_mut().take::<Output>();
+    let mut stderr = output.stderr;
+    let timed_out = timed_out.load(Ordering::SeqCst);
+    let out_of_memory = out_of_memory.load(Ordering::SeqCst);
+    let exit_code = if timed_out {
+        stderr.push_str("Execution timed out");
+        124
+    } else if out_of_memory {
+        stderr.push_str(&format!("Memory limit of {}MB exceeded", heap_mb));
+        137
+    } else if let Err(e) = result {
+        stderr.push_str(&e);
+        1
+    } else {
+        0
+    };
+
+    let usage = ExecutionUsage {
+        stdout_bytes: output.stdout.len() as u64,
+        stderr_bytes: stderr.len() as u64,
+        ..Default::default()
+    };
+    Ok(SandboxResponse {
+        success: exit_code == 0,
+        stdout: output.stdout,
+        stderr,
+        exit_code: Some(exit_code),
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+        is_running: Some(false),
+        dev_server_url: None,
+        canceled: false,
+        usage: Some(usage),
+        termination_reason: Some(if timed_out {
+            TerminationReason::Timeout
+        } else if out_of_memory {
+            TerminationReason::OomKilled
+        } else {
+            TerminationReason::Completed
+        }),
+        execution_id: None,
+        cost: None,
+        diagnostics: None,
+    })
+}
@@ -1,43 +1,258 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use futures_util::Stream;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use tokio::io::AsyncWrite;
 
-use super::{SandboxRequest, SandboxResponse};
+use super::gc::SandboxResource;
+use super::hosts::DockerHostConfig;
+use super::{Artifact, NetworkConfig, PortAllocator, SandboxMode, SandboxRequest, SandboxResponse, SandboxStatus};
+use crate::runtime::{media, playwright, RuntimeDefinition};
+use crate::security::ContainerSecurity;
+use voidrun_types::{BackendFeatures, LogEntry};
 
+mod cgroup;
 pub mod docker;
+#[cfg(feature = "isolate")]
+pub mod isolate;
 pub mod nsjail;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum SandboxBackendType {
+    #[serde(alias = "docker")]
     Docker,
+    #[serde(alias = "nsjail")]
     Nsjail,
     #[cfg(feature = "firecracker")]
+    #[serde(alias = "firecracker")]
     Firecracker,
     #[cfg(feature = "gvisor")]
+    #[serde(alias = "gvisor")]
     Gvisor,
 }
 
+impl SandboxBackendType {
+    /// Name used in requests and settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Nsjail => "nsjail",
+            #[cfg(feature = "firecracker")]
+            Self::Firecracker => "firecracker",
+            #[cfg(feature = "gvisor")]
+            Self::Gvisor => "gvisor",
+        }
+    }
+}
+
+impl std::str::FromStr for SandboxBackendType {
+    type Err = BackendUnavailable;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "docker" => Ok(Self::Docker),
+            "nsjail" => Ok(Self::Nsjail),
+            #[cfg(feature = "firecracker")]
+            "firecracker" => Ok(Self::Firecracker),
+            #[cfg(feature = "gvisor")]
+            "gvisor" => Ok(Self::Gvisor),
+            _ => Err(BackendUnavailable(format!("Unknown backend: {}", name))),
+        }
+    }
+}
+
+/// The backend a request asked for does not exist or is not enabled on this instance
+#[derive(Debug, Clone)]
+pub struct BackendUnavailable(pub String);
+
+impl std::fmt::Display for BackendUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BackendUnavailable {}
+
+/// Backends to try for a request that does not name one, most preferred first
+///
+/// One-shot executions prefer nsjail, which starts much faster than a container; other
+/// sandboxes prefer the configured default. Restoring a snapshot needs the default
+/// backend, which is where snapshots are taken unless a sandbox picked another one.
+pub fn preference_order(request: &SandboxRequest, default: &SandboxBackendType) -> Vec<SandboxBackendType> {
+    let is_persistent = matches!(request.mode, Some(SandboxMode::Persistent));
+    if request.from_snapshot.is_some() {
+        return vec![default.clone()];
+    }
+
+    let mut order = vec![default.clone(), SandboxBackendType::Docker, SandboxBackendType::Nsjail];
+    if !is_persistent {
+        order.insert(0, SandboxBackendType::Nsjail);
+    }
+    let mut seen = Vec::new();
+    order.retain(|backend_type| {
+        let first = !seen.contains(backend_type);
+        seen.push(backend_type.clone());
+        first
+    });
+    order
+}
+
+/// Whether a backend with `features` can serve the request
+pub fn supports(features: &BackendFeatures, request: &SandboxRequest) -> bool {
+    let is_persistent = matches!(request.mode, Some(SandboxMode::Persistent));
+    (!is_persistent || features.persistent)
+        && (!request.dev_server.unwrap_or(false) || features.dev_server)
+        && (!request.install_deps.unwrap_or(false) || features.network)
+        && (request.gpus() == 0 || features.gpu)
+        && (!playwright::is_playwright(&request.runtime) || features.browser)
+        && (!media::is_media(&request.runtime) || features.media)
+        && (request.mounts.as_ref().is_none_or(|mounts| mounts.is_empty()) || features.mounts)
+        && (request.image.is_none() || features.images)
+}
+
+/// A sandbox still present on a backend from before the service started
+pub struct RecoveredSandbox {
+    pub request: SandboxRequest,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub container_id: Option<String>,
+    pub status: SandboxStatus,
+    /// Host port its service is published on, already reserved in the port allocator
+    pub host_port: Option<u16>,
+}
+
+/// Service container run next to a deployment's sandboxes, such as its database
+#[derive(Debug, Clone)]
+pub struct AddonContainer {
+    /// Container name, unique per deployment and add-on
+    pub name: String,
+    pub image: String,
+    /// Command replacing the image's, if any
+    pub command: Option<Vec<String>>,
+    pub env: HashMap<String, String>,
+    /// Shell command that succeeds once the service accepts connections
+    pub ready_check: String,
+    pub memory_limit_mb: u64,
+    /// Private network joined, under the network's host name
+    pub network: NetworkConfig,
+}
+
+/// Interactive shell attached to a pseudo-terminal inside a sandbox
+///
+/// The shell exits once `input` is dropped.
+pub struct TerminalSession {
+    /// Backend handle passed to `resize_terminal`
+    pub id: String,
+    pub output: Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>,
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+/// Whether a sandbox is in a state to run code, as far as its backend can tell
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxHealth {
+    pub healthy: bool,
+    /// What the backend found, such as the container state
+    pub status: String,
+}
+
+/// Resource usage of a sandbox right now
+#[derive(Debug, Clone, Default)]
+pub struct SandboxStats {
+    pub memory_used_bytes: u64,
+    /// Memory limit the backend enforces, when it can read it back
+    pub memory_limit_bytes: Option<u64>,
+    pub cpu_percentage: f64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub disk_read_bytes: u64,
+    pub disk_write_bytes: u64,
+}
+
+/// Output lines of a sandbox's processes, oldest first
+pub type LogStream = Pin<Box<dyn Stream<Item = Result<LogEntry>> + Send>>;
+
 #[async_trait]
 pub trait SandboxBackend: Send + Sync {
-    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<()>;
+    /// Create the sandbox, returning the host port its service is published on, if any
+    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<Option<u16>>;
     async fn execute_sandbox(&self, request: &SandboxRequest) -> Result<SandboxResponse>;
+    /// Run a shell command from the project root of the created sandbox, like an execution
+    async fn run_command(&self, request: &SandboxRequest, command: &str) -> Result<SandboxResponse>;
     async fn cleanup_sandbox(&self, sandbox_id: &str) -> Result<()>;
+    /// Kill the processes of the execution in flight, so `execute_sandbox` returns with the output so far
+    async fn cancel_execution(&self, sandbox_id: &str) -> Result<()>;
+    /// Freeze every process of the sandbox, keeping its memory and filesystem
+    async fn pause_sandbox(&self, sandbox_id: &str) -> Result<()>;
+    async fn resume_sandbox(&self, sandbox_id: &str) -> Result<()>;
     async fn is_available(&self) -> bool;
+    async fn health_check(&self, sandbox_id: &str) -> Result<SandboxHealth>;
+    async fn get_stats(&self, sandbox_id: &str) -> Result<SandboxStats>;
+    /// The last `lines` lines the sandbox printed, then with `follow` new ones as they come
+    async fn get_logs(&self, sandbox_id: &str, lines: usize, follow: bool) -> Result<LogStream>;
+    /// Sandboxes left running by a previous instance of the service
+    async fn recover_sandboxes(&self) -> Result<Vec<RecoveredSandbox>>;
+    /// Containers, directories and the like the backend holds, whether a sandbox claims them or not
+    async fn list_resources(&self) -> Result<Vec<SandboxResource>>;
+    /// Remove a resource [`SandboxBackend::list_resources`] reported
+    async fn remove_resource(&self, resource: &SandboxResource) -> Result<()>;
+    /// What sandboxes on this backend support
+    fn features(&self) -> BackendFeatures;
+    /// Versions of `runtime` the backend can run right now
+    async fn runtime_versions(&self, runtime: &RuntimeDefinition) -> Vec<&'static str>;
     
     // FaaS-specific methods for file updates and dev server management
     async fn update_files(&self, sandbox_id: &str, files: &[super::SandboxFile]) -> Result<()>;
     async fn restart_process(&self, sandbox_id: &str, command: &str) -> Result<()>;
+    /// Host and port the sandbox's service is reached at from the service, if it has one
+    async fn endpoint_for(&self, sandbox_id: &str) -> Result<Option<(String, u16)>>;
+    /// Issue one HTTP GET against `port`/`path` inside the sandbox; errors while the service is not ready
+    async fn probe_http(&self, sandbox_id: &str, port: u16, path: &str) -> Result<()>;
+    /// Last `lines` lines of the dev server log
+    async fn dev_server_logs(&self, sandbox_id: &str, lines: usize) -> Result<String>;
+
+    /// Capture the sandbox filesystem under a named snapshot that `from_snapshot` can restore
+    async fn snapshot_sandbox(&self, sandbox_id: &str, snapshot_name: &str) -> Result<String>;
+
+    /// Archive a snapshot is kept in on the host, for backends that keep snapshots as files
+    fn snapshot_file(&self, _snapshot_name: &str) -> Option<std::path::PathBuf> {
+        None
+    }
+
+    /// Start an add-on container and wait until it is ready, leaving one already running as is
+    async fn start_addon(&self, _addon: &AddonContainer) -> Result<()> {
+        anyhow::bail!("Add-ons need the Docker backend")
+    }
+
+    /// Remove an add-on container, and its network once nothing else is attached to it
+    async fn remove_addon(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Read the project files matching any of the glob `patterns`
+    async fn collect_artifacts(&self, sandbox_id: &str, patterns: &[String]) -> Result<Vec<Artifact>>;
+
+    /// Start `shell` on a `cols`x`rows` pseudo-terminal inside a running sandbox
+    async fn open_terminal(&self, sandbox_id: &str, shell: &str, cols: u16, rows: u16) -> Result<TerminalSession>;
+    async fn resize_terminal(&self, session_id: &str, cols: u16, rows: u16) -> Result<()>;
 }
 
-pub fn create_backend(backend_type: SandboxBackendType) -> Result<Box<dyn SandboxBackend>> {
+/// Create a backend; sandbox services it publishes get host ports from `ports`, and
+/// containers run with the privileges `security` grants
+pub fn create_backend(
+    backend_type: SandboxBackendType,
+    ports: PortAllocator,
+    security: &ContainerSecurity,
+) -> Result<Box<dyn SandboxBackend>> {
     match backend_type {
         SandboxBackendType::Docker => {
             #[cfg(feature = "docker")]
             {
-                Ok(Box::new(docker::DockerBackend::new()?))
+                Ok(Box::new(docker::DockerBackend::new(ports, security.clone())?))
             }
             #[cfg(not(feature = "docker"))]
             {
+                let _ = (ports, security);
                 anyhow::bail!("Docker backend not available. Enable 'docker' feature.")
             }
         }
@@ -53,4 +268,88 @@ pub fn create_backend(backend_type: SandboxBackendType) -> Result<Box<dyn Sandbo
             anyhow::bail!("gVisor backend not yet implemented")
         }
     }
-}
\ No newline at end of file
+}
+
+/// Create the Docker backend of one host of a pool; `ports` is the allocator shared by
+/// all hosts
+pub fn create_docker_host_backend(
+    host: &DockerHostConfig,
+    ports: &PortAllocator,
+    security: &ContainerSecurity,
+) -> Result<Box<dyn SandboxBackend>> {
+    #[cfg(feature = "docker")]
+    {
+        let ports = ports.on_host(&host.address());
+        Ok(Box::new(docker::DockerBackend::connect(&host.url, ports, security.clone())?))
+    }
+    #[cfg(not(feature = "docker"))]
+    {
+        let _ = (host, ports, security);
+        anyhow::bail!("Docker backend not available. Enable 'docker' feature.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(mode: SandboxMode, dev_server: bool) -> SandboxRequest {
+        SandboxRequest {
+            id: "test".to_string(),
+            runtime: "node".to_string(),
+            code: String::new(),
+            entry_point: None,
+            timeout_ms: 1000,
+            memory_limit_mb: 128,
+            env_vars: Default::default(),
+            files: None,
+            mode: Some(mode),
+            install_deps: None,
+            dev_server: Some(dev_server),
+            from_snapshot: None,
+            port: None,
+            artifacts: None,
+            disk_limit_mb: None,
+            max_processes: None,
+            backend: None,
+            security_profile: None,
+            writable_paths: None,
+            gpus: None,
+            labels: Default::default(),
+            api_key: None,
+            install_proxy: None,
+            mounts: None,
+            bind_mounts: Vec::new(),
+            image: None,
+            registry_credential: None,
+            deployment: None,
+            network: None,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn test_backend_preference() {
+        let one_shot = request(SandboxMode::OneShot, false);
+        let dev_server = request(SandboxMode::Persistent, true);
+        let docker = SandboxBackendType::Docker;
+
+        assert_eq!(preference_order(&one_shot, &docker), vec![SandboxBackendType::Nsjail, docker.clone()]);
+        assert_eq!(preference_order(&dev_server, &docker), vec![docker.clone(), SandboxBackendType::Nsjail]);
+
+        let nsjail = BackendFeatures { snapshots: true, ..Default::default() };
+        assert!(supports(&nsjail, &one_shot));
+        assert!(!supports(&nsjail, &dev_server));
+        let browser = SandboxRequest { runtime: "playwright".to_string(), ..one_shot };
+        assert!(!supports(&nsjail, &browser));
+        assert!(supports(&BackendFeatures { browser: true, ..Default::default() }, &browser));
+        assert!(!supports(&BackendFeatures { browser: true, ..Default::default() }, &SandboxRequest { runtime: "media".to_string(), ..browser }));
+        let mounts = SandboxRequest { mounts: Some(vec![voidrun_types::SandboxMount { name: "models".to_string(), target: None }]), ..request(SandboxMode::OneShot, false) };
+        assert!(!supports(&nsjail, &mounts));
+        assert!(supports(&BackendFeatures { mounts: true, ..Default::default() }, &mounts));
+        let custom = SandboxRequest { image: Some("ghcr.io/org/custom:tag".to_string()), ..mounts };
+        assert!(!supports(&BackendFeatures { mounts: true, ..Default::default() }, &custom));
+        assert_eq!("NSJAIL".parse::<SandboxBackendType>().unwrap(), SandboxBackendType::Nsjail);
+        assert!("lxc".parse::<SandboxBackendType>().is_err());
+    }
+}
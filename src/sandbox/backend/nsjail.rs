@@ -1,49 +1,296 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::process::Stdio;
+use futures_util::{stream, StreamExt};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::Mutex;
 use std::time::Instant;
 use tempfile::TempDir;
 use tokio::fs;
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
 use tokio::time::{timeout, Duration};
 
-use super::SandboxBackend;
-use crate::sandbox::{SandboxRequest, SandboxResponse};
+use super::cgroup::{self, CgroupRoot};
+use super::{LogStream, RecoveredSandbox, SandboxBackend, SandboxHealth, SandboxStats, TerminalSession};
+use voidrun_types::{BackendFeatures, LogEntry};
+use crate::runtime::{self, bash, media, playwright, rust, RuntimeDefinition};
+use crate::sandbox::artifacts::{self, Artifact, MAX_ARTIFACT_BYTES};
+use crate::sandbox::gc::SandboxResource;
+use crate::sandbox::{file_contents, ExecutionUsage, OutputChunk, OutputSender, SandboxRequest, SandboxResponse, TerminationReason};
+use crate::security::seccomp;
+
+/// Managed toolchains pinned runtimes run from, laid out as `<runtime>/<version>/bin`
+const DEFAULT_TOOLCHAIN_DIR: &str = "/opt/voidrun/toolchains";
+/// Prefix of the temporary directory each instance keeps sandboxes in, followed by its pid
+const TEMP_DIR_PREFIX: &str = "voidrun-nsjail-";
+/// How long CPU time is sampled over for stats
+const CPU_SAMPLE_PERIOD: Duration = Duration::from_millis(200);
+/// How often a followed log is checked for new output
+const LOG_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct NsjailBackend {
     nsjail_path: String,
     temp_dir: TempDir,
+    toolchain_dir: PathBuf,
+    /// Process ids of the nsjail processes executing each sandbox right now
+    running: Mutex<HashMap<String, u32>>,
+    /// Where executions get a cgroup each; unset where cgroup v2 cannot be used, leaving
+    /// memory to the address-space rlimit
+    cgroups: Option<CgroupRoot>,
+}
+
+/// `root` and every process descending from it, parents first
+///
+/// The jailed program is a child of nsjail in its own session, so nsjail's process
+/// group would miss it; the tree is walked through /proc instead.
+fn process_tree(root: u32) -> Result<Vec<u32>> {
+    let mut parents = HashMap::new();
+    for entry in std::fs::read_dir("/proc").context("Failed to list processes")?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        // The command name may contain spaces and parentheses; the parent pid follows its closing one
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        let ppid = stat.rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(1))
+            .and_then(|ppid| ppid.parse::<u32>().ok());
+        if let Some(ppid) = ppid {
+            parents.insert(pid, ppid);
+        }
+    }
+
+    let mut tree = vec![root];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(parents.iter().filter(|(_, ppid)| **ppid == parent).map(|(pid, _)| *pid));
+        i += 1;
+    }
+    Ok(tree)
+}
+
+/// Send `signal` to `root` and every process descending from it
+fn signal_process_tree(root: u32, signal: libc::c_int) -> Result<()> {
+    // Parents first, so a stopped process cannot fork past the walk
+    for pid in process_tree(root)? {
+        // SAFETY: kill has no memory-safety preconditions
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 && pid == root {
+            return Err(std::io::Error::last_os_error()).context(format!("Failed to signal process {}", root));
+        }
+    }
+    Ok(())
+}
+
+/// What a set of processes has consumed so far, read from /proc
+#[derive(Debug, Default)]
+struct ProcessUsage {
+    cpu_ticks: u64,
+    rss_bytes: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+impl ProcessUsage {
+    /// Processes that exited while being read are left out
+    fn read(pids: &[u32]) -> Self {
+        let mut usage = Self::default();
+        for pid in pids {
+            let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+            // utime and stime are the 12th and 13th fields after the command name
+            if let Ok(stat) = std::fs::read_to_string(proc_dir.join("stat")) {
+                let fields: Vec<&str> = stat.rsplit_once(')').map(|(_, fields)| fields.split_whitespace().collect()).unwrap_or_default();
+                usage.cpu_ticks += fields.get(11..13).into_iter().flatten().filter_map(|ticks| ticks.parse::<u64>().ok()).sum::<u64>();
+            }
+            if let Ok(status) = std::fs::read_to_string(proc_dir.join("status")) {
+                usage.rss_bytes += proc_field(&status, "VmRSS:") * 1024;
+            }
+            if let Ok(io) = std::fs::read_to_string(proc_dir.join("io")) {
+                usage.read_bytes += proc_field(&io, "read_bytes:");
+                usage.write_bytes += proc_field(&io, "write_bytes:");
+            }
+        }
+        usage
+    }
+}
+
+/// Number following `key` in a /proc file of `key value` lines
+fn proc_field(content: &str, key: &str) -> u64 {
+    content.lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Log entries of an execution's output, one per line, stdout first
+fn output_log_entries(sandbox_id: &str, response: &SandboxResponse) -> Vec<LogEntry> {
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    [("INFO", &response.stdout), ("ERROR", &response.stderr)].into_iter()
+        .flat_map(|(level, output)| output.lines().map(move |line| (level, line)))
+        .map(|(level, line)| LogEntry {
+            timestamp: timestamp.clone(),
+            level: level.to_string(),
+            message: line.to_string(),
+            sandbox_id: Some(sandbox_id.to_string()),
+        })
+        .collect()
+}
+
+/// Entries of the complete lines of a log file, and how many bytes they span
+fn parse_log(content: &str) -> (Vec<LogEntry>, usize) {
+    // A line still being written has no newline yet
+    let complete = content.rfind('\n').map_or(0, |end| end + 1);
+    let entries = content[..complete].lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    (entries, complete)
 }
 
 impl NsjailBackend {
+    /// Output of the sandbox's executions, as JSON lines of log entries
+    fn log_path(&self, sandbox_id: &str) -> PathBuf {
+        self.temp_dir.path().join(".logs").join(format!("{}.log", sandbox_id))
+    }
+
+    async fn append_log(&self, sandbox_id: &str, response: &SandboxResponse) -> Result<()> {
+        let mut lines = String::new();
+        for entry in output_log_entries(sandbox_id, response) {
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+        }
+        let path = self.log_path(sandbox_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.context("Failed to create log directory")?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&path).await
+            .context("Failed to open sandbox log")?;
+        file.write_all(lines.as_bytes()).await.context("Failed to write sandbox log")?;
+        Ok(())
+    }
+
+    fn snapshot_path(&self, snapshot_name: &str) -> std::path::PathBuf {
+        self.temp_dir.path().join(".snapshots").join(format!("{}.tar", snapshot_name))
+    }
+
+    async fn run_tar(args: &[&str]) -> Result<()> {
+        let output = Command::new("tar")
+            .args(args)
+            .output()
+            .await
+            .context("Failed to run tar")?;
+
+        if !output.status.success() {
+            anyhow::bail!("tar failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+
+    /// Binary directory of the toolchain a pinned runtime such as `node@20` runs with
+    fn toolchain_bin(&self, runtime: &str) -> Result<PathBuf> {
+        let version = runtime::resolve_version(runtime)?;
+        // TypeScript runs on the Node.js toolchain
+        let toolchain = match runtime::name(runtime) {
+            "nodejs" | "typescript" | "ts" => "node",
+            name => name,
+        };
+        let bin = self.toolchain_dir.join(toolchain).join(version).join("bin");
+        if !bin.is_dir() {
+            anyhow::bail!("Runtime {}@{} is not installed: {} does not exist", toolchain, version, bin.display());
+        }
+        Ok(bin)
+    }
+
     pub fn new() -> Result<Self> {
         let nsjail_path = which::which("nsjail")
             .context("nsjail not found in PATH. Please install nsjail.")?
             .to_string_lossy()
             .to_string();
 
-        let temp_dir = tempfile::TempDir::new()
+        let temp_dir = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", TEMP_DIR_PREFIX, std::process::id()))
+            .tempdir()
             .context("Failed to create temporary directory")?;
 
+        let toolchain_dir = std::env::var("VOIDRUN_TOOLCHAIN_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_TOOLCHAIN_DIR));
+
+        let cgroup_root = std::env::var("VOIDRUN_NSJAIL_CGROUP").unwrap_or_else(|_| cgroup::DEFAULT_ROOT.to_string());
+        let cgroups = match CgroupRoot::init(&cgroup_root) {
+            Ok(root) => {
+                tracing::info!("nsjail executions are limited through cgroups below {}", root.path().display());
+                Some(root)
+            }
+            Err(e) => {
+                tracing::warn!("Cannot use cgroup v2 for nsjail, limiting memory by address space only: {:#}", e);
+                None
+            }
+        };
+
         Ok(Self {
             nsjail_path,
             temp_dir,
+            toolchain_dir,
+            running: Mutex::new(HashMap::new()),
+            cgroups,
         })
     }
 
+    /// Temporary directories of previous instances that are no longer running
+    fn stale_temp_dirs(&self) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+            return Vec::new();
+        };
+        entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| *path != self.temp_dir.path())
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                let owner = name.strip_prefix(TEMP_DIR_PREFIX)
+                    .and_then(|rest| rest.split('-').next())
+                    .and_then(|pid| pid.parse::<u32>().ok());
+                owner.is_some_and(|pid| !PathBuf::from(format!("/proc/{}", pid)).exists())
+            })
+            .collect()
+    }
+
     async fn setup_sandbox_env(&self, request: &SandboxRequest) -> Result<String> {
         let sandbox_dir = self.temp_dir.path().join(&request.id);
         fs::create_dir_all(&sandbox_dir).await
             .context("Failed to create sandbox directory")?;
 
-        let file_extension = match request.runtime.as_str() {
-            "node" | "nodejs" => "js",
-            "bun" => "js",
-            "typescript" | "ts" => "ts",
+        if let Some(snapshot_name) = &request.from_snapshot {
+            crate::sandbox::validate_snapshot_name(snapshot_name)?;
+            let snapshot_path = self.snapshot_path(snapshot_name);
+            if !snapshot_path.exists() {
+                anyhow::bail!("Snapshot {} not found", snapshot_name);
+            }
+            Self::run_tar(&[
+                "-C", &sandbox_dir.to_string_lossy(),
+                "-xf", &snapshot_path.to_string_lossy(),
+            ]).await.context("Failed to restore snapshot")?;
+        }
+
+        let code_file = match runtime::name(&request.runtime) {
+            "node" | "nodejs" => sandbox_dir.join("index.js"),
+            "bun" => sandbox_dir.join("index.js"),
+            "typescript" | "ts" => sandbox_dir.join("index.ts"),
+            rust::RUNTIME => {
+                fs::create_dir_all(sandbox_dir.join("src")).await
+                    .context("Failed to create src directory")?;
+                fs::write(sandbox_dir.join(rust::MANIFEST_FILE), rust::DEFAULT_MANIFEST).await
+                    .context("Failed to write Cargo.toml")?;
+                sandbox_dir.join(rust::MAIN_FILE)
+            }
+            bash::RUNTIME => sandbox_dir.join(bash::SCRIPT_FILE),
             _ => anyhow::bail!("Unsupported runtime: {}", request.runtime),
         };
 
-        let code_file = sandbox_dir.join(format!("index.{}", file_extension));
         fs::write(&code_file, &request.code).await
             .context("Failed to write code file")?;
 
@@ -62,7 +309,7 @@ impl NsjailBackend {
                         .context("Failed to create parent directory")?;
                 }
 
-                fs::write(&file_path, &file.content).await
+                fs::write(&file_path, file_contents(file)?).await
                     .context("Failed to write file")?;
 
                 // Make executable if specified
@@ -85,12 +332,36 @@ impl NsjailBackend {
     async fn execute_with_nsjail(&self, request: &SandboxRequest, sandbox_dir: &str) -> Result<SandboxResponse> {
         let start_time = Instant::now();
 
-        let runtime_cmd = match request.runtime.as_str() {
+        let runtime_cmd = match runtime::name(&request.runtime) {
             "node" | "nodejs" => vec!["node", "index.js"],
             "bun" => vec!["bun", "run", "index.js"],
             "typescript" | "ts" => vec!["npx", "ts-node", "index.ts"],
+            rust::RUNTIME => rust::RUN_COMMAND.split(' ').collect(),
+            bash::RUNTIME => bash::RUN_COMMAND.split(' ').collect(),
             _ => anyhow::bail!("Unsupported runtime: {}", request.runtime),
         };
+        // Check mode runs the checker on the file the run command names
+        let check_cmd = match request.mode {
+            Some(crate::sandbox::SandboxMode::Check) => runtime::check::command(&request.runtime, runtime_cmd[runtime_cmd.len() - 1])
+                .with_context(|| format!("Runtime {} has no check mode", request.runtime))?,
+            _ => String::new(),
+        };
+        let runtime_cmd = if check_cmd.is_empty() { runtime_cmd } else { check_cmd.split(' ').collect() };
+        self.run_in_jail(request, sandbox_dir, &runtime_cmd, start_time).await
+    }
+
+    /// Run `runtime_cmd` in the sandbox directory under nsjail, with the request's limits;
+    /// its program is looked up in the pinned toolchain unless it is an absolute path
+    async fn run_in_jail(&self, request: &SandboxRequest, sandbox_dir: &str, runtime_cmd: &[&str], start_time: Instant) -> Result<SandboxResponse> {
+        // Unpinned runtimes use whatever version the host has on PATH
+        let toolchain_bin = match runtime::parse(&request.runtime) {
+            (_, Some(_)) => Some(self.toolchain_bin(&request.runtime)?),
+            (_, None) => None,
+        };
+        let program = match &toolchain_bin {
+            Some(bin) => bin.join(runtime_cmd[0]).to_string_lossy().to_string(),
+            None => runtime_cmd[0].to_string(),
+        };
 
         let mut cmd = Command::new(&self.nsjail_path);
         cmd.args([
@@ -99,17 +370,55 @@ impl NsjailBackend {
             "--group", "nogroup",
             "--hostname", "sandbox",
             "--cwd", sandbox_dir,
-            "--rlimit_as", &format!("{}", request.memory_limit_mb * 1024 * 1024),
             "--rlimit_cpu", "30", // 30 seconds CPU time
-            "--rlimit_fsize", "10485760", // 10MB file size limit
+            "--rlimit_fsize", &request.disk_limit_mb().to_string(), // File size limit, in MB
             "--rlimit_nofile", "64", // 64 open files
+            "--rlimit_nproc", &request.max_processes().to_string(),
             "--disable_no_new_privs",
             "--time_limit", &format!("{}", request.timeout_ms / 1000), // Convert to seconds
             "--really_quiet",
-            "--",
         ]);
+        // nsjail moves the program into a child of the execution's cgroup, which needs limits
+        // of its own to be created; they match the parent's
+        let cgroup = self.cgroups.as_ref().map(|root| root.create(request)).transpose()?;
+        match &cgroup {
+            Some(cgroup) => {
+                cmd.args([
+                    "--rlimit_as", "max",
+                    "--use_cgroupv2",
+                    "--cgroupv2_mount", &cgroup.path().to_string_lossy(),
+                    "--cgroup_mem_max", &(request.memory_limit_mb * 1024 * 1024).to_string(),
+                    "--cgroup_pids_max", &request.max_processes().to_string(),
+                    "--cgroup_cpu_ms_per_sec", &cgroup::CPU_MS_PER_SEC.to_string(),
+                ]);
+            }
+            None => {
+                cmd.args(["--rlimit_as", &(request.memory_limit_mb * 1024 * 1024).to_string()]);
+            }
+        }
+        if let Some(policy) = seccomp::policy(request.security_profile.unwrap_or_default(), &request.runtime) {
+            cmd.args(["--seccomp_string", &policy]);
+        }
+
+        // The host filesystem is mounted read-only; writable paths get a tmpfs each, mounted
+        // before the project directory so a tmpfs over /tmp cannot hide it
+        cmd.args([
+            "--chroot", "/",
+            "--tmpfs_size", &(request.disk_limit_mb() * 1024 * 1024).to_string(),
+        ]);
+        for path in request.writable_paths()? {
+            cmd.args(["--tmpfsmount", &path]);
+        }
+        cmd.args(["--bindmount", sandbox_dir]);
+        if rust::is_rust(&request.runtime) {
+            let target_dir = self.temp_dir.path().join(".cargo-target");
+            fs::create_dir_all(&target_dir).await.context("Failed to create cargo target directory")?;
+            cmd.args(["--bindmount", &target_dir.to_string_lossy()]);
+        }
+        cmd.arg("--");
 
-        cmd.args(runtime_cmd);
+        cmd.arg(program);
+        cmd.args(&runtime_cmd[1..]);
         cmd.current_dir(sandbox_dir);
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
@@ -119,26 +428,58 @@ impl NsjailBackend {
         for (key, value) in &request.env_vars {
             cmd.env(key, value);
         }
+        if let Some(bin) = &toolchain_bin {
+            let path = std::env::var("PATH").unwrap_or_default();
+            cmd.env("PATH", format!("{}:{}", bin.display(), path));
+        }
+        // Builds share one target directory so dependencies compile once
+        if rust::is_rust(&request.runtime) {
+            cmd.env("CARGO_TARGET_DIR", self.temp_dir.path().join(".cargo-target"));
+            cmd.env("RUSTFLAGS", rust::RUSTFLAGS);
+        }
 
         let child_result = cmd.spawn();
 
         match child_result {
             Ok(child) => {
+                if let Some(pid) = child.id() {
+                    self.running.lock().unwrap().insert(request.id.clone(), pid);
+                }
                 let output_result = timeout(
                     Duration::from_millis(request.timeout_ms + 1000),
-                    async {
-                        child.wait_with_output().await
-                    }
+                    wait_with_usage(child, request.output.as_ref()),
                 ).await;
+                self.running.lock().unwrap().remove(&request.id);
 
                 let execution_time = start_time.elapsed().as_millis() as u64;
+                let cgroup_usage = cgroup.map(|cgroup| {
+                    let usage = cgroup.usage();
+                    if let Err(e) = cgroup.remove() {
+                        tracing::warn!("Failed to remove the cgroup of sandbox {}: {:#}", request.id, e);
+                    }
+                    usage
+                });
 
                 match output_result {
-                    Ok(Ok(output)) => {
+                    Ok(Ok((output, rusage))) => {
+                        let usage = ExecutionUsage {
+                            peak_memory_bytes: match &cgroup_usage {
+                                Some(cgroup_usage) => cgroup_usage.memory_peak_bytes,
+                                None => rusage.map(|rusage| rusage.ru_maxrss as u64 * 1024),
+                            },
+                            cpu_time_ms: match &cgroup_usage {
+                                Some(cgroup_usage) => Some(cgroup_usage.cpu_usec / 1000),
+                                None => rusage.map(|rusage| timeval_ms(rusage.ru_utime) + timeval_ms(rusage.ru_stime)),
+                            },
+                            stdout_bytes: output.stdout.len() as u64,
+                            stderr_bytes: output.stderr.len() as u64,
+                        };
                         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
                         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
                         let exit_code = output.status.code();
                         let success = output.status.success();
+                        let oom_kills = cgroup_usage.map(|cgroup_usage| cgroup_usage.oom_kills);
+                        let termination_reason = termination_reason(output.status, execution_time, request, &usage, oom_kills);
 
                         Ok(SandboxResponse {
                             success,
@@ -148,6 +489,12 @@ impl NsjailBackend {
                             execution_time_ms: execution_time,
                             is_running: Some(false),
                             dev_server_url: None,
+                            canceled: false,
+                            usage: Some(usage),
+                            termination_reason: Some(termination_reason),
+                            execution_id: None,
+                            cost: None,
+                            diagnostics: None,
                         })
                     }
                     Ok(Err(e)) => {
@@ -159,6 +506,12 @@ impl NsjailBackend {
                             execution_time_ms: execution_time,
                             is_running: Some(false),
                             dev_server_url: None,
+                            canceled: false,
+                            usage: None,
+                            termination_reason: Some(TerminationReason::SetupFailed),
+                            execution_id: None,
+                            cost: None,
+                            diagnostics: None,
                         })
                     }
                     Err(_) => {
@@ -170,6 +523,12 @@ impl NsjailBackend {
                             execution_time_ms: execution_time,
                             is_running: Some(false),
                             dev_server_url: None,
+                            canceled: false,
+                            usage: None,
+                            termination_reason: Some(TerminationReason::Timeout),
+                            execution_id: None,
+                            cost: None,
+                            diagnostics: None,
                         })
                     }
                 }
@@ -183,22 +542,124 @@ impl NsjailBackend {
                     execution_time_ms: start_time.elapsed().as_millis() as u64,
                     is_running: Some(false),
                     dev_server_url: None,
+                    canceled: false,
+                    usage: None,
+                    termination_reason: Some(TerminationReason::SetupFailed),
+                    execution_id: None,
+                    cost: None,
+                    diagnostics: None,
                 })
             }
         }
     }
 }
 
+/// Wait for nsjail to exit, collecting its output, also forwarded to `output_sender` as
+/// it is read, and resource usage
+///
+/// Waits with `wait4`, whose usage of nsjail includes the jailed process it waited for.
+async fn wait_with_usage(mut child: Child, output_sender: Option<&OutputSender>) -> std::io::Result<(Output, Option<libc::rusage>)> {
+    async fn read_all(
+        pipe: Option<impl AsyncRead + Unpin>,
+        output_sender: Option<&OutputSender>,
+        chunk: fn(String) -> OutputChunk,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        let Some(mut pipe) = pipe else {
+            return Ok(buffer);
+        };
+        let mut read = [0u8; 8192];
+        loop {
+            let n = pipe.read(&mut read).await?;
+            if n == 0 {
+                return Ok(buffer);
+            }
+            if let Some(sender) = output_sender {
+                let _ = sender.send(chunk(String::from_utf8_lossy(&read[..n]).into_owned()));
+            }
+            buffer.extend_from_slice(&read[..n]);
+        }
+    }
+    let (stdout, stderr) = tokio::try_join!(
+        read_all(child.stdout.take(), output_sender, OutputChunk::Stdout),
+        read_all(child.stderr.take(), output_sender, OutputChunk::Stderr),
+    )?;
+
+    let Some(pid) = child.id() else {
+        let status = child.wait().await?;
+        return Ok((Output { status, stdout, stderr }, None));
+    };
+    let (status, rusage) = tokio::task::spawn_blocking(move || {
+        let mut status = 0;
+        // SAFETY: rusage is plain old data that wait4 fills in
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: waits for our own child, which has not been reaped as nothing else waits for it
+        if unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut rusage) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((ExitStatus::from_raw(status), rusage))
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+    Ok((Output { status, stdout, stderr }, Some(rusage)))
+}
+
+fn timeval_ms(time: libc::timeval) -> u64 {
+    time.tv_sec as u64 * 1000 + time.tv_usec as u64 / 1000
+}
+
+/// How the jailed program ended, from nsjail's exit status
+///
+/// nsjail exits with 128 + n when signal n killed the program, which is also how its time
+/// limit ends it. With a cgroup, `oom_kills` counts the processes its OOM killer ended.
+/// Without one, the address-space limit makes allocations fail rather than waking the OOM
+/// killer, so a program that died of a signal with its memory nearly exhausted counts as
+/// out of memory.
+fn termination_reason(
+    status: ExitStatus,
+    elapsed_ms: u64,
+    request: &SandboxRequest,
+    usage: &ExecutionUsage,
+    oom_kills: Option<u64>,
+) -> TerminationReason {
+    let Some(signal) = status.signal().or_else(|| status.code().filter(|code| *code > 128).map(|code| code - 128)) else {
+        return TerminationReason::Completed;
+    };
+    let time_limit_ms = request.timeout_ms / 1000 * 1000;
+    let memory_limit = request.memory_limit_mb * 1024 * 1024;
+    match signal {
+        _ if oom_kills.is_some_and(|kills| kills > 0) => TerminationReason::OomKilled,
+        libc::SIGXCPU => TerminationReason::Timeout,
+        libc::SIGKILL if time_limit_ms > 0 && elapsed_ms >= time_limit_ms => TerminationReason::Timeout,
+        _ if oom_kills.is_none() && usage.peak_memory_bytes.is_some_and(|peak| peak >= memory_limit / 10 * 9) => {
+            TerminationReason::OomKilled
+        }
+        _ => TerminationReason::Killed,
+    }
+}
+
 #[async_trait]
 impl SandboxBackend for NsjailBackend {
-    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<()> {
+    async fn create_sandbox(&self, request: &SandboxRequest) -> Result<Option<u16>> {
         self.setup_sandbox_env(request).await?;
-        Ok(())
+        Ok(None)
     }
 
     async fn execute_sandbox(&self, request: &SandboxRequest) -> Result<SandboxResponse> {
         let sandbox_dir = self.setup_sandbox_env(request).await?;
         let response = self.execute_with_nsjail(request, &sandbox_dir).await?;
+        if let Err(e) = self.append_log(&request.id, &response).await {
+            tracing::warn!("Failed to log output of sandbox {}: {}", request.id, e);
+        }
+        Ok(response)
+    }
+
+    async fn run_command(&self, request: &SandboxRequest, command: &str) -> Result<SandboxResponse> {
+        let sandbox_dir = self.setup_sandbox_env(request).await?;
+        let response = self.run_in_jail(request, &sandbox_dir, &["/bin/sh", "-c", command], Instant::now()).await?;
+        if let Err(e) = self.append_log(&request.id, &response).await {
+            tracing::warn!("Failed to log output of sandbox {}: {}", request.id, e);
+        }
         Ok(response)
     }
 
@@ -208,6 +669,39 @@ impl SandboxBackend for NsjailBackend {
             fs::remove_dir_all(sandbox_dir).await
                 .context("Failed to cleanup sandbox directory")?;
         }
+        let log_path = self.log_path(sandbox_id);
+        if log_path.exists() {
+            fs::remove_file(log_path).await
+                .context("Failed to remove sandbox log")?;
+        }
+        Ok(())
+    }
+
+    async fn cancel_execution(&self, sandbox_id: &str) -> Result<()> {
+        let Some(pid) = self.running.lock().unwrap().get(sandbox_id).copied() else {
+            return Ok(());
+        };
+        signal_process_tree(pid, libc::SIGKILL)?;
+        tracing::info!("Killed execution of sandbox {} (nsjail pid {})", sandbox_id, pid);
+        Ok(())
+    }
+
+    async fn pause_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        // Between executions nothing runs, so there is nothing to stop
+        let Some(pid) = self.running.lock().unwrap().get(sandbox_id).copied() else {
+            return Ok(());
+        };
+        signal_process_tree(pid, libc::SIGSTOP)?;
+        tracing::info!("Paused sandbox {} (nsjail pid {})", sandbox_id, pid);
+        Ok(())
+    }
+
+    async fn resume_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        let Some(pid) = self.running.lock().unwrap().get(sandbox_id).copied() else {
+            return Ok(());
+        };
+        signal_process_tree(pid, libc::SIGCONT)?;
+        tracing::info!("Resumed sandbox {} (nsjail pid {})", sandbox_id, pid);
         Ok(())
     }
 
@@ -222,6 +716,160 @@ impl SandboxBackend for NsjailBackend {
             .unwrap_or(false)
     }
 
+    async fn health_check(&self, sandbox_id: &str) -> Result<SandboxHealth> {
+        if !self.temp_dir.path().join(sandbox_id).is_dir() {
+            return Ok(SandboxHealth { healthy: false, status: "missing".to_string() });
+        }
+        let executing = self.running.lock().unwrap().contains_key(sandbox_id);
+        let status = if executing { "executing" } else { "idle" };
+        Ok(SandboxHealth { healthy: true, status: status.to_string() })
+    }
+
+    async fn get_stats(&self, sandbox_id: &str) -> Result<SandboxStats> {
+        // Between executions nothing runs, so nothing is used
+        let Some(pid) = self.running.lock().unwrap().get(sandbox_id).copied() else {
+            return Ok(SandboxStats::default());
+        };
+        let cgroup = self.cgroups.as_ref().and_then(|root| root.get(sandbox_id));
+        let before = ProcessUsage::read(&process_tree(pid)?);
+        let cgroup_before = cgroup.as_ref().map(|cgroup| cgroup.usage());
+        tokio::time::sleep(CPU_SAMPLE_PERIOD).await;
+        let after = ProcessUsage::read(&process_tree(pid)?);
+        let cgroup_after = cgroup.as_ref().map(|cgroup| cgroup.usage());
+
+        // SAFETY: sysconf has no memory-safety preconditions
+        let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+        let cpu_seconds = after.cpu_ticks.saturating_sub(before.cpu_ticks) as f64 / ticks_per_second;
+        let stats = SandboxStats {
+            memory_used_bytes: after.rss_bytes,
+            cpu_percentage: cpu_seconds / CPU_SAMPLE_PERIOD.as_secs_f64() * 100.0,
+            disk_read_bytes: after.read_bytes,
+            disk_write_bytes: after.write_bytes,
+            ..Default::default()
+        };
+        // Jails have no network; without a cgroup their memory limit is an address-space
+        // limit, not a cap on usage
+        let (Some(before), Some(after)) = (cgroup_before, cgroup_after) else {
+            return Ok(stats);
+        };
+        let cpu_seconds = after.cpu_usec.saturating_sub(before.cpu_usec) as f64 / 1_000_000.0;
+        Ok(SandboxStats {
+            memory_used_bytes: after.memory_current_bytes,
+            memory_limit_bytes: after.memory_max_bytes,
+            cpu_percentage: cpu_seconds / CPU_SAMPLE_PERIOD.as_secs_f64() * 100.0,
+            ..stats
+        })
+    }
+
+    async fn get_logs(&self, sandbox_id: &str, lines: usize, follow: bool) -> Result<LogStream> {
+        let path = self.log_path(sandbox_id);
+        let content = fs::read_to_string(&path).await.unwrap_or_default();
+        let (mut entries, offset) = parse_log(&content);
+        let recent = entries.split_off(entries.len().saturating_sub(lines));
+        let recent = stream::iter(recent.into_iter().map(Ok));
+        if !follow {
+            return Ok(recent.boxed());
+        }
+
+        // Executions append their output when they end; the log is polled until the sandbox is gone
+        let sandbox_dir = self.temp_dir.path().join(sandbox_id);
+        let appended = stream::unfold(offset, move |offset| {
+            let (path, sandbox_dir) = (path.clone(), sandbox_dir.clone());
+            async move {
+                loop {
+                    tokio::time::sleep(LOG_POLL_INTERVAL).await;
+                    if !sandbox_dir.exists() {
+                        return None;
+                    }
+                    let content = fs::read(&path).await.unwrap_or_default();
+                    let new = String::from_utf8_lossy(content.get(offset..).unwrap_or_default()).to_string();
+                    let (entries, read) = parse_log(&new);
+                    if read > 0 {
+                        return Some((stream::iter(entries.into_iter().map(Ok)), offset + read));
+                    }
+                }
+            }
+        })
+        .flatten();
+        Ok(recent.chain(appended).boxed())
+    }
+
+    async fn recover_sandboxes(&self) -> Result<Vec<RecoveredSandbox>> {
+        // Sandbox directories live in a temporary directory removed with the previous instance
+        Ok(Vec::new())
+    }
+
+    async fn list_resources(&self) -> Result<Vec<SandboxResource>> {
+        let created_at = |metadata: &std::fs::Metadata| {
+            metadata.created().or_else(|_| metadata.modified()).map(chrono::DateTime::<chrono::Utc>::from).ok()
+        };
+        let mut resources = Vec::new();
+
+        // Snapshots and the shared cargo target directory start with a dot and are kept
+        let mut entries = fs::read_dir(self.temp_dir.path()).await
+            .context("Failed to list sandbox directories")?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || self.running.lock().unwrap().contains_key(&name) {
+                continue;
+            }
+            if let Some(created_at) = created_at(&entry.metadata().await?) {
+                resources.push(SandboxResource {
+                    sandbox_id: Some(name),
+                    name: entry.path().to_string_lossy().to_string(),
+                    created_at,
+                });
+            }
+        }
+
+        for path in self.stale_temp_dirs() {
+            if let Some(created_at) = fs::metadata(&path).await.ok().as_ref().and_then(created_at) {
+                resources.push(SandboxResource {
+                    sandbox_id: None,
+                    name: path.to_string_lossy().to_string(),
+                    created_at,
+                });
+            }
+        }
+        Ok(resources)
+    }
+
+    async fn remove_resource(&self, resource: &SandboxResource) -> Result<()> {
+        let path = PathBuf::from(&resource.name);
+        let owned = path.starts_with(self.temp_dir.path()) || self.stale_temp_dirs().contains(&path);
+        if !owned {
+            anyhow::bail!("{} is not a sandbox directory", path.display());
+        }
+        fs::remove_dir_all(&path).await
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        tracing::info!("Removed orphaned sandbox directory {}", path.display());
+        Ok(())
+    }
+
+    fn features(&self) -> BackendFeatures {
+        BackendFeatures {
+            snapshots: true,
+            ..Default::default()
+        }
+    }
+
+    async fn runtime_versions(&self, runtime: &RuntimeDefinition) -> Vec<&'static str> {
+        // Unpinned runs use the host's binary, reported as the default version
+        let host_binary = match runtime.name {
+            // Browsers and media tools run on the Docker backend's images only
+            playwright::RUNTIME | media::RUNTIME => return Vec::new(),
+            "typescript" => "npx",
+            rust::RUNTIME => "cargo",
+            name => name,
+        };
+        runtime.versions.iter().copied()
+            .filter(|version| {
+                (*version == runtime.default_version && which::which(host_binary).is_ok())
+                    || self.toolchain_bin(&format!("{}@{}", runtime.name, version)).is_ok()
+            })
+            .collect()
+    }
+
     
     async fn update_files(&self, sandbox_id: &str, files: &[crate::sandbox::SandboxFile]) -> Result<()> {
         let sandbox_dir = self.temp_dir.path().join(sandbox_id);
@@ -239,7 +887,7 @@ impl SandboxBackend for NsjailBackend {
                     .context("Failed to create parent directory")?;
             }
 
-            fs::write(&file_path, &file.content).await
+            fs::write(&file_path, file_contents(file)?).await
                 .context("Failed to write file")?;
 
             // Make executable if specified
@@ -267,4 +915,88 @@ impl SandboxBackend for NsjailBackend {
         tracing::warn!("nsjail backend doesn't support hot process restart - files updated for next execution");
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn endpoint_for(&self, _sandbox_id: &str) -> Result<Option<(String, u16)>> {
+        // Jails run one execution and exit, so there is no dev server to publish; without
+        // a persistent mode, `features` keeps `dev_server` requests on other backends
+        Ok(None)
+    }
+
+    async fn probe_http(&self, sandbox_id: &str, _port: u16, _path: &str) -> Result<()> {
+        // Jailed processes run to completion inside their own network namespace
+        anyhow::bail!("nsjail backend does not keep a service running for sandbox {}", sandbox_id)
+    }
+
+    async fn dev_server_logs(&self, _sandbox_id: &str, _lines: usize) -> Result<String> {
+        Ok(String::new())
+    }
+
+    async fn snapshot_sandbox(&self, sandbox_id: &str, snapshot_name: &str) -> Result<String> {
+        let sandbox_dir = self.temp_dir.path().join(sandbox_id);
+        if !sandbox_dir.exists() {
+            anyhow::bail!("Sandbox directory for {} not found", sandbox_id);
+        }
+
+        let snapshot_path = self.snapshot_path(snapshot_name);
+        if let Some(parent) = snapshot_path.parent() {
+            fs::create_dir_all(parent).await
+                .context("Failed to create snapshot directory")?;
+        }
+
+        Self::run_tar(&[
+            "-C", &sandbox_dir.to_string_lossy(),
+            "-cf", &snapshot_path.to_string_lossy(),
+            ".",
+        ]).await.context("Failed to archive sandbox directory")?;
+
+        tracing::info!("Sandbox {} snapshotted to {}", sandbox_id, snapshot_path.display());
+        Ok(snapshot_path.to_string_lossy().to_string())
+    }
+
+    fn snapshot_file(&self, snapshot_name: &str) -> Option<PathBuf> {
+        Some(self.snapshot_path(snapshot_name))
+    }
+
+    async fn collect_artifacts(&self, sandbox_id: &str, patterns: &[String]) -> Result<Vec<Artifact>> {
+        let sandbox_dir = self.temp_dir.path().join(sandbox_id);
+        let mut collected = Vec::new();
+        let mut total_bytes = 0;
+        let mut pending = vec![sandbox_dir.clone()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = fs::read_dir(&dir).await
+                .with_context(|| format!("Failed to read {}", dir.display()))?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    if entry.file_name() != "node_modules" {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+
+                let relative = path.strip_prefix(&sandbox_dir)?.to_string_lossy().replace('\\', "/");
+                if !artifacts::matches_any(patterns, &relative) {
+                    continue;
+                }
+                let data = fs::read(&path).await
+                    .with_context(|| format!("Failed to read artifact {}", relative))?;
+                total_bytes += data.len();
+                if total_bytes > MAX_ARTIFACT_BYTES {
+                    anyhow::bail!("Artifacts of sandbox {} exceed {} bytes", sandbox_id, MAX_ARTIFACT_BYTES);
+                }
+                collected.push(Artifact { path: relative, data });
+            }
+        }
+
+        Ok(collected)
+    }
+
+    async fn open_terminal(&self, sandbox_id: &str, _shell: &str, _cols: u16, _rows: u16) -> Result<TerminalSession> {
+        anyhow::bail!("nsjail backend does not support interactive terminals (sandbox {})", sandbox_id)
+    }
+
+    async fn resize_terminal(&self, _session_id: &str, _cols: u16, _rows: u16) -> Result<()> {
+        Ok(())
+    }
+}
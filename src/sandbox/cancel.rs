@@ -0,0 +1,87 @@
+//! Cancellation of executions in flight
+//!
+//! An execution holds the manager's write lock until it finishes, so a cancel request
+//! cannot go through the manager. It signals the execution through this registry
+//! instead, and the task running the execution kills it on the backend.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Executions in flight by sandbox id, shared with the cancel route
+#[derive(Debug, Clone, Default)]
+pub struct Cancellations {
+    executions: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+}
+
+impl Cancellations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an execution of the sandbox; it stays cancelable until the returned guard drops
+    pub fn start(&self, sandbox_id: &str) -> Execution {
+        let signal = Arc::new(Notify::new());
+        self.executions.lock().unwrap().insert(sandbox_id.to_string(), signal.clone());
+        Execution {
+            sandbox_id: sandbox_id.to_string(),
+            signal,
+            registry: self.clone(),
+        }
+    }
+
+    /// Ask the sandbox's execution to stop; false when none is in flight
+    pub fn cancel(&self, sandbox_id: &str) -> bool {
+        match self.executions.lock().unwrap().get(sandbox_id) {
+            Some(signal) => {
+                // Stores a permit, so a cancel is not lost if the execution is not waiting yet
+                signal.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Registration of one execution in [`Cancellations`]
+pub struct Execution {
+    sandbox_id: String,
+    signal: Arc<Notify>,
+    registry: Cancellations,
+}
+
+impl Execution {
+    /// Resolves once the execution is canceled
+    pub async fn canceled(&self) {
+        self.signal.notified().await
+    }
+}
+
+impl Drop for Execution {
+    fn drop(&mut self) {
+        let mut executions = self.registry.executions.lock().unwrap();
+        // A newer execution of the same sandbox may have replaced this one
+        if executions.get(&self.sandbox_id).is_some_and(|signal| Arc::ptr_eq(signal, &self.signal)) {
+            executions.remove(&self.sandbox_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let cancellations = Cancellations::new();
+        assert!(!cancellations.cancel("sandbox"));
+
+        let execution = cancellations.start("sandbox");
+        assert!(cancellations.cancel("sandbox"));
+        // The cancel came before anyone waited for it
+        execution.canceled().await;
+
+        drop(execution);
+        assert!(!cancellations.cancel("sandbox"));
+    }
+}
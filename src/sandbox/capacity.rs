@@ -0,0 +1,94 @@
+/// Host capacity the sandbox manager may commit to sandboxes
+#[derive(Debug, Clone, Default)]
+pub struct CapacityLimits {
+    /// Maximum number of sandboxes alive at once
+    pub max_sandboxes: Option<usize>,
+    /// Maximum sum of the sandboxes' memory limits
+    pub max_total_memory_mb: Option<u64>,
+    /// GPUs of the local Docker daemon that sandboxes may claim
+    pub gpus: u32,
+}
+
+impl CapacityLimits {
+    /// Check whether a sandbox with `memory_mb` fits next to `sandboxes` existing ones committing `committed_mb`
+    pub fn check(&self, sandboxes: usize, committed_mb: u64, memory_mb: u64) -> Result<(), CapacityExceeded> {
+        if let Some(max_sandboxes) = self.max_sandboxes {
+            if sandboxes >= max_sandboxes {
+                return Err(CapacityExceeded {
+                    resource: "sandboxes",
+                    requested: 1,
+                    in_use: sandboxes as u64,
+                    limit: max_sandboxes as u64,
+                });
+            }
+        }
+        if let Some(max_memory_mb) = self.max_total_memory_mb {
+            if committed_mb + memory_mb > max_memory_mb {
+                return Err(CapacityExceeded {
+                    resource: "memory_mb",
+                    requested: memory_mb,
+                    in_use: committed_mb,
+                    limit: max_memory_mb,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether `requested` GPUs are free next to the `claimed` ones
+    pub fn check_gpus(&self, claimed: u32, requested: u32) -> Result<(), CapacityExceeded> {
+        if claimed + requested > self.gpus {
+            return Err(CapacityExceeded {
+                resource: "gpus",
+                requested: requested as u64,
+                in_use: claimed as u64,
+                limit: self.gpus as u64,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Creating a sandbox would exceed the host capacity
+#[derive(Debug, Clone)]
+pub struct CapacityExceeded {
+    /// Exhausted resource: "sandboxes", "memory_mb", "gpus" or "host_sandboxes"
+    pub resource: &'static str,
+    pub requested: u64,
+    pub in_use: u64,
+    pub limit: u64,
+}
+
+impl std::fmt::Display for CapacityExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Insufficient capacity: {} requested with {} of {} {} in use",
+            self.requested, self.in_use, self.limit, self.resource
+        )
+    }
+}
+
+impl std::error::Error for CapacityExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_check() {
+        let limits = CapacityLimits {
+            max_sandboxes: Some(2),
+            max_total_memory_mb: Some(1024),
+            gpus: 2,
+        };
+        assert!(limits.check(1, 512, 512).is_ok());
+        assert_eq!(limits.check(2, 0, 128).unwrap_err().resource, "sandboxes");
+        assert_eq!(limits.check(1, 768, 512).unwrap_err().resource, "memory_mb");
+        assert!(CapacityLimits::default().check(1000, 1 << 20, 1 << 20).is_ok());
+
+        assert!(limits.check_gpus(1, 1).is_ok());
+        assert_eq!(limits.check_gpus(1, 2).unwrap_err().resource, "gpus");
+        assert!(CapacityLimits::default().check_gpus(0, 1).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Line diffs of execution output
+
+use voidrun_types::{LineChange, LineChangeKind, OutputDiff};
+
+/// Largest table of line pairs compared for a minimal diff; past it the differing middle
+/// of the outputs is reported as removed and added whole
+const MAX_COMPARED_PAIRS: usize = 1_000_000;
+
+/// Lines removed from `original` and added in `new`, in output order
+pub fn diff_lines(original: &str, new: &str) -> OutputDiff {
+    if original == new {
+        return OutputDiff { identical: true, changes: Vec::new() };
+    }
+    let a: Vec<&str> = original.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let removed = |i: usize| LineChange { kind: LineChangeKind::Removed, line: prefix + i + 1, text: a_mid[i].to_string() };
+    let added = |j: usize| LineChange { kind: LineChangeKind::Added, line: prefix + j + 1, text: b_mid[j].to_string() };
+    let (n, m) = (a_mid.len(), b_mid.len());
+    let mut changes = Vec::new();
+    if n.saturating_mul(m) > MAX_COMPARED_PAIRS {
+        changes.extend((0..n).map(removed));
+        changes.extend((0..m).map(added));
+        return OutputDiff { identical: false, changes };
+    }
+
+    // Longest common subsequence of the suffixes a_mid[i..] and b_mid[j..]
+    let width = m + 1;
+    let mut lcs = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * width + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a_mid[i] == b_mid[j] {
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+            changes.push(removed(i));
+            i += 1;
+        } else {
+            changes.push(added(j));
+            j += 1;
+        }
+    }
+    // Outputs differing only in a trailing newline have no differing lines
+    OutputDiff { identical: false, changes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(kind: LineChangeKind, line: usize, text: &str) -> LineChange {
+        LineChange { kind, line, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("a\nb\n", "a\nb\n"), OutputDiff { identical: true, changes: vec![] });
+
+        let diff = diff_lines("start\nseed 41\nok\nend\n", "start\nseed 97\nok\nretry\nend\n");
+        assert!(!diff.identical);
+        assert_eq!(diff.changes, vec![
+            change(LineChangeKind::Removed, 2, "seed 41"),
+            change(LineChangeKind::Added, 2, "seed 97"),
+            change(LineChangeKind::Added, 4, "retry"),
+        ]);
+
+        assert_eq!(diff_lines("", "boom").changes, vec![change(LineChangeKind::Added, 1, "boom")]);
+        assert_eq!(diff_lines("x\n", "x").changes, vec![]);
+    }
+}
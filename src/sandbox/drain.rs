@@ -0,0 +1,93 @@
+//! Draining of executions before the service stops
+//!
+//! Once draining starts, new sandboxes and executions are turned away with
+//! [`ShuttingDown`] while those already admitted run on. Shutdown waits for them, up to
+//! its grace period, before stopping every sandbox.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use super::ShuttingDown;
+
+#[derive(Debug, Default)]
+pub struct Drain {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Drain {
+    /// Count a creation or execution in flight until the returned guard drops; refused
+    /// once draining started
+    pub fn admit(&self) -> Result<InFlight<'_>, ShuttingDown> {
+        // Counted before the check, so a drain starting meanwhile waits for it
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self);
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(ShuttingDown);
+        }
+        Ok(in_flight)
+    }
+
+    /// Turn away new work from now on
+    pub fn start(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Work admitted and not finished yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Wait for the work in flight to finish, for at most `grace`; returns how much is left
+    pub async fn wait_idle(&self, grace: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + grace;
+        loop {
+            // Registered before the count is read, so a finish in between is not missed
+            let idle = self.idle.notified();
+            let in_flight = self.in_flight();
+            if in_flight == 0 || tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.in_flight();
+            }
+        }
+    }
+}
+
+/// A creation or execution admitted by [`Drain::admit`]
+pub struct InFlight<'a>(&'a Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_work_in_flight() {
+        let drain = Drain::default();
+        let execution = drain.admit().unwrap();
+
+        drain.start();
+        assert!(drain.admit().is_err());
+        assert_eq!(drain.in_flight(), 1);
+        assert_eq!(drain.wait_idle(Duration::from_millis(10)).await, 1);
+
+        let (left, ()) = tokio::join!(drain.wait_idle(Duration::from_secs(10)), async move {
+            tokio::task::yield_now().await;
+            drop(execution);
+        });
+        assert_eq!(left, 0);
+    }
+}
@@ -0,0 +1,94 @@
+//! Reconciliation of what the backends hold against the sandboxes the manager knows
+//!
+//! Containers and directories outlive their sandbox when the service crashes, when
+//! a cleanup fails or when a creation is interrupted. The collector removes those
+//! no sandbox claims once they are older than a grace period, which keeps it clear
+//! of sandboxes still being created.
+//!
+//! One-shot sandboxes are kept after their execution ends so their result, logs and
+//! artifacts can still be fetched, then removed once the retention period has passed.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// Grace period when the configuration does not set one
+pub const DEFAULT_ORPHAN_GRACE_PERIOD_SECONDS: u64 = 600;
+/// Retention of finished one-shot sandboxes when the configuration does not set one
+pub const DEFAULT_FINISHED_SANDBOX_TTL_SECONDS: u64 = 3600;
+/// How often finished sandboxes are checked against the retention period
+pub const RETENTION_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// A container, directory or the like a backend holds for a sandbox
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxResource {
+    /// Sandbox the resource was created for; unset for leftovers of a previous instance
+    pub sandbox_id: Option<String>,
+    /// Container id or path
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Removal of one orphaned resource
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanAction {
+    pub backend: String,
+    pub resource: SandboxResource,
+    /// Why the removal failed; unset when the resource was removed
+    pub error: Option<String>,
+}
+
+/// Outcome of one collection run
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanReport {
+    pub ran_at: DateTime<Utc>,
+    pub grace_period_seconds: u64,
+    pub actions: Vec<OrphanAction>,
+}
+
+/// Whether no sandbox claims `resource` and it is old enough to collect
+pub fn is_orphan(
+    resource: &SandboxResource,
+    is_known: impl Fn(&str) -> bool,
+    now: DateTime<Utc>,
+    grace_period: Duration,
+) -> bool {
+    let claimed = resource.sandbox_id.as_deref().is_some_and(is_known);
+    !claimed && now - resource.created_at >= grace_period
+}
+
+/// Whether a sandbox that finished at `finished_at` has outlived its retention `ttl`
+pub fn is_expired(finished_at: Option<DateTime<Utc>>, now: DateTime<Utc>, ttl: Duration) -> bool {
+    finished_at.is_some_and(|finished_at| now - finished_at >= ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_orphan() {
+        let now = Utc::now();
+        let grace = Duration::minutes(10);
+        let resource = |sandbox_id: Option<&str>, age_minutes| SandboxResource {
+            sandbox_id: sandbox_id.map(str::to_string),
+            name: "resource".to_string(),
+            created_at: now - Duration::minutes(age_minutes),
+        };
+        let is_known = |id: &str| id == "known";
+
+        assert!(is_orphan(&resource(Some("gone"), 15), is_known, now, grace));
+        assert!(is_orphan(&resource(None, 15), is_known, now, grace));
+        assert!(!is_orphan(&resource(Some("known"), 15), is_known, now, grace));
+        // Possibly a sandbox still being created
+        assert!(!is_orphan(&resource(Some("gone"), 5), is_known, now, grace));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+        let ttl = Duration::hours(1);
+        assert!(is_expired(Some(now - Duration::minutes(61)), now, ttl));
+        assert!(!is_expired(Some(now - Duration::minutes(59)), now, ttl));
+        assert!(!is_expired(None, now, ttl));
+    }
+}
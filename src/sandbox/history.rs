@@ -0,0 +1,55 @@
+//! Requests and results of recent executions, kept for replays
+//!
+//! Every finished execution is recorded under an id of its own with the full request it
+//! ran: code, files, env vars and limits. A replay runs that request again in a fresh
+//! one-shot sandbox, so flaky failures can be reproduced and compared against the
+//! original. Records live in memory, the most recent [`HISTORY_LIMIT`], and are lost
+//! when the service restarts.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::{SandboxRequest, SandboxResponse};
+
+/// Executions kept; the oldest record goes when a new one would exceed it
+pub const HISTORY_LIMIT: usize = 200;
+
+/// One finished execution
+#[derive(Debug, Clone)]
+pub struct ExecutionRecord {
+    pub id: String,
+    /// The request as the sandbox ran it, with the files uploaded until then
+    pub request: SandboxRequest,
+    pub response: SandboxResponse,
+}
+
+#[derive(Debug, Default)]
+pub struct ExecutionHistory {
+    records: Mutex<VecDeque<ExecutionRecord>>,
+}
+
+impl ExecutionHistory {
+    /// Record an execution, returning the id it is kept under
+    pub fn record(&self, request: &SandboxRequest, response: &SandboxResponse) -> String {
+        let id = Uuid::new_v4().to_string();
+        let mut request = request.clone();
+        // Replays are metered to whoever asks for them, and stream to nobody
+        request.api_key = None;
+        request.output = None;
+        let mut records = self.records.lock().unwrap();
+        if records.len() == HISTORY_LIMIT {
+            records.pop_front();
+        }
+        records.push_back(ExecutionRecord {
+            id: id.clone(),
+            request,
+            response: SandboxResponse { execution_id: Some(id.clone()), ..response.clone() },
+        });
+        id
+    }
+
+    pub fn get(&self, execution_id: &str) -> Option<ExecutionRecord> {
+        self.records.lock().unwrap().iter().find(|record| record.id == execution_id).cloned()
+    }
+}
@@ -0,0 +1,130 @@
+//! Docker daemons sandboxes are spread over, `[[sandbox.docker_hosts]]` in the configuration
+//!
+//! With a pool configured, the sandbox manager places each Docker sandbox on the host
+//! with the least memory committed to sandboxes and remembers which host holds it.
+//! This adds capacity without cluster mode: one service instance drives every host.
+
+use serde::{Deserialize, Serialize};
+
+/// Address published ports of the local daemon are bound to and reached at
+pub const LOCAL_ADDRESS: &str = "127.0.0.1";
+
+/// A Docker daemon of the pool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DockerHostConfig {
+    /// Name sandboxes report as their host
+    pub name: String,
+    /// `unix:///var/run/docker.sock`, or `tcp://10.0.0.5:2375` for a remote daemon
+    pub url: String,
+    /// Address of the host that published sandbox ports are bound to and proxied to;
+    /// defaults to the URL's host, or 127.0.0.1 for a Unix socket
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Sandboxes the host takes at most; unset leaves only the service-wide limits
+    #[serde(default)]
+    pub max_sandboxes: Option<usize>,
+    /// GPUs sandboxes on the host may claim
+    #[serde(default)]
+    pub gpus: u32,
+}
+
+impl DockerHostConfig {
+    pub fn address(&self) -> String {
+        self.address.clone().unwrap_or_else(|| daemon_address(&self.url))
+    }
+}
+
+/// Host of the Docker daemon at `url`, or 127.0.0.1 for a Unix socket
+pub fn daemon_address(url: &str) -> String {
+    if url.starts_with("unix://") {
+        return LOCAL_ADDRESS.to_string();
+    }
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or(authority);
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host.to_string(),
+        _ => authority.to_string(),
+    }
+}
+
+/// What a host of the pool runs right now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostLoad {
+    pub name: String,
+    pub sandboxes: usize,
+    /// Sum of the memory limits of its sandboxes
+    pub memory_mb: u64,
+    pub max_sandboxes: Option<usize>,
+    /// GPUs claimed by its sandboxes
+    #[serde(default)]
+    pub gpus: u32,
+    /// GPUs the host has
+    #[serde(default)]
+    pub max_gpus: u32,
+    /// The daemon answered
+    pub available: bool,
+}
+
+impl HostLoad {
+    fn has_room(&self, gpus: u32) -> bool {
+        self.available
+            && self.max_sandboxes.is_none_or(|max| self.sandboxes < max)
+            && self.gpus + gpus <= self.max_gpus
+    }
+}
+
+/// Index of the host a new sandbox claiming `gpus` goes to: of the available hosts with
+/// room, the one with the least memory committed, then the fewest sandboxes, then the
+/// first configured
+pub fn least_loaded(loads: &[HostLoad], gpus: u32) -> Option<usize> {
+    loads.iter()
+        .enumerate()
+        .filter(|(_, load)| load.has_room(gpus))
+        .min_by_key(|(_, load)| (load.memory_mb, load.sandboxes))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, sandboxes: usize, memory_mb: u64) -> HostLoad {
+        HostLoad { name: name.to_string(), sandboxes, memory_mb, max_sandboxes: None, gpus: 0, max_gpus: 0, available: true }
+    }
+
+    #[test]
+    fn test_least_loaded() {
+        let mut loads = vec![load("a", 2, 1024), load("b", 3, 512), load("c", 1, 512)];
+        assert_eq!(least_loaded(&loads, 0), Some(2));
+        assert_eq!(least_loaded(&loads, 1), None);
+        loads[0].max_gpus = 2;
+        loads[1].max_gpus = 1;
+        loads[1].gpus = 1;
+        assert_eq!(least_loaded(&loads, 1), Some(0));
+        assert_eq!(least_loaded(&loads, 3), None);
+
+        loads[2].max_sandboxes = Some(1);
+        assert_eq!(least_loaded(&loads, 0), Some(1));
+        loads[1].available = false;
+        assert_eq!(least_loaded(&loads, 0), Some(0));
+        loads[0].available = false;
+        assert_eq!(least_loaded(&loads, 0), None);
+    }
+
+    #[test]
+    fn test_host_address() {
+        let mut host = DockerHostConfig {
+            name: "a".to_string(),
+            url: "tcp://10.0.0.5:2375".to_string(),
+            address: None,
+            max_sandboxes: None,
+            gpus: 0,
+        };
+        assert_eq!(host.address(), "10.0.0.5");
+        host.url = "unix:///var/run/docker.sock".to_string();
+        assert_eq!(host.address(), LOCAL_ADDRESS);
+        host.address = Some("192.168.1.2".to_string());
+        assert_eq!(host.address(), "192.168.1.2");
+        assert_eq!(daemon_address("tcp://docker.internal:2376"), "docker.internal");
+    }
+}
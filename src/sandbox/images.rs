@@ -0,0 +1,126 @@
+//! Custom images of Docker sandboxes, `sandbox.allowed_images` and
+//! `[[sandbox.registry_credentials]]` in the configuration
+//!
+//! A request's `image` replaces the runtime's image, e.g. one with the caller's system
+//! packages preinstalled, as long as the operator allows it; the runtime still decides
+//! how the code is written and run, so the image must provide its tools. Credentials
+//! are matched to images by registry and also used to pull the runtime images.
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::error::InvalidInput;
+
+/// Registry of images naming none, such as `node:20-alpine`
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+/// Address Docker Hub credentials are sent for
+const DOCKER_HUB_ADDRESS: &str = "https://index.docker.io/v1/";
+
+/// Longest image reference Docker accepts
+const MAX_IMAGE_LEN: usize = 255;
+
+/// Login to a private registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryCredential {
+    /// Registry host, e.g. `ghcr.io` or `registry.corp:5000`; `docker.io` for Docker Hub
+    pub registry: String,
+    pub username: String,
+    /// Password or access token
+    pub password: String,
+}
+
+impl RegistryCredential {
+    /// Credentials in the form the Docker daemon takes them
+    pub fn docker_credentials(&self) -> bollard::auth::DockerCredentials {
+        let address = match self.registry.as_str() {
+            DEFAULT_REGISTRY => DOCKER_HUB_ADDRESS.to_string(),
+            registry => registry.to_string(),
+        };
+        bollard::auth::DockerCredentials {
+            username: Some(self.username.clone()),
+            password: Some(self.password.clone()),
+            serveraddress: Some(address),
+            ..Default::default()
+        }
+    }
+}
+
+/// Which custom images requests may run, and the logins images are pulled with
+#[derive(Debug, Clone, Default)]
+pub struct ImagePolicy {
+    /// Images, or prefixes ending in `*` such as `ghcr.io/org/*`; empty allows none
+    pub allowed: Vec<String>,
+    pub credentials: Vec<RegistryCredential>,
+}
+
+impl ImagePolicy {
+    /// Reject images that are malformed or not on the allowlist
+    pub fn check(&self, image: &str) -> Result<(), InvalidInput> {
+        let valid = !image.is_empty()
+            && image.len() <= MAX_IMAGE_LEN
+            && !image.starts_with(['-', '/', '.', ':'])
+            && image.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"./:_@-".contains(&byte));
+        if !valid {
+            return Err(InvalidInput(format!("Invalid image: {:?}", image)));
+        }
+        if !self.allowed.iter().any(|pattern| matches(pattern, image)) {
+            return Err(InvalidInput(if self.allowed.is_empty() {
+                "Custom images are not allowed on this instance".to_string()
+            } else {
+                format!("Image {} is not allowed; allowed: {}", image, self.allowed.join(", "))
+            }));
+        }
+        Ok(())
+    }
+
+    /// Login for the registry `image` is pulled from
+    pub fn credential_for(&self, image: &str) -> Option<&RegistryCredential> {
+        let registry = registry(image);
+        self.credentials.iter().find(|credential| credential.registry == registry)
+    }
+}
+
+/// Whether `image` is `pattern`, or starts with it when it ends in `*`
+pub fn matches(pattern: &str, image: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => image.starts_with(prefix),
+        None => image == pattern,
+    }
+}
+
+/// Registry host of an image: its first path component when that looks like a host,
+/// as Docker reads it, else Docker Hub
+pub fn registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => DEFAULT_REGISTRY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_policy() {
+        let policy = ImagePolicy {
+            allowed: vec!["ghcr.io/org/*".to_string(), "python:3.12-slim".to_string()],
+            credentials: vec![RegistryCredential {
+                registry: "ghcr.io".to_string(),
+                username: "bot".to_string(),
+                password: "token".to_string(),
+            }],
+        };
+        assert!(policy.check("ghcr.io/org/custom:tag").is_ok());
+        assert!(policy.check("python:3.12-slim").is_ok());
+        assert!(policy.check("python:3.11-slim").is_err());
+        assert!(policy.check("ghcr.io/other/custom:tag").is_err());
+        assert!(policy.check("ghcr.io/org/custom tag").is_err());
+        assert!(ImagePolicy::default().check("python:3.12-slim").is_err());
+
+        assert_eq!(registry("node:20-alpine"), DEFAULT_REGISTRY);
+        assert_eq!(registry("org/app"), DEFAULT_REGISTRY);
+        assert_eq!(registry("registry.corp:5000/app"), "registry.corp:5000");
+        assert_eq!(policy.credential_for("ghcr.io/org/custom:tag").map(|c| c.username.as_str()), Some("bot"));
+        assert!(policy.credential_for("node:20-alpine").is_none());
+    }
+}
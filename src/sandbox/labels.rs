@@ -0,0 +1,96 @@
+//! Free-form labels of sandboxes and deployments
+//!
+//! Labels are `key=value` pairs callers attach to find their sandboxes again, such as
+//! `team=ml`. They are listed with the sandbox, filtered on with `?label=`, and set on
+//! Docker containers so tooling outside the service sees them too. Keys starting with
+//! `voidrun.` belong to the service's own container labels and are rejected.
+
+use std::collections::HashMap;
+
+use crate::api::error::InvalidInput;
+
+/// Labels one sandbox may carry
+pub const MAX_LABELS: usize = 64;
+const MAX_KEY_LEN: usize = 128;
+const MAX_VALUE_LEN: usize = 256;
+const RESERVED_PREFIX: &str = "voidrun.";
+
+/// Reject labels that could not be stored as container labels or matched by a selector
+pub fn validate(labels: &HashMap<String, String>) -> Result<(), InvalidInput> {
+    if labels.len() > MAX_LABELS {
+        return Err(InvalidInput(format!("At most {} labels are allowed", MAX_LABELS)));
+    }
+    for (key, value) in labels {
+        let valid_key = !key.is_empty()
+            && key.len() <= MAX_KEY_LEN
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+        if !valid_key {
+            return Err(InvalidInput(format!(
+                "Invalid label key {:?}: use up to {} letters, digits, '-', '_', '.' or '/'", key, MAX_KEY_LEN
+            )));
+        }
+        if key.starts_with(RESERVED_PREFIX) {
+            return Err(InvalidInput(format!("Label key {:?} uses the reserved prefix {:?}", key, RESERVED_PREFIX)));
+        }
+        if value.len() > MAX_VALUE_LEN || value.chars().any(|c| c.is_control() || c == ',') {
+            return Err(InvalidInput(format!(
+                "Invalid value of label {:?}: use up to {} characters, without commas", key, MAX_VALUE_LEN
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Filter on labels, `?label=team=ml,gpu`: comma-separated terms that must all match,
+/// `key=value` for a value and a bare `key` for any value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSelector(Vec<(String, Option<String>)>);
+
+impl LabelSelector {
+    pub fn parse(selector: &str) -> Result<Self, InvalidInput> {
+        let terms = selector.split(',')
+            .map(|term| match term.split_once('=') {
+                Some((key, value)) => (key.trim().to_string(), Some(value.trim().to_string())),
+                None => (term.trim().to_string(), None),
+            })
+            .collect::<Vec<_>>();
+        if terms.iter().any(|(key, _)| key.is_empty()) {
+            return Err(InvalidInput(format!("Invalid label selector {:?}", selector)));
+        }
+        Ok(Self(terms))
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.0.iter().all(|(key, value)| match value {
+            Some(value) => labels.get(key) == Some(value),
+            None => labels.contains_key(key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let labels = HashMap::from([
+            ("team".to_string(), "ml".to_string()),
+            ("gpu".to_string(), String::new()),
+        ]);
+        assert!(validate(&labels).is_ok());
+        assert!(LabelSelector::parse("team=ml").unwrap().matches(&labels));
+        assert!(LabelSelector::parse("team=ml, gpu").unwrap().matches(&labels));
+        assert!(!LabelSelector::parse("team=web").unwrap().matches(&labels));
+        assert!(!LabelSelector::parse("team=ml,env").unwrap().matches(&labels));
+        assert!(LabelSelector::parse("=ml").is_err());
+        assert!(LabelSelector::parse("team,,gpu").is_err());
+
+        let invalid = |key: &str, value: &str| validate(&HashMap::from([(key.to_string(), value.to_string())])).is_err();
+        assert!(invalid("voidrun.port", "80"));
+        assert!(invalid("team name", "ml"));
+        assert!(invalid("", "ml"));
+        assert!(invalid("team", "ml,web"));
+        assert!(!invalid("example.com/owner", "Ada Lovelace"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Filtering, ordering and cursor pagination of `GET /sandbox`
+//!
+//! Sandboxes are listed by creation time, newest first unless the query asks for
+//! `order=asc`, ties broken by ID, so pages stay stable while sandboxes come and go.
+//! The cursor encodes the sort key of a page's last entry; FaaS deployment lists use
+//! the same cursors. One-shot sandboxes whose execution ended, kept for their retention
+//! period, are only listed with `include=finished` or a `status` filter.
+
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use chrono::{DateTime, SecondsFormat, Utc};
+use voidrun_types::sandbox::{SandboxListQuery, SortOrder};
+
+use super::labels::LabelSelector;
+use super::Sandbox;
+use crate::api::error::InvalidInput;
+use crate::runtime;
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+/// One page of sandboxes and the number matching the filters over all pages
+#[derive(Debug, Clone)]
+pub struct SandboxPage {
+    pub sandboxes: Vec<Sandbox>,
+    pub total: usize,
+    pub next_cursor: Option<String>,
+}
+
+/// The page of `sandboxes` the query asks for
+pub fn page(mut sandboxes: Vec<Sandbox>, query: &SandboxListQuery) -> Result<SandboxPage, InvalidInput> {
+    let limit = validate_limit(query.limit)?;
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+    let labels = query.label.as_deref().map(LabelSelector::parse).transpose()?;
+    let persistent = match query.mode.as_deref() {
+        None => None,
+        Some("oneshot") => Some(false),
+        Some("persistent") => Some(true),
+        Some(mode) => return Err(InvalidInput(format!("Invalid mode {:?}: use oneshot or persistent", mode))),
+    };
+    let include_finished = match query.include.as_deref() {
+        None => query.status.is_some(),
+        Some("finished") => true,
+        Some(include) => return Err(InvalidInput(format!("Invalid include {:?}: use finished", include))),
+    };
+    let order = query.order.unwrap_or_default();
+
+    sandboxes.retain(|sandbox| {
+        query.status.as_deref().is_none_or(|status| format!("{:?}", sandbox.status).eq_ignore_ascii_case(status))
+            && query.runtime.as_deref().is_none_or(|name| runtime::matches(&sandbox.request.runtime, name))
+            && persistent.is_none_or(|persistent| sandbox.is_persistent() == persistent)
+            && labels.as_ref().is_none_or(|labels| labels.matches(&sandbox.request.labels))
+            && (include_finished || sandbox.finished_at.is_none())
+    });
+    let total = sandboxes.len();
+
+    sandboxes.retain(|sandbox| after.as_ref().is_none_or(|(time, id)| {
+        let key = (sandbox.created_at, &sandbox.id);
+        match order {
+            SortOrder::Desc => key < (*time, id),
+            SortOrder::Asc => key > (*time, id),
+        }
+    }));
+    sandboxes.sort_by(|a, b| {
+        let ordering = (a.created_at, &a.id).cmp(&(b.created_at, &b.id));
+        match order {
+            SortOrder::Desc => ordering.reverse(),
+            SortOrder::Asc => ordering,
+        }
+    });
+
+    let next_cursor = (sandboxes.len() > limit)
+        .then(|| encode_cursor(sandboxes[limit - 1].created_at, &sandboxes[limit - 1].id));
+    sandboxes.truncate(limit);
+    Ok(SandboxPage { sandboxes, total, next_cursor })
+}
+
+/// Page size of a list query
+pub fn validate_limit(limit: Option<usize>) -> Result<usize, InvalidInput> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(InvalidInput(format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+    Ok(limit)
+}
+
+/// Cursor continuing a list after the entry created at `created_at` with ID `id`
+pub fn encode_cursor(created_at: DateTime<Utc>, id: &str) -> String {
+    let created_at = created_at.to_rfc3339_opts(SecondsFormat::Nanos, true);
+    BASE64_URL_SAFE_NO_PAD.encode(format!("{} {}", created_at, id))
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<(DateTime<Utc>, String), InvalidInput> {
+    let invalid = || InvalidInput(format!("Invalid cursor {:?}", cursor));
+    let decoded = BASE64_URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+    let (created_at, id) = decoded.split_once(' ').ok_or_else(invalid)?;
+    let created_at = DateTime::parse_from_rfc3339(created_at).map_err(|_| invalid())?;
+    Ok((created_at.with_timezone(&Utc), id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::backend::SandboxBackendType;
+    use crate::sandbox::{SandboxMode, SandboxRequest, SandboxStatus};
+    use chrono::Duration;
+
+    fn sandbox(id: &str, runtime: &str, minutes_ago: i64, status: SandboxStatus, mode: SandboxMode) -> Sandbox {
+        let request: SandboxRequest = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "runtime": runtime,
+            "code": "",
+            "timeout_ms": 1000,
+            "memory_limit_mb": 128,
+            "env_vars": {},
+        })).unwrap();
+        let mut sandbox = Sandbox::new(SandboxRequest { mode: Some(mode), ..request }, SandboxBackendType::Docker);
+        sandbox.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        sandbox.status = status;
+        sandbox
+    }
+
+    #[test]
+    fn test_sandbox_pages() {
+        let sandboxes = vec![
+            sandbox("a", "bun", 30, SandboxStatus::Running, SandboxMode::Persistent),
+            sandbox("b", "node@20", 20, SandboxStatus::Failed, SandboxMode::OneShot),
+            sandbox("c", "node", 10, SandboxStatus::Running, SandboxMode::OneShot),
+            sandbox("d", "bun@1.1", 5, SandboxStatus::Paused, SandboxMode::Persistent),
+        ];
+        let ids = |page: &SandboxPage| page.sandboxes.iter().map(|s| s.id.clone()).collect::<Vec<_>>();
+
+        let query = SandboxListQuery { limit: Some(3), ..Default::default() };
+        let first = page(sandboxes.clone(), &query).unwrap();
+        assert_eq!(ids(&first), ["d", "c", "b"]);
+        assert_eq!(first.total, 4);
+        let query = SandboxListQuery { limit: Some(3), cursor: first.next_cursor, ..Default::default() };
+        let second = page(sandboxes.clone(), &query).unwrap();
+        assert_eq!(ids(&second), ["a"]);
+        assert_eq!(second.total, 4);
+        assert!(second.next_cursor.is_none());
+
+        let query = SandboxListQuery { order: Some(SortOrder::Asc), limit: Some(2), ..Default::default() };
+        let first = page(sandboxes.clone(), &query).unwrap();
+        assert_eq!(ids(&first), ["a", "b"]);
+        let query = SandboxListQuery { order: Some(SortOrder::Asc), cursor: first.next_cursor, ..Default::default() };
+        assert_eq!(ids(&page(sandboxes.clone(), &query).unwrap()), ["c", "d"]);
+
+        let query = SandboxListQuery { runtime: Some("node".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(sandboxes.clone(), &query).unwrap()), ["c", "b"]);
+        let query = SandboxListQuery { status: Some("running".to_string()), limit: Some(1), ..Default::default() };
+        let running = page(sandboxes.clone(), &query).unwrap();
+        assert_eq!((ids(&running), running.total), (vec!["c".to_string()], 2));
+        let query = SandboxListQuery { mode: Some("persistent".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(sandboxes.clone(), &query).unwrap()), ["d", "a"]);
+        let mut labeled = sandboxes.clone();
+        labeled[1].request.labels.insert("team".to_string(), "ml".to_string());
+        let query = SandboxListQuery { label: Some("team=ml".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(labeled, &query).unwrap()), ["b"]);
+
+        let mut finished = sandboxes.clone();
+        finished[2].status = SandboxStatus::Completed;
+        finished[2].finished_at = Some(Utc::now());
+        assert_eq!(ids(&page(finished.clone(), &SandboxListQuery::default()).unwrap()), ["d", "b", "a"]);
+        let query = SandboxListQuery { include: Some("finished".to_string()), ..Default::default() };
+        assert_eq!(page(finished.clone(), &query).unwrap().total, 4);
+        let query = SandboxListQuery { status: Some("completed".to_string()), ..Default::default() };
+        assert_eq!(ids(&page(finished, &query).unwrap()), ["c"]);
+
+        let query = SandboxListQuery { include: Some("deleted".to_string()), ..Default::default() };
+        assert!(page(sandboxes.clone(), &query).is_err());
+        let query = SandboxListQuery { mode: Some("forever".to_string()), ..Default::default() };
+        assert!(page(sandboxes.clone(), &query).is_err());
+        let query = SandboxListQuery { cursor: Some("not a cursor".to_string()), ..Default::default() };
+        assert!(page(sandboxes.clone(), &query).is_err());
+        let query = SandboxListQuery { limit: Some(0), ..Default::default() };
+        assert!(page(sandboxes, &query).is_err());
+    }
+}
@@ -1,92 +1,1260 @@
 use anyhow::Result;
+use dashmap::DashMap;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use tokio::sync::OwnedMutexGuard;
 
-use super::{Sandbox, SandboxRequest, SandboxResponse, SandboxStatus, SandboxFile};
-use super::backend::{create_backend, SandboxBackend, SandboxBackendType};
+use super::{Artifact, Cancellations, CapacityExceeded, CapacityLimits, PortAllocator, Sandbox, SandboxMode, SandboxRequest, SandboxResponse, SandboxStatus, SandboxFile, StatusConflict, TerminationReason};
+use super::backend::{
+    self, create_backend, create_docker_host_backend, AddonContainer, BackendUnavailable, LogStream, SandboxBackend, SandboxBackendType,
+    SandboxHealth, SandboxStats,
+};
+use super::drain::Drain;
+use super::history::{ExecutionHistory, ExecutionRecord};
+use super::result_cache::ResultCache;
+use super::sessions;
+use super::test_runner;
+use super::hosts::{self, DockerHostConfig, HostLoad};
+use super::labels;
+use super::images::ImagePolicy;
+use super::mounts::{self, AllowedMount};
+use crate::api::error::{InvalidInput, NotFound};
 use crate::api::SandboxInfo;
+use voidrun_types::{BackendInfo, Capabilities, EvalRequest, EvalResult, RuntimeInfo};
+use super::gc::{self, OrphanAction, OrphanReport};
+use crate::events::{EventBus, EventKind};
+use crate::metering::{Meter, PricingConfig};
+use crate::runtime;
+use crate::security::ContainerSecurity;
+use crate::storage::ObjectStore;
 
+type Backends = HashMap<SandboxBackendType, Arc<dyn SandboxBackend>>;
+
+/// A Docker daemon of the pool with its connection
+struct DockerHost {
+    config: DockerHostConfig,
+    backend: Arc<dyn SandboxBackend>,
+}
+
+/// A registered sandbox
+///
+/// The record is only locked briefly to read or update it. Backend calls on the sandbox
+/// hold `operation` instead, so they run one at a time per sandbox while reads, and
+/// operations on other sandboxes, go ahead.
+struct SandboxEntry {
+    sandbox: Mutex<Sandbox>,
+    operation: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SandboxEntry {
+    fn new(sandbox: Sandbox) -> Arc<Self> {
+        Arc::new(Self {
+            sandbox: Mutex::new(sandbox),
+            operation: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Sandbox> {
+        self.sandbox.lock().unwrap()
+    }
+}
+
+/// Sandboxes and the backends they run on
+///
+/// Every method takes `&self`: the manager is shared as an `Arc` without an outer lock,
+/// and no lock of its own is held across a backend call except the operated sandbox's.
 pub struct SandboxManager {
-    sandboxes: HashMap<String, Sandbox>,
-    backend: Box<dyn SandboxBackend>,
+    sandboxes: DashMap<String, Arc<SandboxEntry>>,
+    /// Serializes the capacity check and registration of new sandboxes, not their creation
+    admission: tokio::sync::Mutex<()>,
+    /// Available backends; always holds the default `backend_type`. With a Docker host
+    /// pool, the Docker entry is one of its hosts, standing in for all of them
+    backends: Backends,
     backend_type: SandboxBackendType,
+    /// Docker daemons Docker sandboxes are spread over; empty without a pool
+    hosts: Vec<DockerHost>,
+    /// Pool host each snapshot was taken on, where restoring it must run
+    snapshot_hosts: DashMap<String, String>,
+    /// Network and pool host of each add-on container started, by container name
+    addons: DashMap<String, (String, Option<String>)>,
+    /// GPUs and pool host of each one-shot execution in flight that claimed GPUs, by sandbox id
+    one_shot_gpus: DashMap<String, (u32, Option<String>)>,
+    events: EventBus,
+    ports: PortAllocator,
+    /// Privileges of the containers of Docker backends, including ones registered later
+    container_security: ContainerSecurity,
+    cancellations: Cancellations,
+    /// Creations and executions in flight, and whether new ones are still taken
+    drain: Drain,
+    /// Usage per API key, shared with the proxy and the admin API
+    meter: Meter,
+    limits: RwLock<CapacityLimits>,
+    /// Files collected after each sandbox's most recent execution
+    artifacts: DashMap<String, Vec<Artifact>>,
+    /// Sandboxes whose latest artifacts were uploaded to `storage` instead
+    stored_artifacts: dashmap::DashSet<String>,
+    /// Bucket artifacts and snapshot archives are kept in; unset keeps them local
+    storage: Option<Arc<ObjectStore>>,
+    /// Age below which resources no sandbox claims are left alone
+    orphan_grace_period: chrono::Duration,
+    /// How long finished one-shot sandboxes are kept; unset keeps them until deleted
+    finished_sandbox_ttl: Option<chrono::Duration>,
+    last_orphan_report: Mutex<Option<OrphanReport>>,
+    /// Executions finished since startup, by how they ended
+    terminations: Mutex<HashMap<TerminationReason, u64>>,
+    /// Recent executions with the requests they ran, for replays
+    history: ExecutionHistory,
+    /// Results of one-shot executions returned to identical requests; unset when disabled
+    result_cache: Option<ResultCache>,
+    /// Proxy dependency installs go through, handed to backends with each request
+    http_proxy: Option<String>,
+    /// Host directories requests may mount read-only by name
+    allowed_mounts: Vec<AllowedMount>,
+    /// Custom images requests may run, and the registry logins images are pulled with
+    images: ImagePolicy,
+    /// Runs qualifying one-shot JavaScript without the backend
+    #[cfg(feature = "isolate")]
+    isolate: super::backend::isolate::IsolateBackend,
 }
 
 impl SandboxManager {
-    pub async fn new(backend_type: SandboxBackendType) -> Result<Self> {
-        let backend = create_backend(backend_type.clone())?;
+    /// `docker_hosts` is the pool Docker sandboxes are spread over; empty uses the local daemon
+    pub async fn new(
+        backend_type: SandboxBackendType,
+        ports: PortAllocator,
+        container_security: ContainerSecurity,
+        docker_hosts: &[DockerHostConfig],
+    ) -> Result<Self> {
+        let (backend, hosts) = connect_backend(&backend_type, &ports, &container_security, docker_hosts).await?;
         
         if !backend.is_available().await {
             anyhow::bail!("Selected backend {:?} is not available", backend_type);
         }
 
         Ok(Self {
-            sandboxes: HashMap::new(),
-            backend,
+            sandboxes: DashMap::new(),
+            admission: tokio::sync::Mutex::new(()),
+            backends: HashMap::from([(backend_type.clone(), backend)]),
             backend_type,
+            hosts,
+            snapshot_hosts: DashMap::new(),
+            addons: DashMap::new(),
+            one_shot_gpus: DashMap::new(),
+            events: EventBus::new(),
+            ports,
+            container_security,
+            cancellations: Cancellations::new(),
+            drain: Drain::default(),
+            meter: Meter::new(),
+            limits: RwLock::new(CapacityLimits::default()),
+            artifacts: DashMap::new(),
+            stored_artifacts: dashmap::DashSet::new(),
+            storage: None,
+            orphan_grace_period: chrono::Duration::seconds(gc::DEFAULT_ORPHAN_GRACE_PERIOD_SECONDS as i64),
+            finished_sandbox_ttl: Some(chrono::Duration::seconds(gc::DEFAULT_FINISHED_SANDBOX_TTL_SECONDS as i64)),
+            last_orphan_report: Mutex::new(None),
+            terminations: Mutex::new(HashMap::new()),
+            history: ExecutionHistory::default(),
+            result_cache: None,
+            http_proxy: None,
+            allowed_mounts: Vec::new(),
+            images: ImagePolicy::default(),
+            #[cfg(feature = "isolate")]
+            isolate: super::backend::isolate::IsolateBackend::new(),
         })
     }
 
-    pub async fn create_sandbox(&mut self, request: SandboxRequest) -> Result<()> {
-        let sandbox = Sandbox::new(request.clone(), self.backend_type.clone());
-        
-        self.backend.create_sandbox(&request).await?;
-        
-        self.sandboxes.insert(request.id.clone(), sandbox);
+    /// Register further backends that requests can name or fall back to
+    ///
+    /// Backends that cannot be created or fail their availability check are skipped.
+    pub async fn with_backends(mut self, backend_types: &[SandboxBackendType], docker_hosts: &[DockerHostConfig]) -> Self {
+        for backend_type in backend_types {
+            if self.backends.contains_key(backend_type) {
+                continue;
+            }
+            match connect_backend(backend_type, &self.ports, &self.container_security, docker_hosts).await {
+                Ok((backend, hosts)) if backend.is_available().await => {
+                    tracing::info!("Registered additional backend {:?}", backend_type);
+                    self.backends.insert(backend_type.clone(), backend);
+                    if !hosts.is_empty() {
+                        self.hosts = hosts;
+                    }
+                }
+                Ok(_) => tracing::warn!("Backend {:?} is not available, skipping it", backend_type),
+                Err(e) => tracing::warn!("Failed to create backend {:?}, skipping it: {}", backend_type, e),
+            }
+        }
+        self
+    }
+
+    /// Re-register sandboxes a previous instance of the service left on the backends
+    ///
+    /// Returns how many sandboxes were reattached.
+    pub async fn reattach_sandboxes(&self) -> usize {
+        let mut found_by_backend = Vec::new();
+        for (backend_type, host, backend) in self.connections() {
+            match backend.recover_sandboxes().await {
+                Ok(recovered) => found_by_backend.push((backend_type, host.map(str::to_string), recovered)),
+                Err(e) => tracing::warn!("Failed to look for sandboxes on backend {:?}: {}", backend_type, e),
+            }
+        }
+
+        let mut reattached = 0;
+        for (backend_type, host, recovered) in found_by_backend {
+            for found in recovered {
+                if self.sandboxes.contains_key(&found.request.id) {
+                    continue;
+                }
+                let mut sandbox = Sandbox::new(found.request, backend_type.clone());
+                sandbox.docker_host = host.clone();
+                sandbox.created_at = found.created_at;
+                sandbox.container_id = found.container_id;
+                sandbox.dev_server_port = found.host_port;
+                if matches!(found.status, SandboxStatus::Paused) {
+                    sandbox.resume_status = Some(SandboxStatus::Running);
+                }
+                sandbox.status = found.status;
+                tracing::info!("Reattached sandbox {} ({:?}) on backend {:?}", sandbox.id, sandbox.status, backend_type);
+                // Persisted requests carry no API key; their usage from now on is anonymous
+                self.meter.sandbox_started(&sandbox.id, None, sandbox.request.memory_limit_mb);
+                self.sandboxes.insert(sandbox.id.clone(), SandboxEntry::new(sandbox));
+                reattached += 1;
+            }
+        }
+        reattached
+    }
+
+    /// Backend for a request: the one it names, else the most preferred registered one
+    /// offering the features it needs, else the default
+    fn select_backend(&self, request: &SandboxRequest) -> Result<SandboxBackendType> {
+        if let Some(requested) = &request.backend {
+            if !self.backends.contains_key(requested) {
+                return Err(BackendUnavailable(format!(
+                    "Backend {} is not enabled on this instance",
+                    requested.name()
+                )).into());
+            }
+            return Ok(requested.clone());
+        }
+
+        Ok(backend::preference_order(request, &self.backend_type)
+            .into_iter()
+            .find(|backend_type| {
+                self.backends.get(backend_type)
+                    .is_some_and(|backend| backend::supports(&backend.features(), request))
+            })
+            .unwrap_or_else(|| self.backend_type.clone()))
+    }
+
+    /// Reject GPU requests this instance cannot serve at all, before any capacity is checked
+    fn check_gpu_request(&self, backend_type: &SandboxBackendType, request: &SandboxRequest) -> Result<()> {
+        if request.gpus() == 0 {
+            return Ok(());
+        }
+        if *backend_type != SandboxBackendType::Docker {
+            return Err(BackendUnavailable(format!(
+                "GPUs are only available on the Docker backend, not {}", backend_type.name()
+            )).into());
+        }
+        let inventory: u32 = if self.hosts.is_empty() {
+            self.limits.read().unwrap().gpus
+        } else {
+            self.hosts.iter().map(|host| host.config.gpus).sum()
+        };
+        if inventory == 0 {
+            return Err(BackendUnavailable("No GPUs are configured on this instance".to_string()).into());
+        }
+        if request.gpus() > inventory {
+            return Err(InvalidInput(format!(
+                "{} GPUs requested, but this instance has {} at most", request.gpus(), inventory
+            )).into());
+        }
+        Ok(())
+    }
+
+    /// Resolve the data sets a request mounts into host directories, which only the
+    /// Docker backend binds
+    fn resolve_mounts(&self, backend_type: &SandboxBackendType, request: &mut SandboxRequest) -> Result<()> {
+        let requested = request.mounts.as_deref().unwrap_or_default();
+        if requested.is_empty() {
+            request.bind_mounts.clear();
+            return Ok(());
+        }
+        if *backend_type != SandboxBackendType::Docker {
+            return Err(BackendUnavailable(format!(
+                "Mounts are only available on the Docker backend, not {}", backend_type.name()
+            )).into());
+        }
+        request.bind_mounts = mounts::resolve(&self.allowed_mounts, requested)?;
+        Ok(())
+    }
+
+    /// Check a request's custom image, which only the Docker backend runs, and pick the
+    /// login its image is pulled with
+    fn resolve_image(&self, backend_type: &SandboxBackendType, request: &mut SandboxRequest) -> Result<()> {
+        let is_docker = *backend_type == SandboxBackendType::Docker;
+        let image = match &request.image {
+            Some(_) if !is_docker => {
+                return Err(BackendUnavailable(format!(
+                    "Custom images are only available on the Docker backend, not {}", backend_type.name()
+                )).into());
+            }
+            Some(image) => {
+                self.images.check(image)?;
+                image.clone()
+            }
+            None if is_docker => runtime::image(&request.runtime)?,
+            None => return Ok(()),
+        };
+        request.registry_credential = self.images.credential_for(&image).cloned();
+        Ok(())
+    }
+
+    /// Reject new sandboxes beyond `limits`
+    pub fn with_limits(self, limits: CapacityLimits) -> Self {
+        self.set_limits(limits);
+        self
+    }
+
+    /// Only collect orphaned resources older than `seconds`
+    pub fn with_orphan_grace_period(mut self, seconds: u64) -> Self {
+        self.orphan_grace_period = chrono::Duration::seconds(seconds as i64);
+        self
+    }
+
+    /// Remove one-shot sandboxes `seconds` after their execution ended; 0 keeps them until deleted
+    pub fn with_finished_sandbox_ttl(mut self, seconds: u64) -> Self {
+        self.finished_sandbox_ttl = (seconds > 0).then(|| chrono::Duration::seconds(seconds as i64));
+        self
+    }
+
+    /// Route dependency installs through `proxy`, e.g. a corporate proxy of an air-gapped network
+    pub fn with_http_proxy(mut self, proxy: Option<String>) -> Self {
+        self.http_proxy = proxy;
+        self
+    }
+
+    /// Let requests mount the `allowed` host directories read-only
+    pub fn with_allowed_mounts(mut self, allowed: Vec<AllowedMount>) -> Self {
+        self.allowed_mounts = allowed;
+        self
+    }
+
+    /// Let requests run the custom images `policy` allows, and pull images with its logins
+    pub fn with_images(mut self, policy: ImagePolicy) -> Self {
+        self.images = policy;
+        self
+    }
+
+    /// Attach cost estimates at `pricing` to executions and usage reports
+    pub fn with_pricing(self, pricing: PricingConfig) -> Self {
+        self.meter.set_pricing(pricing);
+        self
+    }
+
+    /// Return results of one-shot executions to identical requests for `seconds`; 0 disables it
+    pub fn with_result_cache(mut self, seconds: u64) -> Self {
+        self.result_cache = (seconds > 0).then(|| ResultCache::new(std::time::Duration::from_secs(seconds)));
+        self
+    }
+
+    /// Keep artifacts and nsjail snapshot archives in `store` instead of on the host
+    pub fn with_storage(mut self, store: Option<Arc<ObjectStore>>) -> Self {
+        self.storage = store;
+        self
+    }
+
+    /// Object store of the service, shared with deployment exports and backups
+    pub fn storage(&self) -> Option<Arc<ObjectStore>> {
+        self.storage.clone()
+    }
+
+    /// Every backend connection once: each registered backend, or for Docker with a pool,
+    /// each of its hosts
+    fn connections(&self) -> Vec<(SandboxBackendType, Option<&str>, &dyn SandboxBackend)> {
+        let pooled = !self.hosts.is_empty();
+        let mut connections: Vec<_> = self.backends.iter()
+            .filter(|(backend_type, _)| !(pooled && **backend_type == SandboxBackendType::Docker))
+            .map(|(backend_type, backend)| (backend_type.clone(), None, backend.as_ref()))
+            .collect();
+        connections.extend(self.hosts.iter().map(|host| {
+            (SandboxBackendType::Docker, Some(host.config.name.as_str()), host.backend.as_ref())
+        }));
+        connections
+    }
+
+    /// Load of each Docker host of the pool, in configuration order
+    pub async fn host_loads(&self) -> Vec<HostLoad> {
+        let mut loads = Vec::new();
+        for host in &self.hosts {
+            let (sandboxes, memory_mb, gpus) = self.sandboxes.iter().fold((0, 0, 0), |(count, memory_mb, gpus), entry| {
+                let sandbox = entry.lock();
+                if sandbox.docker_host.as_deref() == Some(host.config.name.as_str()) {
+                    (count + 1, memory_mb + sandbox.request.memory_limit_mb, gpus + sandbox.request.gpus())
+                } else {
+                    (count, memory_mb, gpus)
+                }
+            });
+            let gpus = gpus + self.one_shot_gpus.iter()
+                .filter(|claim| claim.1.as_deref() == Some(host.config.name.as_str()))
+                .map(|claim| claim.0)
+                .sum::<u32>();
+            loads.push(HostLoad {
+                name: host.config.name.clone(),
+                sandboxes,
+                memory_mb,
+                max_sandboxes: host.config.max_sandboxes,
+                gpus,
+                max_gpus: host.config.gpus,
+                available: host.backend.is_available().await,
+            });
+        }
+        loads
+    }
+
+    /// Pool host for a Docker sandbox: the one holding the snapshot it restores, or running
+    /// the other members of its network, else the least loaded one with room
+    async fn place(&self, request: &SandboxRequest) -> Result<String> {
+        let snapshot_host = request.from_snapshot.as_ref()
+            .and_then(|snapshot| self.snapshot_hosts.get(snapshot).map(|host| host.clone()));
+        if let Some(host) = snapshot_host {
+            return Ok(host);
+        }
+        // Members of a private network reach each other only on the same daemon
+        if let Some(host) = request.network.as_ref().and_then(|network| self.network_host(&network.name)) {
+            return Ok(host);
+        }
+
+        let loads = self.host_loads().await;
+        if let Some(index) = hosts::least_loaded(&loads, request.gpus()) {
+            return Ok(loads[index].name.clone());
+        }
+        let available: Vec<&HostLoad> = loads.iter().filter(|load| load.available).collect();
+        if available.is_empty() {
+            return Err(BackendUnavailable("None of the Docker hosts is reachable".to_string()).into());
+        }
+        if request.gpus() > 0 && hosts::least_loaded(&loads, 0).is_some() {
+            return Err(CapacityExceeded {
+                resource: "gpus",
+                requested: request.gpus() as u64,
+                in_use: available.iter().map(|load| load.gpus as u64).sum(),
+                limit: available.iter().map(|load| load.max_gpus as u64).sum(),
+            }.into());
+        }
+        Err(CapacityExceeded {
+            resource: "host_sandboxes",
+            requested: 1,
+            in_use: available.iter().map(|load| load.sandboxes as u64).sum(),
+            limit: available.iter().filter_map(|load| load.max_sandboxes).map(|max| max as u64).sum(),
+        }.into())
+    }
+
+    /// Pool host running members or add-ons of private network `network`, if any
+    fn network_host(&self, network: &str) -> Option<String> {
+        let member_host = self.sandboxes.iter().find_map(|entry| {
+            let sandbox = entry.lock();
+            let member = sandbox.request.network.as_ref().is_some_and(|joined| joined.name == network);
+            sandbox.docker_host.clone().filter(|_| member)
+        });
+        member_host.or_else(|| {
+            self.addons.iter().find_map(|addon| {
+                let (addon_network, host) = addon.value();
+                host.clone().filter(|_| addon_network == network)
+            })
+        })
+    }
+
+    /// Backend to run a request on, and the pool host when it is a pooled Docker sandbox
+    async fn backend_for(&self, backend_type: &SandboxBackendType, request: &SandboxRequest) -> Result<(Arc<dyn SandboxBackend>, Option<String>)> {
+        if *backend_type == SandboxBackendType::Docker && !self.hosts.is_empty() {
+            let name = self.place(request).await?;
+            let host = self.hosts.iter().find(|host| host.config.name == name)
+                .ok_or_else(|| BackendUnavailable(format!("Docker host {} is no longer configured", name)))?;
+            return Ok((host.backend.clone(), Some(name)));
+        }
+        Ok((self.backends[backend_type].clone(), None))
+    }
+
+    /// Start a deployment's add-on container, on the pool host of its network if there is one
+    pub async fn start_addon(&self, addon: &AddonContainer) -> Result<()> {
+        super::validate_network(&addon.network)?;
+        let (backend, docker_host) = if self.hosts.is_empty() {
+            let backend = self.backends.get(&SandboxBackendType::Docker).cloned()
+                .ok_or_else(|| InvalidInput("Add-ons need the Docker backend".to_string()))?;
+            (backend, None)
+        } else {
+            let name = match self.network_host(&addon.network.name) {
+                Some(name) => name,
+                None => {
+                    let loads = self.host_loads().await;
+                    let index = hosts::least_loaded(&loads, 0)
+                        .ok_or_else(|| BackendUnavailable("None of the Docker hosts has room for add-ons".to_string()))?;
+                    loads[index].name.clone()
+                }
+            };
+            let host = self.hosts.iter().find(|host| host.config.name == name)
+                .ok_or_else(|| BackendUnavailable(format!("Docker host {} is no longer configured", name)))?;
+            (host.backend.clone(), Some(name))
+        };
+        self.addons.insert(addon.name.clone(), (addon.network.name.clone(), docker_host));
+        if let Err(e) = backend.start_addon(addon).await {
+            self.addons.remove(&addon.name);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Remove an add-on container; one not started since the service started is looked
+    /// for on every Docker host
+    pub async fn remove_addon(&self, name: &str) -> Result<()> {
+        let docker_host = self.addons.remove(name).and_then(|(_, (_, host))| host);
+        for (backend_type, host, backend) in self.connections() {
+            let started_here = docker_host.is_none() || host == docker_host.as_deref();
+            if backend_type == SandboxBackendType::Docker && started_here {
+                backend.remove_addon(name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the containers and directories on every backend that no sandbox claims
+    pub async fn collect_orphans(&self) -> OrphanReport {
+        let now = chrono::Utc::now();
+        let mut actions = Vec::new();
+        for (backend_type, host, backend) in self.connections() {
+            let resources = match backend.list_resources().await {
+                Ok(resources) => resources,
+                Err(e) => {
+                    tracing::warn!("Failed to list resources of backend {:?}: {}", backend_type, e);
+                    continue;
+                }
+            };
+            let is_known = |sandbox_id: &str| self.sandboxes.contains_key(sandbox_id);
+            for resource in resources {
+                if !gc::is_orphan(&resource, is_known, now, self.orphan_grace_period) {
+                    continue;
+                }
+                let error = backend.remove_resource(&resource).await.err().map(|e| format!("{:#}", e));
+                if let Some(error) = &error {
+                    tracing::warn!("Failed to remove orphaned {}: {}", resource.name, error);
+                }
+                actions.push(OrphanAction {
+                    backend: match host {
+                        Some(host) => format!("{}@{}", backend_type.name(), host),
+                        None => backend_type.name().to_string(),
+                    },
+                    resource,
+                    error,
+                });
+            }
+        }
+
+        if !actions.is_empty() {
+            tracing::info!("Orphan collection handled {} resources", actions.len());
+            self.events.publish(EventKind::OrphansCollected {
+                resources: actions.len(),
+                failed: actions.iter().filter(|action| action.error.is_some()).count(),
+            });
+        }
+        let report = OrphanReport {
+            ran_at: now,
+            grace_period_seconds: self.orphan_grace_period.num_seconds() as u64,
+            actions,
+        };
+        *self.last_orphan_report.lock().unwrap() = Some(report.clone());
+        report
+    }
+
+    /// Delete the one-shot sandboxes whose execution ended longer than the retention period ago
+    pub async fn expire_finished_sandboxes(&self) {
+        let Some(ttl) = self.finished_sandbox_ttl else {
+            return;
+        };
+        let now = chrono::Utc::now();
+        let expired: Vec<String> = self.sandboxes.iter()
+            .filter(|entry| gc::is_expired(entry.lock().finished_at, now, ttl))
+            .map(|entry| entry.key().clone())
+            .collect();
+        for sandbox_id in expired {
+            let Ok((entry, operation)) = self.claim(&sandbox_id).await else {
+                continue;
+            };
+            // The sandbox may have been executed again meanwhile
+            if !gc::is_expired(entry.lock().finished_at, chrono::Utc::now(), ttl) {
+                continue;
+            }
+            match self.remove_claimed(&sandbox_id, &entry, operation).await {
+                Ok(()) => tracing::info!("Removed sandbox {}, finished over {}s ago", sandbox_id, ttl.num_seconds()),
+                Err(e) => tracing::warn!("Failed to remove finished sandbox {}: {}", sandbox_id, e),
+            }
+        }
+    }
+
+    /// Outcome of the most recent orphan collection
+    pub fn last_orphan_report(&self) -> Option<OrphanReport> {
+        self.last_orphan_report.lock().unwrap().clone()
+    }
+
+    /// Replace the capacity limits; sandboxes already running are kept
+    pub fn set_limits(&self, limits: CapacityLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// GPUs claimed by live sandboxes and one-shot executions on the local Docker daemon
+    fn claimed_gpus(&self) -> u32 {
+        let one_shot: u32 = self.one_shot_gpus.iter().map(|claim| claim.0).sum();
+        self.sandboxes.iter()
+            .map(|entry| entry.lock().request.gpus())
+            .sum::<u32>() + one_shot
+    }
+
+    /// Sum of the memory limits of all live sandboxes
+    pub fn committed_memory_mb(&self) -> u64 {
+        self.sandboxes.iter().map(|entry| entry.lock().request.memory_limit_mb).sum()
+    }
+
+    fn entry(&self, sandbox_id: &str) -> Result<Arc<SandboxEntry>> {
+        self.sandboxes.get(sandbox_id)
+            .map(|entry| entry.clone())
+            .ok_or_else(|| NotFound(format!("Sandbox {} not found", sandbox_id)).into())
+    }
+
+    /// The sandbox's entry with its operation lock held, waiting for the operation in progress
+    async fn claim(&self, sandbox_id: &str) -> Result<(Arc<SandboxEntry>, OwnedMutexGuard<()>)> {
+        let entry = self.entry(sandbox_id)?;
+        let operation = entry.operation.clone().lock_owned().await;
+        // The operation waited for may have deleted the sandbox
+        let current = self.sandboxes.get(sandbox_id).is_some_and(|current| Arc::ptr_eq(&current, &entry));
+        if !current {
+            return Err(NotFound(format!("Sandbox {} not found", sandbox_id)).into());
+        }
+        Ok((entry, operation))
+    }
+
+    /// Backend a sandbox runs on: its pool host's if it was placed on one, else its type's
+    fn backend_of(&self, sandbox: &Sandbox) -> Arc<dyn SandboxBackend> {
+        sandbox.docker_host.as_deref()
+            .and_then(|name| self.hosts.iter().find(|host| host.config.name == name))
+            .map(|host| host.backend.clone())
+            .unwrap_or_else(|| self.backends[&sandbox.backend_type].clone())
+    }
+
+    /// Host and port the sandbox's service is reached at, asking the backend it runs on
+    pub async fn endpoint_for(&self, sandbox_id: &str) -> Result<Option<(String, u16)>> {
+        let backend = self.backend_of(&self.entry(sandbox_id)?.lock());
+        backend.endpoint_for(sandbox_id).await
+    }
+
+    /// Whether the backend the sandbox runs on finds it able to run code
+    pub async fn sandbox_health(&self, sandbox_id: &str) -> Result<SandboxHealth> {
+        let backend = self.backend_of(&self.entry(sandbox_id)?.lock());
+        backend.health_check(sandbox_id).await
+    }
+
+    pub async fn sandbox_stats(&self, sandbox_id: &str) -> Result<SandboxStats> {
+        let backend = self.backend_of(&self.entry(sandbox_id)?.lock());
+        backend.get_stats(sandbox_id).await
+    }
+
+    /// The last `lines` lines of the sandbox's output, then with `follow` new ones as they come
+    pub async fn sandbox_logs(&self, sandbox_id: &str, lines: usize, follow: bool) -> Result<LogStream> {
+        let backend = self.backend_of(&self.entry(sandbox_id)?.lock());
+        backend.get_logs(sandbox_id, lines, follow).await
+    }
+
+    /// Event bus that sandbox lifecycle events are published to
+    pub fn events(&self) -> EventBus {
+        self.events.clone()
+    }
+
+    /// Host port allocations of running sandboxes, shared with the proxy
+    pub fn port_allocator(&self) -> PortAllocator {
+        self.ports.clone()
+    }
+
+    /// Executions in flight, shared with the cancel route
+    pub fn cancellations(&self) -> Cancellations {
+        self.cancellations.clone()
+    }
+
+    /// Take no new sandboxes or executions, then wait up to `grace` for those in flight;
+    /// returns how many were still running
+    pub async fn drain(&self, grace: std::time::Duration) -> usize {
+        self.drain.start();
+        self.drain.wait_idle(grace).await
+    }
+
+    /// Whether the service is shutting down, see [`Self::drain`]
+    pub fn is_draining(&self) -> bool {
+        self.drain.is_draining()
+    }
+
+    /// Usage ledger, shared with the proxy and the admin API
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+
+    /// Reject requests with an unknown runtime or malformed fields before they reach a
+    /// backend; snapshot names become host paths on nsjail
+    fn validate_request(request: &SandboxRequest) -> Result<()> {
+        runtime::resolve_version(&request.runtime)?;
+        request.writable_paths()?;
+        super::validate_files(request.files.as_deref())?;
+        labels::validate(&request.labels)?;
+        if let Some(snapshot_name) = &request.from_snapshot {
+            super::validate_snapshot_name(snapshot_name)?;
+        }
+        Ok(())
+    }
+
+    pub async fn create_sandbox(&self, mut request: SandboxRequest) -> Result<()> {
+        let _in_flight = self.drain.admit()?;
+        request.install_proxy = self.http_proxy.clone();
+        Self::validate_request(&request)?;
+        let backend_type = self.select_backend(&request)?;
+        self.check_gpu_request(&backend_type, &request)?;
+        self.resolve_mounts(&backend_type, &mut request)?;
+        self.resolve_image(&backend_type, &mut request)?;
+        if let Some(network) = &request.network {
+            super::validate_network(network)?;
+            if backend_type != SandboxBackendType::Docker {
+                return Err(InvalidInput(format!("Private networks need the Docker backend, not {:?}", backend_type)).into());
+            }
+        }
+
+        // Registered before the backend creates it, so concurrent creations count it
+        // against the limits and pool hosts; the operation lock hides the half-created sandbox
+        // from other operations
+        let (backend, entry, operation) = {
+            let _admission = self.admission.lock().await;
+            let limits = self.limits.read().unwrap().clone();
+            limits.check(self.sandboxes.len(), self.committed_memory_mb(), request.memory_limit_mb)?;
+            if request.gpus() > 0 && self.hosts.is_empty() {
+                limits.check_gpus(self.claimed_gpus(), request.gpus())?;
+            }
+
+            let (backend, docker_host) = self.backend_for(&backend_type, &request).await?;
+            if let Some(host) = &docker_host {
+                tracing::info!("Placed sandbox {} on Docker host {}", request.id, host);
+            }
+            let mut sandbox = Sandbox::new(request.clone(), backend_type.clone());
+            sandbox.docker_host = docker_host;
+            let entry = SandboxEntry::new(sandbox);
+            let operation = entry.operation.clone().lock_owned().await;
+            self.sandboxes.insert(request.id.clone(), entry.clone());
+            (backend, entry, operation)
+        };
+
+        let created = match self.fetch_snapshot(backend.as_ref(), &request).await {
+            Ok(()) => backend.create_sandbox(&request).await,
+            Err(e) => Err(e),
+        };
+        let dev_server_port = match created {
+            Ok(port) => port,
+            Err(e) => {
+                self.sandboxes.remove(&request.id);
+                return Err(e);
+            }
+        };
+        entry.lock().dev_server_port = dev_server_port;
+        drop(operation);
+
+        self.meter.sandbox_started(&request.id, request.api_key.as_deref(), request.memory_limit_mb);
+        self.events.publish(EventKind::SandboxCreated {
+            sandbox_id: request.id.clone(),
+            runtime: request.runtime.clone(),
+        });
         Ok(())
     }
 
-    pub async fn execute_sandbox(&mut self, sandbox_id: &str) -> Result<SandboxResponse> {
-        let sandbox = self.sandboxes.get_mut(sandbox_id)
-            .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found", sandbox_id))?;
+    pub async fn execute_sandbox(&self, sandbox_id: &str) -> Result<SandboxResponse> {
+        self.run_in_sandbox(sandbox_id, None).await
+    }
+
+    /// Run the sandbox's tests with `command`, or the runtime's test command without one
+    pub async fn run_tests(&self, sandbox_id: &str, command: Option<String>) -> Result<SandboxResponse> {
+        let command = match command {
+            Some(command) => command,
+            None => {
+                let runtime = self.entry(sandbox_id)?.lock().request.runtime.clone();
+                test_runner::default_command(&runtime)
+                    .ok_or_else(|| InvalidInput(format!("Runtime {} has no default test command; set `command`", runtime)))?
+                    .to_string()
+            }
+        };
+        self.run_in_sandbox(sandbox_id, Some(&command)).await
+    }
 
-        sandbox.status = SandboxStatus::Running;
+    /// Execute the sandbox's code, or run `command` in it instead
+    async fn run_in_sandbox(&self, sandbox_id: &str, command: Option<&str>) -> Result<SandboxResponse> {
+        let _in_flight = self.drain.admit()?;
+        let (entry, _operation) = self.claim(sandbox_id).await?;
+        let (backend, request) = {
+            let mut sandbox = entry.lock();
+            if matches!(sandbox.status, SandboxStatus::Paused) {
+                return Err(StatusConflict(format!("Sandbox {} is paused", sandbox_id)).into());
+            }
+            sandbox.status = SandboxStatus::Running;
+            sandbox.finished_at = None;
+            (self.backend_of(&sandbox), sandbox.request.clone())
+        };
+        self.events.publish(EventKind::SandboxStatusChanged {
+            sandbox_id: sandbox_id.to_string(),
+            status: SandboxStatus::Running,
+        });
         
-        let response = self.backend.execute_sandbox(&sandbox.request).await?;
+        let mut response = execute_cancelable(backend.as_ref(), &self.cancellations, &request, command).await?;
         
-        sandbox.status = if response.success {
+        let status = if response.success {
             SandboxStatus::Completed
         } else {
             SandboxStatus::Failed
         };
+        {
+            let mut sandbox = entry.lock();
+            sandbox.status = status.clone();
+            sandbox.termination_reason = response.termination_reason;
+            if !sandbox.is_persistent() {
+                sandbox.finished_at = Some(chrono::Utc::now());
+            }
+        }
+        self.events.publish(EventKind::SandboxStatusChanged {
+            sandbox_id: sandbox_id.to_string(),
+            status,
+        });
+        let patterns = request.artifacts.clone().unwrap_or_default();
+        self.meter.execution(sandbox_id);
+        self.finish_execution(&request, &mut response);
+
+        if !patterns.is_empty() {
+            self.stored_artifacts.remove(sandbox_id);
+            match backend.collect_artifacts(sandbox_id, &patterns).await {
+                Ok(artifacts) => match self.upload_artifacts(sandbox_id, &artifacts).await {
+                    Ok(true) => {
+                        self.stored_artifacts.insert(sandbox_id.to_string());
+                        self.artifacts.remove(sandbox_id);
+                    }
+                    Ok(false) => {
+                        self.artifacts.insert(sandbox_id.to_string(), artifacts);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to upload artifacts of sandbox {}, keeping them in memory: {:#}", sandbox_id, e);
+                        self.artifacts.insert(sandbox_id.to_string(), artifacts);
+                    }
+                },
+                Err(e) => {
+                    tracing::warn!("Failed to collect artifacts of sandbox {}: {}", sandbox_id, e);
+                    self.artifacts.remove(sandbox_id);
+                }
+            }
+        }
 
         Ok(response)
     }
 
-    pub async fn execute_sandbox_direct(&mut self, request: SandboxRequest) -> Result<SandboxResponse> {
+    /// Create a session's sandbox and start its driver, removing the sandbox again if the
+    /// driver does not come up
+    pub async fn create_session(&self, request: SandboxRequest) -> Result<()> {
+        let sandbox_id = request.id.clone();
+        let port = request.app_port();
+        self.create_sandbox(request).await?;
+        if let Err(e) = self.start_session(&sandbox_id, port).await {
+            if let Err(cleanup) = self.delete_sandbox(&sandbox_id).await {
+                tracing::warn!("Failed to remove session {} after it did not start: {}", sandbox_id, cleanup);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn start_session(&self, sandbox_id: &str, port: u16) -> Result<()> {
+        let started = self.execute_sandbox(sandbox_id).await?;
+        if !started.success {
+            return Err(anyhow::anyhow!("Session {} did not start: {}", sandbox_id, started.stderr.trim()));
+        }
+        let deadline = std::time::Instant::now() + sessions::START_TIMEOUT;
+        loop {
+            let backend = self.get_backend(sandbox_id)
+                .ok_or_else(|| NotFound(format!("Session {} not found", sandbox_id)))?;
+            match backend.probe_http(sandbox_id, port, sessions::HEALTH_PATH).await {
+                Ok(()) => return Ok(()),
+                Err(e) if std::time::Instant::now() >= deadline => {
+                    return Err(anyhow::anyhow!("Session {} did not start within {:?}: {}", sandbox_id, sessions::START_TIMEOUT, e));
+                }
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(250)).await,
+            }
+        }
+    }
+
+    /// Evaluate code in a session, one evaluation at a time per session
+    pub async fn eval_session(&self, sandbox_id: &str, mut request: EvalRequest) -> Result<EvalResult> {
+        let _in_flight = self.drain.admit()?;
+        let (entry, _operation) = self.claim(sandbox_id).await?;
+        let backend = {
+            let sandbox = entry.lock();
+            if !sessions::is_session(&sandbox.request) {
+                return Err(NotFound(format!("Session {} not found", sandbox_id)).into());
+            }
+            if matches!(sandbox.status, SandboxStatus::Paused) {
+                return Err(StatusConflict(format!("Session {} is paused", sandbox_id)).into());
+            }
+            request.timeout_ms = request.timeout_ms.or(Some(sandbox.request.timeout_ms));
+            self.backend_of(&sandbox)
+        };
+        let (host, port) = backend.endpoint_for(sandbox_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Session {} is not reachable", sandbox_id))?;
+
+        let result = sessions::eval(&host, port, &request).await?;
+        self.meter.execution(sandbox_id);
+        Ok(result)
+    }
+
+    /// Artifacts collected after the sandbox's last execution
+    pub fn artifacts(&self, sandbox_id: &str) -> Option<Vec<Artifact>> {
+        self.artifacts.get(sandbox_id).map(|artifacts| artifacts.clone())
+    }
+
+    /// Presigned URL of the archive of the sandbox's artifacts, when they were uploaded
+    pub fn artifacts_url(&self, sandbox_id: &str) -> Option<String> {
+        let store = self.storage.as_ref()?;
+        self.stored_artifacts.contains(sandbox_id).then(|| store.presigned_url(&artifacts_key(sandbox_id)))
+    }
+
+    /// Upload the archive of `artifacts` when an object store is configured; false without one
+    async fn upload_artifacts(&self, sandbox_id: &str, artifacts: &[Artifact]) -> Result<bool> {
+        let Some(store) = &self.storage else {
+            return Ok(false);
+        };
+        let archive = super::artifacts::to_zip(artifacts)?;
+        store.put(&artifacts_key(sandbox_id), archive, "application/zip").await?;
+        Ok(true)
+    }
+
+    /// Fetch the archive of the snapshot `request` restores from the object store, unless
+    /// the backend keeps no archives or has it already
+    async fn fetch_snapshot(&self, backend: &dyn SandboxBackend, request: &SandboxRequest) -> Result<()> {
+        let (Some(store), Some(snapshot_name)) = (&self.storage, &request.from_snapshot) else {
+            return Ok(());
+        };
+        // The name becomes a host path the archive is written to
+        super::validate_snapshot_name(snapshot_name)?;
+        let Some(path) = backend.snapshot_file(snapshot_name).filter(|path| !path.exists()) else {
+            return Ok(());
+        };
+        // Left to the backend to report when the bucket does not have it either
+        if let Some(archive) = store.get(&snapshot_key(snapshot_name)).await? {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, archive).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn execute_sandbox_direct(&self, mut request: SandboxRequest) -> Result<SandboxResponse> {
+        let _in_flight = self.drain.admit()?;
+        request.install_proxy = self.http_proxy.clone();
         // For one-shot execution, just execute directly without storing the sandbox
-        self.backend.execute_sandbox(&request).await
+        Self::validate_request(&request)?;
+        #[cfg(feature = "isolate")]
+        if super::backend::isolate::accepts(&request) {
+            let mut response = self.isolate.execute(&request).await?;
+            self.meter.one_shot(request.api_key.as_deref(), request.memory_limit_mb, response.execution_time_ms);
+            self.finish_execution(&request, &mut response);
+            return Ok(response);
+        }
+
+        let backend_type = self.select_backend(&request)?;
+        self.check_gpu_request(&backend_type, &request)?;
+        self.resolve_mounts(&backend_type, &mut request)?;
+        self.resolve_image(&backend_type, &mut request)?;
+        // GPUs are claimed like a sandbox's, and given back once the execution ends
+        let (backend, _gpus) = if request.gpus() > 0 {
+            let _admission = self.admission.lock().await;
+            if self.hosts.is_empty() {
+                self.limits.read().unwrap().check_gpus(self.claimed_gpus(), request.gpus())?;
+            }
+            let (backend, docker_host) = self.backend_for(&backend_type, &request).await?;
+            self.one_shot_gpus.insert(request.id.clone(), (request.gpus(), docker_host));
+            (backend, Some(GpuClaim { claims: &self.one_shot_gpus, sandbox_id: &request.id }))
+        } else {
+            (self.backend_for(&backend_type, &request).await?.0, None)
+        };
+        self.fetch_snapshot(backend.as_ref(), &request).await?;
+        let mut response = backend.execute_sandbox(&request).await?;
+        self.meter.one_shot(request.api_key.as_deref(), request.memory_limit_mb, response.execution_time_ms);
+        self.finish_execution(&request, &mut response);
+        Ok(response)
     }
 
-    pub async fn delete_sandbox(&mut self, sandbox_id: &str) -> Result<()> {
-        let _sandbox = self.sandboxes.remove(sandbox_id)
-            .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found", sandbox_id))?;
+    /// One-shot execution through the result cache, unless `use_cache` is false or the cache
+    /// is disabled; returns whether the result came from the cache
+    ///
+    /// A cached result keeps the `execution_id` of the execution that produced it, and
+    /// is not metered as no sandbox runs.
+    pub async fn execute_cached(&self, request: SandboxRequest, use_cache: bool) -> Result<(SandboxResponse, bool)> {
+        let cached = self.result_cache.as_ref()
+            .filter(|_| use_cache)
+            .and_then(|cache| Some((cache, ResultCache::key(&request)?)));
+        let Some((cache, key)) = cached else {
+            return Ok((self.execute_sandbox_direct(request).await?, false));
+        };
+        if let Some(response) = cache.get(&key) {
+            tracing::debug!("Returning cached result of execution {:?} for sandbox {}", response.execution_id, request.id);
+            return Ok((response, true));
+        }
+        let response = self.execute_sandbox_direct(request).await?;
+        cache.insert(key, &response);
+        Ok((response, false))
+    }
+
+    /// Run a past execution's request again in a fresh one-shot sandbox, metered to
+    /// `api_key`; returns the original execution and the replay
+    pub async fn replay_execution(&self, execution_id: &str, api_key: Option<String>) -> Result<(ExecutionRecord, ExecutionRecord)> {
+        let original = self.history.get(execution_id)
+            .ok_or_else(|| NotFound(format!("Execution {} not found", execution_id)))?;
+        let request = SandboxRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            mode: Some(SandboxMode::OneShot),
+            // A replay compares what the program printed; a dev server would keep running
+            dev_server: None,
+            api_key,
+            deployment: None,
+            ..original.request.clone()
+        };
+        tracing::info!("Replaying execution {} of sandbox {} in sandbox {}", execution_id, original.request.id, request.id);
+        let response = self.execute_sandbox_direct(request).await?;
+        let replay = response.execution_id.as_deref()
+            .and_then(|id| self.history.get(id))
+            .ok_or_else(|| anyhow::anyhow!("Replay of execution {} was not recorded", execution_id))?;
+        Ok((original, replay))
+    }
+
+    /// Count, publish and record a finished execution, setting its `execution_id` and `cost`
+    fn finish_execution(&self, request: &SandboxRequest, response: &mut SandboxResponse) {
+        let sandbox_id = &request.id;
+        if matches!(request.mode, Some(SandboxMode::Check)) {
+            response.diagnostics = Some(runtime::check::diagnostics(&request.runtime, sandbox_id, &response.stdout, &response.stderr));
+        }
+        response.cost = self.meter.execution_cost(request.memory_limit_mb, response);
+        response.execution_id = Some(self.history.record(request, response));
+        if let Some(reason) = response.termination_reason {
+            *self.terminations.lock().unwrap().entry(reason).or_default() += 1;
+            if reason == TerminationReason::OomKilled {
+                tracing::warn!("Execution of sandbox {} was killed at its memory limit", sandbox_id);
+            }
+        }
+        self.events.publish(EventKind::ExecutionFinished {
+            sandbox_id: sandbox_id.to_string(),
+            success: response.success,
+            exit_code: response.exit_code,
+            execution_time_ms: response.execution_time_ms,
+            termination_reason: response.termination_reason,
+        });
+    }
+
+    /// Executions finished since startup that ended for each reason
+    pub fn termination_counts(&self) -> Vec<(TerminationReason, u64)> {
+        let terminations = self.terminations.lock().unwrap();
+        TerminationReason::ALL.iter()
+            .map(|reason| (*reason, terminations.get(reason).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    pub async fn delete_sandbox(&self, sandbox_id: &str) -> Result<()> {
+        let (entry, operation) = self.claim(sandbox_id).await?;
+        self.remove_claimed(sandbox_id, &entry, operation).await
+    }
+
+    /// Delete a session's sandbox, leaving other sandboxes alone
+    pub async fn delete_session(&self, sandbox_id: &str) -> Result<()> {
+        let (entry, operation) = self.claim(sandbox_id).await?;
+        if !sessions::is_session(&entry.lock().request) {
+            return Err(NotFound(format!("Session {} not found", sandbox_id)).into());
+        }
+        self.remove_claimed(sandbox_id, &entry, operation).await
+    }
 
-        self.backend.cleanup_sandbox(sandbox_id).await?;
+    /// Forget a sandbox claimed with [`Self::claim`] and clean up what its backend holds
+    async fn remove_claimed(&self, sandbox_id: &str, entry: &SandboxEntry, _operation: OwnedMutexGuard<()>) -> Result<()> {
+        self.sandboxes.remove(sandbox_id);
+
+        self.artifacts.remove(sandbox_id);
+        if let (Some(store), Some(_)) = (&self.storage, self.stored_artifacts.remove(sandbox_id)) {
+            let (store, key) = (store.clone(), artifacts_key(sandbox_id));
+            tokio::spawn(async move {
+                if let Err(e) = store.delete(&key).await {
+                    tracing::warn!("Failed to delete artifacts {}: {:#}", key, e);
+                }
+            });
+        }
+        self.meter.sandbox_stopped(sandbox_id);
+        let backend = self.backend_of(&entry.lock());
+        backend.cleanup_sandbox(sandbox_id).await?;
+        self.events.publish(EventKind::SandboxDeleted {
+            sandbox_id: sandbox_id.to_string(),
+        });
         Ok(())
     }
 
+    /// Freeze the sandbox's processes until [`Self::resume_sandbox`]
+    pub async fn pause_sandbox(&self, sandbox_id: &str) -> Result<SandboxInfo> {
+        let (entry, _operation) = self.claim(sandbox_id).await?;
+        let backend = {
+            let sandbox = entry.lock();
+            if matches!(sandbox.status, SandboxStatus::Paused) {
+                return Err(StatusConflict(format!("Sandbox {} is already paused", sandbox_id)).into());
+            }
+            self.backend_of(&sandbox)
+        };
+
+        backend.pause_sandbox(sandbox_id).await?;
+        let info = {
+            let mut sandbox = entry.lock();
+            sandbox.resume_status = Some(std::mem::replace(&mut sandbox.status, SandboxStatus::Paused));
+            sandbox.to_info()
+        };
+        self.events.publish(EventKind::SandboxStatusChanged {
+            sandbox_id: sandbox_id.to_string(),
+            status: SandboxStatus::Paused,
+        });
+        Ok(info)
+    }
+
+    /// Thaw a paused sandbox, restoring the status it had before
+    pub async fn resume_sandbox(&self, sandbox_id: &str) -> Result<SandboxInfo> {
+        let (entry, _operation) = self.claim(sandbox_id).await?;
+        let backend = {
+            let sandbox = entry.lock();
+            if !matches!(sandbox.status, SandboxStatus::Paused) {
+                return Err(StatusConflict(format!("Sandbox {} is not paused", sandbox_id)).into());
+            }
+            self.backend_of(&sandbox)
+        };
+
+        backend.resume_sandbox(sandbox_id).await?;
+        let (status, info) = {
+            let mut sandbox = entry.lock();
+            sandbox.status = sandbox.resume_status.take().unwrap_or(SandboxStatus::Running);
+            (sandbox.status.clone(), sandbox.to_info())
+        };
+        self.events.publish(EventKind::SandboxStatusChanged {
+            sandbox_id: sandbox_id.to_string(),
+            status,
+        });
+        Ok(info)
+    }
+
+    pub async fn snapshot_sandbox(&self, sandbox_id: &str, snapshot_name: &str) -> Result<String> {
+        let (entry, _operation) = self.claim(sandbox_id).await?;
+        super::validate_snapshot_name(snapshot_name)?;
+
+        let (backend, docker_host) = {
+            let sandbox = entry.lock();
+            (self.backend_of(&sandbox), sandbox.docker_host.clone())
+        };
+        let snapshot = backend.snapshot_sandbox(sandbox_id, snapshot_name).await?;
+        if let Some(host) = docker_host {
+            self.snapshot_hosts.insert(snapshot_name.to_string(), host);
+        }
+        // Archives move to the object store, and are fetched back when restored
+        if let (Some(store), Some(path)) = (&self.storage, backend.snapshot_file(snapshot_name)) {
+            let key = snapshot_key(snapshot_name);
+            store.put(&key, tokio::fs::read(&path).await?, "application/x-tar").await?;
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                tracing::warn!("Failed to remove uploaded snapshot {}: {}", path.display(), e);
+            }
+            return Ok(store.uri(&key));
+        }
+        Ok(snapshot)
+    }
+
     pub async fn get_sandbox_info(&self, sandbox_id: &str) -> Option<SandboxInfo> {
-        self.sandboxes.get(sandbox_id).map(|s| s.to_info())
+        self.sandboxes.get(sandbox_id).map(|entry| entry.lock().to_info())
     }
 
     pub async fn list_sandboxes(&self) -> Vec<SandboxInfo> {
-        self.sandboxes.values().map(|s| s.to_info()).collect()
+        self.sandboxes.iter().map(|entry| entry.lock().to_info()).collect()
     }
     
-    pub async fn get_all_sandboxes(&self) -> Vec<&Sandbox> {
-        self.sandboxes.values().collect()
+    /// Copy of the record of one sandbox
+    pub async fn get_sandbox(&self, sandbox_id: &str) -> Option<Sandbox> {
+        self.sandboxes.get(sandbox_id).map(|entry| entry.lock().clone())
+    }
+
+    /// Copies of the records of all sandboxes
+    pub async fn get_all_sandboxes(&self) -> Vec<Sandbox> {
+        self.sandboxes.iter().map(|entry| entry.lock().clone()).collect()
     }
     
     pub fn get_backend_type(&self) -> &SandboxBackendType {
         &self.backend_type
     }
     
-    pub fn get_backend(&self) -> Option<&dyn SandboxBackend> {
-        Some(self.backend.as_ref())
+    /// Backend the sandbox runs on
+    pub fn get_backend(&self, sandbox_id: &str) -> Option<Arc<dyn SandboxBackend>> {
+        let entry = self.sandboxes.get(sandbox_id)?;
+        let backend = self.backend_of(&entry.lock());
+        Some(backend)
+    }
+
+    /// Runtimes, versions and features the backends can serve right now
+    pub async fn capabilities(&self) -> Capabilities {
+        // Default backend first
+        let mut backend_types: Vec<&SandboxBackendType> = self.backends.keys().collect();
+        backend_types.sort_by_key(|backend_type| (**backend_type != self.backend_type, backend_type.name()));
+
+        let mut backends = Vec::new();
+        let mut versions_by_runtime: HashMap<&str, Vec<&str>> = HashMap::new();
+        for backend_type in backend_types {
+            let backend = &self.backends[backend_type];
+            let available = backend.is_available().await;
+            let mut info = BackendInfo {
+                name: backend_type.name().to_string(),
+                available,
+                features: backend.features(),
+                runtimes: Default::default(),
+            };
+            for definition in runtime::RUNTIMES {
+                let versions = if available { backend.runtime_versions(definition).await } else { Vec::new() };
+                if versions.is_empty() {
+                    continue;
+                }
+                info.runtimes.insert(
+                    definition.name.to_string(),
+                    versions.iter().map(|version| version.to_string()).collect(),
+                );
+                let known = versions_by_runtime.entry(definition.name).or_default();
+                for version in versions {
+                    if !known.contains(&version) {
+                        known.push(version);
+                    }
+                }
+            }
+            backends.push(info);
+        }
+
+        let runtimes = runtime::RUNTIMES.iter()
+            .map(|definition| {
+                // Keep the catalog's version order
+                let available = versions_by_runtime.get(definition.name).cloned().unwrap_or_default();
+                let versions: Vec<String> = definition.versions.iter()
+                    .filter(|version| available.contains(version))
+                    .map(|version| version.to_string())
+                    .collect();
+                RuntimeInfo {
+                    available: !versions.is_empty(),
+                    versions,
+                    ..definition.info()
+                }
+            })
+            .collect();
+
+        Capabilities { runtimes, backends }
     }
 
-    pub async fn cleanup_all(&mut self) -> Result<()> {
-        let sandbox_ids: Vec<String> = self.sandboxes.keys().cloned().collect();
+    pub async fn cleanup_all(&self) -> Result<()> {
+        let sandbox_ids: Vec<String> = self.sandboxes.iter().map(|entry| entry.key().clone()).collect();
         
         for id in sandbox_ids {
             if let Err(e) = self.delete_sandbox(&id).await {
@@ -97,9 +1265,10 @@ impl SandboxManager {
         Ok(())
     }
 
-    pub async fn add_files_to_sandbox(&mut self, sandbox_id: &str, files: Vec<SandboxFile>) -> Result<()> {
-        let sandbox = self.sandboxes.get_mut(sandbox_id)
-            .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found", sandbox_id))?;
+    pub async fn add_files_to_sandbox(&self, sandbox_id: &str, files: Vec<SandboxFile>) -> Result<()> {
+        let entry = self.entry(sandbox_id)?;
+        super::validate_files(Some(&files))?;
+        let mut sandbox = entry.lock();
 
         // Add files to the sandbox request
         if let Some(ref mut existing_files) = sandbox.request.files {
@@ -110,4 +1279,84 @@ impl SandboxManager {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Create a backend; for Docker with a pool, connect every host of the pool and stand
+/// in for all of them with the first that answers
+async fn connect_backend(
+    backend_type: &SandboxBackendType,
+    ports: &PortAllocator,
+    security: &ContainerSecurity,
+    docker_hosts: &[DockerHostConfig],
+) -> Result<(Arc<dyn SandboxBackend>, Vec<DockerHost>)> {
+    if *backend_type != SandboxBackendType::Docker || docker_hosts.is_empty() {
+        let backend = create_backend(backend_type.clone(), ports.clone(), security)?;
+        return Ok((Arc::from(backend), Vec::new()));
+    }
+
+    let mut hosts = Vec::new();
+    let mut representative = None;
+    for config in docker_hosts {
+        let backend: Arc<dyn SandboxBackend> = Arc::from(create_docker_host_backend(config, ports, security)?);
+        if backend.is_available().await {
+            tracing::info!("Docker host {} at {} is available", config.name, config.url);
+            representative.get_or_insert_with(|| backend.clone());
+        } else {
+            tracing::warn!("Docker host {} at {} is not available; sandboxes go elsewhere until it is", config.name, config.url);
+        }
+        hosts.push(DockerHost { config: config.clone(), backend });
+    }
+    let representative = representative.unwrap_or_else(|| hosts[0].backend.clone());
+    Ok((representative, hosts))
+}
+
+/// GPUs a one-shot execution holds in `claims` until dropped
+struct GpuClaim<'a> {
+    claims: &'a DashMap<String, (u32, Option<String>)>,
+    sandbox_id: &'a str,
+}
+
+impl Drop for GpuClaim<'_> {
+    fn drop(&mut self) {
+        self.claims.remove(self.sandbox_id);
+    }
+}
+
+/// Object key of a sandbox's artifact archive
+fn artifacts_key(sandbox_id: &str) -> String {
+    format!("artifacts/{}.zip", sandbox_id)
+}
+
+/// Object key of a snapshot archive
+fn snapshot_key(snapshot_name: &str) -> String {
+    format!("snapshots/{}.tar", snapshot_name)
+}
+
+/// Run the request's execution, killing it on the backend if it is canceled meanwhile
+async fn execute_cancelable(
+    backend: &dyn SandboxBackend,
+    cancellations: &Cancellations,
+    request: &SandboxRequest,
+    command: Option<&str>,
+) -> Result<SandboxResponse> {
+    let execution = cancellations.start(&request.id);
+    let run = match command {
+        Some(command) => backend.run_command(request, command),
+        None => backend.execute_sandbox(request),
+    };
+    tokio::pin!(run);
+
+    tokio::select! {
+        response = &mut run => response,
+        _ = execution.canceled() => {
+            backend.cancel_execution(&request.id).await?;
+            // Once its processes are gone the execution returns with the output so far
+            let mut response = run.await?;
+            response.success = false;
+            response.canceled = true;
+            response.termination_reason = Some(TerminationReason::Killed);
+            tracing::info!("Canceled execution of sandbox {} after {}ms", request.id, response.execution_time_ms);
+            Ok(response)
+        }
+    }
+}
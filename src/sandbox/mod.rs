@@ -1,27 +1,75 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::api::SandboxInfo;
-
+pub mod artifacts;
 pub mod backend;
+pub mod cancel;
+pub mod capacity;
+pub mod diff;
+pub mod drain;
+pub mod gc;
+pub mod history;
+pub mod hosts;
+pub mod images;
+pub mod labels;
+pub mod listing;
 pub mod manager;
+pub mod mounts;
+pub mod persisted;
+pub mod ports;
+pub mod result_cache;
+pub mod sessions;
+pub mod test_runner;
 
+pub use artifacts::Artifact;
 pub use backend::SandboxBackendType;
+pub use cancel::Cancellations;
+pub use capacity::{CapacityExceeded, CapacityLimits};
 pub use manager::SandboxManager;
+pub use ports::PortAllocator;
+pub use voidrun_types::{ExecutionUsage, FileEncoding, NetworkConfig, SandboxFile, SandboxInfo, SandboxMount, SandboxResponse, TerminationReason};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SandboxFile {
-    pub path: String,
-    pub content: String,
-    pub is_executable: Option<bool>,
-}
+use crate::api::error::InvalidInput;
+use crate::api::public_url::DEFAULT_BASE_URL;
+use crate::runtime::{self, playwright};
+use crate::security::SecurityProfile;
+
+/// Port sandboxed services listen on when the request does not choose one
+pub const DEFAULT_APP_PORT: u16 = 3000;
+
+/// Disk quota of one-shot sandboxes when the request does not choose one
+pub const DEFAULT_DISK_LIMIT_MB: u64 = 50;
+/// Disk quota of persistent sandboxes, which install dependencies and run dev servers
+pub const DEFAULT_PERSISTENT_DISK_LIMIT_MB: u64 = 500;
+/// Process limit when the request does not choose one
+pub const DEFAULT_MAX_PROCESSES: u64 = 256;
+/// Writable paths besides the project directory when the request does not list any
+pub const DEFAULT_WRITABLE_PATHS: &[&str] = &["/tmp"];
+/// Home directory of sandboxed programs, which need not exist in the image
+pub const SANDBOX_HOME: &str = "/home/sandbox";
+/// Persistent sandboxes install dependencies, and package managers cache them under the home directory
+pub const DEFAULT_PERSISTENT_WRITABLE_PATHS: &[&str] = &["/tmp", SANDBOX_HOME];
+/// Virtual filesystems a tmpfs would hide from the sandbox
+const RESERVED_PATHS: &[&str] = &["/dev", "/proc", "/sys"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SandboxMode {
     OneShot,    // Execute once and cleanup (default)
     Persistent, // Keep running until explicitly stopped
+    Check,      // Typecheck or syntax check the code instead of running it
 }
 
+/// Output of an execution as the program writes it, for callers streaming it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputChunk {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Where backends forward [`OutputChunk`]s while collecting the output into the response
+pub type OutputSender = tokio::sync::mpsc::UnboundedSender<OutputChunk>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxRequest {
     pub id: String,
@@ -35,17 +83,126 @@ pub struct SandboxRequest {
     pub mode: Option<SandboxMode>,
     pub install_deps: Option<bool>,
     pub dev_server: Option<bool>,
+    pub from_snapshot: Option<String>,
+    /// Port the service listens on inside the sandbox (default: 3000)
+    pub port: Option<u16>,
+    /// Glob patterns, relative to the project root, of files collected after execution
+    pub artifacts: Option<Vec<String>>,
+    /// Size of the writable project directory in MB
+    pub disk_limit_mb: Option<u64>,
+    /// Maximum number of processes, guarding the host against fork bombs
+    pub max_processes: Option<u64>,
+    /// Backend to run on; unset lets the manager choose
+    pub backend: Option<SandboxBackendType>,
+    /// Syscall restrictions; unset means the strict default
+    pub security_profile: Option<SecurityProfile>,
+    /// Absolute paths mounted writable besides the project directory, replacing the defaults
+    pub writable_paths: Option<Vec<String>>,
+    /// GPUs claimed from the host's inventory and passed through to the sandbox
+    pub gpus: Option<u32>,
+    /// Caller's labels, also set on the sandbox's container
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Key of the caller, which the sandbox's usage is metered to; left out of the
+    /// request persisted in container labels
+    #[serde(skip)]
+    pub api_key: Option<String>,
+    /// Outbound proxy dependency installation goes through, set by the manager from
+    /// `sandbox.http_proxy`; left out of persisted requests as it may hold credentials
+    #[serde(skip)]
+    pub install_proxy: Option<String>,
+    /// Data sets of `sandbox.allowed_mounts` the caller asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mounts: Option<Vec<SandboxMount>>,
+    /// `mounts` resolved to host directories by the manager, mounted read-only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bind_mounts: Vec<mounts::BindMount>,
+    /// Image the caller runs instead of the runtime's, from `sandbox.allowed_images`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Login for the registry the sandbox's image is pulled from, set by the manager from
+    /// `sandbox.registry_credentials`; left out of persisted requests
+    #[serde(skip)]
+    pub registry_credential: Option<images::RegistryCredential>,
+    /// Private network the sandbox joins under a host name; Docker only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+    /// FaaS deployment the sandbox serves, persisted with the request so the deployment
+    /// can be restored after a restart; opaque to the sandbox manager
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<serde_json::Value>,
+    /// Receives the output as it is written, set by callers streaming it; backends that
+    /// cannot forward it only return it in the response
+    #[serde(skip)]
+    pub output: Option<OutputSender>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SandboxResponse {
-    pub success: bool,
-    pub stdout: String,
-    pub stderr: String,
-    pub exit_code: Option<i32>,
-    pub execution_time_ms: u64,
-    pub is_running: Option<bool>,
-    pub dev_server_url: Option<String>,
+impl SandboxRequest {
+    /// Port the sandboxed service listens on
+    pub fn app_port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_APP_PORT)
+    }
+
+    /// Disk quota of the project directory, larger by default for persistent sandboxes
+    /// and runtimes handling large files
+    ///
+    /// Zero falls back to the default, as a tmpfs of size 0 is unbounded.
+    pub fn disk_limit_mb(&self) -> u64 {
+        let default = match self.mode {
+            Some(SandboxMode::Persistent) => DEFAULT_PERSISTENT_DISK_LIMIT_MB,
+            _ => DEFAULT_DISK_LIMIT_MB,
+        };
+        self.disk_limit_mb.filter(|mb| *mb > 0)
+            .unwrap_or_else(|| runtime::default_disk_limit_mb(&self.runtime).map_or(default, |mb| mb.max(default)))
+    }
+
+    /// GPUs the sandbox claims, none unless requested
+    pub fn gpus(&self) -> u32 {
+        self.gpus.unwrap_or(0)
+    }
+
+    /// Process limit, falling back to the default for missing or zero values
+    pub fn max_processes(&self) -> u64 {
+        self.max_processes.filter(|n| *n > 0).unwrap_or(if playwright::is_playwright(&self.runtime) {
+            playwright::MAX_PROCESSES
+        } else {
+            DEFAULT_MAX_PROCESSES
+        })
+    }
+
+    /// Paths backends mount writable, each sized like the project directory; the rest of
+    /// the filesystem is read-only
+    ///
+    /// The project directory, `/sandbox`, is always writable and not part of the list.
+    pub fn writable_paths(&self) -> Result<Vec<String>, InvalidInput> {
+        let defaults = match self.mode {
+            Some(SandboxMode::Persistent) => DEFAULT_PERSISTENT_WRITABLE_PATHS,
+            // Browsers keep settings and crash reports under the home directory
+            _ if playwright::is_playwright(&self.runtime) => DEFAULT_PERSISTENT_WRITABLE_PATHS,
+            _ => DEFAULT_WRITABLE_PATHS,
+        };
+        let requested = match &self.writable_paths {
+            Some(paths) => paths.clone(),
+            None => defaults.iter().map(|path| path.to_string()).collect(),
+        };
+
+        let mut paths = Vec::new();
+        for path in requested {
+            let normalized = format!("/{}", path.trim_matches('/'));
+            let is_reserved = |reserved: &&str| normalized == *reserved || normalized.starts_with(&format!("{}/", reserved));
+            if !path.starts_with('/')
+                || normalized == "/"
+                || normalized.split('/').any(|segment| segment == "..")
+                || RESERVED_PATHS.iter().any(is_reserved)
+            {
+                return Err(InvalidInput(format!("Invalid writable path: {}", path)));
+            }
+            if normalized != "/sandbox" && !paths.contains(&normalized) {
+                paths.push(normalized);
+            }
+        }
+        Ok(paths)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +213,17 @@ pub struct Sandbox {
     pub status: SandboxStatus,
     pub container_id: Option<String>,
     pub dev_server_port: Option<u16>,
+    /// Backend the sandbox was created on
+    pub backend_type: SandboxBackendType,
+    /// Docker host of the pool the sandbox was placed on, if Docker sandboxes are pooled
+    pub docker_host: Option<String>,
+    /// Status to return to when a paused sandbox resumes
+    pub resume_status: Option<SandboxStatus>,
+    /// When the last execution of a one-shot sandbox ended; such sandboxes are left out
+    /// of lists by default and removed once the retention period has passed
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// How the last execution ended
+    pub termination_reason: Option<TerminationReason>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,10 +235,92 @@ pub enum SandboxStatus {
     Completed,
     Failed,
     Terminated,
+    /// Processes are frozen until the sandbox is resumed
+    Paused,
+}
+
+/// The operation does not apply to the sandbox in its current status, e.g. pausing it twice
+#[derive(Debug, Clone)]
+pub struct StatusConflict(pub String);
+
+impl std::fmt::Display for StatusConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StatusConflict {}
+
+/// The service is shutting down and takes no new sandboxes or executions
+#[derive(Debug, Clone)]
+pub struct ShuttingDown;
+
+impl std::fmt::Display for ShuttingDown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("The service is shutting down; retry on another instance or once it is back")
+    }
+}
+
+impl std::error::Error for ShuttingDown {}
+
+/// Bytes written for a file, its content decoded per its encoding
+pub fn file_contents(file: &SandboxFile) -> Result<Vec<u8>, InvalidInput> {
+    match file.encoding.unwrap_or_default() {
+        FileEncoding::Utf8 => Ok(file.content.as_bytes().to_vec()),
+        FileEncoding::Base64 => BASE64_STANDARD
+            .decode(file.content.trim())
+            .map_err(|e| InvalidInput(format!("File {} is not valid base64: {}", file.path, e))),
+    }
+}
+
+/// Reject files with paths leaving their directory or content that does not decode,
+/// before any of them is written
+pub fn validate_files(files: Option<&[SandboxFile]>) -> Result<(), InvalidInput> {
+    for file in files.unwrap_or_default() {
+        if file.path.trim_matches('/').is_empty() || file.path.split('/').any(|segment| segment == "..") {
+            return Err(InvalidInput(format!("Invalid file path: {}", file.path)));
+        }
+        file_contents(file)?;
+    }
+    Ok(())
+}
+
+/// Snapshot names double as Docker image tags, so restrict them to the tag charset
+pub fn validate_snapshot_name(name: &str) -> anyhow::Result<()> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+
+    if name.is_empty() || name.len() > 128 || !valid_chars || name.starts_with('.') || name.starts_with('-') {
+        return Err(crate::api::error::InvalidInput(format!(
+            "Invalid snapshot name '{}': use up to 128 characters from [A-Za-z0-9_.-], not starting with '.' or '-'",
+            name
+        )).into());
+    }
+
+    Ok(())
+}
+
+/// Check a private network's name and host name, both DNS labels
+pub fn validate_network(network: &NetworkConfig) -> Result<(), InvalidInput> {
+    for (field, value) in [("name", &network.name), ("hostname", &network.hostname)] {
+        let valid = !value.is_empty()
+            && value.len() <= 63
+            && value.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+            && !value.starts_with('-')
+            && !value.ends_with('-');
+        if !valid {
+            return Err(InvalidInput(format!(
+                "Invalid network {} {:?}: use up to 63 characters from [a-z0-9-], not starting or ending with '-'",
+                field, value
+            )));
+        }
+    }
+    Ok(())
 }
 
 impl Sandbox {
-    pub fn new(request: SandboxRequest, _backend_type: SandboxBackendType) -> Self {
+    pub fn new(request: SandboxRequest, backend_type: SandboxBackendType) -> Self {
         Self {
             id: request.id.clone(),
             request,
@@ -78,10 +328,20 @@ impl Sandbox {
             status: SandboxStatus::Created,
             container_id: None,
             dev_server_port: None,
+            backend_type,
+            docker_host: None,
+            resume_status: None,
+            finished_at: None,
+            termination_reason: None,
         }
     }
 
     pub fn to_info(&self) -> SandboxInfo {
+        self.to_info_at(DEFAULT_BASE_URL)
+    }
+
+    /// Info with the dev server URL under `base_url`, the service's public URL
+    pub fn to_info_at(&self, base_url: &str) -> SandboxInfo {
         SandboxInfo {
             id: self.id.clone(),
             status: format!("{:?}", self.status),
@@ -89,6 +349,97 @@ impl Sandbox {
             created_at: self.created_at.to_rfc3339(),
             timeout_ms: self.request.timeout_ms,
             memory_limit_mb: self.request.memory_limit_mb,
+            is_persistent: self.is_persistent(),
+            dev_server_url: if self.request.dev_server.unwrap_or(false) && self.is_persistent() {
+                Some(format!("{}/proxy/{}/", base_url, self.id))
+            } else {
+                None
+            },
+            allocated_port: self.dev_server_port,
+            backend: Some(self.backend_type.name().to_string()),
+            docker_host: self.docker_host.clone(),
+            labels: self.request.labels.clone(),
+            finished_at: self.finished_at.map(|time| time.to_rfc3339()),
+            termination_reason: self.termination_reason,
         }
     }
-}
\ No newline at end of file
+
+    pub fn is_persistent(&self) -> bool {
+        matches!(self.request.mode, Some(SandboxMode::Persistent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_snapshot_name() {
+        assert!(validate_snapshot_name("before-upgrade_2.1").is_ok());
+        for invalid in ["", "../../etc/shadow", "..", ".hidden", "a/b", "-rf", "name with spaces"] {
+            assert!(validate_snapshot_name(invalid).is_err(), "{:?} accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn test_writable_paths() {
+        let mut request: SandboxRequest = serde_json::from_value(serde_json::json!({
+            "id": "test", "runtime": "node", "code": "", "entry_point": null, "timeout_ms": 1000,
+            "memory_limit_mb": 128, "env_vars": {}, "files": null, "mode": "Persistent",
+            "install_deps": null, "dev_server": null, "from_snapshot": null,
+        }))
+        .unwrap();
+        assert_eq!(request.writable_paths().unwrap(), vec!["/tmp", SANDBOX_HOME]);
+
+        request.writable_paths = Some(vec!["/sandbox".to_string(), "/var/cache/".to_string()]);
+        assert_eq!(request.writable_paths().unwrap(), vec!["/var/cache"]);
+
+        for invalid in ["relative", "/", "/opt/../etc", "/proc/self"] {
+            request.writable_paths = Some(vec![invalid.to_string()]);
+            assert!(request.writable_paths().is_err(), "{} accepted", invalid);
+        }
+
+        request.writable_paths = None;
+        request.mode = Some(SandboxMode::OneShot);
+        assert_eq!(request.writable_paths().unwrap(), vec!["/tmp"]);
+        assert_eq!((request.disk_limit_mb(), request.max_processes()), (DEFAULT_DISK_LIMIT_MB, DEFAULT_MAX_PROCESSES));
+        request.runtime = "playwright@1.47.0".to_string();
+        assert_eq!(request.writable_paths().unwrap(), vec!["/tmp", SANDBOX_HOME]);
+        assert_eq!((request.disk_limit_mb(), request.max_processes()), (playwright::DISK_LIMIT_MB, playwright::MAX_PROCESSES));
+    }
+
+    #[test]
+    fn test_file_contents() {
+        let mut file = SandboxFile {
+            path: "logo.png".to_string(),
+            content: "\u{89}PNG".to_string(),
+            is_executable: None,
+            encoding: None,
+        };
+        assert_eq!(file_contents(&file).unwrap(), "\u{89}PNG".as_bytes());
+
+        file.encoding = Some(FileEncoding::Base64);
+        file.content = "iVBORw==".to_string();
+        assert_eq!(file_contents(&file).unwrap(), [0x89, b'P', b'N', b'G']);
+
+        file.content = "not base64!".to_string();
+        assert!(validate_files(Some(std::slice::from_ref(&file))).is_err());
+
+        file.encoding = None;
+        assert!(validate_files(Some(std::slice::from_ref(&file))).is_ok());
+        file.path = "assets/../../etc/passwd".to_string();
+        assert!(validate_files(Some(std::slice::from_ref(&file))).is_err());
+    }
+
+    #[test]
+    fn test_validate_network() {
+        let network = |name: &str, hostname: &str| NetworkConfig { name: name.to_string(), hostname: hostname.to_string() };
+        assert!(validate_network(&network("shop", "db")).is_ok());
+        assert!(validate_network(&network("shop-2", "api-v1")).is_ok());
+        assert!(validate_network(&network("Shop", "db")).is_err());
+        assert!(validate_network(&network("shop", "")).is_err());
+        assert!(validate_network(&network("shop", "-db")).is_err());
+        assert!(validate_network(&network("shop", "db.local")).is_err());
+        assert!(validate_network(&network(&"a".repeat(64), "db")).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Host directories mounted read-only into sandboxes, `[[sandbox.allowed_mounts]]` in the
+//! configuration
+//!
+//! Operators list the data sets sandboxes may see, such as models or corpora, under a
+//! name; requests pick them by that name in `mounts`, never by host path, so callers
+//! cannot reach anything else on the host. Mounts are read-only and Docker only.
+
+use serde::{Deserialize, Serialize};
+use voidrun_types::SandboxMount;
+
+use crate::api::error::InvalidInput;
+
+/// Parent of the mount points of data sets whose configuration names no target
+pub const DEFAULT_TARGET_DIR: &str = "/data";
+
+/// Paths mounts may not cover: the project directory and the virtual filesystems
+const RESERVED_TARGETS: &[&str] = &["/sandbox", "/dev", "/proc", "/sys"];
+
+/// A host directory sandboxes may mount
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowedMount {
+    /// Name requests pick the mount by
+    pub name: String,
+    /// Absolute path of the directory on the Docker host, on every host of a pool
+    pub source: String,
+    /// Where sandboxes see it; defaults to `/data/<name>`
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+impl AllowedMount {
+    /// Mount point when the request does not choose one
+    pub fn target(&self) -> String {
+        self.target.clone().unwrap_or_else(|| format!("{}/{}", DEFAULT_TARGET_DIR, self.name))
+    }
+
+    /// Problems with the entry, for configuration validation
+    pub fn check(&self) -> Result<(), String> {
+        if self.name.is_empty() || !self.name.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') {
+            return Err(format!("name {:?} must use only [A-Za-z0-9_-]", self.name));
+        }
+        if !is_clean_absolute(&self.source) || self.source == "/" {
+            return Err(format!("source {:?} must be an absolute path other than /", self.source));
+        }
+        check_target(&self.target()).map_err(|e| e.0)
+    }
+}
+
+/// A mount resolved for the backend, persisted with the sandbox's request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindMount {
+    pub source: String,
+    pub target: String,
+}
+
+impl BindMount {
+    /// Docker's `source:target:ro` form
+    pub fn bind(&self) -> String {
+        format!("{}:{}:ro", self.source, self.target)
+    }
+}
+
+/// Bind mounts for the `requested` mounts, each of which must be in `allowed`
+pub fn resolve(allowed: &[AllowedMount], requested: &[SandboxMount]) -> Result<Vec<BindMount>, InvalidInput> {
+    let mut mounts: Vec<BindMount> = Vec::new();
+    for mount in requested {
+        let Some(entry) = allowed.iter().find(|entry| entry.name == mount.name) else {
+            return Err(InvalidInput(if allowed.is_empty() {
+                "No mounts are allowed on this instance".to_string()
+            } else {
+                format!(
+                    "Mount {:?} is not allowed; available: {}",
+                    mount.name,
+                    allowed.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>().join(", ")
+                )
+            }));
+        };
+        let target = match &mount.target {
+            Some(target) => format!("/{}", target.trim_matches('/')),
+            None => entry.target(),
+        };
+        check_target(&target)?;
+        if mounts.iter().any(|mount| mount.target == target) {
+            return Err(InvalidInput(format!("Two mounts target {}", target)));
+        }
+        mounts.push(BindMount { source: entry.source.clone(), target });
+    }
+    Ok(mounts)
+}
+
+fn is_clean_absolute(path: &str) -> bool {
+    path.starts_with('/') && !path.split('/').any(|segment| segment == "..")
+}
+
+fn check_target(target: &str) -> Result<(), InvalidInput> {
+    let is_reserved = |reserved: &&str| target == *reserved || target.starts_with(&format!("{}/", reserved));
+    if !is_clean_absolute(target) || target.trim_matches('/').is_empty() || RESERVED_TARGETS.iter().any(is_reserved) {
+        return Err(InvalidInput(format!("Invalid mount target: {}", target)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let allowed = vec![
+            AllowedMount { name: "models".to_string(), source: "/srv/models".to_string(), target: None },
+            AllowedMount { name: "corpus".to_string(), source: "/srv/corpus".to_string(), target: Some("/corpus".to_string()) },
+        ];
+        let mount = |name: &str, target: Option<&str>| SandboxMount { name: name.to_string(), target: target.map(str::to_string) };
+
+        let mounts = resolve(&allowed, &[mount("models", None), mount("corpus", None)]).unwrap();
+        assert_eq!(mounts[0].bind(), "/srv/models:/data/models:ro");
+        assert_eq!(mounts[1].target, "/corpus");
+        assert_eq!(resolve(&allowed, &[mount("models", Some("opt/models/"))]).unwrap()[0].target, "/opt/models");
+
+        assert!(resolve(&allowed, &[mount("etc", None)]).is_err());
+        assert!(resolve(&allowed, &[mount("models", Some("/sandbox/models"))]).is_err());
+        assert!(resolve(&allowed, &[mount("models", Some("/data/../proc"))]).is_err());
+        assert!(resolve(&allowed, &[mount("models", Some("/x")), mount("corpus", Some("/x"))]).is_err());
+        assert!(resolve(&[], &[mount("models", None)]).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! What a sandbox's container labels keep of its request, to reattach the sandbox
+//! after a restart
+//!
+//! Only the settings the sandbox runs under are kept. The code, files and env vars it
+//! was created with are in the container already, would make labels megabytes large,
+//! and may hold secrets such as registry tokens in an `.npmrc`; a reattached sandbox
+//! runs the files of its project directory.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::mounts::BindMount;
+use super::{NetworkConfig, SandboxMode, SandboxRequest};
+use crate::security::SecurityProfile;
+
+/// Settings of a sandbox request, as persisted in container labels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedRequest {
+    pub id: String,
+    pub runtime: String,
+    pub entry_point: Option<String>,
+    pub timeout_ms: u64,
+    pub memory_limit_mb: u64,
+    pub mode: Option<SandboxMode>,
+    pub dev_server: Option<bool>,
+    pub port: Option<u16>,
+    pub disk_limit_mb: Option<u64>,
+    pub max_processes: Option<u64>,
+    pub security_profile: Option<SecurityProfile>,
+    pub writable_paths: Option<Vec<String>>,
+    pub gpus: Option<u32>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bind_mounts: Vec<BindMount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<serde_json::Value>,
+}
+
+impl From<&SandboxRequest> for PersistedRequest {
+    fn from(request: &SandboxRequest) -> Self {
+        Self {
+            id: request.id.clone(),
+            runtime: request.runtime.clone(),
+            entry_point: request.entry_point.clone(),
+            timeout_ms: request.timeout_ms,
+            memory_limit_mb: request.memory_limit_mb,
+            mode: request.mode.clone(),
+            dev_server: request.dev_server,
+            port: request.port,
+            disk_limit_mb: request.disk_limit_mb,
+            max_processes: request.max_processes,
+            security_profile: request.security_profile,
+            writable_paths: request.writable_paths.clone(),
+            gpus: request.gpus,
+            labels: request.labels.clone(),
+            bind_mounts: request.bind_mounts.clone(),
+            image: request.image.clone(),
+            network: request.network.clone(),
+            deployment: request.deployment.clone(),
+        }
+    }
+}
+
+impl PersistedRequest {
+    /// Request of the reattached sandbox, without code, files or env vars
+    pub fn into_request(self) -> SandboxRequest {
+        SandboxRequest {
+            id: self.id,
+            runtime: self.runtime,
+            code: String::new(),
+            entry_point: self.entry_point,
+            timeout_ms: self.timeout_ms,
+            memory_limit_mb: self.memory_limit_mb,
+            env_vars: HashMap::new(),
+            files: None,
+            mode: self.mode,
+            install_deps: None,
+            dev_server: self.dev_server,
+            from_snapshot: None,
+            port: self.port,
+            artifacts: None,
+            disk_limit_mb: self.disk_limit_mb,
+            max_processes: self.max_processes,
+            backend: None,
+            security_profile: self.security_profile,
+            writable_paths: self.writable_paths,
+            gpus: self.gpus,
+            labels: self.labels,
+            api_key: None,
+            install_proxy: None,
+            mounts: None,
+            bind_mounts: self.bind_mounts,
+            image: self.image,
+            registry_credential: None,
+            output: None,
+            network: self.network,
+            deployment: self.deployment,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::SandboxFile;
+
+    #[test]
+    fn test_persisted_request() {
+        let mut request: SandboxRequest = serde_json::from_value(serde_json::json!({
+            "id": "sandbox",
+            "runtime": "bun",
+            "code": "console.log('x'.repeat(1 << 20))",
+            "timeout_ms": 1000,
+            "memory_limit_mb": 128,
+            "env_vars": { "API_SECRET": "hunter2" },
+            "mode": "Persistent",
+            "port": 8080,
+        })).unwrap();
+        request.files = Some(vec![SandboxFile {
+            path: ".npmrc".to_string(),
+            content: "//npm.pkg.github.com/:_authToken=ghp_secret".to_string(),
+            is_executable: None,
+            encoding: None,
+        }]);
+
+        let json = serde_json::to_string(&PersistedRequest::from(&request)).unwrap();
+        for secret in ["repeat", "hunter2", "ghp_secret"] {
+            assert!(!json.contains(secret), "{} persisted", secret);
+        }
+        let restored = serde_json::from_str::<PersistedRequest>(&json).unwrap().into_request();
+        assert_eq!(restored.id, "sandbox");
+        assert_eq!(restored.app_port(), 8080);
+        assert!(matches!(restored.mode, Some(SandboxMode::Persistent)));
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use super::hosts::LOCAL_ADDRESS;
+use crate::coordination::{Coordinator, KEY_PREFIX};
+
+/// Default host port range published sandbox services are mapped into
+pub const DEFAULT_PORT_RANGE_START: u16 = 8080;
+pub const DEFAULT_PORT_RANGE_END: u16 = 8999;
+
+/// How long a port lease in the shared store outlives its last renewal
+pub const PORT_LEASE_TTL: Duration = Duration::from_secs(300);
+/// How often held port leases are renewed
+pub const PORT_LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Host port allocation for sandbox containers
+///
+/// Shared between the sandbox backend, which allocates a port when it publishes a
+/// service, and the proxy, which looks the port up to forward requests. With a
+/// coordinator, each port is also leased in the shared store, so instances publishing
+/// on the same Docker host skip each other's ports.
+///
+/// Backends of other Docker hosts allocate through handles from [`PortAllocator::on_host`],
+/// which record the host's address with each port. A port is only handed out once
+/// across all hosts.
+#[derive(Clone)]
+pub struct PortAllocator {
+    start_port: u16,
+    end_port: u16,
+    /// Address ports allocated through this handle are published on
+    host: String,
+    allocated_ports: Arc<RwLock<HashMap<String, Allocation>>>,
+    leases: Option<Arc<dyn Coordinator>>,
+}
+
+#[derive(Debug, Clone)]
+struct Allocation {
+    port: u16,
+    host: String,
+}
+
+impl std::fmt::Debug for PortAllocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortAllocator")
+            .field("start_port", &self.start_port)
+            .field("end_port", &self.end_port)
+            .field("host", &self.host)
+            .field("allocated_ports", &self.allocated_ports)
+            .field("shared", &self.leases.is_some())
+            .finish()
+    }
+}
+
+impl PortAllocator {
+    pub fn new(start_port: u16, end_port: u16) -> Self {
+        Self {
+            start_port,
+            end_port: end_port.max(start_port),
+            host: LOCAL_ADDRESS.to_string(),
+            allocated_ports: Arc::new(RwLock::new(HashMap::new())),
+            leases: None,
+        }
+    }
+
+    /// Handle for a backend publishing ports on the host at `address`, sharing this one's allocations
+    pub fn on_host(&self, address: &str) -> Self {
+        Self { host: address.to_string(), ..self.clone() }
+    }
+
+    /// Address ports allocated through this handle are published on
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn allocation(&self, port: u16) -> Allocation {
+        Allocation { port, host: self.host.clone() }
+    }
+
+    /// Lease ports in a store shared with other instances
+    pub fn with_coordinator(mut self, coordinator: Arc<dyn Coordinator>) -> Self {
+        self.leases = Some(coordinator);
+        self
+    }
+
+    fn lease_key(port: u16) -> String {
+        format!("{}port:{}", KEY_PREFIX, port)
+    }
+
+    /// Take the shared lease on `port` for a sandbox; true without a shared store
+    async fn lease(&self, sandbox_id: &str, port: u16) -> bool {
+        let Some(leases) = &self.leases else {
+            return true;
+        };
+        let key = Self::lease_key(port);
+        match leases.set_if_absent(&key, sandbox_id, PORT_LEASE_TTL).await {
+            Ok(true) => true,
+            // Held already, which is fine when the holder is this sandbox
+            Ok(false) => leases.renew_if(&key, sandbox_id, PORT_LEASE_TTL).await.unwrap_or(false),
+            Err(e) => {
+                warn!("Failed to lease port {} for sandbox {}: {}", port, sandbox_id, e);
+                false
+            }
+        }
+    }
+
+    /// Allocate a free host port for a sandbox
+    ///
+    /// Skips ports held by other sandboxes, leased by other instances or, on the local
+    /// host, bound by another process. Returns the existing port if the sandbox already
+    /// has one.
+    pub async fn allocate(&self, sandbox_id: &str) -> Option<u16> {
+        let mut allocated = self.allocated_ports.write().await;
+        if let Some(allocation) = allocated.get(sandbox_id) {
+            return Some(allocation.port);
+        }
+
+        let in_use: HashSet<u16> = allocated.values().map(|allocation| allocation.port).collect();
+        let is_local = self.host == LOCAL_ADDRESS;
+        for port in self.start_port..=self.end_port {
+            if in_use.contains(&port) || (is_local && !Self::is_bindable(port)) {
+                continue;
+            }
+            if self.lease(sandbox_id, port).await {
+                allocated.insert(sandbox_id.to_string(), self.allocation(port));
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// Record a port a sandbox already holds, e.g. one published before a restart
+    ///
+    /// Returns false if another sandbox, here or on another instance, holds the port.
+    pub async fn reserve(&self, sandbox_id: &str, port: u16) -> bool {
+        let mut allocated = self.allocated_ports.write().await;
+        if allocated.iter().any(|(id, held)| held.port == port && id != sandbox_id) {
+            return false;
+        }
+        if !self.lease(sandbox_id, port).await {
+            return false;
+        }
+        allocated.insert(sandbox_id.to_string(), self.allocation(port));
+        true
+    }
+
+    /// Return a sandbox's port to the pool
+    pub async fn release(&self, sandbox_id: &str) -> Option<u16> {
+        let mut allocated = self.allocated_ports.write().await;
+        let port = allocated.remove(sandbox_id)?.port;
+        if let Some(leases) = &self.leases {
+            if let Err(e) = leases.delete_if(&Self::lease_key(port), sandbox_id).await {
+                warn!("Failed to release the lease on port {}: {}; it expires on its own", port, e);
+            }
+        }
+        Some(port)
+    }
+
+    /// Extend the shared leases of every allocated port, retaking ones that lapsed
+    ///
+    /// Run every [`PORT_LEASE_RENEW_INTERVAL`] while a coordinator is set.
+    pub async fn renew_leases(&self) {
+        let Some(leases) = &self.leases else {
+            return;
+        };
+        let allocated: Vec<(String, u16)> = self.allocated_ports.read().await
+            .iter()
+            .map(|(id, allocation)| (id.clone(), allocation.port))
+            .collect();
+        for (sandbox_id, port) in allocated {
+            let key = Self::lease_key(port);
+            let renewed = match leases.renew_if(&key, &sandbox_id, PORT_LEASE_TTL).await {
+                Ok(true) => true,
+                Ok(false) => leases.set_if_absent(&key, &sandbox_id, PORT_LEASE_TTL).await.unwrap_or(false),
+                Err(e) => {
+                    warn!("Failed to renew the lease on port {}: {}", port, e);
+                    continue;
+                }
+            };
+            if !renewed {
+                warn!("Port {} of sandbox {} is leased by another instance", port, sandbox_id);
+            }
+        }
+    }
+
+    /// Address and port the sandbox's service is reached at
+    pub async fn get_target(&self, sandbox_id: &str) -> Option<(String, u16)> {
+        let allocated = self.allocated_ports.read().await;
+        allocated.get(sandbox_id).map(|allocation| (allocation.host.clone(), allocation.port))
+    }
+
+    pub fn range(&self) -> (u16, u16) {
+        (self.start_port, self.end_port)
+    }
+
+    fn is_bindable(port: u16) -> bool {
+        TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port))).is_ok()
+    }
+}
+
+impl Default for PortAllocator {
+    fn default() -> Self {
+        Self::new(DEFAULT_PORT_RANGE_START, DEFAULT_PORT_RANGE_END)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allocations_do_not_conflict() {
+        let ports = PortAllocator::new(41000, 41010);
+
+        let first = ports.allocate("a").await.unwrap();
+        let second = ports.allocate("b").await.unwrap();
+        assert_ne!(first, second);
+        assert_eq!(ports.allocate("a").await, Some(first));
+        assert_eq!(ports.get_target("b").await, Some((LOCAL_ADDRESS.to_string(), second)));
+
+        assert_eq!(ports.release("a").await, Some(first));
+        assert_eq!(ports.get_target("a").await, None);
+
+        assert!(!ports.reserve("c", second).await);
+        assert!(ports.reserve("c", first).await);
+        assert_ne!(ports.allocate("d").await, Some(first));
+
+        let remote = ports.on_host("10.0.0.5");
+        let port = remote.allocate("e").await.unwrap();
+        assert_eq!(ports.get_target("e").await, Some(("10.0.0.5".to_string(), port)));
+    }
+
+    #[tokio::test]
+    async fn test_shared_leases() {
+        let store: Arc<dyn Coordinator> = Arc::new(crate::coordination::LocalCoordinator::default());
+        let first = PortAllocator::new(41030, 41031).with_coordinator(store.clone());
+        let second = PortAllocator::new(41030, 41031).with_coordinator(store);
+
+        let port = first.allocate("a").await.unwrap();
+        assert_ne!(second.allocate("b").await, Some(port));
+        assert!(!second.reserve("c", port).await);
+
+        first.release("a").await;
+        assert!(second.reserve("c", port).await);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_range() {
+        let ports = PortAllocator::new(41020, 41020);
+
+        assert!(ports.allocate("a").await.is_some());
+        assert_eq!(ports.allocate("b").await, None);
+    }
+}
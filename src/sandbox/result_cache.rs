@@ -0,0 +1,152 @@
+//! Cache of one-shot execution results, `sandbox.result_cache_ttl_seconds`
+//!
+//! Results are addressed by the SHA-256 of what decides them: runtime, code, files, env
+//! vars and limits. An identical request within the TTL gets the stored result back
+//! without a sandbox being started, whichever API key sent it. Only executions that
+//! completed are stored; timeouts, memory kills, cancels and setup failures run again.
+
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{SandboxMode, SandboxRequest, SandboxResponse, TerminationReason};
+
+/// Results kept; the least recently used goes first
+pub const MAX_ENTRIES: usize = 500;
+/// Results with more output than this are not stored
+pub const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+/// Request fields that do not change what the program prints
+const IGNORED_FIELDS: [&str; 5] = ["id", "mode", "labels", "deployment", "port"];
+
+#[derive(Debug)]
+pub struct ResultCache {
+    entries: Mutex<LruCache<String, (SandboxResponse, Instant)>>,
+    ttl: Duration,
+}
+
+impl ResultCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_ENTRIES).unwrap_or(NonZeroUsize::MIN))),
+            ttl,
+        }
+    }
+
+    /// Address of the request's result, or None when it must run: with artifacts to
+    /// collect, a dev server or a snapshot there is more to the execution than its output
+    pub fn key(request: &SandboxRequest) -> Option<String> {
+        if request.artifacts.as_ref().is_some_and(|patterns| !patterns.is_empty())
+            || request.dev_server == Some(true)
+            || request.from_snapshot.is_some()
+        {
+            return None;
+        }
+        // Maps serialize with sorted keys, so equal env vars give equal keys
+        let mut value = serde_json::to_value(request).ok()?;
+        let fields = value.as_object_mut()?;
+        for field in IGNORED_FIELDS {
+            fields.remove(field);
+        }
+        // Checking the code prints something else than running it
+        if matches!(request.mode, Some(SandboxMode::Check)) {
+            fields.insert("check".to_string(), true.into());
+        }
+        let digest = Sha256::digest(value.to_string().as_bytes());
+        Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// A stored result younger than the TTL
+    pub fn get(&self, key: &str) -> Option<SandboxResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((response, stored_at)) if stored_at.elapsed() < self.ttl => Some(response.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store a result if it is worth repeating
+    pub fn insert(&self, key: String, response: &SandboxResponse) {
+        let completed = response.termination_reason == Some(TerminationReason::Completed) && !response.canceled;
+        if completed && response.stdout.len() + response.stderr.len() <= MAX_OUTPUT_BYTES {
+            self.entries.lock().unwrap().put(key, (response.clone(), Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: &str, env: &[(&str, &str)]) -> SandboxRequest {
+        let json = serde_json::json!({
+            "id": id,
+            "runtime": "node",
+            "code": "console.log(process.env.A)",
+            "entry_point": null,
+            "timeout_ms": 30000,
+            "memory_limit_mb": 512,
+            "env_vars": env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<std::collections::HashMap<_, _>>(),
+            "files": null,
+            "mode": null,
+            "install_deps": null,
+            "dev_server": null,
+            "from_snapshot": null,
+            "port": null,
+            "artifacts": null,
+            "disk_limit_mb": null,
+            "max_processes": null,
+            "backend": null,
+            "security_profile": null,
+            "writable_paths": null,
+            "gpus": null,
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn response(reason: TerminationReason) -> SandboxResponse {
+        SandboxResponse {
+            success: true,
+            stdout: "1\n".to_string(),
+            stderr: String::new(),
+            exit_code: Some(0),
+            execution_time_ms: 40,
+            is_running: Some(false),
+            dev_server_url: None,
+            canceled: false,
+            usage: None,
+            termination_reason: Some(reason),
+            execution_id: None,
+            cost: None,
+            diagnostics: None,
+        }
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let key = ResultCache::key(&request("a", &[("A", "1"), ("B", "2")])).unwrap();
+        assert_eq!(ResultCache::key(&request("b", &[("B", "2"), ("A", "1")])).unwrap(), key);
+        assert_ne!(ResultCache::key(&request("a", &[("A", "2")])).unwrap(), key);
+        let mut with_artifacts = request("a", &[]);
+        with_artifacts.artifacts = Some(vec!["out.json".to_string()]);
+        assert!(ResultCache::key(&with_artifacts).is_none());
+        let mut check = request("a", &[("A", "1"), ("B", "2")]);
+        check.mode = Some(SandboxMode::Check);
+        assert_ne!(ResultCache::key(&check).unwrap(), key);
+
+        let cache = ResultCache::new(Duration::from_secs(60));
+        cache.insert("timeout".to_string(), &response(TerminationReason::Timeout));
+        assert!(cache.get("timeout").is_none());
+        cache.insert(key.clone(), &response(TerminationReason::Completed));
+        assert_eq!(cache.get(&key).unwrap().stdout, "1\n");
+
+        let expired = ResultCache::new(Duration::ZERO);
+        expired.insert(key.clone(), &response(TerminationReason::Completed));
+        assert!(expired.get(&key).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+//! REPL sessions, `POST /sessions` and `POST /sessions/:id/eval`
+//!
+//! A session is a persistent sandbox whose dev server is a driver script: a small HTTP
+//! server evaluating code in one `vm` context, so variables, functions and required
+//! modules live on from one evaluation to the next. The service posts each evaluation to
+//! the driver and hands back what it answers. Evaluations of a session run one at a time,
+//! like executions of a sandbox.
+
+use anyhow::{Context, Result};
+use std::sync::OnceLock;
+use std::time::Duration;
+use voidrun_types::{CreateSandboxRequest, CreateSessionRequest, EvalRequest, EvalResult, SandboxFile};
+
+use super::SandboxRequest;
+use crate::api::error::InvalidInput;
+use crate::runtime;
+
+/// Where the driver is written, relative to the project root
+pub const DRIVER_FILE: &str = ".voidrun-repl.cjs";
+/// How long a new session's driver has to start answering
+pub const START_TIMEOUT: Duration = Duration::from_secs(30);
+/// Path the driver answers readiness probes on
+pub const HEALTH_PATH: &str = "/health";
+/// Time an evaluation's answer may take beyond its own time limit
+const ANSWER_GRACE: Duration = Duration::from_secs(5);
+
+/// Evaluates each posted cell in a context kept for the session's lifetime. Synchronous
+/// code is stopped at the time limit by `vm`; a returned promise is awaited up to it.
+const DRIVER: &str = r#"const http = require("http");
+const util = require("util");
+const vm = require("vm");
+
+let stdout = "";
+let stderr = "";
+let outputs = [];
+const print = (toStderr) => (...args) => {
+  const line = util.format(...args) + "\n";
+  if (toStderr) stderr += line; else stdout += line;
+};
+const sessionConsole = {
+  log: print(false), info: print(false), debug: print(false), table: print(false),
+  dir: (value) => { stdout += util.inspect(value) + "\n"; },
+  warn: print(true), error: print(true), trace: print(true),
+};
+
+function bundle(value) {
+  const data = { "text/plain": util.inspect(value, { depth: 4 }) };
+  if (typeof value !== "function" && typeof value !== "symbol") {
+    try {
+      const json = JSON.stringify(value);
+      if (json !== undefined) data["application/json"] = JSON.parse(json);
+    } catch (e) {
+      // Circular or BigInt values only have their text form
+    }
+  }
+  return data;
+}
+
+function display(value, mimeType) {
+  if (mimeType) outputs.push({ [mimeType]: value });
+  else outputs.push(typeof value === "string" ? { "text/plain": value } : bundle(value));
+}
+
+const context = vm.createContext({
+  console: sessionConsole, display, require, process, Buffer, URL, URLSearchParams, TextEncoder, TextDecoder,
+  fetch: globalThis.fetch, queueMicrotask, setTimeout, clearTimeout, setInterval, clearInterval, setImmediate, clearImmediate,
+});
+
+function withTimeout(promise, ms) {
+  let timer;
+  const timeout = new Promise((_, reject) => {
+    timer = setTimeout(() => reject(new Error(`Evaluation timed out after ${ms}ms`)), ms);
+  });
+  return Promise.race([promise, timeout]).finally(() => clearTimeout(timer));
+}
+
+async function evaluate(code, timeout) {
+  let value;
+  try {
+    value = vm.runInContext(code, context, { filename: "cell", timeout });
+  } catch (e) {
+    // Top-level await needs an async function, whose declarations stay in the cell
+    if (!e || e.name !== "SyntaxError" || !/await/.test(e.message)) throw e;
+    value = vm.runInContext(`(async () => {\n${code}\n})()`, context, { filename: "cell", timeout });
+  }
+  if (value && typeof value.then === "function") value = await withTimeout(value, timeout);
+  return value;
+}
+
+http.createServer((req, res) => {
+  if (req.method === "GET" && req.url === "/health") return res.end("ok");
+  if (req.method !== "POST" || req.url !== "/eval") {
+    res.statusCode = 404;
+    return res.end();
+  }
+  let body = "";
+  req.on("data", (chunk) => { body += chunk; });
+  req.on("end", async () => {
+    const { code, timeout_ms } = JSON.parse(body);
+    stdout = "";
+    stderr = "";
+    outputs = [];
+    const started = Date.now();
+    const result = { success: true };
+    try {
+      const value = await evaluate(code, timeout_ms);
+      if (value !== undefined) result.result = bundle(value);
+    } catch (e) {
+      result.success = false;
+      result.error = typeof e === "object" && e !== null
+        ? { name: String(e.name || "Error"), message: String(e.message ?? e), stack: e.stack }
+        : { name: "Error", message: String(e) };
+    }
+    Object.assign(result, { stdout, stderr, outputs, execution_time_ms: Date.now() - started });
+    res.setHeader("Content-Type", "application/json");
+    res.end(JSON.stringify(result));
+  });
+}).listen(Number(process.env.PORT) || 3000, "0.0.0.0");
+"#;
+
+/// Sandbox running a session's driver as its dev server
+pub fn sandbox_request(request: CreateSessionRequest) -> Result<CreateSandboxRequest> {
+    let runtime = request.runtime.unwrap_or_else(|| "node".to_string());
+    let entry_point = match runtime::name(&runtime) {
+        "node" | "nodejs" => format!("node {}", DRIVER_FILE),
+        "bun" => format!("bun run {}", DRIVER_FILE),
+        _ => return Err(InvalidInput(format!("Sessions run on node or bun, not {}", runtime)).into()),
+    };
+    let mut files = request.files.unwrap_or_default();
+    files.push(SandboxFile {
+        path: DRIVER_FILE.to_string(),
+        content: DRIVER.to_string(),
+        is_executable: None,
+        encoding: None,
+    });
+    Ok(CreateSandboxRequest {
+        runtime,
+        entry_point: Some(entry_point),
+        timeout_ms: request.timeout_ms,
+        memory_limit_mb: request.memory_limit_mb,
+        env_vars: request.env_vars,
+        files: Some(files),
+        mode: Some("persistent".to_string()),
+        dev_server: Some(true),
+        labels: request.labels,
+        // Drivers run as dev servers, which only the Docker backend serves
+        backend: Some("docker".to_string()),
+        ..Default::default()
+    })
+}
+
+/// Whether the sandbox is a session's
+pub fn is_session(request: &SandboxRequest) -> bool {
+    request.dev_server == Some(true) && request.entry_point.as_deref().is_some_and(|entry| entry.ends_with(DRIVER_FILE))
+}
+
+/// Post an evaluation to the driver listening at `host`:`port`
+pub async fn eval(host: &str, port: u16, request: &EvalRequest) -> Result<EvalResult> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let timeout_ms = request.timeout_ms.unwrap_or_default();
+    let response = CLIENT.get_or_init(reqwest::Client::new)
+        .post(format!("http://{}:{}/eval", host, port))
+        .json(request)
+        .timeout(Duration::from_millis(timeout_ms) + ANSWER_GRACE)
+        .send()
+        .await
+        .context("Session did not answer")?
+        .error_for_status()
+        .context("Session refused the evaluation")?;
+    response.json().await.context("Session answered with an invalid result")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_request() {
+        let request = sandbox_request(CreateSessionRequest { runtime: Some("bun@1.1".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(request.entry_point.as_deref(), Some("bun run .voidrun-repl.cjs"));
+        assert_eq!(request.files.as_ref().unwrap()[0].path, DRIVER_FILE);
+
+        let mut sandbox: SandboxRequest = serde_json::from_value(serde_json::json!({
+            "id": "s1",
+            "runtime": request.runtime,
+            "code": "",
+            "entry_point": request.entry_point,
+            "dev_server": true,
+            "timeout_ms": 30000,
+            "memory_limit_mb": 512,
+            "env_vars": {},
+        })).unwrap();
+        assert!(is_session(&sandbox));
+        sandbox.entry_point = Some("node server.js".to_string());
+        assert!(!is_session(&sandbox));
+
+        assert!(sandbox_request(CreateSessionRequest { runtime: Some("rust".to_string()), ..Default::default() }).is_err());
+    }
+}
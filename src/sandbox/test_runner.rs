@@ -0,0 +1,258 @@
+//! Test runs in sandboxes, `POST /sandbox/:id/test`
+//!
+//! The project's test command runs in the sandbox like an execution, and the tests are
+//! parsed from what it printed: JUnit XML and TAP, which most test runners can report
+//! in, and the default output of `bun test` and `cargo test`. The first format found in
+//! the output wins.
+
+use voidrun_types::{TestCase, TestFormat, TestStatus};
+
+use crate::runtime::{self, rust};
+
+/// Test command of projects on `runtime` that do not name their own
+pub fn default_command(runtime: &str) -> Option<&'static str> {
+    match runtime::name(runtime) {
+        "bun" => Some("bun test"),
+        "node" | "nodejs" | "typescript" | "ts" => Some("npm test"),
+        rust::RUNTIME => Some("cargo test"),
+        _ => None,
+    }
+}
+
+type Parser = fn(&str) -> Vec<TestCase>;
+
+/// Tests reported in a test run's output, and the format they were reported in
+pub fn parse(stdout: &str, stderr: &str) -> Option<(TestFormat, Vec<TestCase>)> {
+    let output = format!("{}\n{}", stdout, stderr);
+    let parsers: [(TestFormat, Parser); 4] = [
+        (TestFormat::Junit, parse_junit),
+        (TestFormat::Tap, parse_tap),
+        (TestFormat::Bun, parse_bun),
+        (TestFormat::Cargo, parse_cargo),
+    ];
+    parsers.iter().find_map(|(format, parse)| {
+        let tests = parse(&output);
+        (!tests.is_empty()).then_some((*format, tests))
+    })
+}
+
+fn test(name: &str, suite: Option<String>, status: TestStatus) -> TestCase {
+    TestCase {
+        name: name.trim().to_string(),
+        suite: suite.filter(|suite| !suite.is_empty()),
+        status,
+        duration_ms: None,
+        message: None,
+    }
+}
+
+/// `<testcase name="adds" classname="math" time="0.002">`, with a `<failure>`, `<error>`
+/// or `<skipped>` child when it did not pass
+fn parse_junit(output: &str) -> Vec<TestCase> {
+    let mut tests: Vec<TestCase> = Vec::new();
+    let mut suites: Vec<String> = Vec::new();
+    let mut rest = output;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let (name, attributes) = tag.trim_end_matches('/').split_once(char::is_whitespace).unwrap_or((tag, ""));
+        match name {
+            "testsuite" if !tag.ends_with('/') => suites.push(attribute(attributes, "name").unwrap_or_default()),
+            "/testsuite" => {
+                suites.pop();
+            }
+            "testcase" => {
+                let suite = attribute(attributes, "classname").or_else(|| suites.last().cloned());
+                let mut case = test(&attribute(attributes, "name").unwrap_or_default(), suite, TestStatus::Passed);
+                case.duration_ms = attribute(attributes, "time").and_then(|time| time.parse::<f64>().ok()).map(|seconds| seconds * 1000.0);
+                tests.push(case);
+            }
+            "failure" | "error" | "skipped" => {
+                if let Some(case) = tests.last_mut() {
+                    case.status = if name == "skipped" { TestStatus::Skipped } else { TestStatus::Failed };
+                    case.message = attribute(attributes, "message");
+                }
+            }
+            _ => {}
+        }
+    }
+    tests
+}
+
+/// Value of the XML attribute `name`, unescaped
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let prefix = format!("{}=\"", name);
+    let start = attributes.match_indices(&prefix)
+        .find(|(index, _)| *index == 0 || attributes[..*index].ends_with(char::is_whitespace))?.0 + prefix.len();
+    let value = &attributes[start..start + attributes[start..].find('"')?];
+    Some(
+        value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&"),
+    )
+}
+
+/// `ok 1 - adds`, `not ok 2 - divides # TODO`, with subtests indented before the test
+/// containing them and the duration and error in a YAML block after each test:
+///
+/// ```text
+/// # Subtest: math
+///     ok 1 - adds
+///       ---
+///       duration_ms: 0.41
+///       ...
+/// ok 1 - math
+/// ```
+fn parse_tap(output: &str) -> Vec<TestCase> {
+    let mut tests: Vec<(usize, TestCase)> = Vec::new();
+    // Whether YAML lines describe the last test in `tests`, rather than a suite
+    let mut in_test = false;
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let (status, rest) = if let Some(rest) = trimmed.strip_prefix("not ok ") {
+            (TestStatus::Failed, rest)
+        } else if let Some(rest) = trimmed.strip_prefix("ok ") {
+            (TestStatus::Passed, rest)
+        } else {
+            if let (true, Some((_, case))) = (in_test, tests.last_mut()) {
+                if let Some(duration) = trimmed.strip_prefix("duration_ms: ") {
+                    case.duration_ms = duration.parse().ok();
+                } else if let Some(error) = trimmed.strip_prefix("error: ").filter(|_| case.status == TestStatus::Failed) {
+                    case.message = Some(error.trim_matches(|c| c == '\'' || c == '"').to_string());
+                }
+            }
+            continue;
+        };
+        let rest = rest.split_once(' ').map(|(_, rest)| rest).unwrap_or_default();
+        let (description, directive) = rest.split_once(" # ").unwrap_or((rest, ""));
+        let name = description.strip_prefix("- ").unwrap_or(description);
+        // A test following more indented ones is the suite they ran in, at its level from then on
+        if tests.last().is_some_and(|(last_indent, _)| *last_indent > indent) {
+            for (child_indent, case) in tests.iter_mut().rev().take_while(|(child_indent, _)| *child_indent > indent) {
+                case.suite.get_or_insert_with(|| name.trim().to_string());
+                *child_indent = indent;
+            }
+            in_test = false;
+            continue;
+        }
+        let mut case = test(name, None, status);
+        let directive = directive.trim();
+        if let Some(keyword) = directive.get(..4).filter(|keyword| ["SKIP", "TODO"].contains(&keyword.to_ascii_uppercase().as_str())) {
+            case.status = TestStatus::Skipped;
+            case.message = Some(directive[keyword.len()..].trim().to_string()).filter(|reason| !reason.is_empty());
+        }
+        tests.push((indent, case));
+        in_test = true;
+    }
+    tests.into_iter().map(|(_, case)| case).collect()
+}
+
+/// `(pass) math > adds [0.12ms]`, `(fail) ...`, `(skip) ...` and `(todo) ...`
+fn parse_bun(output: &str) -> Vec<TestCase> {
+    output.lines().filter_map(|line| {
+        let (status, rest) = line.trim_start().strip_prefix('(')?.split_once(") ")?;
+        let status = match status {
+            "pass" => TestStatus::Passed,
+            "fail" => TestStatus::Failed,
+            "skip" | "todo" => TestStatus::Skipped,
+            _ => return None,
+        };
+        let (rest, duration_ms) = match rest.trim_end().strip_suffix(']').and_then(|rest| rest.rsplit_once(" [")) {
+            Some((rest, duration)) => (rest, parse_duration(duration)),
+            None => (rest, None),
+        };
+        let (suite, name) = match rest.rsplit_once(" > ") {
+            Some((suite, name)) => (Some(suite.to_string()), name),
+            None => (None, rest),
+        };
+        Some(TestCase { duration_ms, ..test(name, suite, status) })
+    }).collect()
+}
+
+/// `1.23ms` or `1.23s`, in milliseconds
+fn parse_duration(duration: &str) -> Option<f64> {
+    match duration.strip_suffix("ms") {
+        Some(ms) => ms.parse().ok(),
+        None => duration.strip_suffix('s')?.parse::<f64>().ok().map(|seconds| seconds * 1000.0),
+    }
+}
+
+/// `test math::adds ... ok`, `... FAILED` and `... ignored`
+fn parse_cargo(output: &str) -> Vec<TestCase> {
+    output.lines().filter_map(|line| {
+        let (path, result) = line.strip_prefix("test ")?.rsplit_once(" ... ")?;
+        let status = match result.split([',', ' ']).next()? {
+            "ok" => TestStatus::Passed,
+            "FAILED" => TestStatus::Failed,
+            "ignored" => TestStatus::Skipped,
+            _ => return None,
+        };
+        let (suite, name) = match path.rsplit_once("::") {
+            Some((suite, name)) => (Some(suite.to_string()), name),
+            None => (None, path),
+        };
+        Some(test(name, suite, status))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str, suite: Option<&str>, status: TestStatus, duration_ms: Option<f64>, message: Option<&str>) -> TestCase {
+        TestCase {
+            name: name.to_string(),
+            suite: suite.map(str::to_string),
+            status,
+            duration_ms,
+            message: message.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let junit = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="math.test.ts" tests="3">
+    <testcase name="adds" classname="math" time="0.002" />
+    <testcase name="divides" classname="math" time="0.5">
+      <failure message="expected 2 &lt; 1" type="AssertionError" />
+    </testcase>
+    <testcase name="rounds" time="0">
+      <skipped />
+    </testcase>
+  </testsuite>
+</testsuites>"#;
+        assert_eq!(parse(junit, ""), Some((TestFormat::Junit, vec![
+            case("adds", Some("math"), TestStatus::Passed, Some(2.0), None),
+            case("divides", Some("math"), TestStatus::Failed, Some(500.0), Some("expected 2 < 1")),
+            case("rounds", Some("math.test.ts"), TestStatus::Skipped, Some(0.0), None),
+        ])));
+
+        let tap = "TAP version 13\n# Subtest: math\n    ok 1 - adds\n      ---\n      duration_ms: 0.41\n      ...\n    \
+                   not ok 2 - divides\n      ---\n      duration_ms: 1.5\n      error: 'expected 2'\n      ...\n    1..2\n\
+                   not ok 1 - math\n  ---\n  duration_ms: 3.2\n  ...\nok 2 - rounds # SKIP not yet\n1..2\n";
+        assert_eq!(parse(tap, ""), Some((TestFormat::Tap, vec![
+            case("adds", Some("math"), TestStatus::Passed, Some(0.41), None),
+            case("divides", Some("math"), TestStatus::Failed, Some(1.5), Some("expected 2")),
+            case("rounds", None, TestStatus::Skipped, None, Some("not yet")),
+        ])));
+
+        let bun = "math.test.ts:\n(pass) math > adds [0.12ms]\nerror: expect(received).toBe(expected)\n(fail) divides [1.00s]\n(skip) rounds\n";
+        assert_eq!(parse("bun test v1.1.0", bun), Some((TestFormat::Bun, vec![
+            case("adds", Some("math"), TestStatus::Passed, Some(0.12), None),
+            case("divides", None, TestStatus::Failed, Some(1000.0), None),
+            case("rounds", None, TestStatus::Skipped, None, None),
+        ])));
+
+        let cargo = "running 2 tests\ntest math::adds ... ok\ntest rounds ... ignored, slow\n\ntest result: ok. 1 passed; 0 failed; 1 ignored\n";
+        assert_eq!(parse(cargo, ""), Some((TestFormat::Cargo, vec![
+            case("adds", Some("math"), TestStatus::Passed, None, None),
+            case("rounds", None, TestStatus::Skipped, None, None),
+        ])));
+
+        assert_eq!(parse("npm ERR! Missing script: \"test\"", ""), None);
+    }
+}
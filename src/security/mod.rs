@@ -0,0 +1,96 @@
+//! Restrictions applied to sandboxed programs
+//!
+//! A request picks a [`SecurityProfile`] by name; each backend turns it into what it
+//! enforces. Only the nsjail backend applies the profiles today, as seccomp filters
+//! from [`seccomp`]; Docker containers keep Docker's default seccomp profile and are
+//! hardened by the operator's [`ContainerSecurity`] instead.
+
+use serde::{Deserialize, Serialize};
+
+pub mod seccomp;
+
+/// Named set of restrictions a sandbox runs under
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityProfile {
+    /// Blocks kernel administration, other processes' memory, debugging and io_uring,
+    /// and for runtimes without a JIT, writable executable memory
+    ///
+    /// Programs that trace themselves, profile with perf or need io_uring should use
+    /// [`SecurityProfile::Permissive`].
+    #[default]
+    Strict,
+    /// Blocks kernel administration only
+    ///
+    /// For debuggers, profilers and io_uring. Programs it still breaks, such as ones
+    /// that mount filesystems, cannot run in a sandbox at all.
+    Permissive,
+    /// No seccomp filter; the sandbox is confined by its namespaces and limits alone
+    ///
+    /// Meant for diagnosing whether a failure comes from the filter, not for regular use.
+    Unconfined,
+}
+
+impl SecurityProfile {
+    pub const ALL: &'static [SecurityProfile] = &[Self::Strict, Self::Permissive, Self::Unconfined];
+
+    /// Name used in requests
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Permissive => "permissive",
+            Self::Unconfined => "unconfined",
+        }
+    }
+}
+
+impl std::str::FromStr for SecurityProfile {
+    type Err = crate::api::error::InvalidInput;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|profile| profile.name() == name.to_lowercase())
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|profile| profile.name()).collect();
+                crate::api::error::InvalidInput(format!(
+                    "Unknown security profile: {} (expected one of {})",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// User containers run as when the configuration does not set one: nobody, like nsjail sandboxes
+pub const DEFAULT_CONTAINER_USER: &str = "65534:65534";
+
+/// Privileges of Docker sandbox containers, `[sandbox.security]` in the configuration
+///
+/// nsjail sandboxes already run as nobody without capabilities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerSecurity {
+    /// `uid:gid` (or a user name the images know) container processes run as
+    pub user: String,
+    /// Keep setuid binaries and file capabilities from raising privileges
+    pub no_new_privileges: bool,
+    /// Capabilities added back after all of them are dropped, e.g. `NET_BIND_SERVICE`
+    pub capabilities: Vec<String>,
+    /// Refuse a Docker daemon that does not remap container users to an unprivileged
+    /// host range (`dockerd --userns-remap`)
+    pub userns_remap: bool,
+}
+
+impl Default for ContainerSecurity {
+    fn default() -> Self {
+        Self {
+            user: DEFAULT_CONTAINER_USER.to_string(),
+            no_new_privileges: true,
+            capabilities: Vec::new(),
+            userns_remap: false,
+        }
+    }
+}
+
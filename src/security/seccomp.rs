@@ -0,0 +1,68 @@
+//! Seccomp policies of the security profiles, in nsjail's Kafel language
+//!
+//! Blocked syscalls fail with EPERM rather than killing the program, so runtimes that
+//! probe for a feature (libuv tries io_uring, for instance) fall back to another way.
+
+use super::SecurityProfile;
+use crate::runtime::{self, bash, rust};
+
+/// Loading kernel code, mounting, entering namespaces, changing the clock and the like
+const KERNEL_ADMIN: &[&str] = &[
+    "acct", "add_key", "bpf", "clock_adjtime", "clock_settime", "delete_module", "finit_module",
+    "fsconfig", "fsmount", "fsopen", "fspick", "init_module", "ioperm", "iopl", "kexec_file_load",
+    "kexec_load", "keyctl", "lookup_dcookie", "mount", "move_mount", "name_to_handle_at",
+    "open_by_handle_at", "open_tree", "pivot_root", "quotactl", "reboot", "request_key", "setns",
+    "settimeofday", "swapoff", "swapon", "syslog", "umount2", "unshare", "vhangup",
+];
+
+/// Reading other processes, debugging and interfaces with a history of kernel exploits
+const INTROSPECTION: &[&str] = &[
+    "io_uring_enter", "io_uring_register", "io_uring_setup", "kcmp", "perf_event_open",
+    "process_vm_readv", "process_vm_writev", "ptrace", "userfaultfd",
+];
+
+/// Mapping memory both writable and executable, which only JIT compilers need
+const WRITABLE_EXECUTABLE: &str = "mmap(addr, len, prot) { (prot & 0x6) == 0x6 }, \
+    mprotect(addr, len, prot) { (prot & 0x6) == 0x6 }";
+
+/// Whether the runtime compiles code at run time into writable executable memory
+fn has_jit(runtime_name: &str) -> bool {
+    !matches!(runtime::name(runtime_name), bash::RUNTIME | rust::RUNTIME)
+}
+
+/// Kafel policy enforcing `profile` on a program of `runtime_name`; `None` runs it unfiltered
+pub fn policy(profile: SecurityProfile, runtime_name: &str) -> Option<String> {
+    let mut denied: Vec<String> = match profile {
+        SecurityProfile::Unconfined => return None,
+        SecurityProfile::Permissive => KERNEL_ADMIN.iter().map(|name| name.to_string()).collect(),
+        SecurityProfile::Strict => KERNEL_ADMIN.iter().chain(INTROSPECTION).map(|name| name.to_string()).collect(),
+    };
+    if profile == SecurityProfile::Strict && !has_jit(runtime_name) {
+        denied.push(WRITABLE_EXECUTABLE.to_string());
+    }
+
+    Some(format!(
+        "POLICY voidrun {{ ERRNO(1) {{ {} }} }} USE voidrun DEFAULT ALLOW",
+        denied.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        assert!(policy(SecurityProfile::Unconfined, "node").is_none());
+
+        let node = policy(SecurityProfile::Strict, "node@20").unwrap();
+        assert!(node.contains("ptrace") && node.contains("mount"));
+        assert!(!node.contains("mprotect"));
+        assert!(policy(SecurityProfile::Strict, bash::RUNTIME).unwrap().contains("mprotect"));
+
+        let permissive = policy(SecurityProfile::Permissive, bash::RUNTIME).unwrap();
+        assert!(permissive.contains("mount") && !permissive.contains("ptrace"));
+        assert_eq!("STRICT".parse::<SecurityProfile>().unwrap(), SecurityProfile::Strict);
+        assert!("lax".parse::<SecurityProfile>().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Built-in starter projects that can be deployed with `POST /faas/deploy?template=<name>`
+
+use voidrun_types::{DeploymentRequest, SandboxFile, TemplateInfo};
+
+/// Starter project deployed as a FaaS function
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub runtime: &'static str,
+    /// Memory the project needs to build and serve (default: the deployment default)
+    pub memory_limit_mb: Option<u32>,
+    /// Project files as (path, content); the package.json `dev` script starts the service,
+    /// except for static sites
+    pub files: &'static [(&'static str, &'static str)],
+}
+
+impl Template {
+    pub fn info(&self) -> TemplateInfo {
+        TemplateInfo {
+            name: self.name.to_string(),
+            description: self.description.to_string(),
+            runtime: self.runtime.to_string(),
+            files: self.files.iter().map(|(path, _)| path.to_string()).collect(),
+        }
+    }
+
+    /// Use the template as the base of a deployment; files and settings in the request win
+    pub fn apply(&self, request: &mut DeploymentRequest) {
+        if request.runtime.is_empty() {
+            request.runtime = self.runtime.to_string();
+        }
+        request.memory_limit_mb = request.memory_limit_mb.or(self.memory_limit_mb);
+
+        let overrides = request.files.take().unwrap_or_default();
+        let mut files: Vec<SandboxFile> = self.files.iter()
+            .filter(|(path, _)| !overrides.iter().any(|f| f.path == *path))
+            .map(|(path, content)| SandboxFile {
+                path: path.to_string(),
+                content: content.to_string(),
+                is_executable: None,
+                encoding: None,
+            })
+            .collect();
+        files.extend(overrides);
+        request.files = Some(files);
+    }
+}
+
+/// Look up a built-in template by name
+pub fn find(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
+
+pub fn all() -> &'static [Template] {
+    TEMPLATES
+}
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        name: "express-bun",
+        description: "Express server running on Bun",
+        runtime: "bun",
+        memory_limit_mb: None,
+        files: &[
+            ("package.json", r#"{
+  "name": "express-bun",
+  "version": "1.0.0",
+  "type": "module",
+  "scripts": {
+    "dev": "bun --watch index.js",
+    "start": "bun index.js"
+  },
+  "dependencies": {
+    "express": "^4.19.2"
+  }
+}
+"#),
+            ("index.js", r#"import express from 'express';
+
+const app = express();
+const port = process.env.PORT || 3000;
+
+app.use(express.json());
+
+app.get('/', (req, res) => {
+  res.json({ message: 'Hello from Express on Bun!' });
+});
+
+app.listen(port, '0.0.0.0', () => {
+  console.log(`Listening on port ${port}`);
+});
+"#),
+        ],
+    },
+    Template {
+        name: "express-node",
+        description: "Express server running on Node.js",
+        runtime: "node",
+        memory_limit_mb: None,
+        files: &[
+            ("package.json", r#"{
+  "name": "express-node",
+  "version": "1.0.0",
+  "main": "index.js",
+  "scripts": {
+    "dev": "node --watch index.js",
+    "start": "node index.js"
+  },
+  "dependencies": {
+    "express": "^4.19.2"
+  }
+}
+"#),
+            ("index.js", r#"const express = require('express');
+
+const app = express();
+const port = process.env.PORT || 3000;
+
+app.use(express.json());
+
+app.get('/', (req, res) => {
+  res.json({ message: 'Hello from Express on Node.js!' });
+});
+
+app.listen(port, '0.0.0.0', () => {
+  console.log(`Listening on port ${port}`);
+});
+"#),
+        ],
+    },
+    Template {
+        name: "hono",
+        description: "Hono server running on Bun",
+        runtime: "bun",
+        memory_limit_mb: None,
+        files: &[
+            ("package.json", r#"{
+  "name": "hono-bun",
+  "version": "1.0.0",
+  "type": "module",
+  "scripts": {
+    "dev": "bun --hot index.ts",
+    "start": "bun index.ts"
+  },
+  "dependencies": {
+    "hono": "^4.4.0"
+  }
+}
+"#),
+            ("index.ts", r#"import { Hono } from 'hono';
+
+const app = new Hono();
+
+app.get('/', (c) => c.json({ message: 'Hello from Hono!' }));
+
+export default {
+  port: Number(process.env.PORT) || 3000,
+  hostname: '0.0.0.0',
+  fetch: app.fetch,
+};
+"#),
+        ],
+    },
+    Template {
+        name: "next-dev",
+        description: "Next.js app served by the Next dev server",
+        runtime: "node",
+        memory_limit_mb: Some(1024),
+        files: &[
+            ("package.json", r#"{
+  "name": "next-dev",
+  "version": "1.0.0",
+  "private": true,
+  "scripts": {
+    "dev": "next dev -H 0.0.0.0 -p ${PORT:-3000}",
+    "build": "next build",
+    "start": "next start -H 0.0.0.0 -p ${PORT:-3000}"
+  },
+  "dependencies": {
+    "next": "^14.2.0",
+    "react": "^18.3.0",
+    "react-dom": "^18.3.0"
+  }
+}
+"#),
+            ("pages/index.js", r#"export default function Home() {
+  return (
+    <main style={{ fontFamily: 'sans-serif', padding: '2rem' }}>
+      <h1>Hello from Next.js!</h1>
+      <p>Edit pages/index.js to get started.</p>
+    </main>
+  );
+}
+"#),
+        ],
+    },
+    Template {
+        name: "static-site",
+        description: "Static HTML site served without a sandbox",
+        runtime: "static",
+        memory_limit_mb: None,
+        files: &[
+            ("index.html", r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Static Site</title>
+  <link rel="stylesheet" href="style.css">
+</head>
+<body>
+  <h1>Hello from a static site!</h1>
+  <p>Add more files to the deployment to publish them.</p>
+</body>
+</html>
+"#),
+            ("style.css", r#"body {
+  font-family: sans-serif;
+  max-width: 40rem;
+  margin: 4rem auto;
+  padding: 0 1rem;
+}
+"#),
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_template() {
+        let mut request = DeploymentRequest {
+            files: Some(vec![SandboxFile {
+                path: "index.js".to_string(),
+                content: "// custom".to_string(),
+                is_executable: None,
+                encoding: None,
+            }]),
+            ..Default::default()
+        };
+        find("express-bun").unwrap().apply(&mut request);
+
+        assert_eq!(request.runtime, "bun");
+        let files = request.files.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files.iter().find(|f| f.path == "index.js").unwrap().content, "// custom");
+        assert!(find("missing").is_none());
+    }
+}
@@ -1,28 +1,29 @@
-use sandbox_service::sandbox::backend::{SandboxBackend, SandboxBackendType, create_backend};
-use sandbox_service::sandbox::{SandboxRequest, SandboxResponse};
-use std::collections::HashMap;
-use tempfile::TempDir;
+use sandbox_service::sandbox::backend::{SandboxBackendType, create_backend};
+use sandbox_service::sandbox::{PortAllocator, SandboxRequest, SandboxResponse};
 use uuid::Uuid;
 
+fn test_request(runtime: &str, code: &str) -> SandboxRequest {
+    serde_json::from_value(serde_json::json!({
+        "id": Uuid::new_v4().to_string(),
+        "runtime": runtime,
+        "code": code,
+        "timeout_ms": 5000,
+        "memory_limit_mb": 128,
+        "env_vars": {},
+    })).unwrap()
+}
+
 #[cfg(test)]
 mod nsjail_tests {
     use super::*;
 
     fn create_test_request(runtime: &str, code: &str) -> SandboxRequest {
-        SandboxRequest {
-            id: Uuid::new_v4().to_string(),
-            runtime: runtime.to_string(),
-            code: code.to_string(),
-            entry_point: None,
-            timeout_ms: 5000,
-            memory_limit_mb: 128,
-            env_vars: HashMap::new(),
-        }
+        test_request(runtime, code)
     }
 
     #[tokio::test]
     async fn test_nsjail_availability() {
-        let backend = create_backend(SandboxBackendType::Nsjail);
+        let backend = create_backend(SandboxBackendType::Nsjail, PortAllocator::default(), &Default::default());
         
         match backend {
             Ok(backend) => {
@@ -40,7 +41,7 @@ mod nsjail_tests {
 
     #[tokio::test]
     async fn test_nsjail_node_execution() {
-        let backend = create_backend(SandboxBackendType::Nsjail);
+        let backend = create_backend(SandboxBackendType::Nsjail, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -70,7 +71,7 @@ mod nsjail_tests {
 
     #[tokio::test]
     async fn test_nsjail_error_handling() {
-        let backend = create_backend(SandboxBackendType::Nsjail);
+        let backend = create_backend(SandboxBackendType::Nsjail, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -99,7 +100,7 @@ mod nsjail_tests {
 
     #[tokio::test]
     async fn test_nsjail_timeout() {
-        let backend = create_backend(SandboxBackendType::Nsjail);
+        let backend = create_backend(SandboxBackendType::Nsjail, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -133,20 +134,12 @@ mod docker_tests {
     use super::*;
 
     fn create_test_request(runtime: &str, code: &str) -> SandboxRequest {
-        SandboxRequest {
-            id: Uuid::new_v4().to_string(),
-            runtime: runtime.to_string(),
-            code: code.to_string(),
-            entry_point: None,
-            timeout_ms: 5000,
-            memory_limit_mb: 128,
-            env_vars: HashMap::new(),
-        }
+        test_request(runtime, code)
     }
 
     #[tokio::test]
     async fn test_docker_availability() {
-        let backend = create_backend(SandboxBackendType::Docker);
+        let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default());
         
         match backend {
             Ok(backend) => {
@@ -165,7 +158,7 @@ mod docker_tests {
 
     #[tokio::test]
     async fn test_docker_node_execution() {
-        let backend = create_backend(SandboxBackendType::Docker);
+        let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -195,7 +188,7 @@ mod docker_tests {
 
     #[tokio::test]
     async fn test_docker_typescript_execution() {
-        let backend = create_backend(SandboxBackendType::Docker);
+        let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -226,7 +219,7 @@ mod docker_tests {
 
     #[tokio::test]
     async fn test_docker_error_handling() {
-        let backend = create_backend(SandboxBackendType::Docker);
+        let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -255,7 +248,7 @@ mod docker_tests {
 
     #[tokio::test]
     async fn test_docker_environment_variables() {
-        let backend = create_backend(SandboxBackendType::Docker);
+        let backend = create_backend(SandboxBackendType::Docker, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
@@ -293,19 +286,11 @@ mod backend_comparison_tests {
     use super::*;
 
     async fn test_backend_with_code(backend_type: SandboxBackendType, code: &str) -> Option<SandboxResponse> {
-        let backend = create_backend(backend_type);
+        let backend = create_backend(backend_type, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
-                let request = SandboxRequest {
-                    id: Uuid::new_v4().to_string(),
-                    runtime: "node".to_string(),
-                    code: code.to_string(),
-                    entry_point: None,
-                    timeout_ms: 5000,
-                    memory_limit_mb: 128,
-                    env_vars: HashMap::new(),
-                };
+                let request = test_request("node", code);
                 
                 if backend.create_sandbox(&request).await.is_ok() {
                     if let Ok(response) = backend.execute_sandbox(&request).await {
@@ -386,27 +371,19 @@ mod performance_tests {
     use std::time::Instant;
 
     async fn measure_execution_time(backend_type: SandboxBackendType, iterations: usize) -> Option<Vec<u128>> {
-        let backend = create_backend(backend_type);
+        let backend = create_backend(backend_type, PortAllocator::default(), &Default::default());
         
         if let Ok(backend) = backend {
             if backend.is_available().await {
                 let mut times = Vec::new();
                 
                 for i in 0..iterations {
-                    let request = SandboxRequest {
-                        id: Uuid::new_v4().to_string(),
-                        runtime: "node".to_string(),
-                        code: format!("console.log('Iteration {}');", i),
-                        entry_point: None,
-                        timeout_ms: 5000,
-                        memory_limit_mb: 128,
-                        env_vars: HashMap::new(),
-                    };
+                    let request = test_request("node", &format!("console.log('Iteration {}');", i));
                     
                     let start = Instant::now();
                     
                     if backend.create_sandbox(&request).await.is_ok() {
-                        if let Ok(_) = backend.execute_sandbox(&request).await {
+                        if backend.execute_sandbox(&request).await.is_ok() {
                             let duration = start.elapsed().as_millis();
                             times.push(duration);
                         }
@@ -2,24 +2,36 @@ use axum::http::StatusCode;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
-use tokio::time::sleep;
 
-use sandbox_service::api::{create_router, CreateSandboxRequest, ExecutionResult, SandboxInfo};
+use sandbox_service::api::{create_router, CreateSandboxRequest};
+use sandbox_service::coordination::{IdempotencyKeys, LocalCoordinator};
 use sandbox_service::sandbox::backend::SandboxBackendType;
 use sandbox_service::sandbox::manager::SandboxManager;
+use sandbox_service::sandbox::{CapacityLimits, PortAllocator};
 
-async fn create_test_app() -> axum::Router {
+/// App on the backend `TEST_BACKEND` names, or None when it is not installed
+async fn create_test_app() -> Option<axum::Router> {
     let backend_type = if std::env::var("TEST_BACKEND").as_deref() == Ok("docker") {
         SandboxBackendType::Docker
     } else {
         SandboxBackendType::Nsjail
     };
+    create_test_app_on(backend_type, CapacityLimits::default()).await
+}
 
-    let manager = SandboxManager::new(backend_type).await.unwrap();
-    let app_state = Arc::new(RwLock::new(manager));
-    create_router(app_state)
+/// App on `backend_type` with `limits`, or None when the backend is not installed
+async fn create_test_app_on(backend_type: SandboxBackendType, limits: CapacityLimits) -> Option<axum::Router> {
+    let manager = match SandboxManager::new(backend_type, PortAllocator::default(), Default::default(), &[]).await {
+        Ok(manager) => manager.with_limits(limits),
+        Err(e) => {
+            println!("Skipping, backend not available: {}", e);
+            return None;
+        }
+    };
+    let cancellations = manager.cancellations();
+    let idempotency = IdempotencyKeys::new(Arc::new(LocalCoordinator::default()));
+    let app_state = Arc::new(manager);
+    Some(create_router(app_state, cancellations, idempotency))
 }
 
 async fn make_request<T>(
@@ -32,7 +44,7 @@ where
     T: serde::Serialize,
 {
     use axum::body::Body;
-    use axum::http::{Method, Request};
+    use axum::http::Request;
     use tower::ServiceExt;
 
     let mut request_builder = Request::builder().method(method).uri(path);
@@ -58,7 +70,7 @@ where
 
 #[tokio::test]
 async fn test_health_endpoint() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     let (status, body) = make_request::<()>(&app, "GET", "/health", None).await;
 
     assert_eq!(status, StatusCode::OK);
@@ -69,7 +81,7 @@ async fn test_health_endpoint() {
 
 #[tokio::test]
 async fn test_create_node_sandbox() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -78,6 +90,7 @@ async fn test_create_node_sandbox() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -92,7 +105,7 @@ async fn test_create_node_sandbox() {
 
 #[tokio::test]
 async fn test_create_and_execute_sandbox() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -101,6 +114,7 @@ async fn test_create_and_execute_sandbox() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -123,7 +137,7 @@ async fn test_create_and_execute_sandbox() {
 
 #[tokio::test]
 async fn test_typescript_sandbox() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "typescript".to_string(),
@@ -132,6 +146,7 @@ async fn test_typescript_sandbox() {
         timeout_ms: Some(10000),
         memory_limit_mb: Some(256),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -149,7 +164,7 @@ async fn test_typescript_sandbox() {
 
 #[tokio::test]
 async fn test_error_handling() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -158,6 +173,7 @@ async fn test_error_handling() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -176,7 +192,7 @@ async fn test_error_handling() {
 
 #[tokio::test]
 async fn test_environment_variables() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let mut env_vars = HashMap::new();
     env_vars.insert("TEST_VAR".to_string(), "test_value".to_string());
@@ -189,6 +205,7 @@ async fn test_environment_variables() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: Some(env_vars),
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -208,7 +225,7 @@ async fn test_environment_variables() {
 
 #[tokio::test]
 async fn test_get_sandbox_info() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -217,6 +234,7 @@ async fn test_get_sandbox_info() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -238,7 +256,7 @@ async fn test_get_sandbox_info() {
 
 #[tokio::test]
 async fn test_list_sandboxes() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     // Create multiple sandboxes
     for i in 0..3 {
@@ -249,6 +267,7 @@ async fn test_list_sandboxes() {
             timeout_ms: Some(5000),
             memory_limit_mb: Some(128),
             env_vars: None,
+            ..Default::default()
         };
 
         let (status, _) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -271,7 +290,7 @@ async fn test_list_sandboxes() {
 
 #[tokio::test]
 async fn test_delete_sandbox() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -280,6 +299,7 @@ async fn test_delete_sandbox() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -298,7 +318,7 @@ async fn test_delete_sandbox() {
 
 #[tokio::test]
 async fn test_invalid_runtime() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "python".to_string(),
@@ -307,15 +327,33 @@ async fn test_invalid_runtime() {
         timeout_ms: Some(5000),
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
-    let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
-    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let (status, _) = make_request(&app, "POST", "/sandbox", Some(request)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_one_shot_gpus_over_inventory() {
+    let limits = CapacityLimits { gpus: 1, ..Default::default() };
+    let Some(app) = create_test_app_on(SandboxBackendType::Docker, limits).await else { return; };
+
+    let request = CreateSandboxRequest {
+        runtime: "node".to_string(),
+        code: "console.log('Hello, GPU!')".to_string(),
+        gpus: Some(2),
+        ..Default::default()
+    };
+
+    let (status, body) = make_request(&app, "POST", "/execute", Some(request)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "validation_failed");
 }
 
 #[tokio::test]
 async fn test_nonexistent_sandbox() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let fake_id = "00000000-0000-0000-0000-000000000000";
     let path = format!("/sandbox/{}", fake_id);
@@ -325,12 +363,12 @@ async fn test_nonexistent_sandbox() {
     
     let execute_path = format!("/sandbox/{}/execute", fake_id);
     let (status, _) = make_request::<()>(&app, "POST", &execute_path, None).await;
-    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
 async fn test_timeout_handling() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -339,6 +377,7 @@ async fn test_timeout_handling() {
         timeout_ms: Some(1000), // 1 second timeout
         memory_limit_mb: Some(128),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;
@@ -357,7 +396,7 @@ async fn test_timeout_handling() {
 
 #[tokio::test]
 async fn test_concurrent_execution() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let mut handles = Vec::new();
     
@@ -371,6 +410,7 @@ async fn test_concurrent_execution() {
                 timeout_ms: Some(5000),
                 memory_limit_mb: Some(128),
                 env_vars: None,
+                ..Default::default()
             };
 
             let (status, body) = make_request(&app_clone, "POST", "/sandbox", Some(request)).await;
@@ -388,7 +428,7 @@ async fn test_concurrent_execution() {
         handles.push(handle);
     }
     
-    let results = futures::future::join_all(handles).await;
+    let results = futures_util::future::join_all(handles).await;
     
     for result in results {
         let stdout = result.unwrap();
@@ -398,7 +438,7 @@ async fn test_concurrent_execution() {
 
 #[tokio::test]
 async fn test_large_output() {
-    let app = create_test_app().await;
+    let Some(app) = create_test_app().await else { return; };
     
     let request = CreateSandboxRequest {
         runtime: "node".to_string(),
@@ -407,6 +447,7 @@ async fn test_large_output() {
         timeout_ms: Some(10000),
         memory_limit_mb: Some(256),
         env_vars: None,
+        ..Default::default()
     };
 
     let (status, body) = make_request(&app, "POST", "/sandbox", Some(request)).await;